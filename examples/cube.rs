@@ -82,8 +82,7 @@ impl MainLoop for App {
             .descriptor_pool(descriptor_pool)
             .set_layouts(&layouts);
 
-        let descriptor_sets =
-            unsafe { core.device.allocate_descriptor_sets(&create_info) }.result()?;
+        let descriptor_sets = core.allocate_descriptor_sets(&create_info)?;
 
         // Write descriptor sets
         for (frame, &descriptor_set) in descriptor_sets.iter().enumerate() {
@@ -121,8 +120,8 @@ impl MainLoop for App {
         // Pipeline
         let pipeline = shader(
             core,
-            &std::fs::read("shaders/unlit.vert.spv")?,
-            &std::fs::read("shaders/unlit.frag.spv")?,
+            UNLIT_VERT,
+            UNLIT_FRAG,
             vk::PrimitiveTopology::TRIANGLE_LIST,
             starter_kit.render_pass,
             pipeline_layout,
@@ -222,6 +221,18 @@ impl SyncMainLoop for App {
     }
 }
 
+impl Drop for App {
+    fn drop(&mut self) {
+        unsafe {
+            self.starter_kit.core.device.device_wait_idle().unwrap();
+            self.starter_kit.core.destroy_descriptor_pool(self.descriptor_pool);
+            self.starter_kit.core.device.destroy_descriptor_set_layout(Some(self.descriptor_set_layout), None);
+            self.starter_kit.core.device.destroy_pipeline_layout(Some(self.pipeline_layout), None);
+            self.starter_kit.core.destroy_pipeline(self.pipeline);
+        }
+    }
+}
+
 fn rainbow_cube() -> (Vec<Vertex>, Vec<u32>) {
     let vertices = vec![
         Vertex::new([-1.0, -1.0, -1.0], [0.0, 1.0, 1.0]),
@@ -21,7 +21,7 @@ struct App {
 fn main() -> Result<()> {
     let info = AppInfo::default().validation(true);
     let vr = std::env::args().count() > 1;
-    launch::<App>(info, vr)
+    launch::<App, _>(info, vr, ())
 }
 
 const TEXTURE_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
@@ -37,18 +37,17 @@ unsafe impl bytemuck::Zeroable for SceneData {}
 unsafe impl bytemuck::Pod for SceneData {}
 
 impl MainLoop for App {
-    fn new(core: &SharedCore, mut platform: Platform<'_>) -> Result<Self> {
+    fn new(core: &SharedCore, mut platform: Platform<'_>, _: ()) -> Result<Self> {
         let mut starter_kit = StarterKit::new(core.clone(), &mut platform)?;
 
         // Camera
         let camera = MultiPlatformCamera::new(&mut platform);
 
         // Image uploads
-        let image_layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
         let command_buffer = starter_kit.current_command_buffer(); // TODO: This probably breaks stuff lmaoo
 
         let (data, info) = read_image("./examples/obama.png").context("Failed to read image")?;
-        let (cube_tex, subresource_range) = starter_kit.staging_buffer.upload_image(
+        let (cube_tex, subresource_range, image_layout) = starter_kit.staging_buffer.upload_image(
             command_buffer,
             info.width,
             info.height,
@@ -141,8 +140,7 @@ impl MainLoop for App {
             .descriptor_pool(descriptor_pool)
             .set_layouts(&layouts);
 
-        let descriptor_sets =
-            unsafe { core.device.allocate_descriptor_sets(&create_info) }.result()?;
+        let descriptor_sets = core.allocate_descriptor_sets(&create_info)?;
 
         // Image info
         let image_infos = [vk::DescriptorImageInfoBuilder::new()
@@ -192,8 +190,8 @@ impl MainLoop for App {
         // Pipeline
         let pipeline = shader(
             core,
-            include_bytes!("unlit.vert.spv"),
-            include_bytes!("unlit_tex.frag.spv"),
+            UNLIT_VERT,
+            UNLIT_TEX_FRAG,
             vk::PrimitiveTopology::TRIANGLE_LIST,
             starter_kit.render_pass,
             pipeline_layout,
@@ -248,14 +246,14 @@ impl MainLoop for App {
                 self.pipeline,
             );
 
-            draw_meshes(
+            draw_mesh(
                 core,
                 command_buffer,
-                std::slice::from_ref(&&self.rainbow_cube),
+                &self.rainbow_cube,
             );
         }
 
-        let (ret, cameras) = self.camera.get_matrices(platform)?;
+        let (ret, cameras) = self.camera.get_matrices(&platform)?;
 
         self.scene_ubo.upload(
             self.starter_kit.frame,
@@ -293,6 +291,18 @@ impl SyncMainLoop for App {
     }
 }
 
+impl Drop for App {
+    fn drop(&mut self) {
+        unsafe {
+            self.starter_kit.core.device.device_wait_idle().unwrap();
+            self.starter_kit.core.destroy_descriptor_pool(self.descriptor_pool);
+            self.starter_kit.core.device.destroy_descriptor_set_layout(Some(self.descriptor_set_layout), None);
+            self.starter_kit.core.device.destroy_pipeline_layout(Some(self.pipeline_layout), None);
+            self.starter_kit.core.destroy_pipeline(self.pipeline);
+        }
+    }
+}
+
 fn rainbow_cube() -> (Vec<Vertex>, Vec<u32>) {
     let vertices = vec![
         Vertex::new([-1.0, -1.0, -1.0], [0.0, 1.0, 1.0]),
@@ -0,0 +1,52 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// Metallic-roughness shading lit by the prefiltered environment of an `ibl::Ibl`
+
+layout(set = 1, binding = 0) uniform sampler samp;
+layout(set = 1, binding = 1) uniform textureCube irradiance;
+layout(set = 1, binding = 2) uniform textureCube specular;
+layout(set = 1, binding = 3) uniform texture2D brdf;
+
+layout(push_constant) uniform Material {
+    mat4 model;
+    vec4 base_color;
+    float metallic;
+    float roughness;
+    // Lod of the fully rough level of the specular map
+    float max_lod;
+};
+
+layout(location = 0) in vec3 fragPosition;
+layout(location = 1) in vec3 fragNormal;
+layout(location = 2) in vec3 fragEye;
+
+layout(location = 0) out vec4 outColor;
+
+// Set from PipelineSettings::encode_srgb, for UNORM outputs which store values as written
+layout(constant_id = 0) const bool ENCODE_SRGB = false;
+
+vec3 linear_to_srgb(vec3 c) {
+    c = max(c, 0.0);
+    return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, c));
+}
+
+void main() {
+    vec3 n = normalize(fragNormal);
+    vec3 v = normalize(fragEye - fragPosition);
+    vec3 r = reflect(-v, n);
+    float n_dot_v = max(dot(n, v), 0.0);
+
+    vec3 f0 = mix(vec3(0.04), base_color.rgb, metallic);
+    vec2 scale_bias = texture(sampler2D(brdf, samp), vec2(n_dot_v, roughness)).rg;
+    vec3 reflected = textureLod(samplerCube(specular, samp), r, roughness * max_lod).rgb;
+    vec3 spec = reflected * (f0 * scale_bias.x + scale_bias.y);
+
+    vec3 diffuse_color = base_color.rgb * (1.0 - metallic);
+    vec3 diffuse = texture(samplerCube(irradiance, samp), n).rgb * diffuse_color;
+
+    outColor = vec4(diffuse + spec, 1.0);
+    if (ENCODE_SRGB) {
+        outColor.rgb = linear_to_srgb(outColor.rgb);
+    }
+}
@@ -0,0 +1,30 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_EXT_multiview : require
+
+layout(binding = 0) uniform Animation {
+    mat4 camera[2];
+    float anim;
+};
+
+layout(push_constant) uniform Model {
+    mat4 model;
+};
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 2) in vec3 inNormal;
+
+layout(location = 0) out vec3 fragPosition;
+layout(location = 1) out vec3 fragNormal;
+layout(location = 2) out vec3 fragEye;
+
+void main() {
+    vec4 world = model * vec4(inPosition, 1.0);
+    gl_Position = camera[gl_ViewIndex] * world;
+    fragPosition = world.xyz;
+    fragNormal = mat3(model) * inNormal;
+
+    // The eye is the point the projection sends to w = 0
+    vec4 eye = inverse(camera[gl_ViewIndex]) * vec4(0.0, 0.0, 1.0, 0.0);
+    fragEye = eye.xyz / eye.w;
+}
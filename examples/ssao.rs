@@ -0,0 +1,435 @@
+use watertender::prelude::*;
+use watertender::ibl::{Ibl, IblSettings};
+use watertender::material::{self, Material};
+use watertender::post::{SsaoSettings, SSAO_GEOMETRY_FRAG, SSAO_GEOMETRY_VERT};
+use watertender::set_layouts;
+use watertender::shader::PipelineSettings;
+use watertender::nalgebra::{Matrix4, Vector3};
+use anyhow::{Context, Result};
+use std::f32::consts::PI;
+
+const PBR_VERT: &[u8] = include_bytes!("shaders/pbr.vert.spv");
+const PBR_FRAG: &[u8] = include_bytes!("shaders/pbr.frag.spv");
+
+/// A mesh drawn with both the SSAO prepass and the lit pass
+struct Object {
+    mesh: ManagedMesh,
+    transform: Matrix4<f32>,
+    material: Material,
+}
+
+struct App {
+    objects: Vec<Object>,
+    prepass: Material,
+    pipeline_layout: vk::PipelineLayout,
+
+    descriptor_pool: vk::DescriptorPool,
+    frame_set_layout: vk::DescriptorSetLayout,
+    ibl_set_layout: vk::DescriptorSetLayout,
+
+    scene_ubo: FrameDataUbo<SceneData>,
+    camera: MultiPlatformCamera,
+    /// Owns the maps bound in the IBL set
+    _ibl: Ibl,
+    starter_kit: StarterKit,
+}
+
+fn main() -> Result<()> {
+    let info = AppInfo::default().validation(true);
+    let vr = std::env::args().count() > 1;
+    launch::<App, _>(info, vr, ())
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct SceneData {
+    cameras: [f32; 4 * 4 * 2],
+    anim: f32,
+}
+
+unsafe impl bytemuck::Zeroable for SceneData {}
+unsafe impl bytemuck::Pod for SceneData {}
+
+/// Parameters pushed after the transform, as laid out in `pbr.frag`
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct PbrParams {
+    base_color: [f32; 4],
+    metallic: f32,
+    roughness: f32,
+    max_lod: f32,
+}
+
+unsafe impl bytemuck::Zeroable for PbrParams {}
+unsafe impl bytemuck::Pod for PbrParams {}
+
+impl MainLoop for App {
+    fn new(core: &SharedCore, mut platform: Platform<'_>, _: ()) -> Result<Self> {
+        // The default camera's field of view matches the default SSAO projection scale
+        let settings = starter_kit::Settings {
+            ssao: Some(SsaoSettings::default()),
+            clear_color: [0.6, 0.7, 0.9, 1.0],
+            ..Default::default()
+        };
+        let mut starter_kit = StarterKit::new_with_settings(core.clone(), &mut platform, settings)?;
+        let frames_in_flight = starter_kit.frames_in_flight();
+
+        // Camera
+        let camera = MultiPlatformCamera::new(&mut platform);
+
+        // Scene data
+        let scene_ubo = FrameDataUbo::new(core.clone(), frames_in_flight)?;
+
+        // Environment lighting
+        let (width, height, sky) = sky(256, 128);
+        let ibl = Ibl::from_equirect(
+            core.clone(),
+            &mut starter_kit.staging_buffer,
+            starter_kit.command_buffers[0],
+            width,
+            height,
+            &sky,
+            IblSettings::default(),
+        )?;
+
+        // Create descriptor set layouts: the frame data, and the IBL maps with a separate sampler
+        let bindings = set_layouts::frame_bindings();
+        let create_info = vk::DescriptorSetLayoutCreateInfoBuilder::new().bindings(&bindings);
+        let frame_set_layout =
+            unsafe { core.device.create_descriptor_set_layout(&create_info, None, None) }
+                .result()?;
+
+        let ibl_binding = |binding: u32, ty: vk::DescriptorType| {
+            vk::DescriptorSetLayoutBindingBuilder::new()
+                .binding(binding)
+                .descriptor_type(ty)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+        };
+        let bindings = [
+            ibl_binding(0, vk::DescriptorType::SAMPLER),
+            ibl_binding(1, vk::DescriptorType::SAMPLED_IMAGE),
+            ibl_binding(2, vk::DescriptorType::SAMPLED_IMAGE),
+            ibl_binding(3, vk::DescriptorType::SAMPLED_IMAGE),
+        ];
+        let create_info = vk::DescriptorSetLayoutCreateInfoBuilder::new().bindings(&bindings);
+        let ibl_set_layout =
+            unsafe { core.device.create_descriptor_set_layout(&create_info, None, None) }
+                .result()?;
+
+        // Create descriptor pool
+        let pool_sizes = [
+            vk::DescriptorPoolSizeBuilder::new()
+                ._type(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(frames_in_flight as _),
+            vk::DescriptorPoolSizeBuilder::new()
+                ._type(vk::DescriptorType::SAMPLER)
+                .descriptor_count(1),
+            vk::DescriptorPoolSizeBuilder::new()
+                ._type(vk::DescriptorType::SAMPLED_IMAGE)
+                .descriptor_count(3),
+        ];
+
+        let create_info = vk::DescriptorPoolCreateInfoBuilder::new()
+            .pool_sizes(&pool_sizes)
+            .max_sets((frames_in_flight + 1) as _);
+
+        let descriptor_pool =
+            unsafe { core.device.create_descriptor_pool(&create_info, None, None) }.result()?;
+
+        // Create descriptor sets
+        let layouts = vec![frame_set_layout; frames_in_flight];
+        let create_info = vk::DescriptorSetAllocateInfoBuilder::new()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&layouts);
+        let frame_sets = core.allocate_descriptor_sets(&create_info)?;
+
+        let layouts = [ibl_set_layout];
+        let create_info = vk::DescriptorSetAllocateInfoBuilder::new()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&layouts);
+        let ibl_set = core.allocate_descriptor_sets(&create_info)?[0];
+
+        // Write descriptor sets
+        for (frame, &descriptor_set) in frame_sets.iter().enumerate() {
+            let frame_data_bi = [scene_ubo.descriptor_buffer_info(frame)];
+            let writes = [vk::WriteDescriptorSetBuilder::new()
+                .buffer_info(&frame_data_bi)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .dst_set(descriptor_set)
+                .dst_binding(0)
+                .dst_array_element(0)];
+
+            unsafe {
+                core.device.update_descriptor_sets(&writes, &[]);
+            }
+        }
+
+        let sampler_ii = [vk::DescriptorImageInfoBuilder::new().sampler(ibl.sampler())];
+        let image_info = |view: vk::ImageView| {
+            [vk::DescriptorImageInfoBuilder::new()
+                .image_view(view)
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)]
+        };
+        let irradiance_ii = image_info(ibl.irradiance_view());
+        let specular_ii = image_info(ibl.specular_view());
+        let brdf_ii = image_info(ibl.brdf_view());
+        let image_write = |binding: u32, info| {
+            vk::WriteDescriptorSetBuilder::new()
+                .image_info(info)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .dst_set(ibl_set)
+                .dst_binding(binding)
+                .dst_array_element(0)
+        };
+        let writes = [
+            vk::WriteDescriptorSetBuilder::new()
+                .image_info(&sampler_ii)
+                .descriptor_type(vk::DescriptorType::SAMPLER)
+                .dst_set(ibl_set)
+                .dst_binding(0)
+                .dst_array_element(0),
+            image_write(1, &irradiance_ii),
+            image_write(2, &specular_ii),
+            image_write(3, &brdf_ii),
+        ];
+        unsafe {
+            core.device.update_descriptor_sets(&writes, &[]);
+        }
+
+        // Pipeline layout, shared by the prepass, which only uses the frame data and transform
+        let descriptor_set_layouts = [frame_set_layout, ibl_set_layout];
+        let push_constant_ranges =
+            [material::push_constant_range(std::mem::size_of::<PbrParams>())];
+
+        let create_info = vk::PipelineLayoutCreateInfoBuilder::new()
+            .push_constant_ranges(&push_constant_ranges)
+            .set_layouts(&descriptor_set_layouts);
+
+        let pipeline_layout =
+            unsafe { core.device.create_pipeline_layout(&create_info, None, None) }.result()?;
+
+        // Pipelines: the geometry the occlusion is computed from, and the lit scene
+        let pipeline_settings = PipelineSettings {
+            cull_mode: vk::CullModeFlags::NONE,
+            ..Default::default()
+        };
+        let prepass_pipeline = starter_kit
+            .ssao_pipelines()
+            .context("SSAO should be enabled")?
+            .add::<VertexNT>(
+                SSAO_GEOMETRY_VERT,
+                SSAO_GEOMETRY_FRAG,
+                pipeline_settings,
+                pipeline_layout,
+            )?;
+        let pbr_pipeline = starter_kit.pipelines.add::<VertexNT>(
+            PBR_VERT,
+            PBR_FRAG,
+            pipeline_settings,
+            pipeline_layout,
+        )?;
+
+        let mut prepass = Material::new(prepass_pipeline, pipeline_layout);
+        prepass.bind_per_frame(set_layouts::FRAME_SET, frame_sets.clone());
+
+        let pbr = |base_color: [f32; 3], metallic: f32, roughness: f32| -> Result<Material> {
+            let mut material = Material::new(pbr_pipeline, pipeline_layout);
+            material.bind_per_frame(set_layouts::FRAME_SET, frame_sets.clone());
+            material.bind(set_layouts::MATERIAL_SET, ibl_set);
+            material.set_params(&PbrParams {
+                base_color: [base_color[0], base_color[1], base_color[2], 1.0],
+                metallic,
+                roughness,
+                max_lod: ibl.max_lod(),
+            })?;
+            Ok(material)
+        };
+
+        // Mesh uploads: spheres of varying roughness resting on a floor, occluding it and each
+        // other where they meet
+        let (vertices, indices) = floor(6.0);
+        let floor = upload_mesh(
+            &mut starter_kit.staging_buffer,
+            starter_kit.command_buffers[0],
+            &vertices,
+            &indices,
+        )?;
+        let mut objects = vec![Object {
+            mesh: floor,
+            transform: Matrix4::identity(),
+            material: pbr([0.8, 0.8, 0.8], 0.0, 0.9)?,
+        }];
+
+        let (vertices, indices) = sphere(32, 16);
+        for i in 0..5 {
+            let mesh = upload_mesh(
+                &mut starter_kit.staging_buffer,
+                starter_kit.command_buffers[0],
+                &vertices,
+                &indices,
+            )?;
+            let t = i as f32 / 4.0;
+            let x = (i as f32 - 2.0) * 1.1;
+            objects.push(Object {
+                mesh,
+                transform: Matrix4::new_scaling(0.5)
+                    .append_translation(&Vector3::new(x, 0.5, 0.0)),
+                material: pbr([0.9, 0.6, 0.3], t, 0.1 + 0.8 * t)?,
+            });
+        }
+
+        Ok(Self {
+            objects,
+            prepass,
+            pipeline_layout,
+            descriptor_pool,
+            frame_set_layout,
+            ibl_set_layout,
+            scene_ubo,
+            camera,
+            _ibl: ibl,
+            starter_kit,
+        })
+    }
+
+    fn frame(
+        &mut self,
+        frame: Frame,
+        _core: &SharedCore,
+        platform: Platform<'_>,
+    ) -> Result<PlatformReturn> {
+        let cmd = self.starter_kit.begin_frame(frame)?;
+
+        // Geometry the occlusion is computed from
+        self.starter_kit.begin_ssao_prepass(&cmd)?;
+        for object in &self.objects {
+            self.starter_kit
+                .draw(&cmd, &object.mesh, &self.prepass, &object.transform);
+        }
+
+        // Lit scene, darkened by the occlusion once it ends
+        self.starter_kit.begin_swapchain_pass(&cmd);
+        for object in &self.objects {
+            self.starter_kit
+                .draw(&cmd, &object.mesh, &object.material, &object.transform);
+        }
+
+        let (ret, cameras) = self.camera.get_matrices(&platform)?;
+
+        self.scene_ubo.upload(
+            self.starter_kit.frame,
+            &SceneData { cameras, anim: 0.0 },
+        )?;
+
+        // End draw cmds
+        self.starter_kit.end_command_buffer(cmd)?;
+
+        Ok(ret)
+    }
+
+    fn swapchain_resize(&mut self, images: Vec<vk::Image>, extent: vk::Extent2D) -> Result<()> {
+        self.starter_kit.swapchain_resize(images, extent)
+    }
+
+    fn event(
+        &mut self,
+        mut event: PlatformEvent<'_, '_>,
+        _core: &Core,
+        mut platform: Platform<'_>,
+    ) -> Result<()> {
+        self.camera.handle_event(&mut event, &mut platform);
+        starter_kit::close_when_asked(event, platform);
+        Ok(())
+    }
+}
+
+impl SyncMainLoop for App {
+    fn winit_sync(&self) -> (vk::Semaphore, vk::Semaphore) {
+        self.starter_kit.winit_sync()
+    }
+}
+
+impl Drop for App {
+    fn drop(&mut self) {
+        unsafe {
+            self.starter_kit.core.device.device_wait_idle().unwrap();
+            self.starter_kit.core.destroy_descriptor_pool(self.descriptor_pool);
+            self.starter_kit.core.device.destroy_descriptor_set_layout(Some(self.frame_set_layout), None);
+            self.starter_kit.core.device.destroy_descriptor_set_layout(Some(self.ibl_set_layout), None);
+            self.starter_kit.core.device.destroy_pipeline_layout(Some(self.pipeline_layout), None);
+        }
+    }
+}
+
+/// Equirectangular sky in linear HDR: a gradient from the horizon to the zenith, a dim ground,
+/// and a bright sun
+fn sky(width: u32, height: u32) -> (u32, u32, Vec<f32>) {
+    let sun = Vector3::new(0.4f32, 0.8, 0.3).normalize();
+    let mut rgba = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        let elevation = PI / 2.0 - (y as f32 + 0.5) / height as f32 * PI;
+        for x in 0..width {
+            let azimuth = (x as f32 + 0.5) / width as f32 * 2.0 * PI;
+            let dir = Vector3::new(
+                elevation.cos() * azimuth.cos(),
+                elevation.sin(),
+                elevation.cos() * azimuth.sin(),
+            );
+            let color = if dir.y < 0.0 {
+                [0.25, 0.22, 0.2]
+            } else {
+                let t = dir.y.sqrt();
+                let sun = dir.dot(&sun).max(0.0).powf(256.0) * 50.0;
+                [
+                    0.9 - 0.6 * t + sun,
+                    1.0 - 0.5 * t + sun,
+                    1.2 - 0.2 * t + sun,
+                ]
+            };
+            rgba.extend_from_slice(&[color[0], color[1], color[2], 1.0]);
+        }
+    }
+    (width, height, rgba)
+}
+
+/// Square in the XZ plane with sides of `size`, facing up
+fn floor(size: f32) -> (Vec<VertexNT>, Vec<u32>) {
+    let h = size / 2.0;
+    let up = [0.0, 1.0, 0.0];
+    let white = [1.0; 3];
+    let vertices = vec![
+        VertexNT::new([-h, 0.0, -h], up, [0.0, 0.0], white),
+        VertexNT::new([h, 0.0, -h], up, [1.0, 0.0], white),
+        VertexNT::new([h, 0.0, h], up, [1.0, 1.0], white),
+        VertexNT::new([-h, 0.0, h], up, [0.0, 1.0], white),
+    ];
+    let indices = vec![0, 2, 1, 0, 3, 2];
+    (vertices, indices)
+}
+
+/// Unit sphere with `segments` around the equator and `rings` from pole to pole
+fn sphere(segments: u32, rings: u32) -> (Vec<VertexNT>, Vec<u32>) {
+    let mut vertices = vec![];
+    for ring in 0..=rings {
+        let v = ring as f32 / rings as f32;
+        let theta = v * PI;
+        for segment in 0..=segments {
+            let u = segment as f32 / segments as f32;
+            let phi = u * 2.0 * PI;
+            let pos = [theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin()];
+            vertices.push(VertexNT::new(pos, pos, [u, v], [1.0; 3]));
+        }
+    }
+
+    let mut indices = vec![];
+    let stride = segments + 1;
+    for ring in 0..rings {
+        for segment in 0..segments {
+            let a = ring * stride + segment;
+            let b = a + stride;
+            indices.extend_from_slice(&[a, a + 1, b, a + 1, b + 1, b]);
+        }
+    }
+    (vertices, indices)
+}
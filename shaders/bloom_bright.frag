@@ -0,0 +1,28 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_EXT_multiview : require
+
+// Bright pass: keep the parts of the image above the threshold, with a soft knee
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+layout(binding = 0) uniform sampler samp;
+layout(binding = 1) uniform texture2DArray scene;
+
+layout(push_constant) uniform Bright {
+    float threshold;
+    float knee;
+};
+
+void main() {
+    vec3 color = texture(sampler2DArray(scene, samp), vec3(fragUv, float(gl_ViewIndex))).rgb;
+    float brightness = max(color.r, max(color.g, color.b));
+
+    float soft = clamp(brightness - threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee + 0.00001);
+    float contribution = max(soft, brightness - threshold) / max(brightness, 0.00001);
+
+    outColor = vec4(color * contribution, 1.0);
+}
@@ -0,0 +1,22 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_EXT_multiview : require
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+layout(binding = 0) uniform sampler samp;
+layout(binding = 1) uniform texture2DArray scene;
+layout(binding = 2) uniform texture2DArray bloom;
+
+layout(push_constant) uniform Composite {
+    float intensity;
+};
+
+void main() {
+    vec3 uv = vec3(fragUv, float(gl_ViewIndex));
+    vec3 color = texture(sampler2DArray(scene, samp), uv).rgb;
+    color += texture(sampler2DArray(bloom, samp), uv).rgb * intensity;
+    outColor = vec4(color, 1.0);
+}
@@ -0,0 +1,43 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_EXT_multiview : require
+
+// 13-tap downsample filter (Jimenez, "Next Generation Post Processing in Call of Duty")
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+layout(binding = 0) uniform sampler samp;
+layout(binding = 1) uniform texture2DArray src;
+
+layout(push_constant) uniform Down {
+    vec2 texel;
+};
+
+vec3 tap(vec2 offset) {
+    return texture(sampler2DArray(src, samp), vec3(fragUv + offset * texel, float(gl_ViewIndex))).rgb;
+}
+
+void main() {
+    vec3 a = tap(vec2(-2.0, 2.0));
+    vec3 b = tap(vec2(0.0, 2.0));
+    vec3 c = tap(vec2(2.0, 2.0));
+    vec3 d = tap(vec2(-2.0, 0.0));
+    vec3 e = tap(vec2(0.0, 0.0));
+    vec3 f = tap(vec2(2.0, 0.0));
+    vec3 g = tap(vec2(-2.0, -2.0));
+    vec3 h = tap(vec2(0.0, -2.0));
+    vec3 i = tap(vec2(2.0, -2.0));
+    vec3 j = tap(vec2(-1.0, 1.0));
+    vec3 k = tap(vec2(1.0, 1.0));
+    vec3 l = tap(vec2(-1.0, -1.0));
+    vec3 m = tap(vec2(1.0, -1.0));
+
+    vec3 color = e * 0.125;
+    color += (a + c + g + i) * 0.03125;
+    color += (b + d + f + h) * 0.0625;
+    color += (j + k + l + m) * 0.125;
+
+    outColor = vec4(color, 1.0);
+}
@@ -0,0 +1,34 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_EXT_multiview : require
+
+// 9-tap tent upsample of the lower level, added to the current level
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+layout(binding = 0) uniform sampler samp;
+layout(binding = 1) uniform texture2DArray current;
+layout(binding = 2) uniform texture2DArray lower;
+
+layout(push_constant) uniform Up {
+    vec2 texel;
+    float radius;
+};
+
+vec3 tap(vec2 offset) {
+    return texture(sampler2DArray(lower, samp), vec3(fragUv + offset * texel * radius, float(gl_ViewIndex))).rgb;
+}
+
+void main() {
+    vec3 color = tap(vec2(0.0, 0.0)) * 4.0;
+    color += (tap(vec2(-1.0, 0.0)) + tap(vec2(1.0, 0.0))
+        + tap(vec2(0.0, -1.0)) + tap(vec2(0.0, 1.0))) * 2.0;
+    color += tap(vec2(-1.0, -1.0)) + tap(vec2(1.0, -1.0))
+        + tap(vec2(-1.0, 1.0)) + tap(vec2(1.0, 1.0));
+    color /= 16.0;
+
+    color += texture(sampler2DArray(current, samp), vec3(fragUv, float(gl_ViewIndex))).rgb;
+    outColor = vec4(color, 1.0);
+}
@@ -7,3 +7,38 @@ compile() {
 compile unlit.vert
 compile unlit.frag
 compile unlit_tex.frag
+compile skinned.vert
+compile fullscreen.vert
+compile tonemap.frag
+compile bloom_bright.frag
+compile bloom_down.frag
+compile bloom_up.frag
+compile bloom_composite.frag
+compile fxaa.frag
+compile ssao_geometry.vert
+compile ssao_geometry.frag
+compile ssao.frag
+compile ssao_blur.frag
+compile ssao_composite.frag
+compile picking.vert
+compile picking.frag
+compile stereo_preview.frag
+compile upscale.frag
+compile trivial.vert
+compile trivial.frag
+compile wide_line.vert
+compile gbuffer.vert
+compile gbuffer.frag
+compile deferred_lighting.frag
+compile ibl_irradiance.comp
+compile ibl_specular.comp
+compile ibl_brdf.comp
+compile world_panel.vert
+compile world_panel.frag
+compile point_cloud.vert
+compile point_cloud.frag
+compile outline.vert
+compile outline.frag
+compile debug_view.frag
+compile ../examples/shaders/pbr.vert
+compile ../examples/shaders/pbr.frag
@@ -0,0 +1,50 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_EXT_multiview : require
+
+// Visualizes an intermediate image, such as depth or G-buffer normals, for debugging
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+layout(binding = 0) uniform sampler samp;
+layout(binding = 1) uniform texture2DArray image;
+
+layout(push_constant) uniform DebugView {
+    int mode;
+    float near;
+    float far;
+    uint reverse_z;
+    uint encode_srgb;
+};
+
+vec3 linear_to_srgb(vec3 c) {
+    c = max(c, 0.0);
+    return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, c));
+}
+
+void main() {
+    vec4 texel = texture(sampler2DArray(image, samp), vec3(fragUv, float(gl_ViewIndex)));
+
+    vec3 color;
+    if (mode == 0) {
+        color = texel.rgb;
+    } else if (mode == 1) {
+        // Perspective depth back to view distance, shown from black (near) to white (far)
+        float z = texel.r;
+        float dist = reverse_z != 0
+            ? near * far / (near + z * (far - near))
+            : near * far / (far - z * (far - near));
+        color = vec3(clamp((dist - near) / (far - near), 0.0, 1.0));
+    } else if (mode == 2) {
+        color = normalize(texel.xyz) * 0.5 + 0.5;
+    } else {
+        color = vec3(texel.r);
+    }
+
+    if (encode_srgb != 0) {
+        color = linear_to_srgb(color);
+    }
+    outColor = vec4(color, 1.0);
+}
@@ -0,0 +1,73 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_EXT_multiview : require
+
+struct Light {
+    // xyz is the position, or the direction towards the light when w is 0
+    vec4 position;
+    // rgb is the color, a the radius of point lights
+    vec4 color;
+};
+
+layout(binding = 0) uniform sampler samp;
+layout(binding = 1) uniform texture2DArray albedo;
+layout(binding = 2) uniform texture2DArray normals;
+layout(binding = 3) uniform texture2DArray depth;
+
+layout(std430, binding = 4) readonly buffer Lights {
+    mat4 inverse_camera[2];
+    vec4 ambient;
+    uint light_count;
+    // Depth of pixels where nothing was drawn: 1, or 0 with reverse-Z
+    float clear_depth;
+    // Encode the output for a UNORM swapchain
+    uint encode_srgb;
+    Light lights[];
+};
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+vec3 linear_to_srgb(vec3 c) {
+    c = max(c, 0.0);
+    return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, c));
+}
+
+vec4 encode(vec3 color) {
+    return vec4(encode_srgb != 0 ? linear_to_srgb(color) : color, 1.0);
+}
+
+void main() {
+    ivec3 texel = ivec3(ivec2(gl_FragCoord.xy), gl_ViewIndex);
+    vec3 base = texelFetch(sampler2DArray(albedo, samp), texel, 0).rgb;
+    float z = texelFetch(sampler2DArray(depth, samp), texel, 0).r;
+
+    // Nothing was drawn here; show the clear color
+    if (z == clear_depth) {
+        outColor = encode(base);
+        return;
+    }
+
+    vec3 normal = normalize(texelFetch(sampler2DArray(normals, samp), texel, 0).xyz);
+    vec4 world = inverse_camera[gl_ViewIndex] * vec4(fragUv * 2.0 - 1.0, z, 1.0);
+    vec3 pos = world.xyz / world.w;
+
+    vec3 light = ambient.rgb;
+    for (uint i = 0; i < light_count; i++) {
+        vec4 position = lights[i].position;
+        vec4 color = lights[i].color;
+
+        vec3 to_light = position.xyz;
+        float attenuation = 1.0;
+        if (position.w != 0.0) {
+            to_light = position.xyz - pos;
+            float falloff = clamp(1.0 - dot(to_light, to_light) / (color.a * color.a), 0.0, 1.0);
+            attenuation = falloff * falloff;
+        }
+
+        light += color.rgb * max(dot(normal, normalize(to_light)), 0.0) * attenuation;
+    }
+
+    outColor = encode(base * light);
+}
@@ -0,0 +1,158 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_EXT_multiview : require
+
+// Fast approximate anti-aliasing, after Lottes' FXAA 3.11 (quality preset with 12 edge steps)
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+layout(binding = 0) uniform sampler samp;
+layout(binding = 1) uniform texture2DArray scene;
+
+layout(push_constant) uniform Fxaa {
+    vec2 texel;
+    float subpixel;
+    float edge_threshold;
+    float edge_threshold_min;
+};
+
+const int ITERATIONS = 12;
+
+vec3 fetch(vec2 uv) {
+    return texture(sampler2DArray(scene, samp), vec3(uv, float(gl_ViewIndex))).rgb;
+}
+
+// Perceptual luma of linear color
+float luma(vec3 color) {
+    return sqrt(dot(color, vec3(0.299, 0.587, 0.114)));
+}
+
+float quality(int i) {
+    if (i < 5) return 1.0;
+    if (i == 5) return 1.5;
+    if (i < 10) return 2.0;
+    if (i == 10) return 4.0;
+    return 8.0;
+}
+
+void main() {
+    vec3 color_center = fetch(fragUv);
+    float luma_center = luma(color_center);
+
+    float luma_down = luma(fetch(fragUv + vec2(0.0, 1.0) * texel));
+    float luma_up = luma(fetch(fragUv + vec2(0.0, -1.0) * texel));
+    float luma_left = luma(fetch(fragUv + vec2(-1.0, 0.0) * texel));
+    float luma_right = luma(fetch(fragUv + vec2(1.0, 0.0) * texel));
+
+    float luma_min = min(luma_center, min(min(luma_down, luma_up), min(luma_left, luma_right)));
+    float luma_max = max(luma_center, max(max(luma_down, luma_up), max(luma_left, luma_right)));
+    float luma_range = luma_max - luma_min;
+
+    // Not on an edge
+    if (luma_range < max(edge_threshold_min, luma_max * edge_threshold)) {
+        outColor = vec4(color_center, 1.0);
+        return;
+    }
+
+    float luma_down_left = luma(fetch(fragUv + vec2(-1.0, 1.0) * texel));
+    float luma_up_right = luma(fetch(fragUv + vec2(1.0, -1.0) * texel));
+    float luma_up_left = luma(fetch(fragUv + vec2(-1.0, -1.0) * texel));
+    float luma_down_right = luma(fetch(fragUv + vec2(1.0, 1.0) * texel));
+
+    float luma_down_up = luma_down + luma_up;
+    float luma_left_right = luma_left + luma_right;
+    float luma_left_corners = luma_down_left + luma_up_left;
+    float luma_down_corners = luma_down_left + luma_down_right;
+    float luma_right_corners = luma_down_right + luma_up_right;
+    float luma_up_corners = luma_up_right + luma_up_left;
+
+    // Edge orientation
+    float edge_horizontal = abs(-2.0 * luma_left + luma_left_corners)
+        + abs(-2.0 * luma_center + luma_down_up) * 2.0
+        + abs(-2.0 * luma_right + luma_right_corners);
+    float edge_vertical = abs(-2.0 * luma_up + luma_up_corners)
+        + abs(-2.0 * luma_center + luma_left_right) * 2.0
+        + abs(-2.0 * luma_down + luma_down_corners);
+    bool is_horizontal = edge_horizontal >= edge_vertical;
+
+    // Which side of the edge this pixel is on
+    float luma1 = is_horizontal ? luma_down : luma_left;
+    float luma2 = is_horizontal ? luma_up : luma_right;
+    float gradient1 = luma1 - luma_center;
+    float gradient2 = luma2 - luma_center;
+    bool is1_steepest = abs(gradient1) >= abs(gradient2);
+    float gradient_scaled = 0.25 * max(abs(gradient1), abs(gradient2));
+
+    float step_length = is_horizontal ? texel.y : texel.x;
+    float luma_local_average = 0.0;
+    if (is1_steepest) {
+        luma_local_average = 0.5 * (luma1 + luma_center);
+    } else {
+        step_length = -step_length;
+        luma_local_average = 0.5 * (luma2 + luma_center);
+    }
+
+    vec2 current_uv = fragUv;
+    if (is_horizontal) {
+        current_uv.y += step_length * 0.5;
+    } else {
+        current_uv.x += step_length * 0.5;
+    }
+
+    // Walk along the edge in both directions until its ends are found
+    vec2 offset = is_horizontal ? vec2(texel.x, 0.0) : vec2(0.0, texel.y);
+    vec2 uv1 = current_uv - offset;
+    vec2 uv2 = current_uv + offset;
+
+    float luma_end1 = luma(fetch(uv1)) - luma_local_average;
+    float luma_end2 = luma(fetch(uv2)) - luma_local_average;
+    bool reached1 = abs(luma_end1) >= gradient_scaled;
+    bool reached2 = abs(luma_end2) >= gradient_scaled;
+
+    if (!reached1) uv1 -= offset;
+    if (!reached2) uv2 += offset;
+
+    for (int i = 2; i < ITERATIONS; i++) {
+        if (reached1 && reached2) break;
+        if (!reached1) {
+            luma_end1 = luma(fetch(uv1)) - luma_local_average;
+            reached1 = abs(luma_end1) >= gradient_scaled;
+            if (!reached1) uv1 -= offset * quality(i);
+        }
+        if (!reached2) {
+            luma_end2 = luma(fetch(uv2)) - luma_local_average;
+            reached2 = abs(luma_end2) >= gradient_scaled;
+            if (!reached2) uv2 += offset * quality(i);
+        }
+    }
+
+    float distance1 = is_horizontal ? (fragUv.x - uv1.x) : (fragUv.y - uv1.y);
+    float distance2 = is_horizontal ? (uv2.x - fragUv.x) : (uv2.y - fragUv.y);
+    bool is_direction1 = distance1 < distance2;
+    float distance_final = min(distance1, distance2);
+    float edge_thickness = distance1 + distance2;
+    float pixel_offset = -distance_final / edge_thickness + 0.5;
+
+    // Only blend if the end of the edge varies in the same direction as the center
+    bool is_luma_center_smaller = luma_center < luma_local_average;
+    bool correct_variation = ((is_direction1 ? luma_end1 : luma_end2) < 0.0) != is_luma_center_smaller;
+    float final_offset = correct_variation ? pixel_offset : 0.0;
+
+    // Subpixel aliasing
+    float luma_average = (1.0 / 12.0) * (2.0 * (luma_down_up + luma_left_right)
+        + luma_left_corners + luma_right_corners);
+    float subpixel_offset1 = clamp(abs(luma_average - luma_center) / luma_range, 0.0, 1.0);
+    float subpixel_offset2 = (-2.0 * subpixel_offset1 + 3.0) * subpixel_offset1 * subpixel_offset1;
+    final_offset = max(final_offset, subpixel_offset2 * subpixel_offset2 * subpixel);
+
+    vec2 final_uv = fragUv;
+    if (is_horizontal) {
+        final_uv.y += final_offset * step_length;
+    } else {
+        final_uv.x += final_offset * step_length;
+    }
+
+    outColor = vec4(fetch(final_uv), 1.0);
+}
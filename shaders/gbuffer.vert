@@ -0,0 +1,23 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_EXT_multiview : require
+
+// G-buffer pass for `VertexNT`, with the camera UBO of the unlit shaders
+
+layout(binding = 0) uniform Animation {
+    mat4 camera[2];
+    float anim;
+};
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inColor;
+layout(location = 2) in vec3 inNormal;
+
+layout(location = 0) out vec3 fragColor;
+layout(location = 1) out vec3 fragNormal;
+
+void main() {
+    gl_Position = camera[gl_ViewIndex] * vec4(inPosition, 1.0);
+    fragColor = inColor;
+    fragNormal = inNormal;
+}
@@ -0,0 +1,21 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_EXT_multiview : require
+
+layout(binding = 0) uniform Animation {
+    mat4 camera[2];
+    float anim;
+};
+
+// Transform and parameters of the current draw, see `outline::OutlineParams`
+layout(push_constant) uniform Outline {
+    mat4 model;
+    vec4 color;
+    float scale;
+};
+
+layout(location = 0) in vec3 inPosition;
+
+void main() {
+    gl_Position = camera[gl_ViewIndex] * model * vec4(inPosition * scale, 1.0);
+}
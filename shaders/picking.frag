@@ -0,0 +1,13 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// Object ID of the current draw, see `Picking::push_id()`
+layout(push_constant) uniform Pick {
+    uint id;
+};
+
+layout(location = 0) out uint outId;
+
+void main() {
+    outId = id;
+}
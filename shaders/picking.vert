@@ -0,0 +1,14 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_EXT_multiview : require
+
+layout(binding = 0) uniform Animation {
+    mat4 camera[2];
+    float anim;
+};
+
+layout(location = 0) in vec3 inPosition;
+
+void main() {
+    gl_Position = camera[gl_ViewIndex] * vec4(inPosition, 1.0);
+}
@@ -0,0 +1,42 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_EXT_multiview : require
+
+// Points drawn as camera-facing squares, one instance per point. Each instance is a strip of four
+// vertices, offset from the point in screen space by half its size.
+
+layout(binding = 0) uniform Animation {
+    mat4 camera[2];
+    float anim;
+};
+
+layout(push_constant) uniform PointParams {
+    vec2 viewport;
+    float size_scale;
+    uint round_points;
+};
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inColor;
+layout(location = 2) in float inSize;
+
+layout(location = 0) out vec3 fragColor;
+layout(location = 1) out vec2 fragCorner;
+
+const vec2 CORNERS[4] = vec2[](
+    vec2(-1.0, -1.0),
+    vec2(1.0, -1.0),
+    vec2(-1.0, 1.0),
+    vec2(1.0, 1.0)
+);
+
+void main() {
+    vec2 corner = CORNERS[gl_VertexIndex];
+    vec4 pos = camera[gl_ViewIndex] * vec4(inPosition, 1.0);
+
+    // Half the size in normalized device coordinates, which span two units
+    vec2 offset = corner * inSize * size_scale / viewport;
+    gl_Position = pos + vec4(offset * pos.w, 0.0, 0.0);
+    fragColor = inColor;
+    fragCorner = corner;
+}
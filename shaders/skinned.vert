@@ -0,0 +1,30 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_EXT_multiview : require
+
+layout(binding = 0) uniform Animation {
+    mat4 camera[2];
+    float anim;
+};
+
+// Joint matrices for the current frame, see `skinning::BoneBuffer`
+layout(std430, binding = 2) readonly buffer Bones {
+    mat4 bones[];
+};
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inColor;
+layout(location = 5) in uvec4 inJoints;
+layout(location = 6) in vec4 inWeights;
+
+layout(location = 0) out vec3 fragColor;
+
+void main() {
+    mat4 skin =
+        inWeights.x * bones[inJoints.x] +
+        inWeights.y * bones[inJoints.y] +
+        inWeights.z * bones[inJoints.z] +
+        inWeights.w * bones[inJoints.w];
+    gl_Position = camera[gl_ViewIndex] * skin * vec4(inPosition, 1.0);
+    fragColor = inColor;
+}
@@ -0,0 +1,78 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_EXT_multiview : require
+
+// Ambient occlusion from a geometry prepass (world position in xyz, linear depth in w, w = 0 for
+// the background), after McGuire et al., "The Alchemy Screen-Space Ambient Obscurance Algorithm".
+// Samples are taken on a spiral over the screen-space projection of a hemisphere around the
+// surface normal, which is reconstructed from neighboring positions.
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+layout(binding = 0) uniform sampler samp;
+layout(binding = 1) uniform texture2DArray geometry;
+
+layout(push_constant) uniform Ssao {
+    vec2 texel;
+    float radius;
+    float bias;
+    float intensity;
+    float projection_scale;
+};
+
+const int SAMPLES = 16;
+const float TURNS = 7.0;
+const float TAU = 6.28318530718;
+
+vec4 fetch(vec2 uv) {
+    return texture(sampler2DArray(geometry, samp), vec3(uv, float(gl_ViewIndex)));
+}
+
+void main() {
+    vec4 center = fetch(fragUv);
+    if (center.w <= 0.0) {
+        outColor = vec4(1.0);
+        return;
+    }
+    vec3 position = center.xyz;
+
+    // Reconstruct the normal, using the smaller difference on each axis to avoid crossing edges
+    vec3 right = fetch(fragUv + vec2(texel.x, 0.0)).xyz - position;
+    vec3 left = position - fetch(fragUv - vec2(texel.x, 0.0)).xyz;
+    vec3 down = fetch(fragUv + vec2(0.0, texel.y)).xyz - position;
+    vec3 up = position - fetch(fragUv - vec2(0.0, texel.y)).xyz;
+    vec3 dx = dot(right, right) < dot(left, left) ? right : left;
+    vec3 dy = dot(down, down) < dot(up, up) ? down : up;
+    vec3 normal = normalize(cross(dy, dx));
+
+    // Per-pixel rotation of the spiral, to trade banding for noise (removed by the blur)
+    vec2 pixel = fragUv / texel;
+    float angle = fract(52.9829189 * fract(dot(pixel, vec2(0.06711056, 0.00583715)))) * TAU;
+
+    float screen_radius = radius * projection_scale / center.w;
+
+    float occlusion = 0.0;
+    for (int i = 0; i < SAMPLES; i++) {
+        float alpha = (float(i) + 0.5) / float(SAMPLES);
+        float theta = alpha * TURNS * TAU + angle;
+        vec2 offset = vec2(cos(theta), sin(theta)) * alpha * screen_radius;
+
+        vec4 sample_geometry = fetch(fragUv + offset);
+        if (sample_geometry.w <= 0.0) continue;
+
+        vec3 v = sample_geometry.xyz - position;
+        float vv = dot(v, v);
+        float vn = dot(v, normal);
+
+        // Ignore samples far outside the hemisphere's radius
+        float falloff = max(radius * radius - vv, 0.0);
+        occlusion += falloff * falloff * max(vn - bias * center.w, 0.0) / (vv + 0.01);
+    }
+
+    float r4 = radius * radius * radius * radius;
+    occlusion = occlusion * 5.0 / (r4 * float(SAMPLES));
+    float ao = clamp(1.0 - occlusion * intensity, 0.0, 1.0);
+    outColor = vec4(ao, ao, ao, 1.0);
+}
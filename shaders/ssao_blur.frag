@@ -0,0 +1,42 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_EXT_multiview : require
+
+// Depth-aware 5x5 blur of the ambient occlusion term. ssao.frag rotates its samples by
+// interleaved gradient noise, which is spread evenly over every 3x3 block of pixels, so averaging
+// a slightly larger block removes it without leaving a pattern. Sampled without filtering, at
+// texel centers.
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+layout(binding = 0) uniform sampler samp;
+layout(binding = 1) uniform texture2DArray ao;
+layout(binding = 2) uniform texture2DArray geometry;
+
+layout(push_constant) uniform Blur {
+    vec2 texel;
+};
+
+const int RADIUS = 2;
+
+void main() {
+    float layer = float(gl_ViewIndex);
+    float center_depth = texture(sampler2DArray(geometry, samp), vec3(fragUv, layer)).w;
+
+    float sum = 0.0;
+    float weight_sum = 0.0;
+    for (int x = -RADIUS; x <= RADIUS; x++) {
+        for (int y = -RADIUS; y <= RADIUS; y++) {
+            vec2 uv = fragUv + vec2(float(x), float(y)) * texel;
+            float depth = texture(sampler2DArray(geometry, samp), vec3(uv, layer)).w;
+            float weight = 1.0 / (0.0001 + abs(depth - center_depth));
+            sum += texture(sampler2DArray(ao, samp), vec3(uv, layer)).r * weight;
+            weight_sum += weight;
+        }
+    }
+
+    float result = sum / weight_sum;
+    outColor = vec4(result, result, result, 1.0);
+}
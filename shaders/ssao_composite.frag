@@ -0,0 +1,18 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_EXT_multiview : require
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+layout(binding = 0) uniform sampler samp;
+layout(binding = 1) uniform texture2DArray scene;
+layout(binding = 2) uniform texture2DArray ao;
+
+void main() {
+    vec3 uv = vec3(fragUv, float(gl_ViewIndex));
+    vec4 color = texture(sampler2DArray(scene, samp), uv);
+    color.rgb *= texture(sampler2DArray(ao, samp), uv).r;
+    outColor = color;
+}
@@ -0,0 +1,11 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec3 fragPosition;
+layout(location = 1) in float fragDepth;
+
+layout(location = 0) out vec4 outGeometry;
+
+void main() {
+    outGeometry = vec4(fragPosition, fragDepth);
+}
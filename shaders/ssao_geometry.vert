@@ -0,0 +1,27 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_EXT_multiview : require
+
+// Geometry prepass for `post::Ssao`: writes world position and linear depth
+
+layout(binding = 0) uniform Animation {
+    mat4 camera[2];
+    float anim;
+};
+
+// Transform of the current draw, as pushed by `StarterKit::draw()`
+layout(push_constant) uniform Model {
+    mat4 model;
+};
+
+layout(location = 0) in vec3 inPosition;
+
+layout(location = 0) out vec3 fragPosition;
+layout(location = 1) out float fragDepth;
+
+void main() {
+    vec4 world = model * vec4(inPosition, 1.0);
+    gl_Position = camera[gl_ViewIndex] * world;
+    fragPosition = world.xyz;
+    fragDepth = gl_Position.w;
+}
@@ -0,0 +1,36 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// Both layers of a multiview target side by side, the left eye on the left; or only the left
+// eye, filling the output
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+layout(binding = 0) uniform sampler samp;
+layout(binding = 1) uniform texture2DArray scene;
+
+layout(push_constant) uniform StereoPreview {
+    uint side_by_side;
+    // Encode the (linear) scene for a UNORM output
+    uint encode_srgb;
+};
+
+vec3 linear_to_srgb(vec3 c) {
+    c = max(c, 0.0);
+    return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, c));
+}
+
+void main() {
+    float eye = 0.0;
+    vec2 uv = fragUv;
+    if (side_by_side != 0) {
+        eye = fragUv.x < 0.5 ? 0.0 : 1.0;
+        uv.x = fragUv.x * 2.0 - eye;
+    }
+    outColor = texture(sampler2DArray(scene, samp), vec3(uv, eye));
+    if (encode_srgb != 0) {
+        outColor.rgb = linear_to_srgb(outColor.rgb);
+    }
+}
@@ -0,0 +1,55 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_EXT_multiview : require
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+layout(binding = 0) uniform sampler samp;
+layout(binding = 1) uniform texture2DArray hdr;
+
+layout(push_constant) uniform Tonemap {
+    int op;
+    float exposure;
+    float gamma;
+    uint encode_srgb;
+};
+
+vec3 linear_to_srgb(vec3 c) {
+    c = max(c, 0.0);
+    return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, c));
+}
+
+// Narkowicz's ACES filmic curve fit
+vec3 aces(vec3 x) {
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+    return clamp((x * (a * x + b)) / (x * (c * x + d) + e), 0.0, 1.0);
+}
+
+vec3 reinhard(vec3 x) {
+    return x / (1.0 + x);
+}
+
+void main() {
+    vec3 color = texture(sampler2DArray(hdr, samp), vec3(fragUv, float(gl_ViewIndex))).rgb;
+    color *= exposure;
+
+    if (op == 0) {
+        color = reinhard(color);
+    } else if (op == 1) {
+        color = aces(color);
+    } else {
+        color = clamp(color, 0.0, 1.0);
+    }
+
+    color = pow(color, vec3(1.0 / gamma));
+    if (encode_srgb != 0) {
+        color = linear_to_srgb(color);
+    }
+    outColor = vec4(color, 1.0);
+}
@@ -11,6 +11,17 @@ layout(binding = 1) uniform Animation {
 
 layout(location = 0) out vec4 outColor;
 
+// Set from PipelineSettings::encode_srgb, for UNORM outputs which store values as written
+layout(constant_id = 0) const bool ENCODE_SRGB = false;
+
+vec3 linear_to_srgb(vec3 c) {
+    c = max(c, 0.0);
+    return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, c));
+}
+
 void main() {
     outColor = vec4(fragColor, 1.0);
+    if (ENCODE_SRGB) {
+        outColor.rgb = linear_to_srgb(outColor.rgb);
+    }
 }
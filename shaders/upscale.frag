@@ -0,0 +1,17 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_EXT_multiview : require
+
+// The scene drawn at a lower (or higher) resolution, filtered to fill the output. Input and
+// output share a format, so colors are copied without encoding.
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+layout(binding = 0) uniform sampler samp;
+layout(binding = 1) uniform texture2DArray scene;
+
+void main() {
+    outColor = texture(sampler2DArray(scene, samp), vec3(fragUv, float(gl_ViewIndex)));
+}
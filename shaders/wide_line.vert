@@ -0,0 +1,40 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_EXT_multiview : require
+
+// Lines drawn as quads, for devices without the wideLines feature. Each segment is four vertices,
+// offset from the line in screen space by half the width.
+
+layout(binding = 0) uniform Animation {
+    mat4 camera[2];
+    float anim;
+};
+
+layout(push_constant) uniform DrawParams {
+    vec4 tint;
+    float point_size;
+    float line_width;
+    vec2 viewport;
+};
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inColor;
+layout(location = 2) in vec3 inOther;
+layout(location = 3) in float inSide;
+
+layout(location = 0) out vec3 fragColor;
+
+void main() {
+    vec4 pos = camera[gl_ViewIndex] * vec4(inPosition, 1.0);
+    vec4 other = camera[gl_ViewIndex] * vec4(inOther, 1.0);
+
+    // Direction of the line in pixels
+    vec2 dir = (other.xy / other.w - pos.xy / pos.w) * viewport;
+    dir = length(dir) > 1e-6 ? normalize(dir) : vec2(1.0, 0.0);
+    vec2 normal = vec2(-dir.y, dir.x);
+
+    // Half the width in normalized device coordinates, which span two units
+    vec2 offset = normal * inSide * line_width / viewport;
+    gl_Position = pos + vec4(offset * pos.w, 0.0, 0.0);
+    fragColor = inColor;
+}
@@ -0,0 +1,18 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_EXT_multiview : require
+
+// Camera and panel transform of each view
+layout(push_constant) uniform Panel {
+    mat4 transform[2];
+};
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inUv;
+
+layout(location = 0) out vec2 fragUv;
+
+void main() {
+    gl_Position = transform[gl_ViewIndex] * vec4(inPosition, 1.0);
+    fragUv = inUv.xy;
+}
@@ -1,12 +1,59 @@
+use crate::defaults::ACQUIRE_TIMEOUT;
+use crate::hardware_query::DevicePreference;
+use crate::ENGINE_NAME;
 use anyhow::Result;
 use erupt::vk;
+use std::time::Duration;
 
 /// Application info
 pub struct AppInfo {
     pub(crate) name: String,
     pub(crate) version: u32,
     pub(crate) api_version: u32,
+    pub(crate) engine_name: String,
+    pub(crate) engine_version: u32,
     pub(crate) validation: bool,
+    pub(crate) validation_features: ValidationFeatures,
+    pub(crate) synchronization2: bool,
+    pub(crate) async_compute: bool,
+    pub(crate) transfer_queue: bool,
+    pub(crate) swapchain_images: Option<u32>,
+    pub(crate) vsync: bool,
+    pub(crate) srgb_swapchain: bool,
+    pub(crate) acquire_timeout: Option<Duration>,
+    pub(crate) redraw_on_demand: bool,
+    pub(crate) background_policy: BackgroundPolicy,
+    pub(crate) xr_reconnect: bool,
+    pub(crate) leak_warnings: bool,
+    pub(crate) device_preference: DevicePreference,
+}
+
+/// Optional validation layer features, enabled through `VK_EXT_validation_features`. These are
+/// only used when validation is enabled.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ValidationFeatures {
+    /// GPU-assisted validation, which instruments shaders to catch out of bounds descriptor and
+    /// buffer accesses. Cannot be combined with `debug_printf`.
+    pub gpu_assisted: bool,
+    /// Warnings about API usage which is valid but likely to perform poorly
+    pub best_practices: bool,
+    /// Detection of read/write hazards caused by missing or insufficient barriers
+    pub synchronization: bool,
+    /// `debugPrintfEXT` in shaders, with output printed to stdout
+    pub debug_printf: bool,
+}
+
+/// What the windowed backends do while the window doesn't have focus, similar to how the OpenXR
+/// backend stops drawing while the session isn't running
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum BackgroundPolicy {
+    /// Draw as usual
+    #[default]
+    KeepRendering,
+    /// Draw at most this many frames per second
+    Throttle(f32),
+    /// Draw only when the window system asks, such as when the window is exposed
+    Pause,
 }
 
 // TODO: Device extensions!
@@ -26,10 +73,131 @@ impl AppInfo {
         Ok(self)
     }
 
+    /// Engine name given to the Vulkan driver and OpenXR runtime, for engines built on this
+    /// crate; drivers and telemetry may key off it. Defaults to `ENGINE_NAME`.
+    pub fn engine_name(mut self, name: String) -> Self {
+        self.engine_name = name;
+        self
+    }
+
+    /// Engine version given along with `engine_name()`. Defaults to `engine_version()`.
+    pub fn engine_version(mut self, major: u32, minor: u32, patch: u32) -> Self {
+        self.engine_version = vk::make_version(major, minor, patch);
+        self
+    }
+
     pub fn validation(mut self, validation: bool) -> Self {
         self.validation = validation;
         self
     }
+
+    /// Enable validation with the given optional features
+    pub fn validation_features(mut self, features: ValidationFeatures) -> Self {
+        self.validation = true;
+        self.validation_features = features;
+        self
+    }
+
+    /// Enable `debugPrintfEXT` in shaders, with output printed to stdout. Only takes effect when
+    /// validation is enabled.
+    pub fn debug_printf(mut self, debug_printf: bool) -> Self {
+        self.validation_features.debug_printf = debug_printf;
+        self
+    }
+
+    /// Number of swapchain images to request in the windowed backend, clamped to what the
+    /// surface supports. Defaults to one more than the surface's minimum.
+    pub fn swapchain_images(mut self, count: u32) -> Self {
+        self.swapchain_images = Some(count);
+        self
+    }
+
+    /// Wait for vertical blank when presenting in the windowed backends (FIFO). Enabled by
+    /// default; when disabled, MAILBOX is preferred, then IMMEDIATE, falling back to FIFO if the
+    /// surface supports neither. The OpenXR runtime paces VR frames itself.
+    pub fn vsync(mut self, vsync: bool) -> Self {
+        self.vsync = vsync;
+        self
+    }
+
+    /// Prefer an sRGB swapchain format (`defaults::COLOR_FORMAT`), which encodes the linear
+    /// colors shaders output. Enabled by default; when disabled, a UNORM format
+    /// (`defaults::UNORM_COLOR_FORMAT`) is preferred, storing outputs as written, for apps which
+    /// do their own gamma, such as UI libraries blending in sRGB space. Pipelines drawing linear
+    /// colors into it need `PipelineSettings::encode_srgb`; see the `color` module. The OpenXR
+    /// swapchain is always sRGB.
+    pub fn srgb_swapchain(mut self, srgb: bool) -> Self {
+        self.srgb_swapchain = srgb;
+        self
+    }
+
+    /// How long the windowed and display backends wait for each attempt to acquire a swapchain
+    /// image. On timeout the swapchain is rebuilt and acquisition retried, failing with an
+    /// `AcquireError` after `defaults::ACQUIRE_ATTEMPTS` attempts. None waits forever, which can
+    /// hang on some drivers while displays are reconfigured. Defaults to
+    /// `defaults::ACQUIRE_TIMEOUT`.
+    pub fn acquire_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.acquire_timeout = timeout;
+        self
+    }
+
+    /// In the windowed backends, wait for events (`ControlFlow::Wait`) rather than redrawing
+    /// continuously, and only draw frames when the window system asks (such as on resize or
+    /// expose) or after `Platform::request_redraw()`. Saves power for editors and visualizations
+    /// whose scene rarely changes. Has no effect in VR.
+    pub fn redraw_on_demand(mut self, redraw_on_demand: bool) -> Self {
+        self.redraw_on_demand = redraw_on_demand;
+        self
+    }
+
+    /// How to draw while the window is unfocused. Minimized windows are never drawn.
+    pub fn background_policy(mut self, policy: BackgroundPolicy) -> Self {
+        self.background_policy = policy;
+        self
+    }
+
+    /// Use `VK_KHR_synchronization2` for barriers and submits where the device supports it.
+    /// Enabled by default; disable to exercise the original synchronization path.
+    pub fn synchronization2(mut self, synchronization2: bool) -> Self {
+        self.synchronization2 = synchronization2;
+        self
+    }
+
+    /// Create a queue from a compute-only family where the device has one, for
+    /// `async_compute::AsyncCompute` to overlap work with rendering. See `Core::compute_queue`.
+    pub fn async_compute(mut self, async_compute: bool) -> Self {
+        self.async_compute = async_compute;
+        self
+    }
+
+    /// Create a queue from a transfer-only family where the device has one, for
+    /// `assets::AssetLoader` to upload alongside rendering. See `Core::transfer_queue`.
+    pub fn transfer_queue(mut self, transfer_queue: bool) -> Self {
+        self.transfer_queue = transfer_queue;
+        self
+    }
+
+    /// In the OpenXR backend, when the session or runtime is lost (such as when the headset is
+    /// disconnected), wait for it to come back and reconnect instead of exiting. The app and its
+    /// Vulkan resources are kept alive; it receives `PlatformEvent::Suspended`, then `Resumed`
+    /// once the new session is running.
+    pub fn xr_reconnect(mut self, reconnect: bool) -> Self {
+        self.xr_reconnect = reconnect;
+        self
+    }
+
+    /// On exit, print any buffers, images, pipelines and descriptor sets which were never freed
+    /// (see `Core::resource_stats()`)
+    pub fn leak_warnings(mut self, leak_warnings: bool) -> Self {
+        self.leak_warnings = leak_warnings;
+        self
+    }
+
+    /// How the physical device is chosen, and which extensions and features it must support
+    pub fn device_preference(mut self, preference: DevicePreference) -> Self {
+        self.device_preference = preference;
+        self
+    }
 }
 
 impl Default for AppInfo {
@@ -39,7 +207,22 @@ impl Default for AppInfo {
             name: env!("CARGO_PKG_NAME").to_owned(),
             api_version: vk::make_version(1, 1, 0),
             version: vk::make_version(1, 0, 0),
+            engine_name: ENGINE_NAME.to_owned(),
+            engine_version: engine_version(),
             validation: false,
+            validation_features: ValidationFeatures::default(),
+            synchronization2: true,
+            async_compute: false,
+            transfer_queue: false,
+            swapchain_images: None,
+            vsync: true,
+            srgb_swapchain: true,
+            acquire_timeout: Some(ACQUIRE_TIMEOUT),
+            redraw_on_demand: false,
+            background_policy: BackgroundPolicy::default(),
+            xr_reconnect: false,
+            leak_warnings: false,
+            device_preference: DevicePreference::default(),
         }
     }
 }
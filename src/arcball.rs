@@ -1,6 +1,7 @@
 use nalgebra::{Matrix4, Point3, Vector3};
 
 /// An arcball camera
+#[derive(Copy, Clone, Debug)]
 pub struct ArcBall {
     pub pivot: Point3<f32>,
     pub distance: f32,
@@ -8,6 +9,9 @@ pub struct ArcBall {
     pub pitch: f32,
     pub fov: f32,
     pub clipping: (f32, f32),
+    /// Map the far plane to a depth of 0 and the near plane to 1, which spreads floating point
+    /// depth precision far more evenly. Requires `PipelineSettings::reverse_z`.
+    pub reverse_z: bool,
 }
 
 impl ArcBall {
@@ -18,12 +22,16 @@ impl ArcBall {
 
     /// Perspective matrix
     pub fn perspective(&self, width: u32, height: u32) -> Matrix4<f32> {
-        let mut perspective = Matrix4::new_perspective(
-            width as f32 / height as f32,
-            self.fov,
-            self.clipping.0,
-            self.clipping.1,
-        );
+        self.perspective_with_aspect(width as f32 / height as f32)
+    }
+
+    /// Perspective matrix for a viewport of width over height `aspect`
+    pub fn perspective_with_aspect(&self, aspect: f32) -> Matrix4<f32> {
+        if self.reverse_z {
+            return reverse_z_perspective(aspect, self.fov, self.clipping.0, self.clipping.1);
+        }
+        let mut perspective =
+            Matrix4::new_perspective(aspect, self.fov, self.clipping.0, self.clipping.1);
         perspective[(1, 1)] *= -1.; // TODO: This is a stupid hack.
         perspective
     }
@@ -42,6 +50,13 @@ impl ArcBall {
         ) * self.distance
     }
 
+    /// Center the camera on `bounds` (in world space, e.g. `ManagedMesh::bounds` of an
+    /// untransformed mesh) and move it back until they fit in view
+    pub fn frame(&mut self, bounds: &crate::mesh::Bounds) {
+        self.pivot = bounds.center.into();
+        self.distance = bounds.framing_distance(self.fov).max(self.clipping.0 * 2.0);
+    }
+
     /// Up direction for the camera
     pub fn up() -> Vector3<f32> {
         Vector3::new(0.0, 1.0, 0.0)
@@ -57,6 +72,23 @@ impl Default for ArcBall {
             pitch: 1.0,
             fov: 45.0f32.to_radians(),
             clipping: (0.1, 2000.0),
+            reverse_z: false,
         }
     }
 }
+
+/// Perspective matrix with a Vulkan (flipped Y, 0 to 1 depth) clip space, mapping `near` to a
+/// depth of 1 and `far` to 0
+pub fn reverse_z_perspective(aspect: f32, fovy: f32, near: f32, far: f32) -> Matrix4<f32> {
+    let f = 1.0 / (fovy / 2.0).tan();
+    let a11 = f / aspect;
+    let a22 = -f;
+    let a33 = near / (far - near);
+    let a34 = (far * near) / (far - near);
+    Matrix4::new(
+        a11, 0.0, 0.0, 0.0, //
+        0.0, a22, 0.0, 0.0, //
+        0.0, 0.0, a33, a34, //
+        0.0, 0.0, -1.0, 0.0, //
+    )
+}
@@ -0,0 +1,612 @@
+//! Loading images and meshes in the background. Assets are decoded by a pool of worker threads,
+//! uploaded on the transfer-only queue where there is one (see `AppInfo::transfer_queue()`),
+//! and handed to the main loop once ready, a few per frame so that large scenes stream in
+//! without stalling it.
+//!
+//! ```ignore
+//! let mut assets = AssetLoader::new(core.clone())?;
+//! let rock = assets.load(|| {
+//!     let (vertices, indices) = parse_obj(&std::fs::read("rock.obj")?)?;
+//!     Ok(AssetData::mesh(&vertices, indices))
+//! });
+//! // Each frame
+//! for loaded in assets.update()? {
+//!     match loaded.asset? {
+//!         Asset::Mesh(mesh) => meshes.insert(loaded.id, mesh),
+//!         Asset::Image(image) => textures.insert(loaded.id, image),
+//!     };
+//! }
+//! ```
+//!
+//! Assets are only handed out once their upload has completed, so frames recorded afterwards
+//! may use them right away.
+use crate::memory::{ManagedBuffer, ManagedImage, UsageFlags};
+use crate::mesh::{vertex_positions, Bounds, ManagedMesh};
+use crate::sync2::{self, Access, ImageBarrier, Stages};
+use crate::synchronization::SharedFence;
+use crate::vertex::VertexLayout;
+use crate::SharedCore;
+use anyhow::{ensure, format_err, Result};
+use erupt::vk;
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+/// Decoded contents of an asset, ready to upload
+pub enum AssetData {
+    /// Tightly packed rows of texels in `format`, an uncompressed color format. Uploading fails if
+    /// `data` isn't exactly `width * height` texels.
+    Image {
+        width: u32,
+        height: u32,
+        format: vk::Format,
+        data: Vec<u8>,
+    },
+    /// Vertices of any `VertexLayout`, see `AssetData::mesh()`
+    Mesh {
+        vertices: Vec<u8>,
+        n_vertices: u32,
+        indices: Vec<u32>,
+        bounds: Option<Bounds>,
+    },
+}
+
+impl AssetData {
+    /// A mesh of `vertices` and `indices`, with its bounds computed on the worker thread
+    pub fn mesh<V: VertexLayout>(vertices: &[V], indices: Vec<u32>) -> Self {
+        AssetData::Mesh {
+            vertices: bytemuck::cast_slice(vertices).to_vec(),
+            n_vertices: vertices.len() as u32,
+            bounds: vertex_positions(vertices).and_then(Bounds::from_positions),
+            indices,
+        }
+    }
+
+    /// Bytes to upload
+    pub fn size(&self) -> u64 {
+        match self {
+            AssetData::Image { data, .. } => data.len() as u64,
+            AssetData::Mesh {
+                vertices, indices, ..
+            } => (vertices.len() + std::mem::size_of_val(indices.as_slice())) as u64,
+        }
+    }
+}
+
+/// An uploaded asset
+pub enum Asset {
+    /// Sampled image in `SHADER_READ_ONLY_OPTIMAL`
+    Image(ManagedImage),
+    Mesh(ManagedMesh),
+}
+
+/// Identifies a load started with `AssetLoader::load()`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AssetId(u64);
+
+/// An asset handed out by `AssetLoader::update()`, or the error decoding or uploading it
+pub struct LoadedAsset {
+    pub id: AssetId,
+    pub asset: Result<Asset>,
+}
+
+/// How much work `AssetLoader::update()` does per call
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AssetBudget {
+    /// Bytes of decoded assets to start uploading. An asset larger than this is uploaded alone.
+    pub upload_bytes: u64,
+    /// Finished assets to hand out
+    pub assets: usize,
+}
+
+impl Default for AssetBudget {
+    fn default() -> Self {
+        Self {
+            upload_bytes: 64 * 1024 * 1024,
+            assets: 16,
+        }
+    }
+}
+
+type Decode = Box<dyn FnOnce() -> Result<AssetData> + Send>;
+
+/// An upload on the loader's queue
+struct Upload {
+    id: AssetId,
+    asset: Asset,
+    _staging: ManagedBuffer,
+    command_buffer: vk::CommandBuffer,
+    fence: SharedFence,
+}
+
+/// Decodes assets on worker threads and uploads them, see the module documentation
+pub struct AssetLoader {
+    /// How much work each `update()` does
+    pub budget: AssetBudget,
+    /// None once dropped, which stops the workers
+    jobs: Option<Sender<(AssetId, Decode)>>,
+    decoded: Receiver<(AssetId, Result<AssetData>)>,
+    /// Decoded assets waiting for upload budget
+    queued: VecDeque<(AssetId, AssetData)>,
+    uploads: Vec<Upload>,
+    /// Failed loads to hand out
+    failed: VecDeque<LoadedAsset>,
+    /// Loads not yet handed out
+    pending: usize,
+    next_id: u64,
+    queue: vk::Queue,
+    queue_families: Vec<u32>,
+    command_pool: vk::CommandPool,
+    core: SharedCore,
+}
+
+impl AssetLoader {
+    /// A loader with a worker for every CPU but one
+    pub fn new(core: SharedCore) -> Result<Self> {
+        let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+        Self::with_workers(core, workers.saturating_sub(1).max(1))
+    }
+
+    /// A loader decoding on `workers` threads
+    pub fn with_workers(core: SharedCore, workers: usize) -> Result<Self> {
+        let (queue, queue_family) = match core.transfer_queue {
+            Some(transfer) => (transfer.queue, transfer.family),
+            None => (core.queue, core.queue_family),
+        };
+        let mut queue_families = vec![core.queue_family];
+        if queue_family != core.queue_family {
+            queue_families.push(queue_family);
+        }
+
+        let create_info = vk::CommandPoolCreateInfoBuilder::new()
+            .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+            .queue_family_index(queue_family);
+        let command_pool =
+            unsafe { core.device.create_command_pool(&create_info, None, None) }.result()?;
+
+        // Workers share the job queue, taking one job at a time
+        let (jobs, job_receiver) = mpsc::channel::<(AssetId, Decode)>();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let (results, decoded) = mpsc::channel();
+        for i in 0..workers.max(1) {
+            let job_receiver = job_receiver.clone();
+            let results = results.clone();
+            std::thread::Builder::new()
+                .name(format!("asset worker {}", i))
+                .spawn(move || loop {
+                    let job = job_receiver
+                        .lock()
+                        .map_err(drop)
+                        .and_then(|r| r.recv().map_err(drop));
+                    let (id, decode) = match job {
+                        Ok(job) => job,
+                        Err(()) => break,
+                    };
+                    let data = panic::catch_unwind(AssertUnwindSafe(decode))
+                        .unwrap_or_else(|_| Err(format_err!("Asset decoding panicked")));
+                    if results.send((id, data)).is_err() {
+                        break;
+                    }
+                })?;
+        }
+
+        Ok(Self {
+            budget: AssetBudget::default(),
+            jobs: Some(jobs),
+            decoded,
+            queued: VecDeque::new(),
+            uploads: vec![],
+            failed: VecDeque::new(),
+            pending: 0,
+            next_id: 0,
+            queue,
+            queue_families,
+            command_pool,
+            core,
+        })
+    }
+
+    /// Decode an asset with `decode` on a worker thread, and upload it
+    pub fn load<F>(&mut self, decode: F) -> AssetId
+    where
+        F: FnOnce() -> Result<AssetData> + Send + 'static,
+    {
+        let id = AssetId(self.next_id);
+        self.next_id += 1;
+        self.pending += 1;
+        let sent = self
+            .jobs
+            .as_ref()
+            .map(|jobs| jobs.send((id, Box::new(decode))).is_ok());
+        if sent != Some(true) {
+            self.failed.push_back(LoadedAsset {
+                id,
+                asset: Err(format_err!("Asset workers have stopped")),
+            });
+        }
+        id
+    }
+
+    /// Loads which have not been handed out yet
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    /// Families of the graphics and upload queues, which uploaded assets are shared between.
+    /// Contains one family without a transfer-only queue.
+    pub fn queue_families(&self) -> &[u32] {
+        &self.queue_families
+    }
+
+    /// Start uploading decoded assets and hand out finished ones, within `budget`. Call once per
+    /// frame from the thread rendering frames.
+    pub fn update(&mut self) -> Result<Vec<LoadedAsset>> {
+        // Collect decoded assets
+        while let Ok((id, data)) = self.decoded.try_recv() {
+            match data {
+                Ok(data) => self.queued.push_back((id, data)),
+                Err(e) => self.failed.push_back(LoadedAsset { id, asset: Err(e) }),
+            }
+        }
+
+        // Start uploads
+        let mut upload_bytes = 0;
+        while let Some((_, data)) = self.queued.front() {
+            if upload_bytes > 0 && upload_bytes + data.size() > self.budget.upload_bytes {
+                break;
+            }
+            upload_bytes += data.size();
+            let (id, data) = self.queued.pop_front().unwrap();
+            if let Err(e) = self.upload(id, data) {
+                self.failed.push_back(LoadedAsset { id, asset: Err(e) });
+            }
+        }
+
+        // Hand out failures and finished uploads
+        let mut loaded = vec![];
+        while loaded.len() < self.budget.assets {
+            match self.failed.pop_front() {
+                Some(failed) => loaded.push(failed),
+                None => break,
+            }
+        }
+        let mut idx = 0;
+        while idx < self.uploads.len() && loaded.len() < self.budget.assets {
+            if !self.uploads[idx].fence.is_signalled()? {
+                idx += 1;
+                continue;
+            }
+            let upload = self.uploads.remove(idx);
+            unsafe {
+                self.core
+                    .device
+                    .free_command_buffers(self.command_pool, &[upload.command_buffer]);
+            }
+            loaded.push(LoadedAsset {
+                id: upload.id,
+                asset: Ok(upload.asset),
+            });
+        }
+
+        self.pending -= loaded.len();
+        Ok(loaded)
+    }
+
+    /// Create the asset's resources, and submit the copies into them
+    fn upload(&mut self, id: AssetId, data: AssetData) -> Result<()> {
+        let ci = vk::BufferCreateInfoBuilder::new()
+            .size(data.size().max(1))
+            .usage(vk::BufferUsageFlags::TRANSFER_SRC)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let mut staging = ManagedBuffer::new(self.core.clone(), ci, UsageFlags::UPLOAD)?;
+
+        let allocate_info = vk::CommandBufferAllocateInfoBuilder::new()
+            .command_pool(self.command_pool)
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_buffer_count(1);
+        let command_buffer =
+            unsafe { self.core.device.allocate_command_buffers(&allocate_info) }.result()?[0];
+        let free = |core: &SharedCore| unsafe {
+            core.device
+                .free_command_buffers(self.command_pool, &[command_buffer]);
+        };
+
+        let begin_info = vk::CommandBufferBeginInfoBuilder::new()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        if let Err(e) = unsafe {
+            self.core
+                .device
+                .begin_command_buffer(command_buffer, &begin_info)
+        }
+        .result()
+        {
+            free(&self.core);
+            return Err(e.into());
+        }
+
+        let asset = match self.record(command_buffer, &mut staging, data) {
+            Ok(asset) => asset,
+            Err(e) => {
+                unsafe {
+                    let _ = self.core.device.end_command_buffer(command_buffer);
+                }
+                free(&self.core);
+                return Err(e);
+            }
+        };
+
+        let fence = SharedFence::new(self.core.clone())?;
+        let submitted = unsafe { self.core.device.end_command_buffer(command_buffer) }
+            .result()
+            .map_err(anyhow::Error::from)
+            .and_then(|()| {
+                self.core
+                    .submit_to(self.queue, command_buffer, &[], &[], Some(fence.fence()))
+            });
+        if let Err(e) = submitted {
+            free(&self.core);
+            return Err(e);
+        }
+
+        let asset = match asset {
+            Asset::Image(mut image) => {
+                image.set_ready(fence.clone());
+                Asset::Image(image)
+            }
+            Asset::Mesh(mut mesh) => {
+                mesh.vertices.set_ready(fence.clone());
+                mesh.indices.set_ready(fence.clone());
+                Asset::Mesh(mesh)
+            }
+        };
+
+        self.uploads.push(Upload {
+            id,
+            asset,
+            _staging: staging,
+            command_buffer,
+            fence,
+        });
+        Ok(())
+    }
+
+    /// Write `data` to `staging`, and record the copies from it into new resources
+    fn record(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        staging: &mut ManagedBuffer,
+        data: AssetData,
+    ) -> Result<Asset> {
+        match data {
+            AssetData::Image {
+                width,
+                height,
+                format,
+                data,
+            } => {
+                let texel_size = texel_size(format)
+                    .ok_or_else(|| format_err!("Unsupported image format {:?}", format))?;
+                let expected = width as u64 * height as u64 * texel_size;
+                ensure!(
+                    data.len() as u64 == expected,
+                    "A {}x{} {:?} image is {} bytes, but {} were given",
+                    width,
+                    height,
+                    format,
+                    expected,
+                    data.len()
+                );
+                staging.write_bytes(0, &data)?;
+
+                let mut ci = vk::ImageCreateInfoBuilder::new()
+                    .image_type(vk::ImageType::_2D)
+                    .extent(vk::Extent3D {
+                        width,
+                        height,
+                        depth: 1,
+                    })
+                    .mip_levels(1)
+                    .array_layers(1)
+                    .format(format)
+                    .tiling(vk::ImageTiling::OPTIMAL)
+                    .initial_layout(vk::ImageLayout::UNDEFINED)
+                    .usage(vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED)
+                    .samples(vk::SampleCountFlagBits::_1);
+                let (sharing_mode, indices) = self.sharing();
+                ci.sharing_mode = sharing_mode;
+                ci.queue_family_index_count = indices.len() as u32;
+                ci.p_queue_family_indices = indices.as_ptr();
+                let mut image =
+                    ManagedImage::new(self.core.clone(), ci, UsageFlags::FAST_DEVICE_ACCESS)?;
+                let range = image.subresource_range();
+
+                let to_transfer = ImageBarrier {
+                    image: image.instance(),
+                    old_layout: vk::ImageLayout::UNDEFINED,
+                    new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    subresource_range: range,
+                    src_stages: Stages::NONE_KHR,
+                    src_access: Access::NONE_KHR,
+                    dst_stages: Stages::TRANSFER_KHR,
+                    dst_access: Access::TRANSFER_WRITE_KHR,
+                };
+                self.core.cmd_barriers(command_buffer, &[], &[to_transfer]);
+
+                let copy = vk::BufferImageCopyBuilder::new()
+                    .image_subresource(vk::ImageSubresourceLayers {
+                        aspect_mask: range.aspect_mask,
+                        mip_level: 0,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    })
+                    .image_extent(vk::Extent3D {
+                        width,
+                        height,
+                        depth: 1,
+                    });
+                unsafe {
+                    self.core.device.cmd_copy_buffer_to_image(
+                        command_buffer,
+                        staging.instance(),
+                        image.instance(),
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        &[copy],
+                    );
+                }
+
+                // Transfer queues can't name shader stages; frames are ordered after the upload
+                // by waiting on its fence before handing the image out
+                let to_sampled = ImageBarrier {
+                    image: image.instance(),
+                    old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    subresource_range: range,
+                    src_stages: Stages::TRANSFER_KHR,
+                    src_access: Access::TRANSFER_WRITE_KHR,
+                    dst_stages: Stages::NONE_KHR,
+                    dst_access: Access::NONE_KHR,
+                };
+                self.core.cmd_barriers(command_buffer, &[], &[to_sampled]);
+
+                let layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+                image.set_layout(layout, sync2::layout_usage(layout).0);
+                Ok(Asset::Image(image))
+            }
+            AssetData::Mesh {
+                vertices,
+                n_vertices,
+                indices,
+                bounds,
+            } => {
+                let indices = bytemuck::cast_slice(&indices);
+                staging.write_bytes(0, &vertices)?;
+                staging.write_bytes(vertices.len() as u64, indices)?;
+
+                // Transfer source for `ManagedMesh::download()`, as in `upload_mesh()`
+                let vertex_buffer =
+                    self.buffer(vertices.len() as u64, vk::BufferUsageFlags::VERTEX_BUFFER)?;
+                let index_buffer =
+                    self.buffer(indices.len() as u64, vk::BufferUsageFlags::INDEX_BUFFER)?;
+
+                let copies = [
+                    (vertex_buffer.instance(), 0, vertices.len() as u64),
+                    (
+                        index_buffer.instance(),
+                        vertices.len() as u64,
+                        indices.len() as u64,
+                    ),
+                ];
+                for (buffer, offset, size) in copies {
+                    if size == 0 {
+                        continue;
+                    }
+                    let region = vk::BufferCopyBuilder::new()
+                        .src_offset(offset)
+                        .dst_offset(0)
+                        .size(size);
+                    unsafe {
+                        self.core.device.cmd_copy_buffer(
+                            command_buffer,
+                            staging.instance(),
+                            buffer,
+                            &[region],
+                        );
+                    }
+                }
+
+                Ok(Asset::Mesh(ManagedMesh {
+                    vertices: vertex_buffer,
+                    indices: index_buffer,
+                    n_vertices,
+                    n_indices: (indices.len() / std::mem::size_of::<u32>()) as u32,
+                    bounds,
+                }))
+            }
+        }
+    }
+
+    /// A device-local buffer of `size` bytes to copy into, shared between `queue_families`
+    fn buffer(&self, size: u64, usage: vk::BufferUsageFlags) -> Result<ManagedBuffer> {
+        let mut ci = vk::BufferCreateInfoBuilder::new()
+            .size(size.max(1))
+            .usage(usage | vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::TRANSFER_SRC);
+        let (sharing_mode, indices) = self.sharing();
+        ci.sharing_mode = sharing_mode;
+        ci.queue_family_index_count = indices.len() as u32;
+        ci.p_queue_family_indices = indices.as_ptr();
+        ManagedBuffer::new(self.core.clone(), ci, UsageFlags::FAST_DEVICE_ACCESS)
+    }
+
+    /// Sharing mode and families of resources shared between `queue_families`, if there are two.
+    /// Set on create infos directly, as the builders require indices which outlive them.
+    fn sharing(&self) -> (vk::SharingMode, &[u32]) {
+        if self.queue_families.len() > 1 {
+            (vk::SharingMode::CONCURRENT, &self.queue_families)
+        } else {
+            (vk::SharingMode::EXCLUSIVE, &[])
+        }
+    }
+}
+
+/// Bytes per texel of the uncompressed color `format`
+fn texel_size(format: vk::Format) -> Option<u64> {
+    use vk::Format as F;
+    match format {
+        F::R8_UNORM | F::R8_SNORM | F::R8_UINT | F::R8_SINT | F::R8_SRGB => Some(1),
+        F::R8G8_UNORM | F::R8G8_SNORM | F::R8G8_UINT | F::R8G8_SINT | F::R8G8_SRGB => Some(2),
+        F::R16_UNORM | F::R16_SNORM | F::R16_UINT | F::R16_SINT | F::R16_SFLOAT => Some(2),
+        F::R8G8B8_UNORM | F::R8G8B8_SRGB | F::B8G8R8_UNORM | F::B8G8R8_SRGB => Some(3),
+        F::R8G8B8A8_UNORM
+        | F::R8G8B8A8_SNORM
+        | F::R8G8B8A8_UINT
+        | F::R8G8B8A8_SINT
+        | F::R8G8B8A8_SRGB
+        | F::B8G8R8A8_UNORM
+        | F::B8G8R8A8_SRGB
+        | F::A2B10G10R10_UNORM_PACK32
+        | F::A2R10G10B10_UNORM_PACK32
+        | F::B10G11R11_UFLOAT_PACK32
+        | F::E5B9G9R9_UFLOAT_PACK32
+        | F::R16G16_UNORM
+        | F::R16G16_SNORM
+        | F::R16G16_UINT
+        | F::R16G16_SINT
+        | F::R16G16_SFLOAT
+        | F::R32_UINT
+        | F::R32_SINT
+        | F::R32_SFLOAT => Some(4),
+        F::R16G16B16A16_UNORM
+        | F::R16G16B16A16_SNORM
+        | F::R16G16B16A16_UINT
+        | F::R16G16B16A16_SINT
+        | F::R16G16B16A16_SFLOAT
+        | F::R32G32_UINT
+        | F::R32G32_SINT
+        | F::R32G32_SFLOAT => Some(8),
+        F::R32G32B32_UINT | F::R32G32B32_SINT | F::R32G32B32_SFLOAT => Some(12),
+        F::R32G32B32A32_UINT | F::R32G32B32A32_SINT | F::R32G32B32A32_SFLOAT => Some(16),
+        _ => None,
+    }
+}
+
+impl Drop for AssetLoader {
+    fn drop(&mut self) {
+        // Workers exit once the job queue closes, or once they fail to send their current job's
+        // result
+        self.jobs = None;
+        for upload in self.uploads.drain(..) {
+            let _ = upload.fence.wait();
+            unsafe {
+                self.core
+                    .device
+                    .free_command_buffers(self.command_pool, &[upload.command_buffer]);
+            }
+        }
+        unsafe {
+            self.core
+                .device
+                .destroy_command_pool(Some(self.command_pool), None);
+        }
+    }
+}
@@ -0,0 +1,253 @@
+//! Compute work submitted to the compute-only queue (see `AppInfo::async_compute()`), running
+//! alongside rendering rather than within a frame. Useful for expensive simulations which don't
+//! need to finish every frame: a job is submitted, and once it is done the frame which reads its
+//! results waits on its semaphore. Without a compute-only queue, jobs are submitted to
+//! `Core::queue` and run in between frames instead.
+//!
+//! Buffers written by jobs and read by frames must be shared between `queue_families()` (with
+//! `vk::SharingMode::CONCURRENT` when there are two), and double buffered so that frames read
+//! one copy while a job writes the other. Before a job writes the copy earlier frames read, it
+//! must wait for them, such as on a semaphore signalled by the frame which switched copies:
+//!
+//! ```ignore
+//! let cmd = starter_kit.begin_command_buffer(frame)?;
+//! let done = simulation.is_finished(job)?;
+//! if done {
+//!     if let Some(finished) = simulation.consume(job, Stages::VERTEX_INPUT_KHR) {
+//!         starter_kit.wait_semaphore(finished);
+//!     }
+//!     starter_kit.signal_semaphore(released);
+//!     states.swap(0, 1);
+//! }
+//! // Draw states[0]
+//! starter_kit.end_command_buffer(cmd)?;
+//! if done {
+//!     // Write states[1] once the frames which read it have finished
+//!     let released = SemaphoreSubmit { semaphore: released, stages: Stages::COMPUTE_SHADER_KHR };
+//!     job = simulation.submit(&[released], |cmd| Ok(step.record(&core, cmd)))?;
+//! }
+//! ```
+use crate::sync2::{SemaphoreSubmit, Stages};
+use crate::synchronization::wait_for_fence;
+use crate::SharedCore;
+use anyhow::{bail, Result};
+use erupt::vk;
+
+/// A job submitted with `AsyncCompute::submit()`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ComputeJob(u64);
+
+/// Submits command buffers to the compute queue, a few at a time
+pub struct AsyncCompute {
+    slots: Vec<Slot>,
+    queue: vk::Queue,
+    queue_family: u32,
+    command_pool: vk::CommandPool,
+    /// Number of jobs submitted so far
+    submitted: u64,
+    core: SharedCore,
+}
+
+struct Slot {
+    command_buffer: vk::CommandBuffer,
+    fence: vk::Fence,
+    /// Signalled when the job finishes, for the submission consuming its results
+    finished: vk::Semaphore,
+    /// Job last submitted in this slot
+    job: Option<u64>,
+    /// Whether `finished` is or will be signalled, with nothing waiting on it
+    unconsumed: bool,
+}
+
+impl AsyncCompute {
+    /// Up to `jobs_in_flight` jobs may run at once; submitting another waits for the oldest
+    pub fn new(core: SharedCore, jobs_in_flight: usize) -> Result<Self> {
+        let (queue, queue_family) = match core.compute_queue {
+            Some(compute) => (compute.queue, compute.family),
+            None => (core.queue, core.queue_family),
+        };
+
+        let create_info = vk::CommandPoolCreateInfoBuilder::new()
+            .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
+            .queue_family_index(queue_family);
+        let command_pool =
+            unsafe { core.device.create_command_pool(&create_info, None, None) }.result()?;
+
+        let allocate_info = vk::CommandBufferAllocateInfoBuilder::new()
+            .command_pool(command_pool)
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_buffer_count(jobs_in_flight as u32);
+        let command_buffers =
+            unsafe { core.device.allocate_command_buffers(&allocate_info) }.result()?;
+
+        let slots = command_buffers
+            .into_iter()
+            .map(|command_buffer| unsafe {
+                // Signalled, so that the first submission in each slot doesn't wait
+                let create_info =
+                    vk::FenceCreateInfoBuilder::new().flags(vk::FenceCreateFlags::SIGNALED);
+                let fence = core
+                    .device
+                    .create_fence(&create_info, None, None)
+                    .result()?;
+                let create_info = vk::SemaphoreCreateInfoBuilder::new();
+                let finished = core
+                    .device
+                    .create_semaphore(&create_info, None, None)
+                    .result()?;
+                Ok(Slot {
+                    command_buffer,
+                    fence,
+                    finished,
+                    job: None,
+                    unconsumed: false,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            slots,
+            queue,
+            queue_family,
+            command_pool,
+            submitted: 0,
+            core,
+        })
+    }
+
+    /// Whether jobs run on a compute-only queue, alongside rendering
+    pub fn is_dedicated(&self) -> bool {
+        self.queue_family != self.core.queue_family
+    }
+
+    /// Families of the graphics and compute queues, which buffers shared between frames and jobs
+    /// must be shared between. Contains one family if jobs aren't dedicated.
+    pub fn queue_families(&self) -> Vec<u32> {
+        let mut families = vec![self.core.queue_family];
+        if self.is_dedicated() {
+            families.push(self.queue_family);
+        }
+        families
+    }
+
+    /// Record a job with `record` and submit it once the semaphores in `wait` are signalled.
+    /// Waits for the oldest job if `jobs_in_flight` are still running. Must be called from the
+    /// thread rendering frames.
+    pub fn submit<F>(&mut self, wait: &[SemaphoreSubmit], record: F) -> Result<ComputeJob>
+    where
+        F: FnOnce(vk::CommandBuffer) -> Result<()>,
+    {
+        let id = self.submitted;
+        let idx = (id % self.slots.len() as u64) as usize;
+        let slot = &mut self.slots[idx];
+        wait_for_fence(&self.core, slot.fence, None)?;
+
+        unsafe {
+            self.core
+                .device
+                .reset_command_buffer(slot.command_buffer, None)
+                .result()?;
+            let begin_info = vk::CommandBufferBeginInfoBuilder::new()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+            self.core
+                .device
+                .begin_command_buffer(slot.command_buffer, &begin_info)
+                .result()?;
+        }
+        record(slot.command_buffer)?;
+        unsafe {
+            self.core
+                .device
+                .end_command_buffer(slot.command_buffer)
+                .result()?;
+        }
+
+        let mut waits = wait.to_vec();
+        if slot.unconsumed {
+            // Nothing waited on the last job in this slot; unsignal its semaphore first
+            waits.push(SemaphoreSubmit {
+                semaphore: slot.finished,
+                stages: Stages::ALL_COMMANDS_KHR,
+            });
+        }
+        let signal = [SemaphoreSubmit {
+            semaphore: slot.finished,
+            stages: Stages::ALL_COMMANDS_KHR,
+        }];
+        unsafe {
+            self.core.device.reset_fences(&[slot.fence]).result()?;
+        }
+        self.core.submit_to(
+            self.queue,
+            slot.command_buffer,
+            &waits,
+            &signal,
+            Some(slot.fence),
+        )?;
+
+        slot.job = Some(id);
+        slot.unconsumed = true;
+        self.submitted += 1;
+        Ok(ComputeJob(id))
+    }
+
+    /// Whether `job` has finished, without blocking
+    pub fn is_finished(&self, job: ComputeJob) -> Result<bool> {
+        // Slots are only reused once their last job has finished
+        let slot = match self.slot(job) {
+            Some(slot) => slot,
+            None => return Ok(true),
+        };
+        let status = unsafe { self.core.device.get_fence_status(slot.fence) };
+        match status.raw {
+            vk::Result::SUCCESS => Ok(true),
+            vk::Result::NOT_READY => Ok(false),
+            other => bail!("Failed to get fence status: {}", other),
+        }
+    }
+
+    /// Block until `job` has finished
+    pub fn wait(&self, job: ComputeJob) -> Result<()> {
+        match self.slot(job) {
+            Some(slot) => wait_for_fence(&self.core, slot.fence, None),
+            None => Ok(()),
+        }
+    }
+
+    /// The semaphore signalled when `job` finishes, for the submission which reads its results
+    /// to wait on at `stages` (see `StarterKit::wait_semaphore()`). It may only be waited on
+    /// once, so this returns None on later calls, and once the job's slot has been reused.
+    pub fn consume(&mut self, job: ComputeJob, stages: Stages) -> Option<SemaphoreSubmit> {
+        let slot = self
+            .slots
+            .iter_mut()
+            .find(|slot| slot.job == Some(job.0) && slot.unconsumed)?;
+        slot.unconsumed = false;
+        Some(SemaphoreSubmit {
+            semaphore: slot.finished,
+            stages,
+        })
+    }
+
+    fn slot(&self, job: ComputeJob) -> Option<&Slot> {
+        self.slots.iter().find(|slot| slot.job == Some(job.0))
+    }
+}
+
+impl Drop for AsyncCompute {
+    fn drop(&mut self) {
+        // Frames may still be waiting on the semaphores
+        let _ = self.core.wait_idle();
+        unsafe {
+            for slot in &self.slots {
+                self.core.device.destroy_fence(Some(slot.fence), None);
+                self.core
+                    .device
+                    .destroy_semaphore(Some(slot.finished), None);
+            }
+            self.core
+                .device
+                .destroy_command_pool(Some(self.command_pool), None);
+        }
+    }
+}
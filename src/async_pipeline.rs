@@ -0,0 +1,121 @@
+//! Pipeline creation off the render thread. Creating a pipeline can take hundreds of
+//! milliseconds, so a `PipelineHandle` builds it on a background thread and hands out a
+//! placeholder (such as a simple unlit pipeline with the same layout and render pass) until it
+//! is ready.
+//!
+//! ```ignore
+//! let pipeline = PipelineHandle::new(core.clone(), unlit_pipeline, move |core| {
+//!     shader(core, VERT, FRAG, vk::PrimitiveTopology::TRIANGLE_LIST, render_pass, layout)
+//! });
+//! // Each frame
+//! core.device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, pipeline.get());
+//! ```
+use crate::deletion_queue::Garbage;
+use crate::{Core, SharedCore};
+use anyhow::{bail, Result};
+use erupt::vk;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+
+enum State {
+    Pending(Receiver<Result<vk::Pipeline>>),
+    Ready(vk::Pipeline),
+    Failed,
+}
+
+/// A pipeline being created on a background thread
+pub struct PipelineHandle {
+    state: State,
+    placeholder: vk::Pipeline,
+    core: SharedCore,
+}
+
+impl PipelineHandle {
+    /// Start creating a pipeline with `create` on a new thread. `placeholder` is used in the
+    /// meantime, and is not destroyed by the handle. The created pipeline is destroyed when the
+    /// handle is dropped, once frames using it have completed.
+    pub fn new<F>(core: SharedCore, placeholder: vk::Pipeline, create: F) -> Self
+    where
+        F: FnOnce(&Core) -> Result<vk::Pipeline> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let thread_core = core.clone();
+        std::thread::spawn(move || {
+            // The handle waits for this result if dropped first, so the pipeline isn't leaked
+            let _ = sender.send(create(&thread_core));
+        });
+
+        Self {
+            state: State::Pending(receiver),
+            placeholder,
+            core,
+        }
+    }
+
+    /// The pipeline to bind this frame: the created one if it's ready, otherwise the placeholder.
+    /// If creation failed, the placeholder is used from then on.
+    pub fn get(&mut self) -> vk::Pipeline {
+        self.poll();
+        match self.state {
+            State::Ready(pipeline) => pipeline,
+            _ => self.placeholder,
+        }
+    }
+
+    /// Whether the created pipeline is ready
+    pub fn is_ready(&mut self) -> bool {
+        self.poll();
+        matches!(self.state, State::Ready(_))
+    }
+
+    /// Block until the pipeline has been created
+    pub fn wait(&mut self) -> Result<vk::Pipeline> {
+        if let State::Pending(receiver) = &self.state {
+            match receiver.recv() {
+                Ok(Ok(pipeline)) => self.state = State::Ready(pipeline),
+                Ok(Err(e)) => {
+                    self.state = State::Failed;
+                    return Err(e);
+                }
+                Err(_) => {
+                    self.state = State::Failed;
+                    bail!("Pipeline creation thread panicked");
+                }
+            }
+        }
+
+        match self.state {
+            State::Ready(pipeline) => Ok(pipeline),
+            _ => bail!("Pipeline creation failed"),
+        }
+    }
+
+    fn poll(&mut self) {
+        if let State::Pending(receiver) = &self.state {
+            self.state = match receiver.try_recv() {
+                Ok(Ok(pipeline)) => State::Ready(pipeline),
+                Ok(Err(e)) => {
+                    eprintln!("Pipeline creation failed, keeping the placeholder: {:#}", e);
+                    State::Failed
+                }
+                Err(TryRecvError::Disconnected) => {
+                    eprintln!("Pipeline creation thread panicked, keeping the placeholder");
+                    State::Failed
+                }
+                Err(TryRecvError::Empty) => return,
+            };
+        }
+    }
+}
+
+impl Drop for PipelineHandle {
+    fn drop(&mut self) {
+        let pipeline = match &self.state {
+            State::Pending(receiver) => receiver.recv().ok().and_then(|res| res.ok()),
+            State::Ready(pipeline) => Some(*pipeline),
+            State::Failed => None,
+        };
+        if let Some(pipeline) = pipeline {
+            self.core.defer_destroy(Garbage::Pipeline(pipeline));
+        }
+    }
+}
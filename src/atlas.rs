@@ -0,0 +1,300 @@
+//! Texture atlases, packing many small images such as sprites, icons or glyphs into one texture
+//! so that they share a single descriptor. Images are packed into shelves at runtime, and each
+//! is looked up afterwards by the `AtlasId` returned when it was added.
+//!
+//! ```ignore
+//! let mut builder = AtlasBuilder::new();
+//! let player = builder.add(16, 16, player_rgba)?;
+//! let coin = builder.add(8, 8, coin_rgba)?;
+//! let atlas = builder.build(core.clone(), &mut staging, command_buffer)?;
+//! // Remap a quad's texture coordinates from 0 to 1 into the coin's region
+//! let uv = atlas.map_uv(coin, [1.0, 0.0]);
+//! ```
+use crate::memory::ManagedImage;
+use crate::staging_buffer::StagingBuffer;
+use crate::SharedCore;
+use anyhow::{ensure, Result};
+use erupt::vk;
+
+/// Format of `TextureAtlas`, with tightly packed RGBA8 images sampled as linear colors
+pub const ATLAS_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+/// Bytes per texel of `ATLAS_FORMAT`
+const TEXEL_SIZE: usize = 4;
+
+/// Identifies an image in a `TextureAtlas`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AtlasId(usize);
+
+/// Placement of an image in an atlas, in texels
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AtlasRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Texture coordinates of an image in an atlas
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct UvRect {
+    /// Top left corner
+    pub min: [f32; 2],
+    /// Bottom right corner
+    pub max: [f32; 2],
+}
+
+struct AtlasImage {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+/// Collects images to pack into a `TextureAtlas`
+pub struct AtlasBuilder {
+    images: Vec<AtlasImage>,
+    padding: u32,
+}
+
+impl Default for AtlasBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AtlasBuilder {
+    /// A builder with one texel of padding between images
+    pub fn new() -> Self {
+        Self {
+            images: vec![],
+            padding: 1,
+        }
+    }
+
+    /// Transparent texels left between images, so that linear filtering at the edge of one
+    /// doesn't blend in its neighbours
+    pub fn padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Add a `width` by `height` image, as tightly packed rows in the layout of `ATLAS_FORMAT`
+    pub fn add(&mut self, width: u32, height: u32, data: Vec<u8>) -> Result<AtlasId> {
+        ensure!(width > 0 && height > 0, "Atlas images must not be empty");
+        ensure!(
+            data.len() == width as usize * height as usize * TEXEL_SIZE,
+            "Expected {} bytes for a {}x{} atlas image, got {}",
+            width as usize * height as usize * TEXEL_SIZE,
+            width,
+            height,
+            data.len()
+        );
+        self.images.push(AtlasImage {
+            width,
+            height,
+            data,
+        });
+        Ok(AtlasId(self.images.len() - 1))
+    }
+
+    /// Number of images added
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    /// Pack the images into an atlas at most `max_size` texels on a side, returning its width,
+    /// height and the region of each image by `AtlasId`. Widths are powers of two, grown until
+    /// the atlas is no taller than it is wide.
+    pub fn pack(&self, max_size: u32) -> Result<(u32, u32, Vec<AtlasRegion>)> {
+        ensure!(!self.images.is_empty(), "No images to pack into an atlas");
+
+        let padded = |image: &AtlasImage| (image.width + self.padding, image.height + self.padding);
+        let area: u64 = self
+            .images
+            .iter()
+            .map(|image| {
+                let (w, h) = padded(image);
+                w as u64 * h as u64
+            })
+            .sum();
+        let widest = self
+            .images
+            .iter()
+            .map(|image| image.width)
+            .max()
+            .unwrap_or(1);
+
+        // Tallest images first, so shelves waste little space
+        let mut order = (0..self.images.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| std::cmp::Reverse((self.images[i].height, self.images[i].width)));
+
+        let mut width = ((area as f64).sqrt() as u32)
+            .max(widest)
+            .next_power_of_two();
+        loop {
+            ensure!(
+                width <= max_size,
+                "{} images don't fit in a {}x{} atlas",
+                self.images.len(),
+                max_size,
+                max_size
+            );
+
+            let (height, regions) = self.pack_shelves(width, &order);
+            if height <= width || width == max_size {
+                ensure!(
+                    height <= max_size,
+                    "{} images don't fit in a {}x{} atlas",
+                    self.images.len(),
+                    max_size,
+                    max_size
+                );
+                return Ok((width, height, regions));
+            }
+            width = (width * 2).min(max_size);
+        }
+    }
+
+    /// Place images left to right in rows as tall as their first image, returning the height of
+    /// the rows
+    fn pack_shelves(&self, width: u32, order: &[usize]) -> (u32, Vec<AtlasRegion>) {
+        let mut regions = vec![
+            AtlasRegion {
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 0,
+            };
+            self.images.len()
+        ];
+        let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+        for &i in order {
+            let image = &self.images[i];
+            if x + image.width > width {
+                x = 0;
+                y += shelf_height + self.padding;
+                shelf_height = 0;
+            }
+            regions[i] = AtlasRegion {
+                x,
+                y,
+                width: image.width,
+                height: image.height,
+            };
+            x += image.width + self.padding;
+            shelf_height = shelf_height.max(image.height);
+        }
+        (y + shelf_height, regions)
+    }
+
+    /// Pack and upload the images, limited to the device's largest 2D image
+    /// Warning: Assumes an inactive command buffer, which must not be reset until the upload is
+    /// complete
+    pub fn build(
+        self,
+        core: SharedCore,
+        staging: &mut StagingBuffer,
+        command_buffer: vk::CommandBuffer,
+    ) -> Result<TextureAtlas> {
+        let max_size = core.device_properties.limits.max_image_dimension2_d;
+        let (width, height, regions) = self.pack(max_size)?;
+
+        let row_size = width as usize * TEXEL_SIZE;
+        let mut texels = vec![0; row_size * height as usize];
+        for (image, region) in self.images.iter().zip(&regions) {
+            let image_row_size = image.width as usize * TEXEL_SIZE;
+            for (row, src) in image.data.chunks_exact(image_row_size).enumerate() {
+                let start = (region.y as usize + row) * row_size + region.x as usize * TEXEL_SIZE;
+                texels[start..start + image_row_size].copy_from_slice(src);
+            }
+        }
+
+        let (image, subresource_range, _) = staging.upload_image(
+            command_buffer,
+            width,
+            height,
+            &texels,
+            ATLAS_FORMAT,
+            vk::ImageUsageFlags::SAMPLED,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        )?;
+
+        let create_info = vk::ImageViewCreateInfoBuilder::new()
+            .image(image.instance())
+            .view_type(vk::ImageViewType::_2D)
+            .format(ATLAS_FORMAT)
+            .subresource_range(subresource_range.build());
+        let view = unsafe { core.device.create_image_view(&create_info, None, None) }.result()?;
+
+        Ok(TextureAtlas {
+            view,
+            width,
+            height,
+            regions,
+            _image: image,
+            core,
+        })
+    }
+}
+
+/// Images packed into one texture in `SHADER_READ_ONLY_OPTIMAL`
+pub struct TextureAtlas {
+    pub view: vk::ImageView,
+    pub width: u32,
+    pub height: u32,
+    regions: Vec<AtlasRegion>,
+    _image: ManagedImage,
+    core: SharedCore,
+}
+
+impl TextureAtlas {
+    /// Placement of an image in texels
+    pub fn region(&self, id: AtlasId) -> AtlasRegion {
+        self.regions[id.0]
+    }
+
+    /// Texture coordinates of an image's corners
+    pub fn uv_rect(&self, id: AtlasId) -> UvRect {
+        let region = self.region(id);
+        let (width, height) = (self.width as f32, self.height as f32);
+        UvRect {
+            min: [region.x as f32 / width, region.y as f32 / height],
+            max: [
+                (region.x + region.width) as f32 / width,
+                (region.y + region.height) as f32 / height,
+            ],
+        }
+    }
+
+    /// Map texture coordinates from 0 to 1 across an image to coordinates in the atlas
+    pub fn map_uv(&self, id: AtlasId, uv: [f32; 2]) -> [f32; 2] {
+        let rect = self.uv_rect(id);
+        [
+            rect.min[0] + uv[0] * (rect.max[0] - rect.min[0]),
+            rect.min[1] + uv[1] * (rect.max[1] - rect.min[1]),
+        ]
+    }
+
+    /// Image info for a `COMBINED_IMAGE_SAMPLER` descriptor
+    pub fn descriptor_image_info(
+        &self,
+        sampler: vk::Sampler,
+    ) -> vk::DescriptorImageInfoBuilder<'static> {
+        vk::DescriptorImageInfoBuilder::new()
+            .sampler(sampler)
+            .image_view(self.view)
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+    }
+}
+
+impl Drop for TextureAtlas {
+    fn drop(&mut self) {
+        unsafe {
+            self.core.device.destroy_image_view(Some(self.view), None);
+        }
+    }
+}
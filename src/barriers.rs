@@ -0,0 +1,107 @@
+//! Shortcuts for common pipeline barriers. Barriers are recorded with `Core::cmd_barriers()`:
+//!
+//! ```ignore
+//! core.cmd_barriers(command_buffer, &[], &[barriers::color_attachment_to_sampled(image, range)]);
+//! ```
+use crate::sync2::{self, Access, BufferBarrier, ImageBarrier, Stages};
+use crate::Core;
+use erupt::vk;
+
+/// Wait for all prior commands and make all of their writes visible to all later commands.
+/// Useful for debugging synchronization issues, but slow.
+pub fn full_pipeline_barrier(core: &Core, command_buffer: vk::CommandBuffer) {
+    core.cmd_memory_barrier(
+        command_buffer,
+        Stages::ALL_COMMANDS_KHR,
+        Access::MEMORY_WRITE_KHR,
+        Stages::ALL_COMMANDS_KHR,
+        Access::MEMORY_READ_KHR | Access::MEMORY_WRITE_KHR,
+    );
+}
+
+/// Transition an image between layouts, with the stages and accesses of each layout's typical
+/// usage (see `sync2::layout_usage()`). Transitions from `UNDEFINED` discard the contents and
+/// wait on nothing.
+pub fn image_barrier(
+    image: vk::Image,
+    subresource_range: vk::ImageSubresourceRange,
+    from: vk::ImageLayout,
+    to: vk::ImageLayout,
+) -> ImageBarrier {
+    let (src_stages, src_access) = match from {
+        vk::ImageLayout::UNDEFINED => (Stages::NONE_KHR, Access::NONE_KHR),
+        layout => sync2::layout_usage(layout),
+    };
+    let (dst_stages, dst_access) = sync2::layout_usage(to);
+    ImageBarrier {
+        image,
+        old_layout: from,
+        new_layout: to,
+        subresource_range,
+        src_stages,
+        src_access,
+        dst_stages,
+        dst_access,
+    }
+}
+
+/// Make writes to a whole buffer in `src` visible to `dst`, each given as stages and accesses
+pub fn buffer_barrier(
+    buffer: vk::Buffer,
+    (src_stages, src_access): (Stages, Access),
+    (dst_stages, dst_access): (Stages, Access),
+) -> BufferBarrier {
+    BufferBarrier {
+        buffer,
+        src_stages,
+        src_access,
+        dst_stages,
+        dst_access,
+    }
+}
+
+/// Rendered color attachment to be sampled by later shaders
+pub fn color_attachment_to_sampled(
+    image: vk::Image,
+    subresource_range: vk::ImageSubresourceRange,
+) -> ImageBarrier {
+    image_barrier(
+        image,
+        subresource_range,
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+    )
+}
+
+/// Copy destination to be read by later shaders
+pub fn transfer_to_shader_read(
+    image: vk::Image,
+    subresource_range: vk::ImageSubresourceRange,
+) -> ImageBarrier {
+    image_barrier(
+        image,
+        subresource_range,
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+    )
+}
+
+/// Copy destination buffer to be read as `usage` (see `sync2::buffer_usage()`)
+pub fn transfer_to_buffer_usage(buffer: vk::Buffer, usage: vk::BufferUsageFlags) -> BufferBarrier {
+    buffer_barrier(
+        buffer,
+        (Stages::TRANSFER_KHR, Access::TRANSFER_WRITE_KHR),
+        sync2::buffer_usage(usage),
+    )
+}
+
+/// Subresource range of the first mip level of a color image with `layers` array layers
+pub fn color_range(layers: u32) -> vk::ImageSubresourceRange {
+    vk::ImageSubresourceRangeBuilder::new()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(0)
+        .level_count(1)
+        .base_array_layer(0)
+        .layer_count(layers)
+        .build()
+}
@@ -0,0 +1,330 @@
+//! Benchmark mode, for performance regression tracking. Runs an app for a fixed number of frames
+//! (in a window, or headless) while the `WinitArcBall` camera, and so `MultiPlatformCamera`,
+//! makes one orbit around its pivot. Frame and CPU times are measured around the app's
+//! `frame()`, and GPU times are collected from the StarterKit through the profiling module. The
+//! report is printed, or written as JSON.
+//!
+//! ```ignore
+//! let settings = BenchSettings {
+//!     report: Some("bench.json".into()),
+//!     ..Default::default()
+//! };
+//! watertender::bench::launch::<App, _>(info, settings, ())?;
+//! ```
+use crate::defaults::COLOR_FORMAT;
+use crate::memory::ManagedImage;
+use crate::profiling::{self, TimingStats};
+use crate::{
+    app_info::AppInfo,
+    headless_backend,
+    mainloop::{Frame, MainLoop, Platform, PlatformEvent, PlatformReturn, SyncMainLoop},
+    Core, SharedCore,
+};
+use anyhow::{ensure, Result};
+use erupt::{extensions::khr_swapchain, vk};
+use gpu_alloc::UsageFlags;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+static CAMERA_PATH: Mutex<Option<f32>> = Mutex::new(None);
+
+/// Progress along the benchmark's camera path, from 0 to 1, while one is running
+pub fn camera_path() -> Option<f32> {
+    *CAMERA_PATH.lock().unwrap()
+}
+
+fn set_camera_path(progress: Option<f32>) {
+    *CAMERA_PATH.lock().unwrap() = progress;
+}
+
+pub struct BenchSettings {
+    /// Frames measured, over which the camera makes one orbit
+    pub frames: usize,
+    /// Frames run before measuring, with the camera at the start of its path
+    pub warmup: usize,
+    /// Render into offscreen images, without a window
+    pub headless: bool,
+    /// Size of the offscreen images when headless
+    pub extent: vk::Extent2D,
+    /// Write the report to this path as JSON, rather than printing it
+    pub report: Option<PathBuf>,
+}
+
+impl Default for BenchSettings {
+    fn default() -> Self {
+        Self {
+            frames: 600,
+            warmup: 60,
+            headless: false,
+            extent: vk::Extent2D {
+                width: 1280,
+                height: 720,
+            },
+            report: None,
+        }
+    }
+}
+
+/// Timings of a benchmark run, in milliseconds
+#[derive(Copy, Clone, Debug)]
+pub struct BenchReport {
+    pub frames: usize,
+    /// Time between the starts of successive frames
+    pub frame_ms: Option<TimingStats>,
+    /// Time spent in the app's `frame()`
+    pub cpu_ms: Option<TimingStats>,
+    /// GPU time of each frame's command buffer. Only measured for apps using the StarterKit.
+    pub gpu_ms: Option<TimingStats>,
+}
+
+impl BenchReport {
+    pub fn to_json(&self) -> String {
+        let stats = |stats: &Option<TimingStats>| match stats {
+            Some(stats) => stats.to_json(),
+            None => "null".into(),
+        };
+        format!(
+            r#"{{"frames": {}, "frame_ms": {}, "cpu_ms": {}, "gpu_ms": {}}}"#,
+            self.frames,
+            stats(&self.frame_ms),
+            stats(&self.cpu_ms),
+            stats(&self.gpu_ms)
+        )
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Benchmark of {} frames (ms):", self.frames)?;
+        for (name, stats) in [
+            ("Frame", self.frame_ms),
+            ("CPU", self.cpu_ms),
+            ("GPU", self.gpu_ms),
+        ] {
+            match stats {
+                Some(s) => writeln!(
+                    f,
+                    "  {:<5} mean {:.3}  min {:.3}  p50 {:.3}  p95 {:.3}  p99 {:.3}  max {:.3}",
+                    name, s.mean, s.min, s.p50, s.p95, s.p99, s.max
+                )?,
+                None => writeln!(f, "  {:<5} not measured", name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run the app as a benchmark, exiting once it completes. `data` is passed to `MainLoop::new()`.
+pub fn launch<M: SyncMainLoop<D> + 'static, D>(
+    info: AppInfo,
+    settings: BenchSettings,
+    data: D,
+) -> Result<()> {
+    ensure!(settings.frames > 0, "A benchmark needs at least one frame");
+    if settings.headless {
+        run_headless::<M, D>(info, settings, data)
+    } else {
+        crate::winit_backend::launch::<Bench<M>, _>(info, (settings, data))
+    }
+}
+
+/// Wraps the app, measuring each frame
+struct Bench<M> {
+    app: M,
+    settings: BenchSettings,
+    frame: usize,
+    last_start: Option<Instant>,
+    frame_ms: Vec<f32>,
+    cpu_ms: Vec<f32>,
+}
+
+impl<M> Bench<M> {
+    fn finish(&mut self) -> Result<()> {
+        profiling::record(false);
+        set_camera_path(None);
+
+        let report = BenchReport {
+            frames: self.settings.frames,
+            frame_ms: TimingStats::from_samples(&self.frame_ms),
+            cpu_ms: TimingStats::from_samples(&self.cpu_ms),
+            gpu_ms: TimingStats::from_samples(&profiling::take_gpu_samples()),
+        };
+
+        match &self.settings.report {
+            Some(path) => {
+                std::fs::write(path, report.to_json())?;
+                println!("Wrote benchmark report to {}", path.display());
+            }
+            None => print!("{}", report),
+        }
+        Ok(())
+    }
+}
+
+fn millis(duration: Duration) -> f32 {
+    duration.as_secs_f32() * 1e3
+}
+
+impl<M: MainLoop<D>, D> MainLoop<(BenchSettings, D)> for Bench<M> {
+    fn new(core: &SharedCore, platform: Platform<'_>, data: (BenchSettings, D)) -> Result<Self> {
+        let (settings, data) = data;
+        set_camera_path(Some(0.));
+        Ok(Self {
+            app: M::new(core, platform, data)?,
+            settings,
+            frame: 0,
+            last_start: None,
+            frame_ms: vec![],
+            cpu_ms: vec![],
+        })
+    }
+
+    fn frame(
+        &mut self,
+        frame: Frame,
+        core: &SharedCore,
+        mut platform: Platform<'_>,
+    ) -> Result<PlatformReturn> {
+        let BenchSettings { warmup, frames, .. } = self.settings;
+        if self.frame == warmup {
+            profiling::take_gpu_samples();
+            profiling::record(true);
+        }
+        let measuring = self.frame >= warmup;
+        let progress = self.frame.saturating_sub(warmup) as f32 / frames as f32;
+        set_camera_path(Some(progress));
+
+        let start = Instant::now();
+        if let (true, Some(last_start)) = (measuring, self.last_start) {
+            self.frame_ms.push(millis(start - last_start));
+        }
+        self.last_start = Some(start);
+
+        let ret = self.app.frame(frame, core, platform.reborrow())?;
+        if measuring {
+            self.cpu_ms.push(millis(start.elapsed()));
+        }
+
+        self.frame += 1;
+        if self.frame == warmup + frames {
+            self.finish()?;
+            platform.request_exit();
+        }
+
+        Ok(ret)
+    }
+
+    fn swapchain_resize(&mut self, images: Vec<vk::Image>, extent: vk::Extent2D) -> Result<()> {
+        self.app.swapchain_resize(images, extent)
+    }
+
+    fn event(
+        &mut self,
+        event: PlatformEvent<'_, '_>,
+        core: &Core,
+        platform: Platform<'_>,
+    ) -> Result<()> {
+        self.app.event(event, core, platform)
+    }
+}
+
+impl<M: SyncMainLoop<D>, D> SyncMainLoop<(BenchSettings, D)> for Bench<M> {
+    fn winit_sync(&self) -> (vk::Semaphore, vk::Semaphore) {
+        self.app.winit_sync()
+    }
+}
+
+/// Number of offscreen images standing in for a swapchain
+const HEADLESS_IMAGES: u32 = 3;
+
+/// Run the benchmark into offscreen images, presenting to nothing. The app sees
+/// `Platform::Display`.
+fn run_headless<M: SyncMainLoop<D>, D>(
+    info: AppInfo,
+    settings: BenchSettings,
+    data: D,
+) -> Result<()> {
+    // Render passes over the swapchain end in PRESENT_SRC_KHR, which needs the swapchain
+    // extension even without a swapchain
+    let core = SharedCore::new(headless_backend::build_core_with_extensions(
+        info,
+        vec![],
+        vec![khr_swapchain::KHR_SWAPCHAIN_EXTENSION_NAME],
+    )?);
+
+    let extent = settings.extent;
+    let color_format = COLOR_FORMAT;
+    let images = (0..HEADLESS_IMAGES)
+        .map(|_| {
+            let create_info = vk::ImageCreateInfoBuilder::new()
+                .image_type(vk::ImageType::_2D)
+                .extent(
+                    vk::Extent3DBuilder::new()
+                        .width(extent.width)
+                        .height(extent.height)
+                        .depth(1)
+                        .build(),
+                )
+                .mip_levels(1)
+                .array_layers(1)
+                .format(color_format)
+                .tiling(vk::ImageTiling::OPTIMAL)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+                .samples(vk::SampleCountFlagBits::_1)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE);
+            ManagedImage::new(core.clone(), create_info, UsageFlags::FAST_DEVICE_ACCESS)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut exit = false;
+    let mut app = Bench::<M>::new(
+        &core,
+        Platform::Display {
+            exit: &mut exit,
+            color_format,
+            extent,
+        },
+        (settings, data),
+    )?;
+    app.swapchain_resize(images.iter().map(|i| i.instance()).collect(), extent)?;
+
+    let mut swapchain_index = 0;
+    while !exit {
+        // Stand in for image acquisition and presentation
+        let (image_available, render_finished) = app.winit_sync();
+        submit_semaphores(&core, &[], &[image_available])?;
+        app.frame(
+            Frame { swapchain_index },
+            &core,
+            Platform::Display {
+                exit: &mut exit,
+                color_format,
+                extent,
+            },
+        )?;
+        submit_semaphores(&core, &[render_finished], &[])?;
+        core.collect_garbage()?;
+        swapchain_index = (swapchain_index + 1) % HEADLESS_IMAGES;
+    }
+
+    unsafe { core.device.device_wait_idle() }.result()?;
+    drop(app);
+    drop(images);
+
+    Ok(())
+}
+
+/// An empty submission waiting on and signalling the given semaphores
+fn submit_semaphores(core: &Core, wait: &[vk::Semaphore], signal: &[vk::Semaphore]) -> Result<()> {
+    let stages = vec![vk::PipelineStageFlags::ALL_COMMANDS; wait.len()];
+    let submit_info = vk::SubmitInfoBuilder::new()
+        .wait_semaphores(wait)
+        .wait_dst_stage_mask(&stages)
+        .signal_semaphores(signal);
+    let _queue = core.lock_queue(core.queue);
+    unsafe { core.device.queue_submit(core.queue, &[submit_info], None) }.result()?;
+    Ok(())
+}
@@ -0,0 +1,145 @@
+//! What the device can do, for apps to pick code paths without querying Vulkan themselves.
+//! Features watertender enables (through `DevicePreference`) are reported as enabled; extensions
+//! it doesn't manage are reported as supported, and must be requested to be used.
+//!
+//! ```ignore
+//! let capabilities = core.capabilities();
+//! let settings = Settings {
+//!     msaa_samples: capabilities.max_msaa_samples.min(vk::SampleCountFlagBits::_4),
+//!     ..Default::default()
+//! };
+//! if !capabilities.wide_lines {
+//!     println!("Lines will be drawn one pixel wide");
+//! }
+//! ```
+use crate::Core;
+use erupt::extensions::{
+    ext_descriptor_indexing, khr_acceleration_structure, khr_ray_query, khr_ray_tracing_pipeline,
+};
+use erupt::{vk, vk1_2};
+use std::ffi::CStr;
+
+/// Capabilities of the device of a `Core`, see `Core::capabilities()`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Capabilities {
+    /// Multiview rendering, otherwise VR eyes are drawn in separate passes (see
+    /// `render_pass::view_passes()`)
+    pub multiview: bool,
+    /// Anisotropic filtering (`sampler_anisotropy`) is enabled
+    pub sampler_anisotropy: bool,
+    /// Largest `max_anisotropy` of a sampler
+    pub max_sampler_anisotropy: f32,
+    /// Lines wider than a pixel (`wide_lines`) are enabled
+    pub wide_lines: bool,
+    /// Smallest and largest line width, if wide lines are enabled
+    pub line_width_range: [f32; 2],
+    /// Most samples per pixel of color and depth attachments, see
+    /// `starter_kit::Settings::msaa_samples`
+    pub max_msaa_samples: vk::SampleCountFlagBits,
+    /// Timestamps on the graphics queue, for `GpuTimer`
+    pub timestamps: bool,
+    /// `VK_KHR_synchronization2` is in use for barriers and submits
+    pub synchronization2: bool,
+    /// A compute-only queue was created, see `AppInfo::async_compute()`
+    pub async_compute: bool,
+    /// A transfer-only queue was created, see `AppInfo::transfer_queue()`
+    pub transfer_queue: bool,
+    /// Bindless descriptors are supported: partially bound, runtime sized arrays of sampled
+    /// images indexed non-uniformly, with Vulkan 1.2 or `VK_EXT_descriptor_indexing`
+    pub descriptor_indexing: bool,
+    /// Ray tracing pipelines and acceleration structures are supported
+    pub ray_tracing: bool,
+    /// Ray queries from any shader stage are supported
+    pub ray_query: bool,
+}
+
+impl Core {
+    /// Query the capabilities of the device
+    pub fn capabilities(&self) -> Capabilities {
+        let limits = &self.device_properties.limits;
+        let wide_lines = self.device_features.wide_lines == vk::TRUE;
+
+        let sample_counts =
+            limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+        let max_msaa_samples = [
+            vk::SampleCountFlagBits::_64,
+            vk::SampleCountFlagBits::_32,
+            vk::SampleCountFlagBits::_16,
+            vk::SampleCountFlagBits::_8,
+            vk::SampleCountFlagBits::_4,
+            vk::SampleCountFlagBits::_2,
+        ]
+        .iter()
+        .copied()
+        .find(|samples| sample_counts.contains(vk::SampleCountFlags::from_bits_truncate(samples.0)))
+        .unwrap_or(vk::SampleCountFlagBits::_1);
+
+        let queue_families = unsafe {
+            self.instance
+                .get_physical_device_queue_family_properties(self.physical_device, None)
+        };
+        let timestamps = queue_families[self.queue_family as usize].timestamp_valid_bits != 0;
+
+        let extensions = unsafe {
+            self.instance
+                .enumerate_device_extension_properties(self.physical_device, None, None)
+        }
+        .result()
+        .unwrap_or_default();
+        let supported = |name: *const std::os::raw::c_char| {
+            let name = unsafe { CStr::from_ptr(name) };
+            extensions
+                .iter()
+                .any(|ext| unsafe { CStr::from_ptr(ext.extension_name.as_ptr()) } == name)
+        };
+
+        let acceleration_structure =
+            supported(khr_acceleration_structure::KHR_ACCELERATION_STRUCTURE_EXTENSION_NAME);
+        let ray_tracing = acceleration_structure
+            && supported(khr_ray_tracing_pipeline::KHR_RAY_TRACING_PIPELINE_EXTENSION_NAME);
+        let ray_query =
+            acceleration_structure && supported(khr_ray_query::KHR_RAY_QUERY_EXTENSION_NAME);
+
+        let descriptor_indexing = (self.device_properties.api_version >= vk::make_version(1, 2, 0)
+            || supported(ext_descriptor_indexing::EXT_DESCRIPTOR_INDEXING_EXTENSION_NAME))
+            && self.descriptor_indexing_supported();
+
+        Capabilities {
+            multiview: self.multiview,
+            sampler_anisotropy: self.device_features.sampler_anisotropy == vk::TRUE,
+            max_sampler_anisotropy: limits.max_sampler_anisotropy,
+            wide_lines,
+            line_width_range: if wide_lines {
+                limits.line_width_range
+            } else {
+                [1.0, 1.0]
+            },
+            max_msaa_samples,
+            timestamps,
+            synchronization2: self.synchronization2(),
+            async_compute: self.compute_queue.is_some(),
+            transfer_queue: self.transfer_queue.is_some(),
+            descriptor_indexing,
+            ray_tracing,
+            ray_query,
+        }
+    }
+
+    /// Whether the descriptor indexing features used for bindless textures are supported. The
+    /// device must support Vulkan 1.2 or the extension.
+    fn descriptor_indexing_supported(&self) -> bool {
+        if self.instance.get_physical_device_features2.is_none() {
+            return false;
+        }
+        let mut indexing = vk1_2::PhysicalDeviceDescriptorIndexingFeaturesBuilder::new().build();
+        let mut features = vk::PhysicalDeviceFeatures2Builder::new().build();
+        features.p_next = &mut indexing as *mut _ as _;
+        unsafe {
+            self.instance
+                .get_physical_device_features2(self.physical_device, Some(features))
+        };
+        indexing.descriptor_binding_partially_bound == vk::TRUE
+            && indexing.runtime_descriptor_array == vk::TRUE
+            && indexing.shader_sampled_image_array_non_uniform_indexing == vk::TRUE
+    }
+}
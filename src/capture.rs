@@ -0,0 +1,78 @@
+//! RenderDoc in-application captures. When the app is running under RenderDoc, `trigger()`
+//! (or `Platform::trigger_capture()`) captures exactly the next frame run by the backend, from
+//! before the app's `frame()` until after the image is presented. This also works in the OpenXR
+//! backend, where RenderDoc's own present-based capture doesn't.
+use renderdoc::{RenderDoc, V110};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+static API: Mutex<Api> = Mutex::new(Api::Unloaded);
+
+enum Api {
+    Unloaded,
+    /// Not running under RenderDoc
+    Unavailable,
+    Loaded {
+        renderdoc: RenderDoc<V110>,
+        capturing: bool,
+    },
+}
+
+/// Capture the next frame
+pub fn trigger() {
+    REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// Whether the app is running under RenderDoc
+pub fn available() -> bool {
+    let mut api = API.lock().unwrap();
+    load(&mut api);
+    matches!(*api, Api::Loaded { .. })
+}
+
+/// Called by the backends before each frame
+pub(crate) fn begin_frame() {
+    if !REQUESTED.swap(false, Ordering::Relaxed) {
+        return;
+    }
+
+    let mut api = API.lock().unwrap();
+    load(&mut api);
+    match &mut *api {
+        Api::Loaded {
+            renderdoc,
+            capturing,
+        } => {
+            // Null device and window match the only device in use
+            renderdoc.start_frame_capture(std::ptr::null(), std::ptr::null());
+            *capturing = true;
+        }
+        _ => println!("Capture requested, but the app is not running under RenderDoc"),
+    }
+}
+
+/// Called by the backends after each frame is presented
+pub(crate) fn end_frame() {
+    let mut api = API.lock().unwrap();
+    if let Api::Loaded {
+        renderdoc,
+        capturing: capturing @ true,
+    } = &mut *api
+    {
+        renderdoc.end_frame_capture(std::ptr::null(), std::ptr::null());
+        *capturing = false;
+    }
+}
+
+fn load(api: &mut Api) {
+    if let Api::Unloaded = api {
+        *api = match RenderDoc::new() {
+            Ok(renderdoc) => Api::Loaded {
+                renderdoc,
+                capturing: false,
+            },
+            Err(_) => Api::Unavailable,
+        };
+    }
+}
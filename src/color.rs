@@ -0,0 +1,93 @@
+//! Conversions between sRGB encoded and linear colors. Vertex colors, clear colors and shader
+//! outputs are linear by convention: with the default `B8G8R8A8_SRGB` swapchain, the hardware
+//! encodes them on write. With a UNORM swapchain (`AppInfo::srgb_swapchain(false)`), values are
+//! stored as written, so the bundled shaders encode them when built with
+//! `PipelineSettings::encode_srgb`, and clear colors must be encoded with `attachment_color()`.
+//!
+//! ```ignore
+//! // A color picked in an image editor
+//! let accent = color::srgb8_to_linear([0xe0, 0x6c, 0x2b]);
+//! let settings = PipelineSettings {
+//!     encode_srgb: color::needs_shader_encoding(platform.color_format()),
+//!     ..Default::default()
+//! };
+//! ```
+use erupt::vk;
+
+/// Decode an sRGB encoded component from 0 to 1
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encode a linear component from 0 to 1 as sRGB
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Decode an sRGB encoded color
+pub fn srgb_to_linear_rgb(color: [f32; 3]) -> [f32; 3] {
+    color.map(srgb_to_linear)
+}
+
+/// Encode a linear color as sRGB
+pub fn linear_to_srgb_rgb(color: [f32; 3]) -> [f32; 3] {
+    color.map(linear_to_srgb)
+}
+
+/// Decode an 8-bit sRGB color, as written in hex codes and color pickers
+pub fn srgb8_to_linear(color: [u8; 3]) -> [f32; 3] {
+    color.map(|c| srgb_to_linear(c as f32 / 255.0))
+}
+
+/// Encode a linear color as 8-bit sRGB, clamping to 0 to 1
+pub fn linear_to_srgb8(color: [f32; 3]) -> [u8; 3] {
+    color.map(|c| (linear_to_srgb(c.clamp(0.0, 1.0)) * 255.0).round() as u8)
+}
+
+/// Whether `format` is sRGB encoded by the hardware on write, and decoded when sampled
+pub fn is_srgb_format(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::R8_SRGB
+            | vk::Format::R8G8_SRGB
+            | vk::Format::R8G8B8_SRGB
+            | vk::Format::B8G8R8_SRGB
+            | vk::Format::R8G8B8A8_SRGB
+            | vk::Format::B8G8R8A8_SRGB
+            | vk::Format::A8B8G8R8_SRGB_PACK32
+    )
+}
+
+/// Whether linear colors written to a color attachment of `format` must be sRGB encoded by the
+/// shader: true for the 8 and 10-bit UNORM formats swapchains are presented from, false for sRGB
+/// formats (encoded in hardware) and float formats (which hold linear colors).
+pub fn needs_shader_encoding(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::R8G8B8A8_UNORM
+            | vk::Format::B8G8R8A8_UNORM
+            | vk::Format::A8B8G8R8_UNORM_PACK32
+            | vk::Format::A2R10G10B10_UNORM_PACK32
+            | vk::Format::A2B10G10R10_UNORM_PACK32
+    )
+}
+
+/// A linear RGBA color as it must be written to an attachment of `format`, such as for a clear
+/// value: encoded if `needs_shader_encoding(format)`, otherwise unchanged. Alpha is always
+/// linear.
+pub fn attachment_color(format: vk::Format, [r, g, b, a]: [f32; 4]) -> [f32; 4] {
+    if needs_shader_encoding(format) {
+        let [r, g, b] = linear_to_srgb_rgb([r, g, b]);
+        [r, g, b, a]
+    } else {
+        [r, g, b, a]
+    }
+}
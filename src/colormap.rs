@@ -0,0 +1,214 @@
+//! Standard colormaps for scalar data, as vertex colors or as a texture to look up in shaders.
+//! Colors are linear, as vertex colors and shader outputs are with the default sRGB swapchain.
+//!
+//! ```ignore
+//! let range = colormap::value_range(&temperatures);
+//! Colormap::Viridis.colorize(&mut vertices, &temperatures, range);
+//! draw(vec![DrawData::new(vertices, indices, Primitive::Triangles)], false)
+//! ```
+use crate::color::srgb_to_linear;
+use crate::memory::ManagedImage;
+use crate::staging_buffer::StagingBuffer;
+use crate::vertex::Vertex;
+use crate::SharedCore;
+use anyhow::Result;
+use erupt::vk;
+
+/// Format of `ColormapTexture`, sampled as linear colors
+pub const COLORMAP_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+/// A map from values between 0 and 1 to colors
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Colormap {
+    /// Perceptually uniform, from dark blue through green to yellow. Readable in grayscale and
+    /// by colorblind viewers.
+    Viridis,
+    /// Perceptually uniform, from dark blue through red to yellow
+    Plasma,
+    /// Rainbow-like, from dark blue through green to dark red, with more contrast than
+    /// viridis but less uniformity
+    Turbo,
+    /// Black to white
+    Grayscale,
+}
+
+impl Colormap {
+    /// Every colormap
+    pub const ALL: [Colormap; 4] = [
+        Colormap::Viridis,
+        Colormap::Plasma,
+        Colormap::Turbo,
+        Colormap::Grayscale,
+    ];
+
+    /// Linear color of `t`, clamped to 0 to 1. NaN maps to 0.
+    pub fn sample(self, t: f32) -> [f32; 3] {
+        self.sample_srgb(t).map(srgb_to_linear)
+    }
+
+    /// sRGB encoded color of `t`, clamped to 0 to 1, e.g. for 8-bit images
+    pub fn sample_srgb(self, t: f32) -> [f32; 3] {
+        let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+        let color = match self {
+            Colormap::Viridis => polynomial(&VIRIDIS, t),
+            Colormap::Plasma => polynomial(&PLASMA, t),
+            Colormap::Turbo => polynomial(&TURBO, t),
+            Colormap::Grayscale => [t; 3],
+        };
+        color.map(|c| c.clamp(0.0, 1.0))
+    }
+
+    /// Linear colors of `values`, mapping `range` (min and max) to the ends of the colormap
+    pub fn map(self, values: &[f32], range: (f32, f32)) -> Vec<[f32; 3]> {
+        values
+            .iter()
+            .map(|&v| self.sample(normalize(v, range)))
+            .collect()
+    }
+
+    /// Set the colors of `vertices` from the value of each, as in `map()`
+    pub fn colorize(self, vertices: &mut [Vertex], values: &[f32], range: (f32, f32)) {
+        for (vertex, &v) in vertices.iter_mut().zip(values) {
+            vertex.color = self.sample(normalize(v, range));
+        }
+    }
+
+    /// `width` texels of the colormap from 0 to 1 as tightly packed sRGB RGBA8, the layout of
+    /// `COLORMAP_FORMAT`
+    pub fn texels(self, width: u32) -> Vec<u8> {
+        (0..width)
+            .flat_map(|x| {
+                let t = x as f32 / (width - 1).max(1) as f32;
+                let [r, g, b] = self.sample_srgb(t).map(|c| (c * 255.0).round() as u8);
+                [r, g, b, 255]
+            })
+            .collect()
+    }
+}
+
+/// Smallest and largest of `values`, ignoring NaN. (0, 1) if there are none.
+pub fn value_range(values: &[f32]) -> (f32, f32) {
+    let mut values = values.iter().copied().filter(|v| !v.is_nan());
+    let first = match values.next() {
+        Some(first) => first,
+        None => return (0.0, 1.0),
+    };
+    values.fold((first, first), |(min, max), v| (min.min(v), max.max(v)))
+}
+
+/// Position of `value` in `range`, from 0 to 1 (before clamping)
+fn normalize(value: f32, (min, max): (f32, f32)) -> f32 {
+    if max > min {
+        (value - min) / (max - min)
+    } else {
+        0.5
+    }
+}
+
+/// A colormap as a `width` by 1 texture in `SHADER_READ_ONLY_OPTIMAL`, for a linear, clamp to
+/// edge sampler. The first and last texels hold the ends of the map, so sample at
+/// `vec2((t * (width - 1) + 0.5) / width, 0.5)` to reach them exactly.
+pub struct ColormapTexture {
+    pub view: vk::ImageView,
+    pub width: u32,
+    _image: ManagedImage,
+    core: SharedCore,
+}
+
+impl ColormapTexture {
+    /// Upload `colormap` with `width` texels, such as 256
+    /// Warning: Assumes an inactive command buffer, which must not be reset until the upload is
+    /// complete
+    pub fn new(
+        core: SharedCore,
+        staging: &mut StagingBuffer,
+        command_buffer: vk::CommandBuffer,
+        colormap: Colormap,
+        width: u32,
+    ) -> Result<Self> {
+        let (image, subresource_range, _) = staging.upload_image(
+            command_buffer,
+            width,
+            1,
+            &colormap.texels(width),
+            COLORMAP_FORMAT,
+            vk::ImageUsageFlags::SAMPLED,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        )?;
+
+        let create_info = vk::ImageViewCreateInfoBuilder::new()
+            .image(image.instance())
+            .view_type(vk::ImageViewType::_2D)
+            .format(COLORMAP_FORMAT)
+            .subresource_range(subresource_range.build());
+        let view = unsafe { core.device.create_image_view(&create_info, None, None) }.result()?;
+
+        Ok(Self {
+            view,
+            width,
+            _image: image,
+            core,
+        })
+    }
+
+    /// Image info for a `COMBINED_IMAGE_SAMPLER` descriptor
+    pub fn descriptor_image_info(
+        &self,
+        sampler: vk::Sampler,
+    ) -> vk::DescriptorImageInfoBuilder<'static> {
+        vk::DescriptorImageInfoBuilder::new()
+            .sampler(sampler)
+            .image_view(self.view)
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+    }
+}
+
+impl Drop for ColormapTexture {
+    fn drop(&mut self) {
+        unsafe {
+            self.core.device.destroy_image_view(Some(self.view), None);
+        }
+    }
+}
+
+/// Evaluate a polynomial fit of a colormap, with the coefficients of each power of `t` in order
+fn polynomial(coefficients: &[[f32; 3]], t: f32) -> [f32; 3] {
+    let mut color = [0.0; 3];
+    for coefficient in coefficients.iter().rev() {
+        for (c, k) in color.iter_mut().zip(coefficient) {
+            *c = *c * t + k;
+        }
+    }
+    color
+}
+
+// Polynomial fits of the matplotlib colormaps by Matt Zucker (CC0)
+const VIRIDIS: [[f32; 3]; 7] = [
+    [0.277_727_33, 0.005_407_344_5, 0.334_099_8],
+    [0.105_093_04, 1.404_613_5, 1.384_590_2],
+    [-0.330_861_83, 0.214_847_56, 0.095_095_16],
+    [-4.634_230_6, -5.799_101, -19.332_441],
+    [6.228_27, 14.179_933, 56.690_55],
+    [4.776_385, -13.745_145, -65.353_03],
+    [-5.435_456, 4.645_852_6, 26.312_435],
+];
+
+const PLASMA: [[f32; 3]; 7] = [
+    [0.058_732_344, 0.023_336_709, 0.543_340_2],
+    [2.176_514_6, 0.238_383_42, 0.753_960_4],
+    [-2.689_460_5, -7.455_851, 3.110_8],
+    [6.130_348, 42.346_188, -28.518_854],
+    [-11.107_436, -82.666_31, 60.139_847],
+    [10.023_066, 71.413_62, -54.072_186],
+    [-3.658_713_8, -22.931_535, 18.191_908],
+];
+
+// Polynomial fit of Turbo by Anton Mikhailov (Apache 2.0)
+const TURBO: [[f32; 3]; 6] = [
+    [0.135_721_38, 0.091_402_61, 0.106_673_3],
+    [4.615_392_6, 2.194_188_4, 12.641_946],
+    [-42.660_324, 4.842_966_6, -60.582_05],
+    [132.131_08, -14.185_033, 110.362_77],
+    [-152.942_4, 4.277_299, -89.903_11],
+    [59.286_38, 2.829_566, 27.348_25],
+];
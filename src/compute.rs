@@ -0,0 +1,173 @@
+//! Compute dispatches recorded before the scene pass, such as particle updates or GPU culling.
+//! The buffers each dispatch writes are listed with how they are read afterwards, and barriers
+//! are recorded against later dispatches and, when the scene pass begins, against the draws
+//! which read them.
+//!
+//! ```ignore
+//! let cmd = starter_kit.begin_frame(frame)?;
+//! let update = Dispatch {
+//!     pipeline,
+//!     layout,
+//!     descriptor_sets: &[descriptor_set],
+//!     push_constants: bytemuck::cast_slice(&[dt]),
+//!     groups: [n_particles.div_ceil(64), 1, 1],
+//! };
+//! let particles = particle_buffer.instance();
+//! starter_kit.dispatch(&cmd, &update, &[(particles, vk::BufferUsageFlags::VERTEX_BUFFER)]);
+//! starter_kit.begin_swapchain_pass(&cmd);
+//! // Draw with `particles` bound as a vertex buffer
+//! ```
+use crate::sync2::{self, Access, Stages};
+use crate::{barriers, Core};
+use anyhow::Result;
+use erupt::vk;
+use std::ffi::CString;
+
+/// Create a compute pipeline from SPIR-V with a `main` entry point
+pub fn compute_pipeline(
+    core: &Core,
+    spirv: &[u8],
+    pipeline_layout: vk::PipelineLayout,
+) -> Result<vk::Pipeline> {
+    let module = core.shader_module(spirv)?;
+    let entry_point = CString::new("main")?;
+    let stage = vk::PipelineShaderStageCreateInfoBuilder::new()
+        .stage(vk::ShaderStageFlagBits::COMPUTE)
+        .module(module)
+        .name(&entry_point);
+    let create_info = vk::ComputePipelineCreateInfoBuilder::new()
+        .stage(*stage)
+        .layout(pipeline_layout);
+    let pipeline = unsafe {
+        core.device
+            .create_compute_pipelines(None, &[create_info], None)
+    }
+    .result()?[0];
+    core.track_pipeline(pipeline);
+    Ok(pipeline)
+}
+
+/// A compute pipeline with its bindings and workgroup count
+#[derive(Copy, Clone, Debug)]
+pub struct Dispatch<'a> {
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+    /// Bound from set 0
+    pub descriptor_sets: &'a [vk::DescriptorSet],
+    /// Pushed at offset 0 to the compute stage, if not empty
+    pub push_constants: &'a [u8],
+    pub groups: [u32; 3],
+}
+
+impl Dispatch<'_> {
+    /// Record the dispatch, without any barriers
+    pub fn record(&self, core: &Core, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            core.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline,
+            );
+            if !self.descriptor_sets.is_empty() {
+                core.device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::COMPUTE,
+                    self.layout,
+                    0,
+                    self.descriptor_sets,
+                    &[],
+                );
+            }
+            if !self.push_constants.is_empty() {
+                core.device.cmd_push_constants(
+                    command_buffer,
+                    self.layout,
+                    vk::ShaderStageFlags::COMPUTE,
+                    0,
+                    self.push_constants.len() as u32,
+                    self.push_constants.as_ptr() as _,
+                );
+            }
+            let [x, y, z] = self.groups;
+            core.device.cmd_dispatch(command_buffer, x, y, z);
+        }
+    }
+}
+
+/// Buffers written by compute dispatches earlier in a command buffer, which are not yet visible
+/// to the commands that read them
+#[derive(Default, Debug)]
+pub struct ComputeWrites {
+    /// Each buffer and the usage it is read with after the dispatches
+    pending: Vec<(vk::Buffer, vk::BufferUsageFlags)>,
+    /// Whether a dispatch wrote buffers since the last compute to compute barrier
+    compute_hazard: bool,
+}
+
+impl ComputeWrites {
+    /// Record a dispatch which writes `writes`, each given with the usage it is read with later
+    /// in the frame (e.g. `VERTEX_BUFFER` or `INDIRECT_BUFFER`). Waits for the writes of earlier
+    /// dispatches, and for earlier frames to finish reading `writes`.
+    pub fn dispatch(
+        &mut self,
+        core: &Core,
+        command_buffer: vk::CommandBuffer,
+        dispatch: &Dispatch,
+        writes: &[(vk::Buffer, vk::BufferUsageFlags)],
+    ) {
+        let mut src_stages = Stages::empty();
+        let mut src_access = Access::empty();
+        if self.compute_hazard {
+            src_stages |= Stages::COMPUTE_SHADER_KHR;
+            src_access |= Access::SHADER_WRITE_KHR;
+        }
+        for &(_, usage) in writes {
+            // Write after read; an execution dependency is enough
+            src_stages |= sync2::buffer_usage(usage).0;
+        }
+        if !src_stages.is_empty() {
+            core.cmd_memory_barrier(
+                command_buffer,
+                src_stages,
+                src_access,
+                Stages::COMPUTE_SHADER_KHR,
+                Access::SHADER_READ_KHR | Access::SHADER_WRITE_KHR,
+            );
+        }
+
+        dispatch.record(core, command_buffer);
+
+        for &(buffer, usage) in writes {
+            match self.pending.iter_mut().find(|(b, _)| *b == buffer) {
+                Some((_, pending_usage)) => *pending_usage |= usage,
+                None => self.pending.push((buffer, usage)),
+            }
+        }
+        self.compute_hazard = !writes.is_empty();
+    }
+
+    /// Make all pending writes visible to their readers. Must be recorded outside of a render
+    /// pass, e.g. just before it begins.
+    pub fn flush(&mut self, core: &Core, command_buffer: vk::CommandBuffer) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let buffers: Vec<_> = self
+            .pending
+            .drain(..)
+            .map(|(buffer, usage)| {
+                barriers::buffer_barrier(
+                    buffer,
+                    (Stages::COMPUTE_SHADER_KHR, Access::SHADER_WRITE_KHR),
+                    sync2::buffer_usage(usage),
+                )
+            })
+            .collect();
+        core.cmd_barriers(command_buffer, &buffers, &[]);
+    }
+
+    /// Whether any writes have yet to be flushed
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
@@ -0,0 +1,192 @@
+//! Blit, copy and resolve shortcuts for whole 2D images (or regions of them). Images must already
+//! be in `TRANSFER_SRC_OPTIMAL` or `TRANSFER_DST_OPTIMAL` as appropriate; see the `barriers`
+//! module.
+use crate::Core;
+use erupt::vk;
+
+/// Color layers `0..layers` of the first mip level
+fn color_layers(layers: u32) -> vk::ImageSubresourceLayers {
+    vk::ImageSubresourceLayersBuilder::new()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .mip_level(0)
+        .base_array_layer(0)
+        .layer_count(layers)
+        .build()
+}
+
+fn far_corner(extent: vk::Extent2D) -> vk::Offset3D {
+    vk::Offset3D {
+        x: extent.width as i32,
+        y: extent.height as i32,
+        z: 1,
+    }
+}
+
+/// Blit all of `src` onto all of `dst`, scaling with `filter`. Both images must have `layers`
+/// color layers.
+#[allow(clippy::too_many_arguments)]
+pub fn cmd_blit_image_simple(
+    core: &Core,
+    command_buffer: vk::CommandBuffer,
+    src: vk::Image,
+    src_extent: vk::Extent2D,
+    dst: vk::Image,
+    dst_extent: vk::Extent2D,
+    layers: u32,
+    filter: vk::Filter,
+) {
+    let zero = vk::Offset3D { x: 0, y: 0, z: 0 };
+    let region = vk::ImageBlitBuilder::new()
+        .src_subresource(color_layers(layers))
+        .src_offsets([zero, far_corner(src_extent)])
+        .dst_subresource(color_layers(layers))
+        .dst_offsets([zero, far_corner(dst_extent)]);
+
+    unsafe {
+        core.device.cmd_blit_image(
+            command_buffer,
+            src,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            dst,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[region],
+            filter,
+        );
+    }
+}
+
+/// Copy all of `src` onto `dst`, which have the same extent and format, and `layers` color
+/// layers each
+pub fn cmd_copy_image_simple(
+    core: &Core,
+    command_buffer: vk::CommandBuffer,
+    src: vk::Image,
+    dst: vk::Image,
+    extent: vk::Extent2D,
+    layers: u32,
+) {
+    let zero = vk::Offset3D { x: 0, y: 0, z: 0 };
+    let region = vk::ImageCopyBuilder::new()
+        .src_subresource(color_layers(layers))
+        .src_offset(zero)
+        .dst_subresource(color_layers(layers))
+        .dst_offset(zero)
+        .extent(vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        });
+
+    unsafe {
+        core.device.cmd_copy_image(
+            command_buffer,
+            src,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            dst,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[region],
+        );
+    }
+}
+
+/// Copy the region of color layer `layer` at `offset` with size `extent` into the start of
+/// `buffer`, as tightly packed rows
+pub fn cmd_copy_image_to_buffer(
+    core: &Core,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    layer: u32,
+    offset: vk::Offset2D,
+    extent: vk::Extent2D,
+    buffer: vk::Buffer,
+) {
+    cmd_copy_aspect_to_buffer(
+        core,
+        command_buffer,
+        image,
+        vk::ImageAspectFlags::COLOR,
+        layer,
+        offset,
+        extent,
+        buffer,
+    );
+}
+
+/// `cmd_copy_image_to_buffer()` for one aspect of the image, such as `DEPTH`
+#[allow(clippy::too_many_arguments)]
+pub fn cmd_copy_aspect_to_buffer(
+    core: &Core,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    aspect: vk::ImageAspectFlags,
+    layer: u32,
+    offset: vk::Offset2D,
+    extent: vk::Extent2D,
+    buffer: vk::Buffer,
+) {
+    let subresource = vk::ImageSubresourceLayersBuilder::new()
+        .aspect_mask(aspect)
+        .mip_level(0)
+        .base_array_layer(layer)
+        .layer_count(1)
+        .build();
+
+    let region = vk::BufferImageCopyBuilder::new()
+        .buffer_offset(0)
+        .buffer_row_length(0)
+        .buffer_image_height(0)
+        .image_subresource(subresource)
+        .image_offset(vk::Offset3D {
+            x: offset.x,
+            y: offset.y,
+            z: 0,
+        })
+        .image_extent(vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        });
+
+    unsafe {
+        core.device.cmd_copy_image_to_buffer(
+            command_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            buffer,
+            &[region],
+        );
+    }
+}
+
+/// Resolve a multisampled color image into a single sampled one of the same extent and format
+pub fn cmd_resolve_msaa(
+    core: &Core,
+    command_buffer: vk::CommandBuffer,
+    src: vk::Image,
+    dst: vk::Image,
+    extent: vk::Extent2D,
+    layers: u32,
+) {
+    let zero = vk::Offset3D { x: 0, y: 0, z: 0 };
+    let region = vk::ImageResolveBuilder::new()
+        .src_subresource(color_layers(layers))
+        .src_offset(zero)
+        .dst_subresource(color_layers(layers))
+        .dst_offset(zero)
+        .extent(vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        });
+
+    unsafe {
+        core.device.cmd_resolve_image(
+            command_buffer,
+            src,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            dst,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[region],
+        );
+    }
+}
@@ -1,9 +1,12 @@
+use crate::deletion_queue::DeletionQueue;
+use crate::resources::ResourceTracker;
+use crate::shader_cache::ShaderModuleCache;
 use anyhow::{format_err, Result};
 use erupt::vk;
 use erupt::{utils::loading::DefaultEntryLoader, DeviceLoader, InstanceLoader};
 use gpu_alloc::{GpuAllocator, MemoryBlock, Request};
 use gpu_alloc_erupt::EruptMemoryDevice;
-use std::sync::MutexGuard;
+use std::sync::{MutexGuard, PoisonError};
 use std::sync::{Arc, Mutex};
 
 /// A collection of commonly referenced Vulkan context
@@ -14,6 +17,17 @@ pub struct Core {
     /// Family the queue is from
     pub queue_family: u32,
 
+    /// Queue from a compute-only family, if requested with `AppInfo::async_compute()` and the
+    /// device has one
+    pub compute_queue: Option<ComputeQueue>,
+
+    /// Queue from a transfer-only family, if requested with `AppInfo::transfer_queue()` and the
+    /// device has one
+    pub transfer_queue: Option<ComputeQueue>,
+
+    /// Held while using each queue, see `lock_queue()`
+    pub queue_locks: QueueLocks,
+
     /// GPU memory allocator
     pub allocator: Mutex<GpuAllocator<vk::DeviceMemory>>,
 
@@ -26,11 +40,45 @@ pub struct Core {
     /// Information about the device
     pub device_properties: vk::PhysicalDeviceProperties,
 
+    /// Features enabled on the device, see `DevicePreference`
+    pub device_features: vk::PhysicalDeviceFeatures,
+
+    /// Depth format supported by the device, see `hardware_query::pick_depth_format()`
+    pub depth_format: vk::Format,
+
+    /// Whether the device supports multiview. Without it, render passes have a single view and
+    /// VR renders each eye in a pass of its own, see `render_pass::view_passes()`.
+    pub multiview: bool,
+
     /// Vulkan instance
     pub instance: InstanceLoader,
 
     /// Erupt entry
     pub entry: DefaultEntryLoader,
+
+    /// Live resource counts, see `resource_stats()`
+    pub resources: ResourceTracker,
+
+    /// Dropped resources waiting for the GPU, see `collect_garbage()`
+    pub deletion_queue: DeletionQueue,
+
+    /// Shader modules shared between pipelines, see `shader_module()`
+    pub shader_modules: ShaderModuleCache,
+}
+
+/// A queue and the family it is from
+#[derive(Copy, Clone, Debug)]
+pub struct ComputeQueue {
+    pub queue: vk::Queue,
+    pub family: u32,
+}
+
+/// Locks of the core's queues, which Vulkan requires to be externally synchronized
+#[derive(Default)]
+pub struct QueueLocks {
+    queue: Mutex<()>,
+    compute: Mutex<()>,
+    transfer: Mutex<()>,
 }
 
 /// An alias of `Arc<Core>`. Useful to include in subsystems for easy access to Vulkan context
@@ -39,12 +87,27 @@ pub type SharedCore = Arc<Core>;
 type Memory = MemoryBlock<vk::DeviceMemory>;
 impl Core {
     /// Memory allocator
-    pub fn allocator(&self) -> Result<MutexGuard<GpuAllocator<vk::DeviceMemory>>> {
+    pub fn allocator(&self) -> Result<MutexGuard<'_, GpuAllocator<vk::DeviceMemory>>> {
         self.allocator
             .lock()
             .map_err(|_| format_err!("GpuAllocator mutex poisoned"))
     }
 
+    /// Lock `queue` for a submission, presentation or wait on it, so that threads using the same
+    /// queue don't race. Queues other than the compute and transfer queues share the lock of
+    /// `queue`. The core's own submissions (such as `submit_to()`) take it already.
+    pub fn lock_queue(&self, queue: vk::Queue) -> MutexGuard<'_, ()> {
+        let is = |q: Option<ComputeQueue>| q.is_some_and(|q| q.queue == queue);
+        let lock = if is(self.compute_queue) {
+            &self.queue_locks.compute
+        } else if is(self.transfer_queue) {
+            &self.queue_locks.transfer
+        } else {
+            &self.queue_locks.queue
+        };
+        lock.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
     pub fn alloc(&self, request: Request) -> Result<Memory> {
         Ok(unsafe {
             self.allocator()?
@@ -1,14 +1,44 @@
 //! Sensible defaults used elsewhere in the Shortcut API. May also be used by the mainloop
 //! abstraction.
 use erupt::{extensions::khr_surface::ColorSpaceKHR, vk};
+use std::time::Duration;
 
-/// Decent depth format
-pub const DEPTH_FORMAT: vk::Format = vk::Format::D32_SFLOAT; // TODO: Add stencil? Check compat...
+/// Decent depth format. Not every device supports it as an attachment; prefer
+/// `Core::depth_format`, the first of `DEPTH_FORMATS` the device supports.
+pub const DEPTH_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
+
+/// Depth formats in order of preference, see `hardware_query::pick_depth_format()`
+pub const DEPTH_FORMATS: [vk::Format; 3] = [
+    DEPTH_FORMAT,
+    vk::Format::D32_SFLOAT_S8_UINT,
+    vk::Format::D24_UNORM_S8_UINT,
+];
 
 /// Decent color format
 pub const COLOR_FORMAT: vk::Format = vk::Format::B8G8R8A8_SRGB;
 
+/// Swapchain format preferred with `AppInfo::srgb_swapchain(false)`, which stores shader
+/// outputs without encoding them
+pub const UNORM_COLOR_FORMAT: vk::Format = vk::Format::B8G8R8A8_UNORM;
+
 /// Used in shortcuts, to make things easier
 pub const COLOR_SPACE: ColorSpaceKHR = ColorSpaceKHR::SRGB_NONLINEAR_KHR;
 
 pub const FRAMES_IN_FLIGHT: usize = 2;
+
+/// How long to wait on a frame's fence before assuming the GPU has hung
+pub const FENCE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Size of a `StagingBuffer` in bytes, which uploads are split to fit
+pub const STAGING_BUFFER_SIZE: u64 = 1024 * 1024;
+
+/// Frames a `StagingBuffer` may keep capacity beyond its initial size without using it, before
+/// shrinking back
+pub const STAGING_SHRINK_FRAMES: u32 = 300;
+
+/// How long each attempt to acquire a swapchain image may wait, see `AppInfo::acquire_timeout()`
+pub const ACQUIRE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Attempts to acquire a swapchain image, rebuilding the swapchain between them, before failing
+/// with an `AcquireError`
+pub const ACQUIRE_ATTEMPTS: u32 = 3;
@@ -0,0 +1,773 @@
+//! Deferred shading. The scene is drawn into a `GBuffer` (albedo, normal and depth), and a
+//! fullscreen `DeferredLighting` pass then shades each pixel once with every light in a storage
+//! buffer. Lighting cost depends on the number of pixels rather than on scene complexity, so
+//! this scales to many more lights than forward shading.
+//!
+//! The StarterKit sets this up with `Settings::deferred`: its `render_pass` is then the G-buffer
+//! pass, and the lit result is drawn into the swapchain.
+//!
+//! ```ignore
+//! let settings = PipelineSettings {
+//!     color_attachments: GBUFFER_ATTACHMENTS,
+//!     ..Default::default()
+//! };
+//! let id = starter_kit
+//!     .pipelines
+//!     .add::<VertexNT>(GBUFFER_VERT, GBUFFER_FRAG, settings, pipeline_layout)?;
+//!
+//! // Each frame, with the same matrices as the camera UBO
+//! let lights = [Light::point([0., 2., 0.], [1., 0.8, 0.6], 5.)];
+//! starter_kit.update_lights(&camera, &lights)?;
+//! ```
+use crate::memory::{aspect_mask, ManagedBuffer, ManagedImage};
+use crate::post::FULLSCREEN_VERT;
+use crate::render_target::create_image;
+use crate::shader::{clear_depth, fullscreen_pipeline};
+use crate::{Core, SharedCore};
+use anyhow::{ensure, Result};
+use erupt::{vk, vk1_1};
+use gpu_alloc::UsageFlags;
+use nalgebra::Matrix4;
+
+/// Bundled G-buffer vertex shader for `VertexNT`, with the camera UBO at binding 0 laid out as
+/// in the bundled unlit shader
+pub const GBUFFER_VERT: &[u8] = include_bytes!("../shaders/gbuffer.vert.spv");
+
+/// Bundled G-buffer fragment shader, writing the vertex color as albedo and the normal
+pub const GBUFFER_FRAG: &[u8] = include_bytes!("../shaders/gbuffer.frag.spv");
+
+/// Color attachments of the G-buffer pass, for `PipelineSettings::color_attachments`. Fragment
+/// shaders write albedo to location 0 and the world space normal to location 1.
+pub const GBUFFER_ATTACHMENTS: u32 = 2;
+
+/// Format of the albedo attachment
+pub const ALBEDO_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+/// Format of the normal attachment
+pub const NORMAL_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+/// Default capacity of the light buffer
+pub const MAX_LIGHTS: usize = 256;
+
+/// A light, as laid out in the light buffer
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Light {
+    /// Position of a point light with `w` = 1, or the direction towards a directional light
+    /// with `w` = 0
+    pub position: [f32; 4],
+    /// Color, scaled by intensity
+    pub color: [f32; 3],
+    /// Distance at which a point light falls off to nothing
+    pub radius: f32,
+}
+
+unsafe impl bytemuck::Zeroable for Light {}
+unsafe impl bytemuck::Pod for Light {}
+
+impl Light {
+    pub fn point(position: [f32; 3], color: [f32; 3], radius: f32) -> Self {
+        let [x, y, z] = position;
+        Self {
+            position: [x, y, z, 1.],
+            color,
+            radius,
+        }
+    }
+
+    /// A light infinitely far away, such as the sun. `direction` points towards the light.
+    pub fn directional(direction: [f32; 3], color: [f32; 3]) -> Self {
+        let [x, y, z] = direction;
+        Self {
+            position: [x, y, z, 0.],
+            color,
+            radius: 0.,
+        }
+    }
+}
+
+/// Start of the light buffer, followed by the lights
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct LightsHeader {
+    inverse_camera: [f32; 4 * 4 * 2],
+    ambient: [f32; 4],
+    light_count: u32,
+    /// Depth of pixels where nothing was drawn
+    clear_depth: f32,
+    encode_srgb: u32,
+    _pad: u32,
+}
+
+unsafe impl bytemuck::Zeroable for LightsHeader {}
+unsafe impl bytemuck::Pod for LightsHeader {}
+
+/// Create a render pass compatible with a `GBuffer` of the same settings, for building
+/// pipelines against
+pub fn create_gbuffer_render_pass(
+    core: &Core,
+    depth_format: vk::Format,
+    vr: bool,
+) -> Result<vk::RenderPass> {
+    ensure!(
+        core.multiview || !vr,
+        "Two-view G-buffers require multiview, which the device doesn't support"
+    );
+
+    let color_attachment = |format| {
+        vk::AttachmentDescriptionBuilder::new()
+            .format(format)
+            .samples(vk::SampleCountFlagBits::_1)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+    };
+    let attachments = [
+        color_attachment(ALBEDO_FORMAT),
+        color_attachment(NORMAL_FORMAT),
+        vk::AttachmentDescriptionBuilder::new()
+            .format(depth_format)
+            .samples(vk::SampleCountFlagBits::_1)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL),
+    ];
+
+    let color_attachment_refs = [
+        vk::AttachmentReferenceBuilder::new()
+            .attachment(0)
+            .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL),
+        vk::AttachmentReferenceBuilder::new()
+            .attachment(1)
+            .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL),
+    ];
+
+    let depth_attachment_ref = vk::AttachmentReferenceBuilder::new()
+        .attachment(2)
+        .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+
+    let subpasses = [vk::SubpassDescriptionBuilder::new()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .color_attachments(&color_attachment_refs)
+        .depth_stencil_attachment(&depth_attachment_ref)];
+
+    // The previous frame's lighting pass may still be reading the attachments, and this frame's
+    // must wait for the writes to finish
+    let dependencies = [
+        vk::SubpassDependencyBuilder::new()
+            .src_subpass(vk::SUBPASS_EXTERNAL)
+            .dst_subpass(0)
+            .src_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+            .src_access_mask(vk::AccessFlags::empty())
+            .dst_stage_mask(
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            )
+            .dst_access_mask(
+                vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            ),
+        vk::SubpassDependencyBuilder::new()
+            .src_subpass(0)
+            .dst_subpass(vk::SUBPASS_EXTERNAL)
+            .src_stage_mask(
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            )
+            .src_access_mask(
+                vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            )
+            .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ),
+    ];
+
+    let mut create_info = vk::RenderPassCreateInfoBuilder::new()
+        .attachments(&attachments)
+        .subpasses(&subpasses)
+        .dependencies(&dependencies);
+
+    let views = if vr { 2 } else { 1 };
+    let view_mask = [!(!0 << views)];
+    let mut multiview = vk1_1::RenderPassMultiviewCreateInfoBuilder::new()
+        .view_masks(&view_mask)
+        .correlation_masks(&view_mask)
+        .build();
+
+    if core.multiview {
+        create_info.p_next = &mut multiview as *mut _ as _;
+    }
+
+    Ok(unsafe { core.device.create_render_pass(&create_info, None, None) }.result()?)
+}
+
+/// Albedo, normal and depth attachments with their own render pass. All are left readable by
+/// shaders at the end of the pass.
+pub struct GBuffer {
+    render_pass: vk::RenderPass,
+    depth_format: vk::Format,
+    vr: bool,
+    reverse_z: bool,
+    internals: Option<Internals>,
+    core: SharedCore,
+}
+
+struct Internals {
+    extent: vk::Extent2D,
+    albedo: (ManagedImage, vk::ImageView),
+    normal: (ManagedImage, vk::ImageView),
+    depth: (ManagedImage, vk::ImageView),
+    /// Depth aspect only, as sampled views may not include stencil
+    depth_sampled_view: vk::ImageView,
+    framebuffer: vk::Framebuffer,
+}
+
+impl GBuffer {
+    /// Create a new G-buffer, with two layers in VR. `resize()` must be called before use.
+    pub fn new(core: SharedCore, depth_format: vk::Format, vr: bool) -> Result<Self> {
+        let render_pass = create_gbuffer_render_pass(&core, depth_format, vr)?;
+        Ok(Self {
+            render_pass,
+            depth_format,
+            vr,
+            reverse_z: false,
+            internals: None,
+            core,
+        })
+    }
+
+    /// (Re)create the attachments. Usually called from `swapchain_resize()`.
+    pub fn resize(&mut self, extent: vk::Extent2D) -> Result<()> {
+        // Waits for the device, as the old framebuffer may still be in use
+        if let Some(internals) = self.internals.take() {
+            internals.free(&self.core);
+        }
+
+        let layers = self.layers();
+        let color_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED;
+        let albedo = create_image(
+            &self.core,
+            extent,
+            layers,
+            ALBEDO_FORMAT,
+            color_usage,
+            vk::ImageAspectFlags::COLOR,
+        )?;
+        let normal = create_image(
+            &self.core,
+            extent,
+            layers,
+            NORMAL_FORMAT,
+            color_usage,
+            vk::ImageAspectFlags::COLOR,
+        )?;
+        let depth = create_image(
+            &self.core,
+            extent,
+            layers,
+            self.depth_format,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            aspect_mask(self.depth_format),
+        )?;
+
+        let create_info = vk::ImageViewCreateInfoBuilder::new()
+            .image(depth.0.instance())
+            .view_type(vk::ImageViewType::_2D_ARRAY)
+            .format(self.depth_format)
+            .subresource_range(
+                vk::ImageSubresourceRangeBuilder::new()
+                    .aspect_mask(vk::ImageAspectFlags::DEPTH)
+                    .base_mip_level(0)
+                    .level_count(1)
+                    .base_array_layer(0)
+                    .layer_count(layers)
+                    .build(),
+            );
+        let depth_sampled_view =
+            unsafe { self.core.device.create_image_view(&create_info, None, None) }.result()?;
+
+        let attachments = [albedo.1, normal.1, depth.1];
+        let create_info = vk::FramebufferCreateInfoBuilder::new()
+            .render_pass(self.render_pass)
+            .attachments(&attachments)
+            .width(extent.width)
+            .height(extent.height)
+            .layers(1);
+
+        let framebuffer = unsafe {
+            self.core
+                .device
+                .create_framebuffer(&create_info, None, None)
+        }
+        .result()?;
+
+        self.internals = Some(Internals {
+            extent,
+            albedo,
+            normal,
+            depth,
+            depth_sampled_view,
+            framebuffer,
+        });
+
+        Ok(())
+    }
+
+    /// Begin the G-buffer pass, and set the viewport and scissor to cover it. Albedo is cleared
+    /// to `clear_color`, which the lighting pass shows wherever nothing was drawn.
+    pub fn begin_pass(&self, command_buffer: vk::CommandBuffer, clear_color: [f32; 4]) {
+        let internals = self.internals();
+        let clear_values = [
+            vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: clear_color,
+                },
+            },
+            vk::ClearValue {
+                color: vk::ClearColorValue { float32: [0.; 4] },
+            },
+            vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: clear_depth(self.reverse_z),
+                    stencil: 0,
+                },
+            },
+        ];
+
+        let render_area = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent: internals.extent,
+        };
+
+        let begin_info = vk::RenderPassBeginInfoBuilder::new()
+            .framebuffer(internals.framebuffer)
+            .render_pass(self.render_pass)
+            .render_area(render_area)
+            .clear_values(&clear_values);
+
+        let viewports = [vk::ViewportBuilder::new()
+            .x(0.0)
+            .y(0.0)
+            .width(internals.extent.width as f32)
+            .height(internals.extent.height as f32)
+            .min_depth(0.0)
+            .max_depth(1.0)];
+
+        let scissors = [vk::Rect2DBuilder::new()
+            .offset(render_area.offset)
+            .extent(render_area.extent)];
+
+        unsafe {
+            self.core.device.cmd_begin_render_pass(
+                command_buffer,
+                &begin_info,
+                vk::SubpassContents::INLINE,
+            );
+            self.core
+                .device
+                .cmd_set_viewport(command_buffer, 0, &viewports);
+            self.core
+                .device
+                .cmd_set_scissor(command_buffer, 0, &scissors);
+        }
+    }
+
+    /// End the G-buffer pass
+    pub fn end_pass(&self, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            self.core.device.cmd_end_render_pass(command_buffer);
+        }
+    }
+
+    /// Render pass to build pipelines against
+    pub fn render_pass(&self) -> vk::RenderPass {
+        self.render_pass
+    }
+
+    pub fn albedo_view(&self) -> vk::ImageView {
+        self.internals().albedo.1
+    }
+
+    pub fn normal_view(&self) -> vk::ImageView {
+        self.internals().normal.1
+    }
+
+    /// View of the depth aspect, in `DEPTH_STENCIL_READ_ONLY_OPTIMAL` after the pass
+    pub fn depth_view(&self) -> vk::ImageView {
+        self.internals().depth_sampled_view
+    }
+
+    pub fn depth_format(&self) -> vk::Format {
+        self.depth_format
+    }
+
+    /// Clear depth to 0 rather than 1, for pipelines built with `PipelineSettings::reverse_z`.
+    /// `DeferredLighting::reverse_z` must match.
+    pub fn set_reverse_z(&mut self, reverse_z: bool) {
+        self.reverse_z = reverse_z;
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.internals().extent
+    }
+
+    /// Number of array layers (2 in VR, 1 otherwise)
+    pub fn layers(&self) -> u32 {
+        if self.vr {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn internals(&self) -> &Internals {
+        self.internals
+            .as_ref()
+            .expect("G-buffer used before resize")
+    }
+}
+
+impl Drop for GBuffer {
+    fn drop(&mut self) {
+        if let Some(internals) = self.internals.take() {
+            internals.free(&self.core);
+        }
+        unsafe {
+            self.core
+                .device
+                .destroy_render_pass(Some(self.render_pass), None);
+        }
+    }
+}
+
+impl Internals {
+    fn free(self, core: &Core) {
+        unsafe {
+            core.device.device_wait_idle().result().unwrap();
+            core.device
+                .destroy_framebuffer(Some(self.framebuffer), None);
+            for view in [
+                self.albedo.1,
+                self.normal.1,
+                self.depth.1,
+                self.depth_sampled_view,
+            ] {
+                core.device.destroy_image_view(Some(view), None);
+            }
+        }
+    }
+}
+
+/// Fullscreen pass shading a `GBuffer` with Lambertian diffuse lighting, from an ambient term
+/// and a list of point and directional lights. Each frame in flight has its own light buffer.
+pub struct DeferredLighting {
+    pipeline: vk::Pipeline,
+    pipeline_layout: vk::PipelineLayout,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    sampler: vk::Sampler,
+    buffers: Vec<ManagedBuffer>,
+    max_lights: usize,
+    /// Light reaching every surface, added to the lights
+    pub ambient: [f32; 3],
+    /// Whether the G-buffer was drawn with `PipelineSettings::reverse_z`
+    pub reverse_z: bool,
+    /// sRGB encode the output, for a UNORM output render pass (see
+    /// `RenderPassSettings::encode_srgb()`)
+    pub encode_srgb: bool,
+    core: SharedCore,
+}
+
+impl DeferredLighting {
+    /// Create the lighting pass, drawing into `render_pass`, for `frames` frames in flight and
+    /// up to `max_lights` lights. `set_inputs()` must be called before use.
+    pub fn new(
+        core: SharedCore,
+        render_pass: vk::RenderPass,
+        frames: usize,
+        max_lights: usize,
+    ) -> Result<Self> {
+        // Attachments are read with texelFetch, so filtering doesn't matter
+        let create_info = vk::SamplerCreateInfoBuilder::new()
+            .mag_filter(vk::Filter::NEAREST)
+            .min_filter(vk::Filter::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .anisotropy_enable(false)
+            .max_anisotropy(1.)
+            .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
+            .unnormalized_coordinates(false)
+            .compare_enable(false)
+            .compare_op(vk::CompareOp::ALWAYS)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .mip_lod_bias(0.)
+            .min_lod(0.)
+            .max_lod(0.);
+
+        let sampler = unsafe { core.device.create_sampler(&create_info, None, None) }.result()?;
+
+        // Descriptor set layout: sampler, albedo, normal, depth and lights
+        let binding = |binding, descriptor_type| {
+            vk::DescriptorSetLayoutBindingBuilder::new()
+                .binding(binding)
+                .descriptor_type(descriptor_type)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+        };
+        let bindings = [
+            binding(0, vk::DescriptorType::SAMPLER),
+            binding(1, vk::DescriptorType::SAMPLED_IMAGE),
+            binding(2, vk::DescriptorType::SAMPLED_IMAGE),
+            binding(3, vk::DescriptorType::SAMPLED_IMAGE),
+            binding(4, vk::DescriptorType::STORAGE_BUFFER),
+        ];
+
+        let create_info = vk::DescriptorSetLayoutCreateInfoBuilder::new().bindings(&bindings);
+        let descriptor_set_layout = unsafe {
+            core.device
+                .create_descriptor_set_layout(&create_info, None, None)
+        }
+        .result()?;
+
+        // Descriptor pool
+        let n_sets = frames as u32;
+        let pool_sizes = [
+            vk::DescriptorPoolSizeBuilder::new()
+                ._type(vk::DescriptorType::SAMPLER)
+                .descriptor_count(n_sets),
+            vk::DescriptorPoolSizeBuilder::new()
+                ._type(vk::DescriptorType::SAMPLED_IMAGE)
+                .descriptor_count(3 * n_sets),
+            vk::DescriptorPoolSizeBuilder::new()
+                ._type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(n_sets),
+        ];
+
+        let create_info = vk::DescriptorPoolCreateInfoBuilder::new()
+            .pool_sizes(&pool_sizes)
+            .max_sets(n_sets);
+
+        let descriptor_pool =
+            unsafe { core.device.create_descriptor_pool(&create_info, None, None) }.result()?;
+
+        // Descriptor sets
+        let layouts = vec![descriptor_set_layout; frames];
+        let create_info = vk::DescriptorSetAllocateInfoBuilder::new()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&layouts);
+
+        let descriptor_sets = core.allocate_descriptor_sets(&create_info)?;
+
+        // Light buffers, written every frame
+        let size = std::mem::size_of::<LightsHeader>() + max_lights * std::mem::size_of::<Light>();
+        let mut buffers = vec![];
+        for &descriptor_set in &descriptor_sets {
+            let ci = vk::BufferCreateInfoBuilder::new()
+                .size(size as u64)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE)
+                .usage(vk::BufferUsageFlags::STORAGE_BUFFER);
+            let mut buffer = ManagedBuffer::new(core.clone(), ci, UsageFlags::UPLOAD)?;
+            buffer.map_persistent()?;
+            buffer.write_slice(0, &[bytemuck::Zeroable::zeroed()] as &[LightsHeader])?;
+
+            // The inputs are written by set_inputs()
+            let sampler_infos = [vk::DescriptorImageInfoBuilder::new().sampler(sampler)];
+            let buffer_infos = [vk::DescriptorBufferInfoBuilder::new()
+                .buffer(buffer.instance())
+                .offset(0)
+                .range(vk::WHOLE_SIZE)];
+            let writes = [
+                vk::WriteDescriptorSetBuilder::new()
+                    .image_info(&sampler_infos)
+                    .descriptor_type(vk::DescriptorType::SAMPLER)
+                    .dst_set(descriptor_set)
+                    .dst_binding(0)
+                    .dst_array_element(0),
+                vk::WriteDescriptorSetBuilder::new()
+                    .buffer_info(&buffer_infos)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .dst_set(descriptor_set)
+                    .dst_binding(4)
+                    .dst_array_element(0),
+            ];
+            unsafe {
+                core.device.update_descriptor_sets(&writes, &[]);
+            }
+
+            buffers.push(buffer);
+        }
+
+        // Pipeline
+        let descriptor_set_layouts = [descriptor_set_layout];
+        let create_info =
+            vk::PipelineLayoutCreateInfoBuilder::new().set_layouts(&descriptor_set_layouts);
+
+        let pipeline_layout =
+            unsafe { core.device.create_pipeline_layout(&create_info, None, None) }.result()?;
+
+        let pipeline = fullscreen_pipeline(
+            &core,
+            FULLSCREEN_VERT,
+            include_bytes!("../shaders/deferred_lighting.frag.spv"),
+            render_pass,
+            pipeline_layout,
+        )?;
+
+        Ok(Self {
+            pipeline,
+            pipeline_layout,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_sets,
+            sampler,
+            buffers,
+            max_lights,
+            ambient: [0.1; 3],
+            reverse_z: false,
+            encode_srgb: false,
+            core,
+        })
+    }
+
+    /// Read from `gbuffer`. Must not be called while a frame using this pass is in flight
+    /// (typically it is called from `swapchain_resize()`, after the G-buffer is resized).
+    pub fn set_inputs(&self, gbuffer: &GBuffer) {
+        let inputs = [
+            (
+                gbuffer.albedo_view(),
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            ),
+            (
+                gbuffer.normal_view(),
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            ),
+            (
+                gbuffer.depth_view(),
+                vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            ),
+        ];
+        let image_infos: Vec<_> = inputs
+            .iter()
+            .map(|&(view, layout)| {
+                [vk::DescriptorImageInfoBuilder::new()
+                    .image_layout(layout)
+                    .image_view(view)]
+            })
+            .collect();
+
+        for &descriptor_set in &self.descriptor_sets {
+            let writes: Vec<_> = image_infos
+                .iter()
+                .enumerate()
+                .map(|(i, info)| {
+                    vk::WriteDescriptorSetBuilder::new()
+                        .image_info(info)
+                        .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                        .dst_set(descriptor_set)
+                        .dst_binding(i as u32 + 1)
+                        .dst_array_element(0)
+                })
+                .collect();
+
+            unsafe {
+                self.core.device.update_descriptor_sets(&writes, &[]);
+            }
+        }
+    }
+
+    /// Upload the lights for `frame`. `camera` holds the view-projection matrix of each view, as
+    /// in the camera UBO (see `MultiPlatformCamera::get_matrices()`), and is used to find the
+    /// position of each pixel.
+    pub fn update(
+        &mut self,
+        frame: usize,
+        camera: &[f32; 4 * 4 * 2],
+        lights: &[Light],
+    ) -> Result<()> {
+        ensure!(
+            lights.len() <= self.max_lights,
+            "{} lights exceed the capacity of {}",
+            lights.len(),
+            self.max_lights
+        );
+
+        let mut inverse_camera = [0.; 4 * 4 * 2];
+        for (inverse, matrix) in inverse_camera
+            .chunks_exact_mut(16)
+            .zip(camera.chunks_exact(16))
+        {
+            let matrix = Matrix4::from_column_slice(matrix);
+            let matrix = matrix.try_inverse().unwrap_or_else(Matrix4::identity);
+            inverse.copy_from_slice(matrix.as_slice());
+        }
+
+        let [r, g, b] = self.ambient;
+        let header = LightsHeader {
+            inverse_camera,
+            ambient: [r, g, b, 0.],
+            light_count: lights.len() as u32,
+            clear_depth: clear_depth(self.reverse_z),
+            encode_srgb: self.encode_srgb.into(),
+            _pad: 0,
+        };
+
+        let buffer = &mut self.buffers[frame];
+        buffer.write_slice(0, &[header])?;
+        if !lights.is_empty() {
+            buffer.write_slice(std::mem::size_of::<LightsHeader>() as u64, lights)?;
+        }
+        Ok(())
+    }
+
+    /// Draw the lit scene using the lights of `frame`. Assumes a render pass compatible with the
+    /// one this pass was created against is active, with the viewport and scissor set.
+    pub fn draw(&self, command_buffer: vk::CommandBuffer, frame: usize) {
+        unsafe {
+            self.core.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            self.core.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_sets[frame]],
+                &[],
+            );
+            self.core.device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+    }
+
+    /// Maximum number of lights per frame
+    pub fn max_lights(&self) -> usize {
+        self.max_lights
+    }
+}
+
+impl Drop for DeferredLighting {
+    fn drop(&mut self) {
+        unsafe {
+            self.core.device.device_wait_idle().unwrap();
+            self.core.destroy_descriptor_pool(self.descriptor_pool);
+            self.core
+                .device
+                .destroy_descriptor_set_layout(Some(self.descriptor_set_layout), None);
+            self.core
+                .device
+                .destroy_pipeline_layout(Some(self.pipeline_layout), None);
+            self.core.destroy_pipeline(self.pipeline);
+            self.core.device.destroy_sampler(Some(self.sampler), None);
+        }
+    }
+}
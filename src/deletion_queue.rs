@@ -0,0 +1,184 @@
+//! Deferred destruction of GPU resources. Dropping a `ManagedBuffer`, `ManagedImage` or
+//! `PipelineHandle` doesn't wait for the queue; its handles and memory are queued here instead,
+//! and destroyed by `Core::collect_garbage()` once all work submitted to the core's queues (the
+//! main queue, and the compute and transfer queues if present) before the next collection has
+//! completed. Work submitted to queues the core doesn't own isn't waited on. The backends
+//! collect garbage after each frame, and anything left over is destroyed along with the `Core`.
+use crate::memory::MemoryBlock;
+use crate::Core;
+use anyhow::{bail, Result};
+use erupt::vk;
+use std::sync::Mutex;
+
+/// A resource which may still be in use by the GPU
+pub(crate) enum Garbage {
+    Buffer(vk::Buffer, MemoryBlock),
+    Image(vk::Image, MemoryBlock),
+    Pipeline(vk::Pipeline),
+}
+
+/// Resources waiting to be destroyed, kept by the `Core`
+pub struct DeletionQueue {
+    inner: Mutex<DeletionQueueInner>,
+}
+
+#[derive(Default)]
+struct DeletionQueueInner {
+    /// Dropped since the last collection
+    pending: Vec<Garbage>,
+    /// Batches of garbage, each freed once its fences (one per queue) are signalled
+    in_flight: Vec<(Vec<vk::Fence>, Vec<Garbage>)>,
+    /// Signalled fences, reset and ready for reuse
+    free_fences: Vec<vk::Fence>,
+}
+
+impl DeletionQueue {
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(Default::default()),
+        }
+    }
+
+    /// Number of resources not yet destroyed
+    pub fn len(&self) -> usize {
+        let inner = self.inner.lock().unwrap();
+        inner.pending.len()
+            + inner
+                .in_flight
+                .iter()
+                .map(|(_, batch)| batch.len())
+                .sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for DeletionQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Core {
+    /// Queue a resource to be destroyed once the GPU is done with it
+    pub(crate) fn defer_destroy(&self, garbage: Garbage) {
+        self.deletion_queue
+            .inner
+            .lock()
+            .unwrap()
+            .pending
+            .push(garbage);
+    }
+
+    /// Destroy resources whose last use has completed, and fence off those dropped since the last
+    /// call. Called by the backends after each frame; apps driving the queue themselves should call
+    /// this periodically. Its fence submissions lock each queue (see `lock_queue()`), so other
+    /// threads may keep submitting, such as with an `AsyncCompute`.
+    pub fn collect_garbage(&self) -> Result<()> {
+        let mut inner = self.deletion_queue.inner.lock().unwrap();
+
+        let mut idx = 0;
+        while idx < inner.in_flight.len() {
+            if self.all_signalled(&inner.in_flight[idx].0)? {
+                let (fences, batch) = inner.in_flight.swap_remove(idx);
+                unsafe { self.device.reset_fences(&fences) }.result()?;
+                inner.free_fences.extend(fences);
+                for garbage in batch {
+                    self.destroy_garbage(garbage);
+                }
+            } else {
+                idx += 1;
+            }
+        }
+
+        if inner.pending.is_empty() {
+            return Ok(());
+        }
+
+        let queues = std::iter::once(self.queue)
+            .chain(self.compute_queue.map(|q| q.queue))
+            .chain(self.transfer_queue.map(|q| q.queue));
+
+        // An empty submission's fence is signalled once all earlier submissions to its queue have
+        // completed; the batch may still be in use by any of them
+        let mut fences = vec![];
+        for queue in queues {
+            let fence = match inner.free_fences.pop() {
+                Some(fence) => fence,
+                None => {
+                    let create_info = vk::FenceCreateInfoBuilder::new();
+                    unsafe { self.device.create_fence(&create_info, None, None) }.result()?
+                }
+            };
+            let _queue = self.lock_queue(queue);
+            unsafe { self.device.queue_submit(queue, &[], Some(fence)) }.result()?;
+            fences.push(fence);
+        }
+        let batch = std::mem::take(&mut inner.pending);
+        inner.in_flight.push((fences, batch));
+
+        Ok(())
+    }
+
+    /// Whether every fence of an in-flight batch has been signalled
+    fn all_signalled(&self, fences: &[vk::Fence]) -> Result<bool> {
+        for &fence in fences {
+            match unsafe { self.device.get_fence_status(fence) }.raw {
+                vk::Result::SUCCESS => (),
+                vk::Result::NOT_READY => return Ok(false),
+                other => bail!("Failed to get fence status: {}", other),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Wait for the device, then destroy all queued resources and the queue's fences
+    pub(crate) fn destroy_all_garbage(&self) {
+        let mut inner = self.deletion_queue.inner.lock().unwrap();
+        let DeletionQueueInner {
+            pending,
+            in_flight,
+            free_fences,
+        } = std::mem::take(&mut *inner);
+        if pending.is_empty() && in_flight.is_empty() && free_fences.is_empty() {
+            return;
+        }
+
+        unsafe { self.device.device_wait_idle() }.result().unwrap();
+        for (fences, batch) in in_flight {
+            for garbage in batch {
+                self.destroy_garbage(garbage);
+            }
+            for fence in fences {
+                unsafe { self.device.destroy_fence(Some(fence), None) };
+            }
+        }
+        for garbage in pending {
+            self.destroy_garbage(garbage);
+        }
+        for fence in free_fences {
+            unsafe { self.device.destroy_fence(Some(fence), None) };
+        }
+    }
+
+    fn destroy_garbage(&self, garbage: Garbage) {
+        match garbage {
+            Garbage::Buffer(buffer, memory) => {
+                unsafe { self.device.destroy_buffer(Some(buffer), None) };
+                self.resources.buffer_destroyed(memory.size());
+                self.deallocate(memory).unwrap();
+            }
+            Garbage::Image(image, memory) => {
+                unsafe { self.device.destroy_image(Some(image), None) };
+                self.resources.image_destroyed(memory.size());
+                self.deallocate(memory).unwrap();
+            }
+            Garbage::Pipeline(pipeline) => {
+                unsafe { self.device.destroy_pipeline(Some(pipeline), None) };
+                self.resources.pipeline_destroyed(pipeline);
+            }
+        }
+    }
+}
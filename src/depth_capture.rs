@@ -0,0 +1,200 @@
+//! Reading depth buffers back to the CPU, such as to export depth maps or to find what is under
+//! the cursor without a pick pass. The depth image must have been created with `TRANSFER_SRC`
+//! usage and stored by its render pass; for a `RenderTarget`, set
+//! `RenderTargetSettings::extra_depth_usage`.
+//!
+//! ```ignore
+//! // After the target's pass, outside of any render pass
+//! let capture = target.capture_depth(command_buffer, 0)?;
+//! // Once the frame's commands have completed
+//! let depth_map = capture.read()?;
+//! let point = depth_map.unproject(&camera, cursor.0, cursor.1, reverse_z);
+//! ```
+use crate::barriers;
+use crate::copy;
+use crate::memory::{aspect_mask, ManagedBuffer, UsageFlags};
+use crate::sync2::{Access, Stages};
+use crate::SharedCore;
+use anyhow::{bail, Context, Result};
+use erupt::vk;
+use std::io::Write;
+use std::path::Path;
+
+/// A layer of a depth image being copied into a readback buffer
+pub struct DepthCapture {
+    buffer: ManagedBuffer,
+    extent: vk::Extent2D,
+    format: vk::Format,
+}
+
+impl DepthCapture {
+    /// Record a copy of the depth aspect of array layer `layer` of `image`, which must be
+    /// outside of a render pass and in `layout`. The image is returned to `layout` afterwards.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record(
+        core: &SharedCore,
+        command_buffer: vk::CommandBuffer,
+        image: vk::Image,
+        layout: vk::ImageLayout,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        layer: u32,
+    ) -> Result<Self> {
+        let texel_size = match texel_size(format) {
+            Some(size) => size,
+            None => bail!("Depth captures of {:?} images are not supported", format),
+        };
+
+        let ci = vk::BufferCreateInfoBuilder::new()
+            .usage(vk::BufferUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .size(extent.width as u64 * extent.height as u64 * texel_size);
+        let buffer = ManagedBuffer::new(core.clone(), ci, UsageFlags::DOWNLOAD)?;
+
+        // Barriers cover every aspect of the format, including stencil
+        let range = vk::ImageSubresourceRangeBuilder::new()
+            .aspect_mask(aspect_mask(format))
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(layer)
+            .layer_count(1)
+            .build();
+        let to_transfer =
+            barriers::image_barrier(image, range, layout, vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
+        let to_host = barriers::buffer_barrier(
+            buffer.instance(),
+            (Stages::TRANSFER_KHR, Access::TRANSFER_WRITE_KHR),
+            (Stages::HOST_KHR, Access::HOST_READ_KHR),
+        );
+        let restore =
+            barriers::image_barrier(image, range, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, layout);
+
+        core.cmd_barriers(command_buffer, &[], &[to_transfer]);
+        copy::cmd_copy_aspect_to_buffer(
+            core,
+            command_buffer,
+            image,
+            vk::ImageAspectFlags::DEPTH,
+            layer,
+            vk::Offset2D { x: 0, y: 0 },
+            extent,
+            buffer.instance(),
+        );
+        core.cmd_barriers(command_buffer, &[to_host], &[restore]);
+
+        Ok(Self {
+            buffer,
+            extent,
+            format,
+        })
+    }
+
+    /// Read back the depths, converted to floats from 0 to 1. Call once the frame's commands
+    /// have completed.
+    pub fn read(mut self) -> Result<DepthMap> {
+        let mut bytes = vec![0u8; self.buffer.size() as usize];
+        self.buffer.read_bytes(0, &mut bytes)?;
+
+        let n_texels = self.extent.width as usize * self.extent.height as usize;
+        let depths = match self.format {
+            vk::Format::D32_SFLOAT | vk::Format::D32_SFLOAT_S8_UINT => bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+                .take(n_texels)
+                .collect(),
+            // 24 bits of depth in the low bits of each 32, the rest undefined
+            vk::Format::D24_UNORM_S8_UINT | vk::Format::X8_D24_UNORM_PACK32 => bytes
+                .chunks_exact(4)
+                .map(|b| {
+                    let depth = u32::from_ne_bytes([b[0], b[1], b[2], b[3]]) & 0xff_ffff;
+                    depth as f32 / 0xff_ffff as f32
+                })
+                .take(n_texels)
+                .collect(),
+            vk::Format::D16_UNORM | vk::Format::D16_UNORM_S8_UINT => bytes
+                .chunks_exact(2)
+                .map(|b| u16::from_ne_bytes([b[0], b[1]]) as f32 / u16::MAX as f32)
+                .take(n_texels)
+                .collect(),
+            format => bail!("Depth captures of {:?} images are not supported", format),
+        };
+
+        Ok(DepthMap {
+            extent: self.extent,
+            depths,
+        })
+    }
+}
+
+/// Bytes per texel of the depth aspect of `format` when copied to a buffer
+fn texel_size(format: vk::Format) -> Option<u64> {
+    match format {
+        vk::Format::D32_SFLOAT
+        | vk::Format::D32_SFLOAT_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::X8_D24_UNORM_PACK32 => Some(4),
+        vk::Format::D16_UNORM | vk::Format::D16_UNORM_S8_UINT => Some(2),
+        _ => None,
+    }
+}
+
+/// Depths read back by a `DepthCapture`, in rows from the top
+pub struct DepthMap {
+    pub extent: vk::Extent2D,
+    pub depths: Vec<f32>,
+}
+
+impl DepthMap {
+    /// Depth of the pixel at `x`, `y` from the top left
+    pub fn get(&self, x: u32, y: u32) -> f32 {
+        self.depths[(y * self.extent.width + x) as usize]
+    }
+
+    /// Whether nothing was drawn at `x`, `y`, leaving the depth as cleared
+    pub fn is_background(&self, x: u32, y: u32, reverse_z: bool) -> bool {
+        self.get(x, y) == crate::shader::clear_depth(reverse_z)
+    }
+
+    /// World position of what was drawn at the center of pixel `x`, `y`, for the camera matrix
+    /// it was drawn with, or None if nothing was drawn there
+    #[cfg(feature = "nalgebra")]
+    pub fn unproject(
+        &self,
+        camera: &nalgebra::Matrix4<f32>,
+        x: u32,
+        y: u32,
+        reverse_z: bool,
+    ) -> Option<nalgebra::Point3<f32>> {
+        if self.is_background(x, y, reverse_z) {
+            return None;
+        }
+        let ndc_x = (x as f32 + 0.5) / self.extent.width as f32 * 2.0 - 1.0;
+        let ndc_y = (y as f32 + 0.5) / self.extent.height as f32 * 2.0 - 1.0;
+        let clip = nalgebra::Vector4::new(ndc_x, ndc_y, self.get(x, y), 1.0);
+        let world = camera.try_inverse()? * clip;
+        Some(nalgebra::Point3::from(world.xyz() / world.w))
+    }
+
+    /// Save as a grayscale PFM file, which keeps the full float precision and is read by most
+    /// image tools
+    pub fn save_pfm(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        let mut pfm = std::io::BufWriter::new(file);
+        // A negative scale means little endian
+        write!(
+            pfm,
+            "Pf\n{} {}\n-1.0\n",
+            self.extent.width, self.extent.height
+        )?;
+        // Rows are stored from the bottom
+        for row in self.depths.chunks_exact(self.extent.width as usize).rev() {
+            for depth in row {
+                pfm.write_all(&depth.to_le_bytes())?;
+            }
+        }
+        pfm.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,290 @@
+//! Presents straight to a display through `VK_KHR_display`, without a window system. Useful for
+//! kiosks and embedded installations, or Linux machines without a compositor (run from a virtual
+//! terminal). The core is built as in the headless backend, and the app runs until it calls
+//! `Platform::request_exit()`.
+use crate::hardware_query::pick_surface_format;
+use crate::winit_backend::{SurfaceSettings, Swapchain};
+use crate::{
+    app_info::AppInfo,
+    headless_backend,
+    mainloop::{Frame, Platform, SyncMainLoop},
+    profiling::{self, FrameEvent},
+    Core, SharedCore,
+};
+use anyhow::{bail, format_err, Result};
+use erupt::extensions::{
+    khr_display::{self, DisplayKHR, DisplayModePropertiesKHR},
+    khr_surface::{self, PresentModeKHR, SurfaceTransformFlagBitsKHR},
+    khr_swapchain,
+};
+use erupt::vk;
+use std::ffi::CStr;
+use std::time::Duration;
+
+/// Run the app fullscreen on the first display connected to the GPU
+pub fn launch<M: SyncMainLoop<D> + 'static, D>(info: AppInfo, data: D) -> Result<()> {
+    launch_on::<M, D>(info, 0, data)
+}
+
+/// Run the app fullscreen on the display with the given index, see `displays()`
+pub fn launch_on<M: SyncMainLoop<D> + 'static, D>(
+    info: AppInfo,
+    display_index: usize,
+    data: D,
+) -> Result<()> {
+    let image_count = info.swapchain_images;
+    let srgb_swapchain = info.srgb_swapchain;
+    let acquire_timeout = info.acquire_timeout;
+    let core = build_core(info)?;
+    let (surface, extent) = create_surface(
+        &core,
+        display_index,
+        image_count,
+        srgb_swapchain,
+        acquire_timeout,
+    )?;
+    begin_loop::<M, D>(core, surface, extent, data)
+}
+
+/// Build a core able to present to displays
+pub fn build_core(info: AppInfo) -> Result<Core> {
+    headless_backend::build_core_with_extensions(
+        info,
+        vec![
+            khr_surface::KHR_SURFACE_EXTENSION_NAME,
+            khr_display::KHR_DISPLAY_EXTENSION_NAME,
+        ],
+        vec![khr_swapchain::KHR_SWAPCHAIN_EXTENSION_NAME],
+    )
+}
+
+/// Names of the displays connected to the core's GPU, in the order used by `launch_on()`
+pub fn displays(core: &Core) -> Result<Vec<String>> {
+    let displays = unsafe {
+        core.instance
+            .get_physical_device_display_properties_khr(core.physical_device, None)
+    }
+    .result()?;
+
+    Ok(displays
+        .iter()
+        .map(|display| match display.display_name.is_null() {
+            true => String::from("Unnamed display"),
+            false => unsafe { CStr::from_ptr(display.display_name) }
+                .to_string_lossy()
+                .into_owned(),
+        })
+        .collect())
+}
+
+/// Create a surface covering the given display at its largest, then fastest, mode, with a
+/// format picked as in `AppInfo::srgb_swapchain()`
+pub fn create_surface(
+    core: &Core,
+    display_index: usize,
+    image_count: Option<u32>,
+    srgb_swapchain: bool,
+    acquire_timeout: Option<Duration>,
+) -> Result<(SurfaceSettings, vk::Extent2D)> {
+    let displays = unsafe {
+        core.instance
+            .get_physical_device_display_properties_khr(core.physical_device, None)
+    }
+    .result()?;
+    let display = displays
+        .get(display_index)
+        .ok_or_else(|| {
+            format_err!(
+                "Display {} requested, but {} are connected",
+                display_index,
+                displays.len()
+            )
+        })?
+        .display;
+
+    let mode = pick_mode(core, display)?;
+    let extent = mode.parameters.visible_region;
+    let plane_index = pick_plane(core, display)?;
+
+    let planes = unsafe {
+        core.instance
+            .get_physical_device_display_plane_properties_khr(core.physical_device, None)
+    }
+    .result()?;
+
+    let caps = unsafe {
+        core.instance.get_display_plane_capabilities_khr(
+            core.physical_device,
+            mode.display_mode,
+            plane_index,
+            None,
+        )
+    }
+    .result()?;
+
+    let alpha_mode = if caps
+        .supported_alpha
+        .contains(khr_display::DisplayPlaneAlphaFlagsKHR::OPAQUE_KHR)
+    {
+        khr_display::DisplayPlaneAlphaFlagBitsKHR::OPAQUE_KHR
+    } else {
+        khr_display::DisplayPlaneAlphaFlagBitsKHR::GLOBAL_KHR
+    };
+
+    let create_info = khr_display::DisplaySurfaceCreateInfoKHRBuilder::new()
+        .display_mode(mode.display_mode)
+        .plane_index(plane_index)
+        .plane_stack_index(planes[plane_index as usize].current_stack_index)
+        .transform(SurfaceTransformFlagBitsKHR::IDENTITY_KHR)
+        .global_alpha(1.0)
+        .alpha_mode(alpha_mode)
+        .image_extent(extent);
+
+    let surface = unsafe {
+        core.instance
+            .create_display_plane_surface_khr(&create_info, None, None)
+    }
+    .result()?;
+
+    let supported = unsafe {
+        core.instance.get_physical_device_surface_support_khr(
+            core.physical_device,
+            core.queue_family,
+            surface,
+            None,
+        )
+    }
+    .result()?;
+    if !supported {
+        unsafe { core.instance.destroy_surface_khr(Some(surface), None) };
+        bail!("The selected queue cannot present to this display");
+    }
+
+    let formats = unsafe {
+        core.instance
+            .get_physical_device_surface_formats_khr(core.physical_device, surface, None)
+    }
+    .result()?;
+    let format = pick_surface_format(&formats, srgb_swapchain)
+        .ok_or_else(|| format_err!("Display surface reports no formats"))?;
+
+    let surface = SurfaceSettings {
+        surface,
+        // The only mode guaranteed to be supported
+        present_mode: PresentModeKHR::FIFO_KHR,
+        format,
+        image_count,
+        acquire_timeout,
+    };
+
+    Ok((surface, extent))
+}
+
+/// Largest resolution, then highest refresh rate
+fn pick_mode(core: &Core, display: DisplayKHR) -> Result<DisplayModePropertiesKHR> {
+    let modes = unsafe {
+        core.instance
+            .get_display_mode_properties_khr(core.physical_device, display, None)
+    }
+    .result()?;
+
+    modes
+        .into_iter()
+        .max_by_key(|mode| {
+            let vk::Extent2D { width, height } = mode.parameters.visible_region;
+            (width * height, mode.parameters.refresh_rate)
+        })
+        .ok_or_else(|| format_err!("Display has no modes"))
+}
+
+/// A plane which can show the display, and isn't in use by another
+fn pick_plane(core: &Core, display: DisplayKHR) -> Result<u32> {
+    let planes = unsafe {
+        core.instance
+            .get_physical_device_display_plane_properties_khr(core.physical_device, None)
+    }
+    .result()?;
+
+    for (index, plane) in planes.iter().enumerate() {
+        let index = index as u32;
+        if plane.current_display != DisplayKHR::null() && plane.current_display != display {
+            continue;
+        }
+
+        let supported = unsafe {
+            core.instance.get_display_plane_supported_displays_khr(
+                core.physical_device,
+                index,
+                None,
+            )
+        }
+        .result()?;
+
+        if supported.contains(&display) {
+            return Ok(index);
+        }
+    }
+
+    bail!("No display plane available for this display")
+}
+
+fn begin_loop<M: SyncMainLoop<D> + 'static, D>(
+    core: Core,
+    surface: SurfaceSettings,
+    extent: vk::Extent2D,
+    data: D,
+) -> Result<()> {
+    let core = SharedCore::new(core);
+    let color_format = surface.format.format;
+    let mut exit = false;
+
+    let mut app = M::new(
+        &core,
+        Platform::Display {
+            exit: &mut exit,
+            color_format,
+            extent,
+        },
+        data,
+    )?;
+
+    let (mut swapchain, (images, swapchain_extent)) = Swapchain::new(core.clone(), surface, extent)?;
+    app.swapchain_resize(images, swapchain_extent)?;
+
+    while !exit {
+        // Prepare inputs
+        let (image_available, render_finished) = app.winit_sync();
+        let (swapchain_index, resize) =
+            profiling::timed(FrameEvent::Acquire, || swapchain.frame(image_available))?;
+        if let Some((images, extent)) = resize {
+            app.swapchain_resize(images, extent)?;
+        }
+
+        // Run app's frame method
+        #[cfg(feature = "renderdoc")]
+        crate::capture::begin_frame();
+        app.frame(
+            Frame { swapchain_index },
+            &core,
+            Platform::Display {
+                exit: &mut exit,
+                color_format,
+                extent,
+            },
+        )?;
+
+        // Present
+        profiling::timed(FrameEvent::Present, || {
+            swapchain.queue_present(swapchain_index, render_finished)
+        })?;
+        #[cfg(feature = "renderdoc")]
+        crate::capture::end_frame();
+        core.collect_garbage()?;
+    }
+
+    // Release the app's resources before the swapchain and surface
+    drop(app);
+    drop(swapchain);
+
+    Ok(())
+}
@@ -0,0 +1,165 @@
+//! Images whose memory is exported as file descriptors, so rendered frames can be handed to a
+//! video encoder or another process without a copy through the CPU, such as in a streaming
+//! server. Requires a core built with `HeadlessExtensions::external_memory`. The exported memory
+//! carries no synchronization: wait for the frame's fence before handing it on, and don't render
+//! into the image again until the receiver is done with it.
+//!
+//! ```ignore
+//! let extensions = HeadlessExtensions {
+//!     external_memory: true,
+//!     ..Default::default()
+//! };
+//! let core = SharedCore::new(headless_backend::build_core_with(info, extensions)?);
+//! let frame = ExportableImage::new(core.clone(), create_info)?;
+//! // Once the commands rendering into `frame.instance()` have completed
+//! encoder.import_opaque_fd(frame.export_fd()?, frame.size())?;
+//! ```
+use crate::SharedCore;
+use anyhow::{bail, ensure, Context, Result};
+use erupt::vk;
+use std::os::unix::io::RawFd;
+
+/// Handle type of the exported memory, importable by Vulkan, OpenGL and CUDA on the same device
+pub const HANDLE_TYPE: vk::ExternalMemoryHandleTypeFlagBits =
+    vk::ExternalMemoryHandleTypeFlagBits::OPAQUE_FD;
+
+/// An image in a dedicated, exportable device-local allocation
+pub struct ExportableImage {
+    instance: vk::Image,
+    memory: vk::DeviceMemory,
+    size: u64,
+    core: SharedCore,
+}
+
+impl ExportableImage {
+    /// Create an image, failing if the device can't export images of its format and usage
+    pub fn new(core: SharedCore, create_info: vk::ImageCreateInfoBuilder<'static>) -> Result<Self> {
+        let mut create_info = create_info.build();
+
+        // Check the image can be exported at all
+        let mut external_format_info = vk::PhysicalDeviceExternalImageFormatInfoBuilder::new()
+            .handle_type(HANDLE_TYPE)
+            .build();
+        let mut format_info = vk::PhysicalDeviceImageFormatInfo2Builder::new()
+            .format(create_info.format)
+            ._type(create_info.image_type)
+            .tiling(create_info.tiling)
+            .usage(create_info.usage)
+            .flags(create_info.flags)
+            .build();
+        format_info.p_next = &mut external_format_info as *mut _ as _;
+        let mut external_properties = vk::ExternalImageFormatPropertiesBuilder::new().build();
+        let mut properties = vk::ImageFormatProperties2Builder::new().build();
+        properties.p_next = &mut external_properties as *mut _ as _;
+        unsafe {
+            core.instance.get_physical_device_image_format_properties2(
+                core.physical_device,
+                &format_info,
+                Some(properties),
+            )
+        }
+        .result()
+        .with_context(|| format!("{:?} images are not supported", create_info.format))?;
+        ensure!(
+            external_properties
+                .external_memory_properties
+                .external_memory_features
+                .contains(vk::ExternalMemoryFeatureFlags::EXPORTABLE),
+            "{:?} images can't be exported by this device",
+            create_info.format
+        );
+
+        // Image
+        let mut external_create_info = vk::ExternalMemoryImageCreateInfoBuilder::new()
+            .handle_types(vk::ExternalMemoryHandleTypeFlags::from_bits_truncate(
+                HANDLE_TYPE.0,
+            ))
+            .build();
+        external_create_info.p_next = create_info.p_next;
+        create_info.p_next = &mut external_create_info as *mut _ as _;
+        let instance = unsafe { core.device.create_image(&create_info, None, None) }.result()?;
+
+        // Dedicated memory, which some drivers require for exports
+        let requirements = unsafe { core.device.get_image_memory_requirements(instance, None) };
+        let memory_properties = unsafe {
+            core.instance
+                .get_physical_device_memory_properties(core.physical_device, None)
+        };
+        let memory_type = (0..memory_properties.memory_type_count).find(|&i| {
+            requirements.memory_type_bits & (1 << i) != 0
+                && memory_properties.memory_types[i as usize]
+                    .property_flags
+                    .contains(vk::MemoryPropertyFlags::DEVICE_LOCAL)
+        });
+        let memory_type = match memory_type {
+            Some(memory_type) => memory_type,
+            None => {
+                unsafe { core.device.destroy_image(Some(instance), None) };
+                bail!("No device local memory type for exportable images");
+            }
+        };
+
+        let mut dedicated_info = vk::MemoryDedicatedAllocateInfoBuilder::new()
+            .image(instance)
+            .build();
+        let mut export_info = vk::ExportMemoryAllocateInfoBuilder::new()
+            .handle_types(vk::ExternalMemoryHandleTypeFlags::from_bits_truncate(
+                HANDLE_TYPE.0,
+            ))
+            .build();
+        export_info.p_next = &mut dedicated_info as *mut _ as _;
+        let mut allocate_info = vk::MemoryAllocateInfoBuilder::new()
+            .allocation_size(requirements.size)
+            .memory_type_index(memory_type)
+            .build();
+        allocate_info.p_next = &mut export_info as *mut _ as _;
+
+        let memory =
+            match unsafe { core.device.allocate_memory(&allocate_info, None, None) }.result() {
+                Ok(memory) => memory,
+                Err(e) => {
+                    unsafe { core.device.destroy_image(Some(instance), None) };
+                    return Err(e).context("Failed to allocate exportable image memory");
+                }
+            };
+        unsafe { core.device.bind_image_memory(instance, memory, 0) }.result()?;
+        core.resources.image_created(requirements.size);
+
+        Ok(Self {
+            instance,
+            memory,
+            size: requirements.size,
+            core,
+        })
+    }
+
+    pub fn instance(&self) -> vk::Image {
+        self.instance
+    }
+
+    /// Size of the image's memory in bytes, which importers usually need alongside the handle
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Export the image's memory as a new file descriptor, owned by the caller. The image stays
+    /// usable, and the memory is freed once it is dropped and every descriptor is closed.
+    pub fn export_fd(&self) -> Result<RawFd> {
+        let get_fd_info = vk::MemoryGetFdInfoKHRBuilder::new()
+            .memory(self.memory)
+            .handle_type(HANDLE_TYPE);
+        let fd = unsafe { self.core.device.get_memory_fd_khr(&get_fd_info, None) }.result()?;
+        Ok(fd)
+    }
+}
+
+impl Drop for ExportableImage {
+    fn drop(&mut self) {
+        unsafe {
+            self.core.device.device_wait_idle().unwrap();
+            self.core.device.destroy_image(Some(self.instance), None);
+            self.core.device.free_memory(Some(self.memory), None);
+        }
+        self.core.resources.image_destroyed(self.size);
+    }
+}
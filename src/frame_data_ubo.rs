@@ -1,4 +1,5 @@
 use crate::{memory, memory::ManagedBuffer};
+use crate::barriers;
 use crate::SharedCore;
 use anyhow::Result;
 use bytemuck::Pod;
@@ -25,7 +26,10 @@ impl<T: Pod> FrameDataUbo<T> {
             .size(total_size)
             .sharing_mode(vk::SharingMode::EXCLUSIVE)
             .usage(vk::BufferUsageFlags::UNIFORM_BUFFER);
-        let buffer = ManagedBuffer::new(core, ci, memory::UsageFlags::UPLOAD)?;
+        let mut buffer = ManagedBuffer::new(core, ci, memory::UsageFlags::UPLOAD)?;
+
+        // Written every frame, so avoid mapping it each time
+        buffer.map_persistent()?;
 
         Ok(Self {
             frames,
@@ -48,9 +52,96 @@ impl<T: Pod> FrameDataUbo<T> {
     }
 
     pub fn upload(&mut self, frame: usize, data: &T) -> Result<()> {
-        self.buffer.write_bytes(
-            self.offset(frame),
-            bytemuck::cast_slice(std::slice::from_ref(data)),
-        )
+        self.buffer
+            .write_slice(self.offset(frame), std::slice::from_ref(data))
+    }
+}
+
+/// A `FrameDataUbo` kept in device-local memory, which is faster for shaders to read on
+/// discrete GPUs. Each upload is written to a host-visible copy and transferred by the frame's
+/// command buffer, so it must be recorded outside of a render pass (e.g. after
+/// `StarterKit::begin_frame()` and before `begin_swapchain_pass()`).
+pub struct DeviceFrameDataUbo<T> {
+    buffer: ManagedBuffer,
+    staging: ManagedBuffer,
+    padded_size: u64,
+    frames: usize,
+    core: SharedCore,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Pod> DeviceFrameDataUbo<T> {
+    pub fn new(core: SharedCore, frames: usize) -> Result<Self> {
+        let padded_size = memory::pad_uniform_buffer_size(
+            core.device_properties,
+            std::mem::size_of::<T>() as u64,
+        );
+        let total_size = padded_size * frames as u64;
+
+        let ci = vk::BufferCreateInfoBuilder::new()
+            .size(total_size)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .usage(vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::TRANSFER_DST);
+        let buffer = ManagedBuffer::new(core.clone(), ci, memory::UsageFlags::FAST_DEVICE_ACCESS)?;
+
+        let ci = vk::BufferCreateInfoBuilder::new()
+            .size(total_size)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .usage(vk::BufferUsageFlags::TRANSFER_SRC);
+        let mut staging = ManagedBuffer::new(core.clone(), ci, memory::UsageFlags::UPLOAD)?;
+        staging.map_persistent()?;
+
+        Ok(Self {
+            buffer,
+            staging,
+            padded_size,
+            frames,
+            core,
+            _phantom: PhantomData,
+        })
+    }
+
+    pub fn descriptor_buffer_info(&self, frame: usize) -> vk::DescriptorBufferInfoBuilder<'static> {
+        vk::DescriptorBufferInfoBuilder::new()
+            .buffer(self.buffer.instance())
+            .offset(self.offset(frame))
+            .range(self.padded_size)
+    }
+
+    fn offset(&self, frame: usize) -> u64 {
+        debug_assert!(frame < self.frames, "Invalid frame {}", frame);
+        self.padded_size * frame as u64
+    }
+
+    /// Record the copy of `data` into `frame`'s uniform data, and a barrier making it visible
+    /// to shaders
+    pub fn upload(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        frame: usize,
+        data: &T,
+    ) -> Result<()> {
+        let offset = self.offset(frame);
+        self.staging
+            .write_slice(offset, std::slice::from_ref(data))?;
+
+        let region = vk::BufferCopyBuilder::new()
+            .src_offset(offset)
+            .dst_offset(offset)
+            .size(std::mem::size_of::<T>() as u64);
+        unsafe {
+            self.core.device.cmd_copy_buffer(
+                command_buffer,
+                self.staging.instance(),
+                self.buffer.instance(),
+                &[region],
+            );
+        }
+        let barrier = barriers::transfer_to_buffer_usage(
+            self.buffer.instance(),
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+        );
+        self.core.cmd_barriers(command_buffer, &[barrier], &[]);
+        Ok(())
     }
-}
\ No newline at end of file
+}
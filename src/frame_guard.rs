@@ -0,0 +1,64 @@
+//! Guarding resources used by frames in flight. A `FrameGuard` is held for each submitted frame
+//! until its fence has been waited on; in debug builds, destroying a pipeline or descriptor pool
+//! through the `Core` while any guarded frame may still be executing panics, rather than
+//! silently freeing something the GPU is reading. Call `Core::wait_idle()` before such teardown.
+//! `ManagedBuffer`s and `ManagedImage`s are always safe to drop, see the `deletion_queue` module.
+use crate::{Core, SharedCore};
+use anyhow::Result;
+use erupt::vk;
+
+/// Marks a submitted frame as in flight until dropped. Drop it once the frame's fence has been
+/// waited on, and before the fence is destroyed.
+pub struct FrameGuard {
+    fence: vk::Fence,
+    core: SharedCore,
+}
+
+impl FrameGuard {
+    /// Guard the frame signalling `fence`, which must already have been submitted
+    pub fn new(core: SharedCore, fence: vk::Fence) -> Self {
+        core.resources.frames_in_flight.lock().unwrap().push(fence);
+        Self { fence, core }
+    }
+
+    pub fn fence(&self) -> vk::Fence {
+        self.fence
+    }
+}
+
+impl Drop for FrameGuard {
+    fn drop(&mut self) {
+        let mut frames = self.core.resources.frames_in_flight.lock().unwrap();
+        if let Some(idx) = frames.iter().position(|&f| f == self.fence) {
+            frames.swap_remove(idx);
+        }
+    }
+}
+
+impl Core {
+    /// Wait for all submitted work to complete, then destroy any resources dropped meanwhile
+    pub fn wait_idle(&self) -> Result<()> {
+        unsafe { self.device.device_wait_idle() }.result()?;
+        self.collect_garbage()
+    }
+
+    /// In debug builds, panic if a guarded frame may still be executing. Call before destroying
+    /// an object which frames may reference. Does nothing in release builds.
+    pub fn check_not_in_flight(&self, what: &str) {
+        #[cfg(debug_assertions)]
+        {
+            let frames = self.resources.frames_in_flight.lock().unwrap();
+            for &fence in frames.iter() {
+                let status = unsafe { self.device.get_fence_status(fence) };
+                if status.raw == vk::Result::NOT_READY {
+                    panic!(
+                        "{} destroyed while a frame which may use it is in flight; call Core::wait_idle() first",
+                        what
+                    );
+                }
+            }
+        }
+        #[cfg(not(debug_assertions))]
+        let _ = what;
+    }
+}
@@ -1,6 +1,7 @@
 use crate::{
-    defaults::{COLOR_FORMAT, DEPTH_FORMAT},
-    memory::ManagedImage,
+    defaults::COLOR_FORMAT,
+    memory::{aspect_mask, ManagedImage},
+    render_pass::{view_passes, RenderPassSettings},
 };
 use crate::{Core, SharedCore};
 use anyhow::Result;
@@ -11,37 +12,76 @@ use gpu_alloc::UsageFlags;
 pub struct FramebufferManager {
     internals: Option<Internals>,
     core: SharedCore,
-    vr: bool,
+    settings: RenderPassSettings,
 }
 
 struct Internals {
     pub extent: vk::Extent2D,
     _depth_image: ManagedImage,
-    depth_image_view: vk::ImageView,
+    /// Views of the depth image, one per view pass
+    depth_image_views: Vec<vk::ImageView>,
+    /// Multisampled color image and its views, resolved into the swapchain images
+    msaa: Option<(ManagedImage, Vec<vk::ImageView>)>,
+    /// Swapchain images the framebuffers were created for
+    images: Vec<vk::Image>,
     frames: Vec<Frame>,
 }
 
+/// Framebuffers of a swapchain image, one per view pass
 struct Frame {
-    pub framebuffer: vk::Framebuffer,
-    pub image_view: vk::ImageView,
+    pub framebuffers: Vec<vk::Framebuffer>,
+    pub image_views: Vec<vk::ImageView>,
 }
 
 impl FramebufferManager {
     pub fn new(core: SharedCore, vr: bool) -> Self {
+        Self::new_with_format(core, COLOR_FORMAT, vr)
+    }
+
+    /// Framebuffer manager for swapchain images of the given format, such as
+    /// `Platform::color_format()`
+    pub fn new_with_format(core: SharedCore, color_format: vk::Format, vr: bool) -> Self {
+        let settings = RenderPassSettings {
+            color_format,
+            depth_format: core.depth_format,
+            vr,
+            ..Default::default()
+        };
+        Self::new_with_settings(core, settings)
+    }
+
+    /// Framebuffer manager for a render pass created with
+    /// `create_render_pass_with_settings(settings)`
+    pub fn new_with_settings(core: SharedCore, settings: RenderPassSettings) -> Self {
         Self {
             internals: None,
             core,
-            vr,
+            settings,
         }
     }
 
+    /// Framebuffer of a swapchain image, for the first view pass
     pub fn frame(&self, swapchain_image_index: u32) -> vk::Framebuffer {
+        self.eye_frame(swapchain_image_index, 0)
+    }
+
+    /// Framebuffer of a swapchain image for the given view pass, see `view_passes()`
+    pub fn eye_frame(&self, swapchain_image_index: u32, view: u32) -> vk::Framebuffer {
         let internals = self.internals.as_ref().expect("Frame called before resize");
         let frame = internals
             .frames
             .get(swapchain_image_index as usize)
             .expect("Invalid swapchain image index");
-        frame.framebuffer
+        *frame
+            .framebuffers
+            .get(view as usize)
+            .expect("Invalid view pass")
+    }
+
+    /// Render pass instances needed to draw every view into a framebuffer, one per eye in VR on
+    /// devices without multiview. See `render_pass::view_passes()`.
+    pub fn view_passes(&self) -> u32 {
+        view_passes(&self.core, self.settings.vr)
     }
 
     pub fn resize(
@@ -50,9 +90,12 @@ impl FramebufferManager {
         extent: vk::Extent2D,
         render_pass: vk::RenderPass,
     ) -> Result<()> {
-        let layers = if self.vr { 2 } else { 1 };
+        let layers = if self.settings.vr { 2 } else { 1 };
+        let passes = self.view_passes();
+        let samples = self.settings.samples;
 
         unsafe {
+            let _queue = self.core.lock_queue(self.core.queue);
             self.core.device.queue_wait_idle(self.core.queue).result()?;
         }
 
@@ -72,11 +115,11 @@ impl FramebufferManager {
             )
             .mip_levels(1)
             .array_layers(layers)
-            .format(DEPTH_FORMAT)
+            .format(self.settings.depth_format)
             .tiling(vk::ImageTiling::OPTIMAL)
             .initial_layout(vk::ImageLayout::UNDEFINED)
             .usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT)
-            .samples(vk::SampleCountFlagBits::_1)
+            .samples(samples)
             .sharing_mode(vk::SharingMode::EXCLUSIVE);
 
         let depth_image = ManagedImage::new(
@@ -85,87 +128,173 @@ impl FramebufferManager {
             UsageFlags::FAST_DEVICE_ACCESS,
         )?;
 
-        let create_info = vk::ImageViewCreateInfoBuilder::new()
-            .image(depth_image.instance())
-            .view_type(vk::ImageViewType::_2D)
-            .format(DEPTH_FORMAT)
-            .subresource_range(
-                vk::ImageSubresourceRangeBuilder::new()
-                    .aspect_mask(vk::ImageAspectFlags::DEPTH)
-                    .base_mip_level(0)
-                    .level_count(1)
-                    .base_array_layer(0)
-                    .layer_count(layers)
-                    .build(),
-            );
-        let depth_image_view =
-            unsafe { self.core.device.create_image_view(&create_info, None, None) }.result()?;
+        let depth_image_views = self.pass_views(
+            depth_image.instance(),
+            self.settings.depth_format,
+            aspect_mask(self.settings.depth_format),
+            layers,
+        )?;
+
+        // Create the multisampled color image, shared by all frames like the depth image
+        let msaa = if self.settings.is_multisampled() {
+            let create_info = vk::ImageCreateInfoBuilder::new()
+                .image_type(vk::ImageType::_2D)
+                .extent(
+                    vk::Extent3DBuilder::new()
+                        .width(extent.width)
+                        .height(extent.height)
+                        .depth(1)
+                        .build(),
+                )
+                .mip_levels(1)
+                .array_layers(layers)
+                .format(self.settings.color_format)
+                .tiling(vk::ImageTiling::OPTIMAL)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .usage(
+                    vk::ImageUsageFlags::COLOR_ATTACHMENT
+                        | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+                )
+                .samples(samples)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+            let image = ManagedImage::new(
+                self.core.clone(),
+                create_info,
+                UsageFlags::FAST_DEVICE_ACCESS,
+            )?;
+
+            let views = self.pass_views(
+                image.instance(),
+                self.settings.color_format,
+                vk::ImageAspectFlags::COLOR,
+                layers,
+            )?;
+            Some((image, views))
+        } else {
+            None
+        };
 
         // Build swapchain image views and buffers
         let frames = swapchain_images
             .iter()
             .map(|&image| {
-                let create_info = vk::ImageViewCreateInfoBuilder::new()
-                    .image(image)
-                    .view_type(vk::ImageViewType::_2D)
-                    .format(COLOR_FORMAT)
-                    .components(vk::ComponentMapping {
-                        r: vk::ComponentSwizzle::IDENTITY,
-                        g: vk::ComponentSwizzle::IDENTITY,
-                        b: vk::ComponentSwizzle::IDENTITY,
-                        a: vk::ComponentSwizzle::IDENTITY,
-                    })
-                    .subresource_range(
-                        vk::ImageSubresourceRangeBuilder::new()
-                            .aspect_mask(vk::ImageAspectFlags::COLOR)
-                            .base_mip_level(0)
-                            .level_count(1)
-                            .base_array_layer(0)
-                            .layer_count(layers)
-                            .build(),
-                    );
+                let image_views = self.pass_views(
+                    image,
+                    self.settings.color_format,
+                    vk::ImageAspectFlags::COLOR,
+                    layers,
+                )?;
 
-                let image_view =
-                    unsafe { self.core.device.create_image_view(&create_info, None, None) }
-                        .result()?;
-
-                let attachments = [image_view, depth_image_view];
-                let create_info = vk::FramebufferCreateInfoBuilder::new()
-                    .render_pass(render_pass)
-                    .attachments(&attachments)
-                    .width(extent.width)
-                    .height(extent.height)
-                    .layers(1);
+                let framebuffers = (0..passes as usize)
+                    .map(|pass| {
+                        // Attachment order matches create_render_pass_with_settings()
+                        let image_view = image_views[pass];
+                        let depth_image_view = depth_image_views[pass];
+                        let attachments = match &msaa {
+                            Some((_, msaa_views)) => {
+                                vec![msaa_views[pass], depth_image_view, image_view]
+                            }
+                            None => vec![image_view, depth_image_view],
+                        };
+                        let create_info = vk::FramebufferCreateInfoBuilder::new()
+                            .render_pass(render_pass)
+                            .attachments(&attachments)
+                            .width(extent.width)
+                            .height(extent.height)
+                            .layers(1);
 
-                let framebuffer = unsafe {
-                    self.core
-                        .device
-                        .create_framebuffer(&create_info, None, None)
-                }
-                .result()?;
+                        Ok(unsafe {
+                            self.core
+                                .device
+                                .create_framebuffer(&create_info, None, None)
+                        }
+                        .result()?)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
                 Ok(Frame {
-                    framebuffer,
-                    image_view,
+                    framebuffers,
+                    image_views,
                 })
             })
             .collect::<Result<Vec<_>>>()?;
 
         self.internals = Some(Internals {
             _depth_image: depth_image,
-            depth_image_view,
+            depth_image_views,
+            msaa,
             extent,
+            images: swapchain_images,
             frames,
         });
 
         Ok(())
     }
 
+    /// Views of `layers` layers of an image for each view pass: one of every layer with
+    /// multiview, otherwise one of each layer
+    fn pass_views(
+        &self,
+        image: vk::Image,
+        format: vk::Format,
+        aspect_mask: vk::ImageAspectFlags,
+        layers: u32,
+    ) -> Result<Vec<vk::ImageView>> {
+        let passes = self.view_passes();
+        (0..passes)
+            .map(|pass| {
+                let create_info = vk::ImageViewCreateInfoBuilder::new()
+                    .image(image)
+                    .view_type(vk::ImageViewType::_2D)
+                    .format(format)
+                    .subresource_range(
+                        vk::ImageSubresourceRangeBuilder::new()
+                            .aspect_mask(aspect_mask)
+                            .base_mip_level(0)
+                            .level_count(1)
+                            .base_array_layer(pass * layers / passes)
+                            .layer_count(layers / passes)
+                            .build(),
+                    );
+                Ok(
+                    unsafe { self.core.device.create_image_view(&create_info, None, None) }
+                        .result()?,
+                )
+            })
+            .collect()
+    }
+
     pub fn extent(&self) -> vk::Extent2D {
         self.internals
             .as_ref()
             .expect("Dimensions called before resize")
             .extent
     }
+
+    /// Swapchain images passed to the last `resize()`
+    pub fn images(&self) -> &[vk::Image] {
+        &self
+            .internals
+            .as_ref()
+            .expect("Images called before resize")
+            .images
+    }
+
+    /// Format of the swapchain images
+    pub fn color_format(&self) -> vk::Format {
+        self.settings.color_format
+    }
+
+    /// Attachments of the render pass the framebuffers are for
+    pub fn settings(&self) -> &RenderPassSettings {
+        &self.settings
+    }
+
+    /// Whether the framebuffers have two views, and the images are left in
+    /// `COLOR_ATTACHMENT_OPTIMAL` rather than `PRESENT_SRC_KHR` by the render pass
+    pub fn is_vr(&self) -> bool {
+        self.settings.vr
+    }
 }
 
 impl Drop for FramebufferManager {
@@ -181,12 +310,21 @@ impl Internals {
         unsafe {
             core.device.device_wait_idle().result().unwrap();
             for frame in self.frames.drain(..) {
-                core.device
-                    .destroy_framebuffer(Some(frame.framebuffer), None);
-                core.device.destroy_image_view(Some(frame.image_view), None);
+                for framebuffer in frame.framebuffers {
+                    core.device.destroy_framebuffer(Some(framebuffer), None);
+                }
+                for image_view in frame.image_views {
+                    core.device.destroy_image_view(Some(image_view), None);
+                }
+            }
+            for view in self.depth_image_views.drain(..) {
+                core.device.destroy_image_view(Some(view), None);
+            }
+            if let Some((_, views)) = self.msaa.take() {
+                for view in views {
+                    core.device.destroy_image_view(Some(view), None);
+                }
             }
-            core.device
-                .destroy_image_view(Some(self.depth_image_view), None);
         }
     }
 }
@@ -0,0 +1,90 @@
+//! Compile GLSL to SPIR-V from a build script, to be embedded with `compile_glsl!` instead of
+//! checking in `.spv` files. Shaders are compiled with `glslc` (from shaderc or the Vulkan SDK),
+//! found on the `PATH` or through the `GLSLC` environment variable. Add watertender as a build
+//! dependency with the `glsl-build` feature, then in `build.rs`:
+//!
+//! ```ignore
+//! fn main() -> anyhow::Result<()> {
+//!     watertender::glsl_build::compile_dir("shaders")?;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! And in the crate:
+//!
+//! ```ignore
+//! const PARTICLE_VERT: &[u8] = watertender::compile_glsl!("shaders/particle.vert");
+//! ```
+//!
+//! Build scripts are rerun when a shader or any file it `#include`s changes.
+use anyhow::{bail, Context, Result};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// File extensions compiled by `compile_dir()`, which `glslc` infers the shader stage from
+pub const SHADER_EXTENSIONS: &[&str] = &["vert", "frag", "comp", "geom", "tesc", "tese"];
+
+/// Compile the shader at `path`, relative to the crate root, to `OUT_DIR` with the same relative
+/// path and `.spv` appended. Returns the output path.
+pub fn compile(path: impl AsRef<Path>) -> Result<PathBuf> {
+    let path = path.as_ref();
+    let out_dir = std::env::var_os("OUT_DIR").context("OUT_DIR is not set; call from build.rs")?;
+    let mut out = Path::new(&out_dir).join(path).into_os_string();
+    out.push(".spv");
+    let out = PathBuf::from(out);
+    let depfile = out.with_extension("spv.d");
+    if let Some(parent) = out.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let glslc = std::env::var_os("GLSLC").unwrap_or_else(|| "glslc".into());
+    println!("cargo:rerun-if-env-changed=GLSLC");
+    let output = Command::new(&glslc)
+        .args(["-g", "-O", "-MD", "-MF"])
+        .arg(&depfile)
+        .arg(path)
+        .arg("-o")
+        .arg(&out)
+        .output()
+        .with_context(|| format!("Failed to run {:?}; is it installed?", glslc))?;
+    if !output.status.success() {
+        bail!(
+            "Failed to compile {}:\n{}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    // Depend on the shader and everything it includes
+    println!("cargo:rerun-if-changed={}", path.display());
+    let deps = std::fs::read_to_string(&depfile).unwrap_or_default();
+    if let Some((_, deps)) = deps.split_once(": ") {
+        for dep in deps.split_whitespace().filter(|dep| *dep != "\\") {
+            println!("cargo:rerun-if-changed={}", dep);
+        }
+    }
+
+    Ok(out)
+}
+
+/// Compile every shader in `dir` and its subdirectories with one of `SHADER_EXTENSIONS`
+pub fn compile_dir(dir: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+    let dir = dir.as_ref();
+    // Pick up new shaders too
+    println!("cargo:rerun-if-changed={}", dir.display());
+    let mut outputs = vec![];
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Reading {}", dir.display()))? {
+        let path = entry?.path();
+        if path.is_dir() {
+            outputs.extend(compile_dir(&path)?);
+        } else if path
+            .extension()
+            .and_then(OsStr::to_str)
+            .is_some_and(|ext| SHADER_EXTENSIONS.contains(&ext))
+        {
+            outputs.push(compile(&path)?);
+        }
+    }
+    Ok(outputs)
+}
@@ -1,8 +1,145 @@
-use crate::defaults::{COLOR_FORMAT, COLOR_SPACE};
-use anyhow::Result;
-use erupt::{extensions::khr_surface, vk1_0 as vk, InstanceLoader};
+use crate::defaults::{COLOR_FORMAT, COLOR_SPACE, DEPTH_FORMATS, UNORM_COLOR_FORMAT};
+use crate::memory::aspect_mask;
+use anyhow::{format_err, Result};
+use erupt::{extensions::khr_surface, vk1_0 as vk, vk1_1, InstanceLoader};
+use std::ffi::CString;
+use std::sync::Arc;
 use std::{ffi::CStr, os::raw::c_char};
 
+/// Scores a device which meets the other requirements of a `DevicePreference`. The device with
+/// the highest score is used; returning None rejects the device.
+pub type DeviceScorer = Arc<dyn Fn(&DeviceCandidate) -> Option<i64> + Send + Sync>;
+
+/// How the windowed and headless backends choose a physical device, see
+/// `AppInfo::device_preference()`. By default discrete GPUs are preferred over integrated ones.
+/// The OpenXR backend always uses the device the runtime asks for.
+#[derive(Clone, Default)]
+pub struct DevicePreference {
+    /// Prefer integrated GPUs over discrete ones, such as to save power
+    pub prefer_integrated: bool,
+    /// Device extensions which must be supported. These are also enabled on the device.
+    pub required_extensions: Vec<CString>,
+    /// Device features which must be supported. These are also enabled on the device.
+    pub required_features: vk::PhysicalDeviceFeatures,
+    /// Device features enabled where supported, without being required. Check
+    /// `Core::device_features` to see which were enabled.
+    pub optional_features: vk::PhysicalDeviceFeatures,
+    /// Minimum size of the largest device-local memory heap, in bytes
+    pub min_vram: u64,
+    /// Replaces the ranking by device type
+    pub score: Option<DeviceScorer>,
+}
+
+/// A physical device being considered by a `DevicePreference`
+pub struct DeviceCandidate {
+    pub physical_device: vk::PhysicalDevice,
+    pub properties: vk::PhysicalDeviceProperties,
+    pub memory_properties: vk::PhysicalDeviceMemoryProperties,
+    pub features: vk::PhysicalDeviceFeatures,
+}
+
+impl DeviceCandidate {
+    fn query(instance: &InstanceLoader, physical_device: vk::PhysicalDevice) -> Self {
+        unsafe {
+            Self {
+                physical_device,
+                properties: instance.get_physical_device_properties(physical_device, None),
+                memory_properties: instance
+                    .get_physical_device_memory_properties(physical_device, None),
+                features: instance.get_physical_device_features(physical_device, None),
+            }
+        }
+    }
+
+    /// Size of the largest device-local memory heap, in bytes
+    pub fn vram(&self) -> u64 {
+        let heaps = &self.memory_properties.memory_heaps
+            [..self.memory_properties.memory_heap_count as usize];
+        heaps
+            .iter()
+            .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|heap| heap.size)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Whether every feature enabled in `required` is supported
+    pub fn supports_features(&self, required: &vk::PhysicalDeviceFeatures) -> bool {
+        feature_flags(&self.features)
+            .iter()
+            .zip(feature_flags(required))
+            .all(|(&supported, &required)| supported == vk::TRUE || required == vk::FALSE)
+    }
+}
+
+const FEATURE_COUNT: usize =
+    std::mem::size_of::<vk::PhysicalDeviceFeatures>() / std::mem::size_of::<vk::Bool32>();
+
+/// The features as a list of flags; the struct consists only of `Bool32`s
+fn feature_flags(features: &vk::PhysicalDeviceFeatures) -> &[vk::Bool32] {
+    unsafe { std::slice::from_raw_parts(features as *const _ as *const vk::Bool32, FEATURE_COUNT) }
+}
+
+fn feature_flags_mut(features: &mut vk::PhysicalDeviceFeatures) -> &mut [vk::Bool32] {
+    unsafe { std::slice::from_raw_parts_mut(features as *mut _ as *mut vk::Bool32, FEATURE_COUNT) }
+}
+
+impl DevicePreference {
+    /// Score the device, or None if it doesn't meet the requirements. Extension support is
+    /// checked by the hardware queries.
+    pub fn score(
+        &self,
+        instance: &InstanceLoader,
+        physical_device: vk::PhysicalDevice,
+    ) -> Option<i64> {
+        let candidate = DeviceCandidate::query(instance, physical_device);
+        let supported = candidate.supports_features(&self.required_features);
+        if !supported || candidate.vram() < self.min_vram {
+            return None;
+        }
+
+        if let Some(score) = &self.score {
+            return score(&candidate);
+        }
+
+        let (discrete, integrated) = if self.prefer_integrated {
+            (1, 2)
+        } else {
+            (2, 1)
+        };
+        Some(match candidate.properties.device_type {
+            vk::PhysicalDeviceType::DISCRETE_GPU => discrete,
+            vk::PhysicalDeviceType::INTEGRATED_GPU => integrated,
+            _ => 0,
+        })
+    }
+
+    /// Features to enable on the device: the required ones, and the optional ones it supports
+    pub fn enabled_features(
+        &self,
+        instance: &InstanceLoader,
+        physical_device: vk::PhysicalDevice,
+    ) -> vk::PhysicalDeviceFeatures {
+        let supported = unsafe { instance.get_physical_device_features(physical_device, None) };
+        let mut enabled = self.required_features;
+        let flags = feature_flags_mut(&mut enabled)
+            .iter_mut()
+            .zip(feature_flags(&self.optional_features))
+            .zip(feature_flags(&supported));
+        for ((enabled, &optional), &supported) in flags {
+            if optional == vk::TRUE && supported == vk::TRUE {
+                *enabled = vk::TRUE;
+            }
+        }
+        enabled
+    }
+
+    /// Pointers to the required extension names, to append to a device's extension list
+    pub(crate) fn extension_ptrs(&self) -> impl Iterator<Item = *const c_char> + '_ {
+        self.required_extensions.iter().map(|ext| ext.as_ptr())
+    }
+}
+
 /// Hardware selection for Winit backend
 #[derive(Debug)]
 pub struct HardwareSelection {
@@ -14,11 +151,14 @@ pub struct HardwareSelection {
 }
 
 impl HardwareSelection {
-    /// Query for hardware with the right properties
+    /// Query for hardware with the right properties. `srgb_swapchain` picks the kind of surface
+    /// format, see `pick_surface_format()`.
     pub fn query(
         instance: &InstanceLoader,
         surface: khr_surface::SurfaceKHR,
         device_extensions: &[*const c_char],
+        preference: &DevicePreference,
+        srgb_swapchain: bool,
     ) -> Result<Self> {
         unsafe { instance.enumerate_physical_devices(None) }
             .unwrap()
@@ -46,27 +186,13 @@ impl HardwareSelection {
                 let formats = instance
                     .get_physical_device_surface_formats_khr(physical_device, surface, None)
                     .unwrap();
-                let format = match formats
-                    .iter()
-                    .find(|surface_format| {
-                        surface_format.format == COLOR_FORMAT
-                            && surface_format.color_space == COLOR_SPACE
-                    })
-                    .or_else(|| formats.get(0))
-                {
-                    Some(surface_format) => surface_format.clone(),
+                let format = match pick_surface_format(&formats, srgb_swapchain) {
+                    Some(surface_format) => surface_format,
                     None => return None,
                 };
 
+                // Replaced by the backends according to `AppInfo::vsync()`
                 let present_mode = khr_surface::PresentModeKHR::FIFO_KHR;
-                /* Use MAILBOX if available
-                let present_mode = instance
-                    .get_physical_device_surface_present_modes_khr(physical_device, surface, None)
-                    .unwrap()
-                    .into_iter()
-                    .find(|present_mode| present_mode == &khr_surface::PresentModeKHR::MAILBOX_KHR)
-                    .unwrap_or(khr_surface::PresentModeKHR::FIFO_KHR);
-                    */
 
                 let supported_extensions = instance
                     .enumerate_device_extension_properties(physical_device, None, None)
@@ -81,21 +207,181 @@ impl HardwareSelection {
                     return None;
                 }
 
+                let score = preference.score(instance, physical_device)?;
                 let physical_device_properties =
                     instance.get_physical_device_properties(physical_device, None);
-                Some(Self {
-                    physical_device,
-                    queue_family,
-                    format,
-                    present_mode,
-                    physical_device_properties,
-                })
-            })
-            .max_by_key(|query| match query.physical_device_properties.device_type {
-                vk::PhysicalDeviceType::DISCRETE_GPU => 2,
-                vk::PhysicalDeviceType::INTEGRATED_GPU => 1,
-                _ => 0,
+                Some((
+                    score,
+                    Self {
+                        physical_device,
+                        queue_family,
+                        format,
+                        present_mode,
+                        physical_device_properties,
+                    },
+                ))
             })
+            .max_by_key(|(score, _)| *score)
+            .map(|(_, query)| query)
             .ok_or_else(|| anyhow::format_err!("No suitable hardware found for this configuration"))
     }
 }
+
+/// Pick a queue family which supports compute but not graphics. Queues from such a family can
+/// run compute work alongside rendering on devices with dedicated compute hardware.
+pub fn pick_compute_queue_family(
+    instance: &InstanceLoader,
+    physical_device: vk::PhysicalDevice,
+) -> Option<u32> {
+    unsafe { instance.get_physical_device_queue_family_properties(physical_device, None) }
+        .iter()
+        .position(|properties| {
+            properties.queue_flags.contains(vk::QueueFlags::COMPUTE)
+                && !properties.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+        })
+        .map(|family| family as u32)
+}
+
+/// Pick a queue family which supports transfers but neither graphics nor compute. Such families
+/// are backed by DMA engines on many discrete GPUs, and copy data alongside rendering.
+pub fn pick_transfer_queue_family(
+    instance: &InstanceLoader,
+    physical_device: vk::PhysicalDevice,
+) -> Option<u32> {
+    unsafe { instance.get_physical_device_queue_family_properties(physical_device, None) }
+        .iter()
+        .position(|properties| {
+            properties.queue_flags.contains(vk::QueueFlags::TRANSFER)
+                && !properties
+                    .queue_flags
+                    .intersects(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+        })
+        .map(|family| family as u32)
+}
+
+/// One queue from the graphics family, and from the compute and transfer families if any
+pub(crate) fn queue_create_infos(
+    queue_family: u32,
+    compute_queue_family: Option<u32>,
+    transfer_queue_family: Option<u32>,
+) -> Vec<vk::DeviceQueueCreateInfoBuilder<'static>> {
+    std::iter::once(queue_family)
+        .chain(compute_queue_family)
+        .chain(transfer_queue_family)
+        .map(|family| {
+            vk::DeviceQueueCreateInfoBuilder::new()
+                .queue_family_index(family)
+                .queue_priorities(&[1.0])
+        })
+        .collect()
+}
+
+/// Pick the first of `DEPTH_FORMATS` usable as a depth attachment with optimal tiling
+pub fn pick_depth_format(
+    instance: &InstanceLoader,
+    physical_device: vk::PhysicalDevice,
+) -> Result<vk::Format> {
+    DEPTH_FORMATS
+        .iter()
+        .copied()
+        .find(|&format| depth_attachment_supported(instance, physical_device, format))
+        .ok_or_else(|| format_err!("The device supports none of {:?}", DEPTH_FORMATS))
+}
+
+/// Pick the first of `DEPTH_FORMATS` with a stencil aspect usable as a depth attachment with
+/// optimal tiling, such as for `StarterKit` settings of apps drawing `outline::Outline`s
+pub fn pick_depth_stencil_format(
+    instance: &InstanceLoader,
+    physical_device: vk::PhysicalDevice,
+) -> Result<vk::Format> {
+    DEPTH_FORMATS
+        .iter()
+        .copied()
+        .filter(|&format| aspect_mask(format).contains(vk::ImageAspectFlags::STENCIL))
+        .find(|&format| depth_attachment_supported(instance, physical_device, format))
+        .ok_or_else(|| format_err!("The device supports no depth format with a stencil aspect"))
+}
+
+fn depth_attachment_supported(
+    instance: &InstanceLoader,
+    physical_device: vk::PhysicalDevice,
+    format: vk::Format,
+) -> bool {
+    let properties =
+        unsafe { instance.get_physical_device_format_properties(physical_device, format, None) };
+    properties
+        .optimal_tiling_features
+        .contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+}
+
+/// Whether the device supports the `multiview` feature, which is queried through Vulkan 1.1
+pub fn multiview_supported(instance: &InstanceLoader, physical_device: vk::PhysicalDevice) -> bool {
+    if instance.get_physical_device_features2.is_none() {
+        return false;
+    }
+    let mut multiview_features = vk1_1::PhysicalDeviceMultiviewFeaturesBuilder::new().build();
+    let mut features = vk1_1::PhysicalDeviceFeatures2Builder::new().build();
+    features.p_next = &mut multiview_features as *mut _ as _;
+    unsafe { instance.get_physical_device_features2(physical_device, Some(features)) };
+    multiview_features.multiview == vk::TRUE
+}
+
+/// Pick a present mode: FIFO with vsync, otherwise MAILBOX, then IMMEDIATE, then FIFO (which
+/// every surface supports)
+pub fn pick_present_mode(
+    modes: &[khr_surface::PresentModeKHR],
+    vsync: bool,
+) -> khr_surface::PresentModeKHR {
+    const NO_VSYNC: [khr_surface::PresentModeKHR; 2] = [
+        khr_surface::PresentModeKHR::MAILBOX_KHR,
+        khr_surface::PresentModeKHR::IMMEDIATE_KHR,
+    ];
+
+    if vsync {
+        return khr_surface::PresentModeKHR::FIFO_KHR;
+    }
+    NO_VSYNC
+        .iter()
+        .copied()
+        .find(|mode| modes.contains(mode))
+        .unwrap_or(khr_surface::PresentModeKHR::FIFO_KHR)
+}
+
+/// Pick the surface format closest to `COLOR_FORMAT` and `COLOR_SPACE`, or to
+/// `UNORM_COLOR_FORMAT` unless `srgb` (see `AppInfo::srgb_swapchain()`): an exact match, then
+/// another 8-bit format of the same encoding in the same color space, then anything in the same
+/// color space, then whatever the surface lists first
+pub fn pick_surface_format(
+    formats: &[khr_surface::SurfaceFormatKHR],
+    srgb: bool,
+) -> Option<khr_surface::SurfaceFormatKHR> {
+    const SRGB_FORMATS: [vk::Format; 3] = [
+        COLOR_FORMAT,
+        vk::Format::B8G8R8A8_SRGB,
+        vk::Format::R8G8B8A8_SRGB,
+    ];
+    const UNORM_FORMATS: [vk::Format; 3] = [
+        UNORM_COLOR_FORMAT,
+        vk::Format::B8G8R8A8_UNORM,
+        vk::Format::R8G8B8A8_UNORM,
+    ];
+    let preferred = if srgb { SRGB_FORMATS } else { UNORM_FORMATS };
+
+    // A single undefined format means any format may be used
+    if let [only] = formats {
+        if only.format == vk::Format::UNDEFINED {
+            return Some(khr_surface::SurfaceFormatKHR {
+                format: preferred[0],
+                color_space: COLOR_SPACE,
+            });
+        }
+    }
+
+    let in_color_space = || formats.iter().filter(|f| f.color_space == COLOR_SPACE);
+    preferred
+        .iter()
+        .find_map(|&format| in_color_space().find(|f| f.format == format))
+        .or_else(|| in_color_space().next())
+        .or_else(|| formats.first())
+        .copied()
+}
@@ -1,10 +1,21 @@
 use crate::{
-    app_info::{engine_version, AppInfo},
+    app_info::AppInfo,
+    sync2, validation,
+    resources::ResourceTracker,
+    deletion_queue::DeletionQueue,
+    shader_cache::ShaderModuleCache,
+    hardware_query::{
+        multiview_supported, pick_compute_queue_family, pick_depth_format,
+        pick_transfer_queue_family, queue_create_infos, DevicePreference,
+    },
+    core::{ComputeQueue, QueueLocks},
     Core,
 };
-use anyhow::Result;
+use anyhow::{ensure, Result};
+#[cfg(unix)]
+use erupt::extensions::khr_external_memory_fd;
 use erupt::{
-    cstr,
+    extensions::{ext_headless_surface, khr_surface, khr_swapchain},
     vk, DeviceLoader, EntryLoader, InstanceLoader,
 };
 use gpu_alloc::GpuAllocator;
@@ -13,58 +24,161 @@ use std::sync::Mutex;
 use std::{ffi::CStr, os::raw::c_char};
 
 pub fn build_core(info: AppInfo) -> Result<Core> {
+    build_core_with_extensions(info, vec![], vec![])
+}
+
+/// Optional extensions of headless cores, for servers which hand rendered frames on to an
+/// encoder or another process rather than presenting them
+#[derive(Copy, Clone, Debug, Default)]
+pub struct HeadlessExtensions {
+    /// Enable `VK_EXT_headless_surface`, so that `create_headless_surface()` can stand in for a
+    /// window surface, such as to run swapchain code paths without a display
+    pub headless_surface: bool,
+    /// Enable exporting memory as file descriptors (`VK_KHR_external_memory_fd`), for images
+    /// created with `external_memory::ExportableImage`. Unix only.
+    pub external_memory: bool,
+}
+
+/// Build a core without a window surface, enabling the chosen extensions. Only hardware
+/// supporting them is considered.
+pub fn build_core_with(info: AppInfo, extensions: HeadlessExtensions) -> Result<Core> {
+    let mut instance_extensions = vec![];
+    let mut device_extensions = vec![];
+    if extensions.headless_surface {
+        instance_extensions.push(khr_surface::KHR_SURFACE_EXTENSION_NAME);
+        instance_extensions.push(ext_headless_surface::EXT_HEADLESS_SURFACE_EXTENSION_NAME);
+        device_extensions.push(khr_swapchain::KHR_SWAPCHAIN_EXTENSION_NAME);
+    }
+    if extensions.external_memory {
+        // External memory itself is core in Vulkan 1.1, only the handle types are extensions
+        ensure!(
+            info.api_version >= vk::make_version(1, 1, 0),
+            "External memory requires Vulkan 1.1"
+        );
+        #[cfg(unix)]
+        device_extensions.push(khr_external_memory_fd::KHR_EXTERNAL_MEMORY_FD_EXTENSION_NAME);
+        #[cfg(not(unix))]
+        anyhow::bail!("External memory export is only supported on Unix");
+    }
+    build_core_with_extensions(info, instance_extensions, device_extensions)
+}
+
+/// Create a surface not backed by any window or display, for a core built with
+/// `HeadlessExtensions::headless_surface`. Its extent is undefined, so swapchains pick their
+/// own as for a window. Destroy it with `destroy_surface_khr()` before the core is dropped.
+pub fn create_headless_surface(core: &Core) -> Result<vk::SurfaceKHR> {
+    let create_info = vk::HeadlessSurfaceCreateInfoEXTBuilder::new();
+    let surface = unsafe {
+        core.instance
+            .create_headless_surface_ext(&create_info, None, None)
+    }
+    .result()?;
+    Ok(surface)
+}
+
+/// Build a core without a window surface, enabling the given instance and device extensions.
+/// Only hardware supporting all of the device extensions is considered.
+pub fn build_core_with_extensions(
+    info: AppInfo,
+    mut instance_extensions: Vec<*const c_char>,
+    mut device_extensions: Vec<*const c_char>,
+) -> Result<Core> {
     // Entry
     let entry = EntryLoader::new()?;
 
     // Instance
-    let app_name = CString::new(info.name)?;
-    let engine_name = CString::new(crate::ENGINE_NAME)?;
+    let app_name = CString::new(info.name.as_str())?;
+    let engine_name = CString::new(info.engine_name.as_str())?;
     let app_info = vk::ApplicationInfoBuilder::new()
         .application_name(&app_name)
         .application_version(info.version)
         .engine_name(&engine_name)
-        .engine_version(engine_version())
+        .engine_version(info.engine_version)
         .api_version(info.api_version);
 
     // Instance and device layers and extensions
     let mut instance_layers = Vec::new();
-    let mut instance_extensions = vec![];
     let mut device_layers = Vec::new();
-    let device_extensions = vec![];
 
+    validation::instance_setup(&info, &mut instance_layers, &mut instance_extensions)?;
     if info.validation {
-        const LAYER_KHRONOS_VALIDATION: *const i8 = cstr!("VK_LAYER_KHRONOS_validation");
-        instance_extensions
-            .push(erupt::extensions::ext_debug_utils::EXT_DEBUG_UTILS_EXTENSION_NAME);
-        instance_layers.push(LAYER_KHRONOS_VALIDATION);
-        device_layers.push(LAYER_KHRONOS_VALIDATION);
+        device_layers.push(validation::LAYER_KHRONOS_VALIDATION);
     }
 
     // Instance creation
-    let create_info = vk::InstanceCreateInfoBuilder::new()
+    let validation_enables = validation::feature_enables(&info);
+    let mut validation_features = vk::ValidationFeaturesEXTBuilder::new()
+        .enabled_validation_features(&validation_enables)
+        .build();
+
+    let mut create_info = vk::InstanceCreateInfoBuilder::new()
         .application_info(&app_info)
         .enabled_extension_names(&instance_extensions)
         .enabled_layer_names(&instance_layers);
 
+    if !validation_enables.is_empty() {
+        create_info.p_next = &mut validation_features as *mut _ as _;
+    }
+
     let instance = InstanceLoader::new(&entry, &create_info, None)?;
+    validation::create_messenger(&info, &instance)?;
 
     // Hardware selection
-    let hardware = HeadlessHardwareSelection::query(&instance, &device_extensions)?;
+    device_extensions.extend(info.device_preference.extension_ptrs());
+    let hardware =
+        HeadlessHardwareSelection::query(&instance, &device_extensions, &info.device_preference)?;
+    validation::device_extensions(&info, &instance, hardware.physical_device, &mut device_extensions)?;
+    let synchronization2 =
+        sync2::device_extensions(&info, &instance, hardware.physical_device, &mut device_extensions)?;
 
     // Create logical device and queues
-    let create_info = [vk::DeviceQueueCreateInfoBuilder::new()
-        .queue_family_index(hardware.queue_family)
-        .queue_priorities(&[1.0])];
+    let compute_queue_family = info
+        .async_compute
+        .then(|| pick_compute_queue_family(&instance, hardware.physical_device))
+        .flatten();
+    let transfer_queue_family = info
+        .transfer_queue
+        .then(|| pick_transfer_queue_family(&instance, hardware.physical_device))
+        .flatten();
+    let create_info = queue_create_infos(
+        hardware.queue_family,
+        compute_queue_family,
+        transfer_queue_family,
+    );
 
-    let physical_device_features = vk::PhysicalDeviceFeaturesBuilder::new();
-    let create_info = vk::DeviceCreateInfoBuilder::new()
+    let device_features = info
+        .device_preference
+        .enabled_features(&instance, hardware.physical_device);
+    let mut create_info = vk::DeviceCreateInfoBuilder::new()
         .queue_create_infos(&create_info)
-        .enabled_features(&physical_device_features)
+        .enabled_features(&device_features)
         .enabled_extension_names(&device_extensions)
-        .enabled_layer_names(&device_layers);
+        .enabled_layer_names(&device_layers)
+        .build();
+
+    // Render passes use multiview where supported, if only with a single view outside of VR
+    let multiview = multiview_supported(&instance, hardware.physical_device);
+    let mut multiview_features = erupt::vk1_1::PhysicalDeviceMultiviewFeaturesBuilder::new()
+        .multiview(multiview)
+        .build();
+
+    let mut sync2_features = sync2::features();
+    if synchronization2 {
+        multiview_features.p_next = &mut sync2_features as *mut _ as _;
+    }
+
+    create_info.p_next = &mut multiview_features as *mut _ as _;
 
     let device = DeviceLoader::new(&instance, hardware.physical_device, &create_info, None)?;
     let queue = unsafe { device.get_device_queue(hardware.queue_family, 0, None) };
+    let compute_queue = compute_queue_family.map(|family| ComputeQueue {
+        queue: unsafe { device.get_device_queue(family, 0, None) },
+        family,
+    });
+    let transfer_queue = transfer_queue_family.map(|family| ComputeQueue {
+        queue: unsafe { device.get_device_queue(family, 0, None) },
+        family,
+    });
 
     let device_props =
         unsafe { gpu_alloc_erupt::device_properties(&instance, hardware.physical_device)? };
@@ -74,16 +188,26 @@ pub fn build_core(info: AppInfo) -> Result<Core> {
     ));
     let device_properties =
         unsafe { instance.get_physical_device_properties(hardware.physical_device, None) };
+    let depth_format = pick_depth_format(&instance, hardware.physical_device)?;
 
     Ok(Core {
         physical_device: hardware.physical_device,
         device_properties,
+        device_features,
+        depth_format,
+        multiview,
         queue_family: hardware.queue_family,
         queue,
+        compute_queue,
+        transfer_queue,
+        queue_locks: QueueLocks::default(),
         device,
         instance,
         allocator,
         entry,
+        resources: ResourceTracker::new(info.leak_warnings),
+        deletion_queue: DeletionQueue::new(),
+        shader_modules: ShaderModuleCache::new(),
     })
 }
 
@@ -97,6 +221,7 @@ impl HeadlessHardwareSelection {
     pub fn query(
         instance: &InstanceLoader,
         device_extensions: &[*const c_char],
+        preference: &DevicePreference,
     ) -> Result<Self> {
         unsafe { instance.enumerate_physical_devices(None) }
         .unwrap()
@@ -124,19 +249,17 @@ impl HeadlessHardwareSelection {
                     return None;
                 }
 
+                let score = preference.score(instance, physical_device)?;
                 let physical_device_properties =
                     instance.get_physical_device_properties(physical_device, None);
-                Some(Self {
+                Some((score, Self {
                     physical_device,
                     queue_family,
                     physical_device_properties,
-                })
+                }))
             })
-        .max_by_key(|query| match query.physical_device_properties.device_type {
-            vk::PhysicalDeviceType::DISCRETE_GPU => 2,
-            vk::PhysicalDeviceType::INTEGRATED_GPU => 1,
-            _ => 0,
-        })
+        .max_by_key(|(score, _)| *score)
+        .map(|(_, query)| query)
         .ok_or_else(|| anyhow::format_err!("No suitable hardware found for this configuration"))
     }
 }
@@ -0,0 +1,449 @@
+//! Starts on the desktop in a window, and switches into OpenXR (and back) at runtime on
+//! `request_switch()`, keeping the `Core` and everything built on it: meshes, textures and
+//! pipelines. The core is built on the device required by the OpenXR runtime, so the runtime
+//! must be available at startup. Use with `starter_kit::Settings::platform_switching`.
+use crate::hardware_query::{pick_present_mode, pick_surface_format};
+use crate::openxr_backend::{self, SharedXrCore};
+use crate::winit_backend::{acquire, res, window_extent, FramePacing, SurfaceSettings, Swapchain};
+use crate::{
+    app_info::AppInfo,
+    mainloop::{Frame, MainLoop, Platform, PlatformEvent, PlatformReturn, SyncMainLoop},
+    profiling::{self, FrameEvent},
+    Core, SharedCore,
+};
+use anyhow::{bail, format_err, Context, Result};
+use erupt::{extensions::khr_swapchain, utils::surface};
+use openxr as xr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use winit::{
+    event::{Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    window::{Window, WindowBuilder},
+};
+
+static SWITCH_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Switch between the desktop and VR after the current frame. Has no effect in other backends.
+pub fn request_switch() {
+    SWITCH_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+pub fn launch<M: SyncMainLoop<D> + 'static, D>(info: AppInfo, data: D) -> Result<()> {
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_title(&info.name)
+        .build(&event_loop)
+        .context("Failed to create window")?;
+
+    let image_count = info.swapchain_images;
+    let vsync = info.vsync;
+    let srgb_swapchain = info.srgb_swapchain;
+    let acquire_timeout = info.acquire_timeout;
+    let pacing = FramePacing::new(&info);
+    let instance_extensions = surface::enumerate_required_extensions(&window).result()?;
+    let (core, xr_instance, system) = openxr_backend::build_core(
+        info,
+        instance_extensions,
+        vec![khr_swapchain::KHR_SWAPCHAIN_EXTENSION_NAME],
+    )?;
+    let surface = create_surface(
+        &core,
+        &window,
+        image_count,
+        vsync,
+        srgb_swapchain,
+        acquire_timeout,
+    )?;
+
+    begin_loop::<M, D>(
+        core,
+        xr_instance,
+        system,
+        event_loop,
+        window,
+        surface,
+        pacing,
+        data,
+    )
+}
+
+/// Create a window surface on the OpenXR runtime's device
+fn create_surface(
+    core: &Core,
+    window: &Window,
+    image_count: Option<u32>,
+    vsync: bool,
+    srgb_swapchain: bool,
+    acquire_timeout: Option<Duration>,
+) -> Result<SurfaceSettings> {
+    let surface = unsafe { surface::create_surface(&core.instance, window, None) }.result()?;
+
+    let supported = unsafe {
+        core.instance.get_physical_device_surface_support_khr(
+            core.physical_device,
+            core.queue_family,
+            surface,
+            None,
+        )
+    }
+    .result()?;
+    if !supported {
+        unsafe { core.instance.destroy_surface_khr(Some(surface), None) };
+        bail!("The GPU used by the OpenXR runtime cannot present to this window");
+    }
+
+    let formats = unsafe {
+        core.instance
+            .get_physical_device_surface_formats_khr(core.physical_device, surface, None)
+    }
+    .result()?;
+    let format = pick_surface_format(&formats, srgb_swapchain)
+        .ok_or_else(|| format_err!("Surface reports no formats"))?;
+
+    let present_modes = unsafe {
+        core.instance.get_physical_device_surface_present_modes_khr(
+            core.physical_device,
+            surface,
+            None,
+        )
+    }
+    .result()?;
+
+    Ok(SurfaceSettings {
+        surface,
+        present_mode: pick_present_mode(&present_modes, vsync),
+        format,
+        image_count,
+        acquire_timeout,
+    })
+}
+
+/// Session and swapchain while in VR
+struct VrState {
+    xr_core: SharedXrCore,
+    swapchain: openxr_backend::Swapchain,
+    frame_waiter: xr::FrameWaiter,
+    session_running: bool,
+    /// Exit the app, rather than returning to the desktop, once the session has exited
+    closing: bool,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn begin_loop<M: SyncMainLoop<D> + 'static, D>(
+    core: SharedCore,
+    xr_instance: xr::Instance,
+    system: xr::SystemId,
+    event_loop: EventLoop<()>,
+    window: Window,
+    surface: SurfaceSettings,
+    mut pacing: FramePacing,
+    data: D,
+) -> Result<()> {
+    let color_format = surface.format.format;
+
+    let mut app = M::new(
+        &core,
+        Platform::Winit {
+            window: &window,
+            control_flow: &mut Default::default(),
+            color_format,
+        },
+        data,
+    )?;
+
+    let (mut swapchain, (images, extent)) = res(Swapchain::new(
+        core.clone(),
+        surface,
+        window_extent(window.inner_size()),
+    ));
+    res(app.swapchain_resize(images, extent));
+
+    let mut vr: Option<VrState> = None;
+    let mut event_storage = xr::EventDataBuffer::new();
+    event_loop.run(move |event, _, control_flow| {
+        pacing.event(&event);
+
+        // Window events are delivered in both modes
+        match &vr {
+            None => res(app.event(
+                PlatformEvent::Winit(&event),
+                &core,
+                Platform::Winit {
+                    window: &window,
+                    control_flow,
+                    color_format,
+                },
+            )),
+            Some(state) => res(app.event(
+                PlatformEvent::Winit(&event),
+                &core,
+                Platform::OpenXr {
+                    xr_core: &state.xr_core,
+                    frame_state: None,
+                },
+            )),
+        }
+
+        // Whether the session has exited, and we should return to the desktop
+        let mut leave_vr = false;
+
+        match event {
+            Event::WindowEvent {
+                event: WindowEvent::Resized(_) | WindowEvent::ScaleFactorChanged { .. },
+                ..
+            } if vr.is_none() => {
+                let resize = res(swapchain.resize(window_extent(window.inner_size())));
+                if let Some((images, extent)) = resize {
+                    res(app.swapchain_resize(images, extent));
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            } => {
+                // The app can only exit from the desktop, so leave VR first
+                if let Some(state) = &mut vr {
+                    state.closing = true;
+                    leave_vr = res(request_exit(state));
+                }
+            }
+            Event::MainEventsCleared => {
+                if SWITCH_REQUESTED.swap(false, Ordering::Relaxed) {
+                    match &mut vr {
+                        None => match enter_vr(&core, &xr_instance, system) {
+                            Ok(state) => {
+                                let state = vr.insert(state);
+                                res(app.event(
+                                    PlatformEvent::PlatformChanged,
+                                    &core,
+                                    Platform::OpenXr {
+                                        xr_core: &state.xr_core,
+                                        frame_state: None,
+                                    },
+                                ));
+                            }
+                            Err(e) => eprintln!("Failed to enter VR: {}", e),
+                        },
+                        Some(state) => leave_vr = res(request_exit(state)),
+                    }
+                }
+
+                match &mut vr {
+                    None => pacing.events_cleared(&window, control_flow),
+                    Some(state) if !leave_vr => {
+                        // VR frames are driven by this event, so don't wait for events
+                        if *control_flow != ControlFlow::Exit {
+                            *control_flow = ControlFlow::Poll;
+                        }
+                        leave_vr = res(vr_frame(&mut app, &core, state, &mut event_storage))
+                    }
+                    Some(_) => (),
+                }
+            }
+            Event::RedrawRequested(_) if vr.is_none() && !swapchain.is_minimized() => {
+                // Prepare inputs
+                let (image_available, render_finished) = app.winit_sync();
+                let acquired = profiling::timed(FrameEvent::Acquire, || {
+                    acquire(
+                        &mut swapchain,
+                        &mut app,
+                        &core,
+                        &window,
+                        control_flow,
+                        image_available,
+                    )
+                });
+                let swapchain_index = match acquired {
+                    Some(swapchain_index) => swapchain_index,
+                    None => return,
+                };
+                let frame = Frame { swapchain_index };
+
+                // Run app's frame method
+                #[cfg(feature = "renderdoc")]
+                crate::capture::begin_frame();
+                res(app.frame(
+                    frame,
+                    &core,
+                    Platform::Winit {
+                        window: &window,
+                        control_flow,
+                        color_format,
+                    },
+                ));
+
+                // Present
+                let out_of_date = res(profiling::timed(FrameEvent::Present, || {
+                    swapchain.queue_present(swapchain_index, render_finished)
+                }));
+                #[cfg(feature = "renderdoc")]
+                crate::capture::end_frame();
+                res(core.collect_garbage());
+                pacing.frame_drawn();
+                if out_of_date {
+                    let resize = res(swapchain.resize(window_extent(window.inner_size())));
+                    if let Some((images, extent)) = resize {
+                        res(app.swapchain_resize(images, extent));
+                    }
+                }
+            }
+            _ => (),
+        }
+
+        if leave_vr {
+            // Tear down the session, keeping the app and Vulkan device
+            res(unsafe { core.device.device_wait_idle() }
+                .result()
+                .map_err(Into::into));
+            let closing = vr.take().is_some_and(|state| state.closing);
+            if closing {
+                *control_flow = ControlFlow::Exit;
+                return;
+            }
+
+            res(app.event(
+                PlatformEvent::PlatformChanged,
+                &core,
+                Platform::Winit {
+                    window: &window,
+                    control_flow,
+                    color_format,
+                },
+            ));
+            let resize = res(swapchain.resize(window_extent(window.inner_size())));
+            if let Some((images, extent)) = resize {
+                res(app.swapchain_resize(images, extent));
+            }
+        }
+    });
+}
+
+/// Create a session on the existing device. The swapchain is created on the first frame.
+fn enter_vr(core: &Core, xr_instance: &xr::Instance, system: xr::SystemId) -> Result<VrState> {
+    unsafe { core.device.device_wait_idle() }.result()?;
+    let (xr_core, frame_stream, frame_waiter) =
+        openxr_backend::create_session(xr_instance.clone(), system, core)?;
+    let swapchain = openxr_backend::Swapchain::new(xr_core.clone(), frame_stream)?;
+    Ok(VrState {
+        xr_core,
+        swapchain,
+        frame_waiter,
+        session_running: false,
+        closing: false,
+    })
+}
+
+/// Ask the session to exit. Returns true if it wasn't running, and may be torn down right away.
+fn request_exit(state: &VrState) -> Result<bool> {
+    match state.xr_core.session.request_exit() {
+        Err(xr::sys::Result::ERROR_SESSION_NOT_RUNNING) => Ok(true),
+        res => res.map(|_| false).map_err(Into::into),
+    }
+}
+
+/// Handle OpenXR events, and run a frame if the session is running. Returns true once the
+/// session has exited (or was lost).
+fn vr_frame<M: MainLoop<D>, D>(
+    app: &mut M,
+    core: &SharedCore,
+    state: &mut VrState,
+    event_storage: &mut xr::EventDataBuffer,
+) -> Result<bool> {
+    while let Some(event) = state.xr_core.instance.poll_event(event_storage)? {
+        use xr::Event::*;
+        let mut exited = false;
+        match event {
+            SessionStateChanged(e) => {
+                println!("OpenXR entered state {:?}", e.state());
+                match e.state() {
+                    xr::SessionState::READY => {
+                        state
+                            .xr_core
+                            .session
+                            .begin(xr::ViewConfigurationType::PRIMARY_STEREO)?;
+                        state.session_running = true;
+                    }
+                    xr::SessionState::STOPPING => {
+                        state.xr_core.session.end()?;
+                        state.session_running = false;
+                    }
+                    xr::SessionState::EXITING | xr::SessionState::LOSS_PENDING => {
+                        println!("OpenXR Returning to the desktop");
+                        exited = true;
+                    }
+                    _ => {}
+                }
+            }
+            InstanceLossPending(_) => {
+                println!("OpenXR Pending instance loss, returning to the desktop");
+                exited = true;
+            }
+            ReferenceSpaceChangePending(e) => {
+                state.xr_core.reference_space_changed(e)?;
+            }
+            EventsLost(e) => {
+                println!("OpenXR lost {} events", e.lost_event_count());
+            }
+            _ => {}
+        }
+        app.event(
+            PlatformEvent::OpenXr(&event),
+            core,
+            Platform::OpenXr {
+                xr_core: &state.xr_core,
+                frame_state: None,
+            },
+        )?;
+        if exited {
+            return Ok(true);
+        }
+    }
+
+    if !state.session_running {
+        // Don't grind up the CPU
+        std::thread::sleep(Duration::from_millis(100));
+        return Ok(false);
+    }
+
+    // Get next frame
+    let xr_frame_state = profiling::timed(FrameEvent::Acquire, || state.frame_waiter.wait())?;
+    let (swapchain_index, resize) = state.swapchain.frame(xr_frame_state)?;
+    let swapchain_index = match swapchain_index {
+        Some(i) => i,
+        None => return Ok(false), // Don't draw
+    };
+
+    // Resize swapchain if necessary
+    if let Some((images, extent)) = resize {
+        app.swapchain_resize(images, extent)?;
+    }
+
+    // Run the app
+    #[cfg(feature = "renderdoc")]
+    crate::capture::begin_frame();
+    let ret = app.frame(
+        Frame { swapchain_index },
+        core,
+        Platform::OpenXr {
+            xr_core: &state.xr_core,
+            frame_state: Some(xr_frame_state),
+        },
+    )?;
+    let views = match ret {
+        PlatformReturn::OpenXr(v) => v,
+        #[allow(unused)]
+        _ => bail!("Wrong platform return"),
+    };
+
+    // Present the image. The runtime may use the queue while releasing and ending the frame.
+    profiling::timed(FrameEvent::Present, || {
+        let _queue = core.lock_queue(core.queue);
+        state.swapchain.queue_present(xr_frame_state, views)
+    })?;
+    #[cfg(feature = "renderdoc")]
+    crate::capture::end_frame();
+    core.collect_garbage()?;
+
+    Ok(false)
+}
@@ -0,0 +1,636 @@
+//! Image based lighting from an HDR environment map. An equirectangular environment is
+//! prefiltered once, by compute passes, into a diffuse irradiance cubemap and a specular cubemap
+//! whose mip levels hold increasing roughness, along with the BRDF lookup table of the split
+//! sum approximation. The results are bound as one descriptor set:
+//!
+//! | Binding | Type          | Contents                                                   |
+//! |---------|---------------|------------------------------------------------------------|
+//! | 0       | `samplerCube` | Irradiance                                                 |
+//! | 1       | `samplerCube` | Prefiltered specular, sample at lod `roughness * max_lod`  |
+//! | 2       | `sampler2D`   | BRDF LUT at (n.v, roughness): scale (r) and bias (g) of F0 |
+//!
+//! ```ignore
+//! let ibl = Ibl::from_equirect(
+//!     core.clone(),
+//!     &mut starter_kit.staging_buffer,
+//!     starter_kit.current_command_buffer(),
+//!     width,
+//!     height,
+//!     &rgba,
+//!     IblSettings::default(),
+//! )?;
+//! // Include ibl.descriptor_set_layout() in the pipeline layout, and bind
+//! // ibl.descriptor_set() when drawing
+//! ```
+use crate::compute::compute_pipeline;
+use crate::memory::ManagedImage;
+use crate::staging_buffer::StagingBuffer;
+use crate::sync2::Stages;
+use crate::synchronization::SharedFence;
+use crate::SharedCore;
+use anyhow::{ensure, Result};
+use erupt::vk;
+use gpu_alloc::UsageFlags;
+
+const IRRADIANCE_COMP: &[u8] = include_bytes!("../shaders/ibl_irradiance.comp.spv");
+const SPECULAR_COMP: &[u8] = include_bytes!("../shaders/ibl_specular.comp.spv");
+const BRDF_COMP: &[u8] = include_bytes!("../shaders/ibl_brdf.comp.spv");
+
+/// Format of the prefiltered cubemaps and the BRDF LUT
+pub const IBL_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+/// Sizes and sample counts of the prefiltered maps
+#[derive(Copy, Clone, Debug)]
+pub struct IblSettings {
+    /// Width of each face of the irradiance cubemap
+    pub irradiance_size: u32,
+    /// Width of each face of the specular cubemap's first mip level, which is mirror-like
+    pub specular_size: u32,
+    /// Mip levels of the specular cubemap; the last is fully rough
+    pub specular_mips: u32,
+    /// Width and height of the BRDF LUT
+    pub brdf_size: u32,
+    /// Environment samples per texel. More reduce noise from bright spots in the environment.
+    pub samples: u32,
+}
+
+impl Default for IblSettings {
+    fn default() -> Self {
+        Self {
+            irradiance_size: 32,
+            specular_size: 128,
+            specular_mips: 5,
+            brdf_size: 256,
+            samples: 512,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct PushConstants {
+    size: u32,
+    samples: u32,
+    roughness: f32,
+}
+
+unsafe impl bytemuck::Zeroable for PushConstants {}
+unsafe impl bytemuck::Pod for PushConstants {}
+
+/// Prefiltered environment lighting, and the descriptor set binding it
+pub struct Ibl {
+    irradiance: (ManagedImage, vk::ImageView),
+    specular: (ManagedImage, vk::ImageView),
+    brdf: (ManagedImage, vk::ImageView),
+    specular_mips: u32,
+    sampler: vk::Sampler,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_set: vk::DescriptorSet,
+    core: SharedCore,
+}
+
+impl Ibl {
+    /// Upload an equirectangular environment map of `width` by `height` RGBA texels in linear
+    /// HDR, and prefilter it with `from_view()`
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_equirect(
+        core: SharedCore,
+        staging_buffer: &mut StagingBuffer,
+        command_buffer: vk::CommandBuffer,
+        width: u32,
+        height: u32,
+        rgba: &[f32],
+        settings: IblSettings,
+    ) -> Result<Self> {
+        ensure!(
+            rgba.len() == (width * height * 4) as usize,
+            "Expected {}x{} RGBA texels",
+            width,
+            height
+        );
+
+        let (image, subresource_range, _) = staging_buffer.upload_image(
+            command_buffer,
+            width,
+            height,
+            bytemuck::cast_slice(rgba),
+            vk::Format::R32G32B32A32_SFLOAT,
+            vk::ImageUsageFlags::SAMPLED,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        )?;
+        staging_buffer.wait_pending()?;
+
+        let create_info = vk::ImageViewCreateInfoBuilder::new()
+            .image(image.instance())
+            .view_type(vk::ImageViewType::_2D)
+            .format(vk::Format::R32G32B32A32_SFLOAT)
+            .subresource_range(subresource_range.build());
+        let view = unsafe { core.device.create_image_view(&create_info, None, None) }.result()?;
+
+        let ibl = Self::from_view(core.clone(), command_buffer, view, settings);
+        unsafe {
+            core.device.destroy_image_view(Some(view), None);
+        }
+        ibl
+    }
+
+    /// Prefilter the equirectangular environment map `environment`, a 2D view in
+    /// `SHADER_READ_ONLY_OPTIMAL`. The passes are recorded into `command_buffer` (which must not
+    /// be in use) and waited on, so this is meant for loading time.
+    pub fn from_view(
+        core: SharedCore,
+        command_buffer: vk::CommandBuffer,
+        environment: vk::ImageView,
+        settings: IblSettings,
+    ) -> Result<Self> {
+        ensure!(
+            settings.specular_mips > 0
+                && settings.specular_size >> (settings.specular_mips - 1) > 0,
+            "Too many specular mip levels for a {} texel cubemap",
+            settings.specular_size
+        );
+
+        let mut irradiance = create_image(&core, settings.irradiance_size, 1, 6)?;
+        let mut specular = create_image(&core, settings.specular_size, settings.specular_mips, 6)?;
+        let mut brdf = create_image(&core, settings.brdf_size, 1, 1)?;
+
+        let sampler = create_sampler(&core, settings.specular_mips)?;
+        let prefilter = Prefilter::new(&core, settings.specular_mips + 2)?;
+
+        // One pass per output: irradiance, each specular mip, then the BRDF LUT
+        let mut storage_views = vec![];
+        let mut passes = vec![];
+        let result = (|| {
+            let view = create_view(&core, &irradiance, vk::ImageViewType::_2D_ARRAY, 0, 1, 6)?;
+            storage_views.push(view);
+            passes.push((prefilter.irradiance, view, settings.irradiance_size, 0.0));
+
+            for mip in 0..settings.specular_mips {
+                let view = create_view(&core, &specular, vk::ImageViewType::_2D_ARRAY, mip, 1, 6)?;
+                storage_views.push(view);
+                let roughness = mip as f32 / (settings.specular_mips - 1).max(1) as f32;
+                passes.push((
+                    prefilter.specular,
+                    view,
+                    settings.specular_size >> mip,
+                    roughness,
+                ));
+            }
+
+            let view = create_view(&core, &brdf, vk::ImageViewType::_2D_ARRAY, 0, 1, 1)?;
+            storage_views.push(view);
+            passes.push((prefilter.brdf, view, settings.brdf_size, 0.0));
+
+            prefilter.run(
+                &core,
+                command_buffer,
+                environment,
+                &passes,
+                settings.samples,
+                [&mut irradiance, &mut specular, &mut brdf],
+            )
+        })();
+
+        unsafe {
+            for view in storage_views {
+                core.device.destroy_image_view(Some(view), None);
+            }
+        }
+        prefilter.destroy(&core);
+        if let Err(e) = result {
+            unsafe {
+                core.device.destroy_sampler(Some(sampler), None);
+            }
+            return Err(e);
+        }
+
+        let irradiance_view = create_view(&core, &irradiance, vk::ImageViewType::CUBE, 0, 1, 6)?;
+        let specular_view = create_view(
+            &core,
+            &specular,
+            vk::ImageViewType::CUBE,
+            0,
+            settings.specular_mips,
+            6,
+        )?;
+        let brdf_view = create_view(&core, &brdf, vk::ImageViewType::_2D, 0, 1, 1)?;
+
+        // Descriptor set for rendering
+        let bindings: Vec<_> = (0..3)
+            .map(|binding| {
+                vk::DescriptorSetLayoutBindingBuilder::new()
+                    .binding(binding)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .descriptor_count(1)
+                    .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            })
+            .collect();
+        let create_info = vk::DescriptorSetLayoutCreateInfoBuilder::new().bindings(&bindings);
+        let descriptor_set_layout = unsafe {
+            core.device
+                .create_descriptor_set_layout(&create_info, None, None)
+        }
+        .result()?;
+
+        let pool_sizes = [vk::DescriptorPoolSizeBuilder::new()
+            ._type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(3)];
+        let create_info = vk::DescriptorPoolCreateInfoBuilder::new()
+            .pool_sizes(&pool_sizes)
+            .max_sets(1);
+        let descriptor_pool =
+            unsafe { core.device.create_descriptor_pool(&create_info, None, None) }.result()?;
+
+        let layouts = [descriptor_set_layout];
+        let create_info = vk::DescriptorSetAllocateInfoBuilder::new()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&layouts);
+        let descriptor_set = core.allocate_descriptor_sets(&create_info)?[0];
+
+        let image_infos: Vec<_> = [irradiance_view, specular_view, brdf_view]
+            .iter()
+            .map(|&view| {
+                [vk::DescriptorImageInfoBuilder::new()
+                    .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                    .image_view(view)
+                    .sampler(sampler)]
+            })
+            .collect();
+        let writes: Vec<_> = image_infos
+            .iter()
+            .enumerate()
+            .map(|(binding, info)| {
+                vk::WriteDescriptorSetBuilder::new()
+                    .image_info(info)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .dst_set(descriptor_set)
+                    .dst_binding(binding as u32)
+                    .dst_array_element(0)
+            })
+            .collect();
+        unsafe {
+            core.device.update_descriptor_sets(&writes, &[]);
+        }
+
+        Ok(Self {
+            irradiance: (irradiance, irradiance_view),
+            specular: (specular, specular_view),
+            brdf: (brdf, brdf_view),
+            specular_mips: settings.specular_mips,
+            sampler,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            core,
+        })
+    }
+
+    /// Layout of `descriptor_set()`, for building pipeline layouts
+    pub fn descriptor_set_layout(&self) -> vk::DescriptorSetLayout {
+        self.descriptor_set_layout
+    }
+
+    /// The irradiance, specular and BRDF maps, at bindings 0, 1 and 2
+    pub fn descriptor_set(&self) -> vk::DescriptorSet {
+        self.descriptor_set
+    }
+
+    /// Lod of the fully rough level of the specular cubemap
+    pub fn max_lod(&self) -> f32 {
+        (self.specular_mips - 1) as f32
+    }
+
+    /// Cube view of the irradiance map
+    pub fn irradiance_view(&self) -> vk::ImageView {
+        self.irradiance.1
+    }
+
+    /// Cube view of all mip levels of the specular map
+    pub fn specular_view(&self) -> vk::ImageView {
+        self.specular.1
+    }
+
+    pub fn brdf_view(&self) -> vk::ImageView {
+        self.brdf.1
+    }
+
+    /// Trilinear clamp-to-edge sampler covering the specular mip levels
+    pub fn sampler(&self) -> vk::Sampler {
+        self.sampler
+    }
+}
+
+impl Drop for Ibl {
+    fn drop(&mut self) {
+        unsafe {
+            self.core.device.device_wait_idle().unwrap();
+            self.core.destroy_descriptor_pool(self.descriptor_pool);
+            self.core
+                .device
+                .destroy_descriptor_set_layout(Some(self.descriptor_set_layout), None);
+            for view in [self.irradiance.1, self.specular.1, self.brdf.1] {
+                self.core.device.destroy_image_view(Some(view), None);
+            }
+            self.core.device.destroy_sampler(Some(self.sampler), None);
+        }
+    }
+}
+
+/// The compute pipelines and transient descriptor sets of the prefiltering passes
+struct Prefilter {
+    irradiance: vk::Pipeline,
+    specular: vk::Pipeline,
+    brdf: vk::Pipeline,
+    pipeline_layout: vk::PipelineLayout,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    /// Samples the environment, which may be in a format without linear filtering
+    sampler: vk::Sampler,
+}
+
+impl Prefilter {
+    fn new(core: &SharedCore, n_passes: u32) -> Result<Self> {
+        let create_info = vk::SamplerCreateInfoBuilder::new()
+            .mag_filter(vk::Filter::NEAREST)
+            .min_filter(vk::Filter::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::REPEAT)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .anisotropy_enable(false)
+            .max_anisotropy(1.)
+            .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
+            .unnormalized_coordinates(false)
+            .compare_enable(false)
+            .compare_op(vk::CompareOp::ALWAYS)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .mip_lod_bias(0.)
+            .min_lod(0.)
+            .max_lod(0.);
+        let sampler = unsafe { core.device.create_sampler(&create_info, None, None) }.result()?;
+
+        // Sampler, environment and output
+        let bindings = [
+            (0, vk::DescriptorType::SAMPLER),
+            (1, vk::DescriptorType::SAMPLED_IMAGE),
+            (2, vk::DescriptorType::STORAGE_IMAGE),
+        ]
+        .map(|(binding, descriptor_type)| {
+            vk::DescriptorSetLayoutBindingBuilder::new()
+                .binding(binding)
+                .descriptor_type(descriptor_type)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        });
+        let create_info = vk::DescriptorSetLayoutCreateInfoBuilder::new().bindings(&bindings);
+        let descriptor_set_layout = unsafe {
+            core.device
+                .create_descriptor_set_layout(&create_info, None, None)
+        }
+        .result()?;
+
+        let pool_sizes = [
+            vk::DescriptorType::SAMPLER,
+            vk::DescriptorType::SAMPLED_IMAGE,
+            vk::DescriptorType::STORAGE_IMAGE,
+        ]
+        .map(|descriptor_type| {
+            vk::DescriptorPoolSizeBuilder::new()
+                ._type(descriptor_type)
+                .descriptor_count(n_passes)
+        });
+        let create_info = vk::DescriptorPoolCreateInfoBuilder::new()
+            .pool_sizes(&pool_sizes)
+            .max_sets(n_passes);
+        let descriptor_pool =
+            unsafe { core.device.create_descriptor_pool(&create_info, None, None) }.result()?;
+
+        let layouts = vec![descriptor_set_layout; n_passes as usize];
+        let create_info = vk::DescriptorSetAllocateInfoBuilder::new()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&layouts);
+        let descriptor_sets = core.allocate_descriptor_sets(&create_info)?;
+
+        let push_constant_ranges = [vk::PushConstantRangeBuilder::new()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .offset(0)
+            .size(std::mem::size_of::<PushConstants>() as u32)];
+        let descriptor_set_layouts = [descriptor_set_layout];
+        let create_info = vk::PipelineLayoutCreateInfoBuilder::new()
+            .set_layouts(&descriptor_set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let pipeline_layout =
+            unsafe { core.device.create_pipeline_layout(&create_info, None, None) }.result()?;
+
+        Ok(Self {
+            irradiance: compute_pipeline(core, IRRADIANCE_COMP, pipeline_layout)?,
+            specular: compute_pipeline(core, SPECULAR_COMP, pipeline_layout)?,
+            brdf: compute_pipeline(core, BRDF_COMP, pipeline_layout)?,
+            pipeline_layout,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_sets,
+            sampler,
+        })
+    }
+
+    /// Record and run `passes`, each a pipeline and the storage view it writes with its size and
+    /// roughness. Waits for them to complete, leaving `images` readable by fragment shaders.
+    fn run(
+        &self,
+        core: &SharedCore,
+        command_buffer: vk::CommandBuffer,
+        environment: vk::ImageView,
+        passes: &[(vk::Pipeline, vk::ImageView, u32, f32)],
+        samples: u32,
+        images: [&mut ManagedImage; 3],
+    ) -> Result<()> {
+        for (&descriptor_set, &(_, output, _, _)) in self.descriptor_sets.iter().zip(passes) {
+            let sampler_infos = [vk::DescriptorImageInfoBuilder::new().sampler(self.sampler)];
+            let environment_infos = [vk::DescriptorImageInfoBuilder::new()
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .image_view(environment)];
+            let output_infos = [vk::DescriptorImageInfoBuilder::new()
+                .image_layout(vk::ImageLayout::GENERAL)
+                .image_view(output)];
+            let writes = [
+                vk::WriteDescriptorSetBuilder::new()
+                    .image_info(&sampler_infos)
+                    .descriptor_type(vk::DescriptorType::SAMPLER)
+                    .dst_set(descriptor_set)
+                    .dst_binding(0),
+                vk::WriteDescriptorSetBuilder::new()
+                    .image_info(&environment_infos)
+                    .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                    .dst_set(descriptor_set)
+                    .dst_binding(1),
+                vk::WriteDescriptorSetBuilder::new()
+                    .image_info(&output_infos)
+                    .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                    .dst_set(descriptor_set)
+                    .dst_binding(2),
+            ];
+            unsafe {
+                core.device.update_descriptor_sets(&writes, &[]);
+            }
+        }
+
+        unsafe {
+            core.device
+                .reset_command_buffer(command_buffer, None)
+                .result()?;
+            let begin_info = vk::CommandBufferBeginInfoBuilder::new()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+            core.device
+                .begin_command_buffer(command_buffer, &begin_info)
+                .result()?;
+        }
+
+        let [irradiance, specular, brdf] = images;
+        for image in [&mut *irradiance, &mut *specular, &mut *brdf] {
+            image.transition(
+                command_buffer,
+                vk::ImageLayout::GENERAL,
+                Stages::COMPUTE_SHADER_KHR,
+            );
+        }
+
+        for (&descriptor_set, &(pipeline, _, size, roughness)) in
+            self.descriptor_sets.iter().zip(passes)
+        {
+            let push = PushConstants {
+                size,
+                samples,
+                roughness,
+            };
+            let groups = size.div_ceil(8);
+            let faces = if pipeline == self.brdf { 1 } else { 6 };
+            unsafe {
+                core.device.cmd_bind_pipeline(
+                    command_buffer,
+                    vk::PipelineBindPoint::COMPUTE,
+                    pipeline,
+                );
+                core.device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::COMPUTE,
+                    self.pipeline_layout,
+                    0,
+                    &[descriptor_set],
+                    &[],
+                );
+                core.device.cmd_push_constants(
+                    command_buffer,
+                    self.pipeline_layout,
+                    vk::ShaderStageFlags::COMPUTE,
+                    0,
+                    std::mem::size_of::<PushConstants>() as u32,
+                    &push as *const PushConstants as _,
+                );
+                core.device
+                    .cmd_dispatch(command_buffer, groups, groups, faces);
+            }
+        }
+
+        for image in [irradiance, specular, brdf] {
+            image.transition(
+                command_buffer,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                Stages::FRAGMENT_SHADER_KHR,
+            );
+        }
+
+        unsafe {
+            core.device.end_command_buffer(command_buffer).result()?;
+        }
+
+        let fence = SharedFence::new(core.clone())?;
+        core.submit(command_buffer, None, None, Some(fence.fence()))?;
+        fence.wait()
+    }
+
+    fn destroy(self, core: &SharedCore) {
+        unsafe {
+            core.destroy_descriptor_pool(self.descriptor_pool);
+            core.device
+                .destroy_descriptor_set_layout(Some(self.descriptor_set_layout), None);
+            for pipeline in [self.irradiance, self.specular, self.brdf] {
+                core.destroy_pipeline(pipeline);
+            }
+            core.device
+                .destroy_pipeline_layout(Some(self.pipeline_layout), None);
+            core.device.destroy_sampler(Some(self.sampler), None);
+        }
+    }
+}
+
+/// A square image with `layers` layers (6 for a cubemap), usable as storage and sampled
+fn create_image(core: &SharedCore, size: u32, mips: u32, layers: u32) -> Result<ManagedImage> {
+    let flags = if layers == 6 {
+        vk::ImageCreateFlags::CUBE_COMPATIBLE
+    } else {
+        vk::ImageCreateFlags::empty()
+    };
+    let create_info = vk::ImageCreateInfoBuilder::new()
+        .flags(flags)
+        .image_type(vk::ImageType::_2D)
+        .extent(vk::Extent3D {
+            width: size,
+            height: size,
+            depth: 1,
+        })
+        .mip_levels(mips)
+        .array_layers(layers)
+        .format(IBL_FORMAT)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .usage(vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED)
+        .samples(vk::SampleCountFlagBits::_1)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE);
+    ManagedImage::new(core.clone(), create_info, UsageFlags::FAST_DEVICE_ACCESS)
+}
+
+fn create_view(
+    core: &SharedCore,
+    image: &ManagedImage,
+    view_type: vk::ImageViewType,
+    base_mip: u32,
+    mips: u32,
+    layers: u32,
+) -> Result<vk::ImageView> {
+    let create_info = vk::ImageViewCreateInfoBuilder::new()
+        .image(image.instance())
+        .view_type(view_type)
+        .format(IBL_FORMAT)
+        .subresource_range(
+            vk::ImageSubresourceRangeBuilder::new()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .base_mip_level(base_mip)
+                .level_count(mips)
+                .base_array_layer(0)
+                .layer_count(layers)
+                .build(),
+        );
+    Ok(unsafe { core.device.create_image_view(&create_info, None, None) }.result()?)
+}
+
+fn create_sampler(core: &SharedCore, mips: u32) -> Result<vk::Sampler> {
+    let create_info = vk::SamplerCreateInfoBuilder::new()
+        .mag_filter(vk::Filter::LINEAR)
+        .min_filter(vk::Filter::LINEAR)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .anisotropy_enable(false)
+        .max_anisotropy(1.)
+        .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
+        .unnormalized_coordinates(false)
+        .compare_enable(false)
+        .compare_op(vk::CompareOp::ALWAYS)
+        .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+        .mip_lod_bias(0.)
+        .min_lod(0.)
+        .max_lod(mips as f32);
+    Ok(unsafe { core.device.create_sampler(&create_info, None, None) }.result()?)
+}
@@ -0,0 +1,393 @@
+//! Named logical actions (such as "select" or "menu"), bound by the app to keys, mouse buttons
+//! and OpenXR controller inputs, so that app logic can ask whether "select" was pressed instead
+//! of matching on each backend's events.
+//!
+//! ```ignore
+//! let mut input = Input::new()
+//!     .bind("select", Binding::Mouse(MouseButton::Left))
+//!     .bind("select", Binding::xr(SIMPLE_CONTROLLER, "/user/hand/right/input/select/click"))
+//!     .bind("menu", Binding::Key(VirtualKeyCode::Escape))
+//!     .bind("menu", Binding::xr(SIMPLE_CONTROLLER, "/user/hand/left/input/menu/click"));
+//!
+//! // MainLoop::event()
+//! input.event(&event);
+//!
+//! // MainLoop::frame()
+//! input.update(&platform)?;
+//! if input.pressed("select") { /* ... */ }
+//! ```
+//!
+//! In VR, the aim pose of each controller is tracked as well; see `Input::aim_ray()`.
+use crate::mainloop::{Platform, PlatformEvent};
+use anyhow::Result;
+use winit::event::{ElementState, Event, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent};
+
+/// The interaction profile every OpenXR runtime supports, with select and menu buttons on each
+/// hand
+pub const SIMPLE_CONTROLLER: &str = "/interaction_profiles/khr/simple_controller";
+
+/// A physical input which triggers an action
+#[derive(Clone, Debug, PartialEq)]
+pub enum Binding {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+    /// A boolean OpenXR input path, suggested for an interaction profile. Ignored without the
+    /// `openxr` feature.
+    Xr {
+        profile: String,
+        path: String,
+    },
+}
+
+impl Binding {
+    /// An OpenXR binding, such as
+    /// `Binding::xr(SIMPLE_CONTROLLER, "/user/hand/right/input/select/click")`
+    pub fn xr(profile: &str, path: &str) -> Self {
+        Binding::Xr {
+            profile: profile.to_owned(),
+            path: path.to_owned(),
+        }
+    }
+}
+
+/// A controller, see `Input::aim_ray()`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Hand {
+    Left,
+    Right,
+}
+
+/// A named action, and the state of its bindings
+struct Action {
+    name: String,
+    bindings: Vec<Binding>,
+    /// Whether each window binding is held
+    held: Vec<bool>,
+    /// A window binding went down since the last update, so quick taps between frames aren't
+    /// missed
+    tapped: bool,
+    down: bool,
+    pressed: bool,
+    released: bool,
+}
+
+/// Logical actions and their bindings. Feed it events with `event()`, and call `update()` once
+/// per frame before reading the actions.
+#[derive(Default)]
+pub struct Input {
+    actions: Vec<Action>,
+    #[cfg(feature = "openxr")]
+    xr: Option<XrActions>,
+    /// Aim pose of each hand in `XrCore::world()`, while tracked
+    #[cfg(feature = "openxr")]
+    aim: [Option<openxr::Posef>; 2],
+}
+
+impl Input {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bind `binding` to the action called `name`, creating it if needed. An action may have
+    /// any number of bindings, and is down while any of them is.
+    pub fn bind(mut self, name: &str, binding: Binding) -> Self {
+        let idx = match self.actions.iter().position(|a| a.name == name) {
+            Some(idx) => idx,
+            None => {
+                self.actions.push(Action {
+                    name: name.to_owned(),
+                    bindings: vec![],
+                    held: vec![],
+                    tapped: false,
+                    down: false,
+                    pressed: false,
+                    released: false,
+                });
+                self.actions.len() - 1
+            }
+        };
+        let action = &mut self.actions[idx];
+        action.bindings.push(binding);
+        action.held.push(false);
+        self
+    }
+
+    /// Track key and mouse button bindings. Call from `MainLoop::event()`.
+    pub fn event(&mut self, event: &PlatformEvent<'_, '_>) {
+        let event = match event {
+            PlatformEvent::Winit(Event::WindowEvent { event, .. }) => event,
+            _ => return,
+        };
+
+        let (input, state) = match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(key),
+                        state,
+                        ..
+                    },
+                ..
+            } => (Binding::Key(*key), *state),
+            WindowEvent::MouseInput { button, state, .. } => (Binding::Mouse(*button), *state),
+            WindowEvent::Focused(false) => {
+                // Releases while unfocused are never seen
+                for action in &mut self.actions {
+                    action.held.iter_mut().for_each(|held| *held = false);
+                }
+                return;
+            }
+            _ => return,
+        };
+
+        let down = state == ElementState::Pressed;
+        for action in &mut self.actions {
+            for (binding, held) in action.bindings.iter().zip(&mut action.held) {
+                if *binding == input {
+                    action.tapped |= down && !*held;
+                    *held = down;
+                }
+            }
+        }
+    }
+
+    /// Update the actions for this frame, reading OpenXR inputs in VR. Call at the start of
+    /// `MainLoop::frame()`.
+    pub fn update(&mut self, platform: &Platform<'_>) -> Result<()> {
+        #[cfg(feature = "openxr")]
+        let xr_down = match platform {
+            Platform::OpenXr { xr_core, .. } => self.xr_states(xr_core)?,
+            _ => {
+                self.aim = [None; 2];
+                vec![false; self.actions.len()]
+            }
+        };
+        #[cfg(not(feature = "openxr"))]
+        let xr_down = {
+            let _ = platform;
+            vec![false; self.actions.len()]
+        };
+
+        for (action, xr_down) in self.actions.iter_mut().zip(xr_down) {
+            let was_down = action.down;
+            action.down = xr_down || action.held.iter().any(|&held| held);
+            action.pressed = (action.down && !was_down) || action.tapped;
+            action.released = !action.down && was_down;
+            action.tapped = false;
+        }
+
+        Ok(())
+    }
+
+    /// Whether the action is held
+    pub fn down(&self, name: &str) -> bool {
+        self.action(name).is_some_and(|a| a.down)
+    }
+
+    /// Whether the action was pressed since the last frame
+    pub fn pressed(&self, name: &str) -> bool {
+        self.action(name).is_some_and(|a| a.pressed)
+    }
+
+    /// Whether the action was released since the last frame
+    pub fn released(&self, name: &str) -> bool {
+        self.action(name).is_some_and(|a| a.released)
+    }
+
+    /// Where a controller points in the world, while it is tracked in VR. Always None on the
+    /// desktop; see `Ray::from_screen()` for the mouse.
+    #[cfg(feature = "nalgebra")]
+    pub fn aim_ray(&self, hand: Hand) -> Option<crate::ray::Ray> {
+        #[cfg(feature = "openxr")]
+        return self.aim_pose(hand).as_ref().map(crate::ray::Ray::from_pose);
+        #[cfg(not(feature = "openxr"))]
+        {
+            let _ = hand;
+            None
+        }
+    }
+
+    /// A controller's aim pose in `XrCore::world()`, while it is tracked in VR
+    #[cfg(feature = "openxr")]
+    pub fn aim_pose(&self, hand: Hand) -> Option<openxr::Posef> {
+        self.aim[hand as usize]
+    }
+
+    fn action(&self, name: &str) -> Option<&Action> {
+        let action = self.actions.iter().find(|a| a.name == name);
+        debug_assert!(action.is_some(), "No action named {:?} is bound", name);
+        action
+    }
+}
+
+/// OpenXR actions for each `Input` action with `Binding::Xr`s, attached to a session
+#[cfg(feature = "openxr")]
+struct XrActions {
+    instance: openxr::sys::Instance,
+    session: openxr::sys::Session,
+    set: openxr::ActionSet,
+    actions: Vec<Option<openxr::Action<bool>>>,
+    /// Kept apart from the app's actions, so their names can't collide
+    aim_set: openxr::ActionSet,
+    aim: openxr::Action<openxr::Posef>,
+    /// `/user/hand/left` and `/user/hand/right`
+    hands: [openxr::Path; 2],
+    /// Aim space of each hand, for the current session
+    aim_spaces: Vec<openxr::Space>,
+}
+
+#[cfg(feature = "openxr")]
+impl Input {
+    /// Whether each action's OpenXR bindings are held. Creates the action set on first use, and
+    /// attaches it to new sessions (such as after the hybrid backend re-enters VR).
+    fn xr_states(&mut self, xr_core: &crate::openxr_backend::XrCore) -> Result<Vec<bool>> {
+        let instance = xr_core.instance.as_raw();
+        if self.xr.as_ref().map(|xr| xr.instance) != Some(instance) {
+            self.xr = Some(self.create_xr_actions(&xr_core.instance)?);
+        }
+
+        let xr = self.xr.as_mut().unwrap();
+        let session = xr_core.session.as_raw();
+        if xr.session != session {
+            xr_core
+                .session
+                .attach_action_sets(&[&xr.set, &xr.aim_set])?;
+            xr.aim_spaces = xr
+                .hands
+                .iter()
+                .map(|&hand| {
+                    xr.aim
+                        .create_space(xr_core.session.clone(), hand, openxr::Posef::IDENTITY)
+                })
+                .collect::<Result<_, _>>()?;
+            xr.session = session;
+        }
+
+        xr_core.session.sync_actions(&[
+            openxr::ActiveActionSet::new(&xr.set),
+            openxr::ActiveActionSet::new(&xr.aim_set),
+        ])?;
+
+        let time = xr_core.frame_stats().predicted_display_time;
+        let tracked = openxr::SpaceLocationFlags::POSITION_VALID
+            | openxr::SpaceLocationFlags::ORIENTATION_VALID;
+        for ((aim, space), &hand) in self.aim.iter_mut().zip(&xr.aim_spaces).zip(&xr.hands) {
+            *aim = None;
+            if time.as_nanos() == 0 || !xr.aim.is_active(&xr_core.session, hand)? {
+                continue;
+            }
+            let location = space.locate(&xr_core.world(), time)?;
+            if location.location_flags.contains(tracked) {
+                *aim = Some(location.pose);
+            }
+        }
+
+        let mut states = vec![];
+        for action in &xr.actions {
+            let down = match action {
+                Some(action) => {
+                    let state = action.state(&xr_core.session, openxr::Path::NULL)?;
+                    state.is_active && state.current_state
+                }
+                None => false,
+            };
+            states.push(down);
+        }
+        Ok(states)
+    }
+
+    fn create_xr_actions(&self, instance: &openxr::Instance) -> Result<XrActions> {
+        let set = instance.create_action_set("watertender_input", "Input", 0)?;
+
+        let mut actions = vec![];
+        for action in &self.actions {
+            let xr_bound = action
+                .bindings
+                .iter()
+                .any(|binding| matches!(binding, Binding::Xr { .. }));
+            actions.push(match xr_bound {
+                true => Some(set.create_action::<bool>(
+                    &xr_action_name(&action.name),
+                    &action.name,
+                    &[],
+                )?),
+                false => None,
+            });
+        }
+
+        // Bindings are suggested per interaction profile
+        let suggested: Vec<(&str, &openxr::Action<bool>, &str)> = self
+            .actions
+            .iter()
+            .zip(&actions)
+            .filter_map(|(action, xr_action)| Some((action, xr_action.as_ref()?)))
+            .flat_map(|(action, xr_action)| {
+                action
+                    .bindings
+                    .iter()
+                    .filter_map(move |binding| match binding {
+                        Binding::Xr { profile, path } => {
+                            Some((profile.as_str(), xr_action, path.as_str()))
+                        }
+                        _ => None,
+                    })
+            })
+            .collect();
+
+        let aim_set = instance.create_action_set("watertender_aim", "Aim", 0)?;
+        let hands = [
+            instance.string_to_path("/user/hand/left")?,
+            instance.string_to_path("/user/hand/right")?,
+        ];
+        let aim = aim_set.create_action::<openxr::Posef>("aim", "Aim", &hands)?;
+        let aim_paths = [
+            instance.string_to_path("/user/hand/left/input/aim/pose")?,
+            instance.string_to_path("/user/hand/right/input/aim/pose")?,
+        ];
+
+        // Every profile gets aim bindings, and the simple controller is always suggested so that
+        // aim rays work without any other bindings
+        let mut profiles: Vec<&str> = suggested.iter().map(|(profile, _, _)| *profile).collect();
+        profiles.push(SIMPLE_CONTROLLER);
+        profiles.sort_unstable();
+        profiles.dedup();
+        for profile in profiles {
+            let mut bindings = vec![];
+            for (_, action, path) in suggested.iter().filter(|(p, _, _)| *p == profile) {
+                bindings.push(openxr::Binding::new(
+                    *action,
+                    instance.string_to_path(path)?,
+                ));
+            }
+            for &path in &aim_paths {
+                bindings.push(openxr::Binding::new(&aim, path));
+            }
+            instance.suggest_interaction_profile_bindings(
+                instance.string_to_path(profile)?,
+                &bindings,
+            )?;
+        }
+
+        Ok(XrActions {
+            instance: instance.as_raw(),
+            session: openxr::sys::Session::NULL,
+            set,
+            actions,
+            aim_set,
+            aim,
+            hands,
+            aim_spaces: vec![],
+        })
+    }
+}
+
+/// OpenXR action names may only contain lowercase letters, digits, `-`, `_` and `.`
+#[cfg(feature = "openxr")]
+fn xr_action_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c.to_ascii_lowercase() {
+            c @ ('a'..='z' | '0'..='9' | '-' | '_' | '.') => c,
+            _ => '_',
+        })
+        .collect()
+}
@@ -1,17 +1,48 @@
 pub mod framebuffer_mgr;
+pub mod render_target;
 pub mod frame_data_ubo;
+pub mod object_data_ubo;
+/// Descriptor set numbering shared by built-in and user pipelines
+pub mod set_layouts;
+pub mod ring_buffer;
+pub mod split_screen;
 pub mod render_pass;
 pub mod shader;
+pub mod shader_cache;
+mod view_index;
+pub mod async_pipeline;
+/// Compute dispatches synchronized with the frame's rendering
+pub mod compute;
+/// Compute work on a dedicated queue, overlapping rendering
+pub mod async_compute;
+pub mod pipeline_manager;
+pub mod transparency;
 pub mod staging_buffer;
 pub mod synchronization;
+pub mod sync2;
+pub mod barriers;
+pub mod copy;
 pub mod vertex;
 pub mod app_info;
 pub mod core;
 pub mod defaults;
 pub mod hardware_query;
+pub mod capabilities;
 pub mod memory;
+pub mod resources;
+pub mod deletion_queue;
+pub mod frame_guard;
 pub mod mesh;
+pub mod mesh_arena;
+/// Pipelines bundled with their descriptor sets and push constants
+pub mod material;
 pub mod headless_backend;
+/// Images and meshes decoded on worker threads and uploaded in the background
+pub mod assets;
+/// Images exported to other processes and APIs, such as video encoders
+#[cfg(unix)]
+pub mod external_memory;
+mod validation;
 
 #[cfg(feature = "nalgebra")]
 pub mod arcball;
@@ -25,11 +56,76 @@ pub mod xr_camera;
 #[cfg(feature = "nalgebra")]
 mod multi_platform_camera;
 #[cfg(feature = "nalgebra")]
-pub use multi_platform_camera::MultiPlatformCamera;
+pub use multi_platform_camera::{CameraSettings, MultiPlatformCamera, XR_CLIPPING};
 
 #[cfg(feature = "nalgebra")]
 pub mod starter_kit;
 
+#[cfg(feature = "nalgebra")]
+pub mod skinning;
+
+/// Deferred shading with a G-buffer and a light list
+#[cfg(feature = "nalgebra")]
+pub mod deferred;
+
+/// Stencil outlines around selected objects
+#[cfg(feature = "nalgebra")]
+pub mod outline;
+
+/// Post-processing passes
+pub mod post;
+
+/// Object picking by ID buffer readback
+pub mod picking;
+
+/// Point clouds drawn as camera-facing splats
+pub mod point_cloud;
+
+/// Colormaps for visualizing scalar data
+pub mod colormap;
+
+/// Small images packed into one texture
+pub mod atlas;
+
+/// sRGB and linear color conversions
+pub mod color;
+
+/// Depth buffer readback
+pub mod depth_capture;
+
+/// Rays from controllers or the cursor, intersected with boxes and triangles
+#[cfg(feature = "nalgebra")]
+pub mod ray;
+
+/// UI panels placed in the world, as quad layers in VR
+#[cfg(feature = "nalgebra")]
+pub mod world_panel;
+
+/// Image based lighting prefiltered from HDR environment maps
+pub mod ibl;
+
+pub mod profiling;
+pub mod bench;
+
+#[cfg(feature = "renderdoc")]
+pub mod capture;
+
+#[cfg(feature = "screenshot")]
+pub mod screenshot;
+
+/// Build script helpers compiling GLSL for `compile_glsl!`
+#[cfg(feature = "glsl-build")]
+pub mod glsl_build;
+
+/// Embed the SPIR-V compiled from a GLSL shader by `glsl_build` in the crate's build script, as
+/// a `&[u8]`. The path is relative to the crate root, as given to the build script.
+#[macro_export]
+macro_rules! compile_glsl {
+    ($path:literal) => {
+        include_bytes!(concat!(env!("OUT_DIR"), "/", $path, ".spv"))
+    };
+}
+
 /// Vulkan implementation supplied by Erupt
 pub use erupt::vk;
 
@@ -38,12 +134,25 @@ pub mod openxr_backend;
 #[cfg(feature = "openxr")]
 pub use openxr;
 
+#[cfg(feature = "openxr")]
+pub mod hybrid_backend;
+#[cfg(feature = "openxr")]
+pub mod xr_frame_stats;
+#[cfg(feature = "openxr")]
+pub mod xr_refresh_rate;
+#[cfg(feature = "openxr")]
+pub mod xr_pose;
+
 pub mod winit_backend;
+pub mod display_backend;
 pub use winit;
 
 /// Mainloop abstraction
 pub mod mainloop;
 
+/// Logical actions bound to keys, mouse buttons and OpenXR inputs
+pub mod input;
+
 #[cfg(feature = "nalgebra")]
 pub use nalgebra;
 
@@ -59,15 +168,19 @@ pub mod prelude {
     pub use super::{
         render_pass::create_render_pass, 
         framebuffer_mgr::FramebufferManager, 
+        render_target::{RenderTarget, RenderTargetSettings},
         staging_buffer::StagingBuffer, 
         synchronization::Synchronization,
-        mesh::{ManagedMesh, upload_mesh, draw_mesh},
+        mesh::{ManagedMesh, upload_mesh, draw_mesh, generate_tangents, Bounds, export_obj},
+        mesh::{RetainedMesh, upload_mesh_retained, LodMesh, LodMeshLevel, upload_lod_mesh, draw_lod},
+        mesh_arena::{ArenaMesh, MeshArena},
         memory::{ManagedImage, ManagedBuffer},
         starter_kit::{self, launch, StarterKit},
-        frame_data_ubo::FrameDataUbo,
-        app_info::AppInfo,
-        vertex::Vertex,
-        shader::shader,
+        frame_data_ubo::{DeviceFrameDataUbo, FrameDataUbo},
+        object_data_ubo::{DrawCall, ObjectDataUbo},
+        app_info::{AppInfo, ValidationFeatures},
+        vertex::{Vertex, VertexNT, VertexTangent, VertexSkinned, VertexLayout},
+        shader::{shader, shader_with_layout, UNLIT_VERT, UNLIT_FRAG, UNLIT_TEX_FRAG},
         Core, SharedCore,
         defaults,
     };
@@ -76,5 +189,5 @@ pub mod prelude {
     pub use super::mainloop::{MainLoop, Platform, PlatformReturn, PlatformEvent, SyncMainLoop, Frame};
 
     #[cfg(feature = "nalgebra")]
-    pub use super::multi_platform_camera::MultiPlatformCamera;
+    pub use super::multi_platform_camera::{CameraSettings, MultiPlatformCamera};
 }
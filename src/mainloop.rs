@@ -1,6 +1,8 @@
+use crate::synchronization::AcquireError;
 use crate::{Core, SharedCore};
 use anyhow::Result;
 use erupt::vk;
+use std::path::Path;
 
 /// Interface to the gpu's commands
 pub struct Frame {
@@ -8,11 +10,12 @@ pub struct Frame {
     pub swapchain_index: u32,
 }
 
-/// All mainloops run on executors must implement this trait
-pub trait MainLoop<T=()>: Sized {
+/// All mainloops run on executors must implement this trait. `D` is construction data, passed
+/// through `launch()` by every backend to `new()`; apps which need none use the default `()`.
+pub trait MainLoop<D = ()>: Sized {
     /// Creates a new instance of your app. Mainly useful for setting up data structures and
-    /// allocating memory.
-    fn new(core: &SharedCore, platform: Platform<'_>, userdata: T) -> Result<Self>;
+    /// allocating memory. `data` is whatever was passed to `launch()`.
+    fn new(core: &SharedCore, platform: Platform<'_>, data: D) -> Result<Self>;
 
     /// A frame handled by your app.
     fn frame(
@@ -35,7 +38,7 @@ pub trait MainLoop<T=()>: Sized {
 }
 
 /// Trait required by the winit backend to synchronize with the swapchain
-pub trait SyncMainLoop<T=()>: MainLoop<T> {
+pub trait SyncMainLoop<D = ()>: MainLoop<D> {
     /// Return (image_available, render_finished). The first semaphore will be signalled by the runtime when the frame is available, and the runtime will wait to present the image until the second semaphore has been signalled.
     /// Therefore you will want to wait on the first semaphore to begin rendering, and signal the second semaphore when you are finished.
     /// This method will be called once before each `frame()`.
@@ -47,15 +50,34 @@ pub enum Platform<'a> {
     Winit {
         window: &'a winit::window::Window,
         control_flow: &'a mut winit::event_loop::ControlFlow, // TODO: Part of PlatformReturn?
+        /// Format of the swapchain images, negotiated with the surface
+        color_format: vk::Format,
     },
     #[cfg(feature = "openxr")]
     OpenXr {
         xr_core: &'a crate::openxr_backend::XrCore,
         frame_state: Option<openxr::FrameState>,
     },
+    /// Presenting directly to a display, without a window system. There are no events.
+    Display {
+        /// Set to exit after the current frame
+        exit: &'a mut bool,
+        /// Format of the swapchain images, negotiated with the display surface
+        color_format: vk::Format,
+        /// Resolution of the display mode in use
+        extent: vk::Extent2D,
+    },
 }
 
 impl Platform<'_> {
+    /// Draw another frame, when running with `AppInfo::redraw_on_demand()`. Other platforms draw
+    /// continuously, so this has no effect there.
+    pub fn request_redraw(&self) {
+        if let Platform::Winit { window, .. } = self {
+            window.request_redraw();
+        }
+    }
+
     pub fn request_exit(&mut self) {
         match self {
             Platform::Winit { control_flow, .. } => {
@@ -65,8 +87,47 @@ impl Platform<'_> {
             Platform::OpenXr { xr_core, .. } => {
                 xr_core.session.request_exit().expect("Failed to request OpenXr exit");
             },
+            Platform::Display { exit, .. } => **exit = true,
+        }
+    }
+
+    /// Borrow this platform for passing on, such as to a wrapped `MainLoop`
+    pub fn reborrow(&mut self) -> Platform<'_> {
+        match self {
+            Platform::Winit {
+                window,
+                control_flow,
+                color_format,
+            } => Platform::Winit {
+                window,
+                control_flow,
+                color_format: *color_format,
+            },
+            #[cfg(feature = "openxr")]
+            Platform::OpenXr {
+                xr_core,
+                frame_state,
+            } => Platform::OpenXr {
+                xr_core,
+                frame_state: *frame_state,
+            },
+            Platform::Display {
+                exit,
+                color_format,
+                extent,
+            } => Platform::Display {
+                exit,
+                color_format: *color_format,
+                extent: *extent,
+            },
         }
     }
+
+    /// Capture the next frame with RenderDoc, if the app is running under it
+    #[cfg(feature = "renderdoc")]
+    pub fn trigger_capture(&self) {
+        crate::capture::trigger();
+    }
 }
 
 /// Multi-platform event
@@ -74,6 +135,23 @@ pub enum PlatformEvent<'a, 'b> {
     Winit(&'b winit::event::Event<'a, ()>),
     #[cfg(feature = "openxr")]
     OpenXr(&'b openxr::Event<'a>),
+    /// The app was sent to the background (winit's `Suspended` on mobile, or the OpenXR session
+    /// stopping). The device is idle when this is delivered, and no frames are run until
+    /// `Resumed`. In the winit backend the window surface may be destroyed, so resources tied to
+    /// the swapchain images may be released here. The OpenXR swapchain stays valid.
+    Suspended,
+    /// Follows `Suspended`. In the winit backend the surface and swapchain have been recreated,
+    /// and `MainLoop::swapchain_resize()` called, before this is delivered. In the OpenXR
+    /// backend the session is running again.
+    Resumed,
+    /// The hybrid backend switched between the desktop and VR, and the platform passed with this
+    /// event is the new one. The device is idle, and `MainLoop::swapchain_resize()` follows
+    /// (on the first frame, in VR) with the new platform's images.
+    PlatformChanged,
+    /// The windowed backends could not acquire a swapchain image, and skipped the frame. A lost
+    /// surface has been recreated before this is delivered; otherwise the next frame retries,
+    /// so apps may exit or fall back if the failure persists. See `AppInfo::acquire_timeout()`.
+    AcquireFailed(AcquireError),
 }
 
 /// Multi-platform return value
@@ -81,9 +159,57 @@ pub enum PlatformReturn {
     Winit,
     #[cfg(feature = "openxr")]
     OpenXr(Vec<openxr::View>),
+    Display,
 }
 
 impl Platform<'_> {
+    /// Format of the images passed to `MainLoop::swapchain_resize()`
+    pub fn color_format(&self) -> vk::Format {
+        match self {
+            Platform::Winit { color_format, .. } => *color_format,
+            #[cfg(feature = "openxr")]
+            Platform::OpenXr { .. } => crate::defaults::COLOR_FORMAT,
+            Platform::Display { color_format, .. } => *color_format,
+        }
+    }
+
+    /// Physical pixels per logical pixel of the window, 1.0 in VR and on a display. Also
+    /// reported by winit's `WindowEvent::ScaleFactorChanged`.
+    pub fn scale_factor(&self) -> f64 {
+        match self {
+            Platform::Winit { window, .. } => window.scale_factor(),
+            #[cfg(feature = "openxr")]
+            Platform::OpenXr { .. } => 1.0,
+            Platform::Display { .. } => 1.0,
+        }
+    }
+
+    /// Size of the window's drawable area (or the display) in physical pixels, which is the
+    /// swapchain extent. None in VR.
+    pub fn physical_size(&self) -> Option<winit::dpi::PhysicalSize<u32>> {
+        match self {
+            Platform::Winit { window, .. } => Some(window.inner_size()),
+            #[cfg(feature = "openxr")]
+            Platform::OpenXr { .. } => None,
+            Platform::Display { extent, .. } => {
+                Some(winit::dpi::PhysicalSize::new(extent.width, extent.height))
+            }
+        }
+    }
+
+    /// Size of the window's drawable area in logical pixels, which UI should be laid out in, if
+    /// there is a window
+    pub fn logical_size(&self) -> Option<winit::dpi::LogicalSize<f64>> {
+        self.physical_size()
+            .map(|size| size.to_logical(self.scale_factor()))
+    }
+
+    /// Switch between the desktop and VR after this frame, in the hybrid backend
+    #[cfg(feature = "openxr")]
+    pub fn request_platform_switch(&self) {
+        crate::hybrid_backend::request_switch();
+    }
+
     pub fn is_vr(&self) -> bool {
         match self {
             #[cfg(feature = "openxr")]
@@ -93,3 +219,39 @@ impl Platform<'_> {
         }
     }
 }
+
+impl PlatformEvent<'_, '_> {
+    /// The window event this carries, if any
+    fn window_event(&self) -> Option<&winit::event::WindowEvent<'_>> {
+        match self {
+            PlatformEvent::Winit(winit::event::Event::WindowEvent { event, .. }) => Some(event),
+            _ => None,
+        }
+    }
+
+    /// A file dropped onto the window, such as an asset to load. Each file of a multi-file drop
+    /// arrives as its own event.
+    pub fn dropped_file(&self) -> Option<&Path> {
+        match self.window_event()? {
+            winit::event::WindowEvent::DroppedFile(path) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// A file being dragged over the window, so that the app can show it would be accepted.
+    /// Followed by `dropped_file()` or `hover_cancelled()`.
+    pub fn hovered_file(&self) -> Option<&Path> {
+        match self.window_event()? {
+            winit::event::WindowEvent::HoveredFile(path) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Files being dragged over the window left it without being dropped
+    pub fn hover_cancelled(&self) -> bool {
+        matches!(
+            self.window_event(),
+            Some(winit::event::WindowEvent::HoveredFileCancelled)
+        )
+    }
+}
@@ -0,0 +1,121 @@
+//! Materials bundle a pipeline with the descriptor sets and push constants its draws need, so
+//! that objects are drawn with `StarterKit::draw()` rather than bound by hand. The pipeline lives
+//! in the StarterKit's `PipelineManager`, so materials survive render pass changes.
+//!
+//! Push constants start with the draw's transform, followed by the material's parameters:
+//!
+//! ```glsl
+//! layout(push_constant) uniform Draw { mat4 model; vec4 tint; };
+//! ```
+//!
+//! ```ignore
+//! let ranges = [material::push_constant_range(std::mem::size_of::<Tint>())];
+//! // Build `layout` from the frame and material set layouts and `ranges`
+//! let pipeline = starter_kit
+//!     .pipelines
+//!     .add::<Vertex>(VERT, FRAG, PipelineSettings::default(), layout)?;
+//! let mut red = Material::new(pipeline, layout);
+//! red.bind_per_frame(FRAME_SET, frame_sets);
+//! red.bind(MATERIAL_SET, red_set);
+//! red.set_params(&Tint { color: [1.0, 0.0, 0.0, 1.0] })?;
+//! // While drawing
+//! starter_kit.draw(&cmd, &cube, &red, &transform);
+//! ```
+use crate::pipeline_manager::PipelineId;
+use anyhow::{ensure, Result};
+use bytemuck::Pod;
+use erupt::vk;
+
+/// Stages the push constants of materials are visible to
+pub const PUSH_CONSTANT_STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::from_bits_truncate(
+    vk::ShaderStageFlags::VERTEX.bits() | vk::ShaderStageFlags::FRAGMENT.bits(),
+);
+
+/// Size of the transform at the start of the push constants, a column-major `mat4`
+pub const TRANSFORM_SIZE: u32 = 16 * 4;
+
+/// Largest size of a material's parameters, so that push constants fit in the 128 bytes every
+/// device supports
+pub const MAX_PARAMS_SIZE: usize = 128 - TRANSFORM_SIZE as usize;
+
+/// Push constant range of pipeline layouts for materials with `params_size` bytes of parameters
+pub fn push_constant_range(params_size: usize) -> vk::PushConstantRangeBuilder<'static> {
+    vk::PushConstantRangeBuilder::new()
+        .stage_flags(PUSH_CONSTANT_STAGES)
+        .offset(0)
+        .size(TRANSFORM_SIZE + params_size as u32)
+}
+
+/// Descriptor sets bound at one set index: a single set, or one per frame in flight
+#[derive(Clone, Debug)]
+pub enum MaterialSet {
+    Shared(vk::DescriptorSet),
+    /// Indexed by `StarterKit::frame`
+    PerFrame(Vec<vk::DescriptorSet>),
+}
+
+impl MaterialSet {
+    /// The set to bind in frame `frame`
+    pub fn get(&self, frame: usize) -> vk::DescriptorSet {
+        match self {
+            MaterialSet::Shared(set) => *set,
+            MaterialSet::PerFrame(sets) => sets[frame],
+        }
+    }
+}
+
+/// A pipeline of a `PipelineManager`, with its layout, descriptor sets and parameters. Cheap to
+/// clone; the pipeline and sets are owned elsewhere.
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub pipeline: PipelineId,
+    /// Layout the pipeline was built with, including `push_constant_range()`
+    pub layout: vk::PipelineLayout,
+    /// Descriptor sets and the set index each is bound at
+    pub sets: Vec<(u32, MaterialSet)>,
+    /// Parameters pushed after the transform
+    params: Vec<u8>,
+}
+
+impl Material {
+    /// A material with no descriptor sets or parameters
+    pub fn new(pipeline: PipelineId, layout: vk::PipelineLayout) -> Self {
+        Self {
+            pipeline,
+            layout,
+            sets: vec![],
+            params: vec![],
+        }
+    }
+
+    /// Bind `descriptor_set` at `set` in every frame
+    pub fn bind(&mut self, set: u32, descriptor_set: vk::DescriptorSet) {
+        self.sets.push((set, MaterialSet::Shared(descriptor_set)));
+    }
+
+    /// Bind one of `descriptor_sets` at `set`, each for its frame in flight, such as the sets of
+    /// a `FrameDataUbo`
+    pub fn bind_per_frame(&mut self, set: u32, descriptor_sets: Vec<vk::DescriptorSet>) {
+        self.sets
+            .push((set, MaterialSet::PerFrame(descriptor_sets)));
+    }
+
+    /// Set the parameters pushed after the transform, which must match the size given to
+    /// `push_constant_range()`
+    pub fn set_params<T: Pod>(&mut self, params: &T) -> Result<()> {
+        let bytes = bytemuck::bytes_of(params);
+        ensure!(
+            bytes.len() <= MAX_PARAMS_SIZE,
+            "Material parameters are {} bytes, but at most {} fit in push constants",
+            bytes.len(),
+            MAX_PARAMS_SIZE
+        );
+        self.params = bytes.to_vec();
+        Ok(())
+    }
+
+    /// Parameters pushed after the transform
+    pub fn params(&self) -> &[u8] {
+        &self.params
+    }
+}
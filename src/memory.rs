@@ -1,8 +1,13 @@
+use crate::deletion_queue::Garbage;
+use crate::sync2::{self, Access, ImageBarrier, Stages};
+use crate::synchronization::SharedFence;
 use crate::{Core, SharedCore};
-use anyhow::Result;
+use anyhow::{ensure, Result};
+use bytemuck::Pod;
 use erupt::vk1_0 as vk;
 pub use gpu_alloc::{Request, UsageFlags};
 use gpu_alloc_erupt::EruptMemoryDevice as EMD;
+use std::ptr::NonNull;
 
 /// Block of allocated device memory
 pub type MemoryBlock = gpu_alloc::MemoryBlock<vk::DeviceMemory>;
@@ -16,7 +21,8 @@ impl Core {
 
     /// Deallocate using a gpu-alloc request
     pub fn deallocate(&self, memory: MemoryBlock) -> Result<()> {
-        unsafe { Ok(self.allocator()?.dealloc(EMD::wrap(&self.device), memory)) }
+        unsafe { self.allocator()?.dealloc(EMD::wrap(&self.device), memory) }
+        Ok(())
     }
 }
 
@@ -25,6 +31,11 @@ impl Core {
 pub struct ManagedImage {
     instance: vk::Image,
     memory: Option<MemoryBlock>,
+    ready: Option<SharedFence>,
+    subresource_range: vk::ImageSubresourceRange,
+    layout: vk::ImageLayout,
+    /// Stages the image was last transitioned for
+    stages: Stages,
     core: SharedCore,
 }
 
@@ -33,9 +44,19 @@ pub struct ManagedImage {
 pub struct ManagedBuffer {
     instance: vk::Buffer,
     pub memory: Option<MemoryBlock>,
+    ready: Option<SharedFence>,
+    /// Start of the whole memory block, once mapped by `map_persistent()`
+    mapped: Option<NonNull<u8>>,
     pub core: SharedCore,
 }
 
+// SAFETY: `mapped` points into `memory`, which is Send, and is only dereferenced through a
+// `MappedBuffer` borrowing the buffer mutably. The mapping is not tied to the creating thread.
+unsafe impl Send for ManagedBuffer {}
+// SAFETY: No `&self` method reads or writes through `mapped`, so shared references can't race on
+// the mapped memory; the remaining fields are Sync.
+unsafe impl Sync for ManagedBuffer {}
+
 const USE_AFTER_FREE_MSG: &str = "Use-after-free!";
 
 impl ManagedBuffer {
@@ -53,35 +74,214 @@ impl ManagedBuffer {
                 .bind_buffer_memory(instance, *memory.memory(), memory.offset())
                 .result()?;
         }
+        core.resources.buffer_created(memory.size());
         Ok(Self {
             instance,
             memory: Some(memory),
+            ready: None,
+            mapped: None,
             core,
         })
     }
 
+    /// Copy `data` into the memory through a transient mapping (or the persistent one, if
+    /// mapped). Non-coherent memory is flushed afterwards, so the write is visible to commands
+    /// submitted after this returns.
     pub fn write_bytes(&mut self, offset: u64, data: &[u8]) -> Result<()> {
-        Ok(unsafe {
+        self.check_range::<u8>(offset, data.len())?;
+        if self.mapped.is_some() {
+            let mut mapped = self.map_persistent()?;
+            mapped.bytes()[offset as usize..][..data.len()].copy_from_slice(data);
+            return mapped.flush(offset, data.len() as u64);
+        }
+
+        unsafe {
             self.memory
                 .as_mut()
                 .expect(USE_AFTER_FREE_MSG)
                 .write_bytes(EMD::wrap(&self.core.device), offset, data)?;
-        })
+        }
+        Ok(())
     }
 
+    /// Copy from the memory through a transient mapping (or the persistent one, if mapped).
+    /// Non-coherent memory is invalidated first, so writes by completed commands are visible.
     pub fn read_bytes(&mut self, offset: u64, data: &mut [u8]) -> Result<()> {
-        Ok(unsafe {
+        self.check_range::<u8>(offset, data.len())?;
+        if self.mapped.is_some() {
+            let mut mapped = self.map_persistent()?;
+            mapped.invalidate(offset, data.len() as u64)?;
+            data.copy_from_slice(&mapped.bytes()[offset as usize..][..data.len()]);
+            return Ok(());
+        }
+
+        unsafe {
             self.memory.as_mut().expect(USE_AFTER_FREE_MSG).read_bytes(
                 EMD::wrap(&self.core.device),
                 offset,
                 data,
             )?;
-        })
+        }
+        Ok(())
+    }
+
+    /// Write `data` starting `offset` bytes into the buffer. The offset must be aligned for `T`.
+    pub fn write_slice<T: Pod>(&mut self, offset: u64, data: &[T]) -> Result<()> {
+        self.check_range::<T>(offset, data.len())?;
+        self.write_bytes(offset, bytemuck::cast_slice(data))
+    }
+
+    /// Read into `data` starting `offset` bytes into the buffer. The offset must be aligned for
+    /// `T`.
+    pub fn read_slice<T: Pod>(&mut self, offset: u64, data: &mut [T]) -> Result<()> {
+        self.check_range::<T>(offset, data.len())?;
+        self.read_bytes(offset, bytemuck::cast_slice_mut(data))
+    }
+
+    /// Write element `index` of a buffer holding an array of `T`s
+    pub fn write_at_index<T: Pod>(&mut self, index: usize, value: &T) -> Result<()> {
+        let offset = (index * std::mem::size_of::<T>()) as u64;
+        self.write_slice(offset, std::slice::from_ref(value))
+    }
+
+    /// Whether the buffer's memory is host coherent. Non-coherent memory needs explicit flushes
+    /// and invalidations around host access, which `write_bytes()` and `read_bytes()` perform.
+    pub fn is_coherent(&self) -> bool {
+        self.memory
+            .as_ref()
+            .expect(USE_AFTER_FREE_MSG)
+            .props()
+            .contains(gpu_alloc::MemoryPropertyFlags::HOST_COHERENT)
+    }
+
+    /// Map the whole buffer, and keep it mapped until the buffer is dropped. Later calls return
+    /// the same mapping, and `write_bytes()` and `read_bytes()` use it instead of mapping the
+    /// memory for each access. The memory must be host visible (e.g. `UsageFlags::UPLOAD`).
+    pub fn map_persistent(&mut self) -> Result<MappedBuffer<'_>> {
+        if self.mapped.is_none() {
+            let memory = self.memory.as_mut().expect(USE_AFTER_FREE_MSG);
+            let size = memory.size() as usize;
+            let ptr = unsafe { memory.map(EMD::wrap(&self.core.device), 0, size)? };
+            self.mapped = Some(ptr);
+        }
+        Ok(MappedBuffer { buffer: self })
+    }
+
+    /// Size of the buffer's memory in bytes
+    pub fn size(&self) -> u64 {
+        self.memory.as_ref().expect(USE_AFTER_FREE_MSG).size()
+    }
+
+    fn check_range<T>(&self, offset: u64, len: usize) -> Result<()> {
+        let align = std::mem::align_of::<T>() as u64;
+        ensure!(
+            offset.is_multiple_of(align),
+            "Offset {} is not aligned to {} bytes for {}",
+            offset,
+            align,
+            std::any::type_name::<T>()
+        );
+        let end = offset + (len * std::mem::size_of::<T>()) as u64;
+        ensure!(
+            end <= self.size(),
+            "Access of bytes {}..{} is out of bounds of a {} byte buffer",
+            offset,
+            end,
+            self.size()
+        );
+        Ok(())
     }
 
     pub fn instance(&self) -> vk::Buffer {
         self.instance
     }
+
+    /// Fence signalled once the upload which filled this buffer has completed, if any
+    pub fn ready(&self) -> Option<&SharedFence> {
+        self.ready.as_ref()
+    }
+
+    /// Whether the contents uploaded by the `StagingBuffer` have arrived. Commands submitted
+    /// to `Core::queue` after the upload are already ordered after it and need not check this.
+    pub fn is_ready(&self) -> Result<bool> {
+        self.ready.as_ref().map_or(Ok(true), |ready| ready.is_signalled())
+    }
+
+    pub(crate) fn set_ready(&mut self, ready: SharedFence) {
+        self.ready = Some(ready);
+    }
+}
+
+/// A persistent mapping of a `ManagedBuffer`'s memory, see `ManagedBuffer::map_persistent()`.
+/// Writes through `bytes()` to non-coherent memory must be followed by `flush()`; the slice
+/// methods do this themselves. As with any host access, the GPU must not be using the range.
+pub struct MappedBuffer<'a> {
+    buffer: &'a mut ManagedBuffer,
+}
+
+impl MappedBuffer<'_> {
+    /// The whole buffer's memory
+    pub fn bytes(&mut self) -> &mut [u8] {
+        let ptr = self.buffer.mapped.expect("Buffer not mapped");
+        let len = self.buffer.size() as usize;
+        unsafe { std::slice::from_raw_parts_mut(ptr.as_ptr(), len) }
+    }
+
+    /// Write `data` starting `offset` bytes into the buffer and flush it
+    pub fn write_slice<T: Pod>(&mut self, offset: u64, data: &[T]) -> Result<()> {
+        self.buffer.write_slice(offset, data)
+    }
+
+    /// Read into `data` starting `offset` bytes into the buffer, invalidating it first
+    pub fn read_slice<T: Pod>(&mut self, offset: u64, data: &mut [T]) -> Result<()> {
+        self.buffer.read_slice(offset, data)
+    }
+
+    /// Make host writes to the range visible to the device. Does nothing for coherent memory.
+    pub fn flush(&self, offset: u64, size: u64) -> Result<()> {
+        if self.buffer.is_coherent() {
+            return Ok(());
+        }
+        let range = self.range(offset, size);
+        unsafe { self.buffer.core.device.flush_mapped_memory_ranges(&[range]) }.result()?;
+        Ok(())
+    }
+
+    /// Make device writes to the range visible to the host. Does nothing for coherent memory.
+    pub fn invalidate(&self, offset: u64, size: u64) -> Result<()> {
+        if self.buffer.is_coherent() {
+            return Ok(());
+        }
+        let range = self.range(offset, size);
+        unsafe {
+            self.buffer
+                .core
+                .device
+                .invalidate_mapped_memory_ranges(&[range])
+        }
+        .result()?;
+        Ok(())
+    }
+
+    /// Range of the device memory covering `offset..offset + size` of the buffer, widened to the
+    /// non-coherent atom size. If widening would pass the end of the allocation, which need not
+    /// be a multiple of the atom size, the range extends to the end of the device memory instead.
+    fn range(&self, offset: u64, size: u64) -> vk::MappedMemoryRangeBuilder<'static> {
+        let memory = self.buffer.memory.as_ref().expect(USE_AFTER_FREE_MSG);
+        let atom = self.buffer.core.device_properties.limits.non_coherent_atom_size;
+        let start = memory.offset() + offset;
+        let aligned_start = start / atom * atom;
+        let aligned_end = (start + size).div_ceil(atom) * atom;
+        let size = if aligned_end > memory.offset() + memory.size() {
+            vk::WHOLE_SIZE
+        } else {
+            aligned_end - aligned_start
+        };
+        vk::MappedMemoryRangeBuilder::new()
+            .memory(*memory.memory())
+            .offset(aligned_start)
+            .size(size)
+    }
 }
 
 impl ManagedImage {
@@ -99,35 +299,127 @@ impl ManagedImage {
                 .bind_image_memory(instance, *memory.memory(), memory.offset())
                 .result()?;
         }
+        core.resources.image_created(memory.size());
+        let subresource_range = vk::ImageSubresourceRangeBuilder::new()
+            .aspect_mask(aspect_mask(create_info.format))
+            .base_mip_level(0)
+            .level_count(create_info.mip_levels)
+            .base_array_layer(0)
+            .layer_count(create_info.array_layers)
+            .build();
         Ok(Self {
             core,
             instance,
             memory: Some(memory),
+            ready: None,
+            subresource_range,
+            layout: create_info.initial_layout,
+            stages: Stages::NONE_KHR,
         })
     }
 
+    /// Copy `data` into the memory through a transient mapping. Non-coherent memory is flushed
+    /// afterwards, so the write is visible to commands submitted after this returns.
     pub fn write_bytes(&mut self, offset: u64, data: &[u8]) -> Result<()> {
-        Ok(unsafe {
+        unsafe {
             self.memory
                 .as_mut()
                 .expect(USE_AFTER_FREE_MSG)
                 .write_bytes(EMD::wrap(&self.core.device), offset, data)?;
-        })
+        }
+        Ok(())
     }
 
+    /// Copy from the memory through a transient mapping. Non-coherent memory is invalidated
+    /// first, so writes by completed commands are visible.
     pub fn read_bytes(&mut self, offset: u64, data: &mut [u8]) -> Result<()> {
-        Ok(unsafe {
+        unsafe {
             self.memory.as_mut().expect(USE_AFTER_FREE_MSG).read_bytes(
                 EMD::wrap(&self.core.device),
                 offset,
                 data,
             )?;
-        })
+        }
+        Ok(())
     }
 
     pub fn instance(&self) -> vk::Image {
         self.instance
     }
+
+    /// The whole image
+    pub fn subresource_range(&self) -> vk::ImageSubresourceRange {
+        self.subresource_range
+    }
+
+    /// Current layout of the whole image, as of the last recorded `transition()`
+    pub fn layout(&self) -> vk::ImageLayout {
+        self.layout
+    }
+
+    /// Record a barrier moving the whole image into `new_layout`, for use in `stages`. The
+    /// barrier waits on the stages given to the previous transition.
+    pub fn transition(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        new_layout: vk::ImageLayout,
+        stages: Stages,
+    ) {
+        let src_access = match self.layout {
+            // Contents are discarded
+            vk::ImageLayout::UNDEFINED => Access::NONE_KHR,
+            layout => sync2::layout_usage(layout).1,
+        };
+        let barrier = ImageBarrier {
+            image: self.instance,
+            old_layout: self.layout,
+            new_layout,
+            subresource_range: self.subresource_range,
+            src_stages: self.stages,
+            src_access,
+            dst_stages: stages,
+            dst_access: sync2::layout_usage(new_layout).1,
+        };
+        self.core.cmd_barriers(command_buffer, &[], &[barrier]);
+        self.set_layout(new_layout, stages);
+    }
+
+    /// Record a layout change made outside of `transition()`, such as by a render pass
+    pub fn set_layout(&mut self, layout: vk::ImageLayout, stages: Stages) {
+        self.layout = layout;
+        self.stages = stages;
+    }
+
+    /// Fence signalled once the upload which filled this image has completed, if any
+    pub fn ready(&self) -> Option<&SharedFence> {
+        self.ready.as_ref()
+    }
+
+    /// Whether the contents uploaded by the `StagingBuffer` have arrived. Commands submitted
+    /// to `Core::queue` after the upload are already ordered after it and need not check this.
+    pub fn is_ready(&self) -> Result<bool> {
+        self.ready.as_ref().map_or(Ok(true), |ready| ready.is_signalled())
+    }
+
+    pub(crate) fn set_ready(&mut self, ready: SharedFence) {
+        self.ready = Some(ready);
+    }
+}
+
+/// Aspects of an image with the given format
+pub fn aspect_mask(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM | vk::Format::X8_D24_UNORM_PACK32 | vk::Format::D32_SFLOAT => {
+            vk::ImageAspectFlags::DEPTH
+        }
+        vk::Format::D16_UNORM_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::D32_SFLOAT_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        }
+        vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+        _ => vk::ImageAspectFlags::COLOR,
+    }
 }
 
 /// Calculate image memory requirements for gpu_alloc
@@ -161,25 +453,18 @@ pub fn request_from_usage_requirements(
 
 impl Drop for ManagedImage {
     fn drop(&mut self) {
-        unsafe {
-            self.core.device.queue_wait_idle(self.core.queue).unwrap(); // TODO: Drop without queue wait?
-            self.core.device.destroy_image(Some(self.instance), None);
-            self.core
-                .deallocate(self.memory.take().expect("Double free of image memory"))
-                .unwrap();
-        }
+        let memory = self.memory.take().expect("Double free of image memory");
+        self.core.defer_destroy(Garbage::Image(self.instance, memory));
     }
 }
 
 impl Drop for ManagedBuffer {
     fn drop(&mut self) {
-        unsafe {
-            self.core.device.queue_wait_idle(self.core.queue).unwrap(); // TODO: Drop without queue wait?
-            self.core.device.destroy_buffer(Some(self.instance), None);
-            self.core
-                .deallocate(self.memory.take().expect("Double free of image memory"))
-                .unwrap();
+        let mut memory = self.memory.take().expect("Double free of buffer memory");
+        if self.mapped.take().is_some() {
+            unsafe { memory.unmap(EMD::wrap(&self.core.device)) };
         }
+        self.core.defer_destroy(Garbage::Buffer(self.instance, memory));
     }
 }
 
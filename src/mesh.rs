@@ -1,37 +1,236 @@
-use crate::{memory::ManagedBuffer, staging_buffer::StagingBuffer, vertex::Vertex};
+use crate::{
+    memory::ManagedBuffer,
+    staging_buffer::StagingBuffer,
+    vertex::{VertexLayout, VertexNT, VertexTangent},
+};
 use crate::Core;
-use anyhow::Result;
+use anyhow::{ensure, Context, Result};
 use erupt::vk;
+use std::io::Write;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
 
-pub fn upload_mesh(
+/// Upload a mesh made of any vertex type implementing `VertexLayout`
+pub fn upload_mesh<V: VertexLayout>(
     staging: &mut StagingBuffer,
     command_buffer: vk::CommandBuffer,
-    vertices: &[Vertex],
+    vertices: &[V],
     indices: &[u32],
 ) -> Result<ManagedMesh> {
+    let n_vertices = vertices.len() as u32;
     let n_indices = indices.len() as u32;
+    let bounds = vertex_positions(vertices).and_then(Bounds::from_positions);
 
+    // Transfer source for `ManagedMesh::download()`
     let vertices = staging.upload_buffer_pod(
         command_buffer,
-        vk::BufferUsageFlags::VERTEX_BUFFER,
-        &vertices,
+        vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::TRANSFER_SRC,
+        vertices,
+    )?;
+    let indices = staging.upload_buffer_pod(
+        command_buffer,
+        vk::BufferUsageFlags::INDEX_BUFFER | vk::BufferUsageFlags::TRANSFER_SRC,
+        indices,
     )?;
-    let indices =
-        staging.upload_buffer_pod(command_buffer, vk::BufferUsageFlags::INDEX_BUFFER, &indices)?;
     Ok(ManagedMesh {
         vertices,
         indices,
+        n_vertices,
         n_indices,
+        bounds,
     })
 }
 
 pub struct ManagedMesh {
     pub vertices: ManagedBuffer,
     pub indices: ManagedBuffer,
+    pub n_vertices: u32,
     pub n_indices: u32,
+    /// Bounds of the vertices in model space. None if there are none, or if the vertex type has
+    /// no position (see `vertex_positions()`).
+    pub bounds: Option<Bounds>,
+}
+
+impl ManagedMesh {
+    /// Whether the vertex and index uploads have completed. See `ManagedBuffer::is_ready()`.
+    pub fn is_ready(&self) -> Result<bool> {
+        Ok(self.vertices.is_ready()? && self.indices.is_ready()?)
+    }
+
+    /// Read the vertices (of the type the mesh was uploaded with) and indices back from the GPU,
+    /// e.g. after a compute pass wrote them. Blocks; see `StagingBuffer::download_buffer()`.
+    /// Warning: Assumes an inactive command buffer
+    pub fn download<V: VertexLayout>(
+        &self,
+        staging: &mut StagingBuffer,
+        command_buffer: vk::CommandBuffer,
+    ) -> Result<(Vec<V>, Vec<u32>)> {
+        let mut vertices = vec![V::zeroed(); self.n_vertices as usize];
+        let mut indices = vec![0u32; self.n_indices as usize];
+        staging.download_buffer(
+            command_buffer,
+            self.vertices.instance(),
+            0,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            bytemuck::cast_slice_mut(&mut vertices),
+        )?;
+        staging.download_buffer(
+            command_buffer,
+            self.indices.instance(),
+            0,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            bytemuck::cast_slice_mut(&mut indices),
+        )?;
+        Ok((vertices, indices))
+    }
+}
+
+/// Save a triangle list as a Wavefront OBJ file, e.g. to open generated geometry in other tools.
+/// Positions are read as in `vertex_positions()`; normals and texture coordinates are written too
+/// if the vertex type has them at locations 2 and 3, as the types in `vertex` do.
+pub fn export_obj<V: VertexLayout>(
+    path: impl AsRef<Path>,
+    vertices: &[V],
+    indices: &[u32],
+) -> Result<()> {
+    let path = path.as_ref();
+    let positions =
+        vertex_positions(vertices).context("Vertex type has no position at location 0")?;
+    let normals = read_attribute::<V, 3>(vertices, 2, vk::Format::R32G32B32_SFLOAT);
+    let uvs = read_attribute::<V, 2>(vertices, 3, vk::Format::R32G32_SFLOAT);
+
+    let file = std::fs::File::create(path)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut obj = std::io::BufWriter::new(file);
+    writeln!(obj, "# Exported by {}", crate::ENGINE_NAME)?;
+    for [x, y, z] in &positions {
+        writeln!(obj, "v {} {} {}", x, y, z)?;
+    }
+    for [u, v] in uvs.iter().flatten() {
+        // OBJ puts the origin of texture coordinates at the bottom left
+        writeln!(obj, "vt {} {}", u, 1.0 - v)?;
+    }
+    for [x, y, z] in normals.iter().flatten() {
+        writeln!(obj, "vn {} {} {}", x, y, z)?;
+    }
+
+    // Indices are 1-based
+    let corner = |i: u32| match (uvs.is_some(), normals.is_some()) {
+        (false, false) => format!("{}", i + 1),
+        (true, false) => format!("{0}/{0}", i + 1),
+        (false, true) => format!("{0}//{0}", i + 1),
+        (true, true) => format!("{0}/{0}/{0}", i + 1),
+    };
+    for tri in indices.chunks_exact(3) {
+        let (a, b, c) = (corner(tri[0]), corner(tri[1]), corner(tri[2]));
+        writeln!(obj, "f {} {} {}", a, b, c)?;
+    }
+    obj.flush()?;
+    Ok(())
+}
+
+/// A mesh shared by everything drawing it, whose buffers may be replaced at any time, such as
+/// once a higher detail version has streamed in. Draws recorded afterwards use the new mesh;
+/// frames in flight keep the old buffers alive until they complete, as dropped buffers are
+/// destroyed once the GPU is done with them. Command buffers recorded once and replayed (see
+/// `StarterKit::submit_prerecorded()`) must be re-recorded when `version()` changes.
+///
+/// ```ignore
+/// let rock = MeshHandle::new(upload_mesh(staging, command_buffer, &low_detail, &indices)?);
+/// // While drawing
+/// starter_kit.draw(&cmd, &rock, &material, &transform);
+/// // Once the detailed mesh has loaded
+/// rock.replace(detailed);
+/// ```
+#[derive(Clone)]
+pub struct MeshHandle(Arc<MeshSlot>);
+
+struct MeshSlot {
+    mesh: RwLock<Arc<ManagedMesh>>,
+    version: AtomicU64,
+}
+
+impl MeshHandle {
+    pub fn new(mesh: ManagedMesh) -> Self {
+        Self(Arc::new(MeshSlot {
+            mesh: RwLock::new(Arc::new(mesh)),
+            version: AtomicU64::new(0),
+        }))
+    }
+
+    /// The current mesh
+    pub fn get(&self) -> Arc<ManagedMesh> {
+        self.0
+            .mesh
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Draw `mesh` from now on, for every clone of this handle, returning the previous mesh
+    pub fn replace(&self, mesh: ManagedMesh) -> Arc<ManagedMesh> {
+        let mut current = self.0.mesh.write().unwrap_or_else(PoisonError::into_inner);
+        self.0.version.fetch_add(1, Ordering::Relaxed);
+        std::mem::replace(&mut *current, Arc::new(mesh))
+    }
+
+    /// Number of times the mesh has been replaced
+    pub fn version(&self) -> u64 {
+        self.0.version.load(Ordering::Relaxed)
+    }
+}
+
+/// Meshes accepted by the draw helpers: a `ManagedMesh`, the current mesh of a `MeshHandle`, or
+/// a level of a `LodMesh`
+pub trait MeshSource {
+    /// Call `f` with the mesh to draw
+    fn with_mesh(&self, f: &mut dyn FnMut(&ManagedMesh));
+
+    /// Range of the mesh's index buffer to draw, or None for all of it
+    fn index_range(&self) -> Option<Range<u32>> {
+        None
+    }
+}
+
+impl MeshSource for ManagedMesh {
+    fn with_mesh(&self, f: &mut dyn FnMut(&ManagedMesh)) {
+        f(self)
+    }
+}
+
+impl MeshSource for Arc<ManagedMesh> {
+    fn with_mesh(&self, f: &mut dyn FnMut(&ManagedMesh)) {
+        f(self)
+    }
+}
+
+impl MeshSource for MeshHandle {
+    fn with_mesh(&self, f: &mut dyn FnMut(&ManagedMesh)) {
+        f(&self.get())
+    }
 }
 
-pub fn draw_mesh(core: &Core, command_buffer: vk::CommandBuffer, mesh: &ManagedMesh) {
+/// Bind the vertex and index buffers of `mesh`, and draw all of its indices
+pub fn draw_mesh(
+    core: &Core,
+    command_buffer: vk::CommandBuffer,
+    mesh: &(impl MeshSource + ?Sized),
+) {
+    let range = mesh.index_range();
+    mesh.with_mesh(&mut |mesh| {
+        let indices = range.clone().unwrap_or(0..mesh.n_indices);
+        draw_managed_mesh(core, command_buffer, mesh, indices)
+    });
+}
+
+fn draw_managed_mesh(
+    core: &Core,
+    command_buffer: vk::CommandBuffer,
+    mesh: &ManagedMesh,
+    indices: Range<u32>,
+) {
     unsafe {
         core.device.cmd_bind_vertex_buffers(
             command_buffer,
@@ -45,6 +244,374 @@ pub fn draw_mesh(core: &Core, command_buffer: vk::CommandBuffer, mesh: &ManagedM
             0,
             vk::IndexType::UINT32,
         );
-        core.device.cmd_draw_indexed(command_buffer, mesh.n_indices, 1, 0, 0, 0);
+        core.device
+            .cmd_draw_indexed(command_buffer, indices.len() as u32, 1, indices.start, 0, 0);
+    }
+}
+
+/// A `ManagedMesh` which keeps the vertices and indices it was uploaded from, for picking,
+/// physics or export without reading them back from the GPU
+pub struct RetainedMesh<V> {
+    pub mesh: ManagedMesh,
+    pub vertices: Vec<V>,
+    pub indices: Vec<u32>,
+}
+
+impl<V: VertexLayout> RetainedMesh<V> {
+    /// Vertex positions, see `vertex_positions()`
+    pub fn positions(&self) -> Option<Vec<[f32; 3]>> {
+        vertex_positions(&self.vertices)
+    }
+
+    /// Nearest hit of `ray` (in model space) on the mesh's triangles. Only tests the triangles
+    /// if the ray hits the bounding box.
+    #[cfg(feature = "nalgebra")]
+    pub fn intersect(&self, ray: &crate::ray::Ray) -> Option<f32> {
+        ray.intersect_aabb(&self.mesh.bounds?.aabb())?;
+        ray.intersect_mesh(&self.positions()?, &self.indices)
+    }
+}
+
+/// Upload a mesh like `upload_mesh()`, keeping the CPU copy of its data
+pub fn upload_mesh_retained<V: VertexLayout>(
+    staging: &mut StagingBuffer,
+    command_buffer: vk::CommandBuffer,
+    vertices: Vec<V>,
+    indices: Vec<u32>,
+) -> Result<RetainedMesh<V>> {
+    let mesh = upload_mesh(staging, command_buffer, &vertices, &indices)?;
+    Ok(RetainedMesh {
+        mesh,
+        vertices,
+        indices,
+    })
+}
+
+/// Axis-aligned bounding box and bounding sphere of a set of points
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Bounds {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    /// Center of the bounding sphere, which is the center of the box
+    pub center: [f32; 3],
+    pub radius: f32,
+}
+
+impl Bounds {
+    /// Bounds of `positions`, or None if there are none
+    pub fn from_positions(positions: impl IntoIterator<Item = [f32; 3]>) -> Option<Self> {
+        let positions: Vec<[f32; 3]> = positions.into_iter().collect();
+        let first = *positions.first()?;
+        let (min, max) = positions.iter().fold((first, first), |(min, max), p| {
+            (
+                [min[0].min(p[0]), min[1].min(p[1]), min[2].min(p[2])],
+                [max[0].max(p[0]), max[1].max(p[1]), max[2].max(p[2])],
+            )
+        });
+        let center = scale(add(min, max), 0.5);
+        // Tighter than half the box's diagonal
+        let radius = positions
+            .iter()
+            .map(|&p| {
+                let d = sub(p, center);
+                dot(d, d)
+            })
+            .fold(0.0, f32::max)
+            .sqrt();
+        Some(Self {
+            min,
+            max,
+            center,
+            radius,
+        })
+    }
+
+    /// The bounding box, e.g. for `Ray::intersect_aabb()`
+    #[cfg(feature = "nalgebra")]
+    pub fn aabb(&self) -> crate::ray::Aabb {
+        crate::ray::Aabb::new(self.min.into(), self.max.into())
+    }
+
+    /// `screen_coverage()` of the bounding sphere seen from `eye`, in the same space as the
+    /// bounds, by a camera with vertical field of view `fovy` (in radians)
+    pub fn screen_coverage(&self, eye: [f32; 3], fovy: f32) -> f32 {
+        let d = sub(self.center, eye);
+        screen_coverage(self.radius, dot(d, d).sqrt(), fovy)
     }
+
+    /// Distance from the center at which a camera with vertical field of view `fovy` (in
+    /// radians) just fits the bounding sphere in view, e.g. for `ArcBall::frame()`
+    pub fn framing_distance(&self, fovy: f32) -> f32 {
+        self.radius / (fovy / 2.0).sin()
+    }
+}
+
+/// Positions of `vertices`, read from their attribute at location 0 if it is an
+/// `R32G32B32_SFLOAT` (as for all of the vertex types in `vertex`). None otherwise.
+pub fn vertex_positions<V: VertexLayout>(vertices: &[V]) -> Option<Vec<[f32; 3]>> {
+    read_attribute(vertices, 0, vk::Format::R32G32B32_SFLOAT)
+}
+
+/// Read the `N` floats of the attribute at `location` from each vertex, if it has `format`
+fn read_attribute<V: VertexLayout, const N: usize>(
+    vertices: &[V],
+    location: u32,
+    format: vk::Format,
+) -> Option<Vec<[f32; N]>> {
+    let offset = V::attribute_descriptions()
+        .into_iter()
+        .find(|attr| attr.location == location && attr.format == format)?
+        .offset as usize;
+    let values = vertices
+        .iter()
+        .map(|vertex| {
+            let bytes = &bytemuck::bytes_of(vertex)[offset..][..N * 4];
+            let mut value = [0.0; N];
+            for (component, bytes) in value.iter_mut().zip(bytes.chunks_exact(4)) {
+                let mut ne = [0; 4];
+                ne.copy_from_slice(bytes);
+                *component = f32::from_ne_bytes(ne);
+            }
+            value
+        })
+        .collect();
+    Some(values)
+}
+
+/// One level of detail of a `LodMesh`, as a range of its index buffer
+#[derive(Copy, Clone, Debug)]
+pub struct LodLevel {
+    pub first_index: u32,
+    pub n_indices: u32,
+    /// Smallest `screen_coverage()` this level is drawn at
+    pub min_coverage: f32,
+}
+
+impl LodLevel {
+    fn indices(&self) -> Range<u32> {
+        self.first_index..self.first_index + self.n_indices
+    }
+}
+
+/// A mesh with several levels of detail, finest first, whose index ranges share one vertex
+/// buffer. Draw a level with `draw_lod()`, or pass `level()` to the other draw helpers; as a
+/// `MeshSource` itself, the finest level is drawn.
+pub struct LodMesh {
+    pub mesh: ManagedMesh,
+    levels: Vec<LodLevel>,
+}
+
+/// One level of a `LodMesh`, to be drawn through `MeshSource`
+#[derive(Copy, Clone)]
+pub struct LodMeshLevel<'a> {
+    mesh: &'a LodMesh,
+    level: usize,
+}
+
+impl LodMesh {
+    /// Levels of detail, finest first. Never empty.
+    pub fn levels(&self) -> &[LodLevel] {
+        &self.levels
+    }
+
+    /// Draw `level` through `MeshSource`. Panics if out of range.
+    pub fn level(&self, level: usize) -> LodMeshLevel<'_> {
+        assert!(level < self.levels.len(), "No level of detail {}", level);
+        LodMeshLevel { mesh: self, level }
+    }
+
+    /// The finest level whose `min_coverage` is at most `coverage`, or the coarsest level
+    pub fn select(&self, coverage: f32) -> usize {
+        self.levels
+            .iter()
+            .position(|level| coverage >= level.min_coverage)
+            .unwrap_or(self.levels.len() - 1)
+    }
+
+    /// The level to draw a mesh whose bounding sphere has `radius` at `distance` from a camera
+    /// with vertical field of view `fovy` (in radians)
+    pub fn select_by_distance(&self, radius: f32, distance: f32, fovy: f32) -> usize {
+        self.select(screen_coverage(radius, distance, fovy))
+    }
+}
+
+impl MeshSource for LodMesh {
+    fn with_mesh(&self, f: &mut dyn FnMut(&ManagedMesh)) {
+        f(&self.mesh)
+    }
+
+    fn index_range(&self) -> Option<Range<u32>> {
+        Some(self.levels[0].indices())
+    }
+}
+
+impl MeshSource for LodMeshLevel<'_> {
+    fn with_mesh(&self, f: &mut dyn FnMut(&ManagedMesh)) {
+        f(&self.mesh.mesh)
+    }
+
+    fn index_range(&self) -> Option<Range<u32>> {
+        Some(self.mesh.levels[self.level].indices())
+    }
+}
+
+/// Fraction of the screen's height covered by a sphere of `radius` at `distance` from a camera
+/// with vertical field of view `fovy` (in radians), from 0 to 1
+pub fn screen_coverage(radius: f32, distance: f32, fovy: f32) -> f32 {
+    if distance <= radius {
+        return 1.0;
+    }
+    (radius / (distance * (fovy / 2.0).tan())).min(1.0)
+}
+
+/// Upload a mesh with levels of detail, each given as its indices into `vertices` and the
+/// smallest screen coverage it is drawn at. Levels are ordered finest first; there must be at
+/// least one.
+pub fn upload_lod_mesh<V: VertexLayout>(
+    staging: &mut StagingBuffer,
+    command_buffer: vk::CommandBuffer,
+    vertices: &[V],
+    levels: &[(&[u32], f32)],
+) -> Result<LodMesh> {
+    ensure!(!levels.is_empty(), "A LodMesh needs at least one level");
+    let mut indices = vec![];
+    let levels = levels
+        .iter()
+        .map(|&(level_indices, min_coverage)| {
+            let first_index = indices.len() as u32;
+            indices.extend_from_slice(level_indices);
+            LodLevel {
+                first_index,
+                n_indices: level_indices.len() as u32,
+                min_coverage,
+            }
+        })
+        .collect();
+    let mesh = upload_mesh(staging, command_buffer, vertices, &indices)?;
+    Ok(LodMesh { mesh, levels })
+}
+
+/// Draw one level of a `LodMesh`
+pub fn draw_lod(core: &Core, command_buffer: vk::CommandBuffer, mesh: &LodMesh, level: usize) {
+    let indices = mesh.levels[level].indices();
+    draw_managed_mesh(core, command_buffer, &mesh.mesh, indices);
+}
+
+/// Generate per-vertex tangents for a triangle list, for use with normal maps. Tangents are
+/// accumulated per-triangle from the uv gradients (weighted by triangle area), then
+/// orthogonalized against each vertex's normal. Degenerate uvs fall back to an arbitrary tangent
+/// perpendicular to the normal, and a zero normal to an arbitrary unit tangent.
+pub fn generate_tangents(vertices: &[VertexNT], indices: &[u32]) -> Vec<VertexTangent> {
+    let mut tangents = vec![[0.0f32; 3]; vertices.len()];
+    let mut bitangents = vec![[0.0f32; 3]; vertices.len()];
+
+    for tri in indices.chunks_exact(3) {
+        let [ia, ib, ic] = [tri[0] as usize, tri[1] as usize, tri[2] as usize];
+        let (va, vb, vc) = (&vertices[ia], &vertices[ib], &vertices[ic]);
+
+        let e1 = sub(vb.pos, va.pos);
+        let e2 = sub(vc.pos, va.pos);
+        let (du1, dv1) = (vb.uv[0] - va.uv[0], vb.uv[1] - va.uv[1]);
+        let (du2, dv2) = (vc.uv[0] - va.uv[0], vc.uv[1] - va.uv[1]);
+
+        // The sine of the angle between the uv edges, so that small uv islands aren't mistaken
+        // for degenerate ones
+        let det = du1 * dv2 - du2 * dv1;
+        if det.abs() <= MIN_UV_SINE * du1.hypot(dv1) * du2.hypot(dv2) {
+            continue;
+        }
+        let r = 1.0 / det;
+
+        let t = scale(sub(scale(e1, dv2), scale(e2, dv1)), r);
+        let b = scale(sub(scale(e2, du1), scale(e1, du2)), r);
+
+        for &i in &[ia, ib, ic] {
+            tangents[i] = add(tangents[i], t);
+            bitangents[i] = add(bitangents[i], b);
+        }
+    }
+
+    vertices
+        .iter()
+        .zip(tangents.iter().zip(&bitangents))
+        .map(|(v, (&t, &b))| {
+            let tangent = if dot(v.normal, v.normal) > 0.0 {
+                orthonormal_tangent(normalize(v.normal), t, b)
+            } else {
+                // Any unit vector will do for a zero normal
+                [1.0, 0.0, 0.0, 1.0]
+            };
+            VertexTangent {
+                pos: v.pos,
+                normal: v.normal,
+                uv: v.uv,
+                color: v.color,
+                tangent,
+            }
+        })
+        .collect()
+}
+
+/// Unit tangent perpendicular to the unit normal `n`, from the accumulated tangent `t` and
+/// bitangent `b`, with the handedness in w
+fn orthonormal_tangent(n: [f32; 3], t: [f32; 3], b: [f32; 3]) -> [f32; 4] {
+    // Gram-Schmidt orthogonalize. What remains is compared with the accumulated tangent, whose
+    // length scales with the mesh.
+    let mut tangent = sub(t, scale(n, dot(n, t)));
+    if dot(tangent, tangent) <= MIN_TANGENT_RATIO * dot(t, t) {
+        tangent = any_perpendicular(n);
+    }
+    let tangent = normalize(tangent);
+
+    // Handedness
+    let w = if dot(cross(n, tangent), b) < 0.0 {
+        -1.0
+    } else {
+        1.0
+    };
+    [tangent[0], tangent[1], tangent[2], w]
+}
+
+/// Smallest sine of the angle between a triangle's uv edges for its uvs to be used
+const MIN_UV_SINE: f32 = 1e-6;
+
+/// Smallest squared length of an orthogonalized tangent relative to the accumulated one, below
+/// which the tangent was (nearly) parallel to the normal
+const MIN_TANGENT_RATIO: f32 = 1e-6;
+
+fn any_perpendicular(n: [f32; 3]) -> [f32; 3] {
+    let axis = if n[0].abs() < 0.9 {
+        [1.0, 0.0, 0.0]
+    } else {
+        [0.0, 1.0, 0.0]
+    };
+    cross(n, axis)
+}
+
+fn add(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn scale(a: [f32; 3], s: f32) -> [f32; 3] {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(a: [f32; 3]) -> [f32; 3] {
+    scale(a, 1.0 / dot(a, a).sqrt())
 }
@@ -0,0 +1,215 @@
+//! Many meshes packed into a few large vertex and index buffers, rather than two allocations
+//! each. Meshes in the same block share their buffers, so drawing them only binds once and
+//! picks each mesh with `first_index` and `vertex_offset`.
+//!
+//! ```ignore
+//! let mut arena = MeshArena::<Vertex>::new(core.clone(), 65536, 3 * 65536);
+//! let meshes = shapes
+//!     .iter()
+//!     .map(|(vertices, indices)| {
+//!         arena.upload(&mut starter_kit.staging_buffer, command_buffer, vertices, indices)
+//!     })
+//!     .collect::<Result<Vec<_>>>()?;
+//! // While drawing
+//! arena.draw(command_buffer, meshes.iter());
+//! ```
+use crate::memory::{ManagedBuffer, UsageFlags};
+use crate::staging_buffer::StagingBuffer;
+use crate::synchronization::SharedFence;
+use crate::vertex::VertexLayout;
+use crate::SharedCore;
+use anyhow::{Context, Result};
+use erupt::vk;
+use std::marker::PhantomData;
+
+/// A mesh in a `MeshArena`
+#[derive(Clone)]
+pub struct ArenaMesh {
+    /// Block of the arena holding the mesh
+    pub block: usize,
+    pub first_index: u32,
+    pub n_indices: u32,
+    /// Added to each index, see `vkCmdDrawIndexed`
+    pub vertex_offset: i32,
+    ready: Option<SharedFence>,
+}
+
+impl ArenaMesh {
+    /// Whether the mesh's upload has completed. See `ManagedBuffer::is_ready()`.
+    pub fn is_ready(&self) -> Result<bool> {
+        self.ready
+            .as_ref()
+            .map_or(Ok(true), |ready| ready.is_signalled())
+    }
+}
+
+struct Block {
+    vertices: ManagedBuffer,
+    indices: ManagedBuffer,
+    vertex_capacity: u32,
+    index_capacity: u32,
+    used_vertices: u32,
+    used_indices: u32,
+}
+
+/// Vertex and index buffers meshes of vertex type `V` are suballocated from. Space is only
+/// reclaimed when the arena is dropped.
+pub struct MeshArena<V> {
+    blocks: Vec<Block>,
+    block_vertices: u32,
+    block_indices: u32,
+    core: SharedCore,
+    _phantom: PhantomData<V>,
+}
+
+impl<V: VertexLayout> MeshArena<V> {
+    /// An arena allocating blocks of `block_vertices` vertices and `block_indices` indices as
+    /// needed. Meshes larger than that get a block of their own.
+    pub fn new(core: SharedCore, block_vertices: u32, block_indices: u32) -> Self {
+        Self {
+            blocks: vec![],
+            block_vertices,
+            block_indices,
+            core,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Upload a mesh into the first block with room for it
+    /// Warning: Assumes an inactive command buffer, which must not be reset until the upload is
+    /// complete
+    pub fn upload(
+        &mut self,
+        staging: &mut StagingBuffer,
+        command_buffer: vk::CommandBuffer,
+        vertices: &[V],
+        indices: &[u32],
+    ) -> Result<ArenaMesh> {
+        let n_vertices = vertices.len() as u32;
+        let n_indices = indices.len() as u32;
+        let block_idx = match self.blocks.iter().position(|block| {
+            block.used_vertices + n_vertices <= block.vertex_capacity
+                && block.used_indices + n_indices <= block.index_capacity
+        }) {
+            Some(idx) => idx,
+            None => {
+                let block = self.create_block(
+                    n_vertices.max(self.block_vertices),
+                    n_indices.max(self.block_indices),
+                )?;
+                self.blocks.push(block);
+                self.blocks.len() - 1
+            }
+        };
+
+        let block = &mut self.blocks[block_idx];
+        let vertex_offset = block.used_vertices;
+        let first_index = block.used_indices;
+
+        staging.upload_into_buffer(
+            command_buffer,
+            block.vertices.instance(),
+            (vertex_offset as usize * std::mem::size_of::<V>()) as u64,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            bytemuck::cast_slice(vertices),
+        )?;
+        let ready = staging.upload_into_buffer(
+            command_buffer,
+            block.indices.instance(),
+            (first_index as usize * std::mem::size_of::<u32>()) as u64,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            bytemuck::cast_slice(indices),
+        )?;
+
+        block.used_vertices += n_vertices;
+        block.used_indices += n_indices;
+
+        Ok(ArenaMesh {
+            block: block_idx,
+            first_index,
+            n_indices,
+            vertex_offset: vertex_offset as i32,
+            ready,
+        })
+    }
+
+    /// Number of blocks allocated so far
+    pub fn blocks(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Bind the vertex and index buffers of a block
+    pub fn bind(&self, command_buffer: vk::CommandBuffer, block: usize) {
+        let block = &self.blocks[block];
+        unsafe {
+            self.core.device.cmd_bind_vertex_buffers(
+                command_buffer,
+                0,
+                &[block.vertices.instance()],
+                &[0],
+            );
+            self.core.device.cmd_bind_index_buffer(
+                command_buffer,
+                block.indices.instance(),
+                0,
+                vk::IndexType::UINT32,
+            );
+        }
+    }
+
+    /// Draw a mesh whose block is bound
+    pub fn draw_bound(&self, command_buffer: vk::CommandBuffer, mesh: &ArenaMesh) {
+        unsafe {
+            self.core.device.cmd_draw_indexed(
+                command_buffer,
+                mesh.n_indices,
+                1,
+                mesh.first_index,
+                mesh.vertex_offset,
+                0,
+            );
+        }
+    }
+
+    /// Draw meshes with the bound pipeline, binding blocks as they change. Sort meshes by block
+    /// to bind each only once.
+    pub fn draw<'a>(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        meshes: impl IntoIterator<Item = &'a ArenaMesh>,
+    ) {
+        let mut bound = None;
+        for mesh in meshes {
+            if bound != Some(mesh.block) {
+                self.bind(command_buffer, mesh.block);
+                bound = Some(mesh.block);
+            }
+            self.draw_bound(command_buffer, mesh);
+        }
+    }
+
+    fn create_block(&self, vertex_capacity: u32, index_capacity: u32) -> Result<Block> {
+        let buffer = |size: usize, usage: vk::BufferUsageFlags| {
+            let ci = vk::BufferCreateInfoBuilder::new()
+                .size(size as u64)
+                .usage(usage | vk::BufferUsageFlags::TRANSFER_DST)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE);
+            ManagedBuffer::new(self.core.clone(), ci, UsageFlags::FAST_DEVICE_ACCESS)
+                .context("Failed to allocate mesh arena block")
+        };
+        Ok(Block {
+            vertices: buffer(
+                vertex_capacity as usize * std::mem::size_of::<V>(),
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+            )?,
+            indices: buffer(
+                index_capacity as usize * std::mem::size_of::<u32>(),
+                vk::BufferUsageFlags::INDEX_BUFFER,
+            )?,
+            vertex_capacity,
+            index_capacity,
+            used_vertices: 0,
+            used_indices: 0,
+        })
+    }
+}
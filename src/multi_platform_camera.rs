@@ -1,3 +1,4 @@
+use crate::arcball::ArcBall;
 use crate::mainloop::{Platform, PlatformEvent, PlatformReturn};
 use crate::winit_arcball::WinitArcBall;
 use anyhow::Result;
@@ -5,7 +6,45 @@ use anyhow::Result;
 #[cfg(feature = "openxr")]
 use crate::xr_camera;
 
-pub enum MultiPlatformCamera {
+/// Clipping planes used in VR when `CameraSettings::clipping` is None
+pub const XR_CLIPPING: (f32, f32) = (0.01, 1000.0);
+
+/// Projection settings shared by both platforms
+#[derive(Copy, Clone, Debug)]
+pub struct CameraSettings {
+    /// Near and far clipping planes, in world units. None uses each platform's default: those of
+    /// `ArcBall::default()` on the desktop, and `XR_CLIPPING` in VR.
+    pub clipping: Option<(f32, f32)>,
+    /// World units per meter of real space. In VR, above 1 the user moves through the world as
+    /// if they were that many times larger. On the desktop, scales the stereo preview's eye
+    /// separation.
+    pub world_scale: f32,
+    /// Use reverse-Z projections, for pipelines built with `PipelineSettings::reverse_z`
+    pub reverse_z: bool,
+    /// Fixed width over height of the desktop projection, matching the starter kit's
+    /// `Settings::aspect_ratio`. VR always uses each eye's field of view.
+    pub aspect_ratio: Option<f32>,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            clipping: None,
+            world_scale: 1.0,
+            reverse_z: false,
+            aspect_ratio: None,
+        }
+    }
+}
+
+pub struct MultiPlatformCamera {
+    settings: CameraSettings,
+    /// Eye separation of the stereo preview, in meters
+    stereo_ipd: Option<f32>,
+    inner: Inner,
+}
+
+enum Inner {
     Winit(WinitArcBall),
     #[cfg(feature = "openxr")]
     OpenXr,
@@ -16,41 +55,78 @@ const PLATFORM_WARNING: &str =
 
 impl MultiPlatformCamera {
     pub fn new(platform: &mut Platform<'_>) -> Self {
-        match platform {
+        Self::with_settings(platform, CameraSettings::default())
+    }
+
+    pub fn with_settings(platform: &mut Platform<'_>, settings: CameraSettings) -> Self {
+        let mut camera = Self {
+            settings,
+            stereo_ipd: None,
+            inner: Inner::new(platform),
+        };
+        camera.apply_settings();
+        camera
+    }
+
+    pub fn settings(&self) -> CameraSettings {
+        self.settings
+    }
+
+    /// Change the settings, taking effect from the next `get_matrices()`
+    pub fn set_settings(&mut self, settings: CameraSettings) {
+        self.settings = settings;
+        self.apply_settings();
+    }
+
+    /// The desktop camera, or None in VR
+    pub fn winit_arcball_mut(&mut self) -> Option<&mut WinitArcBall> {
+        match &mut self.inner {
+            Inner::Winit(winit_arcball) => Some(winit_arcball),
             #[cfg(feature = "openxr")]
-            Platform::OpenXr { .. } => Self::OpenXr,
-            Platform::Winit { .. } => Self::Winit(WinitArcBall::default()),
+            Inner::OpenXr => None,
         }
     }
 
     pub fn get_matrices(&self, platform: &Platform) -> Result<(PlatformReturn, [f32; 4 * 4 * 2])> {
-        match (self, platform) {
+        match (&self.inner, platform) {
             // Winit mode
-            (Self::Winit(winit_arcball), Platform::Winit { .. }) => {
-                let matrix = winit_arcball.matrix();
+            (Inner::Winit(winit_arcball), Platform::Winit { .. } | Platform::Display { .. }) => {
+                let [first, second] = winit_arcball.view_matrices();
                 let mut data = [0.0; 32];
                 data.iter_mut()
-                    .zip(matrix.as_slice().iter())
+                    .zip(first.as_slice().iter().chain(second.as_slice().iter()))
                     .for_each(|(o, i)| *o = *i);
-                Ok((PlatformReturn::Winit, data))
+                let ret = match platform {
+                    Platform::Display { .. } => PlatformReturn::Display,
+                    _ => PlatformReturn::Winit,
+                };
+                Ok((ret, data))
             }
             // OpenXR mode
             #[cfg(feature = "openxr")]
             (
-                Self::OpenXr,
+                Inner::OpenXr,
                 Platform::OpenXr {
                     xr_core,
                     frame_state,
                 },
             ) => {
-                let (_, views) = xr_core.session.locate_views(
-                    openxr::ViewConfigurationType::PRIMARY_STEREO,
-                    frame_state.expect("No frame state").predicted_display_time,
-                    &xr_core.stage,
-                )?;
+                let views = xr_core
+                    .views_at(frame_state.expect("No frame state").predicted_display_time)?
+                    .views;
+                let (near, far) = self.settings.clipping.unwrap_or(XR_CLIPPING);
                 let view_to_mat = |view: openxr::View| {
-                    let proj = xr_camera::projection_from_fov(&view.fov, 0.01, 1000.0); // TODO: Settings?
-                    let view = xr_camera::view_from_pose(&view.pose);
+                    let proj = if self.settings.reverse_z {
+                        xr_camera::projection_from_fov_reverse_z(&view.fov, near, far)
+                    } else {
+                        xr_camera::projection_from_fov(&view.fov, near, far)
+                    };
+                    // Move through the world in world units rather than meters
+                    let mut pose = view.pose;
+                    pose.position.x *= self.settings.world_scale;
+                    pose.position.y *= self.settings.world_scale;
+                    pose.position.z *= self.settings.world_scale;
+                    let view = xr_camera::view_from_pose(&pose);
                     proj * view
                 };
                 let left = view_to_mat(views[0]);
@@ -59,28 +135,77 @@ impl MultiPlatformCamera {
                 data.iter_mut()
                     .zip(left.as_slice().iter().chain(right.as_slice().iter()))
                     .for_each(|(o, i)| *o = *i);
-                Ok((PlatformReturn::OpenXr(views), data))
+                Ok((PlatformReturn::OpenXr(views.to_vec()), data))
             }
-            #[cfg(unreachable_patterns)]
+            #[allow(unreachable_patterns)]
             _ => panic!("{}", PLATFORM_WARNING),
         }
     }
 
+    /// Render both eyes on the desktop, for use with the StarterKit's stereo preview. Has no
+    /// effect in VR. `ipd` is in meters, and scaled by `CameraSettings::world_scale`.
+    pub fn set_stereo_preview(&mut self, ipd: Option<f32>) {
+        self.stereo_ipd = ipd;
+        self.apply_settings();
+    }
+
     pub fn handle_event(
         &mut self,
         event: &mut PlatformEvent<'_, '_>,
-        _platform: &mut Platform<'_>,
+        platform: &mut Platform<'_>,
     ) {
-        match (self, event) {
-            (Self::Winit(winit_arcball), PlatformEvent::Winit(event)) => {
+        match (&mut self.inner, event) {
+            (Inner::Winit(winit_arcball), PlatformEvent::Winit(event)) => {
                 if let winit::event::Event::WindowEvent { event, .. } = event {
                     winit_arcball.handle_events(event);
                 }
             }
             #[cfg(feature = "openxr")]
-            (Self::OpenXr, PlatformEvent::OpenXr(_)) => (),
+            (Inner::OpenXr, PlatformEvent::OpenXr(_) | PlatformEvent::Winit(_)) => (),
+            (inner, PlatformEvent::PlatformChanged) => {
+                *inner = Inner::new(platform);
+                self.apply_settings();
+            }
+            (
+                _,
+                PlatformEvent::Suspended | PlatformEvent::Resumed | PlatformEvent::AcquireFailed(_),
+            ) => (),
             #[allow(unreachable_patterns)]
             _ => panic!("{}", PLATFORM_WARNING),
         }
     }
+
+    /// Pass the settings on to the desktop camera. VR reads them each frame.
+    fn apply_settings(&mut self) {
+        let settings = self.settings;
+        let ipd = self.stereo_ipd.map(|ipd| ipd * settings.world_scale);
+        if let Some(winit_arcball) = self.winit_arcball_mut() {
+            winit_arcball.set_clipping(settings.clipping.unwrap_or(ArcBall::default().clipping));
+            winit_arcball.set_reverse_z(settings.reverse_z);
+            winit_arcball.set_aspect_ratio(settings.aspect_ratio);
+            winit_arcball.set_stereo(ipd);
+        }
+    }
+}
+
+impl Inner {
+    fn new(platform: &mut Platform<'_>) -> Self {
+        match platform {
+            #[cfg(feature = "openxr")]
+            Platform::OpenXr { .. } => Self::OpenXr,
+            Platform::Winit { window, .. } => {
+                let mut arcball = WinitArcBall::default();
+                arcball.set_window(window);
+                Self::Winit(arcball)
+            }
+            // A fixed view; there is no input on a display
+            Platform::Display { extent, .. } => {
+                let mut arcball = WinitArcBall::default();
+                arcball.handle_events(&winit::event::WindowEvent::Resized(
+                    winit::dpi::PhysicalSize::new(extent.width, extent.height),
+                ));
+                Self::Winit(arcball)
+            }
+        }
+    }
 }
@@ -0,0 +1,171 @@
+//! Data for each of many objects (such as model matrices) in one uniform buffer, selected per
+//! draw with a dynamic offset rather than pushed as push constants. The descriptor is written
+//! once and serves every object and frame:
+//!
+//! ```ignore
+//! // Binding 0 of `set_layouts::OBJECT_SET` is a `UNIFORM_BUFFER_DYNAMIC`, written with
+//! // `objects.descriptor_buffer_info()`. In the shader:
+//! // layout(set = 2, binding = 0) uniform Object { mat4 model; };
+//! objects.upload(frame, &transforms)?;
+//! let draws: Vec<_> = scene
+//!     .iter()
+//!     .enumerate()
+//!     .map(|(i, obj)| DrawCall { mesh: &obj.mesh, object_index: i as u32 })
+//!     .collect();
+//! objects.draw(&core, command_buffer, pipeline_layout, OBJECT_SET, object_set, frame, &draws);
+//! ```
+use crate::mesh::MeshSource;
+use crate::{memory, memory::ManagedBuffer};
+use crate::{Core, SharedCore};
+use anyhow::{ensure, Result};
+use bytemuck::Pod;
+use erupt::vk;
+use std::marker::PhantomData;
+
+/// A draw of `mesh` (a `ManagedMesh` or `MeshHandle`) with the data of object `object_index`
+#[derive(Copy, Clone)]
+pub struct DrawCall<'a> {
+    pub mesh: &'a dyn MeshSource,
+    pub object_index: u32,
+}
+
+pub struct ObjectDataUbo<T> {
+    buffer: ManagedBuffer,
+    padded_size: u64,
+    capacity: usize,
+    frames: usize,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Pod> ObjectDataUbo<T> {
+    /// Room for `capacity` objects in each of `frames` frames
+    pub fn new(core: SharedCore, capacity: usize, frames: usize) -> Result<Self> {
+        // Each object's data starts at a valid dynamic offset
+        let padded_size = memory::pad_uniform_buffer_size(
+            core.device_properties,
+            std::mem::size_of::<T>() as u64,
+        );
+        let total_size = padded_size * (capacity * frames) as u64;
+
+        let ci = vk::BufferCreateInfoBuilder::new()
+            .size(total_size)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .usage(vk::BufferUsageFlags::UNIFORM_BUFFER);
+        let mut buffer = ManagedBuffer::new(core, ci, memory::UsageFlags::UPLOAD)?;
+
+        // Written every frame, so avoid mapping it each time
+        buffer.map_persistent()?;
+
+        Ok(Self {
+            buffer,
+            padded_size,
+            capacity,
+            frames,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Maximum number of objects per frame
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Buffer info for a `UNIFORM_BUFFER_DYNAMIC` descriptor covering one object
+    pub fn descriptor_buffer_info(&self) -> vk::DescriptorBufferInfoBuilder<'static> {
+        vk::DescriptorBufferInfoBuilder::new()
+            .buffer(self.buffer.instance())
+            .offset(0)
+            .range(self.padded_size)
+    }
+
+    /// Dynamic offset of an object's data in `frame`
+    pub fn dynamic_offset(&self, frame: usize, object_index: u32) -> u32 {
+        debug_assert!(frame < self.frames, "Invalid frame {}", frame);
+        debug_assert!(
+            (object_index as usize) < self.capacity,
+            "Object {} exceeds the capacity of {}",
+            object_index,
+            self.capacity
+        );
+        let index = (frame * self.capacity) as u64 + object_index as u64;
+        (self.padded_size * index) as u32
+    }
+
+    /// Write the data of objects `0..objects.len()` in `frame`
+    pub fn upload(&mut self, frame: usize, objects: &[T]) -> Result<()> {
+        ensure!(
+            objects.len() <= self.capacity,
+            "{} objects exceed the capacity of {}",
+            objects.len(),
+            self.capacity
+        );
+        if objects.is_empty() {
+            return Ok(());
+        }
+
+        let base = self.dynamic_offset(frame, 0) as usize;
+        let stride = self.padded_size as usize;
+        let size = std::mem::size_of::<T>();
+        let mut mapped = self.buffer.map_persistent()?;
+        let bytes = &mut mapped.bytes()[base..];
+        for (i, object) in objects.iter().enumerate() {
+            bytes[i * stride..][..size].copy_from_slice(bytemuck::bytes_of(object));
+        }
+        mapped.flush(base as u64, (objects.len() * stride) as u64)
+    }
+
+    /// Record `draws` with the bound pipeline, binding `descriptor_set` (whose only dynamic
+    /// descriptor is this buffer) at `set` with each object's offset. Vertex and index buffers
+    /// are only rebound when the mesh changes, so sort draws by mesh where possible.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &self,
+        core: &Core,
+        command_buffer: vk::CommandBuffer,
+        pipeline_layout: vk::PipelineLayout,
+        set: u32,
+        descriptor_set: vk::DescriptorSet,
+        frame: usize,
+        draws: &[DrawCall],
+    ) {
+        // Vertex and index buffers last bound
+        let mut bound: Option<(vk::Buffer, vk::Buffer)> = None;
+        for draw in draws {
+            let offset = self.dynamic_offset(frame, draw.object_index);
+            unsafe {
+                core.device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline_layout,
+                    set,
+                    &[descriptor_set],
+                    &[offset],
+                );
+            }
+            let range = draw.mesh.index_range();
+            draw.mesh.with_mesh(&mut |mesh| unsafe {
+                let buffers = (mesh.vertices.instance(), mesh.indices.instance());
+                if bound != Some(buffers) {
+                    core.device
+                        .cmd_bind_vertex_buffers(command_buffer, 0, &[buffers.0], &[0]);
+                    core.device.cmd_bind_index_buffer(
+                        command_buffer,
+                        buffers.1,
+                        0,
+                        vk::IndexType::UINT32,
+                    );
+                    bound = Some(buffers);
+                }
+                let indices = range.clone().unwrap_or(0..mesh.n_indices);
+                core.device.cmd_draw_indexed(
+                    command_buffer,
+                    indices.len() as u32,
+                    1,
+                    indices.start,
+                    0,
+                    0,
+                );
+            });
+        }
+    }
+}
@@ -1,30 +1,146 @@
 use crate::{
     app_info::{engine_version, AppInfo},
+    sync2, validation,
     mainloop::{Frame, MainLoop, Platform, PlatformEvent, PlatformReturn},
     defaults::COLOR_FORMAT,
+    profiling::{self, FrameEvent},
+    resources::ResourceTracker,
+    deletion_queue::DeletionQueue,
+    shader_cache::ShaderModuleCache,
+    hardware_query::{
+        multiview_supported, pick_compute_queue_family, pick_depth_format,
+        pick_transfer_queue_family, queue_create_infos,
+    },
+    core::{ComputeQueue, QueueLocks},
+    xr_frame_stats::XrFrameStats,
+    xr_pose::XrViews,
+    xr_refresh_rate::{self, RefreshRateFns},
     Core, SharedCore,
 };
 use anyhow::{bail, ensure, Context, Result};
-use erupt::{cstr, vk, DeviceLoader, EntryLoader, InstanceLoader};
+use erupt::{vk, DeviceLoader, EntryLoader, InstanceLoader};
 use gpu_alloc::{self, GpuAllocator};
 use openxr as xr;
 use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
 use std::time::Duration;
 
 pub type SharedXrCore = Arc<XrCore>;
 
+/// A quad layer queued for this frame, whose swapchain image has been acquired and written to.
+/// The image is released once the frame is submitted.
+pub(crate) struct QuadLayer {
+    pub swapchain: Arc<Mutex<xr::Swapchain<xr::Vulkan>>>,
+    pub extent: vk::Extent2D,
+    /// In `XrCore::world()`
+    pub pose: xr::Posef,
+    /// In meters
+    pub size: xr::Extent2Df,
+}
+
 /// A container for several commonly-used OpenXR constants.
 pub struct XrCore {
     pub instance: xr::Instance,
     pub session: xr::Session<xr::Vulkan>,
     pub system: xr::SystemId,
     pub stage: xr::Space,
+    /// The stage, offset by `recenter()`, see `world()`
+    world: RwLock<xr::Space>,
+    /// The head, see `views()`
+    pub(crate) view_space: xr::Space,
+    /// Views located this frame, see `views()`
+    pub(crate) views: Mutex<Option<XrViews>>,
+    /// Quad layers to composite over the scene this frame, see `world_panel`
+    pub(crate) quad_layers: Mutex<Vec<QuadLayer>>,
+    /// Timing of recent frames, see `frame_stats()`
+    pub(crate) frame_stats: Mutex<XrFrameStats>,
+    /// `XR_FB_display_refresh_rate`, if enabled, see `refresh_rates()`
+    pub(crate) refresh_rate: Option<RefreshRateFns>,
+}
+
+impl XrCore {
+    /// Compositor timing of the most recent frames
+    pub fn frame_stats(&self) -> XrFrameStats {
+        *self.frame_stats.lock().unwrap()
+    }
+
+    /// The space views are located in and content is placed in: the stage, offset by the last
+    /// `recenter()`
+    pub fn world(&self) -> RwLockReadGuard<'_, xr::Space> {
+        self.world.read().unwrap()
+    }
+
+    /// Move the world origin to the floor below the head, facing where the head faces, as of the
+    /// latest frame. Undone by `reset_recenter()`, and when the runtime moves the stage itself
+    /// (such as when the user recenters from the system UI).
+    pub fn recenter(&self) -> Result<()> {
+        let time = self.frame_stats().predicted_display_time;
+        ensure!(time.as_nanos() != 0, "Cannot recenter before the first frame");
+
+        let head = self.view_space.locate(&self.stage, time)?;
+        let tracked =
+            xr::SpaceLocationFlags::POSITION_VALID | xr::SpaceLocationFlags::ORIENTATION_VALID;
+        ensure!(
+            head.location_flags.contains(tracked),
+            "Cannot recenter while the head isn't tracked"
+        );
+
+        // Keep only the rotation about the vertical axis, so the floor stays level
+        let xr::Quaternionf { y, w, .. } = head.pose.orientation;
+        let len = (y * y + w * w).sqrt();
+        let orientation = if len > f32::EPSILON {
+            xr::Quaternionf {
+                x: 0.,
+                y: y / len,
+                z: 0.,
+                w: w / len,
+            }
+        } else {
+            xr::Quaternionf::IDENTITY
+        };
+        let pose = xr::Posef {
+            orientation,
+            position: xr::Vector3f {
+                y: 0.,
+                ..head.pose.position
+            },
+        };
+
+        self.set_world(pose)
+    }
+
+    /// Undo `recenter()`, placing the world origin back at the stage origin
+    pub fn reset_recenter(&self) -> Result<()> {
+        self.set_world(xr::Posef::IDENTITY)
+    }
+
+    fn set_world(&self, pose: xr::Posef) -> Result<()> {
+        let world = self
+            .session
+            .create_reference_space(xr::ReferenceSpaceType::STAGE, pose)?;
+        *self.world.write().unwrap() = world;
+        // Views located in the old world are stale
+        *self.views.lock().unwrap() = None;
+        Ok(())
+    }
+
+    /// Handle `ReferenceSpaceChangePending`: when the runtime moves the stage origin, the
+    /// `recenter()` offset from the old origin no longer applies
+    pub(crate) fn reference_space_changed(
+        &self,
+        event: xr::ReferenceSpaceChangePending<'_>,
+    ) -> Result<()> {
+        if event.reference_space_type() == xr::ReferenceSpaceType::STAGE {
+            self.reset_recenter()?;
+        }
+        Ok(())
+    }
 }
 
 /// Launch an `App` using OpenXR as a surface and input mechanism for VR
-pub fn launch<M: MainLoop<T>, T>(info: AppInfo, userdata: T) -> Result<()> {
+pub fn launch<M: MainLoop<D>, D>(info: AppInfo, data: D) -> Result<()> {
     // Handle interrupts gracefully
     let running = Arc::new(AtomicBool::new(true));
     let r = running.clone();
@@ -33,7 +149,10 @@ pub fn launch<M: MainLoop<T>, T>(info: AppInfo, userdata: T) -> Result<()> {
     })
     .expect("setting Ctrl-C handler");
 
-    let (core, xr_core, frame_stream, mut frame_waiter) = build_cores(info)?;
+    let reconnect_info = XrAppInfo::new(&info);
+    let xr_reconnect = info.xr_reconnect;
+
+    let (core, (mut xr_core, frame_stream, mut frame_waiter)) = build_cores(info)?;
     let mut swapchain = Swapchain::new(xr_core.clone(), frame_stream)?;
     let mut app = M::new(
         &core,
@@ -41,11 +160,12 @@ pub fn launch<M: MainLoop<T>, T>(info: AppInfo, userdata: T) -> Result<()> {
             xr_core: &xr_core,
             frame_state: None,
         },
-        userdata,
+        data,
     )?;
 
     let mut event_storage = xr::EventDataBuffer::new();
     let mut session_running = false;
+    let mut suspended = false;
 
     // TODO: STATE TRANSITIONS
     'main_loop: loop {
@@ -59,8 +179,10 @@ pub fn launch<M: MainLoop<T>, T>(info: AppInfo, userdata: T) -> Result<()> {
             res?;
         }
 
+        let mut lost = None;
         while let Some(event) = xr_core.instance.poll_event(&mut event_storage).unwrap() {
             use xr::Event::*;
+            let mut lifecycle = None;
             match event {
                 SessionStateChanged(e) => {
                     println!("OpenXR entered state {:?}", e.state());
@@ -71,22 +193,44 @@ pub fn launch<M: MainLoop<T>, T>(info: AppInfo, userdata: T) -> Result<()> {
                                 .begin(xr::ViewConfigurationType::PRIMARY_STEREO)
                                 .unwrap();
                             session_running = true;
+                            if suspended {
+                                suspended = false;
+                                lifecycle = Some(PlatformEvent::Resumed);
+                            }
                         }
                         xr::SessionState::STOPPING => {
                             xr_core.session.end().unwrap();
                             session_running = false;
+                            suspended = true;
+                            unsafe { core.device.device_wait_idle() }.result()?;
+                            lifecycle = Some(PlatformEvent::Suspended);
+                        }
+                        xr::SessionState::EXITING => {
+                            println!("OpenXR Exiting");
+                            break 'main_loop Ok(());
+                        }
+                        xr::SessionState::LOSS_PENDING if xr_reconnect => {
+                            println!("OpenXR Session lost");
+                            lost = Some(false);
                         }
-                        xr::SessionState::EXITING | xr::SessionState::LOSS_PENDING => {
+                        xr::SessionState::LOSS_PENDING => {
                             println!("OpenXR Exiting");
                             break 'main_loop Ok(());
                         }
                         _ => {}
                     }
                 }
+                InstanceLossPending(_) if xr_reconnect => {
+                    println!("OpenXR Pending instance loss");
+                    lost = Some(true);
+                }
                 InstanceLossPending(_) => {
                     println!("OpenXR Pending instance loss");
                     break 'main_loop Ok(());
                 }
+                ReferenceSpaceChangePending(e) => {
+                    xr_core.reference_space_changed(e)?;
+                }
                 EventsLost(e) => {
                     println!("OpenXR lost {} events", e.lost_event_count());
                 }
@@ -100,6 +244,53 @@ pub fn launch<M: MainLoop<T>, T>(info: AppInfo, userdata: T) -> Result<()> {
                     frame_state: None,
                 },
             )?;
+            if let Some(lifecycle) = lifecycle {
+                app.event(
+                    lifecycle,
+                    &core,
+                    Platform::OpenXr {
+                        xr_core: &xr_core,
+                        frame_state: None,
+                    },
+                )?;
+            }
+            if lost.is_some() {
+                break;
+            }
+        }
+
+        // Tear down the session and swapchain, keeping the app and Vulkan device
+        if let Some(instance_lost) = lost {
+            unsafe { core.device.device_wait_idle() }.result()?;
+            if !suspended {
+                suspended = true;
+                app.event(
+                    PlatformEvent::Suspended,
+                    &core,
+                    Platform::OpenXr {
+                        xr_core: &xr_core,
+                        frame_state: None,
+                    },
+                )?;
+            }
+
+            drop(swapchain);
+            drop(frame_waiter);
+            session_running = false;
+
+            match reconnect(xr_core, &core, &reconnect_info, instance_lost, &running)? {
+                Some((new_xr_core, frame_stream, new_frame_waiter)) => {
+                    xr_core = new_xr_core;
+                    swapchain = Swapchain::new(xr_core.clone(), frame_stream)?;
+                    frame_waiter = new_frame_waiter;
+                    // Resumed is sent once the new session is ready
+                    continue;
+                }
+                None => {
+                    println!("OpenXR Exiting");
+                    break Ok(());
+                }
+            }
         }
 
         if !session_running {
@@ -109,7 +300,8 @@ pub fn launch<M: MainLoop<T>, T>(info: AppInfo, userdata: T) -> Result<()> {
         }
 
         // Get next frame
-        let xr_frame_state = frame_waiter.wait()?; // TODO: Move this around for better latency?
+        // TODO: Move this around for better latency?
+        let xr_frame_state = profiling::timed(FrameEvent::Acquire, || frame_waiter.wait())?;
 
         let (swapchain_index, resize) = swapchain.frame(xr_frame_state)?;
         let swapchain_index = match swapchain_index {
@@ -123,6 +315,8 @@ pub fn launch<M: MainLoop<T>, T>(info: AppInfo, userdata: T) -> Result<()> {
         }
 
         // Run the app
+        #[cfg(feature = "renderdoc")]
+        crate::capture::begin_frame();
         let ret = app.frame(
             Frame { swapchain_index },
             &core,
@@ -137,51 +331,183 @@ pub fn launch<M: MainLoop<T>, T>(info: AppInfo, userdata: T) -> Result<()> {
             _ => bail!("Wrong platform return"),
         };
 
-        // Present the image
-        swapchain.queue_present(xr_frame_state, views)?;
+        // Present the image. The runtime may use the queue while releasing and ending the frame.
+        profiling::timed(FrameEvent::Present, || {
+            let _queue = core.lock_queue(core.queue);
+            swapchain.queue_present(xr_frame_state, views)
+        })?;
+        #[cfg(feature = "renderdoc")]
+        crate::capture::end_frame();
+        core.collect_garbage()?;
     }
 }
 
-fn build_cores(
-    info: AppInfo,
-) -> Result<(
-    SharedCore,
-    SharedXrCore,
-    xr::FrameStream<xr::Vulkan>,
-    xr::FrameWaiter,
-)> {
-    // Load OpenXR runtime
+pub(crate) type XrSession = (SharedXrCore, xr::FrameStream<xr::Vulkan>, xr::FrameWaiter);
+
+/// Why VR can't be used. Returned (through `anyhow::Error`) when the OpenXR backend fails to
+/// start, and by `probe()`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum XrUnavailable {
+    /// No OpenXR runtime is installed, or none is set as the active runtime
+    NoRuntime,
+    /// The runtime can't render with Vulkan, as it lacks `XR_KHR_vulkan_enable2`
+    NoVulkanSupport,
+    /// The runtime doesn't support the OpenXR version this crate is built against
+    VersionMismatch,
+    /// The runtime is running, but no headset is connected
+    NoHeadset,
+    /// Any other runtime error
+    Runtime(xr::sys::Result),
+}
+
+impl From<xr::sys::Result> for XrUnavailable {
+    fn from(result: xr::sys::Result) -> Self {
+        // XR_ERROR_RUNTIME_UNAVAILABLE, newer than the bundled headers
+        const ERROR_RUNTIME_UNAVAILABLE: i32 = -51;
+        match result {
+            xr::sys::Result::ERROR_RUNTIME_FAILURE | xr::sys::Result::ERROR_INSTANCE_LOST => {
+                XrUnavailable::NoRuntime
+            }
+            r if r.into_raw() == ERROR_RUNTIME_UNAVAILABLE => XrUnavailable::NoRuntime,
+            xr::sys::Result::ERROR_API_VERSION_UNSUPPORTED => XrUnavailable::VersionMismatch,
+            xr::sys::Result::ERROR_EXTENSION_NOT_PRESENT => XrUnavailable::NoVulkanSupport,
+            xr::sys::Result::ERROR_FORM_FACTOR_UNAVAILABLE
+            | xr::sys::Result::ERROR_FORM_FACTOR_UNSUPPORTED => XrUnavailable::NoHeadset,
+            other => XrUnavailable::Runtime(other),
+        }
+    }
+}
+
+impl std::fmt::Display for XrUnavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            XrUnavailable::NoRuntime => write!(
+                f,
+                "No OpenXR runtime found. Install one (such as SteamVR, Monado or the Oculus app) \
+                 and set it as the active OpenXR runtime"
+            ),
+            XrUnavailable::NoVulkanSupport => write!(
+                f,
+                "The OpenXR runtime doesn't support Vulkan rendering (XR_KHR_vulkan_enable2)"
+            ),
+            XrUnavailable::VersionMismatch => write!(
+                f,
+                "The OpenXR runtime doesn't support OpenXR {}; try updating it",
+                xr::CURRENT_API_VERSION
+            ),
+            XrUnavailable::NoHeadset => write!(
+                f,
+                "No VR headset found. Check that it is connected and turned on"
+            ),
+            XrUnavailable::Runtime(result) => write!(f, "OpenXR runtime error: {}", result),
+        }
+    }
+}
+
+impl std::error::Error for XrUnavailable {}
+
+/// Check whether VR can be used: that an OpenXR runtime is installed, supports Vulkan, and has a
+/// headset connected. No Vulkan device or session is created, so launchers can call this to pick
+/// between `launch()` and the desktop backend.
+pub fn probe() -> Result<(), XrUnavailable> {
+    let info = XrAppInfo {
+        name: crate::ENGINE_NAME.to_owned(),
+        version: engine_version(),
+        engine_name: crate::ENGINE_NAME.to_owned(),
+        engine_version: engine_version(),
+    };
+    let xr_instance = try_create_xr_instance(&info)?;
+    xr_instance.system(xr::FormFactor::HEAD_MOUNTED_DISPLAY)?;
+    Ok(())
+}
+
+/// Application and engine given to the OpenXR runtime, kept to create instances again when
+/// reconnecting
+struct XrAppInfo {
+    name: String,
+    version: u32,
+    engine_name: String,
+    engine_version: u32,
+}
+
+impl XrAppInfo {
+    fn new(info: &AppInfo) -> Self {
+        Self {
+            name: info.name.clone(),
+            version: info.version,
+            engine_name: info.engine_name.clone(),
+            engine_version: info.engine_version,
+        }
+    }
+}
+
+/// Load the OpenXR runtime and create an instance
+fn create_xr_instance(info: &XrAppInfo) -> Result<xr::Instance> {
+    let xr_instance = try_create_xr_instance(info)?;
+    let instance_props = xr_instance.properties()?;
+
+    println!(
+        "Loaded OpenXR runtime: {} {}",
+        instance_props.runtime_name, instance_props.runtime_version
+    );
+
+    Ok(xr_instance)
+}
+
+fn try_create_xr_instance(info: &XrAppInfo) -> Result<xr::Instance, XrUnavailable> {
+    // The loader is linked statically, so only finding a runtime can fail
     let xr_entry = xr::Entry::linked();
 
     let available_extensions = xr_entry.enumerate_extensions()?;
-    ensure!(
-        available_extensions.khr_vulkan_enable2,
-        "Klystron requires OpenXR with KHR_VULKAN_ENABLE2"
-    );
+    if !available_extensions.khr_vulkan_enable2 {
+        return Err(XrUnavailable::NoVulkanSupport);
+    }
 
     let mut enabled_extensions = xr::ExtensionSet::default();
     enabled_extensions.khr_vulkan_enable2 = true;
+    if available_extensions
+        .other
+        .iter()
+        .any(|name| name == xr_refresh_rate::EXTENSION_NAME)
+    {
+        enabled_extensions
+            .other
+            .push(xr_refresh_rate::EXTENSION_NAME.to_owned());
+    }
 
     let xr_instance = xr_entry.create_instance(
         &xr::ApplicationInfo {
             application_name: &info.name,
             application_version: info.version,
-            engine_name: crate::ENGINE_NAME,
-            engine_version: engine_version(),
+            engine_name: &info.engine_name,
+            engine_version: info.engine_version,
         },
         &enabled_extensions,
         &[],
     )?;
-    let instance_props = xr_instance.properties()?;
 
-    println!(
-        "Loaded OpenXR runtime: {} {}",
-        instance_props.runtime_name, instance_props.runtime_version
-    );
+    Ok(xr_instance)
+}
+
+fn build_cores(info: AppInfo) -> Result<(SharedCore, XrSession)> {
+    let (core, xr_instance, system) = build_core(info, vec![], vec![])?;
+    let session = create_session(xr_instance, system, &core)?;
+    Ok((core, session))
+}
+
+/// Build a core on the device required by the OpenXR runtime, with the given extra Vulkan
+/// extensions (such as those needed to present to a window). No session is created yet.
+pub(crate) fn build_core(
+    info: AppInfo,
+    instance_extensions: Vec<*const c_char>,
+    device_extensions: Vec<*const c_char>,
+) -> Result<(SharedCore, xr::Instance, xr::SystemId)> {
+    // Load OpenXR runtime
+    let xr_instance = create_xr_instance(&XrAppInfo::new(&info))?;
 
     let system = xr_instance
         .system(xr::FormFactor::HEAD_MOUNTED_DISPLAY)
-        .unwrap();
+        .map_err(XrUnavailable::from)?;
 
     // Load Vulkan
     let vk_entry = EntryLoader::new()?;
@@ -208,40 +534,46 @@ fn build_cores(
     }
 
     // Vulkan Instance
-    let application_name = CString::new(info.name)?;
-    let engine_name = CString::new(crate::ENGINE_NAME)?;
+    let application_name = CString::new(info.name.as_str())?;
+    let engine_name = CString::new(info.engine_name.as_str())?;
     let app_info = vk::ApplicationInfoBuilder::new()
         .application_name(&application_name)
         .application_version(info.version)
         .engine_name(&engine_name)
-        .engine_version(engine_version())
+        .engine_version(info.engine_version)
         .api_version(info.api_version);
 
     // Instance and device layers and extensions
     let mut vk_instance_layers = Vec::new();
-    let mut vk_instance_extensions = Vec::new();
+    let mut vk_instance_extensions = instance_extensions;
     let mut vk_device_layers = Vec::new();
-    let vk_device_extensions = Vec::new();
+    let mut vk_device_extensions = device_extensions;
 
+    validation::instance_setup(&info, &mut vk_instance_layers, &mut vk_instance_extensions)?;
     if info.validation {
-        const LAYER_KHRONOS_VALIDATION: *const i8 = cstr!("VK_LAYER_KHRONOS_validation");
-        vk_instance_extensions
-            .push(erupt::extensions::ext_debug_utils::EXT_DEBUG_UTILS_EXTENSION_NAME);
-        vk_instance_layers.push(LAYER_KHRONOS_VALIDATION);
-        vk_device_layers.push(LAYER_KHRONOS_VALIDATION);
+        vk_device_layers.push(validation::LAYER_KHRONOS_VALIDATION);
     }
 
     // Get Instance from OpenXR
-    let create_info = vk::InstanceCreateInfoBuilder::new()
+    let validation_enables = validation::feature_enables(&info);
+    let mut validation_features = vk::ValidationFeaturesEXTBuilder::new()
+        .enabled_validation_features(&validation_enables)
+        .build();
+
+    let mut create_info = vk::InstanceCreateInfoBuilder::new()
         .application_info(&app_info)
         .enabled_layer_names(&vk_instance_layers)
         .enabled_extension_names(&vk_instance_extensions)
         .build();
 
+    if !validation_enables.is_empty() {
+        create_info.p_next = &mut validation_features as *mut _ as _;
+    }
+
     let vk_instance = unsafe {
         xr_instance.create_vulkan_instance(
             system,
-            std::mem::transmute(vk_entry.get_instance_proc_addr),
+            std::mem::transmute::<vk::PFN_vkGetInstanceProcAddr, xr::sys::platform::VkGetInstanceProcAddr>(vk_entry.get_instance_proc_addr),
             &create_info as *const _ as _,
         )
     }?
@@ -262,6 +594,7 @@ fn build_cores(
             erupt::InstanceEnabled::new(vk_version, &vk_instance_ext_cstrs, &[])?;
         InstanceLoader::custom(&vk_entry, vk_instance, instance_enabled, symbol)
     }?;
+    validation::create_messenger(&info, &vk_instance)?;
 
     // Obtain physical vk_device
     let vk_physical_device = vk::PhysicalDevice(
@@ -287,31 +620,54 @@ fn build_cores(
             .context("Vulkan vk_device has no graphics queue")?
     };
 
+    validation::device_extensions(&info, &vk_instance, vk_physical_device, &mut vk_device_extensions)?;
+    let synchronization2 =
+        sync2::device_extensions(&info, &vk_instance, vk_physical_device, &mut vk_device_extensions)?;
+
     // Create device
-    let priorities = [1.0];
-    let queues = [vk::DeviceQueueCreateInfoBuilder::new()
-        .queue_family_index(queue_family_index)
-        .queue_priorities(&priorities)];
+    let compute_queue_family = info
+        .async_compute
+        .then(|| pick_compute_queue_family(&vk_instance, vk_physical_device))
+        .flatten();
+    let transfer_queue_family = info
+        .transfer_queue
+        .then(|| pick_transfer_queue_family(&vk_instance, vk_physical_device))
+        .flatten();
+    let queues = queue_create_infos(
+        queue_family_index,
+        compute_queue_family,
+        transfer_queue_family,
+    );
 
+    let device_features = info
+        .device_preference
+        .enabled_features(&vk_instance, vk_physical_device);
     let mut create_info = vk::DeviceCreateInfoBuilder::new()
         .queue_create_infos(&queues)
+        .enabled_features(&device_features)
         .enabled_layer_names(&vk_device_layers)
         .enabled_extension_names(&vk_device_extensions)
         .build();
 
-    // Enable multiview
+    // Enable multiview where supported, otherwise each eye is rendered in a pass of its own
+    let multiview = multiview_supported(&vk_instance, vk_physical_device);
     let mut phys_device_features = erupt::vk1_2::PhysicalDeviceVulkan11Features {
-        multiview: vk::TRUE,
+        multiview: multiview.into(),
         ..Default::default()
     };
 
+    let mut sync2_features = sync2::features();
+    if synchronization2 {
+        phys_device_features.p_next = &mut sync2_features as *mut _ as _;
+    }
+
     create_info.p_next = &mut phys_device_features as *mut _ as _;
 
     // Get Vulkan Device from OpenXR
     let vk_device = unsafe {
         xr_instance.create_vulkan_device(
             system,
-            std::mem::transmute(vk_entry.get_instance_proc_addr),
+            std::mem::transmute::<vk::PFN_vkGetInstanceProcAddr, xr::sys::platform::VkGetInstanceProcAddr>(vk_entry.get_instance_proc_addr),
             vk_physical_device.0 as _,
             &create_info as *const _ as _,
         )
@@ -332,6 +688,14 @@ fn build_cores(
 
     // Create queue
     let queue = unsafe { vk_device.get_device_queue(queue_family_index, 0, None) };
+    let compute_queue = compute_queue_family.map(|family| ComputeQueue {
+        queue: unsafe { vk_device.get_device_queue(family, 0, None) },
+        family,
+    });
+    let transfer_queue = transfer_queue_family.map(|family| ComputeQueue {
+        queue: unsafe { vk_device.get_device_queue(family, 0, None) },
+        family,
+    });
 
     // Create allocator
     let device_props =
@@ -342,16 +706,46 @@ fn build_cores(
     ));
     let device_properties =
         unsafe { vk_instance.get_physical_device_properties(vk_physical_device, None) };
+    let depth_format = pick_depth_format(&vk_instance, vk_physical_device)?;
 
-    // OpenXR session
+    // Create Core
+    let core = SharedCore::new(Core {
+        queue,
+        queue_family: queue_family_index,
+        compute_queue,
+        transfer_queue,
+        queue_locks: QueueLocks::default(),
+        allocator,
+        device: vk_device,
+        physical_device: vk_physical_device,
+        device_properties,
+        device_features,
+        depth_format,
+        multiview,
+        instance: vk_instance,
+        entry: vk_entry,
+        resources: ResourceTracker::new(info.leak_warnings),
+        deletion_queue: DeletionQueue::new(),
+        shader_modules: ShaderModuleCache::new(),
+    });
+
+    Ok((core, xr_instance, system))
+}
+
+/// Create a session (and the XrCore around it) on an existing Vulkan device
+pub(crate) fn create_session(
+    xr_instance: xr::Instance,
+    system: xr::SystemId,
+    core: &Core,
+) -> Result<XrSession> {
     let (session, frame_wait, frame_stream) = unsafe {
         xr_instance.create_session::<xr::Vulkan>(
             system,
             &xr::vulkan::SessionCreateInfo {
-                instance: vk_instance.handle.0 as _,
-                physical_device: vk_physical_device.0 as _,
-                device: vk_device.handle.0 as _,
-                queue_family_index,
+                instance: core.instance.handle.0 as _,
+                physical_device: core.physical_device.0 as _,
+                device: core.device.handle.0 as _,
+                queue_family_index: core.queue_family,
                 queue_index: 0,
             },
         )
@@ -361,28 +755,89 @@ fn build_cores(
     let stage = session
         .create_reference_space(xr::ReferenceSpaceType::STAGE, xr::Posef::IDENTITY)
         .unwrap();
-
-    // Create Core
-    let core = SharedCore::new(Core {
-        queue,
-        queue_family: queue_family_index,
-        allocator,
-        device: vk_device,
-        physical_device: vk_physical_device,
-        device_properties,
-        instance: vk_instance,
-        entry: vk_entry,
-    });
+    let world =
+        session.create_reference_space(xr::ReferenceSpaceType::STAGE, xr::Posef::IDENTITY)?;
+    let view_space =
+        session.create_reference_space(xr::ReferenceSpaceType::VIEW, xr::Posef::IDENTITY)?;
 
     // Create XrCore
+    let refresh_rate = RefreshRateFns::load(&xr_instance);
     let xr_core = SharedXrCore::new(XrCore {
         instance: xr_instance,
         session,
         system,
         stage,
+        world: RwLock::new(world),
+        view_space,
+        views: Mutex::new(None),
+        quad_layers: Mutex::new(vec![]),
+        frame_stats: Mutex::new(XrFrameStats::default()),
+        refresh_rate,
     });
 
-    Ok((core, xr_core, frame_stream, frame_wait))
+    Ok((xr_core, frame_stream, frame_wait))
+}
+
+/// Wait for the runtime and headset to become available again after the session (or, with
+/// `instance_lost`, the whole instance) was lost, and create a new session on the existing
+/// Vulkan device. Returns `None` if interrupted while waiting.
+fn reconnect(
+    xr_core: SharedXrCore,
+    core: &Core,
+    info: &XrAppInfo,
+    instance_lost: bool,
+    running: &AtomicBool,
+) -> Result<Option<XrSession>> {
+    let XrCore { instance, .. } = Arc::try_unwrap(xr_core)
+        .map_err(|_| anyhow::format_err!("OpenXR core still in use during reconnect"))?;
+    let mut instance = if instance_lost {
+        drop(instance);
+        None
+    } else {
+        Some(instance)
+    };
+
+    println!("OpenXR waiting to reconnect");
+    while running.load(Ordering::Relaxed) {
+        let xr_instance = match instance.take() {
+            Some(xr_instance) => xr_instance,
+            None => match create_xr_instance(info) {
+                Ok(xr_instance) => xr_instance,
+                Err(_) => {
+                    std::thread::sleep(Duration::from_secs(1));
+                    continue;
+                }
+            },
+        };
+
+        let system = match xr_instance.system(xr::FormFactor::HEAD_MOUNTED_DISPLAY) {
+            Ok(system) => system,
+            // The headset is disconnected, but the runtime is fine
+            Err(xr::sys::Result::ERROR_FORM_FACTOR_UNAVAILABLE) => {
+                instance = Some(xr_instance);
+                std::thread::sleep(Duration::from_secs(1));
+                continue;
+            }
+            Err(_) => {
+                std::thread::sleep(Duration::from_secs(1));
+                continue;
+            }
+        };
+
+        // Must be queried before creating a session
+        xr_instance.graphics_requirements::<xr::Vulkan>(system)?;
+        let physical_device =
+            xr_instance.vulkan_graphics_device(system, core.instance.handle.0 as _)?;
+        ensure!(
+            vk::PhysicalDevice(physical_device as _) == core.physical_device,
+            "OpenXR runtime now requires a different GPU, cannot reconnect"
+        );
+
+        println!("OpenXR reconnected");
+        return create_session(xr_instance, system, core).map(Some);
+    }
+
+    Ok(None)
 }
 
 pub struct Swapchain {
@@ -394,6 +849,17 @@ pub struct Swapchain {
 
 type SwapchainImages = (Vec<vk::Image>, vk::Extent2D);
 
+/// The whole of an image of this size
+fn image_rect(extent: vk::Extent2D) -> xr::Rect2Di {
+    xr::Rect2Di {
+        offset: xr::Offset2Di { x: 0, y: 0 },
+        extent: xr::Extent2Di {
+            width: extent.width as _,
+            height: extent.height as _,
+        },
+    }
+}
+
 impl Swapchain {
     /// Create a new engine instance. Returns the OpenXr caddy for use with input handling.
     pub fn new(xr_core: SharedXrCore, frame_stream: xr::FrameStream<xr::Vulkan>) -> Result<Self> {
@@ -409,6 +875,12 @@ impl Swapchain {
         &mut self,
         xr_frame_state: xr::FrameState,
     ) -> Result<(Option<u32>, Option<SwapchainImages>)> {
+        self.xr_core
+            .frame_stats
+            .lock()
+            .unwrap()
+            .begin(&xr_frame_state);
+
         // Wait for OpenXR to signal it has a frame ready
         self.frame_stream.begin()?;
 
@@ -418,6 +890,7 @@ impl Swapchain {
                 xr::EnvironmentBlendMode::OPAQUE,
                 &[],
             )?;
+            self.xr_core.frame_stats.lock().unwrap().end();
             return Ok((None, None));
         }
 
@@ -446,40 +919,68 @@ impl Swapchain {
         // Present to swapchain
         swapchain.release_image()?;
 
+        // Release the images of quad layers written this frame
+        let quads = std::mem::take(&mut *self.xr_core.quad_layers.lock().unwrap());
+        let mut quad_swapchains = vec![];
+        for quad in &quads {
+            let mut quad_swapchain = quad.swapchain.lock().unwrap();
+            quad_swapchain.release_image()?;
+            quad_swapchains.push(quad_swapchain);
+        }
+
         // Tell OpenXR what to present for this frame
-        let rect = xr::Rect2Di {
-            offset: xr::Offset2Di { x: 0, y: 0 },
-            extent: xr::Extent2Di {
-                width: self.current_extent.width as _,
-                height: self.current_extent.height as _,
-            },
-        };
+        let world = self.xr_core.world();
+        let rect = image_rect(self.current_extent);
+        let projection_views = [
+            xr::CompositionLayerProjectionView::new()
+                .pose(views[0].pose)
+                .fov(views[0].fov)
+                .sub_image(
+                    xr::SwapchainSubImage::new()
+                        .swapchain(swapchain)
+                        .image_array_index(0)
+                        .image_rect(rect),
+                ),
+            xr::CompositionLayerProjectionView::new()
+                .pose(views[1].pose)
+                .fov(views[1].fov)
+                .sub_image(
+                    xr::SwapchainSubImage::new()
+                        .swapchain(swapchain)
+                        .image_array_index(1)
+                        .image_rect(rect),
+                ),
+        ];
+        let projection = xr::CompositionLayerProjection::new()
+            .space(&world)
+            .views(&projection_views);
+        let quad_layers: Vec<_> = quads
+            .iter()
+            .zip(&quad_swapchains)
+            .map(|(quad, quad_swapchain)| {
+                xr::CompositionLayerQuad::new()
+                    .space(&world)
+                    .eye_visibility(xr::EyeVisibility::BOTH)
+                    .pose(quad.pose)
+                    .size(quad.size)
+                    .sub_image(
+                        xr::SwapchainSubImage::new()
+                            .swapchain(quad_swapchain)
+                            .image_array_index(0)
+                            .image_rect(image_rect(quad.extent)),
+                    )
+            })
+            .collect();
+
+        // Quads are drawn over the scene, in the order they were queued
+        let mut layers: Vec<&xr::CompositionLayerBase<xr::Vulkan>> = vec![&projection];
+        layers.extend(quad_layers.iter().map(|quad| &**quad));
         self.frame_stream.end(
             xr_frame_state.predicted_display_time,
             xr::EnvironmentBlendMode::OPAQUE,
-            &[&xr::CompositionLayerProjection::new()
-                .space(&self.xr_core.stage)
-                .views(&[
-                    xr::CompositionLayerProjectionView::new()
-                        .pose(views[0].pose)
-                        .fov(views[0].fov)
-                        .sub_image(
-                            xr::SwapchainSubImage::new()
-                                .swapchain(&swapchain)
-                                .image_array_index(0)
-                                .image_rect(rect),
-                        ),
-                    xr::CompositionLayerProjectionView::new()
-                        .pose(views[1].pose)
-                        .fov(views[1].fov)
-                        .sub_image(
-                            xr::SwapchainSubImage::new()
-                                .swapchain(&swapchain)
-                                .image_array_index(1)
-                                .image_rect(rect),
-                        ),
-                ])],
+            &layers,
         )?;
+        self.xr_core.frame_stats.lock().unwrap().end();
 
         Ok(())
     }
@@ -507,7 +1008,8 @@ impl Swapchain {
             .create_swapchain(&xr::SwapchainCreateInfo {
                 create_flags: xr::SwapchainCreateFlags::EMPTY,
                 usage_flags: xr::SwapchainUsageFlags::COLOR_ATTACHMENT
-                    | xr::SwapchainUsageFlags::SAMPLED,
+                    | xr::SwapchainUsageFlags::SAMPLED
+                    | xr::SwapchainUsageFlags::TRANSFER_SRC,
                 format: COLOR_FORMAT.0 as _,
                 sample_count: 1,
                 width: extent.width,
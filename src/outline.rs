@@ -0,0 +1,161 @@
+//! Outlines around selected objects, as drawn by editors. Selected meshes are first drawn into
+//! the stencil aspect of the depth buffer, then again scaled about their origin in a solid color
+//! wherever the stencil wasn't marked, leaving a rim around their silhouettes. Outlines show
+//! through other objects. Not supported with deferred shading.
+//!
+//! The scene's depth format must have a stencil aspect:
+//!
+//! ```ignore
+//! let depth_format =
+//!     hardware_query::pick_depth_stencil_format(&core.instance, core.physical_device)?;
+//! let settings = Settings {
+//!     depth_format: Some(depth_format),
+//!     ..Default::default()
+//! };
+//! // Build `layout` from the frame set layout and `outline::push_constant_range()`
+//! let mut outline = Outline::new::<Vertex>(&mut starter_kit, layout, frame_sets)?;
+//! outline.set_params(&OutlineParams {
+//!     color: [0.2, 0.6, 1.0, 1.0],
+//!     scale: 1.03,
+//! })?;
+//! // After drawing the scene
+//! outline.draw(&starter_kit, &cmd, &[(&cube, transform)]);
+//! ```
+use crate::material::{self, Material};
+use crate::memory::aspect_mask;
+use crate::mesh::MeshSource;
+use crate::shader::{PipelineSettings, StencilTest};
+use crate::starter_kit::{CommandBufferStart, StarterKit};
+use crate::vertex::VertexLayout;
+use anyhow::{ensure, Result};
+use erupt::vk;
+
+/// Bundled vertex shader, taking positions at location 0 and the camera UBO at binding 0 (laid
+/// out as in the bundled unlit shader), followed by `OutlineParams` in push constants
+pub const OUTLINE_VERT: &[u8] = include_bytes!("../shaders/outline.vert.spv");
+
+/// Bundled fragment shader, writing `OutlineParams::color`
+pub const OUTLINE_FRAG: &[u8] = include_bytes!("../shaders/outline.frag.spv");
+
+/// Stencil value marking the pixels covered by selected objects
+pub const OUTLINE_STENCIL: u32 = 1;
+
+/// Descriptor set the frame's camera UBO is bound at
+const FRAME_SET: u32 = 0;
+
+/// Appearance of outlines, pushed after the transform
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OutlineParams {
+    /// Linear color, alpha blended over the scene
+    pub color: [f32; 4],
+    /// Scale of the outlined mesh about its origin; the outline is the part beyond the mesh
+    pub scale: f32,
+}
+
+unsafe impl bytemuck::Zeroable for OutlineParams {}
+unsafe impl bytemuck::Pod for OutlineParams {}
+
+impl Default for OutlineParams {
+    fn default() -> Self {
+        Self {
+            color: [1.0, 0.5, 0.1, 1.0],
+            scale: 1.05,
+        }
+    }
+}
+
+/// Push constant range of the pipeline layout given to `Outline::new()`
+pub fn push_constant_range() -> vk::PushConstantRangeBuilder<'static> {
+    material::push_constant_range(std::mem::size_of::<OutlineParams>())
+}
+
+/// Stencil mask and outline pipelines of a `StarterKit`'s scene
+pub struct Outline {
+    mask: Material,
+    outline: Material,
+    params: OutlineParams,
+}
+
+impl Outline {
+    /// Add the pipelines for meshes of vertex type `V` to the StarterKit's `PipelineManager`.
+    /// `layout` has the frame set layout (the camera UBO) at set 0 and `push_constant_range()`;
+    /// `frame_sets` are the frame sets, one per frame in flight.
+    pub fn new<V: VertexLayout>(
+        starter_kit: &mut StarterKit,
+        layout: vk::PipelineLayout,
+        frame_sets: Vec<vk::DescriptorSet>,
+    ) -> Result<Self> {
+        let depth_format = starter_kit.depth_format();
+        ensure!(
+            aspect_mask(depth_format).contains(vk::ImageAspectFlags::STENCIL),
+            "Outlines need a depth format with a stencil aspect, got {:?}",
+            depth_format
+        );
+
+        // Neither pass is depth tested, so that the whole silhouette is marked and outlined
+        let mask_settings = PipelineSettings {
+            depth_test: false,
+            depth_write: false,
+            stencil: Some(StencilTest::write(OUTLINE_STENCIL)),
+            ..Default::default()
+        };
+        let outline_settings = PipelineSettings {
+            blend: true,
+            stencil: Some(StencilTest::not_equal(OUTLINE_STENCIL)),
+            ..mask_settings
+        };
+
+        let pipelines = &mut starter_kit.pipelines;
+        let mask_pipeline = pipelines.add_depth_only::<V>(OUTLINE_VERT, mask_settings, layout)?;
+        let outline_pipeline =
+            pipelines.add::<V>(OUTLINE_VERT, OUTLINE_FRAG, outline_settings, layout)?;
+
+        let mut mask = Material::new(mask_pipeline, layout);
+        mask.bind_per_frame(FRAME_SET, frame_sets.clone());
+        mask.set_params(&OutlineParams {
+            color: [0.0; 4],
+            scale: 1.0,
+        })?;
+
+        let mut outline = Material::new(outline_pipeline, layout);
+        outline.bind_per_frame(FRAME_SET, frame_sets);
+
+        let mut instance = Self {
+            mask,
+            outline,
+            params: OutlineParams::default(),
+        };
+        instance.set_params(&OutlineParams::default())?;
+        Ok(instance)
+    }
+
+    /// Set the color and width of outlines
+    pub fn set_params(&mut self, params: &OutlineParams) -> Result<()> {
+        ensure!(params.scale > 0.0, "Invalid outline scale {}", params.scale);
+        self.outline.set_params(params)?;
+        self.params = *params;
+        Ok(())
+    }
+
+    /// Color and width of outlines
+    pub fn params(&self) -> OutlineParams {
+        self.params
+    }
+
+    /// Outline the `selected` meshes with their transforms, as one silhouette where they overlap.
+    /// Draw after the rest of the scene, so that outlines cover it.
+    pub fn draw<M: MeshSource>(
+        &self,
+        starter_kit: &StarterKit,
+        cmd: &CommandBufferStart,
+        selected: &[(&M, nalgebra::Matrix4<f32>)],
+    ) {
+        for (mesh, transform) in selected {
+            starter_kit.draw(cmd, *mesh, &self.mask, transform);
+        }
+        for (mesh, transform) in selected {
+            starter_kit.draw(cmd, *mesh, &self.outline, transform);
+        }
+    }
+}
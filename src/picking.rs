@@ -0,0 +1,212 @@
+//! GPU object picking. Objects are drawn with their IDs into a small integer target, restricted
+//! to the pixel under the cursor, and the ID found there is read back once the frame that wrote
+//! it has finished (so results arrive one round of frames in flight late).
+//!
+//! ```ignore
+//! // Each frame, after StarterKit::begin_frame() (which waits on this frame's fence)
+//! if let Some(id) = picking.result(frame)? { /* hovered object */ }
+//!
+//! picking.begin(command_buffer, cursor);
+//! for (id, mesh) in objects {
+//!     Picking::push_id(&core, command_buffer, pipeline_layout, id);
+//!     draw_mesh(&core, command_buffer, mesh);
+//! }
+//! picking.end(command_buffer, frame, cursor);
+//! ```
+//!
+//! For XR aim rays, render the pick pass from a camera looking down the ray and pick
+//! `Picking::center()`.
+use crate::memory::{ManagedBuffer, UsageFlags};
+use crate::render_target::{RenderTarget, RenderTargetSettings};
+use crate::{barriers, copy};
+use crate::sync2::{Access, Stages};
+use crate::{Core, SharedCore};
+use anyhow::Result;
+use erupt::vk;
+
+/// Bundled vertex shader for the pick pass. Takes positions at location 0 and the camera UBO at
+/// binding 0, laid out as in the bundled unlit shader.
+pub const PICKING_VERT: &[u8] = include_bytes!("../shaders/picking.vert.spv");
+
+/// Bundled fragment shader for the pick pass, writing the ID given by `Picking::push_id()`
+pub const PICKING_FRAG: &[u8] = include_bytes!("../shaders/picking.frag.spv");
+
+/// ID reported when nothing was drawn under the cursor. Object IDs should start at 1.
+pub const NO_OBJECT: u32 = 0;
+
+/// Object ID render target and per-frame readback
+pub struct Picking {
+    target: RenderTarget,
+    readback: Vec<ManagedBuffer>,
+    /// Whether a pixel was copied during each frame
+    pending: Vec<bool>,
+    core: SharedCore,
+}
+
+impl Picking {
+    /// Create a picking target. `resize()` must be called before use.
+    pub fn new(core: SharedCore, frames: usize, vr: bool) -> Result<Self> {
+        let settings = RenderTargetSettings {
+            color_format: vk::Format::R32_UINT,
+            depth_format: Some(core.depth_format),
+            vr,
+            extra_usage: vk::ImageUsageFlags::TRANSFER_SRC,
+            extra_depth_usage: vk::ImageUsageFlags::empty(),
+            reverse_z: false,
+            samples: vk::SampleCountFlagBits::_1,
+        };
+        let target = RenderTarget::new(core.clone(), settings)?;
+
+        let readback = (0..frames)
+            .map(|_| {
+                let ci = vk::BufferCreateInfoBuilder::new()
+                    .size(std::mem::size_of::<u32>() as u64)
+                    .sharing_mode(vk::SharingMode::EXCLUSIVE)
+                    .usage(vk::BufferUsageFlags::TRANSFER_DST);
+                ManagedBuffer::new(core.clone(), ci, UsageFlags::DOWNLOAD)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            target,
+            readback,
+            pending: vec![false; frames],
+            core,
+        })
+    }
+
+    /// Resize the ID target, typically to the swapchain extent. A smaller target may be used for
+    /// XR aim rays.
+    pub fn resize(&mut self, extent: vk::Extent2D) -> Result<()> {
+        self.target.resize(extent)
+    }
+
+    /// Render pass to build picking pipelines against. Pipelines need a 4 byte `FRAGMENT` push
+    /// constant range at offset 0 for `push_id()`.
+    pub fn render_pass(&self) -> vk::RenderPass {
+        self.target.render_pass()
+    }
+
+    /// Clear depth for pick pipelines built with `PipelineSettings::reverse_z`
+    pub fn set_reverse_z(&mut self, reverse_z: bool) {
+        self.target.set_reverse_z(reverse_z);
+    }
+
+    /// Center pixel of the target
+    pub fn center(&self) -> (u32, u32) {
+        let extent = self.target.extent();
+        (extent.width / 2, extent.height / 2)
+    }
+
+    /// Begin the pick pass, with the scissor set to the pixel at `cursor`. Must be called outside
+    /// of any render pass.
+    pub fn begin(&self, command_buffer: vk::CommandBuffer, cursor: (u32, u32)) {
+        let (x, y) = self.clamp(cursor);
+        self.target.begin_pass(command_buffer, [0.0; 4]);
+
+        let scissors = [vk::Rect2DBuilder::new()
+            .offset(vk::Offset2D {
+                x: x as i32,
+                y: y as i32,
+            })
+            .extent(vk::Extent2D {
+                width: 1,
+                height: 1,
+            })];
+
+        unsafe {
+            self.core
+                .device
+                .cmd_set_scissor(command_buffer, 0, &scissors);
+        }
+    }
+
+    /// Set the ID of following draws
+    pub fn push_id(
+        core: &Core,
+        command_buffer: vk::CommandBuffer,
+        pipeline_layout: vk::PipelineLayout,
+        id: u32,
+    ) {
+        unsafe {
+            core.device.cmd_push_constants(
+                command_buffer,
+                pipeline_layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                std::mem::size_of::<u32>() as u32,
+                &id as *const u32 as _,
+            );
+        }
+    }
+
+    /// End the pick pass and copy the picked pixel (of the first view in VR) into this frame's
+    /// readback buffer. `cursor` must be the same as was passed to `begin()`.
+    pub fn end(&mut self, command_buffer: vk::CommandBuffer, frame: usize, cursor: (u32, u32)) {
+        let (x, y) = self.clamp(cursor);
+        self.target.end_pass(command_buffer);
+
+        // Chains onto the render pass' outgoing dependency, which covers the fragment stage
+        let to_transfer = barriers::image_barrier(
+            self.target.color_image(),
+            barriers::color_range(1),
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        );
+
+        // Back to the render pass' final layout once read. The next frame's pass waits on the
+        // fragment stage, so this also keeps it from overwriting the image during the copy
+        let from_transfer = barriers::image_barrier(
+            self.target.color_image(),
+            barriers::color_range(1),
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+
+        let to_host = barriers::buffer_barrier(
+            self.readback[frame].instance(),
+            (Stages::TRANSFER_KHR, Access::TRANSFER_WRITE_KHR),
+            (Stages::HOST_KHR, Access::HOST_READ_KHR),
+        );
+
+        self.core.cmd_barriers(command_buffer, &[], &[to_transfer]);
+        copy::cmd_copy_image_to_buffer(
+            &self.core,
+            command_buffer,
+            self.target.color_image(),
+            0,
+            vk::Offset2D {
+                x: x as i32,
+                y: y as i32,
+            },
+            vk::Extent2D {
+                width: 1,
+                height: 1,
+            },
+            self.readback[frame].instance(),
+        );
+        self.core
+            .cmd_barriers(command_buffer, &[to_host], &[from_transfer]);
+
+        self.pending[frame] = true;
+    }
+
+    /// The ID picked the last time `frame` was rendered, if there was a pick and it hit an
+    /// object. Call this only once the GPU has finished that frame, e.g. after the StarterKit
+    /// has begun the same frame index again.
+    pub fn result(&mut self, frame: usize) -> Result<Option<u32>> {
+        if !std::mem::take(&mut self.pending[frame]) {
+            return Ok(None);
+        }
+
+        let mut id = [0u32];
+        self.readback[frame].read_slice(0, &mut id)?;
+        let [id] = id;
+        Ok(if id == NO_OBJECT { None } else { Some(id) })
+    }
+
+    fn clamp(&self, (x, y): (u32, u32)) -> (u32, u32) {
+        let extent = self.target.extent();
+        (x.min(extent.width - 1), y.min(extent.height - 1))
+    }
+}
@@ -0,0 +1,304 @@
+//! Pipelines which follow a render pass. A `PipelineManager` keeps the description of each
+//! pipeline it builds (shaders, fixed-function settings and layout), and rebuilds them all when
+//! the render pass, its sample count or its color encoding changes. The StarterKit owns one for its `render_pass`,
+//! and updates it when `platform_changed()` recreates the render pass for a swapchain of another
+//! format, or on `set_msaa_samples()`; pipelines built directly against the old render pass
+//! would be left incompatible. On devices without multiview, each pipeline is built once per
+//! view pass (see `render_pass::view_passes()`), for the eye that pass draws.
+//!
+//! ```ignore
+//! let id = starter_kit
+//!     .pipelines
+//!     .add::<Vertex>(VERT, FRAG, PipelineSettings::default(), pipeline_layout)?;
+//! // Each frame
+//! let pipeline = starter_kit.pipelines.get(id);
+//! core.device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, pipeline);
+//! ```
+use crate::deletion_queue::Garbage;
+use crate::shader::{shader_depth_only, shader_with_settings, PipelineSettings};
+use crate::vertex::VertexLayout;
+use crate::{Core, SharedCore};
+use anyhow::Result;
+use erupt::vk;
+
+/// `shader_with_settings()` for a particular vertex type
+type Build = fn(
+    &Core,
+    &[u8],
+    &[u8],
+    &PipelineSettings,
+    vk::RenderPass,
+    vk::PipelineLayout,
+) -> Result<vk::Pipeline>;
+
+/// Everything needed to rebuild a pipeline, and the current ones, one per view pass
+struct ManagedPipeline {
+    vertex_src: Vec<u8>,
+    fragment_src: Vec<u8>,
+    settings: PipelineSettings,
+    layout: vk::PipelineLayout,
+    build: Build,
+    pipelines: Vec<vk::Pipeline>,
+}
+
+/// What the pipelines are built against
+#[derive(Copy, Clone, PartialEq, Eq)]
+struct Target {
+    render_pass: vk::RenderPass,
+    samples: vk::SampleCountFlagBits,
+    encode_srgb: bool,
+    view_passes: u32,
+}
+
+impl ManagedPipeline {
+    fn build(&self, core: &Core, target: Target) -> Result<Vec<vk::Pipeline>> {
+        let mut pipelines = vec![];
+        for view_index in 0..target.view_passes {
+            let settings = PipelineSettings {
+                samples: target.samples,
+                encode_srgb: target.encode_srgb,
+                view_index,
+                ..self.settings
+            };
+            let pipeline = (self.build)(
+                core,
+                &self.vertex_src,
+                &self.fragment_src,
+                &settings,
+                target.render_pass,
+                self.layout,
+            );
+            match pipeline {
+                Ok(pipeline) => pipelines.push(pipeline),
+                Err(e) => {
+                    for pipeline in pipelines {
+                        core.defer_destroy(Garbage::Pipeline(pipeline));
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(pipelines)
+    }
+}
+
+/// Identifies a pipeline in a `PipelineManager`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineId(usize);
+
+/// Graphics pipelines built against the same render pass, rebuilt when it changes
+pub struct PipelineManager {
+    core: SharedCore,
+    target: Target,
+    pipelines: Vec<Option<ManagedPipeline>>,
+}
+
+impl PipelineManager {
+    /// Manage pipelines for `render_pass`, which has `samples` samples per pixel, and whose
+    /// color format needs shaders to sRGB encode if `encode_srgb` (see
+    /// `RenderPassSettings::encode_srgb()`)
+    pub fn new(
+        core: SharedCore,
+        render_pass: vk::RenderPass,
+        samples: vk::SampleCountFlagBits,
+        encode_srgb: bool,
+    ) -> Self {
+        Self {
+            core,
+            target: Target {
+                render_pass,
+                samples,
+                encode_srgb,
+                view_passes: 1,
+            },
+            pipelines: vec![],
+        }
+    }
+
+    /// Build a pipeline for the vertex type `V`, as with `shader_with_settings()`. The sample
+    /// count, `encode_srgb` and `view_index` in `settings` are replaced with the render pass's.
+    /// `layout` must outlive the pipeline, as it is used again for rebuilds.
+    pub fn add<V: VertexLayout>(
+        &mut self,
+        vertex_src: &[u8],
+        fragment_src: &[u8],
+        settings: PipelineSettings,
+        layout: vk::PipelineLayout,
+    ) -> Result<PipelineId> {
+        self.insert(ManagedPipeline {
+            vertex_src: vertex_src.to_vec(),
+            fragment_src: fragment_src.to_vec(),
+            settings,
+            layout,
+            build: shader_with_settings::<V>,
+            pipelines: vec![],
+        })
+    }
+
+    /// Build a depth-only pipeline for the vertex type `V`, as with `shader_depth_only()`, such
+    /// as for a depth prepass of the scene
+    pub fn add_depth_only<V: VertexLayout>(
+        &mut self,
+        vertex_src: &[u8],
+        settings: PipelineSettings,
+        layout: vk::PipelineLayout,
+    ) -> Result<PipelineId> {
+        self.insert(ManagedPipeline {
+            vertex_src: vertex_src.to_vec(),
+            fragment_src: vec![],
+            settings,
+            layout,
+            build: |core, vertex_src, _, settings, render_pass, layout| {
+                shader_depth_only::<V>(core, vertex_src, settings, render_pass, layout)
+            },
+            pipelines: vec![],
+        })
+    }
+
+    /// Build a pipeline and keep it in a free slot
+    fn insert(&mut self, mut managed: ManagedPipeline) -> Result<PipelineId> {
+        managed.pipelines = managed.build(&self.core, self.target)?;
+
+        let idx = match self.pipelines.iter().position(Option::is_none) {
+            Some(idx) => {
+                self.pipelines[idx] = Some(managed);
+                idx
+            }
+            None => {
+                self.pipelines.push(Some(managed));
+                self.pipelines.len() - 1
+            }
+        };
+        Ok(PipelineId(idx))
+    }
+
+    /// The current pipeline. Fetch it each frame rather than keeping it, as it changes on
+    /// rebuilds.
+    pub fn get(&self, id: PipelineId) -> vk::Pipeline {
+        self.get_view(id, 0)
+    }
+
+    /// The current pipeline for a view pass, drawing that pass's eye on devices without
+    /// multiview. See `view_passes()`.
+    pub fn get_view(&self, id: PipelineId, view: u32) -> vk::Pipeline {
+        *self
+            .managed(id)
+            .pipelines
+            .get(view as usize)
+            .expect("Invalid view pass")
+    }
+
+    /// Destroy a pipeline, once frames using it have completed
+    pub fn remove(&mut self, id: PipelineId) {
+        let managed = self
+            .pipelines
+            .get_mut(id.0)
+            .and_then(Option::take)
+            .expect("Pipeline was removed");
+        for pipeline in managed.pipelines {
+            self.core.defer_destroy(Garbage::Pipeline(pipeline));
+        }
+    }
+
+    /// Render pass the pipelines are built against
+    pub fn render_pass(&self) -> vk::RenderPass {
+        self.target.render_pass
+    }
+
+    /// Samples per pixel the pipelines are built with
+    pub fn samples(&self) -> vk::SampleCountFlagBits {
+        self.target.samples
+    }
+
+    /// Whether the pipelines sRGB encode their outputs, see `PipelineSettings::encode_srgb`
+    pub fn encode_srgb(&self) -> bool {
+        self.target.encode_srgb
+    }
+
+    /// Number of view passes each pipeline is built for, 1 unless set with `set_view_passes()`
+    pub fn view_passes(&self) -> u32 {
+        self.target.view_passes
+    }
+
+    /// Rebuild all pipelines against a new render pass, if it, the sample count or the encoding
+    /// differ. The old pipelines are destroyed once frames using them have completed. On
+    /// failure, the pipelines are left as they were.
+    pub fn set_render_pass(
+        &mut self,
+        render_pass: vk::RenderPass,
+        samples: vk::SampleCountFlagBits,
+        encode_srgb: bool,
+    ) -> Result<()> {
+        self.rebuild(Target {
+            render_pass,
+            samples,
+            encode_srgb,
+            ..self.target
+        })
+    }
+
+    /// Rebuild all pipelines once for each of `view_passes` view passes, such as
+    /// `FramebufferManager::view_passes()`, if the number differs. On failure, the pipelines are
+    /// left as they were.
+    pub fn set_view_passes(&mut self, view_passes: u32) -> Result<()> {
+        self.rebuild(Target {
+            view_passes,
+            ..self.target
+        })
+    }
+
+    fn rebuild(&mut self, target: Target) -> Result<()> {
+        if target == self.target {
+            return Ok(());
+        }
+
+        let mut rebuilt = vec![];
+        for managed in self.pipelines.iter().flatten() {
+            match managed.build(&self.core, target) {
+                Ok(pipelines) => rebuilt.push(pipelines),
+                Err(e) => {
+                    for pipeline in rebuilt.into_iter().flatten() {
+                        self.core.defer_destroy(Garbage::Pipeline(pipeline));
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        for (managed, pipelines) in self.pipelines.iter_mut().flatten().zip(rebuilt) {
+            let old = std::mem::replace(&mut managed.pipelines, pipelines);
+            for pipeline in old {
+                self.core.defer_destroy(Garbage::Pipeline(pipeline));
+            }
+        }
+        self.target = target;
+
+        Ok(())
+    }
+
+    /// Number of pipelines
+    pub fn len(&self) -> usize {
+        self.pipelines.iter().flatten().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn managed(&self, id: PipelineId) -> &ManagedPipeline {
+        self.pipelines
+            .get(id.0)
+            .and_then(Option::as_ref)
+            .expect("Pipeline was removed")
+    }
+}
+
+impl Drop for PipelineManager {
+    fn drop(&mut self) {
+        for managed in self.pipelines.drain(..).flatten() {
+            for pipeline in managed.pipelines {
+                self.core.defer_destroy(Garbage::Pipeline(pipeline));
+            }
+        }
+    }
+}
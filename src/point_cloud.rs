@@ -0,0 +1,262 @@
+//! Point clouds drawn as camera-facing splats, sized in pixels. Each point is one instance of a
+//! four vertex quad, so sizes aren't limited by the device's point size range and points may be
+//! cut down to circles. Static clouds are uploaded once; changing ones are streamed through a
+//! `RingBuffer` each frame:
+//!
+//! ```ignore
+//! let mut points = PointCloud::new(core.clone(), render_pass, PipelineSettings::default())?;
+//! points.round = true;
+//! let scan = PointCloud::upload(&mut starter_kit.staging_buffer, command_buffer, &scan)?;
+//!
+//! // While drawing, with the descriptor set of the frame's `FrameDataUbo`
+//! points.draw(command_buffer, frame_set, extent, scan.instance(), 0, n_points);
+//! let particles = ring.push(&particles)?;
+//! points.draw_ring(command_buffer, frame_set, extent, &particles);
+//! ```
+use crate::memory::ManagedBuffer;
+use crate::ring_buffer::RingSlice;
+use crate::set_layouts;
+use crate::shader::{shader_with_settings, PipelineSettings};
+use crate::staging_buffer::StagingBuffer;
+use crate::vertex::VertexLayout;
+use crate::SharedCore;
+use anyhow::Result;
+use bytemuck::offset_of;
+use erupt::vk;
+
+/// Bundled vertex shader expanding each `Point` instance into a quad. Takes the camera UBO at
+/// binding 0 of set 0, laid out as in the bundled unlit shader.
+pub const POINT_CLOUD_VERT: &[u8] = include_bytes!("../shaders/point_cloud.vert.spv");
+
+/// Bundled fragment shader outputting the point color, discarding outside the circle if asked
+pub const POINT_CLOUD_FRAG: &[u8] = include_bytes!("../shaders/point_cloud.frag.spv");
+
+/// A point of a `PointCloud`, read once per instance
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Point {
+    pub pos: [f32; 3],
+    pub color: [f32; 3],
+    /// Diameter in pixels
+    pub size: f32,
+}
+
+unsafe impl bytemuck::Zeroable for Point {}
+unsafe impl bytemuck::Pod for Point {}
+
+impl Point {
+    pub fn new(pos: [f32; 3], color: [f32; 3], size: f32) -> Self {
+        Self { pos, color, size }
+    }
+}
+
+impl VertexLayout for Point {
+    fn binding_description() -> vk::VertexInputBindingDescriptionBuilder<'static> {
+        vk::VertexInputBindingDescriptionBuilder::new()
+            .binding(0)
+            .stride(std::mem::size_of::<Self>() as u32)
+            .input_rate(vk::VertexInputRate::INSTANCE)
+    }
+
+    fn attribute_descriptions() -> Vec<vk::VertexInputAttributeDescriptionBuilder<'static>> {
+        let attribute = |location: u32, format: vk::Format, offset: usize| {
+            vk::VertexInputAttributeDescriptionBuilder::new()
+                .binding(0)
+                .location(location)
+                .format(format)
+                .offset(offset as u32)
+        };
+        vec![
+            attribute(0, vk::Format::R32G32B32_SFLOAT, offset_of!(Self, pos)),
+            attribute(1, vk::Format::R32G32B32_SFLOAT, offset_of!(Self, color)),
+            attribute(2, vk::Format::R32_SFLOAT, offset_of!(Self, size)),
+        ]
+    }
+}
+
+/// Push constants of each draw
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct PointParams {
+    /// Framebuffer size in pixels
+    viewport: [f32; 2],
+    size_scale: f32,
+    round_points: u32,
+}
+
+unsafe impl bytemuck::Zeroable for PointParams {}
+unsafe impl bytemuck::Pod for PointParams {}
+
+/// Pipeline drawing buffers of `Point`s
+pub struct PointCloud {
+    /// Cut points down to circles rather than squares
+    pub round: bool,
+    /// Multiplier of every point's size, e.g. to scale with the window's DPI
+    pub size_scale: f32,
+    pipeline: vk::Pipeline,
+    pipeline_layout: vk::PipelineLayout,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    core: SharedCore,
+}
+
+impl PointCloud {
+    /// Create a pipeline built against `render_pass`, reading the camera from a descriptor set
+    /// laid out as `set_layouts::FRAME_SET`. `pipeline_settings` supplies the sample count and
+    /// depth settings; points are always double sided quads.
+    pub fn new(
+        core: SharedCore,
+        render_pass: vk::RenderPass,
+        pipeline_settings: PipelineSettings,
+    ) -> Result<Self> {
+        let bindings = set_layouts::frame_bindings();
+        let create_info = vk::DescriptorSetLayoutCreateInfoBuilder::new().bindings(&bindings);
+        let descriptor_set_layout = unsafe {
+            core.device
+                .create_descriptor_set_layout(&create_info, None, None)
+        }
+        .result()?;
+
+        let push_constant_ranges = [vk::PushConstantRangeBuilder::new()
+            .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+            .offset(0)
+            .size(std::mem::size_of::<PointParams>() as u32)];
+        let layouts = [descriptor_set_layout];
+        let create_info = vk::PipelineLayoutCreateInfoBuilder::new()
+            .push_constant_ranges(&push_constant_ranges)
+            .set_layouts(&layouts);
+        let pipeline_layout =
+            unsafe { core.device.create_pipeline_layout(&create_info, None, None) }.result()?;
+
+        let mut cloud = Self {
+            round: false,
+            size_scale: 1.0,
+            pipeline: vk::Pipeline::null(),
+            pipeline_layout,
+            descriptor_set_layout,
+            core,
+        };
+        cloud.set_render_pass(render_pass, pipeline_settings)?;
+        Ok(cloud)
+    }
+
+    /// Rebuild the pipeline for a new render pass, such as after
+    /// `StarterKit::platform_changed()`. Must not be called while a frame using the pipeline is
+    /// in flight.
+    pub fn set_render_pass(
+        &mut self,
+        render_pass: vk::RenderPass,
+        pipeline_settings: PipelineSettings,
+    ) -> Result<()> {
+        let settings = PipelineSettings {
+            topology: vk::PrimitiveTopology::TRIANGLE_STRIP,
+            cull_mode: vk::CullModeFlags::NONE,
+            ..pipeline_settings
+        };
+        let pipeline = shader_with_settings::<Point>(
+            &self.core,
+            POINT_CLOUD_VERT,
+            POINT_CLOUD_FRAG,
+            &settings,
+            render_pass,
+            self.pipeline_layout,
+        )?;
+        if self.pipeline != vk::Pipeline::null() {
+            self.core.destroy_pipeline(self.pipeline);
+        }
+        self.pipeline = pipeline;
+        Ok(())
+    }
+
+    /// Upload points which don't change to a device-local buffer
+    /// Warning: Assumes an inactive command buffer, which must not be reset until the upload is
+    /// complete
+    pub fn upload(
+        staging: &mut StagingBuffer,
+        command_buffer: vk::CommandBuffer,
+        points: &[Point],
+    ) -> Result<ManagedBuffer> {
+        staging.upload_buffer_pod(command_buffer, vk::BufferUsageFlags::VERTEX_BUFFER, points)
+    }
+
+    /// Draw `count` points from `buffer`, starting `offset` bytes in. `frame_set` holds the
+    /// camera and `extent` is the size of the framebuffer, in pixels.
+    pub fn draw(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        frame_set: vk::DescriptorSet,
+        extent: vk::Extent2D,
+        buffer: vk::Buffer,
+        offset: u64,
+        count: u32,
+    ) {
+        if count == 0 {
+            return;
+        }
+        let params = PointParams {
+            viewport: [extent.width as f32, extent.height as f32],
+            size_scale: self.size_scale,
+            round_points: self.round as u32,
+        };
+        unsafe {
+            self.core.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            self.core.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                set_layouts::FRAME_SET,
+                &[frame_set],
+                &[],
+            );
+            self.core.device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                0,
+                std::mem::size_of::<PointParams>() as u32,
+                &params as *const PointParams as _,
+            );
+            self.core
+                .device
+                .cmd_bind_vertex_buffers(command_buffer, 0, &[buffer], &[offset]);
+            self.core.device.cmd_draw(command_buffer, 4, count, 0, 0);
+        }
+    }
+
+    /// Draw the points pushed to a `RingBuffer` (created with `VERTEX_BUFFER` usage) this frame
+    pub fn draw_ring(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        frame_set: vk::DescriptorSet,
+        extent: vk::Extent2D,
+        points: &RingSlice,
+    ) {
+        let count = points.size / std::mem::size_of::<Point>() as u64;
+        self.draw(
+            command_buffer,
+            frame_set,
+            extent,
+            points.buffer,
+            points.offset,
+            count as u32,
+        );
+    }
+}
+
+impl Drop for PointCloud {
+    fn drop(&mut self) {
+        unsafe {
+            self.core.device.device_wait_idle().unwrap();
+            self.core.destroy_pipeline(self.pipeline);
+            self.core
+                .device
+                .destroy_pipeline_layout(Some(self.pipeline_layout), None);
+            self.core
+                .device
+                .destroy_descriptor_set_layout(Some(self.descriptor_set_layout), None);
+        }
+    }
+}
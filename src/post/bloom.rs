@@ -0,0 +1,272 @@
+use super::{bytes_of, FullscreenPass};
+use crate::render_target::{RenderTarget, RenderTargetSettings};
+use crate::SharedCore;
+use anyhow::{ensure, Result};
+use erupt::vk;
+
+/// Bloom parameters, may be changed between frames
+#[derive(Copy, Clone, Debug)]
+pub struct BloomSettings {
+    /// Brightness above which pixels contribute to the bloom
+    pub threshold: f32,
+    /// Width of the soft transition around the threshold
+    pub knee: f32,
+    /// Strength of the bloom added back onto the scene
+    pub intensity: f32,
+    /// Spread of the upsampling filter, in texels of each level
+    pub radius: f32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self {
+            threshold: 1.0,
+            knee: 0.5,
+            intensity: 0.05,
+            radius: 1.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct BrightPushConstants {
+    threshold: f32,
+    knee: f32,
+}
+
+unsafe impl bytemuck::Zeroable for BrightPushConstants {}
+unsafe impl bytemuck::Pod for BrightPushConstants {}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct DownPushConstants {
+    texel: [f32; 2],
+}
+
+unsafe impl bytemuck::Zeroable for DownPushConstants {}
+unsafe impl bytemuck::Pod for DownPushConstants {}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct UpPushConstants {
+    texel: [f32; 2],
+    radius: f32,
+}
+
+unsafe impl bytemuck::Zeroable for UpPushConstants {}
+unsafe impl bytemuck::Pod for UpPushConstants {}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct CompositePushConstants {
+    intensity: f32,
+}
+
+unsafe impl bytemuck::Zeroable for CompositePushConstants {}
+unsafe impl bytemuck::Pod for CompositePushConstants {}
+
+/// Multi-pass bloom over an HDR `RenderTarget`: a bright pass into a half resolution target, a
+/// chain of downsamples, a chain of upsamples accumulating back up the chain, and a composite of
+/// the result over the scene. The composite is written to `output()`, which is HDR and typically
+/// fed to a `Tonemap`.
+///
+/// ```ignore
+/// // In swapchain_resize(), after resizing the scene target
+/// bloom.resize(extent, &scene)?;
+/// tonemap.set_input(bloom.output());
+///
+/// // Each frame, outside of any render pass, after the scene has been drawn
+/// bloom.record(command_buffer);
+/// ```
+pub struct Bloom {
+    bright: FullscreenPass,
+    down: FullscreenPass,
+    up: FullscreenPass,
+    composite: FullscreenPass,
+    /// Level 0 is the bright pass output at half resolution, each further level is half the last
+    down_targets: Vec<RenderTarget>,
+    /// Upsampled results, one per level except the smallest
+    up_targets: Vec<RenderTarget>,
+    output: RenderTarget,
+    pub settings: BloomSettings,
+}
+
+impl Bloom {
+    /// Create a bloom chain with the given number of levels (at least 1). `resize()` must be
+    /// called before use.
+    pub fn new(core: SharedCore, vr: bool, levels: usize, settings: BloomSettings) -> Result<Self> {
+        ensure!(levels > 0, "Bloom requires at least one level");
+
+        let target_settings = RenderTargetSettings::color(vk::Format::R16G16B16A16_SFLOAT, vr);
+        let output = RenderTarget::new(core.clone(), target_settings)?;
+        let down_targets = (0..levels)
+            .map(|_| RenderTarget::new(core.clone(), target_settings))
+            .collect::<Result<Vec<_>>>()?;
+        let up_targets = (1..levels)
+            .map(|_| RenderTarget::new(core.clone(), target_settings))
+            .collect::<Result<Vec<_>>>()?;
+
+        // All of the targets share settings, so their render passes are compatible
+        let render_pass = output.render_pass();
+        let chain_sets = levels as u32 - 1;
+
+        let bright = FullscreenPass::new(
+            core.clone(),
+            include_bytes!("../../shaders/bloom_bright.frag.spv"),
+            render_pass,
+            1,
+            std::mem::size_of::<BrightPushConstants>() as u32,
+        )?;
+
+        let down = FullscreenPass::new_multi(
+            core.clone(),
+            include_bytes!("../../shaders/bloom_down.frag.spv"),
+            render_pass,
+            1,
+            chain_sets.max(1),
+            std::mem::size_of::<DownPushConstants>() as u32,
+        )?;
+
+        let up = FullscreenPass::new_multi(
+            core.clone(),
+            include_bytes!("../../shaders/bloom_up.frag.spv"),
+            render_pass,
+            2,
+            chain_sets.max(1),
+            std::mem::size_of::<UpPushConstants>() as u32,
+        )?;
+
+        let composite = FullscreenPass::new(
+            core,
+            include_bytes!("../../shaders/bloom_composite.frag.spv"),
+            render_pass,
+            2,
+            std::mem::size_of::<CompositePushConstants>() as u32,
+        )?;
+
+        Ok(Self {
+            bright,
+            down,
+            up,
+            composite,
+            down_targets,
+            up_targets,
+            output,
+            settings,
+        })
+    }
+
+    /// Resize the chain to match the input, and bind the input. Call this after the input target
+    /// is resized.
+    pub fn resize(&mut self, extent: vk::Extent2D, input: &RenderTarget) -> Result<()> {
+        self.output.resize(extent)?;
+        for (level, target) in self.down_targets.iter_mut().enumerate() {
+            target.resize(level_extent(extent, level))?;
+        }
+        for (level, target) in self.up_targets.iter_mut().enumerate() {
+            target.resize(level_extent(extent, level))?;
+        }
+
+        self.bright.set_inputs(&[input.color_view()]);
+        for level in 1..self.down_targets.len() {
+            self.down
+                .set_inputs_of(level - 1, &[self.down_targets[level - 1].color_view()]);
+        }
+        for level in 0..self.up_targets.len() {
+            self.up.set_inputs_of(
+                level,
+                &[
+                    self.down_targets[level].color_view(),
+                    self.lower(level).color_view(),
+                ],
+            );
+        }
+        self.composite
+            .set_inputs(&[input.color_view(), self.accumulated().color_view()]);
+
+        Ok(())
+    }
+
+    /// Record the bloom passes. Must be called outside of any render pass, after the input has
+    /// been rendered.
+    pub fn record(&self, command_buffer: vk::CommandBuffer) {
+        const CLEAR: [f32; 4] = [0.; 4];
+
+        // Bright pass
+        let target = &self.down_targets[0];
+        let push = BrightPushConstants {
+            threshold: self.settings.threshold,
+            knee: self.settings.knee,
+        };
+        target.begin_pass(command_buffer, CLEAR);
+        self.bright.draw(command_buffer, bytes_of(&push));
+        target.end_pass(command_buffer);
+
+        // Downsample chain
+        for level in 1..self.down_targets.len() {
+            let target = &self.down_targets[level];
+            let push = DownPushConstants {
+                texel: texel_size(self.down_targets[level - 1].extent()),
+            };
+            target.begin_pass(command_buffer, CLEAR);
+            self.down
+                .draw_with(command_buffer, level - 1, bytes_of(&push));
+            target.end_pass(command_buffer);
+        }
+
+        // Upsample chain, from the smallest level back up
+        for level in (0..self.up_targets.len()).rev() {
+            let target = &self.up_targets[level];
+            let push = UpPushConstants {
+                texel: texel_size(self.lower(level).extent()),
+                radius: self.settings.radius,
+            };
+            target.begin_pass(command_buffer, CLEAR);
+            self.up.draw_with(command_buffer, level, bytes_of(&push));
+            target.end_pass(command_buffer);
+        }
+
+        // Composite
+        let push = CompositePushConstants {
+            intensity: self.settings.intensity,
+        };
+        self.output.begin_pass(command_buffer, CLEAR);
+        self.composite.draw(command_buffer, bytes_of(&push));
+        self.output.end_pass(command_buffer);
+    }
+
+    /// Scene with bloom applied, at the resolution of the input
+    pub fn output(&self) -> &RenderTarget {
+        &self.output
+    }
+
+    pub fn levels(&self) -> usize {
+        self.down_targets.len()
+    }
+
+    /// Target upsampled into the given level
+    fn lower(&self, level: usize) -> &RenderTarget {
+        if level + 1 == self.up_targets.len() {
+            &self.down_targets[level + 1]
+        } else {
+            &self.up_targets[level + 1]
+        }
+    }
+
+    /// Final result of the chain, at half resolution
+    fn accumulated(&self) -> &RenderTarget {
+        self.up_targets.first().unwrap_or(&self.down_targets[0])
+    }
+}
+
+fn level_extent(extent: vk::Extent2D, level: usize) -> vk::Extent2D {
+    vk::Extent2D {
+        width: (extent.width >> (level + 1)).max(1),
+        height: (extent.height >> (level + 1)).max(1),
+    }
+}
+
+fn texel_size(extent: vk::Extent2D) -> [f32; 2] {
+    [1. / extent.width as f32, 1. / extent.height as f32]
+}
@@ -0,0 +1,96 @@
+use super::{bytes_of, FullscreenPass};
+use crate::SharedCore;
+use anyhow::Result;
+use erupt::vk;
+
+/// How `DebugView` shows its input, may be changed between frames
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DebugMode {
+    /// The input's color as is
+    Color,
+    /// Depth of a perspective projection with the given clip planes, from black at the near
+    /// plane to white at the far plane
+    Depth {
+        near: f32,
+        far: f32,
+        reverse_z: bool,
+    },
+    /// Normals mapped from -1 to 1 into colors, such as `GBuffer::normal_view()`
+    Normals,
+    /// The first component in grayscale, for single channel images such as `Ssao::occlusion()`
+    /// or the depth of orthographic shadow maps
+    Grayscale,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct DebugViewPushConstants {
+    mode: i32,
+    near: f32,
+    far: f32,
+    reverse_z: u32,
+    encode_srgb: u32,
+}
+
+unsafe impl bytemuck::Zeroable for DebugViewPushConstants {}
+unsafe impl bytemuck::Pod for DebugViewPushConstants {}
+
+/// Shows an intermediate image, such as a depth buffer or G-buffer normals, in the output render
+/// pass. Call `set_input()` each time the input is resized, before drawing.
+pub struct DebugView {
+    pass: FullscreenPass,
+    pub mode: DebugMode,
+    /// sRGB encode the output, for a UNORM output render pass (see
+    /// `RenderPassSettings::encode_srgb()`)
+    pub encode_srgb: bool,
+}
+
+impl DebugView {
+    pub fn new(
+        core: SharedCore,
+        output_render_pass: vk::RenderPass,
+        mode: DebugMode,
+    ) -> Result<Self> {
+        let pass = FullscreenPass::new(
+            core,
+            include_bytes!("../../shaders/debug_view.frag.spv"),
+            output_render_pass,
+            1,
+            std::mem::size_of::<DebugViewPushConstants>() as u32,
+        )?;
+        Ok(Self {
+            pass,
+            mode,
+            encode_srgb: false,
+        })
+    }
+
+    /// Bind the input, a `_2D_ARRAY` view which is in `layout` when drawn. Depth is read from
+    /// the first component, so views of depth images must only cover the depth aspect (such as
+    /// `GBuffer::depth_view()`, in `DEPTH_STENCIL_READ_ONLY_OPTIMAL`).
+    pub fn set_input(&self, view: vk::ImageView, layout: vk::ImageLayout) {
+        self.pass.set_inputs_in_layouts(0, &[(view, layout)]);
+    }
+
+    /// Draw into the currently active render pass
+    pub fn draw(&self, command_buffer: vk::CommandBuffer) {
+        let (mode, near, far, reverse_z) = match self.mode {
+            DebugMode::Color => (0, 0.0, 1.0, false),
+            DebugMode::Depth {
+                near,
+                far,
+                reverse_z,
+            } => (1, near, far, reverse_z),
+            DebugMode::Normals => (2, 0.0, 1.0, false),
+            DebugMode::Grayscale => (3, 0.0, 1.0, false),
+        };
+        let push = DebugViewPushConstants {
+            mode,
+            near,
+            far,
+            reverse_z: reverse_z.into(),
+            encode_srgb: self.encode_srgb.into(),
+        };
+        self.pass.draw(command_buffer, bytes_of(&push));
+    }
+}
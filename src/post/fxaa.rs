@@ -0,0 +1,89 @@
+use super::FullscreenPass;
+use crate::render_target::RenderTarget;
+use crate::SharedCore;
+use anyhow::Result;
+use erupt::vk;
+
+/// FXAA parameters, may be changed between frames
+#[derive(Copy, Clone, Debug)]
+pub struct FxaaSettings {
+    /// Amount of subpixel aliasing removal, from 0 (off) to 1 (softest)
+    pub subpixel: f32,
+    /// Minimum local contrast, relative to the brightest neighbor, to be treated as an edge
+    pub edge_threshold: f32,
+    /// Minimum absolute contrast to be treated as an edge, avoids processing dark areas
+    pub edge_threshold_min: f32,
+}
+
+impl Default for FxaaSettings {
+    fn default() -> Self {
+        Self {
+            subpixel: 0.75,
+            edge_threshold: 0.166,
+            edge_threshold_min: 0.0833,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct FxaaPushConstants {
+    texel: [f32; 2],
+    subpixel: f32,
+    edge_threshold: f32,
+    edge_threshold_min: f32,
+}
+
+unsafe impl bytemuck::Zeroable for FxaaPushConstants {}
+unsafe impl bytemuck::Pod for FxaaPushConstants {}
+
+/// Fast approximate anti-aliasing of a `RenderTarget` into the output render pass. Much cheaper
+/// than MSAA, at the cost of some blurring. Works best on LDR (tonemapped) input. Call
+/// `set_input()` each time the input target is resized, before drawing.
+pub struct Fxaa {
+    pass: FullscreenPass,
+    texel: [f32; 2],
+    pub settings: FxaaSettings,
+}
+
+impl Fxaa {
+    pub fn new(
+        core: SharedCore,
+        output_render_pass: vk::RenderPass,
+        settings: FxaaSettings,
+    ) -> Result<Self> {
+        let pass = FullscreenPass::new(
+            core,
+            include_bytes!("../../shaders/fxaa.frag.spv"),
+            output_render_pass,
+            1,
+            std::mem::size_of::<FxaaPushConstants>() as u32,
+        )?;
+        Ok(Self {
+            pass,
+            texel: [0.; 2],
+            settings,
+        })
+    }
+
+    /// Bind the input target. Call this after the target is resized.
+    pub fn set_input(&mut self, input: &RenderTarget) {
+        let extent = input.extent();
+        self.texel = [1. / extent.width as f32, 1. / extent.height as f32];
+        self.pass.set_inputs(&[input.color_view()]);
+    }
+
+    /// Draw into the currently active render pass
+    pub fn draw(&self, command_buffer: vk::CommandBuffer) {
+        let push = FxaaPushConstants {
+            texel: self.texel,
+            subpixel: self.settings.subpixel,
+            edge_threshold: self.settings.edge_threshold,
+            edge_threshold_min: self.settings.edge_threshold_min,
+        };
+        self.pass.draw(
+            command_buffer,
+            bytemuck::cast_slice(std::slice::from_ref(&push)),
+        );
+    }
+}
@@ -0,0 +1,337 @@
+//! Post-processing passes. Render the scene into a `RenderTarget`, then run these passes on its
+//! color image. Each pass draws a fullscreen triangle into whichever render pass it was created
+//! against, so the final pass in a chain is typically drawn inside the StarterKit's swapchain pass.
+use crate::shader::fullscreen_pipeline;
+use crate::{Core, SharedCore};
+use anyhow::Result;
+use erupt::vk;
+
+mod bloom;
+mod debug_view;
+mod fxaa;
+mod overlay;
+mod ssao;
+mod stereo_preview;
+mod tonemap;
+mod upscale;
+pub use bloom::{Bloom, BloomSettings};
+pub use debug_view::{DebugMode, DebugView};
+pub use fxaa::{Fxaa, FxaaSettings};
+pub use overlay::AttachmentOverlay;
+pub use ssao::{Ssao, SsaoSettings, SSAO_GEOMETRY_FRAG, SSAO_GEOMETRY_VERT};
+pub use stereo_preview::StereoPreview;
+pub use tonemap::{Tonemap, TonemapOperator, TonemapSettings};
+pub use upscale::Upscale;
+
+/// Bundled vertex shader producing a fullscreen triangle from `gl_VertexIndex`, for pipelines
+/// built with `shader::fullscreen_pipeline()`
+pub const FULLSCREEN_VERT: &[u8] = include_bytes!("../../shaders/fullscreen.vert.spv");
+
+/// Draw the vertex-less fullscreen triangle with the bound pipeline, which takes no vertex
+/// buffers (see `FULLSCREEN_VERT`)
+pub fn draw_fullscreen_triangle(core: &Core, command_buffer: vk::CommandBuffer) {
+    unsafe {
+        core.device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    }
+}
+
+/// A fullscreen pipeline sampling one or more input images. The descriptor set layout is fixed:
+/// binding 0 is a clamp-to-edge `sampler` (linear, unless created with `new_with_filter()`), and
+/// bindings `1..=n_inputs` are
+/// `texture2DArray`s (indexed by `gl_ViewIndex` in VR). Fragment shaders receive the
+/// interpolated uv at location 0.
+///
+/// A pass may own several descriptor sets, so that one pipeline can be used for several steps of
+/// a chain with different inputs (see `new_multi()`).
+pub struct FullscreenPass {
+    pipeline: vk::Pipeline,
+    pipeline_layout: vk::PipelineLayout,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    sampler: vk::Sampler,
+    n_inputs: u32,
+    push_constant_size: u32,
+    core: SharedCore,
+}
+
+impl FullscreenPass {
+    pub fn new(
+        core: SharedCore,
+        fragment_src: &[u8],
+        render_pass: vk::RenderPass,
+        n_inputs: u32,
+        push_constant_size: u32,
+    ) -> Result<Self> {
+        Self::new_multi(
+            core,
+            fragment_src,
+            render_pass,
+            n_inputs,
+            1,
+            push_constant_size,
+        )
+    }
+
+    /// Create a pass with `n_sets` descriptor sets, each with its own inputs
+    pub fn new_multi(
+        core: SharedCore,
+        fragment_src: &[u8],
+        render_pass: vk::RenderPass,
+        n_inputs: u32,
+        n_sets: u32,
+        push_constant_size: u32,
+    ) -> Result<Self> {
+        Self::new_with_filter(
+            core,
+            fragment_src,
+            render_pass,
+            n_inputs,
+            n_sets,
+            push_constant_size,
+            vk::Filter::LINEAR,
+        )
+    }
+
+    /// Like `new_multi()`, sampling the inputs with `filter`. Use `NEAREST` for inputs which
+    /// must not be blended between texels, such as positions, or formats which don't support
+    /// linear filtering.
+    pub fn new_with_filter(
+        core: SharedCore,
+        fragment_src: &[u8],
+        render_pass: vk::RenderPass,
+        n_inputs: u32,
+        n_sets: u32,
+        push_constant_size: u32,
+        filter: vk::Filter,
+    ) -> Result<Self> {
+        // Sampler
+        let create_info = vk::SamplerCreateInfoBuilder::new()
+            .mag_filter(filter)
+            .min_filter(filter)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .anisotropy_enable(false)
+            .max_anisotropy(1.)
+            .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
+            .unnormalized_coordinates(false)
+            .compare_enable(false)
+            .compare_op(vk::CompareOp::ALWAYS)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .mip_lod_bias(0.)
+            .min_lod(0.)
+            .max_lod(0.);
+
+        let sampler = unsafe { core.device.create_sampler(&create_info, None, None) }.result()?;
+
+        // Create descriptor set layout
+        let mut bindings = vec![vk::DescriptorSetLayoutBindingBuilder::new()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)];
+        bindings.extend((1..=n_inputs).map(|binding| {
+            vk::DescriptorSetLayoutBindingBuilder::new()
+                .binding(binding)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+        }));
+
+        let descriptor_set_layout_ci =
+            vk::DescriptorSetLayoutCreateInfoBuilder::new().bindings(&bindings);
+
+        let descriptor_set_layout = unsafe {
+            core.device
+                .create_descriptor_set_layout(&descriptor_set_layout_ci, None, None)
+        }
+        .result()?;
+
+        // Create descriptor pool
+        let mut pool_sizes = vec![vk::DescriptorPoolSizeBuilder::new()
+            ._type(vk::DescriptorType::SAMPLER)
+            .descriptor_count(n_sets)];
+        if n_inputs > 0 {
+            pool_sizes.push(
+                vk::DescriptorPoolSizeBuilder::new()
+                    ._type(vk::DescriptorType::SAMPLED_IMAGE)
+                    .descriptor_count(n_inputs * n_sets),
+            );
+        }
+
+        let create_info = vk::DescriptorPoolCreateInfoBuilder::new()
+            .pool_sizes(&pool_sizes)
+            .max_sets(n_sets);
+
+        let descriptor_pool =
+            unsafe { core.device.create_descriptor_pool(&create_info, None, None) }.result()?;
+
+        // Create descriptor sets
+        let layouts = vec![descriptor_set_layout; n_sets as usize];
+        let create_info = vk::DescriptorSetAllocateInfoBuilder::new()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&layouts);
+
+        let descriptor_sets = core.allocate_descriptor_sets(&create_info)?;
+
+        // Write the sampler; the inputs are written by set_inputs()
+        let sampler_infos = [vk::DescriptorImageInfoBuilder::new().sampler(sampler)];
+        for &descriptor_set in &descriptor_sets {
+            let writes = [vk::WriteDescriptorSetBuilder::new()
+                .image_info(&sampler_infos)
+                .descriptor_type(vk::DescriptorType::SAMPLER)
+                .dst_set(descriptor_set)
+                .dst_binding(0)
+                .dst_array_element(0)];
+
+            unsafe {
+                core.device.update_descriptor_sets(&writes, &[]);
+            }
+        }
+
+        // Pipeline layout
+        let push_constant_ranges = [vk::PushConstantRangeBuilder::new()
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .offset(0)
+            .size(push_constant_size)];
+
+        let descriptor_set_layouts = [descriptor_set_layout];
+        let mut create_info =
+            vk::PipelineLayoutCreateInfoBuilder::new().set_layouts(&descriptor_set_layouts);
+        if push_constant_size > 0 {
+            create_info = create_info.push_constant_ranges(&push_constant_ranges);
+        }
+
+        let pipeline_layout =
+            unsafe { core.device.create_pipeline_layout(&create_info, None, None) }.result()?;
+
+        // Pipeline
+        let pipeline = fullscreen_pipeline(
+            &core,
+            FULLSCREEN_VERT,
+            fragment_src,
+            render_pass,
+            pipeline_layout,
+        )?;
+
+        Ok(Self {
+            pipeline,
+            pipeline_layout,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_sets: descriptor_sets.to_vec(),
+            sampler,
+            n_inputs,
+            push_constant_size,
+            core,
+        })
+    }
+
+    /// Point the inputs at the given image views, which must be in `SHADER_READ_ONLY_OPTIMAL`
+    /// when drawn. Must not be called while a frame using this pass is in flight (typically it
+    /// is called from `swapchain_resize()`, after the render targets are resized).
+    pub fn set_inputs(&self, views: &[vk::ImageView]) {
+        self.set_inputs_of(0, views)
+    }
+
+    /// Like `set_inputs()`, for the given descriptor set
+    pub fn set_inputs_of(&self, set: usize, views: &[vk::ImageView]) {
+        let inputs: Vec<_> = views
+            .iter()
+            .map(|&view| (view, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL))
+            .collect();
+        self.set_inputs_in_layouts(set, &inputs)
+    }
+
+    /// Like `set_inputs_of()`, for inputs in other layouts when drawn, such as depth images in
+    /// `DEPTH_STENCIL_READ_ONLY_OPTIMAL`
+    pub fn set_inputs_in_layouts(&self, set: usize, inputs: &[(vk::ImageView, vk::ImageLayout)]) {
+        assert_eq!(inputs.len() as u32, self.n_inputs, "Wrong number of inputs");
+        let image_infos: Vec<_> = inputs
+            .iter()
+            .map(|&(view, layout)| {
+                [vk::DescriptorImageInfoBuilder::new()
+                    .image_layout(layout)
+                    .image_view(view)]
+            })
+            .collect();
+
+        let writes: Vec<_> = image_infos
+            .iter()
+            .enumerate()
+            .map(|(i, info)| {
+                vk::WriteDescriptorSetBuilder::new()
+                    .image_info(info)
+                    .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                    .dst_set(self.descriptor_sets[set])
+                    .dst_binding(i as u32 + 1)
+                    .dst_array_element(0)
+            })
+            .collect();
+
+        unsafe {
+            self.core.device.update_descriptor_sets(&writes, &[]);
+        }
+    }
+
+    /// Draw the fullscreen triangle. Assumes a render pass compatible with the one this pass was
+    /// created against is active, with the viewport and scissor set. Compatibility is by format,
+    /// so a pass created against one `RenderTarget` can draw into any target with the same settings.
+    pub fn draw(&self, command_buffer: vk::CommandBuffer, push_constants: &[u8]) {
+        self.draw_with(command_buffer, 0, push_constants)
+    }
+
+    /// Like `draw()`, using the given descriptor set
+    pub fn draw_with(&self, command_buffer: vk::CommandBuffer, set: usize, push_constants: &[u8]) {
+        debug_assert_eq!(push_constants.len() as u32, self.push_constant_size);
+        unsafe {
+            self.core.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            self.core.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_sets[set]],
+                &[],
+            );
+            if !push_constants.is_empty() {
+                self.core.device.cmd_push_constants(
+                    command_buffer,
+                    self.pipeline_layout,
+                    vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    push_constants.len() as u32,
+                    push_constants.as_ptr() as _,
+                );
+            }
+        }
+        draw_fullscreen_triangle(&self.core, command_buffer);
+    }
+}
+
+impl Drop for FullscreenPass {
+    fn drop(&mut self) {
+        unsafe {
+            self.core.device.device_wait_idle().unwrap();
+            self.core.destroy_descriptor_pool(self.descriptor_pool);
+            self.core
+                .device
+                .destroy_descriptor_set_layout(Some(self.descriptor_set_layout), None);
+            self.core
+                .device
+                .destroy_pipeline_layout(Some(self.pipeline_layout), None);
+            self.core.destroy_pipeline(self.pipeline);
+            self.core.device.destroy_sampler(Some(self.sampler), None);
+        }
+    }
+}
+
+/// View push constants as bytes
+fn bytes_of<T: bytemuck::Pod>(value: &T) -> &[u8] {
+    bytemuck::cast_slice(std::slice::from_ref(value))
+}
@@ -0,0 +1,129 @@
+use super::{DebugMode, DebugView};
+use crate::split_screen::ViewportRegion;
+use crate::SharedCore;
+use anyhow::Result;
+use erupt::vk;
+
+/// Intermediate attachments (depth, normals, shadow maps, occlusion...) shown as tiles along the
+/// bottom of the output, to debug multi-pass pipelines without a frame debugger. Draw it last in
+/// the swapchain pass, and toggle it at runtime.
+///
+/// ```ignore
+/// let mut overlay = AttachmentOverlay::new(core.clone(), render_pass, false);
+/// let depth = overlay.add(DebugMode::Depth { near: 0.1, far: 100.0, reverse_z: false })?;
+/// let normals = overlay.add(DebugMode::Normals)?;
+/// // In swapchain_resize(), after the inputs are resized
+/// let read_only = vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL;
+/// overlay.set_input(depth, gbuffer.depth_view(), read_only);
+/// overlay.set_input(normals, gbuffer.normal_view(), vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+/// // On a key press
+/// overlay.toggle();
+/// // At the end of the swapchain pass
+/// overlay.draw(command_buffer, extent);
+/// ```
+pub struct AttachmentOverlay {
+    tiles: Vec<DebugView>,
+    output_render_pass: vk::RenderPass,
+    /// sRGB encode the tiles, for a UNORM output render pass (see
+    /// `RenderPassSettings::encode_srgb()`)
+    encode_srgb: bool,
+    /// Whether `draw()` draws anything
+    pub enabled: bool,
+    /// Height of the tiles as a fraction of the output's. Tiles keep the output's aspect ratio,
+    /// and shrink to fit side by side.
+    pub tile_height: f32,
+    core: SharedCore,
+}
+
+impl AttachmentOverlay {
+    /// An enabled overlay with no tiles
+    pub fn new(core: SharedCore, output_render_pass: vk::RenderPass, encode_srgb: bool) -> Self {
+        Self {
+            tiles: vec![],
+            output_render_pass,
+            encode_srgb,
+            enabled: true,
+            tile_height: 0.25,
+            core,
+        }
+    }
+
+    /// Add a tile right of the others, returning its index. Its input must be set with
+    /// `set_input()` before drawing.
+    pub fn add(&mut self, mode: DebugMode) -> Result<usize> {
+        let mut tile = DebugView::new(self.core.clone(), self.output_render_pass, mode)?;
+        tile.encode_srgb = self.encode_srgb;
+        self.tiles.push(tile);
+        Ok(self.tiles.len() - 1)
+    }
+
+    /// Bind the input of a tile, see `DebugView::set_input()`. Call this after the input is
+    /// resized.
+    pub fn set_input(&self, tile: usize, view: vk::ImageView, layout: vk::ImageLayout) {
+        self.tiles[tile].set_input(view, layout);
+    }
+
+    /// A tile, such as to change its `DebugMode`
+    pub fn tile_mut(&mut self, tile: usize) -> &mut DebugView {
+        &mut self.tiles[tile]
+    }
+
+    /// Number of tiles
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    /// Show or hide the overlay
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    /// Where each tile is drawn in an output of `extent`, left to right from the bottom left
+    /// corner
+    pub fn regions(&self, extent: vk::Extent2D) -> Vec<ViewportRegion> {
+        if self.tiles.is_empty() || extent.width == 0 || extent.height == 0 {
+            return vec![];
+        }
+
+        let aspect = extent.width as f32 / extent.height as f32;
+        let mut height = extent.height as f32 * self.tile_height.clamp(0.0, 1.0);
+        let width = (height * aspect).min(extent.width as f32 / self.tiles.len() as f32);
+        height = height.min(width / aspect);
+        let (width, height) = ((width as u32).max(1), (height as u32).max(1));
+
+        (0..self.tiles.len() as u32)
+            .map(|i| {
+                ViewportRegion::from_rect(vk::Rect2D {
+                    offset: vk::Offset2D {
+                        x: (i * width) as i32,
+                        y: (extent.height - height) as i32,
+                    },
+                    extent: vk::Extent2D { width, height },
+                })
+            })
+            .collect()
+    }
+
+    /// Draw the tiles into the currently active render pass, if enabled. Afterwards the viewport
+    /// and scissor cover all of `extent`.
+    pub fn draw(&self, command_buffer: vk::CommandBuffer, extent: vk::Extent2D) {
+        if !self.enabled || self.tiles.is_empty() {
+            return;
+        }
+
+        for (tile, region) in self.tiles.iter().zip(self.regions(extent)) {
+            region.set(&self.core, command_buffer);
+            tile.draw(command_buffer);
+        }
+
+        ViewportRegion::from_rect(vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        })
+        .set(&self.core, command_buffer);
+    }
+}
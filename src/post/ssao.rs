@@ -0,0 +1,212 @@
+use super::{bytes_of, FullscreenPass};
+use crate::render_target::{RenderTarget, RenderTargetSettings};
+use crate::{Core, SharedCore};
+use anyhow::Result;
+use erupt::vk;
+
+/// Bundled vertex shader for the SSAO geometry prepass. Takes positions at location 0, the
+/// camera UBO at binding 0 (laid out as in the bundled unlit shader) and the model transform in
+/// push constants, so pipeline layouts need `material::push_constant_range()`.
+pub const SSAO_GEOMETRY_VERT: &[u8] = include_bytes!("../../shaders/ssao_geometry.vert.spv");
+
+/// Bundled fragment shader for the SSAO geometry prepass
+pub const SSAO_GEOMETRY_FRAG: &[u8] = include_bytes!("../../shaders/ssao_geometry.frag.spv");
+
+/// Ambient occlusion parameters, may be changed between frames
+#[derive(Copy, Clone, Debug)]
+pub struct SsaoSettings {
+    /// Radius of the sampled hemisphere, in world units
+    pub radius: f32,
+    /// Offset ignoring occluders nearly coplanar with the surface, scaled by depth
+    pub bias: f32,
+    /// Strength of the darkening
+    pub intensity: f32,
+    /// Half the vertical focal length of the projection (`0.5 / tan(fov_y / 2)`), used to size
+    /// the sampled area on screen
+    pub projection_scale: f32,
+}
+
+impl Default for SsaoSettings {
+    fn default() -> Self {
+        Self {
+            radius: 0.5,
+            bias: 0.001,
+            intensity: 1.0,
+            projection_scale: 0.5 / (45.0f32.to_radians() / 2.).tan(),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct SsaoPushConstants {
+    texel: [f32; 2],
+    radius: f32,
+    bias: f32,
+    intensity: f32,
+    projection_scale: f32,
+}
+
+unsafe impl bytemuck::Zeroable for SsaoPushConstants {}
+unsafe impl bytemuck::Pod for SsaoPushConstants {}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct BlurPushConstants {
+    texel: [f32; 2],
+}
+
+unsafe impl bytemuck::Zeroable for BlurPushConstants {}
+unsafe impl bytemuck::Pod for BlurPushConstants {}
+
+/// Screen-space ambient occlusion. Requires a geometry prepass: draw the scene into a target
+/// created with `Ssao::geometry_settings()`, using `SSAO_GEOMETRY_VERT` and `SSAO_GEOMETRY_FRAG`
+/// (or shaders writing world position to xyz and linear depth to w), cleared to zero. The
+/// occlusion is computed from it, blurred, and multiplied onto the scene color in `output()`.
+/// The StarterKit sets this up with `Settings::ssao`.
+///
+/// ```ignore
+/// // In swapchain_resize(), after resizing the scene and geometry targets
+/// ssao.resize(extent, &scene, &geometry)?;
+/// tonemap.set_input(ssao.output());
+///
+/// // Each frame, outside of any render pass, after the scene and geometry have been drawn
+/// ssao.record(command_buffer);
+/// ```
+pub struct Ssao {
+    occlusion: FullscreenPass,
+    blur: FullscreenPass,
+    composite: FullscreenPass,
+    occlusion_target: RenderTarget,
+    blur_target: RenderTarget,
+    output: RenderTarget,
+    pub settings: SsaoSettings,
+}
+
+impl Ssao {
+    /// Settings for the geometry prepass target. Full floats, as half floats are too coarse for
+    /// the world positions the normals are reconstructed from; the target is sampled without
+    /// filtering.
+    pub fn geometry_settings(core: &Core, vr: bool) -> RenderTargetSettings {
+        RenderTargetSettings {
+            color_format: vk::Format::R32G32B32A32_SFLOAT,
+            depth_format: Some(core.depth_format),
+            vr,
+            extra_usage: vk::ImageUsageFlags::empty(),
+            extra_depth_usage: vk::ImageUsageFlags::empty(),
+            reverse_z: false,
+            samples: vk::SampleCountFlagBits::_1,
+        }
+    }
+
+    /// `resize()` must be called before use
+    pub fn new(core: SharedCore, vr: bool, settings: SsaoSettings) -> Result<Self> {
+        let ao_settings = RenderTargetSettings::color(vk::Format::R8_UNORM, vr);
+        let occlusion_target = RenderTarget::new(core.clone(), ao_settings)?;
+        let blur_target = RenderTarget::new(core.clone(), ao_settings)?;
+        let output = RenderTarget::new(
+            core.clone(),
+            RenderTargetSettings::color(vk::Format::R16G16B16A16_SFLOAT, vr),
+        )?;
+
+        // Positions are sampled unfiltered, as blending them across silhouettes would place
+        // surfaces between the objects
+        let occlusion = FullscreenPass::new_with_filter(
+            core.clone(),
+            include_bytes!("../../shaders/ssao.frag.spv"),
+            occlusion_target.render_pass(),
+            1,
+            1,
+            std::mem::size_of::<SsaoPushConstants>() as u32,
+            vk::Filter::NEAREST,
+        )?;
+
+        let blur = FullscreenPass::new_with_filter(
+            core.clone(),
+            include_bytes!("../../shaders/ssao_blur.frag.spv"),
+            blur_target.render_pass(),
+            2,
+            1,
+            std::mem::size_of::<BlurPushConstants>() as u32,
+            vk::Filter::NEAREST,
+        )?;
+
+        let composite = FullscreenPass::new(
+            core,
+            include_bytes!("../../shaders/ssao_composite.frag.spv"),
+            output.render_pass(),
+            2,
+            0,
+        )?;
+
+        Ok(Self {
+            occlusion,
+            blur,
+            composite,
+            occlusion_target,
+            blur_target,
+            output,
+            settings,
+        })
+    }
+
+    /// Resize the intermediate targets and bind the inputs. Call this after the scene and
+    /// geometry targets are resized.
+    pub fn resize(
+        &mut self,
+        extent: vk::Extent2D,
+        scene: &RenderTarget,
+        geometry: &RenderTarget,
+    ) -> Result<()> {
+        self.occlusion_target.resize(extent)?;
+        self.blur_target.resize(extent)?;
+        self.output.resize(extent)?;
+
+        self.occlusion.set_inputs(&[geometry.color_view()]);
+        self.blur
+            .set_inputs(&[self.occlusion_target.color_view(), geometry.color_view()]);
+        self.composite
+            .set_inputs(&[scene.color_view(), self.blur_target.color_view()]);
+
+        Ok(())
+    }
+
+    /// Record the occlusion, blur and composite passes. Must be called outside of any render
+    /// pass, after the scene and geometry have been rendered.
+    pub fn record(&self, command_buffer: vk::CommandBuffer) {
+        const CLEAR: [f32; 4] = [1.; 4];
+
+        let extent = self.output.extent();
+        let texel = [1. / extent.width as f32, 1. / extent.height as f32];
+
+        let push = SsaoPushConstants {
+            texel,
+            radius: self.settings.radius,
+            bias: self.settings.bias,
+            intensity: self.settings.intensity,
+            projection_scale: self.settings.projection_scale,
+        };
+        self.occlusion_target.begin_pass(command_buffer, CLEAR);
+        self.occlusion.draw(command_buffer, bytes_of(&push));
+        self.occlusion_target.end_pass(command_buffer);
+
+        let push = BlurPushConstants { texel };
+        self.blur_target.begin_pass(command_buffer, CLEAR);
+        self.blur.draw(command_buffer, bytes_of(&push));
+        self.blur_target.end_pass(command_buffer);
+
+        self.output.begin_pass(command_buffer, CLEAR);
+        self.composite.draw(command_buffer, &[]);
+        self.output.end_pass(command_buffer);
+    }
+
+    /// Scene with ambient occlusion applied
+    pub fn output(&self) -> &RenderTarget {
+        &self.output
+    }
+
+    /// The blurred occlusion term, useful for debugging or for applying to ambient light only
+    pub fn occlusion(&self) -> &RenderTarget {
+        &self.blur_target
+    }
+}
@@ -0,0 +1,71 @@
+use super::{bytes_of, FullscreenPass};
+use crate::render_target::RenderTarget;
+use crate::SharedCore;
+use anyhow::Result;
+use erupt::vk;
+
+/// Draws both layers of a two-view (VR) `RenderTarget` side by side into a single-view render
+/// pass, so that multiview rendering can be checked on the desktop. Call `set_input()` each time
+/// the input target is resized, before drawing.
+pub struct StereoPreview {
+    pass: FullscreenPass,
+    side_by_side: u32,
+    /// sRGB encode the scene, for an sRGB or float input drawn into a UNORM output render pass
+    /// (see `RenderPassSettings::encode_srgb()`)
+    pub encode_srgb: bool,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct StereoPreviewPushConstants {
+    side_by_side: u32,
+    encode_srgb: u32,
+}
+
+unsafe impl bytemuck::Zeroable for StereoPreviewPushConstants {}
+unsafe impl bytemuck::Pod for StereoPreviewPushConstants {}
+
+impl StereoPreview {
+    pub fn new(core: SharedCore, output_render_pass: vk::RenderPass) -> Result<Self> {
+        Self::with_mode(core, output_render_pass, true)
+    }
+
+    /// Show only the left eye, filling the output
+    pub fn left_eye(core: SharedCore, output_render_pass: vk::RenderPass) -> Result<Self> {
+        Self::with_mode(core, output_render_pass, false)
+    }
+
+    fn with_mode(
+        core: SharedCore,
+        output_render_pass: vk::RenderPass,
+        side_by_side: bool,
+    ) -> Result<Self> {
+        let pass = FullscreenPass::new(
+            core,
+            include_bytes!("../../shaders/stereo_preview.frag.spv"),
+            output_render_pass,
+            1,
+            std::mem::size_of::<StereoPreviewPushConstants>() as u32,
+        )?;
+        Ok(Self {
+            pass,
+            side_by_side: side_by_side.into(),
+            encode_srgb: false,
+        })
+    }
+
+    /// Bind the input target, which must have two layers
+    pub fn set_input(&mut self, input: &RenderTarget) {
+        debug_assert_eq!(input.layers(), 2, "Stereo preview input must have two views");
+        self.pass.set_inputs(&[input.color_view()]);
+    }
+
+    /// Draw into the currently active render pass
+    pub fn draw(&self, command_buffer: vk::CommandBuffer) {
+        let push = StereoPreviewPushConstants {
+            side_by_side: self.side_by_side,
+            encode_srgb: self.encode_srgb.into(),
+        };
+        self.pass.draw(command_buffer, bytes_of(&push));
+    }
+}
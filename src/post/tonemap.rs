@@ -0,0 +1,97 @@
+use super::FullscreenPass;
+use crate::render_target::RenderTarget;
+use crate::SharedCore;
+use anyhow::Result;
+use erupt::vk;
+
+/// Curve used to map HDR color into the displayable range
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TonemapOperator {
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve
+    Aces,
+    /// Clamp to [0, 1]
+    Clamp,
+}
+
+/// Tonemapping parameters, may be changed between frames
+#[derive(Copy, Clone, Debug)]
+pub struct TonemapSettings {
+    pub operator: TonemapOperator,
+    /// Linear multiplier applied before the curve
+    pub exposure: f32,
+    /// Gamma applied after the curve. Leave this at 1.0 when drawing into an sRGB swapchain,
+    /// which encodes gamma in hardware.
+    pub gamma: f32,
+    /// sRGB encode the output, for a UNORM output render pass (see
+    /// `RenderPassSettings::encode_srgb()`)
+    pub encode_srgb: bool,
+}
+
+impl Default for TonemapSettings {
+    fn default() -> Self {
+        Self {
+            operator: TonemapOperator::Aces,
+            exposure: 1.0,
+            gamma: 1.0,
+            encode_srgb: false,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct TonemapPushConstants {
+    operator: i32,
+    exposure: f32,
+    gamma: f32,
+    encode_srgb: u32,
+}
+
+unsafe impl bytemuck::Zeroable for TonemapPushConstants {}
+unsafe impl bytemuck::Pod for TonemapPushConstants {}
+
+/// Converts an HDR `RenderTarget` into the output render pass (usually the swapchain's). Call
+/// `set_input()` each time the input target is resized, before drawing.
+pub struct Tonemap {
+    pass: FullscreenPass,
+    pub settings: TonemapSettings,
+}
+
+impl Tonemap {
+    pub fn new(
+        core: SharedCore,
+        output_render_pass: vk::RenderPass,
+        settings: TonemapSettings,
+    ) -> Result<Self> {
+        let pass = FullscreenPass::new(
+            core,
+            include_bytes!("../../shaders/tonemap.frag.spv"),
+            output_render_pass,
+            1,
+            std::mem::size_of::<TonemapPushConstants>() as u32,
+        )?;
+        Ok(Self { pass, settings })
+    }
+
+    /// Bind the input target. Call this after the target is resized.
+    pub fn set_input(&self, input: &RenderTarget) {
+        self.pass.set_inputs(&[input.color_view()]);
+    }
+
+    /// Draw into the currently active render pass
+    pub fn draw(&self, command_buffer: vk::CommandBuffer) {
+        let push = TonemapPushConstants {
+            operator: match self.settings.operator {
+                TonemapOperator::Reinhard => 0,
+                TonemapOperator::Aces => 1,
+                TonemapOperator::Clamp => 2,
+            },
+            exposure: self.settings.exposure,
+            gamma: self.settings.gamma,
+            encode_srgb: self.settings.encode_srgb.into(),
+        };
+        self.pass
+            .draw(command_buffer, bytemuck::cast_slice(std::slice::from_ref(&push)));
+    }
+}
@@ -0,0 +1,37 @@
+use super::FullscreenPass;
+use crate::render_target::RenderTarget;
+use crate::SharedCore;
+use anyhow::Result;
+use erupt::vk;
+
+/// Stretches a `RenderTarget` over the output render pass with bilinear filtering, such as to
+/// show a scene drawn at a lower resolution. In VR, each view is copied to the same view of the
+/// output. Colors are copied without encoding, so the target must hold them as the output does,
+/// such as by having its color format. Call `set_input()` each time the input target
+/// is resized, before drawing.
+pub struct Upscale {
+    pass: FullscreenPass,
+}
+
+impl Upscale {
+    pub fn new(core: SharedCore, output_render_pass: vk::RenderPass) -> Result<Self> {
+        let pass = FullscreenPass::new(
+            core,
+            include_bytes!("../../shaders/upscale.frag.spv"),
+            output_render_pass,
+            1,
+            0,
+        )?;
+        Ok(Self { pass })
+    }
+
+    /// Bind the input target
+    pub fn set_input(&self, input: &RenderTarget) {
+        self.pass.set_inputs(&[input.color_view()]);
+    }
+
+    /// Draw into the currently active render pass
+    pub fn draw(&self, command_buffer: vk::CommandBuffer) {
+        self.pass.draw(command_buffer, &[]);
+    }
+}
@@ -0,0 +1,371 @@
+//! Frame timing. `GpuTimer` measures the GPU time of each frame's command buffer with timestamp
+//! queries; the StarterKit keeps one, and its measurements are collected here while `record()`
+//! is enabled (as in the bench mode). `TimingStats` summarizes a series of samples.
+//!
+//! While `record_timeline()` is enabled, the backends and the StarterKit also record when each
+//! frame waits and submits, for plotting how the CPU and GPU overlap:
+//!
+//! ```ignore
+//! profiling::record_timeline(true);
+//! // Later, such as in a HUD
+//! for frame in profiling::timeline() {
+//!     // A long fence wait means the CPU is waiting on the GPU: GPU-bound. Long acquires or
+//!     // presents mean waiting on the display (such as vsync): sync-bound. Neither: CPU-bound.
+//!     plot(frame.index, frame.fence_wait, frame.gpu);
+//! }
+//! ```
+use crate::SharedCore;
+use anyhow::Result;
+use erupt::vk;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+static RECORDING: AtomicBool = AtomicBool::new(false);
+static GPU_SAMPLES: Mutex<Vec<f32>> = Mutex::new(Vec::new());
+static TIMELINE: Mutex<Option<Timeline>> = Mutex::new(None);
+
+/// Most recent frames kept by the timeline
+pub const TIMELINE_FRAMES: usize = 240;
+
+/// Start or stop collecting GPU frame times reported by the StarterKit
+pub fn record(enable: bool) {
+    RECORDING.store(enable, Ordering::Relaxed);
+}
+
+/// Whether GPU frame times are being collected
+pub fn recording() -> bool {
+    RECORDING.load(Ordering::Relaxed)
+}
+
+/// Take the GPU frame times collected so far, in milliseconds
+pub fn take_gpu_samples() -> Vec<f32> {
+    std::mem::take(&mut *GPU_SAMPLES.lock().unwrap())
+}
+
+/// Called with each measured GPU frame time
+pub(crate) fn report_gpu(ms: f32) {
+    if recording() {
+        GPU_SAMPLES.lock().unwrap().push(ms);
+    }
+}
+
+/// Start and end of an event, in milliseconds
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Span {
+    pub start: f64,
+    pub end: f64,
+}
+
+impl Span {
+    pub fn duration(&self) -> f64 {
+        self.end - self.start
+    }
+}
+
+/// When a frame waited, submitted and ran, see `timeline()`. CPU spans are measured from when
+/// the timeline started recording. Events the platform doesn't have (such as presenting in the
+/// headless backend) are None.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct FrameRecord {
+    /// Frames recorded before this one since the timeline started
+    pub index: u64,
+    /// Acquiring the swapchain image, or waiting for the OpenXR runtime's next frame
+    pub acquire: Option<Span>,
+    /// Waiting for the GPU to finish the frame which last used this frame's resources
+    pub fence_wait: Option<Span>,
+    /// Submitting the frame's command buffer
+    pub submit: Option<Span>,
+    /// Presenting the swapchain image, or ending the OpenXR frame
+    pub present: Option<Span>,
+    /// GPU execution of the command buffer, from `GpuTimer`. Measured on the GPU's clock from
+    /// its first timestamp, so only durations and gaps compare with the CPU spans. Arrives
+    /// frames in flight later than the other events.
+    pub gpu: Option<Span>,
+}
+
+impl FrameRecord {
+    fn event_mut(&mut self, event: FrameEvent) -> &mut Option<Span> {
+        match event {
+            FrameEvent::Acquire => &mut self.acquire,
+            FrameEvent::FenceWait => &mut self.fence_wait,
+            FrameEvent::Submit => &mut self.submit,
+            FrameEvent::Present => &mut self.present,
+        }
+    }
+}
+
+/// Events of a frame recorded in the timeline
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum FrameEvent {
+    Acquire,
+    FenceWait,
+    Submit,
+    Present,
+}
+
+struct Timeline {
+    origin: Instant,
+    /// Origin of GPU spans, in milliseconds on the GPU's clock
+    gpu_origin: Option<f64>,
+    frames: VecDeque<FrameRecord>,
+}
+
+/// Start or stop recording the frame timeline. Starting clears earlier frames.
+pub fn record_timeline(enable: bool) {
+    *TIMELINE.lock().unwrap() = enable.then(|| Timeline {
+        origin: Instant::now(),
+        gpu_origin: None,
+        frames: VecDeque::new(),
+    });
+}
+
+/// Whether the frame timeline is being recorded
+pub fn recording_timeline() -> bool {
+    TIMELINE.lock().unwrap().is_some()
+}
+
+/// The last `TIMELINE_FRAMES` frames, oldest first. The last is still in progress.
+pub fn timeline() -> Vec<FrameRecord> {
+    TIMELINE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|timeline| timeline.frames.iter().copied().collect())
+        .unwrap_or_default()
+}
+
+/// Time `f` as an event of the current frame
+pub(crate) fn timed<T>(event: FrameEvent, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let ret = f();
+    report_event(event, start);
+    ret
+}
+
+/// Record an event of the current frame which started at `start` and ends now, returning the
+/// frame's index. A frame ends when an event it already has happens again.
+pub(crate) fn report_event(event: FrameEvent, start: Instant) -> Option<u64> {
+    let end = Instant::now();
+    let mut timeline = TIMELINE.lock().unwrap();
+    let timeline = timeline.as_mut()?;
+
+    let ms = |instant: Instant| {
+        instant
+            .saturating_duration_since(timeline.origin)
+            .as_secs_f64()
+            * 1e3
+    };
+    let span = Some(Span {
+        start: ms(start),
+        end: ms(end),
+    });
+
+    let begin_frame = match timeline.frames.back_mut() {
+        Some(frame) => frame.event_mut(event).is_some(),
+        None => true,
+    };
+    if begin_frame {
+        let index = timeline.frames.back().map_or(0, |frame| frame.index + 1);
+        timeline.frames.push_back(FrameRecord {
+            index,
+            ..Default::default()
+        });
+        if timeline.frames.len() > TIMELINE_FRAMES {
+            timeline.frames.pop_front();
+        }
+    }
+
+    let frame = timeline.frames.back_mut()?;
+    *frame.event_mut(event) = span;
+    Some(frame.index)
+}
+
+/// Record the GPU timestamps of frame `index`, in milliseconds on the GPU's clock
+pub(crate) fn report_gpu_span(index: u64, timestamps: [f64; 2]) {
+    let mut timeline = TIMELINE.lock().unwrap();
+    let timeline = match timeline.as_mut() {
+        Some(timeline) => timeline,
+        None => return,
+    };
+
+    let origin = *timeline.gpu_origin.get_or_insert(timestamps[0]);
+    if let Some(frame) = timeline
+        .frames
+        .iter_mut()
+        .find(|frame| frame.index == index)
+    {
+        frame.gpu = Some(Span {
+            start: timestamps[0] - origin,
+            end: timestamps[1] - origin,
+        });
+    }
+}
+
+/// Summary of a series of timings, in milliseconds
+#[derive(Copy, Clone, Debug)]
+pub struct TimingStats {
+    pub mean: f32,
+    pub min: f32,
+    pub max: f32,
+    pub p50: f32,
+    pub p95: f32,
+    pub p99: f32,
+}
+
+impl TimingStats {
+    /// Summarize the given samples, or None if there are none
+    pub fn from_samples(samples: &[f32]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let percentile = |p: f32| {
+            let idx = ((sorted.len() - 1) as f32 * p).round() as usize;
+            sorted[idx]
+        };
+
+        Some(Self {
+            mean: sorted.iter().sum::<f32>() / sorted.len() as f32,
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+            p50: percentile(0.5),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+        })
+    }
+
+    /// As a JSON object
+    pub fn to_json(&self) -> String {
+        format!(
+            r#"{{"mean": {}, "min": {}, "max": {}, "p50": {}, "p95": {}, "p99": {}}}"#,
+            self.mean, self.min, self.max, self.p50, self.p95, self.p99
+        )
+    }
+}
+
+/// Measures GPU time between `begin()` and `end()` in each frame's command buffer, using a pair
+/// of timestamp queries per frame in flight
+pub struct GpuTimer {
+    query_pool: vk::QueryPool,
+    /// Nanoseconds per timestamp tick
+    period: f32,
+    /// Whether each frame's queries have been written since they were last read
+    written: Vec<bool>,
+    core: SharedCore,
+}
+
+impl GpuTimer {
+    /// Returns None if the graphics queue doesn't support timestamps
+    pub fn new(core: SharedCore, frames: usize) -> Result<Option<Self>> {
+        let queue_families = unsafe {
+            core.instance
+                .get_physical_device_queue_family_properties(core.physical_device, None)
+        };
+        if queue_families[core.queue_family as usize].timestamp_valid_bits == 0 {
+            return Ok(None);
+        }
+
+        let create_info = vk::QueryPoolCreateInfoBuilder::new()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count(frames as u32 * 2);
+        let query_pool =
+            unsafe { core.device.create_query_pool(&create_info, None, None) }.result()?;
+
+        Ok(Some(Self {
+            query_pool,
+            period: core.device_properties.limits.timestamp_period,
+            written: vec![false; frames],
+            core,
+        }))
+    }
+
+    /// Record the starting timestamp. Must be outside of a render pass.
+    pub fn begin(&mut self, command_buffer: vk::CommandBuffer, frame: usize) {
+        let first = frame as u32 * 2;
+        unsafe {
+            self.core
+                .device
+                .cmd_reset_query_pool(command_buffer, self.query_pool, first, 2);
+            self.core.device.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlagBits::TOP_OF_PIPE,
+                self.query_pool,
+                first,
+            );
+        }
+    }
+
+    /// Record the ending timestamp. Must be outside of a render pass.
+    pub fn end(&mut self, command_buffer: vk::CommandBuffer, frame: usize) {
+        unsafe {
+            self.core.device.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlagBits::BOTTOM_OF_PIPE,
+                self.query_pool,
+                frame as u32 * 2 + 1,
+            );
+        }
+        self.written[frame] = true;
+    }
+
+    /// Expect results for `frame` from a command buffer recorded earlier with `begin()` and
+    /// `end()`, which is being submitted again
+    pub fn resubmitted(&mut self, frame: usize) {
+        self.written[frame] = true;
+    }
+
+    /// GPU time of the frame's last submission in milliseconds, once it has completed (such as
+    /// after waiting on its fence). None if nothing was recorded or the results aren't ready.
+    pub fn read(&mut self, frame: usize) -> Result<Option<f32>> {
+        Ok(self
+            .read_timestamps(frame)?
+            .map(|[start, end]| (end - start) as f32))
+    }
+
+    /// Like `read()`, returning the starting and ending timestamps in milliseconds on the GPU's
+    /// clock
+    pub fn read_timestamps(&mut self, frame: usize) -> Result<Option<[f64; 2]>> {
+        if !self.written[frame] {
+            return Ok(None);
+        }
+
+        let mut timestamps = [0u64; 2];
+        let res = unsafe {
+            self.core.device.get_query_pool_results(
+                self.query_pool,
+                frame as u32 * 2,
+                2,
+                std::mem::size_of_val(&timestamps),
+                timestamps.as_mut_ptr() as _,
+                std::mem::size_of::<u64>() as _,
+                Some(vk::QueryResultFlags::_64),
+            )
+        };
+        if res.raw == vk::Result::NOT_READY {
+            return Ok(None);
+        }
+        res.result()?;
+
+        self.written[frame] = false;
+        let ticks = timestamps[1].wrapping_sub(timestamps[0]);
+        let start = timestamps[0] as f64 * self.period as f64 / 1e6;
+        Ok(Some([
+            start,
+            start + ticks as f64 * self.period as f64 / 1e6,
+        ]))
+    }
+}
+
+impl Drop for GpuTimer {
+    fn drop(&mut self) {
+        unsafe {
+            self.core
+                .device
+                .destroy_query_pool(Some(self.query_pool), None);
+        }
+    }
+}
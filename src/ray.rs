@@ -0,0 +1,161 @@
+//! Rays for pointing at things: controller aim rays in VR (see `Input::aim_ray()`) or the mouse
+//! cursor on the desktop, tested against boxes and triangles on the CPU. For picking exactly what
+//! was drawn, see the `picking` module.
+//!
+//! ```ignore
+//! let ray = input.aim_ray(Hand::Right).unwrap_or_else(|| Ray::from_screen(&camera, cursor_ndc));
+//! let hit = ray.closest(objects.iter().map(|object| ray.intersect_aabb(&object.bounds)));
+//! if let Some((idx, t)) = hit {
+//!     let point = ray.at(t);
+//! }
+//! // Draw it with a LINE_LIST pipeline
+//! let line = ray.line(hit.map_or(10.0, |(_, t)| t), [1.0, 0.2, 0.2]);
+//! ```
+use crate::vertex::Vertex;
+use nalgebra::{Matrix4, Point3, Unit, Vector3, Vector4};
+
+/// A half-line from `origin`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    pub direction: Unit<Vector3<f32>>,
+}
+
+/// An axis-aligned bounding box
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: Point3<f32>, max: Point3<f32>) -> Self {
+        Self { min, max }
+    }
+
+    /// Bounds of some points, or None if there are none
+    pub fn from_points(points: impl IntoIterator<Item = Point3<f32>>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(points.fold(Self::new(first, first), |aabb, p| Self {
+            min: aabb.min.inf(&p),
+            max: aabb.max.sup(&p),
+        }))
+    }
+}
+
+impl Ray {
+    pub fn new(origin: Point3<f32>, direction: Vector3<f32>) -> Self {
+        Self {
+            origin,
+            direction: Unit::new_normalize(direction),
+        }
+    }
+
+    /// The ray pointing down -Z of an OpenXR pose, such as a controller's aim pose
+    #[cfg(feature = "openxr")]
+    pub fn from_pose(pose: &openxr::Posef) -> Self {
+        let pose = crate::xr_pose::isometry_from_pose(pose);
+        Self {
+            origin: pose.translation.vector.into(),
+            direction: pose.rotation * -Vector3::z_axis(),
+        }
+    }
+
+    /// The ray through a point on screen, given in normalized device coordinates (-1 to 1, with
+    /// +Y down as in Vulkan), for a camera matrix such as one view of
+    /// `MultiPlatformCamera::get_matrices()`. Works with reverse-Z projections too.
+    pub fn from_screen(camera: &Matrix4<f32>, ndc: [f32; 2]) -> Self {
+        let inverse = camera.try_inverse().unwrap_or_else(Matrix4::identity);
+        let a = inverse * Vector4::new(ndc[0], ndc[1], 0.0, 1.0);
+        let b = inverse * Vector4::new(ndc[0], ndc[1], 1.0, 1.0);
+        // w is the inverse of the distance from the camera, so the nearer plane has the larger w
+        let (near, far) = if a.w.abs() > b.w.abs() {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        let near = Point3::from(near.xyz() / near.w);
+        let far = Point3::from(far.xyz() / far.w);
+        Self::new(near, far - near)
+    }
+
+    /// Point `t` along the ray
+    pub fn at(&self, t: f32) -> Point3<f32> {
+        self.origin + self.direction.into_inner() * t
+    }
+
+    /// Distance along the ray to where it enters the box, zero if it starts inside
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+        let mut t_min = 0.0f32;
+        let mut t_max = f32::INFINITY;
+        for axis in 0..3 {
+            let inv = 1.0 / self.direction[axis];
+            let mut t0 = (aabb.min[axis] - self.origin[axis]) * inv;
+            let mut t1 = (aabb.max[axis] - self.origin[axis]) * inv;
+            if inv < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // NaN (a parallel ray on the slab's boundary) leaves the bounds as they were
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_max < t_min {
+                return None;
+            }
+        }
+        Some(t_min)
+    }
+
+    /// Distance along the ray to a triangle, from either side
+    pub fn intersect_triangle(&self, triangle: [Point3<f32>; 3]) -> Option<f32> {
+        // Möller–Trumbore
+        let [a, b, c] = triangle;
+        let edge1 = b - a;
+        let edge2 = c - a;
+        let p = self.direction.cross(&edge2);
+        let det = edge1.dot(&p);
+        if det.abs() < f32::EPSILON {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+        let s = self.origin - a;
+        let u = s.dot(&p) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = s.cross(&edge1);
+        let v = self.direction.dot(&q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t = edge2.dot(&q) * inv_det;
+        (t >= 0.0).then_some(t)
+    }
+
+    /// Nearest hit of a triangle mesh, given its vertex positions and triangle list indices
+    pub fn intersect_mesh(&self, positions: &[[f32; 3]], indices: &[u32]) -> Option<f32> {
+        indices
+            .chunks_exact(3)
+            .filter_map(|tri| {
+                let corner = |i: u32| Point3::from(positions[i as usize]);
+                self.intersect_triangle([corner(tri[0]), corner(tri[1]), corner(tri[2])])
+            })
+            .min_by(f32::total_cmp)
+    }
+
+    /// Index and distance of the nearest hit, given the result of testing each object
+    pub fn closest(&self, hits: impl IntoIterator<Item = Option<f32>>) -> Option<(usize, f32)> {
+        hits.into_iter()
+            .enumerate()
+            .filter_map(|(idx, t)| Some((idx, t?)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    /// The first `length` of the ray as a line, for drawing with a `LINE_LIST` pipeline
+    pub fn line(&self, length: f32, color: [f32; 3]) -> [Vertex; 2] {
+        [
+            Vertex::new(self.origin.into(), color),
+            Vertex::new(self.at(length).into(), color),
+        ]
+    }
+}
@@ -1,51 +1,176 @@
+use crate::color::needs_shader_encoding;
 use crate::defaults::{COLOR_FORMAT, DEPTH_FORMAT};
+use crate::memory::aspect_mask;
 use crate::Core;
 use anyhow::Result;
 use erupt::{vk, vk1_1};
 
+/// Create the default render pass for a swapchain of `defaults::COLOR_FORMAT`. Swapchains of
+/// other formats, such as with `AppInfo::srgb_swapchain(false)`, need
+/// `create_render_pass_with_format()` with `Platform::color_format()`.
 pub fn create_render_pass(core: &Core, vr: bool) -> Result<vk::RenderPass> {
+    create_render_pass_with_format(core, COLOR_FORMAT, vr)
+}
+
+/// Create the default render pass with a color attachment of the given format, such as
+/// `Platform::color_format()`
+pub fn create_render_pass_with_format(
+    core: &Core,
+    color_format: vk::Format,
+    vr: bool,
+) -> Result<vk::RenderPass> {
+    let settings = RenderPassSettings {
+        color_format,
+        depth_format: core.depth_format,
+        vr,
+        ..Default::default()
+    };
+    create_render_pass_with_settings(core, &settings)
+}
+
+/// Attachments of the default render pass, and of the `FramebufferManager` framebuffers for it
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RenderPassSettings {
+    /// Format of the swapchain images
+    pub color_format: vk::Format,
+    /// Defaults to `DEPTH_FORMAT`; `Core::depth_format` is one the device supports
+    pub depth_format: vk::Format,
+    /// Samples per pixel. With more than one, the scene is drawn into multisampled color and
+    /// depth attachments and resolved into the swapchain image at the end of the pass; pipelines
+    /// must be created with the same count (see `PipelineSettings::samples`).
+    pub samples: vk::SampleCountFlagBits,
+    /// Two views, with multiview where the device supports it, otherwise one pass per view (see
+    /// `view_passes()`)
+    pub vr: bool,
+}
+
+impl Default for RenderPassSettings {
+    fn default() -> Self {
+        Self {
+            color_format: COLOR_FORMAT,
+            depth_format: DEPTH_FORMAT,
+            samples: vk::SampleCountFlagBits::_1,
+            vr: false,
+        }
+    }
+}
+
+impl RenderPassSettings {
+    pub fn is_multisampled(&self) -> bool {
+        self.samples != vk::SampleCountFlagBits::_1
+    }
+
+    /// Whether the color format stores shader outputs as written, such as a UNORM swapchain
+    /// picked with `AppInfo::srgb_swapchain(false)`. Pipelines must then set
+    /// `PipelineSettings::encode_srgb`, and clear colors be encoded with
+    /// `color::attachment_color()`.
+    pub fn encode_srgb(&self) -> bool {
+        needs_shader_encoding(self.color_format)
+    }
+}
+
+/// Render pass instances needed to draw every view: one, unless two views (`vr`) are drawn on a
+/// device without multiview. Each pass then draws the eye given by `PipelineSettings::view_index`
+/// into its own layer of the images.
+pub fn view_passes(core: &Core, vr: bool) -> u32 {
+    if vr && !core.multiview {
+        2
+    } else {
+        1
+    }
+}
+
+/// Load op of the stencil aspect of a depth attachment: cleared to 0 for formats with one, so
+/// that pipelines may test it (see `PipelineSettings::stencil`)
+pub fn stencil_load_op(depth_format: vk::Format) -> vk::AttachmentLoadOp {
+    if aspect_mask(depth_format).contains(vk::ImageAspectFlags::STENCIL) {
+        vk::AttachmentLoadOp::CLEAR
+    } else {
+        vk::AttachmentLoadOp::DONT_CARE
+    }
+}
+
+/// Create the default render pass with the given attachments
+pub fn create_render_pass_with_settings(
+    core: &Core,
+    settings: &RenderPassSettings,
+) -> Result<vk::RenderPass> {
     let device = &core.device;
+    let msaa = settings.is_multisampled();
 
-    // Render pass
+    let final_layout = if settings.vr {
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+    } else {
+        vk::ImageLayout::PRESENT_SRC_KHR
+    };
+
+    // Render pass. When multisampled, the color attachment is only resolved, never stored.
     let color_attachment = vk::AttachmentDescriptionBuilder::new()
-        .format(COLOR_FORMAT)
-        .samples(vk::SampleCountFlagBits::_1)
+        .format(settings.color_format)
+        .samples(settings.samples)
         .load_op(vk::AttachmentLoadOp::CLEAR)
-        .store_op(vk::AttachmentStoreOp::STORE)
+        .store_op(if msaa {
+            vk::AttachmentStoreOp::DONT_CARE
+        } else {
+            vk::AttachmentStoreOp::STORE
+        })
         .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
         .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
         .initial_layout(vk::ImageLayout::UNDEFINED)
-        .final_layout(if vr {
+        .final_layout(if msaa {
             vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
         } else {
-            vk::ImageLayout::PRESENT_SRC_KHR
+            final_layout
         });
 
     let depth_attachment = vk::AttachmentDescriptionBuilder::new()
-        .format(DEPTH_FORMAT)
-        .samples(vk::SampleCountFlagBits::_1)
+        .format(settings.depth_format)
+        .samples(settings.samples)
         .load_op(vk::AttachmentLoadOp::CLEAR)
         .store_op(vk::AttachmentStoreOp::DONT_CARE)
-        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_load_op(stencil_load_op(settings.depth_format))
         .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
         .initial_layout(vk::ImageLayout::UNDEFINED)
         .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
 
-    let attachments = [color_attachment, depth_attachment];
+    let resolve_attachment = vk::AttachmentDescriptionBuilder::new()
+        .format(settings.color_format)
+        .samples(vk::SampleCountFlagBits::_1)
+        .load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .final_layout(final_layout);
+
+    let attachments = [color_attachment, depth_attachment, resolve_attachment];
+    let attachments = if msaa {
+        &attachments[..]
+    } else {
+        &attachments[..2]
+    };
 
     let color_attachment_refs = [vk::AttachmentReferenceBuilder::new()
         .attachment(0)
         .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
 
+    let resolve_attachment_refs = [vk::AttachmentReferenceBuilder::new()
+        .attachment(2)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+
     let depth_attachment_ref = vk::AttachmentReferenceBuilder::new()
         .attachment(1)
         .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
         .build();
 
-    let subpasses = [vk::SubpassDescriptionBuilder::new()
+    let mut subpass = vk::SubpassDescriptionBuilder::new()
         .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
         .color_attachments(&color_attachment_refs)
-        .depth_stencil_attachment(&depth_attachment_ref)];
+        .depth_stencil_attachment(&depth_attachment_ref);
+    if msaa {
+        subpass = subpass.resolve_attachments(&resolve_attachment_refs);
+    }
+    let subpasses = [subpass];
 
     let dependencies = [vk::SubpassDependencyBuilder::new()
         .src_subpass(vk::SUBPASS_EXTERNAL)
@@ -56,18 +181,20 @@ pub fn create_render_pass(core: &Core, vr: bool) -> Result<vk::RenderPass> {
         .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)];
 
     let mut create_info = vk::RenderPassCreateInfoBuilder::new()
-        .attachments(&attachments)
+        .attachments(attachments)
         .subpasses(&subpasses)
         .dependencies(&dependencies);
 
-    let views = if vr { 2 } else { 1 };
+    let views = if settings.vr { 2 } else { 1 };
     let view_mask = [!(!0 << views)];
     let mut multiview = vk1_1::RenderPassMultiviewCreateInfoBuilder::new()
         .view_masks(&view_mask)
         .correlation_masks(&view_mask)
         .build();
 
-    create_info.p_next = &mut multiview as *mut _ as _;
+    if core.multiview {
+        create_info.p_next = &mut multiview as *mut _ as _;
+    }
 
     Ok(unsafe { device.create_render_pass(&create_info, None, None) }.result()?)
 }
@@ -0,0 +1,559 @@
+use crate::depth_capture::DepthCapture;
+use crate::memory::{aspect_mask, ManagedImage};
+use crate::render_pass::stencil_load_op;
+use crate::shader::clear_depth;
+use crate::{Core, SharedCore};
+use anyhow::{bail, ensure, Result};
+use erupt::{vk, vk1_1};
+use gpu_alloc::UsageFlags;
+
+/// Settings for an offscreen `RenderTarget`
+#[derive(Copy, Clone, Debug)]
+pub struct RenderTargetSettings {
+    /// Format of the color attachment
+    pub color_format: vk::Format,
+    /// Format of the depth attachment, if any
+    pub depth_format: Option<vk::Format>,
+    /// Render both eyes with multiview; the color image will have two array layers
+    pub vr: bool,
+    /// Additional usage for the color image. `COLOR_ATTACHMENT` and `SAMPLED` are always set.
+    pub extra_usage: vk::ImageUsageFlags,
+    /// Additional usage for the depth image, such as `TRANSFER_SRC` for a `DepthCapture`. If
+    /// any is set, depth is kept after the pass rather than discarded.
+    pub extra_depth_usage: vk::ImageUsageFlags,
+    /// Clear depth to 0 rather than 1, for pipelines built with `PipelineSettings::reverse_z`
+    pub reverse_z: bool,
+    /// Samples per pixel. Above 1, the pass draws into a multisampled color image which is
+    /// resolved into the color image at the end of each pass, so later passes sample the
+    /// anti-aliased result. Pipelines must be built with the same count
+    /// (`PipelineSettings::samples`).
+    pub samples: vk::SampleCountFlagBits,
+}
+
+impl RenderTargetSettings {
+    /// 16-bit float color with depth, suitable for HDR rendering followed by a `post::Tonemap`.
+    /// The depth format is `DEPTH_FORMAT`; set it to `Core::depth_format` for devices without it.
+    pub fn hdr(vr: bool) -> Self {
+        Self {
+            color_format: vk::Format::R16G16B16A16_SFLOAT,
+            depth_format: Some(crate::defaults::DEPTH_FORMAT),
+            vr,
+            extra_usage: vk::ImageUsageFlags::empty(),
+            extra_depth_usage: vk::ImageUsageFlags::empty(),
+            reverse_z: false,
+            samples: vk::SampleCountFlagBits::_1,
+        }
+    }
+
+    /// Color only, with no depth attachment. Useful for post-processing intermediates.
+    pub fn color(color_format: vk::Format, vr: bool) -> Self {
+        Self {
+            color_format,
+            depth_format: None,
+            vr,
+            extra_usage: vk::ImageUsageFlags::empty(),
+            extra_depth_usage: vk::ImageUsageFlags::empty(),
+            reverse_z: false,
+            samples: vk::SampleCountFlagBits::_1,
+        }
+    }
+
+    fn is_multisampled(&self) -> bool {
+        self.samples != vk::SampleCountFlagBits::_1
+    }
+}
+
+/// An offscreen color (and optionally depth) target with its own render pass. The color image is
+/// left in `SHADER_READ_ONLY_OPTIMAL` at the end of the pass, ready to be sampled by later passes.
+pub struct RenderTarget {
+    render_pass: vk::RenderPass,
+    settings: RenderTargetSettings,
+    internals: Option<Internals>,
+    core: SharedCore,
+}
+
+struct Internals {
+    extent: vk::Extent2D,
+    color_image: ManagedImage,
+    color_view: vk::ImageView,
+    depth: Option<(ManagedImage, vk::ImageView)>,
+    /// Multisampled color drawn into and resolved into `color_image`
+    msaa: Option<(ManagedImage, vk::ImageView)>,
+    framebuffer: vk::Framebuffer,
+}
+
+impl RenderTarget {
+    /// Create a new render target. `resize()` must be called before use.
+    pub fn new(core: SharedCore, settings: RenderTargetSettings) -> Result<Self> {
+        if settings.is_multisampled() {
+            let limits = &core.device_properties.limits;
+            let mut supported = limits.framebuffer_color_sample_counts;
+            if settings.depth_format.is_some() {
+                supported &= limits.framebuffer_depth_sample_counts;
+            }
+            ensure!(
+                supported.contains(vk::SampleCountFlags::from_bits_truncate(settings.samples.0)),
+                "{:?} MSAA is not supported by the device",
+                settings.samples
+            );
+        }
+        let render_pass = create_target_render_pass(&core, &settings)?;
+        Ok(Self {
+            render_pass,
+            settings,
+            internals: None,
+            core,
+        })
+    }
+
+    /// (Re)create the images of this target. Usually called from `swapchain_resize()`.
+    pub fn resize(&mut self, extent: vk::Extent2D) -> Result<()> {
+        // Waits for the device, as the old framebuffer may still be in use
+        if let Some(internals) = self.internals.take() {
+            internals.free(&self.core);
+        }
+
+        let layers = self.layers();
+
+        let (color_image, color_view) = create_image(
+            &self.core,
+            extent,
+            layers,
+            self.settings.color_format,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | vk::ImageUsageFlags::SAMPLED
+                | self.settings.extra_usage,
+            vk::ImageAspectFlags::COLOR,
+        )?;
+
+        let depth = match self.settings.depth_format {
+            Some(format) => Some(create_multisampled_image(
+                &self.core,
+                extent,
+                layers,
+                format,
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | self.settings.extra_depth_usage,
+                aspect_mask(format),
+                self.settings.samples,
+            )?),
+            None => None,
+        };
+
+        let msaa = if self.settings.is_multisampled() {
+            Some(create_multisampled_image(
+                &self.core,
+                extent,
+                layers,
+                self.settings.color_format,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+                vk::ImageAspectFlags::COLOR,
+                self.settings.samples,
+            )?)
+        } else {
+            None
+        };
+
+        // Ordered as in `create_target_render_pass()`
+        let mut attachments = vec![msaa.as_ref().map_or(color_view, |(_, view)| *view)];
+        if let Some((_, depth_view)) = &depth {
+            attachments.push(*depth_view);
+        }
+        if msaa.is_some() {
+            attachments.push(color_view);
+        }
+
+        let create_info = vk::FramebufferCreateInfoBuilder::new()
+            .render_pass(self.render_pass)
+            .attachments(&attachments)
+            .width(extent.width)
+            .height(extent.height)
+            .layers(1);
+
+        let framebuffer =
+            unsafe { self.core.device.create_framebuffer(&create_info, None, None) }.result()?;
+
+        self.internals = Some(Internals {
+            extent,
+            color_image,
+            color_view,
+            depth,
+            msaa,
+            framebuffer,
+        });
+
+        Ok(())
+    }
+
+    /// Begin this target's render pass, and set the viewport and scissor to cover it
+    pub fn begin_pass(&self, command_buffer: vk::CommandBuffer, clear_color: [f32; 4]) {
+        let internals = self.internals();
+        let mut clear_values = vec![vk::ClearValue {
+            color: vk::ClearColorValue {
+                float32: clear_color,
+            },
+        }];
+        if internals.depth.is_some() {
+            clear_values.push(vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: clear_depth(self.settings.reverse_z),
+                    stencil: 0,
+                },
+            });
+        }
+
+        let render_area = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent: internals.extent,
+        };
+
+        let begin_info = vk::RenderPassBeginInfoBuilder::new()
+            .framebuffer(internals.framebuffer)
+            .render_pass(self.render_pass)
+            .render_area(render_area)
+            .clear_values(&clear_values);
+
+        let viewports = [vk::ViewportBuilder::new()
+            .x(0.0)
+            .y(0.0)
+            .width(internals.extent.width as f32)
+            .height(internals.extent.height as f32)
+            .min_depth(0.0)
+            .max_depth(1.0)];
+
+        let scissors = [vk::Rect2DBuilder::new()
+            .offset(render_area.offset)
+            .extent(render_area.extent)];
+
+        unsafe {
+            self.core.device.cmd_begin_render_pass(
+                command_buffer,
+                &begin_info,
+                vk::SubpassContents::INLINE,
+            );
+            self.core
+                .device
+                .cmd_set_viewport(command_buffer, 0, &viewports);
+            self.core
+                .device
+                .cmd_set_scissor(command_buffer, 0, &scissors);
+        }
+    }
+
+    /// End this target's render pass
+    pub fn end_pass(&self, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            self.core.device.cmd_end_render_pass(command_buffer);
+        }
+    }
+
+    /// Render pass to build pipelines against
+    pub fn render_pass(&self) -> vk::RenderPass {
+        self.render_pass
+    }
+
+    pub fn settings(&self) -> &RenderTargetSettings {
+        &self.settings
+    }
+
+    /// Change `RenderTargetSettings::reverse_z`, which only affects how depth is cleared
+    pub fn set_reverse_z(&mut self, reverse_z: bool) {
+        self.settings.reverse_z = reverse_z;
+    }
+
+    /// View of the color image covering all layers, with `_2D_ARRAY` view type
+    pub fn color_view(&self) -> vk::ImageView {
+        self.internals().color_view
+    }
+
+    pub fn color_image(&self) -> vk::Image {
+        self.internals().color_image.instance()
+    }
+
+    /// View of the depth image, if this target has one
+    pub fn depth_view(&self) -> Option<vk::ImageView> {
+        self.internals().depth.as_ref().map(|(_, view)| *view)
+    }
+
+    pub fn depth_image(&self) -> Option<vk::Image> {
+        self.internals()
+            .depth
+            .as_ref()
+            .map(|(image, _)| image.instance())
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.internals().extent
+    }
+
+    /// Record a copy of depth layer `layer` to read back, after the pass has ended. Requires
+    /// `TRANSFER_SRC` in `RenderTargetSettings::extra_depth_usage`.
+    pub fn capture_depth(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        layer: u32,
+    ) -> Result<DepthCapture> {
+        let (format, image) = match (self.settings.depth_format, self.depth_image()) {
+            (Some(format), Some(image)) => (format, image),
+            _ => bail!("Render target has no depth"),
+        };
+        ensure!(
+            !self.settings.is_multisampled(),
+            "Multisampled depth can't be captured"
+        );
+        ensure!(
+            self.settings
+                .extra_depth_usage
+                .contains(vk::ImageUsageFlags::TRANSFER_SRC),
+            "Render target depth was not created with TRANSFER_SRC usage"
+        );
+        DepthCapture::record(
+            &self.core,
+            command_buffer,
+            image,
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            self.extent(),
+            format,
+            layer,
+        )
+    }
+
+    /// Number of array layers (2 in VR, 1 otherwise)
+    pub fn layers(&self) -> u32 {
+        if self.settings.vr {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn internals(&self) -> &Internals {
+        self.internals
+            .as_ref()
+            .expect("Render target used before resize")
+    }
+}
+
+pub(crate) fn create_image(
+    core: &SharedCore,
+    extent: vk::Extent2D,
+    layers: u32,
+    format: vk::Format,
+    usage: vk::ImageUsageFlags,
+    aspect: vk::ImageAspectFlags,
+) -> Result<(ManagedImage, vk::ImageView)> {
+    let samples = vk::SampleCountFlagBits::_1;
+    create_multisampled_image(core, extent, layers, format, usage, aspect, samples)
+}
+
+fn create_multisampled_image(
+    core: &SharedCore,
+    extent: vk::Extent2D,
+    layers: u32,
+    format: vk::Format,
+    usage: vk::ImageUsageFlags,
+    aspect: vk::ImageAspectFlags,
+    samples: vk::SampleCountFlagBits,
+) -> Result<(ManagedImage, vk::ImageView)> {
+    let create_info = vk::ImageCreateInfoBuilder::new()
+        .image_type(vk::ImageType::_2D)
+        .extent(
+            vk::Extent3DBuilder::new()
+                .width(extent.width)
+                .height(extent.height)
+                .depth(1)
+                .build(),
+        )
+        .mip_levels(1)
+        .array_layers(layers)
+        .format(format)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .usage(usage)
+        .samples(samples)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+    let image = ManagedImage::new(core.clone(), create_info, UsageFlags::FAST_DEVICE_ACCESS)?;
+
+    let create_info = vk::ImageViewCreateInfoBuilder::new()
+        .image(image.instance())
+        .view_type(vk::ImageViewType::_2D_ARRAY)
+        .format(format)
+        .subresource_range(
+            vk::ImageSubresourceRangeBuilder::new()
+                .aspect_mask(aspect)
+                .base_mip_level(0)
+                .level_count(1)
+                .base_array_layer(0)
+                .layer_count(layers)
+                .build(),
+        );
+    let view = unsafe { core.device.create_image_view(&create_info, None, None) }.result()?;
+
+    Ok((image, view))
+}
+
+fn create_target_render_pass(core: &Core, settings: &RenderTargetSettings) -> Result<vk::RenderPass> {
+    ensure!(
+        core.multiview || !settings.vr,
+        "Two-view render targets require multiview, which the device doesn't support"
+    );
+
+    // The color image, or the multisampled image resolved into it
+    let msaa = settings.is_multisampled();
+    let mut attachments = vec![vk::AttachmentDescriptionBuilder::new()
+        .format(settings.color_format)
+        .samples(settings.samples)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        .store_op(if msaa {
+            vk::AttachmentStoreOp::DONT_CARE
+        } else {
+            vk::AttachmentStoreOp::STORE
+        })
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .final_layout(if msaa {
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+        } else {
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        })];
+
+    if let Some(depth_format) = settings.depth_format {
+        // Depth is only needed after the pass if it's read somehow
+        let store_op = if settings.extra_depth_usage.is_empty() {
+            vk::AttachmentStoreOp::DONT_CARE
+        } else {
+            vk::AttachmentStoreOp::STORE
+        };
+        attachments.push(
+            vk::AttachmentDescriptionBuilder::new()
+                .format(depth_format)
+                .samples(settings.samples)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(store_op)
+                .stencil_load_op(stencil_load_op(depth_format))
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL),
+        );
+    }
+
+    // The single sampled color image, resolved into after the subpass
+    let resolve_attachment = attachments.len() as u32;
+    if msaa {
+        attachments.push(
+            vk::AttachmentDescriptionBuilder::new()
+                .format(settings.color_format)
+                .samples(vk::SampleCountFlagBits::_1)
+                .load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+        );
+    }
+
+    let color_attachment_refs = [vk::AttachmentReferenceBuilder::new()
+        .attachment(0)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+
+    let depth_attachment_ref = vk::AttachmentReferenceBuilder::new()
+        .attachment(1)
+        .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+
+    let resolve_attachment_refs = [vk::AttachmentReferenceBuilder::new()
+        .attachment(resolve_attachment)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+
+    let mut subpass = vk::SubpassDescriptionBuilder::new()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .color_attachments(&color_attachment_refs);
+    if settings.depth_format.is_some() {
+        subpass = subpass.depth_stencil_attachment(&depth_attachment_ref);
+    }
+    if msaa {
+        subpass = subpass.resolve_attachments(&resolve_attachment_refs);
+    }
+    let subpasses = [subpass];
+
+    // The previous frame may still be sampling this image, and this frame's readers must wait
+    // for the writes to finish
+    let dependencies = [
+        vk::SubpassDependencyBuilder::new()
+            .src_subpass(vk::SUBPASS_EXTERNAL)
+            .dst_subpass(0)
+            .src_stage_mask(
+                vk::PipelineStageFlags::FRAGMENT_SHADER
+                    | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            )
+            .src_access_mask(vk::AccessFlags::empty())
+            .dst_stage_mask(
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            )
+            .dst_access_mask(
+                vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            ),
+        vk::SubpassDependencyBuilder::new()
+            .src_subpass(0)
+            .dst_subpass(vk::SUBPASS_EXTERNAL)
+            .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+            .dst_stage_mask(
+                vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER,
+            )
+            .dst_access_mask(vk::AccessFlags::SHADER_READ),
+    ];
+
+    let mut create_info = vk::RenderPassCreateInfoBuilder::new()
+        .attachments(&attachments)
+        .subpasses(&subpasses)
+        .dependencies(&dependencies);
+
+    let views = if settings.vr { 2 } else { 1 };
+    let view_mask = [!(!0 << views)];
+    let mut multiview = vk1_1::RenderPassMultiviewCreateInfoBuilder::new()
+        .view_masks(&view_mask)
+        .correlation_masks(&view_mask)
+        .build();
+
+    if core.multiview {
+        create_info.p_next = &mut multiview as *mut _ as _;
+    }
+
+    Ok(unsafe { core.device.create_render_pass(&create_info, None, None) }.result()?)
+}
+
+impl Drop for RenderTarget {
+    fn drop(&mut self) {
+        if let Some(internals) = self.internals.take() {
+            internals.free(&self.core);
+        }
+        unsafe {
+            self.core
+                .device
+                .destroy_render_pass(Some(self.render_pass), None);
+        }
+    }
+}
+
+impl Internals {
+    fn free(self, core: &Core) {
+        unsafe {
+            core.device.device_wait_idle().result().unwrap();
+            core.device
+                .destroy_framebuffer(Some(self.framebuffer), None);
+            core.device.destroy_image_view(Some(self.color_view), None);
+            if let Some((_, depth_view)) = self.depth {
+                core.device.destroy_image_view(Some(depth_view), None);
+            }
+            if let Some((_, msaa_view)) = self.msaa {
+                core.device.destroy_image_view(Some(msaa_view), None);
+            }
+        }
+    }
+}
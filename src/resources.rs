@@ -0,0 +1,179 @@
+//! Live resource accounting, see `Core::resource_stats()`. `ManagedBuffer`s and `ManagedImage`s
+//! are counted automatically. Pipelines are counted when created by the `shader` module (or
+//! registered with `Core::track_pipeline()`) until `Core::destroy_pipeline()`, and descriptor
+//! sets when allocated with `Core::allocate_descriptor_sets()` until their pool is destroyed
+//! with `Core::destroy_descriptor_pool()`. Objects destroyed directly through the device are not
+//! seen, and will be reported as leaked.
+use crate::Core;
+use anyhow::Result;
+use erupt::vk;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Counts of live resources
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ResourceStats {
+    pub buffers: usize,
+    /// Device memory bound to buffers
+    pub buffer_bytes: u64,
+    pub images: usize,
+    /// Device memory bound to images
+    pub image_bytes: u64,
+    pub pipelines: usize,
+    pub descriptor_sets: usize,
+}
+
+impl ResourceStats {
+    /// Whether nothing is alive
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl fmt::Display for ResourceStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mib = |bytes: u64| bytes as f64 / (1024. * 1024.);
+        write!(
+            f,
+            "{} buffers ({:.2} MiB), {} images ({:.2} MiB), {} pipelines, {} descriptor sets",
+            self.buffers,
+            mib(self.buffer_bytes),
+            self.images,
+            mib(self.image_bytes),
+            self.pipelines,
+            self.descriptor_sets
+        )
+    }
+}
+
+/// Live resource counters kept by the `Core`
+pub struct ResourceTracker {
+    buffers: AtomicUsize,
+    buffer_bytes: AtomicU64,
+    images: AtomicUsize,
+    image_bytes: AtomicU64,
+    pipelines: Mutex<HashSet<vk::Pipeline>>,
+    /// Number of sets allocated from each pool
+    descriptor_sets: Mutex<HashMap<vk::DescriptorPool, usize>>,
+    /// Fences of frames guarded by a `FrameGuard`
+    pub(crate) frames_in_flight: Mutex<Vec<vk::Fence>>,
+    /// Print any resources still alive when the `Core` is dropped
+    warn_on_drop: bool,
+}
+
+impl ResourceTracker {
+    pub fn new(warn_on_drop: bool) -> Self {
+        Self {
+            buffers: AtomicUsize::new(0),
+            buffer_bytes: AtomicU64::new(0),
+            images: AtomicUsize::new(0),
+            image_bytes: AtomicU64::new(0),
+            pipelines: Mutex::new(HashSet::new()),
+            descriptor_sets: Mutex::new(HashMap::new()),
+            frames_in_flight: Mutex::new(Vec::new()),
+            warn_on_drop,
+        }
+    }
+
+    pub fn stats(&self) -> ResourceStats {
+        ResourceStats {
+            buffers: self.buffers.load(Ordering::Relaxed),
+            buffer_bytes: self.buffer_bytes.load(Ordering::Relaxed),
+            images: self.images.load(Ordering::Relaxed),
+            image_bytes: self.image_bytes.load(Ordering::Relaxed),
+            pipelines: self.pipelines.lock().unwrap().len(),
+            descriptor_sets: self.descriptor_sets.lock().unwrap().values().sum(),
+        }
+    }
+
+    pub(crate) fn buffer_created(&self, bytes: u64) {
+        self.buffers.fetch_add(1, Ordering::Relaxed);
+        self.buffer_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub(crate) fn buffer_destroyed(&self, bytes: u64) {
+        self.buffers.fetch_sub(1, Ordering::Relaxed);
+        self.buffer_bytes.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    pub(crate) fn pipeline_destroyed(&self, pipeline: vk::Pipeline) {
+        self.pipelines.lock().unwrap().remove(&pipeline);
+    }
+
+    pub(crate) fn image_created(&self, bytes: u64) {
+        self.images.fetch_add(1, Ordering::Relaxed);
+        self.image_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub(crate) fn image_destroyed(&self, bytes: u64) {
+        self.images.fetch_sub(1, Ordering::Relaxed);
+        self.image_bytes.fetch_sub(bytes, Ordering::Relaxed);
+    }
+}
+
+impl Core {
+    /// Counts of live buffers, images, pipelines and descriptor sets
+    pub fn resource_stats(&self) -> ResourceStats {
+        self.resources.stats()
+    }
+
+    /// Count a pipeline created outside of this crate's helpers
+    pub fn track_pipeline(&self, pipeline: vk::Pipeline) {
+        self.resources.pipelines.lock().unwrap().insert(pipeline);
+    }
+
+    /// Destroy a pipeline, removing it from the resource stats
+    pub fn destroy_pipeline(&self, pipeline: vk::Pipeline) {
+        self.check_not_in_flight("Pipeline");
+        self.resources.pipeline_destroyed(pipeline);
+        unsafe {
+            self.device.destroy_pipeline(Some(pipeline), None);
+        }
+    }
+
+    /// Allocate descriptor sets, counting them until their pool is destroyed with
+    /// `destroy_descriptor_pool()`
+    pub fn allocate_descriptor_sets(
+        &self,
+        allocate_info: &vk::DescriptorSetAllocateInfoBuilder<'_>,
+    ) -> Result<Vec<vk::DescriptorSet>> {
+        let descriptor_sets = unsafe { self.device.allocate_descriptor_sets(allocate_info) }
+            .result()?
+            .to_vec();
+        *self
+            .resources
+            .descriptor_sets
+            .lock()
+            .unwrap()
+            .entry(allocate_info.descriptor_pool)
+            .or_default() += descriptor_sets.len();
+        Ok(descriptor_sets)
+    }
+
+    /// Destroy a descriptor pool, removing its sets from the resource stats
+    pub fn destroy_descriptor_pool(&self, descriptor_pool: vk::DescriptorPool) {
+        self.check_not_in_flight("Descriptor pool");
+        self.resources
+            .descriptor_sets
+            .lock()
+            .unwrap()
+            .remove(&descriptor_pool);
+        unsafe {
+            self.device
+                .destroy_descriptor_pool(Some(descriptor_pool), None);
+        }
+    }
+}
+
+impl Drop for Core {
+    fn drop(&mut self) {
+        self.destroy_all_garbage();
+        self.clear_shader_modules();
+        let stats = self.resources.stats();
+        if self.resources.warn_on_drop && !stats.is_empty() {
+            eprintln!("Resources never freed: {}", stats);
+        }
+    }
+}
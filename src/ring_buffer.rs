@@ -0,0 +1,146 @@
+//! Streaming of per-frame data (such as uniforms, instance data or dynamic vertices) through one
+//! persistently mapped buffer. Each frame's data is appended after the last, wrapping around to
+//! the start, and space is reclaimed once the frame which wrote it comes around again.
+use crate::memory::{ManagedBuffer, MappedBuffer, UsageFlags};
+use crate::SharedCore;
+use anyhow::{bail, Result};
+use bytemuck::Pod;
+use erupt::vk;
+
+/// A range of the ring buffer written this frame
+#[derive(Copy, Clone, Debug)]
+pub struct RingSlice {
+    pub buffer: vk::Buffer,
+    pub offset: u64,
+    pub size: u64,
+}
+
+impl RingSlice {
+    pub fn descriptor_buffer_info(&self) -> vk::DescriptorBufferInfoBuilder<'static> {
+        vk::DescriptorBufferInfoBuilder::new()
+            .buffer(self.buffer)
+            .offset(self.offset)
+            .range(self.size)
+    }
+}
+
+/// Per-frame streaming buffer. Call `begin_frame()` with the StarterKit's frame index once that
+/// frame's previous submission has completed (after `StarterKit::begin_frame()`), then `push()`
+/// the frame's data.
+///
+/// ```ignore
+/// ring.begin_frame(starter_kit.frame);
+/// let points = ring.push(&points)?;
+/// core.device.cmd_bind_vertex_buffers(cmd, 0, &[points.buffer], &[points.offset]);
+/// ```
+pub struct RingBuffer {
+    buffer: ManagedBuffer,
+    capacity: u64,
+    alignment: u64,
+    /// Total bytes ever allocated; positions increase monotonically, and wrap modulo capacity
+    head: u64,
+    /// Position of the start of each in-flight frame's data
+    frame_starts: Vec<Option<u64>>,
+}
+
+impl RingBuffer {
+    /// A ring of `capacity` bytes, usable as `usage` (e.g. `VERTEX_BUFFER | STORAGE_BUFFER`).
+    /// Slices are aligned so that they may be bound as uniform or storage buffers.
+    pub fn new(
+        core: SharedCore,
+        capacity: u64,
+        usage: vk::BufferUsageFlags,
+        frames: usize,
+    ) -> Result<Self> {
+        let limits = core.device_properties.limits;
+        let alignment = limits
+            .min_uniform_buffer_offset_alignment
+            .max(limits.min_storage_buffer_offset_alignment)
+            .max(16);
+
+        let ci = vk::BufferCreateInfoBuilder::new()
+            .size(capacity)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .usage(usage);
+        let mut buffer = ManagedBuffer::new(core, ci, UsageFlags::UPLOAD)?;
+        buffer.map_persistent()?;
+
+        Ok(Self {
+            buffer,
+            capacity,
+            alignment,
+            head: 0,
+            frame_starts: vec![None; frames],
+        })
+    }
+
+    /// Start writing the given frame, reclaiming the space its previous submission used
+    pub fn begin_frame(&mut self, frame: usize) {
+        self.frame_starts[frame] = Some(self.head);
+    }
+
+    /// Copy `data` into the ring, returning where it was written
+    pub fn push<T: Pod>(&mut self, data: &[T]) -> Result<RingSlice> {
+        let bytes: &[u8] = bytemuck::cast_slice(data);
+        let slice = self.allocate(bytes.len() as u64)?;
+        self.buffer
+            .map_persistent()?
+            .write_slice(slice.offset, bytes)?;
+        Ok(slice)
+    }
+
+    /// Reserve `size` bytes, to be written through `mapped()`
+    pub fn allocate(&mut self, size: u64) -> Result<RingSlice> {
+        if size > self.capacity {
+            bail!(
+                "Ring buffer allocation of {} bytes exceeds its capacity of {}",
+                size,
+                self.capacity
+            );
+        }
+
+        // Align within the current lap, or start the next lap if the data doesn't fit
+        let lap_start = self.head - self.head % self.capacity;
+        let offset = (self.head % self.capacity).div_ceil(self.alignment) * self.alignment;
+        let position = if offset + size <= self.capacity {
+            lap_start + offset
+        } else {
+            lap_start + self.capacity
+        };
+
+        // The oldest data still in use by the GPU
+        let tail = self
+            .frame_starts
+            .iter()
+            .flatten()
+            .copied()
+            .min()
+            .unwrap_or(self.head);
+        if position + size - tail > self.capacity {
+            bail!(
+                "Ring buffer of {} bytes is full; data from frames in flight can't be overwritten",
+                self.capacity
+            );
+        }
+
+        self.head = position + size;
+        Ok(RingSlice {
+            buffer: self.buffer.instance(),
+            offset: position % self.capacity,
+            size,
+        })
+    }
+
+    /// The mapped buffer, for writing slices returned by `allocate()` in place
+    pub fn mapped(&mut self) -> Result<MappedBuffer<'_>> {
+        self.buffer.map_persistent()
+    }
+
+    pub fn buffer(&self) -> vk::Buffer {
+        self.buffer.instance()
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+}
@@ -0,0 +1,135 @@
+//! Saving rendered frames as PNG files. The StarterKit captures a frame when its screenshot key
+//! is pressed (see `Settings::screenshot_key`) or on `StarterKit::request_screenshot()`: the
+//! swapchain image (the first eye in VR) is copied into a readback buffer at the end of the
+//! frame, and encoded and written on a background thread once the frame has completed.
+use crate::barriers;
+use crate::copy;
+use crate::memory::ManagedBuffer;
+use crate::sync2::{Access, Stages};
+use crate::SharedCore;
+use anyhow::{bail, Context, Result};
+use erupt::vk;
+use gpu_alloc::UsageFlags;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A frame being copied into a readback buffer
+pub struct Capture {
+    buffer: ManagedBuffer,
+    extent: vk::Extent2D,
+    format: vk::Format,
+}
+
+impl Capture {
+    /// Record a copy of color layer 0 of `image`, which must be outside of a render pass and in
+    /// `layout`. The image is returned to `layout` afterwards.
+    pub fn record(
+        core: &SharedCore,
+        command_buffer: vk::CommandBuffer,
+        image: vk::Image,
+        layout: vk::ImageLayout,
+        extent: vk::Extent2D,
+        format: vk::Format,
+    ) -> Result<Self> {
+        if bgra(format).is_none() {
+            bail!("Screenshots of {:?} images are not supported", format);
+        }
+
+        let ci = vk::BufferCreateInfoBuilder::new()
+            .usage(vk::BufferUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .size(extent.width as u64 * extent.height as u64 * 4);
+        let buffer = ManagedBuffer::new(core.clone(), ci, UsageFlags::DOWNLOAD)?;
+
+        let to_transfer = barriers::image_barrier(
+            image,
+            barriers::color_range(1),
+            layout,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        );
+        let to_host = barriers::buffer_barrier(
+            buffer.instance(),
+            (Stages::TRANSFER_KHR, Access::TRANSFER_WRITE_KHR),
+            (Stages::HOST_KHR, Access::HOST_READ_KHR),
+        );
+        let restore = barriers::image_barrier(
+            image,
+            barriers::color_range(1),
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            layout,
+        );
+
+        core.cmd_barriers(command_buffer, &[], &[to_transfer]);
+        copy::cmd_copy_image_to_buffer(
+            core,
+            command_buffer,
+            image,
+            0,
+            vk::Offset2D { x: 0, y: 0 },
+            extent,
+            buffer.instance(),
+        );
+        core.cmd_barriers(command_buffer, &[to_host], &[restore]);
+
+        Ok(Self {
+            buffer,
+            extent,
+            format,
+        })
+    }
+
+    /// Read back the pixels and save them to a timestamped PNG in `dir`, on a background thread.
+    /// Call once the frame's commands have completed. Returns the path written to.
+    pub fn save(mut self, dir: &Path) -> Result<PathBuf> {
+        let mut pixels = vec![0u8; self.buffer.size() as usize];
+        self.buffer.read_bytes(0, &mut pixels)?;
+
+        // Swapchain alpha is meaningless, so make the image opaque
+        let swap = bgra(self.format) == Some(true);
+        for pixel in pixels.chunks_exact_mut(4) {
+            if swap {
+                pixel.swap(0, 2);
+            }
+            pixel[3] = 255;
+        }
+
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        let path = dir.join(format!(
+            "screenshot-{}-{:03}.png",
+            timestamp.as_secs(),
+            timestamp.subsec_millis()
+        ));
+
+        let extent = self.extent;
+        let thread_path = path.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = write_png(&thread_path, extent, &pixels) {
+                eprintln!("Failed to save {}: {:#}", thread_path.display(), e);
+            }
+        });
+
+        Ok(path)
+    }
+}
+
+/// Whether the format's channels are stored blue first, or None if it's not 8-bit RGBA
+fn bgra(format: vk::Format) -> Option<bool> {
+    match format {
+        vk::Format::B8G8R8A8_SRGB | vk::Format::B8G8R8A8_UNORM => Some(true),
+        vk::Format::R8G8B8A8_SRGB | vk::Format::R8G8B8A8_UNORM => Some(false),
+        _ => None,
+    }
+}
+
+fn write_png(path: &Path, extent: vk::Extent2D, rgba: &[u8]) -> Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, extent.width, extent.height);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(rgba)?;
+    Ok(())
+}
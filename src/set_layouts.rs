@@ -0,0 +1,163 @@
+//! Descriptor set numbering shared by the built-in and user pipelines. Pipelines following it
+//! can share bound sets and mix each other's shaders:
+//!
+//! * `FRAME_SET` (0): per-frame data such as the camera, in a `FrameDataUbo`. Binding 0 is a
+//!   `UNIFORM_BUFFER`.
+//! * `MATERIAL_SET` (1): material parameters in a `UNIFORM_BUFFER` at binding 0, followed by
+//!   a `COMBINED_IMAGE_SAMPLER` for each texture.
+//! * `OBJECT_SET` (2): per-object data such as transforms, in an `ObjectDataUbo`. Binding 0 is
+//!   a `UNIFORM_BUFFER_DYNAMIC`.
+//!
+//! All bindings are visible to every graphics stage. In GLSL:
+//!
+//! ```glsl
+//! layout(set = 0, binding = 0) uniform Frame { mat4 camera[2]; };
+//! layout(set = 1, binding = 0) uniform Material { vec4 base_color; };
+//! layout(set = 1, binding = 1) uniform sampler2D albedo;
+//! layout(set = 2, binding = 0) uniform Object { mat4 model; };
+//! ```
+use crate::SharedCore;
+use anyhow::{ensure, Result};
+use erupt::vk;
+
+/// Per-frame data
+pub const FRAME_SET: u32 = 0;
+/// Material parameters and textures
+pub const MATERIAL_SET: u32 = 1;
+/// Per-object data
+pub const OBJECT_SET: u32 = 2;
+
+const STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::ALL_GRAPHICS;
+
+/// Bindings of `FRAME_SET`
+pub fn frame_bindings() -> Vec<vk::DescriptorSetLayoutBindingBuilder<'static>> {
+    vec![binding(0, vk::DescriptorType::UNIFORM_BUFFER)]
+}
+
+/// Bindings of `MATERIAL_SET` with `textures` textures
+pub fn material_bindings(textures: u32) -> Vec<vk::DescriptorSetLayoutBindingBuilder<'static>> {
+    std::iter::once(binding(0, vk::DescriptorType::UNIFORM_BUFFER))
+        .chain((1..=textures).map(|i| binding(i, vk::DescriptorType::COMBINED_IMAGE_SAMPLER)))
+        .collect()
+}
+
+/// Bindings of `OBJECT_SET`
+pub fn object_bindings() -> Vec<vk::DescriptorSetLayoutBindingBuilder<'static>> {
+    vec![binding(0, vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)]
+}
+
+fn binding(binding: u32, ty: vk::DescriptorType) -> vk::DescriptorSetLayoutBindingBuilder<'static> {
+    vk::DescriptorSetLayoutBindingBuilder::new()
+        .binding(binding)
+        .descriptor_type(ty)
+        .descriptor_count(1)
+        .stage_flags(STAGES)
+}
+
+/// Check that `bindings` match the convention for `set`, so that a layout built from them is
+/// compatible with the standard layouts. `textures` is the number of material textures.
+pub fn check_compatible(
+    set: u32,
+    textures: u32,
+    bindings: &[vk::DescriptorSetLayoutBindingBuilder<'_>],
+) -> Result<()> {
+    let expected = match set {
+        FRAME_SET => frame_bindings(),
+        MATERIAL_SET => material_bindings(textures),
+        OBJECT_SET => object_bindings(),
+        _ => return Ok(()),
+    };
+    ensure!(
+        bindings.len() == expected.len(),
+        "Set {} has {} bindings, but the convention has {}",
+        set,
+        bindings.len(),
+        expected.len()
+    );
+    for expected in &expected {
+        let actual = bindings.iter().find(|b| b.binding == expected.binding);
+        ensure!(
+            actual.is_some_and(|actual| actual.descriptor_type == expected.descriptor_type
+                && actual.descriptor_count == expected.descriptor_count
+                && actual.stage_flags == expected.stage_flags),
+            "Binding {} of set {} should be one {:?} visible to all graphics stages",
+            expected.binding,
+            set,
+            expected.descriptor_type
+        );
+    }
+    Ok(())
+}
+
+/// The descriptor set layouts of the convention
+pub struct StandardLayouts {
+    pub frame: vk::DescriptorSetLayout,
+    pub material: vk::DescriptorSetLayout,
+    pub object: vk::DescriptorSetLayout,
+    textures: u32,
+    core: SharedCore,
+}
+
+impl StandardLayouts {
+    /// Create the layouts, with `textures` material textures
+    pub fn new(core: SharedCore, textures: u32) -> Result<Self> {
+        let create = |bindings: Vec<vk::DescriptorSetLayoutBindingBuilder<'_>>| {
+            let create_info = vk::DescriptorSetLayoutCreateInfoBuilder::new().bindings(&bindings);
+            unsafe {
+                core.device
+                    .create_descriptor_set_layout(&create_info, None, None)
+            }
+            .result()
+        };
+        let frame = create(frame_bindings())?;
+        let material = create(material_bindings(textures))?;
+        let object = create(object_bindings())?;
+        Ok(Self {
+            frame,
+            material,
+            object,
+            textures,
+            core,
+        })
+    }
+
+    /// Number of material textures
+    pub fn textures(&self) -> u32 {
+        self.textures
+    }
+
+    /// Layouts indexed by set number
+    pub fn set_layouts(&self) -> [vk::DescriptorSetLayout; 3] {
+        [self.frame, self.material, self.object]
+    }
+
+    /// Create a pipeline layout using the first `sets` sets, e.g. 1 for just the frame data
+    pub fn pipeline_layout(
+        &self,
+        sets: usize,
+        push_constant_ranges: &[vk::PushConstantRangeBuilder<'_>],
+    ) -> Result<vk::PipelineLayout> {
+        let set_layouts = self.set_layouts();
+        let create_info = vk::PipelineLayoutCreateInfoBuilder::new()
+            .set_layouts(&set_layouts[..sets])
+            .push_constant_ranges(push_constant_ranges);
+        Ok(unsafe {
+            self.core
+                .device
+                .create_pipeline_layout(&create_info, None, None)
+        }
+        .result()?)
+    }
+}
+
+impl Drop for StandardLayouts {
+    fn drop(&mut self) {
+        unsafe {
+            for layout in self.set_layouts() {
+                self.core
+                    .device
+                    .destroy_descriptor_set_layout(Some(layout), None);
+            }
+        }
+    }
+}
@@ -1,10 +1,62 @@
-use crate::vertex::Vertex;
+use crate::vertex::{Vertex, VertexLayout};
 use crate::Core;
 use anyhow::Result;
-use erupt::{utils, vk};
+use erupt::vk;
 use std::ffi::CString;
 
-// Build a graphics pipeline compatible with `Vertex` which renders the given primitive
+// The bundled shaders index per-eye data with `gl_ViewIndex`. Pipelines are built from the
+// variant of their SPIR-V suited to the device (see `ShaderVariant`), so one set serves both the
+// desktop and VR.
+
+/// Bundled vertex shader for `Vertex`, transforming by `camera[gl_ViewIndex]` from the uniform
+/// buffer at binding 0 (`mat4 camera[2]; float anim;`)
+pub const UNLIT_VERT: &[u8] = include_bytes!("../shaders/unlit.vert.spv");
+
+/// Bundled fragment shader outputting the vertex color
+pub const UNLIT_FRAG: &[u8] = include_bytes!("../shaders/unlit.frag.spv");
+
+/// Bundled fragment shader sampling the texture at binding 1, with the vertex color as uv
+pub const UNLIT_TEX_FRAG: &[u8] = include_bytes!("../shaders/unlit_tex.frag.spv");
+
+/// Specialization constant set from `PipelineSettings::encode_srgb` in fragment shaders. The
+/// bundled shaders declare it as `layout(constant_id = 0) const bool ENCODE_SRGB = false;`, and
+/// custom shaders may too.
+pub const ENCODE_SRGB_CONSTANT_ID: u32 = 0;
+
+/// Specialization constant set from `PipelineSettings::view_index` in both stages. Reserved: on
+/// devices without multiview, `gl_ViewIndex` is rewritten to read it, and shader modules already
+/// declaring it are rejected. Past the largest `constant_id` GLSL allows (2046), so that GLSL
+/// shaders can't collide with it.
+pub const VIEW_INDEX_CONSTANT_ID: u32 = 0xffff;
+
+/// Variant of a shader's SPIR-V which pipelines are built from, picked by
+/// `Core::shader_variant()`. Shaders are written once for multiview, indexing per-eye data with
+/// `gl_ViewIndex`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ShaderVariant {
+    /// The SPIR-V as given. Render passes use multiview, with a single view (index 0) outside of
+    /// VR, so `gl_ViewIndex` picks the right eye on the desktop too.
+    Multiview,
+    /// `gl_ViewIndex` rewritten to read the `VIEW_INDEX_CONSTANT_ID` specialization constant, set
+    /// from `PipelineSettings::view_index`. For devices without multiview, which draw each eye in
+    /// a render pass of its own.
+    SingleView,
+}
+
+impl Core {
+    /// The variant shader modules are created in: `SingleView` on devices without multiview,
+    /// otherwise `Multiview`
+    pub fn shader_variant(&self) -> ShaderVariant {
+        if self.multiview {
+            ShaderVariant::Multiview
+        } else {
+            ShaderVariant::SingleView
+        }
+    }
+}
+
+/// Build a graphics pipeline compatible with `Vertex` which renders the given primitive, from the
+/// variant of each shader suited to the device (see `ShaderVariant`)
 pub fn shader(
     prelude: &Core,
     vertex_src: &[u8],
@@ -13,35 +65,401 @@ pub fn shader(
     render_pass: vk::RenderPass,
     pipeline_layout: vk::PipelineLayout,
 ) -> Result<vk::Pipeline> {
-    // Create shader modules
-    let vert_decoded = utils::decode_spv(vertex_src)?;
-    let create_info = vk::ShaderModuleCreateInfoBuilder::new().code(&vert_decoded);
-    let vertex = unsafe {
-        prelude
-            .device
-            .create_shader_module(&create_info, None, None)
+    shader_with_layout::<Vertex>(
+        prelude,
+        vertex_src,
+        fragment_src,
+        primitive,
+        render_pass,
+        pipeline_layout,
+    )
+}
+
+/// Build a graphics pipeline compatible with the vertex type `V` which renders the given primitive
+/// topology. Primitive restart is enabled for strips and fans.
+pub fn shader_with_layout<V: VertexLayout>(
+    prelude: &Core,
+    vertex_src: &[u8],
+    fragment_src: &[u8],
+    primitive: vk::PrimitiveTopology,
+    render_pass: vk::RenderPass,
+    pipeline_layout: vk::PipelineLayout,
+) -> Result<vk::Pipeline> {
+    let settings = PipelineSettings {
+        topology: primitive,
+        ..Default::default()
+    };
+    shader_with_settings::<V>(
+        prelude,
+        vertex_src,
+        fragment_src,
+        &settings,
+        render_pass,
+        pipeline_layout,
+    )
+}
+
+/// Fixed-function state of a pipeline built with `shader_with_settings()`
+#[derive(Copy, Clone, Debug)]
+pub struct PipelineSettings {
+    pub topology: vk::PrimitiveTopology,
+    pub cull_mode: vk::CullModeFlags,
+    /// Line width is set with `cmd_set_line_width()` while drawing, rather than fixed at 1.
+    /// Widths other than 1 require the `wide_lines` device feature.
+    pub dynamic_line_width: bool,
+    /// Alpha blending over what has already been drawn
+    pub blend: bool,
+    /// Write to the depth buffer
+    pub depth_write: bool,
+    /// Discard fragments behind what has already been drawn
+    pub depth_test: bool,
+    /// Samples per pixel, which must match the render pass (see `RenderPassSettings::samples`)
+    pub samples: vk::SampleCountFlagBits,
+    /// Color attachments written by the fragment shader, which must match the subpass. Blending
+    /// applies to each. `deferred::GBUFFER_ATTACHMENTS` for the G-buffer.
+    pub color_attachments: u32,
+    /// Pass fragments nearer to the camera with `GREATER` rather than `LESS`, for reverse-Z
+    /// projections which map the far plane to 0 and the near plane to 1. The depth buffer must
+    /// be cleared to 0; see `StarterKit::reverse_z()`.
+    pub reverse_z: bool,
+    /// sRGB encode linear outputs in the fragment shader, for render passes whose color format
+    /// doesn't (see `RenderPassSettings::encode_srgb()`), through `ENCODE_SRGB_CONSTANT_ID`.
+    /// Blending then happens in sRGB space.
+    pub encode_srgb: bool,
+    /// The eye drawn by the pipeline on devices without multiview, which draw each view in a
+    /// render pass of its own (see `render_pass::view_passes()`). Ignored with multiview.
+    pub view_index: u32,
+    /// Offset depth values away from the camera, such as to keep shadow maps from shadowing the
+    /// surfaces they were drawn from
+    pub depth_bias: Option<DepthBias>,
+    /// Test and update the stencil aspect of the depth attachment, which must have one (see
+    /// `hardware_query::pick_depth_stencil_format()`)
+    pub stencil: Option<StencilTest>,
+}
+
+/// Depth offset of a pipeline's fragments, see `vkCmdSetDepthBias`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DepthBias {
+    /// Constant offset, in units of the smallest depth difference the depth format resolves
+    pub constant: f32,
+    /// Offset scaled by the slope of the triangle in depth
+    pub slope: f32,
+}
+
+/// Stencil test of a pipeline, the same for front and back faces
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StencilTest {
+    /// Comparison of `reference` against the stored value, passing if `reference <op> stored`
+    pub compare_op: vk::CompareOp,
+    /// Value written to the stencil aspect where both the stencil and depth tests pass
+    pub pass_op: vk::StencilOp,
+    pub reference: u32,
+}
+
+impl StencilTest {
+    /// Write `reference` wherever the pipeline draws
+    pub fn write(reference: u32) -> Self {
+        Self {
+            compare_op: vk::CompareOp::ALWAYS,
+            pass_op: vk::StencilOp::REPLACE,
+            reference,
+        }
     }
-    .result()?;
 
-    let frag_decoded = utils::decode_spv(fragment_src)?;
-    let create_info = vk::ShaderModuleCreateInfoBuilder::new().code(&frag_decoded);
-    let fragment = unsafe {
-        prelude
-            .device
-            .create_shader_module(&create_info, None, None)
+    /// Draw only where the stored value differs from `reference`, leaving it unchanged
+    pub fn not_equal(reference: u32) -> Self {
+        Self {
+            compare_op: vk::CompareOp::NOT_EQUAL,
+            pass_op: vk::StencilOp::KEEP,
+            reference,
+        }
+    }
+
+    fn op_state(&self) -> vk::StencilOpStateBuilder<'static> {
+        vk::StencilOpStateBuilder::new()
+            .fail_op(vk::StencilOp::KEEP)
+            .pass_op(self.pass_op)
+            .depth_fail_op(vk::StencilOp::KEEP)
+            .compare_op(self.compare_op)
+            .compare_mask(!0)
+            .write_mask(!0)
+            .reference(self.reference)
+    }
+}
+
+impl PipelineSettings {
+    /// Alpha blended triangles, depth tested but not depth written, for drawing after opaque
+    /// geometry; see the `transparency` module
+    pub fn transparent() -> Self {
+        Self {
+            blend: true,
+            depth_write: false,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for PipelineSettings {
+    /// Opaque triangle lists with back-face culling
+    fn default() -> Self {
+        Self {
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            cull_mode: vk::CullModeFlags::BACK,
+            dynamic_line_width: false,
+            blend: false,
+            depth_write: true,
+            depth_test: true,
+            samples: vk::SampleCountFlagBits::_1,
+            color_attachments: 1,
+            reverse_z: false,
+            encode_srgb: false,
+            view_index: 0,
+            depth_bias: None,
+            stencil: None,
+        }
     }
-    .result()?;
+}
+
+/// Depth comparison passing fragments nearer to the camera
+pub fn depth_compare_op(reverse_z: bool) -> vk::CompareOp {
+    if reverse_z {
+        vk::CompareOp::GREATER
+    } else {
+        vk::CompareOp::LESS
+    }
+}
 
-    let attribute_descriptions = Vertex::get_attribute_descriptions();
-    let binding_descriptions = [Vertex::binding_description()];
+/// Depth the depth buffer is cleared to, which is the far plane
+pub fn clear_depth(reverse_z: bool) -> f32 {
+    if reverse_z {
+        0.0
+    } else {
+        1.0
+    }
+}
+
+/// Build a graphics pipeline compatible with the vertex type `V` with the given settings
+pub fn shader_with_settings<V: VertexLayout>(
+    prelude: &Core,
+    vertex_src: &[u8],
+    fragment_src: &[u8],
+    settings: &PipelineSettings,
+    render_pass: vk::RenderPass,
+    pipeline_layout: vk::PipelineLayout,
+) -> Result<vk::Pipeline> {
+    build_pipeline::<V>(
+        prelude,
+        vertex_src,
+        Some(fragment_src),
+        settings,
+        render_pass,
+        pipeline_layout,
+    )
+}
+
+/// Build a pipeline compatible with the vertex type `V` which only writes depth, with no
+/// fragment shader, for shadow maps, depth prepasses and occlusion proxies. Color attachments
+/// of the subpass (`settings.color_attachments`, 0 for depth-only render passes) are left
+/// unwritten; blending and `encode_srgb` are ignored.
+pub fn shader_depth_only<V: VertexLayout>(
+    prelude: &Core,
+    vertex_src: &[u8],
+    settings: &PipelineSettings,
+    render_pass: vk::RenderPass,
+    pipeline_layout: vk::PipelineLayout,
+) -> Result<vk::Pipeline> {
+    build_pipeline::<V>(
+        prelude,
+        vertex_src,
+        None,
+        settings,
+        render_pass,
+        pipeline_layout,
+    )
+}
+
+/// Build a pipeline for `shader_with_settings()`, or for `shader_depth_only()` without a
+/// fragment shader
+fn build_pipeline<V: VertexLayout>(
+    prelude: &Core,
+    vertex_src: &[u8],
+    fragment_src: Option<&[u8]>,
+    settings: &PipelineSettings,
+    render_pass: vk::RenderPass,
+    pipeline_layout: vk::PipelineLayout,
+) -> Result<vk::Pipeline> {
+    let primitive = settings.topology;
+
+    // Shader modules, shared with other pipelines using the same code
+    let vertex = prelude.shader_module(vertex_src)?;
+    let fragment = fragment_src
+        .map(|fragment_src| prelude.shader_module(fragment_src))
+        .transpose()?;
+
+    let attribute_descriptions = V::attribute_descriptions();
+    let binding_descriptions = [V::binding_description()];
 
     // Build pipeline
     let vertex_input = vk::PipelineVertexInputStateCreateInfoBuilder::new()
         .vertex_attribute_descriptions(&attribute_descriptions[..])
         .vertex_binding_descriptions(&binding_descriptions);
 
+    // Strips and fans may be split with the maximum index value
+    let primitive_restart = matches!(
+        primitive,
+        vk::PrimitiveTopology::LINE_STRIP
+            | vk::PrimitiveTopology::TRIANGLE_STRIP
+            | vk::PrimitiveTopology::TRIANGLE_FAN
+    );
     let input_assembly = vk::PipelineInputAssemblyStateCreateInfoBuilder::new()
         .topology(primitive)
+        .primitive_restart_enable(primitive_restart);
+
+    let viewport_state = vk::PipelineViewportStateCreateInfoBuilder::new()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let mut dynamic_states = vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    if settings.dynamic_line_width {
+        dynamic_states.push(vk::DynamicState::LINE_WIDTH);
+    }
+    let dynamic_state =
+        vk::PipelineDynamicStateCreateInfoBuilder::new().dynamic_states(&dynamic_states);
+
+    let depth_bias = settings.depth_bias.unwrap_or(DepthBias {
+        constant: 0.0,
+        slope: 0.0,
+    });
+    let rasterizer = vk::PipelineRasterizationStateCreateInfoBuilder::new()
+        .depth_clamp_enable(false)
+        .rasterizer_discard_enable(false)
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(settings.cull_mode)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .depth_clamp_enable(false)
+        .depth_bias_enable(settings.depth_bias.is_some())
+        .depth_bias_constant_factor(depth_bias.constant)
+        .depth_bias_slope_factor(depth_bias.slope);
+
+    let multisampling = vk::PipelineMultisampleStateCreateInfoBuilder::new()
+        .sample_shading_enable(false)
+        .rasterization_samples(settings.samples);
+
+    // Depth-only pipelines leave any color attachments as they are
+    let color_write_mask = if fragment.is_some() {
+        vk::ColorComponentFlags::R
+            | vk::ColorComponentFlags::G
+            | vk::ColorComponentFlags::B
+            | vk::ColorComponentFlags::A
+    } else {
+        vk::ColorComponentFlags::empty()
+    };
+    let color_blend_attachment = vk::PipelineColorBlendAttachmentStateBuilder::new()
+        .color_write_mask(color_write_mask)
+        .blend_enable(settings.blend && fragment.is_some())
+        .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ONE)
+        .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .alpha_blend_op(vk::BlendOp::ADD);
+    let color_blend_attachments = vec![color_blend_attachment; settings.color_attachments as usize];
+    let color_blending = vk::PipelineColorBlendStateCreateInfoBuilder::new()
+        .logic_op_enable(false)
+        .attachments(&color_blend_attachments);
+
+    let entry_point = CString::new("main")?;
+
+    // Shaders without the constants ignore them
+    let constants = [vk::Bool32::from(settings.encode_srgb), settings.view_index];
+    let map_entries = [
+        vk::SpecializationMapEntryBuilder::new()
+            .constant_id(ENCODE_SRGB_CONSTANT_ID)
+            .offset(0)
+            .size(std::mem::size_of::<vk::Bool32>()),
+        vk::SpecializationMapEntryBuilder::new()
+            .constant_id(VIEW_INDEX_CONSTANT_ID)
+            .offset(std::mem::size_of::<vk::Bool32>() as u32)
+            .size(std::mem::size_of::<u32>()),
+    ];
+    let specialization_info = vk::SpecializationInfoBuilder::new()
+        .map_entries(&map_entries)
+        .data_size(std::mem::size_of_val(&constants))
+        .data(constants.as_ptr() as _);
+
+    let mut shader_stages = vec![vk::PipelineShaderStageCreateInfoBuilder::new()
+        .stage(vk::ShaderStageFlagBits::VERTEX)
+        .module(vertex)
+        .name(&entry_point)
+        .specialization_info(&specialization_info)];
+    if let Some(fragment) = fragment {
+        shader_stages.push(
+            vk::PipelineShaderStageCreateInfoBuilder::new()
+                .stage(vk::ShaderStageFlagBits::FRAGMENT)
+                .module(fragment)
+                .name(&entry_point)
+                .specialization_info(&specialization_info),
+        );
+    }
+
+    let stencil = settings
+        .stencil
+        .map(|stencil| stencil.op_state().build())
+        .unwrap_or_default();
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfoBuilder::new()
+        .depth_test_enable(settings.depth_test)
+        .depth_write_enable(settings.depth_write)
+        .depth_compare_op(depth_compare_op(settings.reverse_z))
+        .depth_bounds_test_enable(false)
+        .stencil_test_enable(settings.stencil.is_some())
+        .front(stencil)
+        .back(stencil);
+
+    let create_info = vk::GraphicsPipelineCreateInfoBuilder::new()
+        .stages(&shader_stages)
+        .vertex_input_state(&vertex_input)
+        .input_assembly_state(&input_assembly)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterizer)
+        .multisample_state(&multisampling)
+        .color_blend_state(&color_blending)
+        .depth_stencil_state(&depth_stencil_state)
+        .dynamic_state(&dynamic_state)
+        .layout(pipeline_layout)
+        .render_pass(render_pass)
+        .subpass(0);
+
+    let pipeline = unsafe {
+        prelude
+            .device
+            .create_graphics_pipelines(None, &[create_info], None)
+    }
+    .result()?[0];
+    prelude.track_pipeline(pipeline);
+
+    Ok(pipeline)
+}
+
+/// Build a pipeline with no vertex input, for drawing the fullscreen triangle produced by the
+/// bundled `post::FULLSCREEN_VERT` with `post::draw_fullscreen_triangle()`. Depth testing and
+/// culling are disabled. `post::FullscreenPass` also manages the inputs and layout.
+pub fn fullscreen_pipeline(
+    prelude: &Core,
+    vertex_src: &[u8],
+    fragment_src: &[u8],
+    render_pass: vk::RenderPass,
+    pipeline_layout: vk::PipelineLayout,
+) -> Result<vk::Pipeline> {
+    // Shader modules, shared with other pipelines using the same code
+    let vertex = prelude.shader_module(vertex_src)?;
+    let fragment = prelude.shader_module(fragment_src)?;
+
+    // Build pipeline
+    let vertex_input = vk::PipelineVertexInputStateCreateInfoBuilder::new();
+
+    let input_assembly = vk::PipelineInputAssemblyStateCreateInfoBuilder::new()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
         .primitive_restart_enable(false);
 
     let viewport_state = vk::PipelineViewportStateCreateInfoBuilder::new()
@@ -57,9 +475,8 @@ pub fn shader(
         .rasterizer_discard_enable(false)
         .polygon_mode(vk::PolygonMode::FILL)
         .line_width(1.0)
-        .cull_mode(vk::CullModeFlags::BACK)
-        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
-        .depth_clamp_enable(false);
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE);
 
     let multisampling = vk::PipelineMultisampleStateCreateInfoBuilder::new()
         .sample_shading_enable(false)
@@ -91,9 +508,9 @@ pub fn shader(
     ];
 
     let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfoBuilder::new()
-        .depth_test_enable(true)
-        .depth_write_enable(true)
-        .depth_compare_op(vk::CompareOp::LESS)
+        .depth_test_enable(false)
+        .depth_write_enable(false)
+        .depth_compare_op(vk::CompareOp::ALWAYS)
         .depth_bounds_test_enable(false)
         .stencil_test_enable(false);
 
@@ -117,11 +534,7 @@ pub fn shader(
             .create_graphics_pipelines(None, &[create_info], None)
     }
     .result()?[0];
-
-    unsafe {
-        prelude.device.destroy_shader_module(Some(fragment), None);
-        prelude.device.destroy_shader_module(Some(vertex), None);
-    }
+    prelude.track_pipeline(pipeline);
 
     Ok(pipeline)
 }
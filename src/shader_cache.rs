@@ -0,0 +1,78 @@
+//! Shader modules cached by their SPIR-V, so that pipelines sharing a shader (or rebuilt from
+//! unchanged bytes on hot reload) reuse one module. Modules live until the cache is cleared with
+//! `Core::clear_shader_modules()`, or the `Core` is dropped.
+use crate::shader::ShaderVariant;
+use crate::view_index::{check_reserved_constants, replace_view_index};
+use crate::Core;
+use anyhow::Result;
+use erupt::{utils, vk};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Shader modules kept by the `Core`, keyed by their decoded SPIR-V
+pub struct ShaderModuleCache {
+    modules: Mutex<HashMap<Vec<u32>, vk::ShaderModule>>,
+}
+
+impl ShaderModuleCache {
+    pub fn new() -> Self {
+        Self {
+            modules: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Number of cached modules
+    pub fn len(&self) -> usize {
+        self.modules.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for ShaderModuleCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Core {
+    /// A shader module for the given SPIR-V, created on first use. Owned by the cache; don't
+    /// destroy it. Created in the device's `shader_variant()`: `gl_ViewIndex` is read from the
+    /// `shader::VIEW_INDEX_CONSTANT_ID` specialization constant on devices without multiview,
+    /// which the SPIR-V must not declare itself.
+    pub fn shader_module(&self, spirv: &[u8]) -> Result<vk::ShaderModule> {
+        let code = utils::decode_spv(spirv)?;
+        let mut modules = self.shader_modules.modules.lock().unwrap();
+        if let Some(&module) = modules.get(&code) {
+            return Ok(module);
+        }
+
+        check_reserved_constants(&code)?;
+        let patched;
+        let module_code = match self.shader_variant() {
+            ShaderVariant::Multiview => &code,
+            ShaderVariant::SingleView => {
+                patched = replace_view_index(&code)?;
+                &patched
+            }
+        };
+        let create_info = vk::ShaderModuleCreateInfoBuilder::new().code(module_code);
+        let module =
+            unsafe { self.device.create_shader_module(&create_info, None, None) }.result()?;
+        modules.insert(code, module);
+        Ok(module)
+    }
+
+    /// Destroy all cached shader modules, such as after a hot reload replaced most of them.
+    /// Pipelines already created from them are unaffected.
+    pub fn clear_shader_modules(&self) {
+        let mut modules = self.shader_modules.modules.lock().unwrap();
+        for (_, module) in modules.drain() {
+            unsafe {
+                self.device.destroy_shader_module(Some(module), None);
+            }
+        }
+    }
+}
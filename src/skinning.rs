@@ -0,0 +1,242 @@
+//! Skeletal animation shortcuts. Pair `VertexSkinned` meshes with a `BoneBuffer` bound at
+//! `BONE_BINDING` and the bundled `shaders/skinned.vert`, and drive the joint matrices from an
+//! `Animation` sampled into a `Skeleton`. The data model mirrors glTF skins and animations, so
+//! loaded glTF data maps onto it directly.
+use crate::SharedCore;
+use crate::{memory, memory::ManagedBuffer};
+use anyhow::{ensure, Result};
+use erupt::vk;
+use nalgebra::{Matrix4, Quaternion, Translation3, UnitQuaternion, Vector3};
+
+/// Binding used for the bone storage buffer by the bundled skinning vertex shader
+pub const BONE_BINDING: u32 = 2;
+
+/// Per-frame storage buffer of joint matrices, in the style of `FrameDataUbo`
+pub struct BoneBuffer {
+    buffer: ManagedBuffer,
+    padded_size: u64,
+    frames: usize,
+    max_joints: usize,
+}
+
+impl BoneBuffer {
+    pub fn new(core: SharedCore, frames: usize, max_joints: usize) -> Result<Self> {
+        let padded_size = memory::pad_size(
+            core.device_properties
+                .limits
+                .min_storage_buffer_offset_alignment,
+            (std::mem::size_of::<[f32; 16]>() * max_joints) as u64,
+        );
+        let total_size = padded_size * frames as u64;
+
+        let ci = vk::BufferCreateInfoBuilder::new()
+            .size(total_size)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .usage(vk::BufferUsageFlags::STORAGE_BUFFER);
+        let buffer = ManagedBuffer::new(core, ci, memory::UsageFlags::UPLOAD)?;
+
+        Ok(Self {
+            buffer,
+            padded_size,
+            frames,
+            max_joints,
+        })
+    }
+
+    pub fn descriptor_buffer_info(&self, frame: usize) -> vk::DescriptorBufferInfoBuilder<'static> {
+        vk::DescriptorBufferInfoBuilder::new()
+            .buffer(self.buffer.instance())
+            .offset(self.offset(frame))
+            .range(self.padded_size)
+    }
+
+    fn offset(&self, frame: usize) -> u64 {
+        debug_assert!(frame < self.frames, "Invalid frame {}", frame);
+        self.padded_size * frame as u64
+    }
+
+    /// Upload joint matrices for the given frame
+    pub fn upload(&mut self, frame: usize, joints: &[Matrix4<f32>]) -> Result<()> {
+        ensure!(
+            joints.len() <= self.max_joints,
+            "{} joints exceeds the maximum of {}",
+            joints.len(),
+            self.max_joints
+        );
+        let data: Vec<f32> = joints
+            .iter()
+            .flat_map(|m| m.as_slice().iter().copied())
+            .collect();
+        self.buffer
+            .write_slice(self.offset(frame), &data)
+    }
+}
+
+/// Local transform of a single joint
+#[derive(Copy, Clone, Debug)]
+pub struct JointTransform {
+    pub translation: Vector3<f32>,
+    pub rotation: UnitQuaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Default for JointTransform {
+    fn default() -> Self {
+        Self {
+            translation: Vector3::zeros(),
+            rotation: UnitQuaternion::identity(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl JointTransform {
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Translation3::from(self.translation).to_homogeneous()
+            * self.rotation.to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&self.scale)
+    }
+}
+
+/// Joint hierarchy of a skin. Joints must be ordered such that parents come before children.
+#[derive(Clone, Debug)]
+pub struct Skeleton {
+    /// Parent of each joint, or None for roots
+    pub parents: Vec<Option<usize>>,
+    /// Inverse bind matrix of each joint
+    pub inverse_bind: Vec<Matrix4<f32>>,
+    /// Rest pose, used for joints an animation does not touch
+    pub rest_pose: Vec<JointTransform>,
+}
+
+impl Skeleton {
+    pub fn n_joints(&self) -> usize {
+        self.parents.len()
+    }
+
+    /// Compute the skinning matrices for the given pose, ready for `BoneBuffer::upload()`
+    pub fn joint_matrices(&self, pose: &[JointTransform]) -> Vec<Matrix4<f32>> {
+        let mut global: Vec<Matrix4<f32>> = Vec::with_capacity(self.n_joints());
+        for (joint, local) in pose.iter().enumerate() {
+            let local = local.matrix();
+            let matrix = match self.parents[joint] {
+                Some(parent) => {
+                    debug_assert!(parent < joint, "Parent joints must precede children");
+                    global[parent] * local
+                }
+                None => local,
+            };
+            global.push(matrix);
+        }
+
+        global
+            .iter()
+            .zip(&self.inverse_bind)
+            .map(|(global, inverse_bind)| global * inverse_bind)
+            .collect()
+    }
+}
+
+/// Keyframe interpolation mode
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Interpolation {
+    Step,
+    Linear,
+}
+
+/// Keyframe values for one joint property
+#[derive(Clone, Debug)]
+pub enum Keyframes {
+    Translation(Vec<Vector3<f32>>),
+    Rotation(Vec<UnitQuaternion<f32>>),
+    Scale(Vec<Vector3<f32>>),
+}
+
+/// Animates one property of one joint
+#[derive(Clone, Debug)]
+pub struct Channel {
+    pub joint: usize,
+    pub interpolation: Interpolation,
+    /// Keyframe times in seconds, ascending
+    pub times: Vec<f32>,
+    pub keyframes: Keyframes,
+}
+
+/// A set of channels animating a skeleton
+#[derive(Clone, Debug, Default)]
+pub struct Animation {
+    pub channels: Vec<Channel>,
+}
+
+impl Animation {
+    /// Length of the animation in seconds
+    pub fn duration(&self) -> f32 {
+        self.channels
+            .iter()
+            .filter_map(|c| c.times.last().copied())
+            .fold(0.0, f32::max)
+    }
+
+    /// Sample the animation at `time` seconds, starting from the skeleton's rest pose
+    pub fn sample(&self, skeleton: &Skeleton, time: f32) -> Vec<JointTransform> {
+        let mut pose = skeleton.rest_pose.clone();
+        for channel in &self.channels {
+            channel.apply(time, &mut pose[channel.joint]);
+        }
+        pose
+    }
+
+    /// Sample the animation, wrapping `time` around its duration
+    pub fn sample_looped(&self, skeleton: &Skeleton, time: f32) -> Vec<JointTransform> {
+        let duration = self.duration();
+        let time = if duration > 0.0 {
+            time.rem_euclid(duration)
+        } else {
+            0.0
+        };
+        self.sample(skeleton, time)
+    }
+}
+
+impl Channel {
+    /// Returns the keyframe indices surrounding `time` and the blend factor between them
+    fn locate(&self, time: f32) -> (usize, usize, f32) {
+        let last = self.times.len() - 1;
+        match self.times.iter().position(|&t| t > time) {
+            Some(0) => (0, 0, 0.0),
+            None => (last, last, 0.0),
+            Some(next) => {
+                let prev = next - 1;
+                let span = self.times[next] - self.times[prev];
+                let t = if span > 0.0 {
+                    (time - self.times[prev]) / span
+                } else {
+                    0.0
+                };
+                match self.interpolation {
+                    Interpolation::Step => (prev, prev, 0.0),
+                    Interpolation::Linear => (prev, next, t),
+                }
+            }
+        }
+    }
+
+    fn apply(&self, time: f32, joint: &mut JointTransform) {
+        if self.times.is_empty() {
+            return;
+        }
+        let (a, b, t) = self.locate(time);
+        match &self.keyframes {
+            Keyframes::Translation(v) => joint.translation = v[a].lerp(&v[b], t),
+            Keyframes::Scale(v) => joint.scale = v[a].lerp(&v[b], t),
+            Keyframes::Rotation(v) => {
+                joint.rotation = v[a].try_slerp(&v[b], t, 1e-6).unwrap_or(v[b])
+            }
+        }
+    }
+}
+
+/// Convert a glTF-style `[x, y, z, w]` quaternion
+pub fn quat_from_xyzw(q: [f32; 4]) -> UnitQuaternion<f32> {
+    UnitQuaternion::from_quaternion(Quaternion::new(q[3], q[0], q[1], q[2]))
+}
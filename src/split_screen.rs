@@ -0,0 +1,227 @@
+//! Split-screen rendering: partition the render area into several viewports, each with its own
+//! scissor and camera data, all drawn within a single render pass.
+use crate::frame_data_ubo::FrameDataUbo;
+use crate::{Core, SharedCore};
+use anyhow::{ensure, Result};
+use bytemuck::Pod;
+use erupt::vk;
+
+/// How viewports are arranged
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SplitLayout {
+    /// Side by side, left to right
+    #[default]
+    Columns,
+    /// Stacked, top to bottom
+    Rows,
+    /// As square a grid as possible, filled row by row
+    Grid,
+}
+
+/// One region of a split render area
+#[derive(Copy, Clone, Debug)]
+pub struct ViewportRegion {
+    pub viewport: vk::Viewport,
+    pub scissor: vk::Rect2D,
+}
+
+impl ViewportRegion {
+    /// The region covering `rect`, with the full depth range
+    pub fn from_rect(rect: vk::Rect2D) -> Self {
+        Self {
+            viewport: vk::Viewport {
+                x: rect.offset.x as f32,
+                y: rect.offset.y as f32,
+                width: rect.extent.width as f32,
+                height: rect.extent.height as f32,
+                min_depth: 0.0,
+                max_depth: 1.0,
+            },
+            scissor: rect,
+        }
+    }
+
+    /// Size of this region in pixels, suitable for computing a projection matrix
+    pub fn extent(&self) -> vk::Extent2D {
+        self.scissor.extent
+    }
+
+    /// Width over height
+    pub fn aspect(&self) -> f32 {
+        self.viewport.width / self.viewport.height
+    }
+
+    /// Whether the given pixel position (such as the cursor) lies within this region
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        let vk::Rect2D { offset, extent } = self.scissor;
+        let (x, y) = (x - offset.x as f64, y - offset.y as f64);
+        x >= 0. && y >= 0. && x < extent.width as f64 && y < extent.height as f64
+    }
+
+    /// Set the dynamic viewport and scissor to this region
+    pub fn set(&self, core: &Core, command_buffer: vk::CommandBuffer) {
+        let viewports = [self.viewport.into_builder()];
+        let scissors = [self.scissor.into_builder()];
+        unsafe {
+            core.device.cmd_set_viewport(command_buffer, 0, &viewports);
+            core.device.cmd_set_scissor(command_buffer, 0, &scissors);
+        }
+    }
+}
+
+/// Partition `extent` into `count` regions
+pub fn split(extent: vk::Extent2D, count: usize, layout: SplitLayout) -> Vec<ViewportRegion> {
+    if count == 0 {
+        return vec![];
+    }
+
+    let (columns, rows) = match layout {
+        SplitLayout::Columns => (count, 1),
+        SplitLayout::Rows => (1, count),
+        SplitLayout::Grid => {
+            let columns = (count as f32).sqrt().ceil() as usize;
+            (columns, count.div_ceil(columns))
+        }
+    };
+
+    // Integer edges, so that neighbouring regions share a boundary with no gaps
+    let edge =
+        |size: u32, divisions: usize, i: usize| (size as u64 * i as u64 / divisions as u64) as u32;
+
+    (0..count)
+        .map(|i| {
+            let (column, row) = (i % columns, i / columns);
+            let (x0, x1) = (
+                edge(extent.width, columns, column),
+                edge(extent.width, columns, column + 1),
+            );
+            let (y0, y1) = (
+                edge(extent.height, rows, row),
+                edge(extent.height, rows, row + 1),
+            );
+            ViewportRegion::from_rect(vk::Rect2D {
+                offset: vk::Offset2D {
+                    x: x0 as i32,
+                    y: y0 as i32,
+                },
+                extent: vk::Extent2D {
+                    width: x1 - x0,
+                    height: y1 - y0,
+                },
+            })
+        })
+        .collect()
+}
+
+/// The largest region of `extent` with width over height `aspect`, centered. Wider extents get
+/// bars at the sides (pillarboxing), taller ones bars above and below (letterboxing).
+pub fn letterbox(extent: vk::Extent2D, aspect: f32) -> ViewportRegion {
+    let (width, height) = (extent.width as f32, extent.height as f32);
+    let (width, height) = if width > height * aspect {
+        ((height * aspect).round() as u32, extent.height)
+    } else {
+        (extent.width, (width / aspect).round() as u32)
+    };
+    let (width, height) = (width.clamp(1, extent.width), height.clamp(1, extent.height));
+    ViewportRegion::from_rect(vk::Rect2D {
+        offset: vk::Offset2D {
+            x: ((extent.width - width) / 2) as i32,
+            y: ((extent.height - height) / 2) as i32,
+        },
+        extent: vk::Extent2D { width, height },
+    })
+}
+
+/// A fixed number of viewports over the swapchain, each with a per-frame UBO slot for its camera
+/// (or other per-view data). Bind one descriptor set per frame and viewport, pointing at
+/// `descriptor_buffer_info(frame, viewport)`.
+///
+/// ```ignore
+/// // In swapchain_resize()
+/// split.resize(extent);
+///
+/// // Each frame, inside the swapchain pass
+/// for viewport in 0..split.len() {
+///     split.upload(frame, viewport, &cameras[viewport])?;
+///     split.begin_viewport(core, command_buffer, viewport);
+///     // Bind descriptor_sets[frame][viewport] and draw the scene
+/// }
+/// ```
+pub struct SplitScreen<T> {
+    ubo: FrameDataUbo<T>,
+    regions: Vec<ViewportRegion>,
+    layout: SplitLayout,
+    count: usize,
+    frames: usize,
+}
+
+impl<T: Pod> SplitScreen<T> {
+    /// `resize()` must be called before use
+    pub fn new(core: SharedCore, frames: usize, count: usize, layout: SplitLayout) -> Result<Self> {
+        ensure!(count > 0, "Split screen needs at least one viewport");
+        Ok(Self {
+            ubo: FrameDataUbo::new(core, frames * count)?,
+            regions: vec![],
+            layout,
+            count,
+            frames,
+        })
+    }
+
+    /// Recompute the regions. Call this after the swapchain is resized.
+    pub fn resize(&mut self, extent: vk::Extent2D) {
+        self.regions = split(extent, self.count, self.layout);
+    }
+
+    /// Change the arrangement, takes effect on the next `resize()`
+    pub fn set_layout(&mut self, layout: SplitLayout) {
+        self.layout = layout;
+    }
+
+    /// Number of viewports
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Always false; there is at least one viewport
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    pub fn regions(&self) -> &[ViewportRegion] {
+        &self.regions
+    }
+
+    pub fn region(&self, viewport: usize) -> ViewportRegion {
+        self.regions[viewport]
+    }
+
+    /// Index of the viewport containing the given pixel position, for routing input
+    pub fn viewport_at(&self, x: f64, y: f64) -> Option<usize> {
+        self.regions.iter().position(|region| region.contains(x, y))
+    }
+
+    /// Set the viewport and scissor for drawing into the given viewport
+    pub fn begin_viewport(&self, core: &Core, command_buffer: vk::CommandBuffer, viewport: usize) {
+        self.region(viewport).set(core, command_buffer);
+    }
+
+    pub fn descriptor_buffer_info(
+        &self,
+        frame: usize,
+        viewport: usize,
+    ) -> vk::DescriptorBufferInfoBuilder<'static> {
+        self.ubo.descriptor_buffer_info(self.slot(frame, viewport))
+    }
+
+    pub fn upload(&mut self, frame: usize, viewport: usize, data: &T) -> Result<()> {
+        let slot = self.slot(frame, viewport);
+        self.ubo.upload(slot, data)
+    }
+
+    fn slot(&self, frame: usize, viewport: usize) -> usize {
+        debug_assert!(frame < self.frames, "Invalid frame {}", frame);
+        debug_assert!(viewport < self.count, "Invalid viewport {}", viewport);
+        frame * self.count + viewport
+    }
+}
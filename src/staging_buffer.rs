@@ -1,27 +1,208 @@
 use crate::{memory::{UsageFlags, ManagedBuffer, ManagedImage}};
-use crate::SharedCore;
-use anyhow::{Result, Context};
+use crate::{Core, SharedCore};
+use crate::barriers;
+use crate::defaults::{STAGING_BUFFER_SIZE, STAGING_SHRINK_FRAMES};
+use crate::sync2::{self, Access, Stages};
+use crate::synchronization::SharedFence;
+use anyhow::{ensure, Result, Context};
 use bytemuck::Pod;
 use erupt::vk;
 
+/// Layout an image is in while it is copied to. Refer to:
+/// https://www.khronos.org/registry/vulkan/specs/1.2-extensions/man/html/vkCmdCopyBufferToImage.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum CopyLayout {
+    /// Fastest on most hardware
+    #[default]
+    TransferDstOptimal,
+    General,
+}
+
+impl From<CopyLayout> for vk::ImageLayout {
+    fn from(layout: CopyLayout) -> Self {
+        match layout {
+            CopyLayout::TransferDstOptimal => vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            CopyLayout::General => vk::ImageLayout::GENERAL,
+        }
+    }
+}
+
+/// Progress of the uploads of a `StagingBuffer`, reported as each part of a resource is
+/// submitted (see `StagingBuffer::set_progress_callback()`). Earlier parts have completed by
+/// then, as each part waits on the one before it.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct UploadProgress {
+    /// Bytes of the resource being uploaded which have been submitted
+    pub resource_bytes: u64,
+    /// Size of the resource being uploaded in bytes
+    pub resource_size: u64,
+    /// Resources fully submitted since `begin_batch()`
+    pub resources_done: usize,
+    /// Bytes submitted since `begin_batch()`
+    pub batch_bytes: u64,
+    /// Size of the batch given to `begin_batch()`, or 0 if unknown
+    pub batch_size: u64,
+}
+
+impl UploadProgress {
+    /// Whether the resource's final part has been submitted
+    pub fn resource_done(&self) -> bool {
+        self.resource_bytes == self.resource_size
+    }
+
+    /// Fraction of the batch submitted from 0 to 1, if its size is known
+    pub fn batch_fraction(&self) -> Option<f32> {
+        (self.batch_size > 0)
+            .then(|| (self.batch_bytes as f64 / self.batch_size as f64).min(1.0) as f32)
+    }
+}
+
+/// Called with the progress of each part of an upload. `Send`, like the `StagingBuffer` holding it
+pub type ProgressCallback = Box<dyn FnMut(UploadProgress) + Send>;
+
+/// Uploads data to device-local memory. Uploads are submitted with a fence rather than waited
+/// on, so they overlap with rendering; the results carry the fence (see
+/// `ManagedBuffer::is_ready()`). Commands submitted to `Core::queue` after an upload are ordered
+/// after it by its final barrier, so draws using the results may be recorded right away.
+///
+/// The buffer grows to fit `update_buffer_bytes()` calls and image rows larger than it, and
+/// shrinks back to its initial size once the extra space goes unused for `shrink_after` frames
+/// (see `end_frame()`), so a single large upload doesn't hold on to host memory for good.
 pub struct StagingBuffer {
     buffer: ManagedBuffer,
     current_size: u64,
+    /// Size the buffer was created with, which it shrinks back to
+    initial_size: u64,
+    /// Frames since capacity beyond `initial_size` was last used
+    idle_frames: u32,
+    /// Frames the buffer may stay larger than its initial size without using the extra space,
+    /// before `end_frame()` shrinks it. None keeps it at its largest. Defaults to
+    /// `STAGING_SHRINK_FRAMES`.
+    pub shrink_after: Option<u32>,
+    /// Layout images are copied into by `upload_image()`
+    pub copy_layout: CopyLayout,
+    /// Most recent upload, which may still be reading from the staging buffer
+    pending: Option<SharedFence>,
+    /// Progress since the last `begin_batch()`
+    progress: UploadProgress,
+    progress_callback: Option<ProgressCallback>,
     // TODO: Storing this here is sort of wasteful?
     core: SharedCore,
 }
 
 impl StagingBuffer {
     pub fn new(core: SharedCore) -> Result<Self> {
-        let current_size = 1024 * 1024; // 1 MB
+        Self::with_capacity(core, STAGING_BUFFER_SIZE)
+    }
+
+    /// Create a staging buffer of the given size in bytes. Uploads larger than this are split
+    /// into parts; only `update_buffer_bytes()` and images with larger rows grow the buffer.
+    pub fn with_capacity(core: SharedCore, current_size: u64) -> Result<Self> {
         Ok(Self {
             buffer: Self::build_staging_buffer(core.clone(), current_size)?,
             current_size,
+            initial_size: current_size,
+            idle_frames: 0,
+            shrink_after: Some(STAGING_SHRINK_FRAMES),
+            copy_layout: CopyLayout::default(),
+            pending: None,
+            progress: UploadProgress::default(),
+            progress_callback: None,
             core,
         })
     }
 
-    /// Warning: Assumes an inactive command buffer
+    /// Block until the most recent upload has completed, after which its command buffer may be
+    /// reset
+    pub fn wait_pending(&mut self) -> Result<()> {
+        if let Some(fence) = self.pending.take() {
+            fence.wait()?;
+        }
+        Ok(())
+    }
+
+    /// Report the progress of uploads to `callback` as each part is submitted, such as to draw a
+    /// loading screen or to send it to one over a channel:
+    ///
+    /// ```ignore
+    /// let (sender, receiver) = std::sync::mpsc::channel();
+    /// staging.set_progress_callback(Some(Box::new(move |progress| {
+    ///     let _ = sender.send(progress);
+    /// })));
+    /// ```
+    ///
+    /// Only uploads split into parts by the staging buffer are reported, not
+    /// `update_buffer_bytes()`.
+    pub fn set_progress_callback(&mut self, callback: Option<ProgressCallback>) {
+        self.progress_callback = callback;
+    }
+
+    /// Start counting the progress of a batch of uploads, such as the resources of a scene,
+    /// expected to total `size` bytes (0 if unknown)
+    pub fn begin_batch(&mut self, size: u64) {
+        self.progress = UploadProgress {
+            batch_size: size,
+            ..Default::default()
+        };
+    }
+
+    /// Progress since the last `begin_batch()`
+    pub fn progress(&self) -> UploadProgress {
+        self.progress
+    }
+
+    /// Current size of the buffer in bytes
+    pub fn capacity(&self) -> u64 {
+        self.current_size
+    }
+
+    /// Shrink the buffer to `size` bytes, if it is larger, waiting on the pending upload first.
+    /// Frames still in flight may read the old buffer, which is destroyed once they complete.
+    pub fn shrink_to(&mut self, size: u64) -> Result<()> {
+        ensure!(size > 0, "Staging buffers must not be empty");
+        if size >= self.current_size {
+            return Ok(());
+        }
+        self.resize(size)
+    }
+
+    /// Advance the shrink policy by a frame, shrinking the buffer back to its initial size if
+    /// the capacity beyond it has gone unused for `shrink_after` frames. Called once per frame
+    /// by the StarterKit; call it from your own frame loop otherwise.
+    pub fn end_frame(&mut self) -> Result<()> {
+        if self.current_size <= self.initial_size {
+            self.idle_frames = 0;
+            return Ok(());
+        }
+        self.idle_frames += 1;
+        match self.shrink_after {
+            Some(frames) if self.idle_frames > frames => self.shrink_to(self.initial_size),
+            _ => Ok(()),
+        }
+    }
+
+    /// Grow the buffer to at least `size` bytes, and note that the space was used
+    fn reserve(&mut self, size: u64) -> Result<()> {
+        if size > self.initial_size {
+            self.idle_frames = 0;
+        }
+        if size > self.current_size {
+            self.resize(size)?;
+        }
+        Ok(())
+    }
+
+    /// Replace the buffer with one of `size` bytes, once the pending upload is done with it
+    fn resize(&mut self, size: u64) -> Result<()> {
+        self.wait_pending()?;
+        self.buffer = Self::build_staging_buffer(self.core.clone(), size)
+            .context("Failed to alloc staging buffer")?;
+        self.current_size = size;
+        Ok(())
+    }
+
+    /// Warning: Assumes an inactive command buffer, which must not be reset until the upload is
+    /// complete
     pub fn upload_buffer_pod<T: Pod>(
         &mut self,
         command_buffer: vk::CommandBuffer,
@@ -45,15 +226,15 @@ impl StagingBuffer {
         gpu_buffer: &mut ManagedBuffer,
         data: &[u8],
     ) -> Result<()> {
+        // Don't overwrite data an upload is still reading from
+        self.wait_pending()?;
+
         // Expand our internal buffer to match the size of the data to be uploaded
         let data_len: u64 = data.len() as u64;
-        if data_len > self.current_size {
-            self.current_size = data_len;
-            self.buffer = Self::build_staging_buffer(self.core.clone(), self.current_size).context("Failed to alloc staging buffer")?;
-        }
+        self.reserve(data_len)?;
 
         // Write to the staging buffer
-        self.buffer.write_bytes(0, bytemuck::cast_slice(data))?;
+        self.buffer.write_bytes(0, data)?;
 
         unsafe {
             let region = vk::BufferCopyBuilder::new()
@@ -72,10 +253,11 @@ impl StagingBuffer {
         Ok(())
     }
 
-    // TODO: Make a batched upload option? (So that you don't have to do a million queue idles...
     // TODO: This should also probably use a transfer queue...
-    // TODO: Multi-part uploads for BIG data?
-    /// Warning: Assumes an inactive command buffer
+    /// Data larger than the staging buffer is uploaded in several parts, waiting on each part
+    /// before the next is written.
+    /// Warning: Assumes an inactive command buffer, which must not be reset until the upload is
+    /// complete
     pub fn upload_buffer_bytes(
         &mut self,
         command_buffer: vk::CommandBuffer,
@@ -86,37 +268,119 @@ impl StagingBuffer {
         ci.usage |= vk::BufferUsageFlags::TRANSFER_DST;
         let mut gpu_buffer = ManagedBuffer::new(self.core.clone(), ci, UsageFlags::FAST_DEVICE_ACCESS).context("Failed to allocate device buffer")?;
 
-        // Record command buffer to upload to gpu_buffer
-        unsafe {
-            self.core
-                .device
-                .reset_command_buffer(command_buffer, None)
-                .result()?;
-            let begin_info = vk::CommandBufferBeginInfoBuilder::new();
-            self.core
-                .device
-                .begin_command_buffer(command_buffer, &begin_info)
-                .result()?;
+        if let Some(fence) = self.upload_into_buffer(command_buffer, gpu_buffer.instance(), 0, ci.usage, data)? {
+            gpu_buffer.set_ready(fence);
+        }
 
-            self.update_buffer_bytes(command_buffer, &mut gpu_buffer, data)?;
+        Ok(gpu_buffer)
+    }
 
-            self.core
-                .device
-                .end_command_buffer(command_buffer)
-                .result()?;
-            let command_buffers = [command_buffer];
-            let submit_info = vk::SubmitInfoBuilder::new().command_buffers(&command_buffers);
-            self.core
-                .device
-                .queue_submit(self.core.queue, &[submit_info], None)
-                .result()?;
-            self.core.device.queue_wait_idle(self.core.queue).result()?;
+    /// Upload `data` into part of an existing device buffer (created with `TRANSFER_DST`),
+    /// starting `offset` bytes in, to be read as `usage`. Returns the fence of the upload's final
+    /// part, or None if there was no data. Parts are split as in `upload_buffer_bytes()`.
+    /// Warning: Assumes an inactive command buffer, which must not be reset until the upload is
+    /// complete
+    pub fn upload_into_buffer(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        gpu_buffer: vk::Buffer,
+        offset: u64,
+        usage: vk::BufferUsageFlags,
+        data: &[u8],
+    ) -> Result<Option<SharedFence>> {
+        let chunk_size = self.current_size as usize;
+        let n_chunks = data.len().div_ceil(chunk_size);
+        let mut fence = None;
+        for (idx, chunk) in data.chunks(chunk_size).enumerate() {
+            fence = Some(self.upload_chunk(command_buffer, chunk, |core, staging| unsafe {
+                let region = vk::BufferCopyBuilder::new()
+                    .size(chunk.len() as u64)
+                    .src_offset(0)
+                    .dst_offset(offset + (idx * chunk_size) as u64);
+
+                core.device.cmd_copy_buffer(
+                    command_buffer,
+                    staging,
+                    gpu_buffer,
+                    &[region],
+                );
+
+                // Make the upload visible to later submissions
+                if idx + 1 == n_chunks {
+                    let barrier = barriers::transfer_to_buffer_usage(gpu_buffer, usage);
+                    core.cmd_barriers(command_buffer, &[barrier], &[]);
+                }
+            })?);
+            self.report(chunk.len() as u64, data.len() as u64);
         }
 
-        Ok(gpu_buffer)
+        Ok(fence)
     }
 
+    /// Read `data.len()` bytes of a device buffer (created with `TRANSFER_SRC`) last used as
+    /// `usage`, starting `offset` bytes in. Blocks until the copy has completed, so this is meant
+    /// for occasional readbacks such as saving generated data, not for every frame.
     /// Warning: Assumes an inactive command buffer
+    pub fn download_buffer(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        gpu_buffer: vk::Buffer,
+        offset: u64,
+        usage: vk::BufferUsageFlags,
+        data: &mut [u8],
+    ) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let ci = vk::BufferCreateInfoBuilder::new()
+            .size(data.len() as u64)
+            .usage(vk::BufferUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let mut readback = ManagedBuffer::new(self.core.clone(), ci, UsageFlags::DOWNLOAD)
+            .context("Failed to allocate readback buffer")?;
+
+        // Don't reset a command buffer an upload is still using
+        self.wait_pending()?;
+        let fence = self.record_and_submit(command_buffer, |core, _| unsafe {
+            // Wait for whatever last wrote the buffer
+            let to_transfer = barriers::buffer_barrier(
+                gpu_buffer,
+                (sync2::buffer_usage(usage).0, Access::MEMORY_WRITE_KHR),
+                (Stages::TRANSFER_KHR, Access::TRANSFER_READ_KHR),
+            );
+            core.cmd_barriers(command_buffer, &[to_transfer], &[]);
+
+            let region = vk::BufferCopyBuilder::new()
+                .size(data.len() as u64)
+                .src_offset(offset)
+                .dst_offset(0);
+            core.device.cmd_copy_buffer(
+                command_buffer,
+                gpu_buffer,
+                readback.instance(),
+                &[region],
+            );
+
+            let to_host = barriers::buffer_barrier(
+                readback.instance(),
+                (Stages::TRANSFER_KHR, Access::TRANSFER_WRITE_KHR),
+                (Stages::HOST_KHR, Access::HOST_READ_KHR),
+            );
+            core.cmd_barriers(command_buffer, &[to_host], &[]);
+        })?;
+        fence.wait()?;
+
+        readback.read_bytes(0, data)
+    }
+
+    /// Upload an image, returning it with its subresource range and layout (`final_layout`, to be
+    /// used in descriptor writes). Images larger than the staging buffer are uploaded a band of
+    /// rows at a time, waiting on each band before the next is written. `data` must be tightly
+    /// packed rows of texels.
+    /// Warning: Assumes an inactive command buffer, which must not be reset until the upload is
+    /// complete
+    #[allow(clippy::too_many_arguments)]
     pub fn upload_image(
         &mut self,
         command_buffer: vk::CommandBuffer,
@@ -126,7 +390,7 @@ impl StagingBuffer {
         format: vk::Format,
         usage: vk::ImageUsageFlags,
         final_layout: vk::ImageLayout,
-    ) -> Result<(ManagedImage, vk::ImageSubresourceRangeBuilder<'static>)> {
+    ) -> Result<(ManagedImage, vk::ImageSubresourceRangeBuilder<'static>, vk::ImageLayout)> {
         // Image settings
         let extent = vk::Extent3DBuilder::new()
             .width(width)
@@ -146,8 +410,6 @@ impl StagingBuffer {
             .sharing_mode(vk::SharingMode::EXCLUSIVE)
             .samples(vk::SampleCountFlagBits::_1);
 
-        let offset = vk::Offset3DBuilder::new().x(0).y(0).z(0).build();
-
         let image_subresources = vk::ImageSubresourceLayersBuilder::new()
             .aspect_mask(vk::ImageAspectFlags::COLOR)
             .mip_level(0)
@@ -162,31 +424,105 @@ impl StagingBuffer {
             .base_array_layer(image_subresources.base_array_layer)
             .layer_count(image_subresources.layer_count);
 
-        let copy = vk::BufferImageCopyBuilder::new()
-            .buffer_offset(0)
-            .buffer_row_length(0)
-            .buffer_image_height(0)
-            .image_subresource(image_subresources)
-            .image_offset(offset)
-            .image_extent(extent);
+        // Split into bands of whole rows which fit in the staging buffer
+        let row_size = data.len() / height.max(1) as usize;
+        self.reserve(row_size as u64)?;
+        let band_rows = (self.current_size as usize / row_size.max(1)).max(1);
+        let n_bands = (height as usize).div_ceil(band_rows);
 
-        // Expand our internal buffer to match the size of the data to be uploaded
-        if data.len() as u64 > self.current_size {
-            self.current_size = data.len() as u64;
-            self.buffer = Self::build_staging_buffer(self.core.clone(), self.current_size).context("Failed to build staging buffer")?;
+        // Create the final buffer
+        let mut gpu_image = ManagedImage::new(self.core.clone(), ci, UsageFlags::FAST_DEVICE_ACCESS).context("Failed to allocate GPU image")?;
+
+        let image_layout = self.copy_layout.into();
+
+        // Upload to this new buffer
+        for (idx, band) in data.chunks(band_rows * row_size).enumerate() {
+            let first_row = (idx * band_rows) as u32;
+            let rows = (band.len() / row_size.max(1)) as u32;
+
+            let fence = self.upload_chunk(command_buffer, band, |core, staging| unsafe {
+                if idx == 0 {
+                    gpu_image.transition(command_buffer, image_layout, Stages::TRANSFER_KHR);
+                }
+
+                let copy = vk::BufferImageCopyBuilder::new()
+                    .buffer_offset(0)
+                    .buffer_row_length(0)
+                    .buffer_image_height(0)
+                    .image_subresource(image_subresources)
+                    .image_offset(vk::Offset3D {
+                        x: 0,
+                        y: first_row as i32,
+                        z: 0,
+                    })
+                    .image_extent(vk::Extent3D {
+                        width,
+                        height: rows,
+                        depth: 1,
+                    });
+
+                core.device.cmd_copy_buffer_to_image(
+                    command_buffer,
+                    staging,
+                    gpu_image.instance(),
+                    image_layout,
+                    &[copy],
+                );
+
+                // Make the upload visible to wherever the final layout is typically used
+                if idx + 1 == n_bands {
+                    let stages = sync2::layout_usage(final_layout).0;
+                    gpu_image.transition(command_buffer, final_layout, stages);
+                }
+            })?;
+            gpu_image.set_ready(fence);
+            self.report(band.len() as u64, data.len() as u64);
         }
 
-        // Write to the staging buffer
-        self.buffer.write_bytes(0, data)?;
+        Ok((gpu_image, subresource_range, final_layout))
+    }
 
-        // Create the final buffer
-        let gpu_image = ManagedImage::new(self.core.clone(), ci, UsageFlags::FAST_DEVICE_ACCESS).context("Failed to allocate GPU image")?;
+    /// Count `bytes` more of a `size` byte resource as submitted, and report the progress
+    fn report(&mut self, bytes: u64, size: u64) {
+        let progress = &mut self.progress;
+        if progress.resource_bytes >= progress.resource_size {
+            progress.resource_bytes = 0;
+        }
+        progress.resource_size = size;
+        progress.resource_bytes += bytes;
+        progress.batch_bytes += bytes;
+        if progress.resource_done() {
+            progress.resources_done += 1;
+        }
+        if let Some(callback) = &mut self.progress_callback {
+            callback(*progress);
+        }
+    }
 
-        // NOTE: image_layout must be one of VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL, VK_IMAGE_LAYOUT_GENERAL, or VK_IMAGE_LAYOUT_SHARED_PRESENT_KHR
-        // Refer to: https://www.khronos.org/registry/vulkan/specs/1.2-extensions/man/html/vkCmdCopyBufferToImage.html
-        let image_layout = vk::ImageLayout::GENERAL; // TODO: Add an enum for some common modes? (like DST_OPTIMAL)
+    /// Write `chunk` to the staging buffer and submit the commands recorded by `record`, which is
+    /// given the staging buffer. Waits on the previous upload first.
+    fn upload_chunk(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        chunk: &[u8],
+        record: impl FnOnce(&Core, vk::Buffer),
+    ) -> Result<SharedFence> {
+        // Don't overwrite data (or reset a command buffer) an upload is still using
+        self.wait_pending()?;
+        if chunk.len() as u64 > self.initial_size {
+            self.idle_frames = 0;
+        }
+        self.buffer.write_bytes(0, chunk)?;
+        self.record_and_submit(command_buffer, record)
+    }
 
-        // Upload to this new buffer
+    /// Record `command_buffer` with `record`, which is given the staging buffer, and submit it.
+    /// The previous upload must have completed.
+    fn record_and_submit(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        record: impl FnOnce(&Core, vk::Buffer),
+    ) -> Result<SharedFence> {
         unsafe {
             self.core
                 .device
@@ -198,68 +534,24 @@ impl StagingBuffer {
                 .begin_command_buffer(command_buffer, &begin_info)
                 .result()?;
 
-            let barrier = vk::ImageMemoryBarrierBuilder::new()
-                .image(gpu_image.instance())
-                .old_layout(vk::ImageLayout::UNDEFINED)
-                .new_layout(image_layout)
-                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-                .src_access_mask(vk::AccessFlags::empty())
-                .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
-                .subresource_range(subresource_range.build());
-
-            self.core.device.cmd_pipeline_barrier(
-                command_buffer,
-                vk::PipelineStageFlags::TOP_OF_PIPE,
-                vk::PipelineStageFlags::TRANSFER,
-                None,
-                &[],
-                &[],
-                &[barrier],
-            );
-
-            self.core.device.cmd_copy_buffer_to_image(
-                command_buffer,
-                self.buffer.instance(),
-                gpu_image.instance(),
-                image_layout,
-                &[copy],
-            );
-
-            let barrier = vk::ImageMemoryBarrierBuilder::new()
-                .image(gpu_image.instance())
-                .old_layout(image_layout)
-                .new_layout(final_layout)
-                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
-                .dst_access_mask(vk::AccessFlags::empty())
-                .subresource_range(subresource_range.build());
-
-            self.core.device.cmd_pipeline_barrier(
-                command_buffer,
-                vk::PipelineStageFlags::TRANSFER,
-                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
-                None,
-                &[],
-                &[],
-                &[barrier],
-            );
+            record(&self.core, self.buffer.instance());
 
             self.core
                 .device
                 .end_command_buffer(command_buffer)
                 .result()?;
-            let command_buffers = [command_buffer];
-            let submit_info = vk::SubmitInfoBuilder::new().command_buffers(&command_buffers);
-            self.core
-                .device
-                .queue_submit(self.core.queue, &[submit_info], None)
-                .result()?;
-            self.core.device.queue_wait_idle(self.core.queue).result()?;
         }
 
-        Ok((gpu_image, subresource_range))
+        self.submit(command_buffer)
+    }
+
+    /// Submit an upload, which becomes the pending one
+    fn submit(&mut self, command_buffer: vk::CommandBuffer) -> Result<SharedFence> {
+        let fence = SharedFence::new(self.core.clone())?;
+        self.core
+            .submit(command_buffer, None, None, Some(fence.fence()))?;
+        self.pending = Some(fence.clone());
+        Ok(fence)
     }
 
     fn build_staging_buffer(core: SharedCore, size: u64) -> Result<ManagedBuffer> {
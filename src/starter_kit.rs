@@ -1,32 +1,596 @@
 use crate::app_info::AppInfo;
 use crate::mainloop::{Frame, Platform, PlatformEvent, SyncMainLoop};
-use crate::{render_pass::create_render_pass, framebuffer_mgr::FramebufferManager, staging_buffer::StagingBuffer, synchronization::Synchronization};
-use crate::SharedCore;
-use anyhow::Result;
+use crate::{render_pass::{create_render_pass_with_settings, RenderPassSettings}, framebuffer_mgr::FramebufferManager, staging_buffer::StagingBuffer, synchronization::Synchronization};
+use crate::post::{Fxaa, FxaaSettings, Ssao, SsaoSettings, StereoPreview, Upscale};
+use crate::color::{attachment_color, needs_shader_encoding};
+use crate::compute::{ComputeWrites, Dispatch};
+use crate::deferred::{self, DeferredLighting, GBuffer, Light, MAX_LIGHTS};
+use crate::pipeline_manager::PipelineManager;
+use crate::material::{Material, PUSH_CONSTANT_STAGES, TRANSFORM_SIZE};
+use crate::mesh::{draw_mesh, MeshSource};
+use crate::frame_guard::FrameGuard;
+use crate::profiling::{self, FrameEvent, GpuTimer};
+use crate::render_target::{RenderTarget, RenderTargetSettings};
+use crate::shader::clear_depth;
+use crate::split_screen::{self, ViewportRegion};
+use crate::sync2::{SemaphoreSubmit, Stages};
+use crate::{Core, SharedCore};
+use anyhow::{bail, ensure, Result};
 use erupt::vk;
-use crate::defaults::FRAMES_IN_FLIGHT;
+use crate::defaults::{
+    COLOR_FORMAT, FENCE_TIMEOUT, FRAMES_IN_FLIGHT, STAGING_BUFFER_SIZE, STAGING_SHRINK_FRAMES,
+};
+#[cfg(feature = "screenshot")]
+use crate::screenshot::Capture;
+#[cfg(feature = "screenshot")]
+use crate::synchronization::wait_for_fence;
+#[cfg(feature = "screenshot")]
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 /// The StarterKit is a collection of commonly used utilities and code, and is made out of other shortcuts.
 pub struct StarterKit {
+    /// Guards for each submitted frame. Declared first so they are dropped before `sync`
+    /// destroys their fences.
+    frame_guards: Vec<Option<FrameGuard>>,
     pub framebuffer: FramebufferManager,
     pub sync: Synchronization,
     pub render_pass: vk::RenderPass,
+    /// Pipelines built against `render_pass`, rebuilt when it is recreated
+    pub pipelines: PipelineManager,
+    /// Uploads of the StarterKit and the app. Prefer it over a staging buffer of your own, which
+    /// would hold another host allocation as large as the biggest upload.
     pub staging_buffer: StagingBuffer,
     pub command_buffers: Vec<vk::CommandBuffer>,
     pub core: SharedCore,
     pub frame: usize,
+    /// Transient pool of each frame in flight, which `command_buffers` are allocated from
+    frame_pools: Vec<FramePool>,
+    /// Long-lived pool of the pre-recorded command buffers
+    command_pool: vk::CommandPool,
+    prerecorded: Prerecorded,
+    /// Render pass over the swapchain images. Differs from `render_pass` when the scene is drawn
+    /// with two views on the desktop.
+    output_render_pass: vk::RenderPass,
+    /// Color format of `render_pass`
+    scene_format: vk::Format,
+    settings: Settings,
+    /// View pass being recorded, see `next_view()`
+    view: u32,
+    fxaa: Option<FxaaPath>,
+    stereo: Option<StereoPath>,
+    deferred: Option<DeferredPath>,
+    scaled: Option<ScaledPath>,
+    ssao: Option<SsaoPath>,
+    /// Whether the SSAO geometry prepass is being recorded, see `begin_ssao_prepass()`
+    ssao_prepass: bool,
+    /// Compute writes to make visible before the scene pass
+    compute_writes: ComputeWrites,
+    /// Extra semaphores for the next frame submitted to wait on and signal
+    frame_waits: Vec<SemaphoreSubmit>,
+    frame_signals: Vec<SemaphoreSubmit>,
+    /// None if the queue doesn't support timestamps
+    gpu_timer: Option<GpuTimer>,
+    gpu_frame_time: Option<f32>,
+    /// Timeline index of the frame last submitted by each frame in flight, see
+    /// `profiling::timeline()`
+    timeline_frames: Vec<Option<u64>>,
+    #[cfg(feature = "renderdoc")]
+    capture_key: Option<winit::event::VirtualKeyCode>,
+    #[cfg(feature = "screenshot")]
+    screenshots: Screenshots,
 }
 
-/// Launch a mainloop, and change platform depending on a boolean
-pub fn launch<M: SyncMainLoop<T> + 'static, T>(info: AppInfo, vr: bool, userdata: T) -> anyhow::Result<()> {
+/// Settings for the StarterKit
+#[derive(Copy, Clone, Debug)]
+pub struct Settings {
+    pub anti_aliasing: AntiAliasing,
+    /// Samples per pixel of the scene render pass, resolved into the swapchain image. Pipelines
+    /// must be built with the same count, see `StarterKit::msaa_samples()`. Must be supported for
+    /// both color and depth attachments by the device, and can't be combined with FXAA, the
+    /// stereo preview or platform switching. Defaults to one sample (no MSAA).
+    pub msaa_samples: vk::SampleCountFlagBits,
+    /// Linear color the scene is cleared to at the start of each frame, encoded as needed for
+    /// UNORM swapchains
+    pub clear_color: [f32; 4],
+    /// Format of the scene depth buffer. Defaults to `Core::depth_format`, the device's
+    /// preferred depth format.
+    pub depth_format: Option<vk::Format>,
+    /// Number of frames which may be recorded while earlier ones are still rendering. More
+    /// allows deeper CPU/GPU pipelining at the cost of latency. Defaults to `FRAMES_IN_FLIGHT`.
+    pub frames_in_flight: usize,
+    /// On the desktop, render both eyes with multiview as in VR, and show them side by side in
+    /// the window. `render_pass` then has two views, so stereo shaders can be debugged without a
+    /// headset; see `MultiPlatformCamera::set_stereo_preview()`. Ignored in VR.
+    pub stereo_preview: bool,
+    /// Allow switching between the desktop and VR at runtime with the hybrid backend. The scene
+    /// `render_pass` then always has two views in the OpenXR swapchain format, so pipelines built
+    /// against it stay valid across switches. On the desktop the left eye is shown in the window
+    /// (or both, with `stereo_preview`). Call `platform_changed()` on
+    /// `PlatformEvent::PlatformChanged`.
+    pub platform_switching: bool,
+    /// Draw the scene into a G-buffer and light it in the swapchain pass, see the `deferred`
+    /// module. `render_pass` is then the G-buffer pass, so pipelines must be built with
+    /// `PipelineSettings::color_attachments` set to `deferred::GBUFFER_ATTACHMENTS`. Lights are
+    /// set each frame with `update_lights()`. Can't be combined with MSAA, FXAA, the stereo
+    /// preview or platform switching.
+    pub deferred: bool,
+    /// Apply screen-space ambient occlusion to the scene, see `post::Ssao`. The scene is drawn
+    /// offscreen, and each frame the app draws its geometry into a prepass first with
+    /// `begin_ssao_prepass()`, using pipelines from `ssao_pipelines()`. `projection_scale` must
+    /// match the camera. Can't be combined with MSAA, FXAA, deferred shading, the stereo preview,
+    /// platform switching or render scales other than 1.
+    pub ssao: Option<SsaoSettings>,
+    /// Clear depth to 0 rather than 1, for reverse-Z projections (see `ArcBall::reverse_z` and
+    /// `CameraSettings::reverse_z`). Pipelines must be built with
+    /// `PipelineSettings::reverse_z`, see `StarterKit::reverse_z()`.
+    pub reverse_z: bool,
+    /// Keep the scene at this width over height, drawing it in the largest centered region of
+    /// that shape and leaving bars of the clear color at the sides or above and below. Cameras
+    /// must project for the same aspect, see `CameraSettings::aspect_ratio`. Ignored in VR.
+    pub aspect_ratio: Option<f32>,
+    /// Draw the scene at this fraction of the window's resolution, into an offscreen target which
+    /// is stretched over the window, such as to keep the frame rate up on weak hardware. Above 1
+    /// supersamples, though only bilinearly filtered down. Can be changed at runtime with
+    /// `set_render_scale()`. Can't be combined with MSAA, FXAA, the stereo preview, platform
+    /// switching or deferred shading. Ignored in VR, where the runtime picks the resolution.
+    /// Defaults to 1.
+    pub render_scale: f32,
+    /// How long to wait on a frame in flight before failing with a `SyncError`, rather than
+    /// freezing on a hung GPU. None waits forever. Defaults to `FENCE_TIMEOUT`.
+    pub fence_timeout: Option<Duration>,
+    /// Initial size of `staging_buffer` in bytes, which uploads are split to fit. Defaults to
+    /// `STAGING_BUFFER_SIZE`.
+    pub staging_buffer_size: u64,
+    /// Frames `staging_buffer` may stay grown without using the extra space before shrinking
+    /// back, see `StagingBuffer::shrink_after`. Defaults to `STAGING_SHRINK_FRAMES`.
+    pub staging_shrink_after: Option<u32>,
+    /// Key which captures the next frame with RenderDoc, see `StarterKit::event()`
+    #[cfg(feature = "renderdoc")]
+    pub capture_key: Option<winit::event::VirtualKeyCode>,
+    /// Key which saves a screenshot of the next frame, see `StarterKit::request_screenshot()`.
+    /// Defaults to F12.
+    #[cfg(feature = "screenshot")]
+    pub screenshot_key: Option<winit::event::VirtualKeyCode>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            anti_aliasing: AntiAliasing::default(),
+            msaa_samples: vk::SampleCountFlagBits::_1,
+            clear_color: [0.0, 0.0, 0.0, 1.0],
+            depth_format: None,
+            frames_in_flight: FRAMES_IN_FLIGHT,
+            stereo_preview: false,
+            platform_switching: false,
+            deferred: false,
+            ssao: None,
+            reverse_z: false,
+            aspect_ratio: None,
+            render_scale: 1.0,
+            fence_timeout: Some(FENCE_TIMEOUT),
+            staging_buffer_size: STAGING_BUFFER_SIZE,
+            staging_shrink_after: Some(STAGING_SHRINK_FRAMES),
+            #[cfg(feature = "renderdoc")]
+            capture_key: None,
+            #[cfg(feature = "screenshot")]
+            screenshot_key: Some(winit::event::VirtualKeyCode::F12),
+        }
+    }
+}
+
+impl Settings {
+    fn depth_format(&self, core: &Core) -> vk::Format {
+        self.depth_format.unwrap_or(core.depth_format)
+    }
+}
+
+/// Anti-aliasing applied to the swapchain pass
+#[derive(Copy, Clone, Debug, Default)]
+pub enum AntiAliasing {
+    #[default]
+    None,
+    /// Draw the scene into an offscreen target, then apply FXAA while copying it to the
+    /// swapchain. Pipelines are still built against `StarterKit::render_pass`, which is
+    /// compatible with the offscreen target.
+    Fxaa(FxaaSettings),
+}
+
+/// Offscreen scene target and the FXAA pass resolving it to the swapchain
+struct FxaaPath {
+    target: RenderTarget,
+    pass: Fxaa,
+}
+
+/// Offscreen two-view scene target, shown in the swapchain
+struct StereoPath {
+    target: RenderTarget,
+    pass: StereoPreview,
+}
+
+/// Offscreen scene target at `Settings::render_scale`, stretched over the swapchain
+struct ScaledPath {
+    target: RenderTarget,
+    pass: Upscale,
+}
+
+/// Offscreen scene and geometry prepass targets, and the passes applying ambient occlusion to the
+/// scene and copying the result to the swapchain
+struct SsaoPath {
+    scene: RenderTarget,
+    geometry: RenderTarget,
+    ssao: Ssao,
+    pass: Upscale,
+    /// Pipelines built against the geometry prepass
+    pipelines: PipelineManager,
+}
+
+/// G-buffer the scene is drawn into, and the pass lighting it in the swapchain
+struct DeferredPath {
+    gbuffer: GBuffer,
+    lighting: DeferredLighting,
+}
+
+/// Screenshot requests, and the frame being captured
+#[cfg(feature = "screenshot")]
+struct Screenshots {
+    key: Option<winit::event::VirtualKeyCode>,
+    dir: PathBuf,
+    requested: bool,
+    capture: Option<Capture>,
+    /// Swapchain images, indexed by `Frame::swapchain_index`
+    images: Vec<vk::Image>,
+}
+
+/// Command pool of one frame in flight, reset as a whole when the frame begins
+struct FramePool {
+    pool: vk::CommandPool,
+    /// Secondary command buffers allocated from the pool
+    secondary: Vec<vk::CommandBuffer>,
+    /// Number of `secondary` handed out this frame
+    secondary_used: usize,
+}
+
+/// Command buffers recorded once for each swapchain image and frame in flight, see
+/// `StarterKit::submit_prerecorded()`
+#[derive(Default)]
+struct Prerecorded {
+    command_buffers: Vec<vk::CommandBuffer>,
+    /// Whether each command buffer holds the current scene
+    valid: Vec<bool>,
+}
+
+/// Sync, framebuffers, output render pass and stereo path, which depend on the platform
+type Output = (
+    Synchronization,
+    FramebufferManager,
+    vk::RenderPass,
+    Option<StereoPath>,
+);
+
+/// Create the parts of the StarterKit which depend on the platform
+fn create_output(
+    core: &SharedCore,
+    platform: &Platform<'_>,
+    settings: &Settings,
+    render_pass: vk::RenderPass,
+    scene_format: vk::Format,
+) -> Result<Output> {
+    // Frame-frame sync
+    let mut sync = Synchronization::new(
+        core.clone(),
+        settings.frames_in_flight,
+        matches!(platform, Platform::Winit { .. } | Platform::Display { .. }),
+    )?;
+    sync.set_timeout(settings.fence_timeout);
+
+    // Framebuffers
+    let color_format = platform.color_format();
+    let mut output_settings = RenderPassSettings {
+        color_format,
+        depth_format: settings.depth_format(core),
+        samples: settings.msaa_samples,
+        vr: platform.is_vr(),
+    };
+    let framebuffer = FramebufferManager::new_with_settings(core.clone(), output_settings);
+
+    // The scene is drawn into the G-buffer, and only lit in the swapchain
+    if settings.deferred {
+        let output_render_pass = create_render_pass_with_settings(core, &output_settings)?;
+        return Ok((sync, framebuffer, output_render_pass, None));
+    }
+
+    // On the desktop, two views are drawn offscreen and then shown in the window
+    let two_views = settings.stereo_preview || settings.platform_switching;
+    if platform.is_vr() || !two_views {
+        return Ok((sync, framebuffer, render_pass, None));
+    }
+
+    output_settings.vr = false;
+    let output_render_pass = create_render_pass_with_settings(core, &output_settings)?;
+    let target_settings = RenderTargetSettings {
+        color_format: scene_format,
+        depth_format: Some(settings.depth_format(core)),
+        reverse_z: settings.reverse_z,
+        ..RenderTargetSettings::hdr(true)
+    };
+    let mut pass = if settings.stereo_preview {
+        StereoPreview::new(core.clone(), output_render_pass)?
+    } else {
+        StereoPreview::left_eye(core.clone(), output_render_pass)?
+    };
+    // With platform switching, an sRGB scene may be shown in a UNORM swapchain
+    pass.encode_srgb = needs_shader_encoding(color_format) && !needs_shader_encoding(scene_format);
+    let stereo = StereoPath {
+        target: RenderTarget::new(core.clone(), target_settings)?,
+        pass,
+    };
+
+    Ok((sync, framebuffer, output_render_pass, Some(stereo)))
+}
+
+/// Check that the settings can be used together on this platform and device
+fn validate_settings(core: &SharedCore, platform: &Platform<'_>, settings: &Settings) -> Result<()> {
+    ensure!(settings.frames_in_flight > 0, "At least one frame must be in flight");
+    ensure!(
+        settings.staging_buffer_size > 0,
+        "The staging buffer must not be empty"
+    );
+    if let Some(aspect) = settings.aspect_ratio {
+        ensure!(
+            aspect.is_finite() && aspect > 0.0,
+            "Invalid aspect ratio {}",
+            aspect
+        );
+    }
+
+    let stereo_preview = settings.stereo_preview && !platform.is_vr();
+    if !core.multiview {
+        ensure!(
+            !stereo_preview && !settings.platform_switching,
+            "The stereo preview and platform switching require multiview"
+        );
+        if platform.is_vr()
+            && (settings.deferred
+                || settings.ssao.is_some()
+                || !matches!(settings.anti_aliasing, AntiAliasing::None))
+        {
+            bail!("FXAA, SSAO and deferred shading in VR require multiview");
+        }
+    }
+
+    if (stereo_preview || settings.platform_switching)
+        && matches!(settings.anti_aliasing, AntiAliasing::Fxaa(_))
+    {
+        bail!("FXAA is not supported in the stereo preview or with platform switching");
+    }
+
+    if settings.msaa_samples != vk::SampleCountFlagBits::_1 {
+        if stereo_preview
+            || settings.platform_switching
+            || !matches!(settings.anti_aliasing, AntiAliasing::None)
+        {
+            bail!("MSAA is not supported with FXAA, in the stereo preview or with platform switching");
+        }
+        ensure!(!settings.deferred, "MSAA is not supported with deferred shading");
+        let limits = &core.device_properties.limits;
+        let supported =
+            limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+        let samples = vk::SampleCountFlags::from_bits_truncate(settings.msaa_samples.0);
+        ensure!(
+            supported.contains(samples),
+            "{:?} MSAA is not supported by the device",
+            settings.msaa_samples
+        );
+    }
+
+    check_render_scale(settings, platform.is_vr())?;
+
+    if settings.deferred
+        && (stereo_preview
+            || settings.platform_switching
+            || !matches!(settings.anti_aliasing, AntiAliasing::None))
+    {
+        bail!("Deferred shading is not supported with FXAA, in the stereo preview or with platform switching");
+    }
+
+    if settings.ssao.is_some()
+        && (stereo_preview
+            || settings.platform_switching
+            || settings.deferred
+            || settings.msaa_samples != vk::SampleCountFlagBits::_1
+            || !matches!(settings.anti_aliasing, AntiAliasing::None))
+    {
+        bail!("SSAO is not supported with MSAA, FXAA, deferred shading, in the stereo preview or with platform switching");
+    }
+
+    Ok(())
+}
+
+/// Check that `Settings::render_scale` is valid, and can be used with the other settings
+fn check_render_scale(settings: &Settings, vr: bool) -> Result<()> {
+    let scale = settings.render_scale;
+    ensure!(
+        scale.is_finite() && scale > 0.0,
+        "Invalid render scale {}",
+        scale
+    );
+    if scale != 1.0
+        && !vr
+        && (settings.stereo_preview
+            || settings.platform_switching
+            || settings.deferred
+            || settings.ssao.is_some()
+            || settings.msaa_samples != vk::SampleCountFlagBits::_1
+            || !matches!(settings.anti_aliasing, AntiAliasing::None))
+    {
+        bail!("Render scales other than 1 are not supported with MSAA, FXAA, SSAO, the stereo preview, platform switching or deferred shading");
+    }
+    Ok(())
+}
+
+/// Size of the scene drawn at `scale` of a window of `extent`
+fn scaled_extent(extent: vk::Extent2D, scale: f32) -> vk::Extent2D {
+    vk::Extent2D {
+        width: ((extent.width as f32 * scale).round() as u32).max(1),
+        height: ((extent.height as f32 * scale).round() as u32).max(1),
+    }
+}
+
+/// Create the scene render pass, returning it and its color format. With platform switching it
+/// matches the OpenXR swapchain in every mode, so that pipelines built against it survive
+/// switches.
+fn create_scene_render_pass(
+    core: &SharedCore,
+    platform: &Platform<'_>,
+    settings: &Settings,
+) -> Result<(vk::RenderPass, vk::Format)> {
+    if settings.deferred {
+        let render_pass = deferred::create_gbuffer_render_pass(
+            core,
+            settings.depth_format(core),
+            platform.is_vr(),
+        )?;
+        return Ok((render_pass, deferred::ALBEDO_FORMAT));
+    }
+
+    let stereo_preview = settings.stereo_preview && !platform.is_vr();
+    let (scene_format, scene_vr) = if settings.platform_switching {
+        (COLOR_FORMAT, true)
+    } else {
+        (platform.color_format(), platform.is_vr() || stereo_preview)
+    };
+    let scene_settings = RenderPassSettings {
+        color_format: scene_format,
+        depth_format: settings.depth_format(core),
+        samples: settings.msaa_samples,
+        vr: scene_vr,
+    };
+    let render_pass = create_render_pass_with_settings(core, &scene_settings)?;
+    Ok((render_pass, scene_format))
+}
+
+/// Create the FXAA path, if enabled
+fn create_fxaa(
+    core: &SharedCore,
+    platform: &Platform<'_>,
+    settings: &Settings,
+    output_render_pass: vk::RenderPass,
+) -> Result<Option<FxaaPath>> {
+    let fxaa_settings = match settings.anti_aliasing {
+        AntiAliasing::None => return Ok(None),
+        AntiAliasing::Fxaa(fxaa_settings) => fxaa_settings,
+    };
+    let target_settings = RenderTargetSettings {
+        color_format: platform.color_format(),
+        depth_format: Some(settings.depth_format(core)),
+        reverse_z: settings.reverse_z,
+        ..RenderTargetSettings::hdr(platform.is_vr())
+    };
+    Ok(Some(FxaaPath {
+        target: RenderTarget::new(core.clone(), target_settings)?,
+        pass: Fxaa::new(core.clone(), output_render_pass, fxaa_settings)?,
+    }))
+}
+
+/// Create the SSAO path, if enabled. The targets are resized with the swapchain.
+fn create_ssao(
+    core: &SharedCore,
+    platform: &Platform<'_>,
+    settings: &Settings,
+    output_render_pass: vk::RenderPass,
+) -> Result<Option<SsaoPath>> {
+    let ssao_settings = match settings.ssao {
+        Some(ssao_settings) => ssao_settings,
+        None => return Ok(None),
+    };
+    let vr = platform.is_vr();
+    let depth_format = Some(settings.depth_format(core));
+    let scene_settings = RenderTargetSettings {
+        color_format: platform.color_format(),
+        depth_format,
+        reverse_z: settings.reverse_z,
+        ..RenderTargetSettings::hdr(vr)
+    };
+    let geometry_settings = RenderTargetSettings {
+        depth_format,
+        reverse_z: settings.reverse_z,
+        ..Ssao::geometry_settings(core, vr)
+    };
+    let geometry = RenderTarget::new(core.clone(), geometry_settings)?;
+    let pipelines = PipelineManager::new(
+        core.clone(),
+        geometry.render_pass(),
+        vk::SampleCountFlagBits::_1,
+        false,
+    );
+    Ok(Some(SsaoPath {
+        scene: RenderTarget::new(core.clone(), scene_settings)?,
+        geometry,
+        ssao: Ssao::new(core.clone(), vr, ssao_settings)?,
+        pass: Upscale::new(core.clone(), output_render_pass)?,
+        pipelines,
+    }))
+}
+
+/// Create the render scale path, if the scale isn't 1 on the desktop. The target is resized
+/// with the swapchain.
+fn create_scaled(
+    core: &SharedCore,
+    color_format: vk::Format,
+    vr: bool,
+    settings: &Settings,
+    output_render_pass: vk::RenderPass,
+) -> Result<Option<ScaledPath>> {
+    if vr || settings.render_scale == 1.0 {
+        return Ok(None);
+    }
+    let target_settings = RenderTargetSettings {
+        depth_format: Some(settings.depth_format(core)),
+        reverse_z: settings.reverse_z,
+        ..RenderTargetSettings::color(color_format, false)
+    };
+    Ok(Some(ScaledPath {
+        target: RenderTarget::new(core.clone(), target_settings)?,
+        pass: Upscale::new(core.clone(), output_render_pass)?,
+    }))
+}
+
+/// Create the deferred shading path, if enabled
+fn create_deferred(
+    core: &SharedCore,
+    platform: &Platform<'_>,
+    settings: &Settings,
+    output_render_pass: vk::RenderPass,
+) -> Result<Option<DeferredPath>> {
+    if !settings.deferred {
+        return Ok(None);
+    }
+    let mut gbuffer = GBuffer::new(core.clone(), settings.depth_format(core), platform.is_vr())?;
+    gbuffer.set_reverse_z(settings.reverse_z);
+    let mut lighting = DeferredLighting::new(
+        core.clone(),
+        output_render_pass,
+        settings.frames_in_flight,
+        MAX_LIGHTS,
+    )?;
+    lighting.reverse_z = settings.reverse_z;
+    lighting.encode_srgb = needs_shader_encoding(platform.color_format());
+    Ok(Some(DeferredPath { gbuffer, lighting }))
+}
+
+/// Launch a mainloop, and change platform depending on a boolean. `data` is passed to
+/// `MainLoop::new()`.
+pub fn launch<M: SyncMainLoop<D> + 'static, D>(info: AppInfo, vr: bool, data: D) -> anyhow::Result<()> {
     if vr {
         #[cfg(not(feature = "openxr"))]
         panic!("Please enable the `openxr` feature!");
 
         #[cfg(feature = "openxr")]
-        crate::openxr_backend::launch::<M, T>(info, userdata)
+        crate::openxr_backend::launch::<M, D>(info, data)
     } else {
-        crate::winit_backend::launch::<M, T>(info, userdata)
+        crate::winit_backend::launch::<M, D>(info, data)
     }
 }
 
@@ -36,7 +600,7 @@ pub fn launch<M: SyncMainLoop<T> + 'static, T>(info: AppInfo, vr: bool, userdata
 pub fn debug<App: SyncMainLoop + 'static>() -> Result<()> {
     let info = AppInfo::default().validation(true);
     let vr = std::env::args().count() > 1;
-    launch::<App>(info, vr)
+    launch::<App, ()>(info, vr, ())
 }
 */
 
@@ -44,81 +608,476 @@ pub fn debug<App: SyncMainLoop + 'static>() -> Result<()> {
 /// `end_command_buffer()` function.
 pub struct CommandBufferStart {
     pub command_buffer: vk::CommandBuffer,
+    swapchain_index: u32,
     fence: vk::Fence,
 }
 
 impl StarterKit {
     pub fn new(core: SharedCore, platform: &mut Platform<'_>) -> Result<Self> {
-        // Frame-frame sync
-        let sync = Synchronization::new(
+        Self::new_with_settings(core, platform, Settings::default())
+    }
+
+    pub fn new_with_settings(
+        core: SharedCore,
+        platform: &mut Platform<'_>,
+        settings: Settings,
+    ) -> Result<Self> {
+        validate_settings(&core, platform, &settings)?;
+
+        // Scene render pass, and the pipelines following it
+        let (render_pass, scene_format) = create_scene_render_pass(&core, platform, &settings)?;
+        let mut pipelines = PipelineManager::new(
             core.clone(),
-            FRAMES_IN_FLIGHT,
-            matches!(platform, Platform::Winit { .. }),
-        )?;
+            render_pass,
+            settings.msaa_samples,
+            needs_shader_encoding(scene_format),
+        );
+
+        // Sync, framebuffers and output render pass
+        let (sync, framebuffer, output_render_pass, stereo) =
+            create_output(&core, platform, &settings, render_pass, scene_format)?;
+        pipelines.set_view_passes(framebuffer.view_passes())?;
 
-        // Freambuffer and render pass
-        let framebuffer = FramebufferManager::new(core.clone(), platform.is_vr());
-        let render_pass = create_render_pass(&core, platform.is_vr())?;
+        // Command pools. The frame pools are reset as a whole at the start of each frame, but
+        // allow individual resets too, as the frame command buffers are also used for uploads.
+        let create_info = vk::CommandPoolCreateInfoBuilder::new()
+            .flags(
+                vk::CommandPoolCreateFlags::TRANSIENT
+                    | vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+            )
+            .queue_family_index(core.queue_family);
+        let mut frame_pools = vec![];
+        let mut command_buffers = vec![];
+        for _ in 0..settings.frames_in_flight {
+            let pool =
+                unsafe { core.device.create_command_pool(&create_info, None, None) }.result()?;
+            frame_pools.push(FramePool {
+                pool,
+                secondary: vec![],
+                secondary_used: 0,
+            });
+
+            let allocate_info = vk::CommandBufferAllocateInfoBuilder::new()
+                .command_pool(pool)
+                .level(vk::CommandBufferLevel::PRIMARY)
+                .command_buffer_count(1);
+            let buffers =
+                unsafe { core.device.allocate_command_buffers(&allocate_info) }.result()?;
+            command_buffers.extend(buffers);
+        }
 
-        // Command pool
         let create_info = vk::CommandPoolCreateInfoBuilder::new()
             .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
             .queue_family_index(core.queue_family);
         let command_pool =
             unsafe { core.device.create_command_pool(&create_info, None, None) }.result()?;
 
-        // Allocate command buffers
-        let allocate_info = vk::CommandBufferAllocateInfoBuilder::new()
-            .command_pool(command_pool)
-            .level(vk::CommandBufferLevel::PRIMARY)
-            .command_buffer_count(FRAMES_IN_FLIGHT as u32);
-
-        let command_buffers =
-            unsafe { core.device.allocate_command_buffers(&allocate_info) }.result()?;
+        // Frame timing
+        let gpu_timer = GpuTimer::new(core.clone(), settings.frames_in_flight)?;
 
         // Mesh uploads
-        let staging_buffer = StagingBuffer::new(core.clone())?;
+        let mut staging_buffer =
+            StagingBuffer::with_capacity(core.clone(), settings.staging_buffer_size)?;
+        staging_buffer.shrink_after = settings.staging_shrink_after;
+
+        // Anti-aliasing
+        let fxaa = create_fxaa(&core, platform, &settings, output_render_pass)?;
+
+        // Deferred shading
+        let deferred = create_deferred(&core, platform, &settings, output_render_pass)?;
+
+        // Ambient occlusion
+        let ssao = create_ssao(&core, platform, &settings, output_render_pass)?;
+
+        // Render scale
+        let scaled = create_scaled(
+            &core,
+            platform.color_format(),
+            platform.is_vr(),
+            &settings,
+            output_render_pass,
+        )?;
 
         Ok(Self {
+            frame_guards: (0..settings.frames_in_flight).map(|_| None).collect(),
+            compute_writes: ComputeWrites::default(),
+            frame_waits: vec![],
+            frame_signals: vec![],
             staging_buffer,
             sync,
             command_buffers,
             framebuffer,
             render_pass,
+            pipelines,
+            output_render_pass,
+            scene_format,
+            settings,
+            view: 0,
             frame: 0,
+            frame_pools,
+            command_pool,
+            prerecorded: Prerecorded::default(),
             core,
+            fxaa,
+            stereo,
+            deferred,
+            scaled,
+            ssao,
+            ssao_prepass: false,
+            gpu_timer,
+            gpu_frame_time: None,
+            timeline_frames: vec![None; settings.frames_in_flight],
+            #[cfg(feature = "renderdoc")]
+            capture_key: settings.capture_key,
+            #[cfg(feature = "screenshot")]
+            screenshots: Screenshots {
+                key: settings.screenshot_key,
+                dir: PathBuf::from("screenshots"),
+                requested: false,
+                capture: None,
+                images: vec![],
+            },
         })
     }
 
     /// Begins command buffer, render pass, and sets viewports
     pub fn begin_command_buffer(&mut self, frame: Frame) -> Result<CommandBufferStart> {
-        let fence = self.sync.sync(frame.swapchain_index, self.frame)?;
+        let cmd = self.begin_frame(frame)?;
+        self.begin_swapchain_pass(&cmd);
+        Ok(cmd)
+    }
 
-        let command_buffer = self.command_buffers[self.frame];
-        let framebuffer = self.framebuffer.frame(frame.swapchain_index);
+    /// Begins the command buffer without starting the swapchain render pass, so that offscreen
+    /// passes (such as a `RenderTarget`) can be recorded first. `begin_swapchain_pass()` must be
+    /// called before `end_command_buffer()`.
+    pub fn begin_frame(&mut self, frame: Frame) -> Result<CommandBufferStart> {
+        let fence = self.wait_frame(frame.swapchain_index)?;
 
+        // Recycle everything recorded for this frame last time around
+        let frame_pool = &mut self.frame_pools[self.frame];
         unsafe {
             self.core
                 .device
-                .reset_command_buffer(command_buffer, None)
+                .reset_command_pool(frame_pool.pool, None)
                 .result()?;
+        }
+        frame_pool.secondary_used = 0;
+
+        let command_buffer = self.command_buffers[self.frame];
+        self.begin_recording(command_buffer)?;
+
+        Ok(CommandBufferStart {
+            command_buffer,
+            swapchain_index: frame.swapchain_index,
+            fence,
+        })
+    }
+
+    /// Submit a command buffer recorded once for this swapchain image and frame, instead of
+    /// recording one every frame. This cuts CPU time for static scenes, such as visualizations.
+    /// `record` is called with the scene pass begun and viewports set (as after
+    /// `begin_command_buffer()`) only when the command buffer is first used, or after
+    /// `invalidate_prerecorded()`. The recorded commands are replayed on later frames with the
+    /// same frame index, so per-frame resources such as a `FrameDataUbo` may still be updated
+    /// before each submission; anything else they reference must stay valid and unchanged.
+    pub fn submit_prerecorded<F>(&mut self, frame: Frame, record: F) -> Result<()>
+    where
+        F: FnOnce(vk::CommandBuffer) -> Result<()>,
+    {
+        let fence = self.wait_frame(frame.swapchain_index)?;
+
+        let idx = frame.swapchain_index as usize * self.frames_in_flight() + self.frame;
+        if idx >= self.prerecorded.command_buffers.len() {
+            let allocate_info = vk::CommandBufferAllocateInfoBuilder::new()
+                .command_pool(self.command_pool)
+                .level(vk::CommandBufferLevel::PRIMARY)
+                .command_buffer_count((idx + 1 - self.prerecorded.command_buffers.len()) as u32);
+            let command_buffers =
+                unsafe { self.core.device.allocate_command_buffers(&allocate_info) }.result()?;
+            self.prerecorded.command_buffers.extend(command_buffers);
+            self.prerecorded.valid.resize(idx + 1, false);
+        }
+
+        let cmd = CommandBufferStart {
+            command_buffer: self.prerecorded.command_buffers[idx],
+            swapchain_index: frame.swapchain_index,
+            fence,
+        };
+
+        // Screenshots are recorded into the command buffer, so leave it to be re-recorded
+        let screenshot = self.screenshot_requested();
+        if self.prerecorded.valid[idx] && !screenshot {
+            if let Some(timer) = &mut self.gpu_timer {
+                timer.resubmitted(self.frame);
+            }
+        } else {
+            unsafe {
+                self.core
+                    .device
+                    .reset_command_buffer(cmd.command_buffer, None)
+                    .result()?;
+            }
+            self.begin_recording(cmd.command_buffer)?;
+            self.begin_swapchain_pass(&cmd);
+            record(cmd.command_buffer)?;
+            self.finish_recording(&cmd)?;
+            self.prerecorded.valid[idx] = !screenshot;
+        }
+
+        self.submit_frame(cmd)
+    }
+
+    /// Re-record the command buffers used by `submit_prerecorded()` as each is next submitted,
+    /// such as after the scene changed. Called by the StarterKit on resize, platform changes and
+    /// FXAA settings changes.
+    pub fn invalidate_prerecorded(&mut self) {
+        self.prerecorded.valid.iter_mut().for_each(|valid| *valid = false);
+    }
+
+    /// Wait until this frame's resources are available, and return the fence to signal
+    fn wait_frame(&mut self, swapchain_index: u32) -> Result<vk::Fence> {
+        let fence = profiling::timed(FrameEvent::FenceWait, || {
+            self.sync.sync(swapchain_index, self.frame)
+        })?;
+        self.frame_guards[self.frame] = None;
+
+        // Uploads are often recorded into the frame command buffers
+        self.staging_buffer.wait_pending()?;
+        self.staging_buffer.end_frame()?;
 
+        // This frame's previous submission has completed
+        if let Some(timer) = &mut self.gpu_timer {
+            if let Some([start, end]) = timer.read_timestamps(self.frame)? {
+                let ms = (end - start) as f32;
+                self.gpu_frame_time = Some(ms);
+                profiling::report_gpu(ms);
+                if let Some(index) = self.timeline_frames[self.frame].take() {
+                    profiling::report_gpu_span(index, [start, end]);
+                }
+            }
+        }
+
+        Ok(fence)
+    }
+
+    /// Begin the command buffer, and start the frame timer
+    fn begin_recording(&mut self, command_buffer: vk::CommandBuffer) -> Result<()> {
+        unsafe {
             let begin_info = vk::CommandBufferBeginInfoBuilder::new();
             self.core
                 .device
                 .begin_command_buffer(command_buffer, &begin_info)
                 .result()?;
+        }
+
+        if let Some(timer) = &mut self.gpu_timer {
+            timer.begin(command_buffer, self.frame);
+        }
+
+        Ok(())
+    }
+
+    /// Record a compute dispatch between `begin_frame()` and `begin_swapchain_pass()`. `writes`
+    /// lists the buffers it writes along with how the frame reads them afterwards (e.g.
+    /// `VERTEX_BUFFER`, or `STORAGE_BUFFER` for a later dispatch); the barriers are recorded for
+    /// you, see the `compute` module.
+    pub fn dispatch(
+        &mut self,
+        cmd: &CommandBufferStart,
+        dispatch: &Dispatch,
+        writes: &[(vk::Buffer, vk::BufferUsageFlags)],
+    ) {
+        self.compute_writes
+            .dispatch(&self.core, cmd.command_buffer, dispatch, writes);
+    }
+
+    /// Make the next frame submitted wait on a semaphore, such as the results of an
+    /// `AsyncCompute` job
+    pub fn wait_semaphore(&mut self, wait: SemaphoreSubmit) {
+        self.frame_waits.push(wait);
+    }
+
+    /// Signal a semaphore once the next frame submitted has finished
+    pub fn signal_semaphore(&mut self, semaphore: vk::Semaphore) {
+        self.frame_signals.push(SemaphoreSubmit {
+            semaphore,
+            stages: Stages::ALL_COMMANDS_KHR,
+        });
+    }
+
+    /// Begins the SSAO geometry prepass (see `Settings::ssao`) between `begin_frame()` and
+    /// `begin_swapchain_pass()`, which ends it. Draw the geometry occluding the scene with
+    /// pipelines from `ssao_pipelines()`, such as with `draw()`. If a frame has no prepass, its
+    /// geometry is cleared and no occlusion is applied.
+    pub fn begin_ssao_prepass(&mut self, cmd: &CommandBufferStart) -> Result<()> {
+        let ssao = match &self.ssao {
+            Some(ssao) => ssao,
+            None => bail!("SSAO is not enabled"),
+        };
+        ensure!(!self.ssao_prepass, "The SSAO prepass has already begun");
+        self.compute_writes.flush(&self.core, cmd.command_buffer);
+        // Cleared to zero, marking the background
+        ssao.geometry.begin_pass(cmd.command_buffer, [0.0; 4]);
+        if self.aspect_ratio().is_some() {
+            self.scene_region().set(&self.core, cmd.command_buffer);
+        }
+        self.ssao_prepass = true;
+        Ok(())
+    }
+
+    /// Pipelines of the SSAO geometry prepass, if SSAO is enabled. Build them from
+    /// `post::SSAO_GEOMETRY_VERT` and `post::SSAO_GEOMETRY_FRAG`, or shaders writing the same
+    /// outputs. Their ids are only valid during the prepass.
+    pub fn ssao_pipelines(&mut self) -> Option<&mut PipelineManager> {
+        self.ssao.as_mut().map(|ssao| &mut ssao.pipelines)
+    }
+
+    /// SSAO settings, if SSAO is enabled. These may be changed between frames.
+    pub fn ssao_settings_mut(&mut self) -> Option<&mut SsaoSettings> {
+        self.invalidate_prerecorded();
+        self.ssao.as_mut().map(|ssao| &mut ssao.ssao.settings)
+    }
 
+    /// Begins the render pass the scene is drawn in and sets viewports to `scene_region()`. This
+    /// is the swapchain render pass, or an offscreen target when FXAA, SSAO, the stereo preview
+    /// or deferred shading is enabled. Makes the writes of earlier `dispatch()`es visible first.
+    pub fn begin_swapchain_pass(&mut self, cmd: &CommandBufferStart) {
+        if let Some(ssao) = &self.ssao {
+            if !std::mem::take(&mut self.ssao_prepass) {
+                ssao.geometry.begin_pass(cmd.command_buffer, [0.0; 4]);
+            }
+            ssao.geometry.end_pass(cmd.command_buffer);
+        }
+        self.compute_writes.flush(&self.core, cmd.command_buffer);
+        self.view = 0;
+        let clear_color = attachment_color(self.scene_format, self.settings.clear_color);
+        if let Some(deferred) = &self.deferred {
+            deferred.gbuffer.begin_pass(cmd.command_buffer, clear_color);
+        } else if let Some(ssao) = &self.ssao {
+            ssao.scene.begin_pass(cmd.command_buffer, clear_color);
+        } else if let Some(fxaa) = &self.fxaa {
+            fxaa.target.begin_pass(cmd.command_buffer, clear_color);
+        } else if let Some(stereo) = &self.stereo {
+            stereo.target.begin_pass(cmd.command_buffer, clear_color);
+        } else if let Some(scaled) = &self.scaled {
+            scaled.target.begin_pass(cmd.command_buffer, clear_color);
+        } else {
+            self.begin_output_pass(cmd);
+        }
+        if self.aspect_ratio().is_some() {
+            self.scene_region().set(&self.core, cmd.command_buffer);
+        }
+    }
+
+    /// Render pass instances the scene is drawn in each frame: one, unless VR is rendered on a
+    /// device without multiview, which draws each eye in a pass of its own. The scene must then
+    /// be recorded once per pass with pipelines for that eye, see `next_view()`.
+    pub fn view_passes(&self) -> u32 {
+        self.framebuffer.view_passes()
+    }
+
+    /// View pass being recorded, for `PipelineManager::get_view()`
+    pub fn view(&self) -> u32 {
+        self.view
+    }
+
+    /// End the current view pass and begin the next, returning false once every view has been
+    /// drawn. Record the scene in a loop until it returns false:
+    ///
+    /// ```ignore
+    /// loop {
+    ///     let pipeline = starter_kit.pipelines.get_view(id, starter_kit.view());
+    ///     // Draw the scene with `pipeline`
+    ///     if !starter_kit.next_view(&cmd) {
+    ///         break;
+    ///     }
+    /// }
+    /// ```
+    pub fn next_view(&mut self, cmd: &CommandBufferStart) -> bool {
+        if self.view + 1 >= self.view_passes() {
+            return false;
+        }
+        unsafe {
+            self.core.device.cmd_end_render_pass(cmd.command_buffer);
+        }
+        self.view += 1;
+        self.begin_output_pass(cmd);
+        true
+    }
+
+    /// Draw `mesh` (see `MeshSource`) with `material` in the current view pass, pushing
+    /// `transform` ahead of the material's parameters. During the SSAO prepass, the material's
+    /// pipeline is one of `ssao_pipelines()`.
+    pub fn draw(
+        &self,
+        cmd: &CommandBufferStart,
+        mesh: &impl MeshSource,
+        material: &Material,
+        transform: &nalgebra::Matrix4<f32>,
+    ) {
+        let command_buffer = cmd.command_buffer;
+        let pipelines = match &self.ssao {
+            Some(ssao) if self.ssao_prepass => &ssao.pipelines,
+            _ => &self.pipelines,
+        };
+        let pipeline = pipelines.get_view(material.pipeline, self.view);
+        unsafe {
+            self.core.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline,
+            );
+            for (set, descriptor_set) in &material.sets {
+                self.core.device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    material.layout,
+                    *set,
+                    &[descriptor_set.get(self.frame)],
+                    &[],
+                );
+            }
+            self.core.device.cmd_push_constants(
+                command_buffer,
+                material.layout,
+                PUSH_CONSTANT_STAGES,
+                0,
+                TRANSFORM_SIZE,
+                transform.as_ptr() as _,
+            );
+            if !material.params().is_empty() {
+                self.core.device.cmd_push_constants(
+                    command_buffer,
+                    material.layout,
+                    PUSH_CONSTANT_STAGES,
+                    TRANSFORM_SIZE,
+                    material.params().len() as u32,
+                    material.params().as_ptr() as _,
+                );
+            }
+        }
+        draw_mesh(&self.core, command_buffer, mesh);
+    }
+
+    /// Begins the actual swapchain render pass, for the current view pass
+    fn begin_output_pass(&self, cmd: &CommandBufferStart) {
+        let command_buffer = cmd.command_buffer;
+        let framebuffer = self.framebuffer.eye_frame(cmd.swapchain_index, self.view);
+
+        unsafe {
             // Set render pass
             let clear_values = [
                 vk::ClearValue {
                     color: vk::ClearColorValue {
-                        float32: [0.0, 0.0, 0.0, 1.0],
+                        float32: attachment_color(
+                            self.framebuffer.color_format(),
+                            self.settings.clear_color,
+                        ),
                     },
                 },
                 vk::ClearValue {
                     depth_stencil: vk::ClearDepthStencilValue {
-                        depth: 1.0,
+                        depth: clear_depth(self.settings.reverse_z),
                         stencil: 0,
                     },
                 },
@@ -126,7 +1085,7 @@ impl StarterKit {
 
             let begin_info = vk::RenderPassBeginInfoBuilder::new()
                 .framebuffer(framebuffer)
-                .render_pass(self.render_pass)
+                .render_pass(self.output_render_pass)
                 .render_area(vk::Rect2D {
                     offset: vk::Offset2D { x: 0, y: 0 },
                     extent: self.framebuffer.extent(),
@@ -159,60 +1118,505 @@ impl StarterKit {
                 .device
                 .cmd_set_scissor(command_buffer, 0, &scissors);
         }
-
-        Ok(CommandBufferStart {
-            command_buffer,
-            fence,
-        })
     }
 
     /// End and submit command buffer, and advance to the next frame.
     pub fn end_command_buffer(&mut self, cmd: CommandBufferStart) -> Result<()> {
+        self.finish_recording(&cmd)?;
+        self.submit_frame(cmd)
+    }
+
+    /// End the scene and swapchain passes, stop the frame timer and end the command buffer
+    fn finish_recording(&mut self, cmd: &CommandBufferStart) -> Result<()> {
         let command_buffer = cmd.command_buffer;
+        if let Some(ssao) = &self.ssao {
+            ssao.scene.end_pass(command_buffer);
+            ssao.ssao.record(command_buffer);
+            self.begin_output_pass(cmd);
+            ssao.pass.draw(command_buffer);
+        }
+        if let Some(fxaa) = &self.fxaa {
+            fxaa.target.end_pass(command_buffer);
+            self.begin_output_pass(cmd);
+            fxaa.pass.draw(command_buffer);
+        }
+        if let Some(stereo) = &self.stereo {
+            stereo.target.end_pass(command_buffer);
+            self.begin_output_pass(cmd);
+            stereo.pass.draw(command_buffer);
+        }
+        if let Some(scaled) = &self.scaled {
+            scaled.target.end_pass(command_buffer);
+            self.begin_output_pass(cmd);
+            scaled.pass.draw(command_buffer);
+        }
+        if let Some(deferred) = &self.deferred {
+            deferred.gbuffer.end_pass(command_buffer);
+            self.begin_output_pass(cmd);
+            if self.aspect_ratio().is_some() {
+                self.scene_region().set(&self.core, command_buffer);
+            }
+            deferred.lighting.draw(command_buffer, self.frame);
+        }
+
         unsafe {
             self.core.device.cmd_end_render_pass(command_buffer);
+        }
+
+        #[cfg(feature = "screenshot")]
+        self.record_screenshot(cmd);
+
+        if let Some(timer) = &mut self.gpu_timer {
+            timer.end(command_buffer, self.frame);
+        }
+
+        unsafe {
             self.core
                 .device
                 .end_command_buffer(command_buffer)
                 .result()?;
         }
 
-        let command_buffers = [command_buffer];
-        if let Some((image_available, render_finished)) = self.sync.swapchain_sync(self.frame) {
-            let wait_semaphores = [image_available];
-            let signal_semaphores = [render_finished];
-            let submit_info = vk::SubmitInfoBuilder::new()
-                .wait_semaphores(&wait_semaphores)
-                .wait_dst_stage_mask(&[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT])
-                .command_buffers(&command_buffers)
-                .signal_semaphores(&signal_semaphores);
-            unsafe {
+        Ok(())
+    }
+
+    /// Submit the frame's command buffer, and advance to the next frame
+    fn submit_frame(&mut self, cmd: CommandBufferStart) -> Result<()> {
+        // Drawing to the swapchain image only begins at color attachment output, but presentation
+        // must wait on everything the frame recorded, including copies and compute after the pass
+        let swapchain_sync = self.sync.swapchain_sync(self.frame);
+        let wait = swapchain_sync.map(|(image_available, _)| SemaphoreSubmit {
+            semaphore: image_available,
+            stages: Stages::COLOR_ATTACHMENT_OUTPUT_KHR,
+        });
+        let signal = swapchain_sync.map(|(_, render_finished)| SemaphoreSubmit {
+            semaphore: render_finished,
+            stages: Stages::ALL_COMMANDS_KHR,
+        });
+        let waits: Vec<_> = wait.into_iter().chain(self.frame_waits.drain(..)).collect();
+        let signals: Vec<_> = signal.into_iter().chain(self.frame_signals.drain(..)).collect();
+        let start = Instant::now();
+        self.core.submit_to(
+            self.core.queue,
+            cmd.command_buffer,
+            &waits,
+            &signals,
+            Some(cmd.fence),
+        )?;
+        self.timeline_frames[self.frame] = profiling::report_event(FrameEvent::Submit, start);
+        self.frame_guards[self.frame] = Some(FrameGuard::new(self.core.clone(), cmd.fence));
+
+        #[cfg(feature = "screenshot")]
+        if let Some(capture) = self.screenshots.capture.take() {
+            wait_for_fence(&self.core, cmd.fence, None)?;
+            match capture.save(&self.screenshots.dir) {
+                Ok(path) => println!("Saving screenshot to {}", path.display()),
+                Err(e) => eprintln!("Failed to save screenshot: {:#}", e),
+            }
+        }
+
+        self.frame = (self.frame + 1) % self.frames_in_flight();
+
+        Ok(())
+    }
+
+    /// Save a screenshot of the next frame submitted, as a timestamped PNG in the screenshot
+    /// directory. In VR, the first eye is saved.
+    #[cfg(feature = "screenshot")]
+    pub fn request_screenshot(&mut self) {
+        self.screenshots.requested = true;
+    }
+
+    /// Directory screenshots are saved to, created if needed. Defaults to `screenshots` in the
+    /// working directory.
+    #[cfg(feature = "screenshot")]
+    pub fn set_screenshot_dir(&mut self, dir: impl Into<PathBuf>) {
+        self.screenshots.dir = dir.into();
+    }
+
+    fn screenshot_requested(&self) -> bool {
+        #[cfg(feature = "screenshot")]
+        return self.screenshots.requested;
+        #[cfg(not(feature = "screenshot"))]
+        false
+    }
+
+    /// Copy the swapchain image out, if a screenshot was requested. Must follow the output pass.
+    #[cfg(feature = "screenshot")]
+    fn record_screenshot(&mut self, cmd: &CommandBufferStart) {
+        if !std::mem::take(&mut self.screenshots.requested) {
+            return;
+        }
+
+        let layout = if self.framebuffer.is_vr() {
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+        } else {
+            vk::ImageLayout::PRESENT_SRC_KHR
+        };
+        let capture = Capture::record(
+            &self.core,
+            cmd.command_buffer,
+            self.screenshots.images[cmd.swapchain_index as usize],
+            layout,
+            self.framebuffer.extent(),
+            self.framebuffer.color_format(),
+        );
+        match capture {
+            Ok(capture) => self.screenshots.capture = Some(capture),
+            Err(e) => eprintln!("Failed to take screenshot: {:#}", e),
+        }
+    }
+
+    /// Number of frames in flight, and so of command buffers. Per-frame resources (such as a
+    /// `FrameDataUbo`) should be created with this many frames.
+    pub fn frames_in_flight(&self) -> usize {
+        self.command_buffers.len()
+    }
+
+    pub fn current_command_buffer(&self) -> vk::CommandBuffer {
+        self.command_buffers[self.frame]
+    }
+
+    /// A secondary command buffer from the current frame's pool, to be begun by the caller and
+    /// executed in this frame's command buffer. It is recycled when this frame index next
+    /// begins, so request it after `begin_frame()`.
+    pub fn secondary_command_buffer(&mut self) -> Result<vk::CommandBuffer> {
+        let frame_pool = &mut self.frame_pools[self.frame];
+        if frame_pool.secondary_used == frame_pool.secondary.len() {
+            let allocate_info = vk::CommandBufferAllocateInfoBuilder::new()
+                .command_pool(frame_pool.pool)
+                .level(vk::CommandBufferLevel::SECONDARY)
+                .command_buffer_count(1);
+            let buffers =
+                unsafe { self.core.device.allocate_command_buffers(&allocate_info) }.result()?;
+            frame_pool.secondary.extend(buffers);
+        }
+
+        let command_buffer = frame_pool.secondary[frame_pool.secondary_used];
+        frame_pool.secondary_used += 1;
+        Ok(command_buffer)
+    }
+
+    pub fn swapchain_resize(&mut self, images: Vec<vk::Image>, extent: vk::Extent2D) -> Result<()> {
+        self.invalidate_prerecorded();
+        #[cfg(feature = "screenshot")]
+        {
+            self.screenshots.images = images.clone();
+        }
+        self.framebuffer
+            .resize(images, extent, self.output_render_pass)?;
+        if let Some(fxaa) = &mut self.fxaa {
+            fxaa.target.resize(extent)?;
+            fxaa.pass.set_input(&fxaa.target);
+        }
+        if let Some(stereo) = &mut self.stereo {
+            // Each eye gets half of the window
+            let eye_extent = vk::Extent2D {
+                width: (extent.width / 2).max(1),
+                height: extent.height,
+            };
+            stereo.target.resize(eye_extent)?;
+            stereo.pass.set_input(&stereo.target);
+        }
+        if let Some(deferred) = &mut self.deferred {
+            deferred.gbuffer.resize(extent)?;
+            deferred.lighting.set_inputs(&deferred.gbuffer);
+        }
+        if let Some(scaled) = &mut self.scaled {
+            scaled
+                .target
+                .resize(scaled_extent(extent, self.settings.render_scale))?;
+            scaled.pass.set_input(&scaled.target);
+        }
+        if let Some(ssao) = &mut self.ssao {
+            ssao.scene.resize(extent)?;
+            ssao.geometry.resize(extent)?;
+            ssao.ssao.resize(extent, &ssao.scene, &ssao.geometry)?;
+            ssao.pass.set_input(ssao.ssao.output());
+        }
+        Ok(())
+    }
+
+    /// GPU time of the most recently completed frame in milliseconds, if timestamps are supported
+    pub fn gpu_frame_time(&self) -> Option<f32> {
+        self.gpu_frame_time
+    }
+
+    /// Whether the scene is drawn with two views on the desktop, as in the stereo preview or the
+    /// desktop mode of platform switching
+    pub fn stereo_preview(&self) -> bool {
+        self.stereo.is_some()
+    }
+
+    /// Rebuild the parts which depend on the platform, after the hybrid backend sends
+    /// `PlatformEvent::PlatformChanged`. `swapchain_resize()` follows as usual. With
+    /// `Settings::platform_switching`, `render_pass` stays valid across switches. Otherwise it is
+    /// recreated for the new swapchain, and `pipelines` are rebuilt against it; pipelines the app
+    /// built against the old `render_pass` itself must be rebuilt too.
+    pub fn platform_changed(&mut self, platform: &Platform<'_>) -> Result<()> {
+        self.rebuild(platform, !self.settings.platform_switching)
+    }
+
+    /// Change the MSAA sample count (see `Settings::msaa_samples`), recreating `render_pass` and
+    /// the framebuffers and rebuilding `pipelines`. Call after the first `swapchain_resize()`.
+    pub fn set_msaa_samples(
+        &mut self,
+        platform: &Platform<'_>,
+        samples: vk::SampleCountFlagBits,
+    ) -> Result<()> {
+        let settings = Settings {
+            msaa_samples: samples,
+            ..self.settings
+        };
+        validate_settings(&self.core, platform, &settings)?;
+        self.settings = settings;
+
+        let images = self.framebuffer.images().to_vec();
+        let extent = self.framebuffer.extent();
+        self.rebuild(platform, true)?;
+        self.swapchain_resize(images, extent)
+    }
+
+    /// Recreate the platform dependent parts, and optionally the scene render pass
+    fn rebuild(&mut self, platform: &Platform<'_>, recreate_scene: bool) -> Result<()> {
+        self.core.wait_idle()?;
+        self.frame_guards.iter_mut().for_each(|guard| *guard = None);
+
+        let (render_pass, scene_format) = if recreate_scene {
+            create_scene_render_pass(&self.core, platform, &self.settings)?
+        } else {
+            (self.render_pass, self.scene_format)
+        };
+
+        let (sync, framebuffer, output_render_pass, stereo) =
+            create_output(&self.core, platform, &self.settings, render_pass, scene_format)?;
+        let mut fxaa = create_fxaa(&self.core, platform, &self.settings, output_render_pass)?;
+        if let (Some(new), Some(old)) = (&mut fxaa, &self.fxaa) {
+            new.pass.settings = old.pass.settings;
+        }
+        let mut deferred =
+            create_deferred(&self.core, platform, &self.settings, output_render_pass)?;
+        if let (Some(new), Some(old)) = (&mut deferred, &self.deferred) {
+            new.lighting.ambient = old.lighting.ambient;
+        }
+        let scaled = create_scaled(
+            &self.core,
+            platform.color_format(),
+            platform.is_vr(),
+            &self.settings,
+            output_render_pass,
+        )?;
+        self.pipelines.set_render_pass(
+            render_pass,
+            self.settings.msaa_samples,
+            needs_shader_encoding(scene_format),
+        )?;
+        self.pipelines.set_view_passes(framebuffer.view_passes())?;
+
+        // The prepass pipelines move to the new geometry target
+        let mut ssao = create_ssao(&self.core, platform, &self.settings, output_render_pass)?;
+        if let (Some(new), Some(old)) = (&mut ssao, &mut self.ssao) {
+            new.ssao.settings = old.ssao.settings;
+            old.pipelines.set_render_pass(
+                new.geometry.render_pass(),
+                vk::SampleCountFlagBits::_1,
+                false,
+            )?;
+            std::mem::swap(&mut new.pipelines, &mut old.pipelines);
+        }
+
+        unsafe {
+            if self.output_render_pass != self.render_pass {
                 self.core
                     .device
-                    .queue_submit(self.core.queue, &[submit_info], Some(cmd.fence))
-                    .result()?;
+                    .destroy_render_pass(Some(self.output_render_pass), None);
             }
-        } else {
-            let submit_info = vk::SubmitInfoBuilder::new().command_buffers(&command_buffers);
-            unsafe {
+            if render_pass != self.render_pass {
                 self.core
                     .device
-                    .queue_submit(self.core.queue, &[submit_info], Some(cmd.fence))
-                    .result()?;
+                    .destroy_render_pass(Some(self.render_pass), None);
             }
-        };
+        }
 
-        self.frame = (self.frame + 1) % FRAMES_IN_FLIGHT;
+        self.sync = sync;
+        self.framebuffer = framebuffer;
+        self.render_pass = render_pass;
+        self.scene_format = scene_format;
+        self.output_render_pass = output_render_pass;
+        self.fxaa = fxaa;
+        self.stereo = stereo;
+        self.deferred = deferred;
+        self.scaled = scaled;
+        self.ssao = ssao;
+        self.ssao_prepass = false;
+        self.frame = 0;
+        self.invalidate_prerecorded();
 
         Ok(())
     }
 
-    pub fn current_command_buffer(&self) -> vk::CommandBuffer {
-        self.command_buffers[self.frame]
+    /// FXAA settings, if FXAA is enabled. These may be changed between frames.
+    pub fn fxaa_settings_mut(&mut self) -> Option<&mut FxaaSettings> {
+        self.invalidate_prerecorded();
+        self.fxaa.as_mut().map(|fxaa| &mut fxaa.pass.settings)
     }
 
-    pub fn swapchain_resize(&mut self, images: Vec<vk::Image>, extent: vk::Extent2D) -> Result<()> {
-        self.framebuffer.resize(images, extent, self.render_pass)
+    /// Upload the lights of the current frame, with deferred shading. `camera` holds the
+    /// matrices of the camera UBO, see `DeferredLighting::update()`.
+    pub fn update_lights(&mut self, camera: &[f32; 4 * 4 * 2], lights: &[Light]) -> Result<()> {
+        let frame = self.frame;
+        match self.lighting_mut() {
+            Some(lighting) => lighting.update(frame, camera, lights),
+            None => bail!("Deferred shading is not enabled"),
+        }
+    }
+
+    /// The deferred lighting pass (such as to change the ambient light), if deferred shading is
+    /// enabled
+    pub fn lighting_mut(&mut self) -> Option<&mut DeferredLighting> {
+        self.deferred.as_mut().map(|deferred| &mut deferred.lighting)
+    }
+
+    /// Samples per pixel of `render_pass`, for `PipelineSettings::samples`
+    pub fn msaa_samples(&self) -> vk::SampleCountFlagBits {
+        self.settings.msaa_samples
+    }
+
+    /// Whether the scene's color format needs shaders to sRGB encode their output, for
+    /// `PipelineSettings::encode_srgb`. `pipelines` are built with it already.
+    pub fn encode_srgb(&self) -> bool {
+        self.pipelines.encode_srgb()
+    }
+
+    /// Whether depth is cleared for reverse-Z, for `PipelineSettings::reverse_z`
+    pub fn reverse_z(&self) -> bool {
+        self.settings.reverse_z
+    }
+
+    /// Fixed width over height of the scene, if `Settings::aspect_ratio` is set and not in VR
+    pub fn aspect_ratio(&self) -> Option<f32> {
+        self.settings
+            .aspect_ratio
+            .filter(|_| !self.framebuffer.is_vr())
+    }
+
+    /// Change the fixed aspect ratio of the scene (see `Settings::aspect_ratio`), from the next
+    /// frame on
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: Option<f32>) -> Result<()> {
+        if let Some(aspect) = aspect_ratio {
+            ensure!(
+                aspect.is_finite() && aspect > 0.0,
+                "Invalid aspect ratio {}",
+                aspect
+            );
+        }
+        self.invalidate_prerecorded();
+        self.settings.aspect_ratio = aspect_ratio;
+        Ok(())
+    }
+
+    /// Fraction of the window's resolution the scene is drawn at, see `Settings::render_scale`.
+    /// Always 1 in VR.
+    pub fn render_scale(&self) -> f32 {
+        if self.framebuffer.is_vr() {
+            1.0
+        } else {
+            self.settings.render_scale
+        }
+    }
+
+    /// Change the resolution the scene is drawn at (see `Settings::render_scale`), from the next
+    /// frame on. Waits for the frames in flight if the scene target must be resized.
+    pub fn set_render_scale(&mut self, render_scale: f32) -> Result<()> {
+        let vr = self.framebuffer.is_vr();
+        let settings = Settings {
+            render_scale,
+            ..self.settings
+        };
+        check_render_scale(&settings, vr)?;
+        if render_scale == self.settings.render_scale {
+            return Ok(());
+        }
+
+        self.core.wait_idle()?;
+        self.settings = settings;
+        if self.scaled.is_none() || render_scale == 1.0 {
+            self.scaled = create_scaled(
+                &self.core,
+                self.framebuffer.color_format(),
+                vr,
+                &self.settings,
+                self.output_render_pass,
+            )?;
+        }
+        if let Some(scaled) = &mut self.scaled {
+            let extent = scaled_extent(self.framebuffer.extent(), render_scale);
+            scaled.target.resize(extent)?;
+            scaled.pass.set_input(&scaled.target);
+        }
+        self.invalidate_prerecorded();
+        Ok(())
+    }
+
+    /// Region of the scene's framebuffer drawn to, letterboxed to `aspect_ratio()` if set. In
+    /// the stereo preview, the region of each eye.
+    pub fn scene_region(&self) -> ViewportRegion {
+        let extent = match (&self.stereo, &self.scaled) {
+            (Some(stereo), _) => stereo.target.extent(),
+            (None, Some(scaled)) => scaled.target.extent(),
+            (None, None) => self.framebuffer.extent(),
+        };
+        match self.aspect_ratio() {
+            Some(aspect) => split_screen::letterbox(extent, aspect),
+            None => ViewportRegion::from_rect(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent,
+            }),
+        }
+    }
+
+    /// Format of the depth buffer of `render_pass`
+    pub fn depth_format(&self) -> vk::Format {
+        self.settings.depth_format(&self.core)
+    }
+
+    /// Change the color the scene is cleared to, from the next frame on
+    pub fn set_clear_color(&mut self, clear_color: [f32; 4]) {
+        self.invalidate_prerecorded();
+        self.settings.clear_color = clear_color;
+    }
+
+    /// Handle events the StarterKit responds to (the RenderDoc capture and screenshot keys, if
+    /// enabled). Call this from `MainLoop::event()`.
+    #[cfg_attr(
+        not(any(feature = "renderdoc", feature = "screenshot")),
+        allow(unused_variables)
+    )]
+    pub fn event(&mut self, event: &PlatformEvent<'_, '_>) {
+        #[cfg(any(feature = "renderdoc", feature = "screenshot"))]
+        if let PlatformEvent::Winit(winit::event::Event::WindowEvent {
+            event:
+                winit::event::WindowEvent::KeyboardInput {
+                    input:
+                        winit::event::KeyboardInput {
+                            virtual_keycode: Some(key),
+                            state: winit::event::ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                },
+            ..
+        }) = event
+        {
+            #[cfg(feature = "renderdoc")]
+            if self.capture_key == Some(*key) {
+                crate::capture::trigger();
+            }
+            #[cfg(feature = "screenshot")]
+            if self.screenshots.key == Some(*key) {
+                self.request_screenshot();
+            }
+        }
     }
 
     pub fn winit_sync(&self) -> (vk::Semaphore, vk::Semaphore) {
@@ -222,13 +1626,32 @@ impl StarterKit {
     }
 }
 
-pub fn close_when_asked(event: PlatformEvent<'_, '_>, platform: Platform<'_>) {
-    if let PlatformEvent::Winit(winit::event::Event::WindowEvent { event, .. }) = event {
-        if let winit::event::WindowEvent::CloseRequested = event {
-            #[allow(irrefutable_let_patterns)]
-            if let Platform::Winit { control_flow, .. } = platform {
-                *control_flow = winit::event_loop::ControlFlow::Exit;
+impl Drop for StarterKit {
+    fn drop(&mut self) {
+        // Command pools may only be destroyed once their command buffers have completed
+        let _ = self.core.wait_idle();
+        unsafe {
+            for frame_pool in &self.frame_pools {
+                self.core
+                    .device
+                    .destroy_command_pool(Some(frame_pool.pool), None);
             }
+            self.core
+                .device
+                .destroy_command_pool(Some(self.command_pool), None);
+        }
+    }
+}
+
+pub fn close_when_asked(event: PlatformEvent<'_, '_>, platform: Platform<'_>) {
+    if let PlatformEvent::Winit(winit::event::Event::WindowEvent {
+        event: winit::event::WindowEvent::CloseRequested,
+        ..
+    }) = event
+    {
+        #[allow(irrefutable_let_patterns)]
+        if let Platform::Winit { control_flow, .. } = platform {
+            *control_flow = winit::event_loop::ControlFlow::Exit;
         }
     }
 }
@@ -0,0 +1,372 @@
+//! Pipeline barriers and queue submission through `VK_KHR_synchronization2`, where the device
+//! supports it. Stage and access masks are always given in their synchronization2 form; on
+//! devices without the extension they are truncated to the original flags (so stick to the bits
+//! which have a Vulkan 1.0 equivalent, e.g. `TRANSFER_KHR` rather than `COPY_KHR`), and empty
+//! stage masks become `TOP_OF_PIPE`/`BOTTOM_OF_PIPE`.
+use crate::app_info::AppInfo;
+use crate::Core;
+use anyhow::Result;
+use erupt::extensions::khr_synchronization2 as khr_sync2;
+use erupt::{vk, InstanceLoader};
+use std::ffi::CStr;
+use std::os::raw::c_char;
+
+pub use khr_sync2::{AccessFlags2KHR as Access, PipelineStageFlags2KHR as Stages};
+
+/// An image layout transition and/or memory dependency
+#[derive(Copy, Clone, Debug)]
+pub struct ImageBarrier {
+    pub image: vk::Image,
+    pub old_layout: vk::ImageLayout,
+    pub new_layout: vk::ImageLayout,
+    pub subresource_range: vk::ImageSubresourceRange,
+    pub src_stages: Stages,
+    pub src_access: Access,
+    pub dst_stages: Stages,
+    pub dst_access: Access,
+}
+
+/// A memory dependency on a whole buffer
+#[derive(Copy, Clone, Debug)]
+pub struct BufferBarrier {
+    pub buffer: vk::Buffer,
+    pub src_stages: Stages,
+    pub src_access: Access,
+    pub dst_stages: Stages,
+    pub dst_access: Access,
+}
+
+/// A semaphore to wait on or signal, and the stages which wait for it or which it waits for
+#[derive(Copy, Clone, Debug)]
+pub struct SemaphoreSubmit {
+    pub semaphore: vk::Semaphore,
+    pub stages: Stages,
+}
+
+/// The stages and accesses an image is typically used with in the given layout
+pub fn layout_usage(layout: vk::ImageLayout) -> (Stages, Access) {
+    match layout {
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL => (
+            Stages::VERTEX_SHADER_KHR | Stages::FRAGMENT_SHADER_KHR | Stages::COMPUTE_SHADER_KHR,
+            Access::SHADER_READ_KHR,
+        ),
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => (
+            Stages::COLOR_ATTACHMENT_OUTPUT_KHR,
+            Access::COLOR_ATTACHMENT_READ_KHR | Access::COLOR_ATTACHMENT_WRITE_KHR,
+        ),
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL => (
+            Stages::EARLY_FRAGMENT_TESTS_KHR | Stages::LATE_FRAGMENT_TESTS_KHR,
+            Access::DEPTH_STENCIL_ATTACHMENT_READ_KHR | Access::DEPTH_STENCIL_ATTACHMENT_WRITE_KHR,
+        ),
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL => (Stages::TRANSFER_KHR, Access::TRANSFER_READ_KHR),
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL => (Stages::TRANSFER_KHR, Access::TRANSFER_WRITE_KHR),
+        _ => (
+            Stages::ALL_COMMANDS_KHR,
+            Access::MEMORY_READ_KHR | Access::MEMORY_WRITE_KHR,
+        ),
+    }
+}
+
+/// The stages and accesses a buffer with the given usage is typically read with
+pub fn buffer_usage(usage: vk::BufferUsageFlags) -> (Stages, Access) {
+    let shaders =
+        Stages::VERTEX_SHADER_KHR | Stages::FRAGMENT_SHADER_KHR | Stages::COMPUTE_SHADER_KHR;
+    let mut stages = Stages::empty();
+    let mut access = Access::empty();
+    if usage.contains(vk::BufferUsageFlags::VERTEX_BUFFER) {
+        stages |= Stages::VERTEX_INPUT_KHR;
+        access |= Access::VERTEX_ATTRIBUTE_READ_KHR;
+    }
+    if usage.contains(vk::BufferUsageFlags::INDEX_BUFFER) {
+        stages |= Stages::VERTEX_INPUT_KHR;
+        access |= Access::INDEX_READ_KHR;
+    }
+    if usage.contains(vk::BufferUsageFlags::UNIFORM_BUFFER) {
+        stages |= shaders;
+        access |= Access::UNIFORM_READ_KHR;
+    }
+    if usage.contains(vk::BufferUsageFlags::STORAGE_BUFFER) {
+        stages |= shaders;
+        access |= Access::SHADER_READ_KHR | Access::SHADER_WRITE_KHR;
+    }
+    if usage.contains(vk::BufferUsageFlags::INDIRECT_BUFFER) {
+        stages |= Stages::DRAW_INDIRECT_KHR;
+        access |= Access::INDIRECT_COMMAND_READ_KHR;
+    }
+    if stages.is_empty() {
+        (
+            Stages::ALL_COMMANDS_KHR,
+            Access::MEMORY_READ_KHR | Access::MEMORY_WRITE_KHR,
+        )
+    } else {
+        (stages, access)
+    }
+}
+
+impl Core {
+    /// Whether `VK_KHR_synchronization2` is in use
+    pub fn synchronization2(&self) -> bool {
+        self.device.enabled().khr_synchronization2
+    }
+
+    /// Record a pipeline barrier
+    pub fn cmd_barriers(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffers: &[BufferBarrier],
+        images: &[ImageBarrier],
+    ) {
+        if self.synchronization2() {
+            let buffers: Vec<_> = buffers
+                .iter()
+                .map(|b| {
+                    khr_sync2::BufferMemoryBarrier2KHRBuilder::new()
+                        .buffer(b.buffer)
+                        .offset(0)
+                        .size(vk::WHOLE_SIZE)
+                        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .src_stage_mask(b.src_stages)
+                        .src_access_mask(b.src_access)
+                        .dst_stage_mask(b.dst_stages)
+                        .dst_access_mask(b.dst_access)
+                })
+                .collect();
+            let images: Vec<_> = images
+                .iter()
+                .map(|i| {
+                    khr_sync2::ImageMemoryBarrier2KHRBuilder::new()
+                        .image(i.image)
+                        .old_layout(i.old_layout)
+                        .new_layout(i.new_layout)
+                        .subresource_range(i.subresource_range)
+                        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .src_stage_mask(i.src_stages)
+                        .src_access_mask(i.src_access)
+                        .dst_stage_mask(i.dst_stages)
+                        .dst_access_mask(i.dst_access)
+                })
+                .collect();
+            let dependency = khr_sync2::DependencyInfoKHRBuilder::new()
+                .buffer_memory_barriers(&buffers)
+                .image_memory_barriers(&images);
+            unsafe {
+                self.device
+                    .cmd_pipeline_barrier2_khr(command_buffer, &dependency);
+            }
+        } else {
+            let mut src_stages = Stages::empty();
+            let mut dst_stages = Stages::empty();
+
+            let buffers: Vec<_> = buffers
+                .iter()
+                .map(|b| {
+                    src_stages |= b.src_stages;
+                    dst_stages |= b.dst_stages;
+                    vk::BufferMemoryBarrierBuilder::new()
+                        .buffer(b.buffer)
+                        .offset(0)
+                        .size(vk::WHOLE_SIZE)
+                        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .src_access_mask(legacy_access(b.src_access))
+                        .dst_access_mask(legacy_access(b.dst_access))
+                })
+                .collect();
+            let images: Vec<_> = images
+                .iter()
+                .map(|i| {
+                    src_stages |= i.src_stages;
+                    dst_stages |= i.dst_stages;
+                    vk::ImageMemoryBarrierBuilder::new()
+                        .image(i.image)
+                        .old_layout(i.old_layout)
+                        .new_layout(i.new_layout)
+                        .subresource_range(i.subresource_range)
+                        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .src_access_mask(legacy_access(i.src_access))
+                        .dst_access_mask(legacy_access(i.dst_access))
+                })
+                .collect();
+            unsafe {
+                self.device.cmd_pipeline_barrier(
+                    command_buffer,
+                    legacy_stages(src_stages, vk::PipelineStageFlags::TOP_OF_PIPE),
+                    legacy_stages(dst_stages, vk::PipelineStageFlags::BOTTOM_OF_PIPE),
+                    None,
+                    &[],
+                    &buffers,
+                    &images,
+                );
+            }
+        }
+    }
+
+    /// Record a global memory barrier
+    pub fn cmd_memory_barrier(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        src_stages: Stages,
+        src_access: Access,
+        dst_stages: Stages,
+        dst_access: Access,
+    ) {
+        if self.synchronization2() {
+            let barriers = [khr_sync2::MemoryBarrier2KHRBuilder::new()
+                .src_stage_mask(src_stages)
+                .src_access_mask(src_access)
+                .dst_stage_mask(dst_stages)
+                .dst_access_mask(dst_access)];
+            let dependency = khr_sync2::DependencyInfoKHRBuilder::new().memory_barriers(&barriers);
+            unsafe {
+                self.device
+                    .cmd_pipeline_barrier2_khr(command_buffer, &dependency);
+            }
+        } else {
+            let barriers = [vk::MemoryBarrierBuilder::new()
+                .src_access_mask(legacy_access(src_access))
+                .dst_access_mask(legacy_access(dst_access))];
+            unsafe {
+                self.device.cmd_pipeline_barrier(
+                    command_buffer,
+                    legacy_stages(src_stages, vk::PipelineStageFlags::TOP_OF_PIPE),
+                    legacy_stages(dst_stages, vk::PipelineStageFlags::BOTTOM_OF_PIPE),
+                    None,
+                    &barriers,
+                    &[],
+                    &[],
+                );
+            }
+        }
+    }
+
+    /// Submit a command buffer to the queue
+    pub fn submit(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        wait: Option<SemaphoreSubmit>,
+        signal: Option<SemaphoreSubmit>,
+        fence: Option<vk::Fence>,
+    ) -> Result<()> {
+        self.submit_to(
+            self.queue,
+            command_buffer,
+            wait.as_slice(),
+            signal.as_slice(),
+            fence,
+        )
+    }
+
+    /// Submit a command buffer to any queue of the device, waiting on and signalling any number
+    /// of semaphores
+    pub fn submit_to(
+        &self,
+        queue: vk::Queue,
+        command_buffer: vk::CommandBuffer,
+        wait: &[SemaphoreSubmit],
+        signal: &[SemaphoreSubmit],
+        fence: Option<vk::Fence>,
+    ) -> Result<()> {
+        let _queue = self.lock_queue(queue);
+        if self.synchronization2() {
+            let semaphore_info = |s: &SemaphoreSubmit| {
+                khr_sync2::SemaphoreSubmitInfoKHRBuilder::new()
+                    .semaphore(s.semaphore)
+                    .stage_mask(s.stages)
+            };
+            let waits: Vec<_> = wait.iter().map(semaphore_info).collect();
+            let signals: Vec<_> = signal.iter().map(semaphore_info).collect();
+            let command_buffers =
+                [khr_sync2::CommandBufferSubmitInfoKHRBuilder::new()
+                    .command_buffer(command_buffer)];
+            let submit_info = khr_sync2::SubmitInfo2KHRBuilder::new()
+                .wait_semaphore_infos(&waits)
+                .command_buffer_infos(&command_buffers)
+                .signal_semaphore_infos(&signals);
+            unsafe {
+                self.device
+                    .queue_submit2_khr(queue, &[submit_info], fence)
+                    .result()?;
+            }
+        } else {
+            let wait_semaphores: Vec<_> = wait.iter().map(|s| s.semaphore).collect();
+            let wait_stages: Vec<_> = wait
+                .iter()
+                .map(|s| legacy_stages(s.stages, vk::PipelineStageFlags::ALL_COMMANDS))
+                .collect();
+            let signal_semaphores: Vec<_> = signal.iter().map(|s| s.semaphore).collect();
+            let command_buffers = [command_buffer];
+            let submit_info = vk::SubmitInfoBuilder::new()
+                .wait_semaphores(&wait_semaphores)
+                .wait_dst_stage_mask(&wait_stages)
+                .command_buffers(&command_buffers)
+                .signal_semaphores(&signal_semaphores);
+            unsafe {
+                self.device
+                    .queue_submit(queue, &[submit_info], fence)
+                    .result()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn legacy_stages(stages: Stages, empty: vk::PipelineStageFlags) -> vk::PipelineStageFlags {
+    let stages = vk::PipelineStageFlags::from_bits_truncate(stages.bits() as u32);
+    if stages.is_empty() {
+        empty
+    } else {
+        stages
+    }
+}
+
+fn legacy_access(access: Access) -> vk::AccessFlags {
+    vk::AccessFlags::from_bits_truncate(access.bits() as u32)
+}
+
+/// Add `VK_KHR_synchronization2` to the device extensions if it is requested and supported.
+/// Returns whether it was added, in which case the feature must be enabled too.
+pub(crate) fn device_extensions(
+    info: &AppInfo,
+    instance: &InstanceLoader,
+    physical_device: vk::PhysicalDevice,
+    extensions: &mut Vec<*const c_char>,
+) -> Result<bool> {
+    // Querying the feature needs Vulkan 1.1
+    if !info.synchronization2 || instance.get_physical_device_features2.is_none() {
+        return Ok(false);
+    }
+
+    let name = khr_sync2::KHR_SYNCHRONIZATION_2_EXTENSION_NAME;
+    let extension_supported =
+        unsafe { instance.enumerate_device_extension_properties(physical_device, None, None) }
+            .result()?
+            .iter()
+            .any(|ext| unsafe {
+                CStr::from_ptr(ext.extension_name.as_ptr()) == CStr::from_ptr(name)
+            });
+    if !extension_supported {
+        return Ok(false);
+    }
+
+    let mut sync2_features = khr_sync2::PhysicalDeviceSynchronization2FeaturesKHR::default();
+    let mut features = vk::PhysicalDeviceFeatures2Builder::new().build();
+    features.p_next = &mut sync2_features as *mut _ as _;
+    unsafe { instance.get_physical_device_features2(physical_device, Some(features)) };
+
+    if sync2_features.synchronization2 == vk::TRUE {
+        extensions.push(name);
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
+
+/// Features to chain into the device create info when `device_extensions()` returns true
+pub(crate) fn features() -> khr_sync2::PhysicalDeviceSynchronization2FeaturesKHR {
+    khr_sync2::PhysicalDeviceSynchronization2FeaturesKHRBuilder::new()
+        .synchronization2(true)
+        .build()
+}
@@ -1,13 +1,103 @@
+use crate::defaults::FENCE_TIMEOUT;
 use crate::SharedCore;
-use anyhow::Result;
+use anyhow::{bail, Result};
 use erupt::vk;
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The GPU failed to complete a frame. Returned (through `anyhow::Error`) by
+/// `Synchronization::sync()`; check for it with `downcast_ref()`, or `is_device_lost()`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SyncError {
+    /// A frame didn't complete within the timeout, so the GPU has likely hung
+    Timeout(Duration),
+    /// The device was lost, such as after a GPU crash or driver reset
+    DeviceLost,
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncError::Timeout(timeout) => write!(
+                f,
+                "GPU did not complete a frame within {:.1}s",
+                timeout.as_secs_f32()
+            ),
+            SyncError::DeviceLost => write!(f, "GPU device lost"),
+        }
+    }
+}
+
+impl std::error::Error for SyncError {}
+
+/// No swapchain image could be acquired to draw to. The windowed backends skip the frame and
+/// deliver it to the app as `PlatformEvent::AcquireFailed`; the display backend returns it from
+/// `launch()`. Check for it with `downcast_ref()`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AcquireError {
+    /// Every attempt timed out, even after rebuilding the swapchain, as some drivers do while
+    /// displays are reconfigured
+    Timeout { attempts: u32, timeout: Duration },
+    /// The surface was lost, such as when its display was disconnected. The windowed backends
+    /// recreate it for the window.
+    SurfaceLost,
+}
+
+impl fmt::Display for AcquireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AcquireError::Timeout { attempts, timeout } => write!(
+                f,
+                "No swapchain image was acquired within {:.1}s in {} attempts",
+                timeout.as_secs_f32(),
+                attempts
+            ),
+            AcquireError::SurfaceLost => write!(f, "Window surface lost"),
+        }
+    }
+}
+
+impl std::error::Error for AcquireError {}
+
+/// `timeout` in nanoseconds for Vulkan waits, where None waits forever
+pub(crate) fn timeout_nanos(timeout: Option<Duration>) -> u64 {
+    timeout.map_or(u64::MAX, |t| t.as_nanos().min(u64::MAX as u128) as u64)
+}
+
+/// Whether `error` means the device is unusable: a `SyncError`, or `ERROR_DEVICE_LOST` from any
+/// Vulkan call
+pub fn is_device_lost(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<SyncError>().is_some()
+        || error.downcast_ref::<vk::Result>() == Some(&vk::Result::ERROR_DEVICE_LOST)
+}
+
+/// Wait for `fence`, failing with a `SyncError` if it isn't signalled within `timeout` (if any)
+/// or the device is lost
+pub fn wait_for_fence(
+    core: &SharedCore,
+    fence: vk::Fence,
+    timeout: Option<Duration>,
+) -> Result<()> {
+    let res = unsafe {
+        core.device
+            .wait_for_fences(&[fence], false, timeout_nanos(timeout))
+    };
+    match res.raw {
+        vk::Result::SUCCESS => Ok(()),
+        vk::Result::TIMEOUT => Err(SyncError::Timeout(timeout.unwrap_or(Duration::MAX)).into()),
+        vk::Result::ERROR_DEVICE_LOST => Err(SyncError::DeviceLost.into()),
+        other => bail!("Failed to wait for fence: {}", other),
+    }
+}
 
 /// Basic frmame/swapchain synchronization utility
 pub struct Synchronization {
     in_flight_fences: Vec<vk::Fence>,
     swapchain_sync: Vec<(vk::Semaphore, vk::Semaphore)>,
     swapchain_img_lut: HashMap<u32, vk::Fence>, // Mapping from swapchain image to
+    timeout: Option<Duration>,
     core: SharedCore,
 }
 
@@ -49,31 +139,34 @@ impl Synchronization {
             in_flight_fences,
             swapchain_sync,
             swapchain_img_lut: Default::default(),
+            timeout: Some(FENCE_TIMEOUT),
             core,
         })
     }
 
+    /// How long `sync()` waits on the GPU before failing with `SyncError::Timeout`, or None to
+    /// wait forever. Defaults to `FENCE_TIMEOUT`.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
     /// Synchronize with per-frame gpu resources and swapchain frame. Blocks if a needed GPU
     /// resources is unavailable. Returns a fence that must be signalled when the corresponding
-    /// frame is complete.
+    /// frame is complete. Fails with a `SyncError` if the GPU hangs or is lost.
     pub fn sync(&mut self, swapchain_image_index: u32, frame: usize) -> Result<vk::Fence> {
         // Ensure this swapchain image is not already in use by the GPU
         if let Some(&fence) = self.swapchain_img_lut.get(&swapchain_image_index) {
-            unsafe {
-                self.core
-                    .device
-                    .wait_for_fences(&[fence], false, u64::MAX)
-                    .result()?;
-            }
+            wait_for_fence(&self.core, fence, self.timeout)?;
         }
 
         // Ensure this frame is not already in use by the GPU
         let fence = self.in_flight_fences[frame];
+        wait_for_fence(&self.core, fence, self.timeout)?;
         unsafe {
-            self.core
-                .device
-                .wait_for_fences(&[fence], false, u64::MAX)
-                .result()?;
             self.core.device.reset_fences(&[fence]).result()?; // TODO: Move this into the swapchain next_image
         }
         self.swapchain_img_lut.insert(swapchain_image_index, fence);
@@ -103,3 +196,56 @@ impl Drop for Synchronization {
         }
     }
 }
+
+/// A fence shared by everything waiting on the same submission, destroyed along with its last
+/// clone
+#[derive(Clone)]
+pub struct SharedFence(Arc<FenceInner>);
+
+struct FenceInner {
+    fence: vk::Fence,
+    core: SharedCore,
+}
+
+impl SharedFence {
+    /// Create an unsignalled fence
+    pub fn new(core: SharedCore) -> Result<Self> {
+        let create_info = vk::FenceCreateInfoBuilder::new();
+        let fence = unsafe { core.device.create_fence(&create_info, None, None) }.result()?;
+        Ok(Self(Arc::new(FenceInner { fence, core })))
+    }
+
+    pub fn fence(&self) -> vk::Fence {
+        self.0.fence
+    }
+
+    /// Whether the fence has been signalled, without blocking
+    pub fn is_signalled(&self) -> Result<bool> {
+        let status = unsafe { self.0.core.device.get_fence_status(self.0.fence) };
+        match status.raw {
+            vk::Result::SUCCESS => Ok(true),
+            vk::Result::NOT_READY => Ok(false),
+            other => bail!("Failed to get fence status: {}", other),
+        }
+    }
+
+    /// Block until the fence is signalled
+    pub fn wait(&self) -> Result<()> {
+        unsafe {
+            self.0
+                .core
+                .device
+                .wait_for_fences(&[self.0.fence], true, u64::MAX)
+                .result()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for FenceInner {
+    fn drop(&mut self) {
+        unsafe {
+            self.core.device.destroy_fence(Some(self.fence), None);
+        }
+    }
+}
@@ -0,0 +1,71 @@
+//! Alpha blended geometry. Transparent draws are recorded after all opaque ones in the same
+//! render pass, using pipelines built with `PipelineSettings::transparent()`: they are depth
+//! tested against the opaque geometry but don't write depth, and composite correctly when drawn
+//! back to front. `TransparentQueue` collects them over a frame and sorts them by their distance
+//! from the camera.
+//!
+//! ```ignore
+//! queue.push(mesh_center, mesh_id);
+//! // After the opaque draws
+//! for mesh_id in queue.take_sorted(&cameras) {
+//!     draw_mesh(core, command_buffer, &meshes[mesh_id]);
+//! }
+//! ```
+
+/// Distance of `position` in front of the camera, along its view direction. `camera` is a
+/// column-major perspective view-projection matrix, such as the first of those returned by
+/// `MultiPlatformCamera::get_matrices()`; only its first 16 elements are used.
+pub fn view_depth(camera: &[f32], position: [f32; 3]) -> f32 {
+    // The clip space w of a perspective projection is the view space depth
+    let [x, y, z] = position;
+    camera[3] * x + camera[7] * y + camera[11] * z + camera[15]
+}
+
+/// Sort items farthest from the camera first, given the world space position of each
+pub fn sort_back_to_front<T>(items: &mut [T], camera: &[f32], position: impl Fn(&T) -> [f32; 3]) {
+    items.sort_by(|a, b| {
+        let (a, b) = (
+            view_depth(camera, position(a)),
+            view_depth(camera, position(b)),
+        );
+        b.total_cmp(&a)
+    });
+}
+
+/// Transparent draws queued over a frame, each tagged with its world space position (such as
+/// the center of its mesh)
+pub struct TransparentQueue<T> {
+    draws: Vec<([f32; 3], T)>,
+}
+
+impl<T> TransparentQueue<T> {
+    pub fn new() -> Self {
+        Self { draws: Vec::new() }
+    }
+
+    /// Queue a transparent draw
+    pub fn push(&mut self, position: [f32; 3], draw: T) {
+        self.draws.push((position, draw));
+    }
+
+    pub fn len(&self) -> usize {
+        self.draws.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.draws.is_empty()
+    }
+
+    /// Take the queued draws, farthest from the camera first
+    pub fn take_sorted(&mut self, camera: &[f32]) -> Vec<T> {
+        let mut draws = std::mem::take(&mut self.draws);
+        sort_back_to_front(&mut draws, camera, |(position, _)| *position);
+        draws.into_iter().map(|(_, draw)| draw).collect()
+    }
+}
+
+impl<T> Default for TransparentQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
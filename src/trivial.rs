@@ -1,30 +1,117 @@
+use crate::hardware_query::DevicePreference;
 use crate::prelude::*;
-use crate::defaults::FRAMES_IN_FLIGHT;
+use crate::pipeline_manager::PipelineId;
+use crate::set_layouts;
+use crate::shader::PipelineSettings;
+use crate::transparency::TransparentQueue;
 use anyhow::Result;
+use bytemuck::offset_of;
+use std::collections::HashMap;
 
 pub fn draw(draw: DrawList, vr: bool) -> Result<()> {
-    let info = AppInfo::default().validation(cfg!(debug_assertions));
-    launch::<App, DrawList>(info, vr, draw)
+    draw_with_settings(draw, vr, starter_kit::Settings::default())
+}
+
+/// Draw with the given StarterKit settings, such as MSAA or a clear color
+pub fn draw_with_settings(draw: DrawList, vr: bool, settings: starter_kit::Settings) -> Result<()> {
+    // Wide lines and large points where supported; lines are drawn as quads otherwise
+    let optional_features = vk::PhysicalDeviceFeaturesBuilder::new()
+        .wide_lines(true)
+        .large_points(true)
+        .build();
+    let info = AppInfo::default()
+        .validation(cfg!(debug_assertions))
+        .device_preference(DevicePreference {
+            optional_features,
+            ..Default::default()
+        });
+    launch::<App, (DrawList, starter_kit::Settings)>(info, vr, (draw, settings))
 }
 
 /// A list of meshes to draw
 pub type DrawList = Vec<DrawData>;
 
-/// A mesh and the primitive it is constructed of
+/// A mesh, the primitive it is constructed of, and how it is drawn
 #[derive(Clone)]
 pub struct DrawData {
     pub indices: Vec<u32>,
     pub vertices: Vec<Vertex>,
     pub primitive: Primitive,
+    /// Diameter of points, in pixels
+    pub point_size: f32,
+    /// Width of lines, in pixels. Lines wider than 1 are drawn as quads on devices without the
+    /// `wide_lines` feature.
+    pub line_width: f32,
+    /// RGBA multiplier of the vertex colors, so that one mesh may be drawn in several colors.
+    /// Draws with an alpha below 1 are blended over the opaque ones, back to front.
+    pub tint: [f32; 4],
+}
+
+impl DrawData {
+    /// Drawn with single pixel points and lines, and no tint
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>, primitive: Primitive) -> Self {
+        Self {
+            indices,
+            vertices,
+            primitive,
+            point_size: 1.0,
+            line_width: 1.0,
+            tint: [1.0; 4],
+        }
+    }
+
+    /// The same mesh with a different tint
+    pub fn with_tint(mut self, tint: [f32; 4]) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    fn is_lines(&self) -> bool {
+        matches!(self.primitive, Primitive::Lines | Primitive::LineStrip)
+    }
+
+    fn is_transparent(&self) -> bool {
+        self.tint[3] < 1.0
+    }
+
+    /// Mean of the vertex positions
+    fn center(&self) -> [f32; 3] {
+        let mut sum = [0.0; 3];
+        for vertex in &self.vertices {
+            sum.iter_mut().zip(vertex.pos).for_each(|(s, p)| *s += p);
+        }
+        sum.map(|s| s / self.vertices.len().max(1) as f32)
+    }
+}
+
+/// Meshes drawn with the same pipeline
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum Batch {
+    Primitive(Primitive),
+    /// Lines expanded into quads, see `expand_lines()`
+    WideLines,
+}
+
+/// A draw of one of the uploaded meshes
+struct DrawItem {
+    /// Index into `App::meshes`
+    mesh: usize,
+    /// Index into `App::pipelines`
+    pipeline: usize,
+    params: DrawParams,
+    /// Where the draw is sorted from, if it's transparent
+    transparent: Option<[f32; 3]>,
 }
 
 struct App {
-    draw: Vec<(ManagedMesh, Primitive)>,
+    meshes: Vec<ManagedMesh>,
+    draw: Vec<DrawItem>,
 
-    point_pipeline: vk::Pipeline,
-    line_pipeline: vk::Pipeline,
-    tri_pipeline: vk::Pipeline,
+    /// One pipeline for each batch in the draw list, opaque or transparent
+    pipelines: Vec<((Batch, bool), PipelineId)>,
     pipeline_layout: vk::PipelineLayout,
+    /// Range of dynamic line widths, if the device supports wide lines
+    line_width_range: Option<[f32; 2]>,
 
     descriptor_sets: Vec<vk::DescriptorSet>,
     descriptor_pool: vk::DescriptorPool,
@@ -36,19 +123,45 @@ struct App {
     starter_kit: StarterKit,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+/// Index which ends a strip or fan, starting a new one with the next index. Polylines can then
+/// share one mesh without duplicating interior vertices.
+pub const PRIMITIVE_RESTART: u32 = u32::MAX;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Primitive {
     Points,
     Lines,
     Triangles,
+    /// Connected lines, which may be split with `PRIMITIVE_RESTART`
+    LineStrip,
+    /// Connected triangles, which may be split with `PRIMITIVE_RESTART`
+    TriangleStrip,
+    /// Triangles sharing their first vertex, which may be split with `PRIMITIVE_RESTART`. Not
+    /// available on portability implementations such as MoltenVK.
+    TriangleFan,
+}
+
+impl Primitive {
+    /// Every primitive, in the order they are drawn
+    pub const ALL: [Primitive; 6] = [
+        Primitive::Points,
+        Primitive::Lines,
+        Primitive::LineStrip,
+        Primitive::Triangles,
+        Primitive::TriangleStrip,
+        Primitive::TriangleFan,
+    ];
 }
 
-impl Into<vk::PrimitiveTopology> for Primitive {
-    fn into(self) -> vk::PrimitiveTopology {
-        match self {
+impl From<Primitive> for vk::PrimitiveTopology {
+    fn from(primitive: Primitive) -> Self {
+        match primitive {
             Primitive::Points => vk::PrimitiveTopology::POINT_LIST,
             Primitive::Lines => vk::PrimitiveTopology::LINE_LIST,
             Primitive::Triangles => vk::PrimitiveTopology::TRIANGLE_LIST,
+            Primitive::LineStrip => vk::PrimitiveTopology::LINE_STRIP,
+            Primitive::TriangleStrip => vk::PrimitiveTopology::TRIANGLE_STRIP,
+            Primitive::TriangleFan => vk::PrimitiveTopology::TRIANGLE_FAN,
         }
     }
 }
@@ -63,25 +176,107 @@ struct SceneData {
 unsafe impl bytemuck::Zeroable for SceneData {}
 unsafe impl bytemuck::Pod for SceneData {}
 
-impl MainLoop<DrawList> for App {
-    fn new(core: &SharedCore, mut platform: Platform<'_>, draw_data: DrawList) -> Result<Self> {
-        let mut starter_kit = StarterKit::new(core.clone(), &mut platform)?;
+/// Push constants of each draw
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct DrawParams {
+    tint: [f32; 4],
+    point_size: f32,
+    line_width: f32,
+    /// Framebuffer size in pixels
+    viewport: [f32; 2],
+}
+
+unsafe impl bytemuck::Zeroable for DrawParams {}
+unsafe impl bytemuck::Pod for DrawParams {}
+
+/// Vertex of a line expanded into a quad, offset to one side of the line by `wide_line.vert`
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+struct LineVertex {
+    pos: [f32; 3],
+    color: [f32; 3],
+    /// Position of the other end of the segment
+    other: [f32; 3],
+    /// Side of the line, -1 or 1
+    side: f32,
+}
+
+unsafe impl bytemuck::Zeroable for LineVertex {}
+unsafe impl bytemuck::Pod for LineVertex {}
+
+impl VertexLayout for LineVertex {
+    fn attribute_descriptions() -> Vec<vk::VertexInputAttributeDescriptionBuilder<'static>> {
+        let attribute = |location: u32, format: vk::Format, offset: usize| {
+            vk::VertexInputAttributeDescriptionBuilder::new()
+                .binding(0)
+                .location(location)
+                .format(format)
+                .offset(offset as u32)
+        };
+        vec![
+            attribute(0, vk::Format::R32G32B32_SFLOAT, offset_of!(Self, pos)),
+            attribute(1, vk::Format::R32G32B32_SFLOAT, offset_of!(Self, color)),
+            attribute(2, vk::Format::R32G32B32_SFLOAT, offset_of!(Self, other)),
+            attribute(3, vk::Format::R32_SFLOAT, offset_of!(Self, side)),
+        ]
+    }
+}
+
+/// Expand a line list or strip into a triangle list of one quad per segment
+fn expand_lines(data: &DrawData) -> (Vec<LineVertex>, Vec<u32>) {
+    let segments: Vec<[u32; 2]> = match data.primitive {
+        Primitive::LineStrip => data
+            .indices
+            .windows(2)
+            .filter(|pair| !pair.contains(&PRIMITIVE_RESTART))
+            .map(|pair| [pair[0], pair[1]])
+            .collect(),
+        _ => data
+            .indices
+            .chunks_exact(2)
+            .map(|pair| [pair[0], pair[1]])
+            .collect(),
+    };
+
+    let mut vertices = vec![];
+    let mut indices = vec![];
+    for [a, b] in segments {
+        let (a, b) = (data.vertices[a as usize], data.vertices[b as usize]);
+        let base = vertices.len() as u32;
+        // The far end sees the line reversed, so its sides are swapped
+        for (vertex, other, side) in [(a, b, 1.), (a, b, -1.), (b, a, -1.), (b, a, 1.)] {
+            vertices.push(LineVertex {
+                pos: vertex.pos,
+                color: vertex.color,
+                other: other.pos,
+                side,
+            });
+        }
+        indices.extend([0, 1, 2, 2, 1, 3].iter().map(|i| base + i));
+    }
+
+    (vertices, indices)
+}
+
+impl MainLoop<(DrawList, starter_kit::Settings)> for App {
+    fn new(
+        core: &SharedCore,
+        mut platform: Platform<'_>,
+        (draw_data, settings): (DrawList, starter_kit::Settings),
+    ) -> Result<Self> {
+        let mut starter_kit = StarterKit::new_with_settings(core.clone(), &mut platform, settings)?;
 
         // Camera
         let camera = MultiPlatformCamera::new(&mut platform);
 
         // Scene data
-        let scene_ubo = FrameDataUbo::new(core.clone(), FRAMES_IN_FLIGHT)?;
+        let frames = starter_kit.frames_in_flight();
+        let scene_ubo = FrameDataUbo::new(core.clone(), frames)?;
 
-        // Create descriptor set layout
+        // Create descriptor set layout, following the frame data set convention
         const FRAME_DATA_BINDING: u32 = 0;
-        let bindings = [
-            vk::DescriptorSetLayoutBindingBuilder::new()
-                .binding(FRAME_DATA_BINDING)
-                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-                .descriptor_count(1)
-                .stage_flags(vk::ShaderStageFlags::ALL_GRAPHICS),
-        ];
+        let bindings = set_layouts::frame_bindings();
 
         let descriptor_set_layout_ci =
             vk::DescriptorSetLayoutCreateInfoBuilder::new().bindings(&bindings);
@@ -96,24 +291,23 @@ impl MainLoop<DrawList> for App {
         let pool_sizes = [
             vk::DescriptorPoolSizeBuilder::new()
                 ._type(vk::DescriptorType::UNIFORM_BUFFER)
-                .descriptor_count(FRAMES_IN_FLIGHT as _),
+                .descriptor_count(frames as _),
         ];
 
         let create_info = vk::DescriptorPoolCreateInfoBuilder::new()
             .pool_sizes(&pool_sizes)
-            .max_sets((FRAMES_IN_FLIGHT * 2) as _);
+            .max_sets((frames * 2) as _);
 
         let descriptor_pool =
             unsafe { core.device.create_descriptor_pool(&create_info, None, None) }.result()?;
 
         // Create descriptor sets
-        let layouts = vec![descriptor_set_layout; FRAMES_IN_FLIGHT];
+        let layouts = vec![descriptor_set_layout; frames];
         let create_info = vk::DescriptorSetAllocateInfoBuilder::new()
             .descriptor_pool(descriptor_pool)
             .set_layouts(&layouts);
 
-        let descriptor_sets =
-            unsafe { core.device.allocate_descriptor_sets(&create_info) }.result()?;
+        let descriptor_sets = core.allocate_descriptor_sets(&create_info)?;
 
         // Write descriptor sets
         for (frame, &descriptor_set) in descriptor_sets.iter().enumerate() {
@@ -137,9 +331,9 @@ impl MainLoop<DrawList> for App {
 
         // Pipeline layout
         let push_constant_ranges = [vk::PushConstantRangeBuilder::new()
-            .stage_flags(vk::ShaderStageFlags::VERTEX)
+            .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
             .offset(0)
-            .size(std::mem::size_of::<[f32; 4 * 4]>() as u32)];
+            .size(std::mem::size_of::<DrawParams>() as u32)];
 
         let create_info = vk::PipelineLayoutCreateInfoBuilder::new()
             .push_constant_ranges(&push_constant_ranges)
@@ -148,49 +342,124 @@ impl MainLoop<DrawList> for App {
         let pipeline_layout =
             unsafe { core.device.create_pipeline_layout(&create_info, None, None) }.result()?;
 
-        // Pipelines
-        let unlit_vert = include_bytes!("../shaders/unlit.vert.spv");
-        let unlit_frag = include_bytes!("../shaders/unlit.frag.spv");
-
-        let point_pipeline = shader(
-            core,
-            unlit_vert,
-            unlit_frag,
-            Primitive::Points.into(),
-            starter_kit.render_pass,
-            pipeline_layout,
-        )?;
+        // Mesh uploads. Draws of the same geometry (such as in different tints) share a mesh.
+        let wide_lines = core.device_features.wide_lines == vk::TRUE;
+        let mut meshes = vec![];
+        let mut mesh_lookup: HashMap<(Batch, Vec<u8>, Vec<u32>), usize> = HashMap::new();
+        let mut draw = vec![];
+        for data in draw_data {
+            let params = DrawParams {
+                tint: data.tint,
+                point_size: data.point_size,
+                line_width: data.line_width,
+                viewport: [0.; 2],
+            };
+
+            let batch = if data.is_lines() && !wide_lines && data.line_width != 1.0 {
+                Batch::WideLines
+            } else {
+                Batch::Primitive(data.primitive)
+            };
+
+            let key = (
+                batch,
+                bytemuck::cast_slice(&data.vertices).to_vec(),
+                data.indices.clone(),
+            );
+            let mesh = match mesh_lookup.get(&key) {
+                Some(&mesh) => mesh,
+                None => {
+                    let mesh = match batch {
+                        Batch::WideLines => {
+                            let (vertices, indices) = expand_lines(&data);
+                            upload_mesh(
+                                &mut starter_kit.staging_buffer,
+                                starter_kit.command_buffers[0],
+                                &vertices,
+                                &indices,
+                            )?
+                        }
+                        Batch::Primitive(_) => upload_mesh(
+                            &mut starter_kit.staging_buffer,
+                            starter_kit.command_buffers[0],
+                            &data.vertices,
+                            &data.indices,
+                        )?,
+                    };
+                    meshes.push(mesh);
+                    mesh_lookup.insert(key, meshes.len() - 1);
+                    meshes.len() - 1
+                }
+            };
 
-        let line_pipeline = shader(
-            core,
-            unlit_vert,
-            unlit_frag,
-            Primitive::Lines.into(),
-            starter_kit.render_pass,
-            pipeline_layout,
-        )?;
+            let transparent = data.is_transparent().then(|| data.center());
+            draw.push((mesh, (batch, transparent.is_some()), params, transparent));
+        }
 
-        let tri_pipeline = shader(
-            core,
-            unlit_vert,
-            unlit_frag,
-            Primitive::Triangles.into(),
-            starter_kit.render_pass,
-            pipeline_layout,
-        )?;
+        // Pipelines
+        let trivial_vert = include_bytes!("../shaders/trivial.vert.spv");
+        let wide_line_vert = include_bytes!("../shaders/wide_line.vert.spv");
+        let trivial_frag = include_bytes!("../shaders/trivial.frag.spv");
+
+        let batches = Primitive::ALL
+            .iter()
+            .map(|&primitive| Batch::Primitive(primitive))
+            .chain(std::iter::once(Batch::WideLines));
+
+        let mut pipelines = vec![];
+        for (batch, transparent) in batches.flat_map(|batch| [(batch, false), (batch, true)]) {
+            if !draw.iter().any(|(_, key, _, _)| *key == (batch, transparent)) {
+                continue;
+            }
 
-        // Mesh uploads
-        let mut draw = vec![];
-        for data in draw_data {
-            let mesh = upload_mesh(
-                &mut starter_kit.staging_buffer,
-                starter_kit.command_buffers[0],
-                &data.vertices,
-                &data.indices,
-            )?;
-            draw.push((mesh, data.primitive));
+            let blending = if transparent {
+                PipelineSettings::transparent()
+            } else {
+                PipelineSettings::default()
+            };
+
+            let pipeline = match batch {
+                Batch::Primitive(primitive) => {
+                    let settings = PipelineSettings {
+                        topology: primitive.into(),
+                        dynamic_line_width: wide_lines,
+                        ..blending
+                    };
+                    starter_kit.pipelines.add::<Vertex>(
+                        trivial_vert,
+                        trivial_frag,
+                        settings,
+                        pipeline_layout,
+                    )?
+                }
+                Batch::WideLines => {
+                    let settings = PipelineSettings {
+                        cull_mode: vk::CullModeFlags::NONE,
+                        ..blending
+                    };
+                    starter_kit.pipelines.add::<LineVertex>(
+                        wide_line_vert,
+                        trivial_frag,
+                        settings,
+                        pipeline_layout,
+                    )?
+                }
+            };
+            pipelines.push(((batch, transparent), pipeline));
         }
 
+        let draw = draw
+            .into_iter()
+            .map(|(mesh, key, params, transparent)| DrawItem {
+                mesh,
+                pipeline: pipelines.iter().position(|(k, _)| *k == key).unwrap(),
+                params,
+                transparent,
+            })
+            .collect();
+
+        let line_width_range = wide_lines.then(|| core.device_properties.limits.line_width_range);
+
         Ok(Self {
             camera,
             descriptor_set_layout,
@@ -199,10 +468,10 @@ impl MainLoop<DrawList> for App {
             anim: 0.0,
             pipeline_layout,
             scene_ubo,
+            meshes,
             draw,
-            point_pipeline,
-            line_pipeline,
-            tri_pipeline,
+            pipelines,
+            line_width_range,
             starter_kit,
         })
     }
@@ -216,6 +485,8 @@ impl MainLoop<DrawList> for App {
         let cmd = self.starter_kit.begin_command_buffer(frame)?;
         let command_buffer = cmd.command_buffer;
 
+        let (ret, cameras) = self.camera.get_matrices(&platform)?;
+
         unsafe {
             core.device.cmd_bind_descriptor_sets(
                 command_buffer,
@@ -226,28 +497,53 @@ impl MainLoop<DrawList> for App {
                 &[],
             );
 
+            // Opaque draws grouped by pipeline, then transparent ones back to front
+            let mut order: Vec<usize> = (0..self.draw.len())
+                .filter(|&i| self.draw[i].transparent.is_none())
+                .collect();
+            order.sort_by_key(|&i| self.draw[i].pipeline);
+
+            let mut transparent = TransparentQueue::new();
+            for (i, item) in self.draw.iter().enumerate() {
+                if let Some(center) = item.transparent {
+                    transparent.push(center, i);
+                }
+            }
+            order.extend(transparent.take_sorted(&cameras));
+
             // Draw cmds
-            for filter in [Primitive::Points, Primitive::Lines, Primitive::Triangles] {
-                core.device.cmd_bind_pipeline(
+            let extent = self.starter_kit.framebuffer.extent();
+            let viewport = [extent.width as f32, extent.height as f32];
+
+            let mut bound = None;
+            for item in order.into_iter().map(|i| &self.draw[i]) {
+                let ((batch, _), pipeline) = self.pipelines[item.pipeline];
+                if bound != Some(item.pipeline) {
+                    core.device.cmd_bind_pipeline(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        self.starter_kit.pipelines.get(pipeline),
+                    );
+                    bound = Some(item.pipeline);
+                }
+
+                let params = DrawParams { viewport, ..item.params };
+                core.device.cmd_push_constants(
                     command_buffer,
-                    vk::PipelineBindPoint::GRAPHICS,
-                    match filter {
-                        Primitive::Points => self.point_pipeline,
-                        Primitive::Lines => self.line_pipeline,
-                        Primitive::Triangles => self.tri_pipeline,
-                    }
+                    self.pipeline_layout,
+                    vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    std::mem::size_of::<DrawParams>() as u32,
+                    &params as *const DrawParams as _,
                 );
-
-                for (mesh, primitive) in &self.draw {
-                    if *primitive == filter {
-                        draw_mesh(core, command_buffer, &mesh);
-                    }
+                if let (Batch::Primitive(_), Some([min, max])) = (batch, self.line_width_range) {
+                    core.device.cmd_set_line_width(command_buffer, params.line_width.clamp(min, max));
                 }
+
+                draw_mesh(core, command_buffer, &self.meshes[item.mesh]);
             }
         }
 
-        let (ret, cameras) = self.camera.get_matrices(&platform)?;
-
         self.scene_ubo.upload(
             self.starter_kit.frame,
             &SceneData {
@@ -280,7 +576,7 @@ impl MainLoop<DrawList> for App {
     }
 }
 
-impl SyncMainLoop<DrawList> for App {
+impl SyncMainLoop<(DrawList, starter_kit::Settings)> for App {
     fn winit_sync(&self) -> (vk::Semaphore, vk::Semaphore) {
         self.starter_kit.winit_sync()
     }
@@ -289,12 +585,12 @@ impl SyncMainLoop<DrawList> for App {
 impl Drop for App {
     fn drop(&mut self) {
         unsafe {
-            self.starter_kit.core.device.device_wait_idle().unwrap();
-            self.starter_kit.core.device.destroy_descriptor_pool(Some(self.descriptor_pool), None);
+            self.starter_kit.core.wait_idle().unwrap();
+            self.starter_kit.core.destroy_descriptor_pool(self.descriptor_pool);
             self.starter_kit.core.device.destroy_descriptor_set_layout(Some(self.descriptor_set_layout), None);
             self.starter_kit.core.device.destroy_pipeline_layout(Some(self.pipeline_layout), None);
-            for pipeline in [self.tri_pipeline, self.line_pipeline, self.point_pipeline] {
-                self.starter_kit.core.device.destroy_pipeline(Some(pipeline), None);
+            for &(_, pipeline) in &self.pipelines {
+                self.starter_kit.pipelines.remove(pipeline);
             }
         }
     }
@@ -0,0 +1,129 @@
+//! Validation layer setup shared by the backends
+use crate::app_info::AppInfo;
+use anyhow::{ensure, Result};
+use erupt::extensions::{ext_debug_utils, ext_validation_features, khr_shader_non_semantic_info};
+use erupt::{cstr, vk, InstanceLoader};
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_void};
+
+pub(crate) const LAYER_KHRONOS_VALIDATION: *const c_char = cstr!("VK_LAYER_KHRONOS_validation");
+
+/// Add the validation layer and the instance extensions it needs, if validation is enabled
+pub(crate) fn instance_setup(
+    info: &AppInfo,
+    layers: &mut Vec<*const c_char>,
+    extensions: &mut Vec<*const c_char>,
+) -> Result<()> {
+    let features = &info.validation_features;
+    ensure!(
+        !(features.gpu_assisted && features.debug_printf),
+        "GPU-assisted validation and debug printf cannot be enabled together"
+    );
+
+    if info.validation {
+        extensions.push(ext_debug_utils::EXT_DEBUG_UTILS_EXTENSION_NAME);
+        layers.push(LAYER_KHRONOS_VALIDATION);
+        if !feature_enables(info).is_empty() {
+            extensions.push(ext_validation_features::EXT_VALIDATION_FEATURES_EXTENSION_NAME);
+        }
+    }
+    Ok(())
+}
+
+/// Validation features to chain into the instance create info. Empty if none are requested.
+pub(crate) fn feature_enables(info: &AppInfo) -> Vec<vk::ValidationFeatureEnableEXT> {
+    let features = &info.validation_features;
+    let mut enables = vec![];
+    if !info.validation {
+        return enables;
+    }
+    if features.gpu_assisted {
+        enables.push(vk::ValidationFeatureEnableEXT::GPU_ASSISTED_EXT);
+        enables.push(vk::ValidationFeatureEnableEXT::GPU_ASSISTED_RESERVE_BINDING_SLOT_EXT);
+    }
+    if features.best_practices {
+        enables.push(vk::ValidationFeatureEnableEXT::BEST_PRACTICES_EXT);
+    }
+    if features.synchronization {
+        enables.push(vk::ValidationFeatureEnableEXT::SYNCHRONIZATION_VALIDATION_EXT);
+    }
+    if features.debug_printf {
+        enables.push(vk::ValidationFeatureEnableEXT::DEBUG_PRINTF_EXT);
+    }
+    enables
+}
+
+/// Add the device extensions needed by the enabled validation features, where supported
+pub(crate) fn device_extensions(
+    info: &AppInfo,
+    instance: &InstanceLoader,
+    physical_device: vk::PhysicalDevice,
+    extensions: &mut Vec<*const c_char>,
+) -> Result<()> {
+    if info.validation && info.validation_features.debug_printf {
+        let name = khr_shader_non_semantic_info::KHR_SHADER_NON_SEMANTIC_INFO_EXTENSION_NAME;
+        let supported =
+            unsafe { instance.enumerate_device_extension_properties(physical_device, None, None) }
+                .result()?
+                .iter()
+                .any(|ext| unsafe {
+                    CStr::from_ptr(ext.extension_name.as_ptr()) == CStr::from_ptr(name)
+                });
+
+        if supported {
+            extensions.push(name);
+        } else {
+            eprintln!(
+                "VK_KHR_shader_non_semantic_info is not supported, debugPrintfEXT will not work"
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Route validation messages (and debugPrintfEXT output, if enabled) to stdout/stderr
+pub(crate) fn create_messenger(info: &AppInfo, instance: &InstanceLoader) -> Result<()> {
+    if !info.validation {
+        return Ok(());
+    }
+
+    let mut severity = vk::DebugUtilsMessageSeverityFlagsEXT::WARNING_EXT
+        | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR_EXT;
+    if info.validation_features.debug_printf {
+        // Printf output is reported with info severity
+        severity |= vk::DebugUtilsMessageSeverityFlagsEXT::INFO_EXT;
+    }
+
+    let create_info = vk::DebugUtilsMessengerCreateInfoEXTBuilder::new()
+        .message_severity(severity)
+        .message_type(
+            vk::DebugUtilsMessageTypeFlagsEXT::GENERAL_EXT
+                | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION_EXT
+                | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE_EXT,
+        )
+        .pfn_user_callback(Some(debug_callback));
+
+    // Lives as long as the instance, which is never destroyed
+    unsafe { instance.create_debug_utils_messenger_ext(&create_info, None, None) }.result()?;
+
+    Ok(())
+}
+
+unsafe extern "system" fn debug_callback(
+    message_severity: vk::DebugUtilsMessageSeverityFlagBitsEXT,
+    _message_types: vk::DebugUtilsMessageTypeFlagsEXT,
+    p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
+    _p_user_data: *mut c_void,
+) -> vk::Bool32 {
+    let message = CStr::from_ptr((*p_callback_data).p_message).to_string_lossy();
+    match message_severity {
+        vk::DebugUtilsMessageSeverityFlagBitsEXT::ERROR_EXT => {
+            eprintln!("Vulkan error: {}", message)
+        }
+        vk::DebugUtilsMessageSeverityFlagBitsEXT::WARNING_EXT => {
+            eprintln!("Vulkan warning: {}", message)
+        }
+        _ => println!("Vulkan: {}", message),
+    }
+    vk::FALSE
+}
@@ -1,6 +1,21 @@
 use bytemuck::offset_of;
 use erupt::vk;
 
+/// Describes how a vertex type is laid out in a vertex buffer. Implement this for your own
+/// vertex types to use them with `shader_with_layout()` and `upload_mesh()`.
+pub trait VertexLayout: bytemuck::Pod {
+    /// Binding description for binding 0
+    fn binding_description() -> vk::VertexInputBindingDescriptionBuilder<'static> {
+        vk::VertexInputBindingDescriptionBuilder::new()
+            .binding(0)
+            .stride(std::mem::size_of::<Self>() as u32)
+            .input_rate(vk::VertexInputRate::VERTEX)
+    }
+
+    /// Attribute descriptions, one per shader input location
+    fn attribute_descriptions() -> Vec<vk::VertexInputAttributeDescriptionBuilder<'static>>;
+}
+
 /// Vertex suitable for use from vertex shaders
 #[repr(C)]
 #[derive(Debug, Default, Copy, Clone)]
@@ -18,10 +33,7 @@ impl Vertex {
     }
 
     pub fn binding_description() -> vk::VertexInputBindingDescriptionBuilder<'static> {
-        vk::VertexInputBindingDescriptionBuilder::new()
-            .binding(0)
-            .stride(std::mem::size_of::<Self>() as u32)
-            .input_rate(vk::VertexInputRate::VERTEX)
+        <Self as VertexLayout>::binding_description()
     }
 
     pub fn get_attribute_descriptions() -> [vk::VertexInputAttributeDescriptionBuilder<'static>; 2]
@@ -40,3 +52,163 @@ impl Vertex {
         ]
     }
 }
+
+impl VertexLayout for Vertex {
+    fn attribute_descriptions() -> Vec<vk::VertexInputAttributeDescriptionBuilder<'static>> {
+        Self::get_attribute_descriptions().to_vec()
+    }
+}
+
+/// Vertex with normals and texture coordinates, for lit and textured meshes. Position and color
+/// keep locations 0 and 1 so that shaders written for `Vertex` (such as the bundled unlit shaders)
+/// also work with this layout; the normal is at location 2 and the uv at location 3.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct VertexNT {
+    pub pos: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+    pub color: [f32; 3],
+}
+
+unsafe impl bytemuck::Zeroable for VertexNT {}
+unsafe impl bytemuck::Pod for VertexNT {}
+
+impl VertexNT {
+    pub fn new(pos: [f32; 3], normal: [f32; 3], uv: [f32; 2], color: [f32; 3]) -> Self {
+        Self {
+            pos,
+            normal,
+            uv,
+            color,
+        }
+    }
+}
+
+impl VertexLayout for VertexNT {
+    fn attribute_descriptions() -> Vec<vk::VertexInputAttributeDescriptionBuilder<'static>> {
+        vec![
+            vk::VertexInputAttributeDescriptionBuilder::new()
+                .binding(0)
+                .location(0)
+                .format(vk::Format::R32G32B32_SFLOAT)
+                .offset(offset_of!(Self, pos) as u32),
+            vk::VertexInputAttributeDescriptionBuilder::new()
+                .binding(0)
+                .location(1)
+                .format(vk::Format::R32G32B32_SFLOAT)
+                .offset(offset_of!(Self, color) as u32),
+            vk::VertexInputAttributeDescriptionBuilder::new()
+                .binding(0)
+                .location(2)
+                .format(vk::Format::R32G32B32_SFLOAT)
+                .offset(offset_of!(Self, normal) as u32),
+            vk::VertexInputAttributeDescriptionBuilder::new()
+                .binding(0)
+                .location(3)
+                .format(vk::Format::R32G32_SFLOAT)
+                .offset(offset_of!(Self, uv) as u32),
+        ]
+    }
+}
+
+/// `VertexNT` with an additional tangent, for normal mapping. The tangent's `w` component holds
+/// the handedness of the bitangent (`bitangent = cross(normal, tangent.xyz) * tangent.w`), as in
+/// glTF. The tangent is at location 4. Typically produced by `mesh::generate_tangents()`.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct VertexTangent {
+    pub pos: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+    pub color: [f32; 3],
+    pub tangent: [f32; 4],
+}
+
+unsafe impl bytemuck::Zeroable for VertexTangent {}
+unsafe impl bytemuck::Pod for VertexTangent {}
+
+impl VertexLayout for VertexTangent {
+    fn attribute_descriptions() -> Vec<vk::VertexInputAttributeDescriptionBuilder<'static>> {
+        vec![
+            vk::VertexInputAttributeDescriptionBuilder::new()
+                .binding(0)
+                .location(0)
+                .format(vk::Format::R32G32B32_SFLOAT)
+                .offset(offset_of!(Self, pos) as u32),
+            vk::VertexInputAttributeDescriptionBuilder::new()
+                .binding(0)
+                .location(1)
+                .format(vk::Format::R32G32B32_SFLOAT)
+                .offset(offset_of!(Self, color) as u32),
+            vk::VertexInputAttributeDescriptionBuilder::new()
+                .binding(0)
+                .location(2)
+                .format(vk::Format::R32G32B32_SFLOAT)
+                .offset(offset_of!(Self, normal) as u32),
+            vk::VertexInputAttributeDescriptionBuilder::new()
+                .binding(0)
+                .location(3)
+                .format(vk::Format::R32G32_SFLOAT)
+                .offset(offset_of!(Self, uv) as u32),
+            vk::VertexInputAttributeDescriptionBuilder::new()
+                .binding(0)
+                .location(4)
+                .format(vk::Format::R32G32B32A32_SFLOAT)
+                .offset(offset_of!(Self, tangent) as u32),
+        ]
+    }
+}
+
+/// `VertexNT` with joint indices and weights for skeletal animation. Joints are at location 5 and
+/// weights at location 6, matching `shaders/skinned.vert` (location 4 is left for the tangent).
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct VertexSkinned {
+    pub pos: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+    pub color: [f32; 3],
+    pub joints: [u32; 4],
+    pub weights: [f32; 4],
+}
+
+unsafe impl bytemuck::Zeroable for VertexSkinned {}
+unsafe impl bytemuck::Pod for VertexSkinned {}
+
+impl VertexLayout for VertexSkinned {
+    fn attribute_descriptions() -> Vec<vk::VertexInputAttributeDescriptionBuilder<'static>> {
+        vec![
+            vk::VertexInputAttributeDescriptionBuilder::new()
+                .binding(0)
+                .location(0)
+                .format(vk::Format::R32G32B32_SFLOAT)
+                .offset(offset_of!(Self, pos) as u32),
+            vk::VertexInputAttributeDescriptionBuilder::new()
+                .binding(0)
+                .location(1)
+                .format(vk::Format::R32G32B32_SFLOAT)
+                .offset(offset_of!(Self, color) as u32),
+            vk::VertexInputAttributeDescriptionBuilder::new()
+                .binding(0)
+                .location(2)
+                .format(vk::Format::R32G32B32_SFLOAT)
+                .offset(offset_of!(Self, normal) as u32),
+            vk::VertexInputAttributeDescriptionBuilder::new()
+                .binding(0)
+                .location(3)
+                .format(vk::Format::R32G32_SFLOAT)
+                .offset(offset_of!(Self, uv) as u32),
+            vk::VertexInputAttributeDescriptionBuilder::new()
+                .binding(0)
+                .location(5)
+                .format(vk::Format::R32G32B32A32_UINT)
+                .offset(offset_of!(Self, joints) as u32),
+            vk::VertexInputAttributeDescriptionBuilder::new()
+                .binding(0)
+                .location(6)
+                .format(vk::Format::R32G32B32A32_SFLOAT)
+                .offset(offset_of!(Self, weights) as u32),
+        ]
+    }
+}
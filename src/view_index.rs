@@ -0,0 +1,170 @@
+//! Running multiview shaders on devices without multiview. `gl_ViewIndex` is rewritten into a
+//! specialization constant (`shader::VIEW_INDEX_CONSTANT_ID`), so that each pipeline draws the
+//! eye of its `PipelineSettings::view_index` and the bundled shaders need no single-view
+//! variants.
+use crate::shader::VIEW_INDEX_CONSTANT_ID;
+use anyhow::{bail, ensure, Result};
+
+const OP_EXTENSION: u32 = 10;
+const OP_ENTRY_POINT: u32 = 15;
+const OP_CAPABILITY: u32 = 17;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_SPEC_CONSTANT: u32 = 50;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+
+const CAPABILITY_MULTIVIEW: u32 = 4439;
+const DECORATION_SPEC_ID: u32 = 1;
+const DECORATION_BUILT_IN: u32 = 11;
+const BUILT_IN_VIEW_INDEX: u32 = 4440;
+const STORAGE_CLASS_PRIVATE: u32 = 6;
+
+/// Entry points must list private variables from SPIR-V 1.4
+const VERSION_1_4: u32 = 0x0001_0400;
+
+/// Fail if the SPIR-V declares a specialization constant with `VIEW_INDEX_CONSTANT_ID`, which
+/// would receive the view index
+pub(crate) fn check_reserved_constants(code: &[u32]) -> Result<()> {
+    ensure!(code.len() >= 5, "SPIR-V is missing its header");
+    let reserved = instructions(code)?.any(|inst| {
+        inst[0] & 0xffff == OP_DECORATE
+            && inst.len() == 4
+            && inst[2] == DECORATION_SPEC_ID
+            && inst[3] == VIEW_INDEX_CONSTANT_ID
+    });
+    ensure!(
+        !reserved,
+        "Specialization constant ID {} is reserved for the view index",
+        VIEW_INDEX_CONSTANT_ID
+    );
+    Ok(())
+}
+
+/// Rewrite SPIR-V using the `MultiView` capability to read the view index from a specialization
+/// constant instead, defaulting to 0. Other modules are returned unchanged.
+pub(crate) fn replace_view_index(code: &[u32]) -> Result<Vec<u32>> {
+    ensure!(code.len() >= 5, "SPIR-V is missing its header");
+    let version = code[1];
+
+    // Ids of the added pointer type and spec constant
+    let private_pointer = code[3];
+    let constant = code[3] + 1;
+
+    // Find the ViewIndex builtin, if the module reads it
+    let view_index = instructions(code)?.find_map(|inst| {
+        (inst[0] & 0xffff == OP_DECORATE
+            && inst.len() == 4
+            && inst[2] == DECORATION_BUILT_IN
+            && inst[3] == BUILT_IN_VIEW_INDEX)
+            .then(|| inst[1])
+    });
+
+    let mut patched = code[..5].to_vec();
+    for inst in instructions(code)? {
+        let opcode = inst[0] & 0xffff;
+        match opcode {
+            OP_CAPABILITY if inst[1] == CAPABILITY_MULTIVIEW => continue,
+            OP_EXTENSION if literal_string(&inst[1..]) == "SPV_KHR_multiview" => continue,
+            _ => (),
+        }
+
+        let view_index = match view_index {
+            Some(view_index) => view_index,
+            None => {
+                patched.extend_from_slice(inst);
+                continue;
+            }
+        };
+
+        match opcode {
+            // The variable is no longer part of the interface before SPIR-V 1.4
+            OP_ENTRY_POINT if version < VERSION_1_4 => {
+                let name_words = literal_string(&inst[3..]).len() / 4 + 1;
+                let interface_start = 3 + name_words;
+                let kept = inst[..interface_start]
+                    .iter()
+                    .chain(
+                        inst[interface_start..]
+                            .iter()
+                            .filter(|&&id| id != view_index),
+                    )
+                    .copied()
+                    .collect::<Vec<_>>();
+                patched.push(((kept.len() as u32) << 16) | OP_ENTRY_POINT);
+                patched.extend_from_slice(&kept[1..]);
+            }
+            // The builtin becomes the spec constant's ID, and other decorations (e.g. Flat) are
+            // invalid on private variables
+            OP_DECORATE if inst[1] == view_index => {
+                if inst[2] == DECORATION_BUILT_IN {
+                    patched.extend_from_slice(&[
+                        (4 << 16) | OP_DECORATE,
+                        constant,
+                        DECORATION_SPEC_ID,
+                        VIEW_INDEX_CONSTANT_ID,
+                    ]);
+                }
+            }
+            // A private variable initialized from the spec constant, which is declared just
+            // before it with a pointer type of its own
+            OP_VARIABLE if inst[2] == view_index => {
+                let input_pointer = inst[1];
+                let int_type = instructions(code)?
+                    .find(|inst| inst[0] & 0xffff == OP_TYPE_POINTER && inst[1] == input_pointer)
+                    .map(|inst| inst[3]);
+                let int_type = match int_type {
+                    Some(int_type) => int_type,
+                    None => bail!("gl_ViewIndex has no pointer type"),
+                };
+                patched.extend_from_slice(&[
+                    (4 << 16) | OP_TYPE_POINTER,
+                    private_pointer,
+                    STORAGE_CLASS_PRIVATE,
+                    int_type,
+                    (4 << 16) | OP_SPEC_CONSTANT,
+                    int_type,
+                    constant,
+                    0,
+                    (5 << 16) | OP_VARIABLE,
+                    private_pointer,
+                    view_index,
+                    STORAGE_CLASS_PRIVATE,
+                    constant,
+                ]);
+            }
+            _ => patched.extend_from_slice(inst),
+        }
+    }
+
+    if view_index.is_some() {
+        patched[3] = constant + 1;
+    }
+    Ok(patched)
+}
+
+/// Instructions after the header, each starting with its word count and opcode
+fn instructions(code: &[u32]) -> Result<impl Iterator<Item = &[u32]>> {
+    let mut words = &code[5..];
+    let mut instructions = Vec::new();
+    while !words.is_empty() {
+        let count = (words[0] >> 16) as usize;
+        ensure!(
+            count > 0 && count <= words.len(),
+            "Malformed SPIR-V instruction"
+        );
+        let (inst, rest) = words.split_at(count);
+        instructions.push(inst);
+        words = rest;
+    }
+    Ok(instructions.into_iter())
+}
+
+/// A nul-terminated string packed into words, little end first
+fn literal_string(words: &[u32]) -> String {
+    let bytes = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .take_while(|&b| b != 0)
+        .collect::<Vec<u8>>();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
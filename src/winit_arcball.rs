@@ -1,7 +1,12 @@
 use crate::arcball::ArcBall;
-use winit::dpi::PhysicalPosition;
+use winit::dpi::LogicalPosition;
+use nalgebra::{Matrix4, Vector3};
+use std::f32::consts::TAU;
 use winit::event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent};
 
+/// Typical human interpupillary distance, in meters
+pub const DEFAULT_IPD: f32 = 0.064;
+
 pub struct WinitArcBall {
     inner: ArcBall,
     pan_sensitivity: f32,
@@ -9,6 +14,12 @@ pub struct WinitArcBall {
     last_mouse_position: Option<(f64, f64)>,
     width: u32,
     height: u32,
+    /// Physical pixels per logical pixel; mouse movement is measured in logical pixels
+    scale_factor: f64,
+    /// Eye separation when rendering the desktop stereo preview
+    stereo: Option<f32>,
+    /// Fixed width over height of the rendered region, rather than the window's
+    aspect_ratio: Option<f32>,
     left_is_clicked: bool,
     right_is_clicked: bool,
 }
@@ -24,13 +35,24 @@ impl WinitArcBall {
             right_is_clicked: false,
             width: 100,
             height: 100,
+            scale_factor: 1.0,
+            stereo: None,
+            aspect_ratio: None,
         }
     }
 
+    /// Set the window's size and scale factor, for use before any events have arrived
+    pub fn set_window(&mut self, window: &winit::window::Window) {
+        let size = window.inner_size();
+        self.width = size.width;
+        self.height = size.height;
+        self.scale_factor = window.scale_factor();
+    }
+
     pub fn handle_events(&mut self, event: &WindowEvent) {
         match event {
             WindowEvent::CursorMoved { position, .. } => {
-                let &PhysicalPosition { x, y } = position;
+                let LogicalPosition { x, y } = position.to_logical::<f64>(self.scale_factor);
                 if let Some((last_x, last_y)) = self.last_mouse_position {
                     let x_delta = (last_x - x) as f32;
                     let y_delta = (last_y - y) as f32;
@@ -47,18 +69,32 @@ impl WinitArcBall {
                 MouseButton::Right => self.right_is_clicked = *state == ElementState::Pressed,
                 _ => (),
             },
-            WindowEvent::MouseWheel { delta, .. } => {
-                if let MouseScrollDelta::LineDelta(_x, y) = delta {
-                    self.inner.distance += y * 0.3;
-                    if self.inner.distance <= 0.01 {
-                        self.inner.distance = 0.01;
-                    }
+            WindowEvent::MouseWheel {
+                delta: MouseScrollDelta::LineDelta(_x, y),
+                ..
+            } => {
+                self.inner.distance += y * 0.3;
+                if self.inner.distance <= 0.01 {
+                    self.inner.distance = 0.01;
                 }
             }
             WindowEvent::Resized(size) => {
                 self.width = size.width;
                 self.height = size.height;
             }
+            WindowEvent::ScaleFactorChanged {
+                scale_factor,
+                new_inner_size,
+            } => {
+                // Keep the cursor position in the same units
+                if let Some((x, y)) = &mut self.last_mouse_position {
+                    *x *= self.scale_factor / scale_factor;
+                    *y *= self.scale_factor / scale_factor;
+                }
+                self.scale_factor = *scale_factor;
+                self.width = new_inner_size.width;
+                self.height = new_inner_size.height;
+            }
             _ => (),
         }
     }
@@ -66,7 +102,7 @@ impl WinitArcBall {
     fn mouse_pivot(&mut self, delta_x: f32, delta_y: f32) {
         use std::f32::consts::FRAC_PI_2;
         self.inner.yaw -= delta_x * self.swivel_sensitivity;
-        self.inner.pitch -= delta_y * self.swivel_sensitivity.max(-FRAC_PI_2).min(FRAC_PI_2);
+        self.inner.pitch -= delta_y * self.swivel_sensitivity.clamp(-FRAC_PI_2, FRAC_PI_2);
     }
 
     fn mouse_pan(&mut self, delta_x: f32, delta_y: f32) {
@@ -74,13 +110,68 @@ impl WinitArcBall {
         let x_pan = ArcBall::up().cross(&eye).normalize();
         let y_pan = x_pan.cross(&eye).normalize();
         let rate = self.inner.distance * self.pan_sensitivity;
-        self.inner.pivot += x_pan * (delta_x as f32) * rate;
-        self.inner.pivot += y_pan * (delta_y as f32) * rate;
+        self.inner.pivot += x_pan * delta_x * rate;
+        self.inner.pivot += y_pan * delta_y * rate;
     }
 
     // TODO: Perspective and view matrices?
-    pub fn matrix(&self) -> nalgebra::Matrix4<f32> {
-        self.inner.matrix(self.width, self.height)
+    pub fn matrix(&self) -> Matrix4<f32> {
+        let camera = self.camera();
+        match self.aspect_ratio {
+            Some(aspect) => camera.perspective_with_aspect(aspect) * camera.view(),
+            None => camera.matrix(self.width, self.height),
+        }
+    }
+
+    /// The arcball as seen, which follows the bench mode's camera path while it runs
+    fn camera(&self) -> ArcBall {
+        let mut camera = self.inner;
+        if let Some(progress) = crate::bench::camera_path() {
+            camera.yaw += progress * TAU;
+        }
+        camera
+    }
+
+    /// Render two eyes `ipd` apart (in world units) for the desktop stereo preview, where each
+    /// eye covers half of the window. `None` returns to a single view.
+    pub fn set_stereo(&mut self, ipd: Option<f32>) {
+        self.stereo = ipd;
+    }
+
+    /// Use a reverse-Z projection, see `ArcBall::reverse_z`
+    pub fn set_reverse_z(&mut self, reverse_z: bool) {
+        self.inner.reverse_z = reverse_z;
+    }
+
+    /// Project for a fixed width over height, such as with `Settings::aspect_ratio` of the
+    /// starter kit. `None` follows the window's shape.
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: Option<f32>) {
+        self.aspect_ratio = aspect_ratio;
+    }
+
+    /// Near and far clipping planes, see `ArcBall::clipping`
+    pub fn set_clipping(&mut self, clipping: (f32, f32)) {
+        self.inner.clipping = clipping;
+    }
+
+    /// Camera matrices for each view. Outside of stereo, the second is zeroed.
+    pub fn view_matrices(&self) -> [Matrix4<f32>; 2] {
+        match self.stereo {
+            None => [self.matrix(), Matrix4::zeros()],
+            Some(ipd) => {
+                let camera = self.camera();
+                let perspective = match self.aspect_ratio {
+                    Some(aspect) => camera.perspective_with_aspect(aspect),
+                    None => camera.perspective((self.width / 2).max(1), self.height),
+                };
+                let view = camera.view();
+                let eye = |offset: f32| {
+                    perspective * Matrix4::new_translation(&Vector3::new(offset, 0., 0.)) * view
+                };
+                // The left eye sits at -ipd/2 along the camera's x axis
+                [eye(ipd / 2.), eye(-ipd / 2.)]
+            }
+        }
     }
 }
 
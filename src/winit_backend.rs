@@ -1,15 +1,24 @@
-use crate::hardware_query::HardwareSelection;
+use crate::hardware_query::{
+    multiview_supported, pick_compute_queue_family, pick_depth_format, pick_present_mode,
+    pick_transfer_queue_family, queue_create_infos, HardwareSelection,
+};
+use crate::defaults::ACQUIRE_ATTEMPTS;
+use crate::synchronization::{is_device_lost, timeout_nanos, AcquireError, SyncError};
 use crate::{
-    app_info::{engine_version, AppInfo},
+    app_info::{AppInfo, BackgroundPolicy},
+    sync2, validation,
     mainloop::{Frame, Platform, PlatformEvent, SyncMainLoop},
-    defaults::{COLOR_FORMAT, COLOR_SPACE},
+    profiling::{self, FrameEvent},
+    resources::ResourceTracker,
+    deletion_queue::DeletionQueue,
+    shader_cache::ShaderModuleCache,
+    core::{ComputeQueue, QueueLocks},
     Core, SharedCore,
 };
 use anyhow::{Context, Result};
 use erupt::{
-    cstr,
     extensions::{
-        khr_surface::{self, PresentModeKHR, SurfaceKHR},
+        khr_surface::{self, PresentModeKHR, SurfaceFormatKHR, SurfaceKHR},
         khr_swapchain::{self, SwapchainKHR},
     },
     utils::surface,
@@ -18,27 +27,45 @@ use erupt::{
 use gpu_alloc::GpuAllocator;
 use std::ffi::CString;
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use winit::{
+    dpi::PhysicalSize,
     event::{Event, WindowEvent},
-    event_loop::EventLoop,
+    event_loop::{ControlFlow, EventLoop},
     window::{Window, WindowBuilder},
 };
 
-pub fn launch<M: SyncMainLoop<T> + 'static, T>(info: AppInfo, userdata: T) -> Result<()> {
+pub fn launch<M: SyncMainLoop<D> + 'static, D>(info: AppInfo, data: D) -> Result<()> {
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new()
         .with_title(&info.name)
         .build(&event_loop)
         .context("Failed to create window")?;
 
-    let (core, surface, present_mode) = build_core(info, &window)?;
-    begin_loop::<M, T>(core, event_loop, window, surface, present_mode, userdata)
+    let pacing = FramePacing::new(&info);
+    let (core, surface) = build_core(info, &window)?;
+    begin_loop::<M, D>(core, event_loop, window, surface, pacing, data)
+}
+
+/// Window surface, and the swapchain settings negotiated for it
+pub struct SurfaceSettings {
+    pub surface: SurfaceKHR,
+    pub present_mode: PresentModeKHR,
+    pub format: SurfaceFormatKHR,
+    /// Requested number of images, if not the default
+    pub image_count: Option<u32>,
+    /// How long each attempt to acquire an image may wait, see `AppInfo::acquire_timeout()`
+    pub acquire_timeout: Option<Duration>,
 }
 
 // TODO: Swap this out for better behaviour! (At least sorta exit gracefully...)
 /// Print and exit if `r` is an error.
-fn res<T>(r: Result<T>) -> T {
+pub(crate) fn res<T>(r: Result<T>) -> T {
     match r {
+        Err(e) if is_device_lost(&e) => {
+            eprintln!("Fatal GPU error, exiting: {:#}", e);
+            std::process::exit(-1)
+        }
         Err(e) => {
             eprintln!("Error: {}", e);
             std::process::exit(-1)
@@ -47,73 +74,228 @@ fn res<T>(r: Result<T>) -> T {
     }
 }
 
-fn begin_loop<M: SyncMainLoop<T> + 'static, T>(
+/// When the windowed backends draw frames, following `AppInfo::redraw_on_demand()` and the
+/// `BackgroundPolicy`
+pub(crate) struct FramePacing {
+    redraw_on_demand: bool,
+    background_policy: BackgroundPolicy,
+    focused: bool,
+    last_frame: Instant,
+    /// Whether the control flow was changed for the background policy, and should be restored
+    /// once focused
+    throttled: bool,
+}
+
+impl FramePacing {
+    pub fn new(info: &AppInfo) -> Self {
+        Self {
+            redraw_on_demand: info.redraw_on_demand,
+            background_policy: info.background_policy,
+            focused: true,
+            last_frame: Instant::now(),
+            throttled: false,
+        }
+    }
+
+    /// Track window focus
+    pub fn event(&mut self, event: &Event<()>) {
+        if let Event::WindowEvent {
+            event: WindowEvent::Focused(focused),
+            ..
+        } = event
+        {
+            self.focused = *focused;
+        }
+    }
+
+    /// A frame was drawn
+    pub fn frame_drawn(&mut self) {
+        self.last_frame = Instant::now();
+    }
+
+    /// Request the next frame (or not), and set how long to wait for events. Call on
+    /// `MainEventsCleared`.
+    pub fn events_cleared(&mut self, window: &Window, control_flow: &mut ControlFlow) {
+        if *control_flow == ControlFlow::Exit {
+            return;
+        }
+
+        let policy = match self.focused {
+            true => BackgroundPolicy::KeepRendering,
+            false => self.background_policy,
+        };
+
+        match policy {
+            BackgroundPolicy::KeepRendering if self.redraw_on_demand => {
+                *control_flow = ControlFlow::Wait;
+            }
+            BackgroundPolicy::KeepRendering => {
+                if self.throttled {
+                    *control_flow = ControlFlow::Poll;
+                }
+                window.request_redraw();
+            }
+            BackgroundPolicy::Throttle(fps) if !self.redraw_on_demand => {
+                let interval = Duration::from_secs_f32(1.0 / fps.max(f32::EPSILON));
+                let now = Instant::now();
+                let next = self.last_frame + interval;
+                *control_flow = if now >= next {
+                    window.request_redraw();
+                    ControlFlow::WaitUntil(now + interval)
+                } else {
+                    ControlFlow::WaitUntil(next)
+                };
+            }
+            BackgroundPolicy::Throttle(_) | BackgroundPolicy::Pause => {
+                *control_flow = ControlFlow::Wait;
+            }
+        }
+        self.throttled = policy != BackgroundPolicy::KeepRendering;
+    }
+}
+
+fn begin_loop<M: SyncMainLoop<D> + 'static, D>(
     core: Core,
     event_loop: EventLoop<()>,
     window: Window,
-    surface: SurfaceKHR,
-    present_mode: PresentModeKHR,
-    userdata: T,
+    surface: SurfaceSettings,
+    mut pacing: FramePacing,
+    data: D,
 ) -> Result<()> {
     let core = SharedCore::new(core);
+    let color_format = surface.format.format;
 
     let mut app = M::new(
         &core,
         Platform::Winit {
             window: &window,
             control_flow: &mut Default::default(),
+            color_format,
         },
-        userdata,
+        data,
     )?;
 
-    let (mut swapchain, (images, extent)) =
-        res(Swapchain::new(core.clone(), surface, present_mode));
+    let (mut swapchain, (images, extent)) = res(Swapchain::new(
+        core.clone(),
+        surface,
+        window_extent(window.inner_size()),
+    ));
     res(app.swapchain_resize(images, extent));
 
     let mut frame_num = 0;
     let mut time = std::time::Instant::now();
+    let mut suspended = false;
     event_loop.run(move |event, _, control_flow| {
+        pacing.event(&event);
+
+        // Lifecycle events are delivered as their own PlatformEvents
+        match event {
+            Event::Suspended => {
+                suspended = true;
+                res(unsafe { core.device.device_wait_idle() }
+                    .result()
+                    .map_err(Into::into));
+                res(app.event(
+                    PlatformEvent::Suspended,
+                    &core,
+                    Platform::Winit {
+                        window: &window,
+                        control_flow,
+                        color_format,
+                    },
+                ));
+                return;
+            }
+            Event::Resumed => {
+                if suspended {
+                    // The surface may have been destroyed while suspended
+                    let (images, extent) = res(swapchain.recreate_surface(&window));
+                    res(app.swapchain_resize(images, extent));
+                    suspended = false;
+                    res(app.event(
+                        PlatformEvent::Resumed,
+                        &core,
+                        Platform::Winit {
+                            window: &window,
+                            control_flow,
+                            color_format,
+                        },
+                    ));
+                }
+                return;
+            }
+            _ => (),
+        }
+
         res(app.event(
             PlatformEvent::Winit(&event),
             &core,
             Platform::Winit {
                 window: &window,
                 control_flow,
+                color_format,
             },
         ));
 
         match event {
             Event::WindowEvent {
-                event: WindowEvent::Resized(_),
+                event: WindowEvent::Resized(_) | WindowEvent::ScaleFactorChanged { .. },
                 ..
-            } => {
-                let (images, extent) = res(swapchain.rebuild_swapchain());
-                res(app.swapchain_resize(images, extent));
+            } if !suspended => {
+                let resize = res(swapchain.resize(window_extent(window.inner_size())));
+                if let Some((images, extent)) = resize {
+                    res(app.swapchain_resize(images, extent));
+                }
             }
             Event::MainEventsCleared => {
-                window.request_redraw();
+                pacing.events_cleared(&window, control_flow);
             }
-            Event::RedrawRequested(_) => {
+            Event::RedrawRequested(_) if !suspended && !swapchain.is_minimized() => {
                 // Prepare inputs
                 let (image_available, render_finished) = app.winit_sync();
-                let (swapchain_index, resize) = res(swapchain.frame(image_available));
+                let acquired = profiling::timed(FrameEvent::Acquire, || {
+                    acquire(
+                        &mut swapchain,
+                        &mut app,
+                        &core,
+                        &window,
+                        control_flow,
+                        image_available,
+                    )
+                });
+                let swapchain_index = match acquired {
+                    Some(swapchain_index) => swapchain_index,
+                    None => return,
+                };
                 let frame = Frame { swapchain_index };
-                if let Some((images, extent)) = resize {
-                    res(app.swapchain_resize(images, extent));
-                }
 
                 // Run app's frame method
+                #[cfg(feature = "renderdoc")]
+                crate::capture::begin_frame();
                 res(app.frame(
                     frame,
                     &core,
                     Platform::Winit {
                         window: &window,
                         control_flow,
+                        color_format,
                     },
                 ));
 
                 // Present
-                res(swapchain.queue_present(swapchain_index, render_finished));
+                let out_of_date = res(profiling::timed(FrameEvent::Present, || {
+                    swapchain.queue_present(swapchain_index, render_finished)
+                }));
+                #[cfg(feature = "renderdoc")]
+                crate::capture::end_frame();
+                res(core.collect_garbage());
+                pacing.frame_drawn();
+                if out_of_date {
+                    let resize = res(swapchain.resize(window_extent(window.inner_size())));
+                    if let Some((images, extent)) = resize {
+                        res(app.swapchain_resize(images, extent));
+                    }
+                }
 
                 // FPS counter
                 const N_FRAMES: u32 = 20;
@@ -131,62 +313,155 @@ fn begin_loop<M: SyncMainLoop<T> + 'static, T>(
     });
 }
 
-pub fn build_core(info: AppInfo, window: &Window) -> Result<(Core, SurfaceKHR, PresentModeKHR)> {
+/// Acquire the next swapchain image, passing any new images to the app. If none can be acquired,
+/// deliver the `AcquireError` to the app, recreate a lost surface and request another redraw,
+/// returning None to skip the frame.
+pub(crate) fn acquire<M: SyncMainLoop<D>, D>(
+    swapchain: &mut Swapchain,
+    app: &mut M,
+    core: &SharedCore,
+    window: &Window,
+    control_flow: &mut ControlFlow,
+    image_available: vk::Semaphore,
+) -> Option<u32> {
+    let (swapchain_index, resize) = match swapchain.frame(image_available) {
+        Ok(acquired) => acquired,
+        Err(e) => match e.downcast_ref::<AcquireError>() {
+            Some(&error) => {
+                eprintln!("{}, skipping frame", error);
+                let color_format = swapchain.surface.format.format;
+                res(app.event(
+                    PlatformEvent::AcquireFailed(error),
+                    core,
+                    Platform::Winit {
+                        window,
+                        control_flow,
+                        color_format,
+                    },
+                ));
+                if error == AcquireError::SurfaceLost {
+                    let (images, extent) = res(swapchain.recreate_surface(window));
+                    res(app.swapchain_resize(images, extent));
+                }
+                window.request_redraw();
+                return None;
+            }
+            None => res(Err(e)),
+        },
+    };
+    if let Some((images, extent)) = resize {
+        res(app.swapchain_resize(images, extent));
+    }
+    Some(swapchain_index)
+}
+
+pub fn build_core(info: AppInfo, window: &Window) -> Result<(Core, SurfaceSettings)> {
     // Entry
     let entry = EntryLoader::new()?;
 
     // Instance
-    let app_name = CString::new(info.name)?;
-    let engine_name = CString::new(crate::ENGINE_NAME)?;
+    let app_name = CString::new(info.name.as_str())?;
+    let engine_name = CString::new(info.engine_name.as_str())?;
     let app_info = vk::ApplicationInfoBuilder::new()
         .application_name(&app_name)
         .application_version(info.version)
         .engine_name(&engine_name)
-        .engine_version(engine_version())
+        .engine_version(info.engine_version)
         .api_version(info.api_version);
 
     // Instance and device layers and extensions
     let mut instance_layers = Vec::new();
     let mut instance_extensions = surface::enumerate_required_extensions(window).result()?;
     let mut device_layers = Vec::new();
-    let device_extensions = vec![khr_swapchain::KHR_SWAPCHAIN_EXTENSION_NAME];
+    let mut device_extensions = vec![khr_swapchain::KHR_SWAPCHAIN_EXTENSION_NAME];
 
+    validation::instance_setup(&info, &mut instance_layers, &mut instance_extensions)?;
     if info.validation {
-        const LAYER_KHRONOS_VALIDATION: *const i8 = cstr!("VK_LAYER_KHRONOS_validation");
-        instance_extensions
-            .push(erupt::extensions::ext_debug_utils::EXT_DEBUG_UTILS_EXTENSION_NAME);
-        instance_layers.push(LAYER_KHRONOS_VALIDATION);
-        device_layers.push(LAYER_KHRONOS_VALIDATION);
+        device_layers.push(validation::LAYER_KHRONOS_VALIDATION);
     }
 
     // Instance creation
-    let create_info = vk::InstanceCreateInfoBuilder::new()
+    let validation_enables = validation::feature_enables(&info);
+    let mut validation_features = vk::ValidationFeaturesEXTBuilder::new()
+        .enabled_validation_features(&validation_enables)
+        .build();
+
+    let mut create_info = vk::InstanceCreateInfoBuilder::new()
         .application_info(&app_info)
         .enabled_extension_names(&instance_extensions)
         .enabled_layer_names(&instance_layers);
 
-    let mut instance = InstanceLoader::new(&entry, &create_info, None)?;
+    if !validation_enables.is_empty() {
+        create_info.p_next = &mut validation_features as *mut _ as _;
+    }
+
+    let instance = InstanceLoader::new(&entry, &create_info, None)?;
+    validation::create_messenger(&info, &instance)?;
 
     // Surface
-    let surface = unsafe { surface::create_surface(&mut instance, window, None) }.result()?;
+    let surface = unsafe { surface::create_surface(&instance, window, None) }.result()?;
 
     // Hardware selection
-    let hardware = HardwareSelection::query(&instance, surface, &device_extensions)?;
+    device_extensions.extend(info.device_preference.extension_ptrs());
+    let hardware = HardwareSelection::query(
+        &instance,
+        surface,
+        &device_extensions,
+        &info.device_preference,
+        info.srgb_swapchain,
+    )?;
+    validation::device_extensions(&info, &instance, hardware.physical_device, &mut device_extensions)?;
+    let synchronization2 =
+        sync2::device_extensions(&info, &instance, hardware.physical_device, &mut device_extensions)?;
 
     // Create logical device and queues
-    let create_info = [vk::DeviceQueueCreateInfoBuilder::new()
-        .queue_family_index(hardware.queue_family)
-        .queue_priorities(&[1.0])];
-
-    let physical_device_features = vk::PhysicalDeviceFeaturesBuilder::new();
-    let create_info = vk::DeviceCreateInfoBuilder::new()
+    let compute_queue_family = info
+        .async_compute
+        .then(|| pick_compute_queue_family(&instance, hardware.physical_device))
+        .flatten();
+    let transfer_queue_family = info
+        .transfer_queue
+        .then(|| pick_transfer_queue_family(&instance, hardware.physical_device))
+        .flatten();
+    let create_info = queue_create_infos(
+        hardware.queue_family,
+        compute_queue_family,
+        transfer_queue_family,
+    );
+
+    let device_features = info
+        .device_preference
+        .enabled_features(&instance, hardware.physical_device);
+    let mut create_info = vk::DeviceCreateInfoBuilder::new()
         .queue_create_infos(&create_info)
-        .enabled_features(&physical_device_features)
+        .enabled_features(&device_features)
         .enabled_extension_names(&device_extensions)
-        .enabled_layer_names(&device_layers);
+        .enabled_layer_names(&device_layers)
+        .build();
+
+    // Render passes use multiview where supported, if only with a single view outside of VR
+    let multiview = multiview_supported(&instance, hardware.physical_device);
+    let mut multiview_features = erupt::vk1_1::PhysicalDeviceMultiviewFeaturesBuilder::new()
+        .multiview(multiview)
+        .build();
+
+    let mut sync2_features = sync2::features();
+    if synchronization2 {
+        multiview_features.p_next = &mut sync2_features as *mut _ as _;
+    }
+
+    create_info.p_next = &mut multiview_features as *mut _ as _;
 
     let device = DeviceLoader::new(&instance, hardware.physical_device, &create_info, None)?;
     let queue = unsafe { device.get_device_queue(hardware.queue_family, 0, None) };
+    let compute_queue = compute_queue_family.map(|family| ComputeQueue {
+        queue: unsafe { device.get_device_queue(family, 0, None) },
+        family,
+    });
+    let transfer_queue = transfer_queue_family.map(|family| ComputeQueue {
+        queue: unsafe { device.get_device_queue(family, 0, None) },
+        family,
+    });
 
     let device_props =
         unsafe { gpu_alloc_erupt::device_properties(&instance, hardware.physical_device)? };
@@ -196,67 +471,179 @@ pub fn build_core(info: AppInfo, window: &Window) -> Result<(Core, SurfaceKHR, P
     ));
     let device_properties =
         unsafe { instance.get_physical_device_properties(hardware.physical_device, None) };
+    let depth_format = pick_depth_format(&instance, hardware.physical_device)?;
 
     let core = Core {
         physical_device: hardware.physical_device,
         device_properties,
+        device_features,
+        depth_format,
+        multiview,
         queue_family: hardware.queue_family,
         queue,
+        compute_queue,
+        transfer_queue,
+        queue_locks: QueueLocks::default(),
         device,
         instance,
         allocator,
         entry,
+        resources: ResourceTracker::new(info.leak_warnings),
+        deletion_queue: DeletionQueue::new(),
+        shader_modules: ShaderModuleCache::new(),
     };
 
-    Ok((core, surface, hardware.present_mode))
+    let present_modes = unsafe {
+        core.instance.get_physical_device_surface_present_modes_khr(
+            core.physical_device,
+            surface,
+            None,
+        )
+    }
+    .result()?;
+
+    let surface = SurfaceSettings {
+        surface,
+        present_mode: pick_present_mode(&present_modes, info.vsync),
+        format: hardware.format,
+        image_count: info.swapchain_images,
+        acquire_timeout: info.acquire_timeout,
+    };
+
+    Ok((core, surface))
 }
 
-struct Swapchain {
+/// Swapchain over a `SurfaceSettings`, also used by the display backend
+pub(crate) struct Swapchain {
     inner: SwapchainKHR,
-    surface: SurfaceKHR,
+    surface: SurfaceSettings,
+    /// Size of the window, used when the surface leaves the extent to the swapchain
+    window_extent: vk::Extent2D,
+    /// The surface has no area, so no swapchain can be created and no frames drawn
+    minimized: bool,
+    /// Images of a swapchain rebuilt by a failed `frame()`, handed over by the next one
+    pending_resize: Option<SwapchainImages>,
     core: SharedCore,
-    present_mode: PresentModeKHR,
 }
 
-type SwapchainImages = (Vec<vk::Image>, vk::Extent2D);
+pub(crate) type SwapchainImages = (Vec<vk::Image>, vk::Extent2D);
+
+/// Swapchain extent of a window with the given size
+pub(crate) fn window_extent(size: PhysicalSize<u32>) -> vk::Extent2D {
+    vk::Extent2D {
+        width: size.width,
+        height: size.height,
+    }
+}
 
 impl Swapchain {
     pub fn new(
         core: SharedCore,
-        surface: SurfaceKHR,
-        present_mode: PresentModeKHR,
+        surface: SurfaceSettings,
+        window_extent: vk::Extent2D,
     ) -> Result<(Self, SwapchainImages)> {
-        let (inner, images) = Self::create_swapchain(&core, surface, present_mode, None)?;
+        let (inner, images) = Self::create_swapchain(&core, &surface, window_extent, None)?;
         let instance = Self {
             inner,
             surface,
+            window_extent,
+            minimized: false,
+            pending_resize: None,
             core,
-            present_mode,
         };
         Ok((instance, images))
     }
 
+    /// Recreate the swapchain for the window's new size, as soon as it's resized rather than
+    /// once presentation reports it out of date. Returns None if the window is minimized, in
+    /// which case no frames may be drawn until it is resized again.
+    pub fn resize(&mut self, window_extent: vk::Extent2D) -> Result<Option<SwapchainImages>> {
+        self.window_extent = window_extent;
+        let surface_caps = Self::surface_caps(&self.core, &self.surface)?;
+        let extent = Self::choose_extent(&surface_caps, window_extent);
+        self.minimized = extent.width == 0 || extent.height == 0;
+        if self.minimized {
+            return Ok(None);
+        }
+        self.rebuild_swapchain().map(Some)
+    }
+
+    /// Whether the window is minimized, see `resize()`
+    pub fn is_minimized(&self) -> bool {
+        self.minimized
+    }
+
+    /// Acquire the next image, signalled by `image_available`, along with the new images if the
+    /// swapchain was rebuilt. An out of date swapchain is rebuilt once. Attempts which time out
+    /// rebuild it too, failing with an `AcquireError` after `ACQUIRE_ATTEMPTS`.
     pub fn frame(
         &mut self,
         image_available: vk::Semaphore,
     ) -> Result<(u32, Option<SwapchainImages>)> {
-        let ret = self.acquire_image(image_available);
-
-        // Early return and invalidate swapchain
-        if ret.raw == vk::Result::ERROR_OUT_OF_DATE_KHR {
-            let resize = self.rebuild_swapchain()?;
-            let img_idx = self.acquire_image(image_available).result()?; // Fail if we already tried once
-            Ok((img_idx, Some(resize)))
-        } else {
-            Ok((ret.result()?, None))
+        let mut resize = self.pending_resize.take();
+        let mut rebuilt_out_of_date = false;
+        let mut attempts = 0;
+        let error = loop {
+            let ret = self.acquire_image(image_available);
+            match ret.raw {
+                vk::Result::SUCCESS | vk::Result::SUBOPTIMAL_KHR => {
+                    return Ok((ret.unwrap(), resize));
+                }
+                // Fail if we already tried once
+                vk::Result::ERROR_OUT_OF_DATE_KHR if !rebuilt_out_of_date => {
+                    rebuilt_out_of_date = true;
+                    resize = Some(self.rebuild_swapchain()?);
+                }
+                vk::Result::TIMEOUT | vk::Result::NOT_READY => {
+                    attempts += 1;
+                    if attempts == ACQUIRE_ATTEMPTS {
+                        break AcquireError::Timeout {
+                            attempts,
+                            timeout: self.surface.acquire_timeout.unwrap_or(Duration::MAX),
+                        };
+                    }
+                    resize = Some(self.rebuild_swapchain()?);
+                }
+                vk::Result::ERROR_SURFACE_LOST_KHR => break AcquireError::SurfaceLost,
+                vk::Result::ERROR_DEVICE_LOST => return Err(SyncError::DeviceLost.into()),
+                other => return Err(other.into()),
+            }
+        };
+
+        // The app still needs the images of any rebuilt swapchain
+        self.pending_resize = resize;
+        Err(error.into())
+    }
+
+    /// Replace the surface with a new one for `window`, for platforms which destroy the surface
+    /// when the app is suspended
+    pub fn recreate_surface(&mut self, window: &Window) -> Result<SwapchainImages> {
+        unsafe {
+            self.core.device.device_wait_idle().result()?;
         }
+        self.free_swapchain();
+        self.inner = SwapchainKHR::null();
+        self.pending_resize = None;
+        unsafe {
+            self.core
+                .instance
+                .destroy_surface_khr(Some(self.surface.surface), None);
+            self.surface.surface = SurfaceKHR::null();
+            self.surface.surface =
+                surface::create_surface(&self.core.instance, window, None).result()?;
+        }
+
+        let (inner, images) =
+            Self::create_swapchain(&self.core, &self.surface, self.window_extent, None)?;
+        self.inner = inner;
+        Ok(images)
     }
 
     fn acquire_image(&mut self, image_available: vk::Semaphore) -> erupt::utils::VulkanResult<u32> {
         unsafe {
             self.core.device.acquire_next_image_khr(
                 self.inner,
-                u64::MAX,
+                timeout_nanos(self.surface.acquire_timeout),
                 Some(image_available),
                 None,
                 None,
@@ -272,39 +659,77 @@ impl Swapchain {
         }
     }
 
-    fn create_swapchain(
+    fn surface_caps(
         core: &Core,
-        surface: SurfaceKHR,
-        present_mode: PresentModeKHR,
-        old_swapchain: Option<SwapchainKHR>,
-    ) -> Result<(SwapchainKHR, SwapchainImages)> {
+        surface: &SurfaceSettings,
+    ) -> Result<khr_surface::SurfaceCapabilitiesKHR> {
         let surface_caps = unsafe {
             core.instance.get_physical_device_surface_capabilities_khr(
                 core.physical_device,
-                surface,
+                surface.surface,
                 None,
             )
         }
         .result()?;
+        Ok(surface_caps)
+    }
+
+    /// The surface's current extent, or the window's if the surface leaves it to the swapchain
+    /// (as on Wayland), clamped to what the surface supports
+    fn choose_extent(
+        surface_caps: &khr_surface::SurfaceCapabilitiesKHR,
+        window_extent: vk::Extent2D,
+    ) -> vk::Extent2D {
+        if surface_caps.current_extent.width != u32::MAX {
+            return surface_caps.current_extent;
+        }
+
+        let (min, max) = (surface_caps.min_image_extent, surface_caps.max_image_extent);
+        vk::Extent2D {
+            width: window_extent.width.clamp(min.width, max.width),
+            height: window_extent.height.clamp(min.height, max.height),
+        }
+    }
 
-        let mut image_count = surface_caps.min_image_count + 1;
+    fn create_swapchain(
+        core: &Core,
+        surface: &SurfaceSettings,
+        window_extent: vk::Extent2D,
+        old_swapchain: Option<SwapchainKHR>,
+    ) -> Result<(SwapchainKHR, SwapchainImages)> {
+        let surface_caps = Self::surface_caps(core, surface)?;
+        let extent = Self::choose_extent(&surface_caps, window_extent);
+
+        // Allow copying out of the images where possible, such as for screenshots
+        let image_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+            | (surface_caps.supported_usage_flags & vk::ImageUsageFlags::TRANSFER_SRC);
+
+        let requested_image_count = surface.image_count;
+        let requested = requested_image_count.unwrap_or(surface_caps.min_image_count + 1);
+        let mut image_count = requested.max(surface_caps.min_image_count);
         if surface_caps.max_image_count > 0 && image_count > surface_caps.max_image_count {
             image_count = surface_caps.max_image_count;
         }
+        if requested_image_count.is_some() && image_count != requested && old_swapchain.is_none() {
+            eprintln!(
+                "Requested {} swapchain images, but the surface supports {}..={}; using {}",
+                requested, surface_caps.min_image_count, surface_caps.max_image_count, image_count
+            );
+        }
 
         // Build the actual swapchain
         let create_info = khr_swapchain::SwapchainCreateInfoKHRBuilder::new()
-            .surface(surface)
+            .surface(surface.surface)
             .min_image_count(image_count)
-            .image_format(COLOR_FORMAT)
-            .image_color_space(COLOR_SPACE)
-            .image_extent(surface_caps.current_extent)
+            .image_format(surface.format.format)
+            .image_color_space(surface.format.color_space)
+            .image_extent(extent)
             .image_array_layers(1)
-            .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+            .image_usage(image_usage)
             .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
             .pre_transform(surface_caps.current_transform)
             .composite_alpha(khr_surface::CompositeAlphaFlagBitsKHR::OPAQUE_KHR)
-            .present_mode(present_mode)
+            .present_mode(surface.present_mode)
             .clipped(true)
             .old_swapchain(match old_swapchain {
                 Some(s) => s,
@@ -317,10 +742,12 @@ impl Swapchain {
         let swapchain_images =
             unsafe { core.device.get_swapchain_images_khr(swapchain, None) }.result()?;
 
-        Ok((swapchain, (swapchain_images, surface_caps.current_extent)))
+        Ok((swapchain, (swapchain_images, extent)))
     }
 
-    fn queue_present(&mut self, image_index: u32, render_finished: vk::Semaphore) -> Result<()> {
+    /// Present the image. Returns true if the swapchain no longer matches the surface, and
+    /// should be recreated with `resize()`.
+    pub fn queue_present(&mut self, image_index: u32, render_finished: vk::Semaphore) -> Result<bool> {
         // Present to swapchain
         let swapchains = [self.inner];
         let image_indices = [image_index];
@@ -330,25 +757,32 @@ impl Swapchain {
             .swapchains(&swapchains)
             .image_indices(&image_indices);
 
-        // TODO: Handle queue result?
-        let _ = unsafe {
+        let res = unsafe {
+            let _queue = self.core.lock_queue(self.core.queue);
             self.core
                 .device
                 .queue_present_khr(self.core.queue, &present_info)
         };
 
-        Ok(())
+        match res.raw {
+            vk::Result::ERROR_OUT_OF_DATE_KHR | vk::Result::SUBOPTIMAL_KHR => Ok(true),
+            _ => res.result().map(|_| false).map_err(Into::into),
+        }
     }
 
-    fn rebuild_swapchain(&mut self) -> Result<SwapchainImages> {
+    pub fn rebuild_swapchain(&mut self) -> Result<SwapchainImages> {
         let (swapchain, resize) = Self::create_swapchain(
             &self.core,
-            self.surface,
-            self.present_mode,
+            &self.surface,
+            self.window_extent,
             Some(self.inner),
         )?;
+
+        // Frames in flight may still be presenting the old swapchain's images
+        unsafe { self.core.device.device_wait_idle() }.result()?;
         self.free_swapchain();
         self.inner = swapchain;
+        self.pending_resize = None;
         Ok(resize)
     }
 }
@@ -359,7 +793,7 @@ impl Drop for Swapchain {
         unsafe {
             self.core
                 .instance
-                .destroy_surface_khr(Some(self.surface), None);
+                .destroy_surface_khr(Some(self.surface.surface), None);
         }
     }
 }
@@ -0,0 +1,488 @@
+//! Flat UI panels placed in the world. The app draws the panel's contents into an offscreen
+//! target with whatever it likes (text, an immediate mode GUI), and the panel shows it: as a
+//! textured quad in the scene on the desktop, or as an OpenXR quad layer in VR, which the
+//! compositor samples directly for sharper text.
+//!
+//! ```ignore
+//! let mut panel = WorldPanel::new(core.clone(), WorldPanelSettings::default(), render_pass, settings)?;
+//! panel.pose = Isometry3::translation(0.0, 1.5, -1.0);
+//!
+//! // Each frame, outside of any render pass
+//! panel.begin(command_buffer, [0.1, 0.1, 0.1, 0.9]);
+//! // Draw the UI with pipelines built against panel.render_pass()
+//! panel.end(command_buffer, &platform)?;
+//!
+//! // Later, in the scene render pass
+//! panel.draw(command_buffer, &platform, &camera);
+//! ```
+//!
+//! Quad layers are composited over the whole scene, so in VR the panel is never hidden behind
+//! scene geometry.
+use crate::mainloop::Platform;
+use crate::memory::{ManagedBuffer, UsageFlags};
+use crate::render_target::{RenderTarget, RenderTargetSettings};
+use crate::shader::{shader_with_settings, PipelineSettings};
+use crate::vertex::Vertex;
+use crate::SharedCore;
+use anyhow::Result;
+use erupt::vk;
+use nalgebra::{Isometry3, Matrix4, Vector3};
+
+#[cfg(feature = "openxr")]
+use crate::barriers::{self, image_barrier};
+#[cfg(feature = "openxr")]
+use openxr as xr;
+#[cfg(feature = "openxr")]
+use std::sync::{Arc, Mutex};
+
+/// Bundled vertex shader drawing the panel quad, transformed by the `mat4 transform[2]` push
+/// constant
+pub const WORLD_PANEL_VERT: &[u8] = include_bytes!("../shaders/world_panel.vert.spv");
+
+/// Bundled fragment shader sampling the panel's contents (sampler at binding 0, texture at 1)
+pub const WORLD_PANEL_FRAG: &[u8] = include_bytes!("../shaders/world_panel.frag.spv");
+
+/// Resolution and size of a `WorldPanel`
+#[derive(Copy, Clone, Debug)]
+pub struct WorldPanelSettings {
+    /// Resolution of the panel's contents, in pixels
+    pub extent: vk::Extent2D,
+    /// Width and height in the world. In VR this is in meters.
+    pub size: [f32; 2],
+}
+
+impl Default for WorldPanelSettings {
+    /// A 1024x768 pixel panel, 0.8 by 0.6 meters
+    fn default() -> Self {
+        Self {
+            extent: vk::Extent2D {
+                width: 1024,
+                height: 768,
+            },
+            size: [0.8, 0.6],
+        }
+    }
+}
+
+/// A quad in the world, showing the contents of its own render target. The front faces +Z of
+/// `pose`, with the top of the contents towards +Y.
+pub struct WorldPanel {
+    /// Where the center of the panel is, in world space
+    pub pose: Isometry3<f32>,
+    /// Width and height in the world
+    pub size: [f32; 2],
+    target: RenderTarget,
+    quad: ManagedBuffer,
+    pipeline: vk::Pipeline,
+    pipeline_layout: vk::PipelineLayout,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_set: vk::DescriptorSet,
+    sampler: vk::Sampler,
+    #[cfg(feature = "openxr")]
+    xr_swapchain: Option<PanelSwapchain>,
+    core: SharedCore,
+}
+
+/// The OpenXR swapchain the panel is copied into, for the session it was created with
+#[cfg(feature = "openxr")]
+struct PanelSwapchain {
+    session: xr::sys::Session,
+    swapchain: Arc<Mutex<xr::Swapchain<xr::Vulkan>>>,
+    images: Vec<vk::Image>,
+}
+
+impl WorldPanel {
+    /// Create a panel drawn on the desktop with a pipeline built against `render_pass`, the
+    /// scene's (such as `StarterKit::render_pass`). `pipeline_settings` supplies the sample
+    /// count and depth settings; the panel is always blended, double sided and not depth
+    /// written.
+    pub fn new(
+        core: SharedCore,
+        settings: WorldPanelSettings,
+        render_pass: vk::RenderPass,
+        pipeline_settings: PipelineSettings,
+    ) -> Result<Self> {
+        let target_settings = RenderTargetSettings {
+            extra_usage: vk::ImageUsageFlags::TRANSFER_SRC,
+            ..RenderTargetSettings::color(crate::defaults::COLOR_FORMAT, false)
+        };
+        let mut target = RenderTarget::new(core.clone(), target_settings)?;
+        target.resize(settings.extent)?;
+
+        // A unit quad as a triangle strip, with the uv in the vertex color
+        let vertices = [
+            Vertex::new([-0.5, 0.5, 0.], [0., 0., 0.]),
+            Vertex::new([-0.5, -0.5, 0.], [0., 1., 0.]),
+            Vertex::new([0.5, 0.5, 0.], [1., 0., 0.]),
+            Vertex::new([0.5, -0.5, 0.], [1., 1., 0.]),
+        ];
+        let ci = vk::BufferCreateInfoBuilder::new()
+            .size(std::mem::size_of_val(&vertices) as u64)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .usage(vk::BufferUsageFlags::VERTEX_BUFFER);
+        let mut quad = ManagedBuffer::new(core.clone(), ci, UsageFlags::UPLOAD)?;
+        quad.write_slice(0, &vertices)?;
+
+        let create_info = vk::SamplerCreateInfoBuilder::new()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .max_lod(0.);
+        let sampler = unsafe { core.device.create_sampler(&create_info, None, None) }.result()?;
+
+        // Descriptor set layout: sampler and panel contents
+        let bindings = [
+            vk::DescriptorSetLayoutBindingBuilder::new()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+            vk::DescriptorSetLayoutBindingBuilder::new()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+        ];
+        let create_info = vk::DescriptorSetLayoutCreateInfoBuilder::new().bindings(&bindings);
+        let descriptor_set_layout = unsafe {
+            core.device
+                .create_descriptor_set_layout(&create_info, None, None)
+        }
+        .result()?;
+
+        let pool_sizes = [
+            vk::DescriptorPoolSizeBuilder::new()
+                ._type(vk::DescriptorType::SAMPLER)
+                .descriptor_count(1),
+            vk::DescriptorPoolSizeBuilder::new()
+                ._type(vk::DescriptorType::SAMPLED_IMAGE)
+                .descriptor_count(1),
+        ];
+        let create_info = vk::DescriptorPoolCreateInfoBuilder::new()
+            .pool_sizes(&pool_sizes)
+            .max_sets(1);
+        let descriptor_pool =
+            unsafe { core.device.create_descriptor_pool(&create_info, None, None) }.result()?;
+
+        let layouts = [descriptor_set_layout];
+        let create_info = vk::DescriptorSetAllocateInfoBuilder::new()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&layouts);
+        let descriptor_set = core.allocate_descriptor_sets(&create_info)?[0];
+
+        let sampler_info = [vk::DescriptorImageInfoBuilder::new().sampler(sampler)];
+        let image_info = [vk::DescriptorImageInfoBuilder::new()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(target.color_view())];
+        let writes = [
+            vk::WriteDescriptorSetBuilder::new()
+                .image_info(&sampler_info)
+                .descriptor_type(vk::DescriptorType::SAMPLER)
+                .dst_set(descriptor_set)
+                .dst_binding(0)
+                .dst_array_element(0),
+            vk::WriteDescriptorSetBuilder::new()
+                .image_info(&image_info)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .dst_set(descriptor_set)
+                .dst_binding(1)
+                .dst_array_element(0),
+        ];
+        unsafe {
+            core.device.update_descriptor_sets(&writes, &[]);
+        }
+
+        // Pipeline layout: the panel's descriptor set, and a transform for each view
+        let push_constant_ranges = [vk::PushConstantRangeBuilder::new()
+            .stage_flags(vk::ShaderStageFlags::VERTEX)
+            .offset(0)
+            .size(std::mem::size_of::<[f32; 4 * 4 * 2]>() as u32)];
+        let create_info = vk::PipelineLayoutCreateInfoBuilder::new()
+            .push_constant_ranges(&push_constant_ranges)
+            .set_layouts(&layouts);
+        let pipeline_layout =
+            unsafe { core.device.create_pipeline_layout(&create_info, None, None) }.result()?;
+
+        let mut panel = Self {
+            pose: Isometry3::identity(),
+            size: settings.size,
+            target,
+            quad,
+            pipeline: vk::Pipeline::null(),
+            pipeline_layout,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            sampler,
+            #[cfg(feature = "openxr")]
+            xr_swapchain: None,
+            core,
+        };
+        panel.set_render_pass(render_pass, pipeline_settings)?;
+        Ok(panel)
+    }
+
+    /// Rebuild the pipeline for a new scene render pass, such as after
+    /// `StarterKit::platform_changed()`. Must not be called while a frame using the panel is in
+    /// flight.
+    pub fn set_render_pass(
+        &mut self,
+        render_pass: vk::RenderPass,
+        pipeline_settings: PipelineSettings,
+    ) -> Result<()> {
+        let settings = PipelineSettings {
+            topology: vk::PrimitiveTopology::TRIANGLE_STRIP,
+            cull_mode: vk::CullModeFlags::NONE,
+            blend: true,
+            depth_write: false,
+            ..pipeline_settings
+        };
+        let pipeline = shader_with_settings::<Vertex>(
+            &self.core,
+            WORLD_PANEL_VERT,
+            WORLD_PANEL_FRAG,
+            &settings,
+            render_pass,
+            self.pipeline_layout,
+        )?;
+        if self.pipeline != vk::Pipeline::null() {
+            self.core.destroy_pipeline(self.pipeline);
+        }
+        self.pipeline = pipeline;
+        Ok(())
+    }
+
+    /// Render pass to build pipelines drawing the panel's contents against. It has a single
+    /// view, and a color attachment of `defaults::COLOR_FORMAT` with no depth.
+    pub fn render_pass(&self) -> vk::RenderPass {
+        self.target.render_pass()
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.target.extent()
+    }
+
+    /// Begin drawing the panel's contents, clearing it to `clear_color`. Alpha below 1 leaves
+    /// the panel see-through. Must be called outside of any render pass.
+    pub fn begin(&self, command_buffer: vk::CommandBuffer, clear_color: [f32; 4]) {
+        self.target.begin_pass(command_buffer, clear_color);
+    }
+
+    /// Finish drawing the panel's contents. In VR, also copies them to the panel's quad layer,
+    /// which is submitted with this frame.
+    pub fn end(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        platform: &Platform<'_>,
+    ) -> Result<()> {
+        self.target.end_pass(command_buffer);
+        #[cfg(feature = "openxr")]
+        if let Platform::OpenXr { xr_core, .. } = platform {
+            self.submit_quad_layer(command_buffer, xr_core)?;
+        }
+        #[cfg(not(feature = "openxr"))]
+        let _ = platform;
+        Ok(())
+    }
+
+    /// Draw the panel in the scene. `camera` holds the view-projection matrix of each view (see
+    /// `MultiPlatformCamera::get_matrices()`). In VR the panel is a quad layer instead, so
+    /// nothing is drawn.
+    pub fn draw(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        platform: &Platform<'_>,
+        camera: &[f32; 4 * 4 * 2],
+    ) {
+        if platform.is_vr() {
+            return;
+        }
+
+        let model = self.pose.to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&Vector3::new(self.size[0], self.size[1], 1.0));
+        let mut transforms = [0.0f32; 4 * 4 * 2];
+        for (transform, camera) in transforms.chunks_exact_mut(16).zip(camera.chunks_exact(16)) {
+            let matrix = Matrix4::from_column_slice(camera) * model;
+            transform.copy_from_slice(matrix.as_slice());
+        }
+
+        unsafe {
+            self.core.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            self.core.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+            self.core.device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                std::mem::size_of_val(&transforms) as u32,
+                transforms.as_ptr() as _,
+            );
+            self.core.device.cmd_bind_vertex_buffers(
+                command_buffer,
+                0,
+                &[self.quad.instance()],
+                &[0],
+            );
+            self.core.device.cmd_draw(command_buffer, 4, 1, 0, 0);
+        }
+    }
+
+    /// Copy the contents into the next image of the panel's swapchain, and queue the quad layer
+    /// showing it
+    #[cfg(feature = "openxr")]
+    fn submit_quad_layer(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        xr_core: &crate::openxr_backend::XrCore,
+    ) -> Result<()> {
+        let session = xr_core.session.as_raw();
+        if self.xr_swapchain.as_ref().map(|s| s.session) != Some(session) {
+            self.xr_swapchain = Some(self.create_swapchain(xr_core)?);
+        }
+        let xr_swapchain = self.xr_swapchain.as_ref().unwrap();
+
+        let image = {
+            let mut swapchain = xr_swapchain.swapchain.lock().unwrap();
+            let index = swapchain.acquire_image()?;
+            swapchain.wait_image(xr::Duration::INFINITE)?;
+            xr_swapchain.images[index as usize]
+        };
+
+        // Swapchain images are acquired and released as color attachments
+        let range = barriers::color_range(1);
+        let src = self.target.color_image();
+        self.core.cmd_barriers(
+            command_buffer,
+            &[],
+            &[
+                image_barrier(
+                    src,
+                    range,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                ),
+                image_barrier(
+                    image,
+                    range,
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                ),
+            ],
+        );
+        crate::copy::cmd_copy_image_simple(
+            &self.core,
+            command_buffer,
+            src,
+            image,
+            self.extent(),
+            1,
+        );
+        self.core.cmd_barriers(
+            command_buffer,
+            &[],
+            &[
+                image_barrier(
+                    src,
+                    range,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                ),
+                image_barrier(
+                    image,
+                    range,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                ),
+            ],
+        );
+
+        let translation = self.pose.translation.vector;
+        let rotation = self.pose.rotation;
+        let pose = xr::Posef {
+            orientation: xr::Quaternionf {
+                x: rotation.i,
+                y: rotation.j,
+                z: rotation.k,
+                w: rotation.w,
+            },
+            position: xr::Vector3f {
+                x: translation.x,
+                y: translation.y,
+                z: translation.z,
+            },
+        };
+        xr_core
+            .quad_layers
+            .lock()
+            .unwrap()
+            .push(crate::openxr_backend::QuadLayer {
+                swapchain: xr_swapchain.swapchain.clone(),
+                extent: self.extent(),
+                pose,
+                size: xr::Extent2Df {
+                    width: self.size[0],
+                    height: self.size[1],
+                },
+            });
+        Ok(())
+    }
+
+    #[cfg(feature = "openxr")]
+    fn create_swapchain(&self, xr_core: &crate::openxr_backend::XrCore) -> Result<PanelSwapchain> {
+        let extent = self.extent();
+        let swapchain = xr_core.session.create_swapchain(&xr::SwapchainCreateInfo {
+            create_flags: xr::SwapchainCreateFlags::EMPTY,
+            usage_flags: xr::SwapchainUsageFlags::COLOR_ATTACHMENT
+                | xr::SwapchainUsageFlags::TRANSFER_DST,
+            format: crate::defaults::COLOR_FORMAT.0 as _,
+            sample_count: 1,
+            width: extent.width,
+            height: extent.height,
+            face_count: 1,
+            array_size: 1,
+            mip_count: 1,
+        })?;
+        let images = swapchain
+            .enumerate_images()?
+            .into_iter()
+            .map(vk::Image)
+            .collect();
+        Ok(PanelSwapchain {
+            session: xr_core.session.as_raw(),
+            swapchain: Arc::new(Mutex::new(swapchain)),
+            images,
+        })
+    }
+}
+
+impl Drop for WorldPanel {
+    fn drop(&mut self) {
+        unsafe {
+            self.core.device.device_wait_idle().unwrap();
+            self.core.destroy_pipeline(self.pipeline);
+            self.core
+                .device
+                .destroy_pipeline_layout(Some(self.pipeline_layout), None);
+            self.core.destroy_descriptor_pool(self.descriptor_pool);
+            self.core
+                .device
+                .destroy_descriptor_set_layout(Some(self.descriptor_set_layout), None);
+            self.core.device.destroy_sampler(Some(self.sampler), None);
+        }
+    }
+}
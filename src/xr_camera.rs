@@ -15,12 +15,25 @@ pub fn view_from_pose(pose: &xr::Posef) -> Matrix4<f32> {
     let translation = Matrix4::new_translation(&position);
 
     let view = translation * rotation;
-    let inv = view.try_inverse().expect("Matrix didn't invert");
-    inv
+    view.try_inverse().expect("Matrix didn't invert")
 }
 
 /// Create a projection matrix for the given pose
 pub fn projection_from_fov(fov: &xr::Fovf, near: f32, far: f32) -> Matrix4<f32> {
+    let a33 = -far / (far - near);
+    let a43 = -(far * near) / (far - near);
+    projection(fov, a33, a43)
+}
+
+/// Create a reverse-Z projection matrix for the given pose, which maps `near` to a depth of 1
+/// and `far` to 0. Use with `PipelineSettings::reverse_z`.
+pub fn projection_from_fov_reverse_z(fov: &xr::Fovf, near: f32, far: f32) -> Matrix4<f32> {
+    let a33 = near / (far - near);
+    let a43 = (far * near) / (far - near);
+    projection(fov, a33, a43)
+}
+
+fn projection(fov: &xr::Fovf, a33: f32, a43: f32) -> Matrix4<f32> {
     let tan_left = fov.angle_left.tan();
     let tan_right = fov.angle_right.tan();
 
@@ -35,9 +48,7 @@ pub fn projection_from_fov(fov: &xr::Fovf, near: f32, far: f32) -> Matrix4<f32>
 
     let a31 = (tan_right + tan_left) / tan_width;
     let a32 = (tan_up + tan_down) / tan_height;
-    let a33 = -far / (far - near);
 
-    let a43 = -(far * near) / (far - near);
     Matrix4::new(
         a11, 0.0, a31, 0.0, //
         0.0, -a22, a32, 0.0, //
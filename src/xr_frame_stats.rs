@@ -0,0 +1,106 @@
+//! Frame timing of the OpenXR compositor, for performance HUDs and dynamic resolution. The
+//! backends record each frame's `xr::FrameState` and CPU timing in the `XrCore`; read them with
+//! `XrCore::frame_stats()`.
+//!
+//! Core OpenXR only reports when a frame is *predicted* to be displayed, so dropped frames are
+//! detected from gaps between consecutive predictions rather than reported by the runtime.
+//!
+//! ```ignore
+//! if let Platform::OpenXr { xr_core, .. } = platform {
+//!     let stats = xr_core.frame_stats();
+//!     if let Some(gpu_ms) = starter_kit.gpu_frame_time() {
+//!         // Above 1, the GPU is missing the compositor's deadline
+//!         let load = stats.gpu_load(gpu_ms);
+//!     }
+//! }
+//! ```
+use openxr as xr;
+use std::time::{Duration, Instant};
+
+/// Compositor timing of the most recent frames
+#[derive(Copy, Clone, Debug)]
+pub struct XrFrameStats {
+    /// Frames begun since the session started
+    pub frames: u64,
+    /// Display refreshes without a new frame from the app since the session started
+    pub dropped_frames: u64,
+    /// Display refreshes missed just before the latest frame
+    pub dropped_last: u32,
+    /// Time between display refreshes; each frame's CPU and GPU work must fit in it
+    pub display_period: Duration,
+    /// When the latest frame is predicted to be displayed
+    pub predicted_display_time: xr::Time,
+    /// Time between submitting the previous frame and beginning the latest, mostly spent
+    /// blocked in `xrWaitFrame`. Close to zero when the app isn't keeping up.
+    pub wait_time: Duration,
+    /// CPU time from beginning to submitting the previous frame
+    pub cpu_time: Duration,
+    began: Option<Instant>,
+    ended: Option<Instant>,
+}
+
+impl Default for XrFrameStats {
+    fn default() -> Self {
+        Self {
+            frames: 0,
+            dropped_frames: 0,
+            dropped_last: 0,
+            display_period: Duration::ZERO,
+            predicted_display_time: xr::Time::from_nanos(0),
+            wait_time: Duration::ZERO,
+            cpu_time: Duration::ZERO,
+            began: None,
+            ended: None,
+        }
+    }
+}
+
+impl XrFrameStats {
+    /// Fraction of the display period taken by `gpu_time_ms` of GPU work, such as
+    /// `StarterKit::gpu_frame_time()`. Above 1 the compositor's deadline is missed; apps
+    /// adjusting their resolution typically aim for around 0.8.
+    pub fn gpu_load(&self, gpu_time_ms: f32) -> f32 {
+        let period_ms = self.display_period.as_secs_f32() * 1000.0;
+        if period_ms > 0.0 {
+            gpu_time_ms / period_ms
+        } else {
+            0.0
+        }
+    }
+
+    /// Time to spare in the display period after `gpu_time_ms` of GPU work. Negative when the
+    /// deadline is missed.
+    pub fn gpu_headroom_ms(&self, gpu_time_ms: f32) -> f32 {
+        self.display_period.as_secs_f32() * 1000.0 - gpu_time_ms
+    }
+
+    /// Record the start of a frame, after `xrWaitFrame` returns
+    pub(crate) fn begin(&mut self, frame_state: &xr::FrameState) {
+        let now = Instant::now();
+        let period = frame_state.predicted_display_period.as_nanos().max(0);
+
+        self.dropped_last = 0;
+        if self.frames > 0 && period > 0 {
+            let gap = frame_state.predicted_display_time.as_nanos()
+                - self.predicted_display_time.as_nanos();
+            let refreshes = (gap + period / 2) / period;
+            self.dropped_last = (refreshes - 1).max(0) as u32;
+        }
+        self.dropped_frames += self.dropped_last as u64;
+
+        self.frames += 1;
+        self.display_period = Duration::from_nanos(period as u64);
+        self.predicted_display_time = frame_state.predicted_display_time;
+        self.wait_time = self.ended.map_or(Duration::ZERO, |ended| now - ended);
+        self.began = Some(now);
+    }
+
+    /// Record the end of a frame, after it has been submitted to the compositor
+    pub(crate) fn end(&mut self) {
+        let now = Instant::now();
+        if let Some(began) = self.began.take() {
+            self.cpu_time = now - began;
+        }
+        self.ended = Some(now);
+    }
+}
@@ -0,0 +1,149 @@
+//! Where the head and eyes are in the world as of the current frame, for gaze raycasts and
+//! head-relative placement. Views are located once per frame and cached in the `XrCore`, so the
+//! `MultiPlatformCamera` and app logic share the same poses.
+//!
+//! ```ignore
+//! if let Platform::OpenXr { xr_core, .. } = platform {
+//!     let views = xr_core.views()?;
+//!     if views.head_tracked() {
+//!         let (origin, direction) = views.gaze();
+//!         // Raycast from origin along direction
+//!     }
+//! }
+//! ```
+use crate::openxr_backend::XrCore;
+use anyhow::{ensure, Result};
+use openxr as xr;
+
+#[cfg(feature = "nalgebra")]
+use nalgebra::{Isometry3, Point3, Quaternion, Translation3, Unit, UnitQuaternion, Vector3};
+
+/// Head and eye poses in `XrCore::world()` at one time, in meters
+#[derive(Copy, Clone)]
+pub struct XrViews {
+    /// Time the poses were located at, usually a frame's predicted display time
+    pub time: xr::Time,
+    /// Left and right eye
+    pub views: [xr::View; 2],
+    pub view_flags: xr::ViewStateFlags,
+    /// The head, centered between the eyes
+    pub head: xr::Posef,
+    pub head_flags: xr::SpaceLocationFlags,
+}
+
+impl XrViews {
+    /// Whether the head's position and orientation are currently tracked, rather than
+    /// estimated or stale
+    pub fn head_tracked(&self) -> bool {
+        self.head_flags.contains(
+            xr::SpaceLocationFlags::POSITION_TRACKED | xr::SpaceLocationFlags::ORIENTATION_TRACKED,
+        )
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl XrViews {
+    /// Pose of the head
+    pub fn head(&self) -> Isometry3<f32> {
+        isometry_from_pose(&self.head)
+    }
+
+    /// Pose and field of view of the left and right eye
+    pub fn eyes(&self) -> [EyePose; 2] {
+        self.views.map(|view| EyePose {
+            pose: isometry_from_pose(&view.pose),
+            fov: view.fov,
+        })
+    }
+
+    /// Position of the head and the direction it faces, for gaze raycasts
+    pub fn gaze(&self) -> (Point3<f32>, Unit<Vector3<f32>>) {
+        let head = self.head();
+        (head.translation.vector.into(), forward(&head))
+    }
+}
+
+/// Pose of an eye, in `XrCore::world()`
+#[cfg(feature = "nalgebra")]
+#[derive(Copy, Clone)]
+pub struct EyePose {
+    pub pose: Isometry3<f32>,
+    pub fov: xr::Fovf,
+}
+
+#[cfg(feature = "nalgebra")]
+impl EyePose {
+    pub fn position(&self) -> Point3<f32> {
+        self.pose.translation.vector.into()
+    }
+
+    pub fn orientation(&self) -> UnitQuaternion<f32> {
+        self.pose.rotation
+    }
+
+    /// Direction the eye looks in
+    pub fn forward(&self) -> Unit<Vector3<f32>> {
+        forward(&self.pose)
+    }
+}
+
+/// Convert an OpenXR pose. Invalid orientations (such as before tracking starts) become the
+/// identity.
+#[cfg(feature = "nalgebra")]
+pub fn isometry_from_pose(pose: &xr::Posef) -> Isometry3<f32> {
+    let xr::Quaternionf { x, y, z, w } = pose.orientation;
+    let rotation = Unit::try_new(Quaternion::new(w, x, y, z), f32::EPSILON)
+        .unwrap_or_else(UnitQuaternion::identity);
+    let xr::Vector3f { x, y, z } = pose.position;
+    Isometry3::from_parts(Translation3::new(x, y, z), rotation)
+}
+
+/// OpenXR views look down -Z
+#[cfg(feature = "nalgebra")]
+fn forward(pose: &Isometry3<f32>) -> Unit<Vector3<f32>> {
+    pose.rotation * -Vector3::z_axis()
+}
+
+impl XrCore {
+    /// Head and eye poses as of the current frame's predicted display time. Located on the first
+    /// call each frame, then cached.
+    pub fn views(&self) -> Result<XrViews> {
+        let time = self.frame_stats().predicted_display_time;
+        ensure!(
+            time.as_nanos() != 0,
+            "Cannot locate views before the first frame"
+        );
+        self.views_at(time)
+    }
+
+    /// Head and eye poses at `time`. Cached when `time` is the same as the last call's.
+    pub fn views_at(&self, time: xr::Time) -> Result<XrViews> {
+        let mut cached = self.views.lock().unwrap();
+        if let Some(views) = *cached {
+            if views.time == time {
+                return Ok(views);
+            }
+        }
+
+        let world = self.world();
+        let (view_flags, located) =
+            self.session
+                .locate_views(xr::ViewConfigurationType::PRIMARY_STEREO, time, &world)?;
+        ensure!(
+            located.len() == 2,
+            "Expected two views, got {}",
+            located.len()
+        );
+        let head = self.view_space.locate(&world, time)?;
+
+        let views = XrViews {
+            time,
+            views: [located[0], located[1]],
+            view_flags,
+            head: head.pose,
+            head_flags: head.location_flags,
+        };
+        *cached = Some(views);
+        Ok(views)
+    }
+}
@@ -0,0 +1,142 @@
+//! Display refresh rate selection with `XR_FB_display_refresh_rate`, on runtimes which support
+//! it (such as standalone Quest headsets). Lower rates give each frame more GPU time, higher
+//! ones smoother motion. The `openxr` crate doesn't wrap the extension, so its functions are
+//! loaded here; the backend enables it whenever the runtime offers it.
+//!
+//! ```ignore
+//! if let Platform::OpenXr { xr_core, .. } = platform {
+//!     xr_core.request_closest_refresh_rate(90.0)?;
+//! }
+//! ```
+use crate::openxr_backend::XrCore;
+use anyhow::{format_err, Result};
+use openxr as xr;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+
+/// Name of the extension, enabled at instance creation when available
+pub(crate) const EXTENSION_NAME: &str = "XR_FB_display_refresh_rate";
+
+type EnumerateDisplayRefreshRates = unsafe extern "system" fn(
+    session: xr::sys::Session,
+    capacity_input: u32,
+    count_output: *mut u32,
+    rates: *mut f32,
+) -> xr::sys::Result;
+type GetDisplayRefreshRate =
+    unsafe extern "system" fn(session: xr::sys::Session, rate: *mut f32) -> xr::sys::Result;
+type RequestDisplayRefreshRate =
+    unsafe extern "system" fn(session: xr::sys::Session, rate: f32) -> xr::sys::Result;
+
+/// Function pointers of `XR_FB_display_refresh_rate`
+pub(crate) struct RefreshRateFns {
+    enumerate: EnumerateDisplayRefreshRates,
+    get: GetDisplayRefreshRate,
+    request: RequestDisplayRefreshRate,
+}
+
+impl RefreshRateFns {
+    /// Load the functions, or None if the extension isn't enabled on the instance
+    pub(crate) fn load(instance: &xr::Instance) -> Option<Self> {
+        let enumerate = proc_addr(instance, b"xrEnumerateDisplayRefreshRatesFB\0")?;
+        let get = proc_addr(instance, b"xrGetDisplayRefreshRateFB\0")?;
+        let request = proc_addr(instance, b"xrRequestDisplayRefreshRateFB\0")?;
+        unsafe {
+            Some(Self {
+                enumerate: std::mem::transmute::<
+                    xr::sys::pfn::VoidFunction,
+                    EnumerateDisplayRefreshRates,
+                >(enumerate),
+                get: std::mem::transmute::<xr::sys::pfn::VoidFunction, GetDisplayRefreshRate>(get),
+                request: std::mem::transmute::<xr::sys::pfn::VoidFunction, RequestDisplayRefreshRate>(
+                    request,
+                ),
+            })
+        }
+    }
+}
+
+fn proc_addr(instance: &xr::Instance, name: &[u8]) -> Option<xr::sys::pfn::VoidFunction> {
+    let name = CStr::from_bytes_with_nul(name).ok()?;
+    let mut function = None;
+    let result = unsafe {
+        (instance.fp().get_instance_proc_addr)(
+            instance.as_raw(),
+            name.as_ptr() as *const c_char,
+            &mut function,
+        )
+    };
+    if result == xr::sys::Result::SUCCESS {
+        function
+    } else {
+        None
+    }
+}
+
+fn check(result: xr::sys::Result) -> Result<()> {
+    if result.into_raw() >= 0 {
+        Ok(())
+    } else {
+        Err(result.into())
+    }
+}
+
+impl XrCore {
+    /// Whether the runtime supports choosing the display refresh rate
+    pub fn supports_refresh_rate(&self) -> bool {
+        self.refresh_rate.is_some()
+    }
+
+    /// Refresh rates the display supports in Hz, in ascending order. Empty if the runtime doesn't
+    /// support `XR_FB_display_refresh_rate`.
+    pub fn refresh_rates(&self) -> Result<Vec<f32>> {
+        let fns = match &self.refresh_rate {
+            Some(fns) => fns,
+            None => return Ok(vec![]),
+        };
+        let session = self.session.as_raw();
+
+        let mut count = 0;
+        check(unsafe { (fns.enumerate)(session, 0, &mut count, std::ptr::null_mut()) })?;
+        let mut rates = vec![0.0; count as usize];
+        check(unsafe { (fns.enumerate)(session, count, &mut count, rates.as_mut_ptr()) })?;
+        rates.truncate(count as usize);
+        rates.sort_by(f32::total_cmp);
+        Ok(rates)
+    }
+
+    /// The current display refresh rate in Hz, if the runtime supports
+    /// `XR_FB_display_refresh_rate`
+    pub fn refresh_rate(&self) -> Result<Option<f32>> {
+        let fns = match &self.refresh_rate {
+            Some(fns) => fns,
+            None => return Ok(None),
+        };
+        let mut rate = 0.0;
+        check(unsafe { (fns.get)(self.session.as_raw(), &mut rate) })?;
+        Ok(Some(rate))
+    }
+
+    /// Request one of `refresh_rates()`, or 0 to let the runtime choose. The change takes effect
+    /// after a few frames.
+    pub fn request_refresh_rate(&self, hz: f32) -> Result<()> {
+        let fns = self
+            .refresh_rate
+            .as_ref()
+            .ok_or_else(|| format_err!("The OpenXR runtime doesn't support {}", EXTENSION_NAME))?;
+        check(unsafe { (fns.request)(self.session.as_raw(), hz) })
+    }
+
+    /// Request the supported refresh rate closest to `hz`, such as 72, 90 or 120. Returns the
+    /// rate requested, or None if the runtime doesn't support choosing one.
+    pub fn request_closest_refresh_rate(&self, hz: f32) -> Result<Option<f32>> {
+        let closest = self
+            .refresh_rates()?
+            .into_iter()
+            .min_by(|a, b| (a - hz).abs().total_cmp(&(b - hz).abs()));
+        if let Some(rate) = closest {
+            self.request_refresh_rate(rate)?;
+        }
+        Ok(closest)
+    }
+}